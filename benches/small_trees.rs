@@ -0,0 +1,36 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkleproof::{HashScheme, MerkleTree};
+
+// Trees of a few leaves, such as per-block transaction batches. `new` builds these on the
+// stack; the builder takes the generic path to the same tree, for comparison.
+fn small_trees(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_trees");
+    for leaf_count in [4, 8, 16] {
+        let data: Vec<Vec<u8>> = (0..leaf_count)
+            .map(|i| format!("Transaction {}", i).into_bytes())
+            .collect();
+
+        group.bench_with_input(BenchmarkId::new("new", leaf_count), &data, |b, data| {
+            b.iter(|| MerkleTree::new(black_box(data.clone())))
+        });
+        group.bench_with_input(BenchmarkId::new("generic", leaf_count), &data, |b, data| {
+            b.iter(|| {
+                MerkleTree::builder()
+                    .hash_scheme(HashScheme::Plain)
+                    .build(black_box(data.clone()))
+            })
+        });
+
+        let tree = MerkleTree::new(data.clone());
+        let leaf = tree.leaf_index(leaf_count - 1).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("generate_proof_at", leaf_count),
+            &tree,
+            |b, tree| b.iter(|| tree.generate_proof_at(black_box(leaf))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, small_trees);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+// Print the conformance suite as JSON
+//
+// The repository's conformance.json is generated with
+//
+//     cargo run --example conformance --features conformance > conformance.json
+
+fn main() {
+    print!("{}", merkleproof::conformance::generate());
+}
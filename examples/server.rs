@@ -0,0 +1,86 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use merkleproof::{MerkleError, MerkleTree, ProofBundle, ProofRequest, ProofResponse};
+use serde_json::json;
+use std::sync::Arc;
+
+/// This example serves the root of a tree and proofs for its leaves over HTTP
+///
+/// Run it with `cargo run --example server --features serde`, then:
+///
+/// ```text
+/// curl http://127.0.0.1:3000/root
+/// curl http://127.0.0.1:3000/proof/2
+/// curl -d '{"leaf": {"ByData": "5472616e73616374696f6e2033"}}' \
+///     -H 'Content-Type: application/json' http://127.0.0.1:3000/proof
+/// ```
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    // The tree is built once and shared by every request without locking
+    let transactions: Vec<Vec<u8>> = (1..=10)
+        .map(|i| format!("Transaction {}", i).into_bytes())
+        .collect();
+    let tree = Arc::new(MerkleTree::builder().build(transactions));
+
+    let app = Router::new()
+        .route("/root", get(root))
+        .route("/proof/:index", get(proof))
+        .route("/proof", post(prove))
+        .with_state(tree);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("Serving proofs on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await
+}
+
+/// `GET /root`: the root hash and leaf count
+async fn root(State(tree): State<Arc<MerkleTree>>) -> Json<serde_json::Value> {
+    Json(json!({
+        "root": tree.root_hash_ref().map(hex::encode),
+        "leaf_count": tree.leaf_count(),
+    }))
+}
+
+/// `GET /proof/:index`: a proof bundle for a leaf, which `MerkleVerifier::verify_bundle`
+/// checks on the client
+async fn proof(
+    State(tree): State<Arc<MerkleTree>>,
+    Path(index): Path<usize>,
+) -> Result<Json<ProofBundle>, ApiError> {
+    let bundle = tree
+        .generate_bundle(index)
+        .ok_or(MerkleError::IndexOutOfRange {
+            index,
+            leaf_count: tree.leaf_count(),
+        })?;
+    Ok(Json(bundle))
+}
+
+/// `POST /proof`: a proof for the leaf a `ProofRequest` selects by index, hash or data
+async fn prove(
+    State(tree): State<Arc<MerkleTree>>,
+    Json(request): Json<ProofRequest>,
+) -> Result<Json<ProofResponse>, ApiError> {
+    Ok(Json(tree.handle_proof_request(&request)?))
+}
+
+/// A tree error answered with its HTTP status code
+struct ApiError(MerkleError);
+
+impl From<MerkleError> for ApiError {
+    fn from(error: MerkleError) -> Self {
+        ApiError(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.0.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = Json(json!({ "error": self.0.to_string() }));
+        (status, body).into_response()
+    }
+}
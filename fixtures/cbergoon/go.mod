@@ -0,0 +1,5 @@
+module merkleproof/fixtures/cbergoon
+
+go 1.21
+
+require github.com/cbergoon/merkletree v0.2.0
@@ -0,0 +1,78 @@
+// Generates vectors.json, the fixtures merkleproof checks Compat::CbergoonGo against:
+//
+//	go mod tidy && go run . > vectors.json
+//
+// Each leaf is the string "leaf <i>", hashed as SHA-256 of its bytes like the
+// package's example content.
+package main
+
+import (
+	"crypto/sha256"
+	"encoding/hex"
+	"encoding/json"
+	"fmt"
+	"os"
+
+	"github.com/cbergoon/merkletree"
+)
+
+type leaf struct {
+	x string
+}
+
+func (l leaf) CalculateHash() ([]byte, error) {
+	h := sha256.Sum256([]byte(l.x))
+	return h[:], nil
+}
+
+func (l leaf) Equals(other merkletree.Content) (bool, error) {
+	o, ok := other.(leaf)
+	return ok && o.x == l.x, nil
+}
+
+type proof struct {
+	Index      int      `json:"index"`
+	Path       []string `json:"path"`
+	Directions []int64  `json:"directions"`
+}
+
+type fixture struct {
+	Leaves []string `json:"leaves"`
+	Root   string   `json:"root"`
+	Proofs []proof  `json:"proofs"`
+}
+
+func main() {
+	var fixtures []fixture
+	for _, count := range []int{1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 13, 16, 17} {
+		var contents []merkletree.Content
+		var f fixture
+		for i := 0; i < count; i++ {
+			x := fmt.Sprintf("leaf %d", i)
+			contents = append(contents, leaf{x})
+			f.Leaves = append(f.Leaves, x)
+		}
+		tree, err := merkletree.NewTree(contents)
+		if err != nil {
+			panic(err)
+		}
+		f.Root = hex.EncodeToString(tree.MerkleRoot())
+		for i, c := range contents {
+			path, directions, err := tree.GetMerklePath(c)
+			if err != nil {
+				panic(err)
+			}
+			p := proof{Index: i, Path: []string{}, Directions: directions}
+			for _, h := range path {
+				p.Path = append(p.Path, hex.EncodeToString(h))
+			}
+			f.Proofs = append(f.Proofs, p)
+		}
+		fixtures = append(fixtures, f)
+	}
+	out := json.NewEncoder(os.Stdout)
+	out.SetIndent("", "  ")
+	if err := out.Encode(fixtures); err != nil {
+		panic(err)
+	}
+}
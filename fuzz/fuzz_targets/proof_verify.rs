@@ -0,0 +1,83 @@
+//! Decode untrusted proofs and verify them against untrusted roots and leaf counts
+//!
+//! Run with `cargo fuzz run proof_verify` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use merkleproof::{
+    HashScheme, MerkleProof, MerkleTree, MerkleVerifier, MultiProof, PaddingStrategy,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    encoded: &'a [u8],
+    text: &'a str,
+    data: &'a [u8],
+    root: &'a [u8],
+    leaf_count: usize,
+    index: usize,
+    domain_separated: bool,
+    depth: u8,
+    indices: Vec<usize>,
+}
+
+fuzz_target!(|input: Input| {
+    let _ = MerkleProof::from_hex(input.text);
+    let _ = MerkleProof::from_base64(input.text);
+    let Ok(proof) = MerkleProof::from_bytes(input.encoded) else {
+        return;
+    };
+    // Decoding is lossless
+    assert_eq!(
+        MerkleProof::from_bytes(&proof.to_bytes()).as_ref(),
+        Ok(&proof)
+    );
+
+    let scheme = if input.domain_separated {
+        HashScheme::DomainSeparated
+    } else {
+        HashScheme::Plain
+    };
+    let leaf_count = Some(input.leaf_count);
+    let _ = MerkleTree::verify_proof(input.data, &proof, input.root);
+    let _ = MerkleTree::verify_proof_with(scheme, input.data, &proof, input.root, leaf_count);
+    let _ =
+        MerkleTree::verify_proof_for_hash_with(scheme, input.data, &proof, input.root, leaf_count);
+    let _ = MerkleTree::verify_proof_positional(
+        scheme,
+        input.index,
+        input.data,
+        &proof,
+        input.root,
+        leaf_count,
+    );
+    let _ = MerkleTree::verify_proof_checked(
+        scheme,
+        input.data,
+        &proof,
+        input.root,
+        leaf_count,
+        Some(input.index),
+    );
+    let _ = MerkleVerifier::new(input.root)
+        .with_hash_config(scheme)
+        .with_leaf_count(input.leaf_count)
+        .verify(input.data, &proof);
+
+    let leaves = vec![input.data; input.indices.len()];
+    for padding in [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: input.depth },
+    ] {
+        let multiproof = MultiProof {
+            leaf_count: input.leaf_count,
+            padding,
+            indices: input.indices.clone(),
+            hashes: proof.iter().map(|(hash, _)| hash.clone()).collect(),
+        };
+        let _ = multiproof.verify(scheme, &leaves, input.root);
+    }
+});
@@ -0,0 +1,51 @@
+//! Deserialize untrusted tree snapshots and use them
+//!
+//! Covers JSON tree structures, canopies, pruned trees, proof bundles and verifier
+//! policies. Run with `cargo fuzz run structure_import` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkleproof::{
+    Canopy, HashScheme, MerkleProof, MerkleTree, MerkleVerifier, ProofBundle, PrunedMerkleTree,
+    TreeParams,
+};
+use serde_json::Value;
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(structure) = serde_json::from_slice::<Value>(bytes) {
+        for scheme in [HashScheme::Plain, HashScheme::DomainSeparated] {
+            if let Ok(tree) =
+                MerkleTree::from_json_structure(&structure, TreeParams::new(scheme, false))
+            {
+                // An imported tree is consistent with its own export
+                assert_eq!(tree.audit(), Ok(()));
+            }
+        }
+    }
+
+    if let Ok(canopy) = serde_json::from_slice::<Canopy>(bytes) {
+        let _ = canopy.root_hash();
+        for index in [0, 1, canopy.leaf_count().saturating_sub(1), usize::MAX] {
+            let _ = canopy.subtree_leaves(index);
+            let _ = canopy.complete_proof(&MerkleProof::default(), index);
+        }
+    }
+
+    if let Ok(pruned) = serde_json::from_slice::<PrunedMerkleTree>(bytes) {
+        for &index in pruned.kept_indices().iter().take(16) {
+            let _ = pruned.generate_proof_by_index(index);
+        }
+    }
+
+    if let Ok(bundle) = serde_json::from_slice::<ProofBundle>(bytes) {
+        let verifier = MerkleVerifier::new(vec![0; 32])
+            .with_hash_config(bundle.params.hasher)
+            .with_leaf_count(bundle.leaf_count);
+        let _ = verifier.verify_bundle(b"", &bundle);
+    }
+
+    if let Ok(verifier) = serde_json::from_slice::<MerkleVerifier>(bytes) {
+        let _ = verifier.verify(b"", &MerkleProof::default());
+    }
+});
@@ -0,0 +1,168 @@
+use crate::error::AuditError;
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+use crate::sample::{audit_sample_indices, binds_index};
+use crate::verify::level_sizes;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+impl MerkleTree {
+    /// Recompute every node and check it against the stored hash
+    ///
+    /// Levels are checked from the leaves up, left to right. A leaf is checked against
+    /// its data unless the data was cleared, the padding leaf against the last leaf, and
+    /// a branch against the hash of its children (or the child itself, for a node
+    /// carried up unpaired). The padding of a complete tree is checked against the empty
+    /// subtree of its height.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the tree is consistent, or an error describing the first inconsistency
+    pub fn audit(&self) -> Result<(), AuditError> {
+        self.audit_shape()?;
+        for (level, nodes) in self.levels().iter().enumerate() {
+            for index in 0..nodes.len() {
+                self.audit_node(level, index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the nodes on randomly chosen paths against their stored hashes
+    ///
+    /// This costs one leaf hash and one pair hash per level for each path, so large
+    /// trees can be spot-checked cheaply. Paths are chosen afresh on every call.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The number of leaf-to-root paths to check
+    ///
+    /// # Returns
+    ///
+    /// Ok if every checked node is consistent, or an error describing the first
+    /// inconsistency found
+    pub fn audit_sampled(&self, paths: usize) -> Result<(), AuditError> {
+        self.audit_shape()?;
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let leaves = self.levels()[0].len() as u64;
+        let mut state = RandomState::new().build_hasher().finish();
+        for _ in 0..paths {
+            // splitmix64
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            let leaf = ((z ^ (z >> 31)) % leaves) as usize;
+
+            for level in 0..self.height() {
+                self.audit_node(level, leaf >> level)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reveal the leaves the root chooses for a spot-audit
+    ///
+    /// The leaves are chosen by `audit_sample_indices` from the root itself, so the
+    /// prover cannot pick them, and the sample can be checked with
+    /// `MerkleTree::verify_audit_sample` by anyone who knows the root and leaf count.
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - The number of leaves to reveal (all of them if the tree has fewer)
+    ///
+    /// # Returns
+    ///
+    /// The index, data and proof of each chosen leaf, in the order they were chosen,
+    /// `AuditError::SampleIndexUnbound` if the tree's proofs do not fix their leaf's
+    /// index, or `AuditError::SampleDataUnavailable` if a chosen leaf's data was cleared
+    pub fn audit_sample(&self, k: usize) -> Result<Vec<(usize, Vec<u8>, MerkleProof)>, AuditError> {
+        if !binds_index(self.params()) {
+            return Err(AuditError::SampleIndexUnbound);
+        }
+        let Some(root) = self.root_hash_ref() else {
+            return Ok(Vec::new());
+        };
+        audit_sample_indices(root, self.leaf_count(), k)
+            .into_iter()
+            .map(|index| {
+                let data = self
+                    .get_leaf(index)
+                    .ok_or(AuditError::SampleDataUnavailable { index })?
+                    .to_vec();
+                let proof = self
+                    .proof_by_index(index)
+                    .expect("a sampled index is in range");
+                Ok((index, data, proof))
+            })
+            .collect()
+    }
+
+    /// Check that every level has the length the leaf count requires
+    fn audit_shape(&self) -> Result<(), AuditError> {
+        let expected = level_sizes(self.committed_leaf_count(), self.params().padding);
+        if expected.len() != self.height() {
+            return Err(AuditError::HeightMismatch {
+                expected: expected.len(),
+                actual: self.height(),
+            });
+        }
+        for (level, (nodes, &expected)) in self.levels().iter().zip(&expected).enumerate() {
+            if nodes.len() != expected {
+                return Err(AuditError::LevelSizeMismatch {
+                    level,
+                    expected,
+                    actual: nodes.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Recompute one node and check it against the stored hash
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// Ok if the stored hash is the recomputed one (or the node cannot be recomputed
+    /// because its data was cleared), or `AuditError::NodeMismatch`
+    fn audit_node(&self, level: usize, index: usize) -> Result<(), AuditError> {
+        let levels = self.levels();
+        let expected = if let Some(empty) = self.empty_subtree_at(level, index) {
+            empty
+        } else if level == 0 {
+            if index >= self.leaf_count() {
+                levels[0][self.leaf_count() - 1].clone()
+            } else {
+                match self.get_leaf(index) {
+                    Some(data) => self.leaf_hash_at(index, data),
+                    None => return Ok(()),
+                }
+            }
+        } else {
+            let children = &levels[level - 1];
+            match children.get(index * 2 + 1) {
+                Some(right) => self.pair_hash(&children[index * 2], right),
+                None => children[index * 2].clone(),
+            }
+        };
+
+        let stored = &levels[level][index];
+        if *stored != expected {
+            return Err(AuditError::NodeMismatch {
+                level,
+                index,
+                expected,
+                stored: stored.clone(),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use crate::hashing::{EmptyRootPolicy, HashScheme};
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A batch of typed items committed to by a Merkle tree
+///
+/// Each item is serialized with one canonical encoding, compact JSON with object members
+/// sorted by key, so that the committing and verifying sides cannot disagree on the leaf
+/// bytes: both go through `Batch` and `TypedProof` and never handle the bytes
+/// themselves. The tree uses the `MerkleTree::builder()` defaults, and an empty batch
+/// commits to the hash of empty input.
+pub struct Batch<T> {
+    items: Vec<T>,
+    tree: MerkleTree,
+}
+
+/// A proof that an item is in a committed batch
+///
+/// Created by `Batch::prove`. The proof serializes as its index, the batch size and the
+/// proof steps.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct TypedProof<T> {
+    /// The index of the item in the batch
+    pub index: usize,
+    /// The number of items in the batch
+    pub leaf_count: usize,
+    /// The proof of the encoded item
+    pub proof: MerkleProof,
+    #[serde(skip)]
+    item: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize> Batch<T> {
+    /// Commit to a batch of items
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items, in order
+    ///
+    /// # Returns
+    ///
+    /// The batch, or the error of the first item that cannot be serialized as JSON
+    pub fn new(items: Vec<T>) -> Result<Self, serde_json::Error> {
+        let encoded = items
+            .iter()
+            .map(canonical_encoding)
+            .collect::<Result<_, _>>()?;
+        let tree = MerkleTree::builder()
+            .empty_root(EmptyRootPolicy::HashOfEmpty)
+            .build(encoded);
+        Ok(Batch { items, tree })
+    }
+
+    /// Get the commitment to the batch
+    ///
+    /// # Returns
+    ///
+    /// The root hash of the batch's tree
+    pub fn commit(&self) -> Vec<u8> {
+        self.tree.root_hash().unwrap()
+    }
+
+    /// Generate a proof for an item
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the item
+    ///
+    /// # Returns
+    ///
+    /// The proof, or None if the index is out of range
+    pub fn prove(&self, index: usize) -> Option<TypedProof<T>> {
+        Some(TypedProof {
+            index,
+            leaf_count: self.items.len(),
+            proof: self.tree.proof_by_index(index)?,
+            item: PhantomData,
+        })
+    }
+
+    /// Get the items
+    ///
+    /// # Returns
+    ///
+    /// The items, in order
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Get the tree committing to the items
+    ///
+    /// # Returns
+    ///
+    /// The tree, whose leaves are the encoded items
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+}
+
+impl<T: Serialize> TypedProof<T> {
+    /// Verify that an item is in the batch with a given commitment
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item
+    /// * `root` - The batch's commitment
+    ///
+    /// # Returns
+    ///
+    /// True if the item is at the proof's index in the batch, false otherwise (including
+    /// when the item cannot be serialized or the proof addresses another index)
+    pub fn verify(&self, item: &T, root: &[u8]) -> bool {
+        match canonical_encoding(item) {
+            Ok(data) => MerkleTree::verify_proof_checked(
+                HashScheme::DomainSeparated,
+                &data,
+                &self.proof,
+                root,
+                Some(self.leaf_count),
+                Some(self.index),
+            )
+            .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl<T> Clone for TypedProof<T> {
+    fn clone(&self) -> Self {
+        TypedProof {
+            index: self.index,
+            leaf_count: self.leaf_count,
+            proof: self.proof.clone(),
+            item: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TypedProof<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedProof")
+            .field("index", &self.index)
+            .field("leaf_count", &self.leaf_count)
+            .field("proof", &self.proof)
+            .finish()
+    }
+}
+
+/// Serialize an item as compact JSON with object members sorted by key
+///
+/// # Arguments
+///
+/// * `item` - The item
+///
+/// # Returns
+///
+/// The encoded item, or an error if it cannot be represented as JSON
+fn canonical_encoding<T: Serialize>(item: &T) -> Result<Vec<u8>, serde_json::Error> {
+    serde_json::to_vec(&sort_members(serde_json::to_value(item)?))
+}
+
+/// Sort the members of every object in a JSON value by key
+fn sort_members(value: Value) -> Value {
+    match value {
+        Value::Object(members) => {
+            let mut members: Vec<_> = members.into_iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                members
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_members(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_members).collect()),
+        other => other,
+    }
+}
@@ -0,0 +1,662 @@
+use crate::encoding::LeafEncoder;
+use crate::error::{IngestError, MerkleError};
+use crate::hashing::{
+    ByteOrder, EmptyRootPolicy, HashAlgorithm, HashConfig, HashScheme, PairOrdering,
+};
+use crate::merkle_tree::{BuildOptions, MerkleTree};
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::progress::ProgressEvent;
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+
+/// A callback receiving construction progress events
+type ProgressCallback = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// A builder for configuring how a Merkle tree is constructed
+///
+/// The builder is the recommended way to create new trees. Unlike `MerkleTree::new`, it
+/// defaults to domain-separated hashing.
+#[derive(Clone)]
+pub struct MerkleTreeBuilder {
+    /// The configuration used to hash leaves and branches
+    hasher: HashConfig,
+    /// Whether each leaf's index is hashed together with its data
+    positional: bool,
+    /// How an odd leaf level is padded
+    padding: PaddingStrategy,
+    /// Options that do not change how leaves and branches are hashed
+    options: BuildOptions,
+    /// The callback to report construction progress to, if any
+    progress: Option<ProgressCallback>,
+}
+
+impl MerkleTreeBuilder {
+    /// Create a new builder with the default options
+    ///
+    /// # Returns
+    ///
+    /// A builder using SHA-256, `HashScheme::DomainSeparated` and positional pairs
+    pub fn new() -> Self {
+        MerkleTreeBuilder {
+            hasher: HashScheme::DomainSeparated.into(),
+            positional: false,
+            padding: PaddingStrategy::DuplicateLast,
+            options: BuildOptions::default(),
+            progress: None,
+        }
+    }
+
+    /// Set the scheme used to hash leaves and branches
+    ///
+    /// # Arguments
+    ///
+    /// * `scheme` - The hash scheme to use
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn hash_scheme(mut self, scheme: HashScheme) -> Self {
+        self.hasher.scheme = scheme;
+        self
+    }
+
+    /// Set the hash function used for leaves and branches
+    ///
+    /// # Arguments
+    ///
+    /// * `algorithm` - The hash algorithm to use
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hasher.algorithm = algorithm;
+        self
+    }
+
+    /// Set how child hashes are ordered before being hashed together
+    ///
+    /// # Arguments
+    ///
+    /// * `ordering` - The pair ordering to use
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn pair_ordering(mut self, ordering: PairOrdering) -> Self {
+        self.hasher.ordering = ordering;
+        self
+    }
+
+    /// Set the byte order child hashes are concatenated in
+    ///
+    /// # Arguments
+    ///
+    /// * `byte_order` - The byte order to use
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.hasher.byte_order = byte_order;
+        self
+    }
+
+    /// Set whether leaves commit to their position
+    ///
+    /// A positional tree hashes each leaf as `H(le64(index) || data)` (before any domain
+    /// separation prefix), so the same data at a different index gives a different root
+    /// and proofs must be checked with `MerkleTree::verify_proof_positional`.
+    ///
+    /// # Arguments
+    ///
+    /// * `positional` - Whether to hash each leaf's index together with its data
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn positional(mut self, positional: bool) -> Self {
+        self.positional = positional;
+        self
+    }
+
+    /// Set how an odd number of leaves is handled
+    ///
+    /// By default the last leaf is duplicated. With `PaddingStrategy::CarryUp` it is
+    /// carried up unpaired instead, giving the tree shape of Certificate Transparency
+    /// (RFC 6962). The tree records the strategy, and `MerkleTree::verify` accepts the
+    /// shorter proofs such trees give their last leaves. The static verifiers assume
+    /// duplicated leaves when given a leaf count, so pass no leaf count to them for
+    /// such trees.
+    ///
+    /// # Arguments
+    ///
+    /// * `padding` - The padding strategy
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn padding(mut self, padding: PaddingStrategy) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Set whether building fails when two leaves have the same hash
+    ///
+    /// When set, build with `try_build` or `try_build_encoded`, which return
+    /// `MerkleError::DuplicateLeaf` naming the first repeated leaf; `build` and
+    /// `build_encoded` panic on a duplicate instead. In a positional tree every leaf hash
+    /// commits to its index, so no two leaves collide.
+    ///
+    /// # Arguments
+    ///
+    /// * `reject` - Whether to reject duplicate leaves
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn reject_duplicates(mut self, reject: bool) -> Self {
+        self.options.reject_duplicates = reject;
+        self
+    }
+
+    /// Set whether the leaf hashes are required to be strictly increasing
+    ///
+    /// When set, `MerkleTree::index_of` and `generate_proof` binary search the leaf level
+    /// instead of building a map from leaf hash to index, which costs memory for a copy
+    /// of every distinct hash. Building checks the precondition: `try_build` and
+    /// `try_build_encoded` return `MerkleError::UnsortedLeaf` naming the first leaf out
+    /// of order (or repeated), and `build` and `build_encoded` panic. Combine with
+    /// `sort_leaves` to have the builder establish the order. A later `push` or
+    /// `update_leaf` that breaks the order makes lookups use the map. Positional trees
+    /// are always scanned, as their leaf hashes depend on the index being searched for.
+    ///
+    /// # Arguments
+    ///
+    /// * `sorted_unique` - Whether to require strictly increasing leaf hashes
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn leaves_sorted_unique(mut self, sorted_unique: bool) -> Self {
+        self.options.sorted_unique = sorted_unique;
+        self
+    }
+
+    /// Set the root reported while the tree has no leaves
+    ///
+    /// The policy is kept by the tree, so a tree that starts empty reports the policy's
+    /// root until its first `push`, and the leaf-derived root from then on. Under
+    /// `HashScheme::Plain`, `EmptyRootPolicy::HashOfEmpty` is refused with
+    /// `MerkleError::AmbiguousEmptyRoot`, as it is also the root of a single empty leaf.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The empty root policy to use
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn empty_root(mut self, policy: EmptyRootPolicy) -> Self {
+        self.options.empty_root = policy;
+        self
+    }
+
+    /// Set whether leaves are sorted by hash before the tree is built
+    ///
+    /// Sorting makes the root independent of the order the leaves arrive in: any
+    /// permutation of the same leaves gives the same tree. Leaves are ordered by their
+    /// plain leaf hash (the hash without any position, even in a positional tree), and
+    /// leaves with equal hashes keep their relative order. `MerkleTree::original_index`
+    /// maps a leaf back to its position in the input, and lookups by data such as
+    /// `generate_proof` work as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `sort` - Whether to sort the leaves
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn sort_leaves(mut self, sort: bool) -> Self {
+        self.options.sort_leaves = sort;
+        self
+    }
+
+    /// Set whether each distinct pair of child hashes is hashed only once while building
+    ///
+    /// Data with long runs of identical leaves, such as zero-filled reserved slots,
+    /// produces the same pairs over and over; with memoization each is hashed once and
+    /// the result reused. The root is unchanged. Remembering every pair costs memory and
+    /// a lookup per node, so this only pays off for repetitive data. Later `push` and
+    /// `update_leaf` calls are not memoized.
+    ///
+    /// # Arguments
+    ///
+    /// * `memoize` - Whether to memoize pair hashes
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn memoize_pairs(mut self, memoize: bool) -> Self {
+        self.options.memoize_pairs = memoize;
+        self
+    }
+
+    /// Set whether leaves and levels are hashed on rayon's thread pool
+    ///
+    /// Runs on the current pool: the global one, sized by `RAYON_NUM_THREADS`, unless
+    /// called inside `ThreadPool::install`.
+    ///
+    /// Determinism is part of the API: the root, every node and therefore every proof are
+    /// byte-for-byte those of a sequential build, whatever the number of threads or the
+    /// order the pool runs the work in. Each hash is written to the slot of its leaf or
+    /// pair and nothing is folded in completion order, so roots published from machines
+    /// with different core counts always agree.
+    ///
+    /// Memoized builds (see `memoize_pairs`) hash their levels sequentially, and
+    /// `try_build_from_readers` reads its leaves sequentially. Progress events are
+    /// reported in order, with the leaf events after all leaves are hashed.
+    ///
+    /// # Arguments
+    ///
+    /// * `parallel` - Whether to build in parallel
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    #[cfg(feature = "parallel")]
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.options.parallel = parallel;
+        self
+    }
+
+    /// Limit the size of each leaf
+    ///
+    /// Oversized leaves are rejected before anything is hashed or copied: `try_build`
+    /// and `try_build_encoded` return `MerkleError::LeafTooLarge` naming the first one
+    /// and its size, and `build` and `build_encoded` panic. `try_build_from_readers`
+    /// stops reading a leaf one byte past the limit, so a source feeding an unbounded
+    /// leaf in chunks cannot get around it. Leaves are unlimited by default.
+    ///
+    /// The limit applies to this builder's build calls only. The tree does not keep it,
+    /// so later `push` and `update_leaf` calls are not limited, and neither are
+    /// `FileTree::from_reader` and `ChunkedFileTree::from_reader`, which split their
+    /// input into chunks of a fixed size rather than taking leaves whole.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The largest leaf accepted, in bytes
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn max_leaf_size(mut self, max: u64) -> Self {
+        self.options.max_leaf_size = Some(max);
+        self
+    }
+
+    /// Set the number of epochs whose roots the tree retains
+    ///
+    /// Every `push` or `update_leaf` starts a new epoch, and
+    /// `MerkleTree::verify_at_epoch` accepts proofs made at any retained epoch. An epoch
+    /// is evicted once it is `history` or more epochs older than the current one. The
+    /// default is 16.
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - The number of epochs to retain, including the current one (at
+    ///   least 1)
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn epoch_history(mut self, history: usize) -> Self {
+        self.options.epoch_history = Some(history);
+        self
+    }
+
+    /// Cache recently generated proofs
+    ///
+    /// `generate_proof`, `generate_proof_at` and the methods built on them serve repeated
+    /// requests for the same leaf from a least-recently-used cache instead of walking the
+    /// tree again. The cache is shared by all threads using the tree and is emptied by
+    /// every `push` or `update_leaf`. `MerkleTree::proof_cache_stats` reports its hits
+    /// and misses. By default there is no cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The largest number of proofs cached (0 for no cache)
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn proof_cache(mut self, entries: usize) -> Self {
+        self.options.proof_cache = entries;
+        self
+    }
+
+    /// Store each distinct leaf payload once
+    ///
+    /// Identical payloads are kept in a content-addressed pool and every leaf holding one
+    /// references the pooled copy, so `get_leaf` returns the same bytes while the memory
+    /// taken is that of the distinct payloads (see `MerkleTree::storage_stats`). Leaves
+    /// keep their own positions, hashes and proofs. `push` and `update_leaf` maintain the
+    /// pool, freeing a payload once no leaf references it. Pooled payloads are shared, so
+    /// like those of `build_shared` they are not wiped by the `zeroize` feature once
+    /// pooled. By default each leaf owns its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `dedup` - Whether to deduplicate leaf payloads
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn dedup_storage(mut self, dedup: bool) -> Self {
+        self.options.dedup_storage = dedup;
+        self
+    }
+
+    /// Record every mutation of the tree in an `OperationLog`
+    ///
+    /// Each `push` and `update_leaf` appends an entry with the leaf's index and hash and
+    /// the roots before and after, available from `MerkleTree::operation_log`. The log
+    /// grows with every mutation and is never truncated. By default nothing is recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to record mutations
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn operation_log(mut self, enabled: bool) -> Self {
+        self.options.operation_log = enabled;
+        self
+    }
+
+    /// Set a callback to report construction progress to
+    ///
+    /// The callback receives a `ProgressEvent::LeafHashed` for every leaf and a
+    /// `ProgressEvent::LevelCompleted` for every level, including the leaf level and the
+    /// root's level. It is called on the building thread, so it should be quick; when no
+    /// callback is set no events are created.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - The function to call with each event
+    ///
+    /// # Returns
+    ///
+    /// The updated builder
+    pub fn on_progress(
+        mut self,
+        callback: impl Fn(&ProgressEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Build a Merkle tree from a list of data items
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - A vector of data items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree containing the data items
+    ///
+    /// # Panics
+    ///
+    /// Panics if a leaf is over the size limit, or if duplicates are rejected and two
+    /// leaves have the same hash; use `try_build` to handle those cases
+    pub fn build(self, data_items: Vec<Vec<u8>>) -> MerkleTree {
+        self.try_build(data_items)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Build a Merkle tree from a list of data items, failing on oversized leaves, and on
+    /// duplicate leaves if they are rejected
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - A vector of data items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree containing the data items, or `MerkleError::LeafTooLarge` or
+    /// `MerkleError::DuplicateLeaf`
+    pub fn try_build(self, data_items: Vec<Vec<u8>>) -> Result<MerkleTree, MerkleError> {
+        MerkleTree::build_with(
+            data_items,
+            self.params(),
+            self.options,
+            self.progress.as_deref().map(as_fn),
+        )
+    }
+
+    /// Build a Merkle tree over data shared with the application
+    ///
+    /// The tree references the payloads instead of copying them, so cloning it does not
+    /// copy any leaf data. The root and proofs are those of `build` over the same bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - The payloads to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree referencing the payloads
+    ///
+    /// # Panics
+    ///
+    /// Panics if a leaf is over the size limit, or if duplicates are rejected and two
+    /// leaves have the same hash
+    pub fn build_shared(self, data_items: Vec<Arc<[u8]>>) -> MerkleTree {
+        MerkleTree::build_with(
+            data_items,
+            self.params(),
+            self.options,
+            self.progress.as_deref().map(as_fn),
+        )
+        .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Build two Merkle trees over the same leaves, hashed with two algorithms
+    ///
+    /// The trees are identical to `build` with this builder and with this builder after
+    /// `hash_algorithm(second)`, but the leaves are read in a single pass that hashes
+    /// each with both algorithms, and both trees reference one shared copy of each
+    /// leaf's data (see `MerkleTree::shared_leaf`). This suits publishing roots under
+    /// an old and a new algorithm during a migration.
+    ///
+    /// # Arguments
+    ///
+    /// * `second` - The hash function of the second tree
+    /// * `data_items` - A vector of data items to include in the trees
+    ///
+    /// # Returns
+    ///
+    /// The tree under the builder's hash function and the tree under `second`
+    ///
+    /// # Panics
+    ///
+    /// Panics if a leaf is over the size limit, or if duplicates are rejected and two
+    /// leaves have the same hash; use `try_build_dual` to handle those cases
+    pub fn build_dual(
+        self,
+        second: HashAlgorithm,
+        data_items: Vec<Vec<u8>>,
+    ) -> (MerkleTree, MerkleTree) {
+        self.try_build_dual(second, data_items)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Build two Merkle trees over the same leaves, hashed with two algorithms, failing
+    /// on oversized leaves, and on duplicate leaves if they are rejected
+    ///
+    /// # Arguments
+    ///
+    /// * `second` - The hash function of the second tree
+    /// * `data_items` - A vector of data items to include in the trees
+    ///
+    /// # Returns
+    ///
+    /// The tree under the builder's hash function and the tree under `second`, or
+    /// `MerkleError::LeafTooLarge` or `MerkleError::DuplicateLeaf`
+    pub fn try_build_dual(
+        self,
+        second: HashAlgorithm,
+        data_items: Vec<Vec<u8>>,
+    ) -> Result<(MerkleTree, MerkleTree), MerkleError> {
+        MerkleTree::build_dual_with(
+            data_items,
+            self.params(),
+            second,
+            self.options,
+            self.progress.as_deref().map(as_fn),
+        )
+    }
+
+    /// Build a Merkle tree that keeps only the leaf hashes
+    ///
+    /// The data is hashed and then dropped, as by `MerkleTree::clear_leaf_data`, so
+    /// `get_leaf` returns None for every leaf while roots and proofs are unaffected.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - The data items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree without leaf data
+    ///
+    /// # Panics
+    ///
+    /// Panics if a leaf is over the size limit, or if duplicates are rejected and two
+    /// leaves have the same hash
+    pub fn build_without_data(self, data_items: Vec<Vec<u8>>) -> MerkleTree {
+        let mut tree = self.build(data_items);
+        tree.clear_leaf_data();
+        tree
+    }
+
+    /// Build a Merkle tree from typed items using a leaf encoder
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree over the encoded items
+    ///
+    /// # Panics
+    ///
+    /// Panics if an item cannot be encoded, if an encoded leaf is over the size limit, or
+    /// if duplicates are rejected and two leaves have the same hash; use
+    /// `try_build_encoded` to handle those cases
+    pub fn build_encoded<E: LeafEncoder>(self, items: Vec<E::Item>) -> MerkleTree {
+        self.try_build_encoded::<E>(items)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Build a Merkle tree from typed items using a leaf encoder, failing on oversized
+    /// leaves, and on duplicate leaves if they are rejected
+    ///
+    /// Each item is checked against the size limit as soon as it is encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree over the encoded items, the encoder's error (such as
+    /// `MerkleError::FieldTooLarge`), `MerkleError::LeafTooLarge` or
+    /// `MerkleError::DuplicateLeaf`
+    pub fn try_build_encoded<E: LeafEncoder>(
+        self,
+        items: Vec<E::Item>,
+    ) -> Result<MerkleTree, MerkleError> {
+        MerkleTree::build_encoded_with::<E>(
+            items,
+            self.params(),
+            self.options,
+            self.progress.as_deref().map(as_fn),
+        )
+    }
+
+    /// Build a Merkle tree that keeps only the leaf hashes, reading each leaf from a reader
+    ///
+    /// Each reader is read to its end and hashed a buffer at a time, so leaves never need
+    /// to fit in memory. With `max_leaf_size`, reading a leaf stops one byte past the
+    /// limit, making this safe to point at untrusted sources. As with
+    /// `build_without_data`, `get_leaf` returns None for every leaf. Unless the tree is
+    /// positional, `sort_leaves` sorts the leaves by hash; the leaves of a positional tree
+    /// keep the readers' order, since sorting them would mean reading each leaf twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `readers` - The readers yielding each leaf's data, in order
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree without leaf data, `IngestError::Io` if a reader fails, or
+    /// `IngestError::Build` with `MerkleError::LeafTooLarge` naming the first oversized
+    /// leaf (or another error the options call for)
+    pub fn try_build_from_readers<R: Read>(
+        self,
+        readers: impl IntoIterator<Item = R>,
+    ) -> Result<MerkleTree, IngestError> {
+        MerkleTree::build_from_readers(
+            readers,
+            self.params(),
+            self.options,
+            self.progress.as_deref().map(as_fn),
+        )
+    }
+
+    /// Collect the parameters the tree is built with
+    fn params(&self) -> TreeParams {
+        TreeParams {
+            hasher: self.hasher,
+            padding: self.padding,
+            positional: self.positional,
+        }
+    }
+}
+
+/// Drop the thread-safety bounds of a progress callback for use during construction
+///
+/// # Arguments
+///
+/// * `callback` - The callback
+///
+/// # Returns
+///
+/// The same callback
+fn as_fn(callback: &(dyn Fn(&ProgressEvent) + Send + Sync)) -> &dyn Fn(&ProgressEvent) {
+    callback
+}
+
+impl fmt::Debug for MerkleTreeBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MerkleTreeBuilder")
+            .field("hasher", &self.hasher)
+            .field("positional", &self.positional)
+            .field("padding", &self.padding)
+            .field("options", &self.options)
+            .field("progress", &self.progress.as_ref().map(|_| "<callback>"))
+            .finish()
+    }
+}
+
+impl Default for MerkleTreeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,224 @@
+//! Verification of many leaves in one pass, with a report of every outcome
+
+use crate::merkle_tree::MerkleTree;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The outcome of checking one item against a tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LeafOutcome {
+    /// The item hashes to the stored leaf hash
+    Ok,
+    /// The item hashes to something other than the stored leaf hash
+    HashMismatch,
+    /// The tree has no leaf at the item's index
+    IndexOutOfRange,
+}
+
+/// The outcome of checking the item given for one index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeafCheck {
+    /// The index the item was given for
+    pub index: usize,
+    /// The outcome
+    pub outcome: LeafOutcome,
+}
+
+/// The outcome of checking many items against a tree
+///
+/// Returned by `MerkleTree::verify_all_leaves`. Every item is checked, whatever the
+/// outcomes before it. The `Display` implementation renders a human-readable summary
+/// listing the first failures; with the `serde` feature the whole report serializes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BulkReport {
+    /// The outcome for each item, in the order the items were given
+    pub checks: Vec<LeafCheck>,
+    /// The number of items that match their leaf
+    pub ok: usize,
+    /// The number of items that do not match their leaf
+    pub hash_mismatches: usize,
+    /// The number of items whose index is out of range
+    pub out_of_range: usize,
+    /// The time the checks took
+    pub elapsed: Duration,
+}
+
+impl BulkReport {
+    /// The maximum number of failures listed by the `Display` implementation
+    pub const MAX_LISTED_FAILURES: usize = 10;
+
+    /// Whether every item matches its leaf
+    ///
+    /// # Returns
+    ///
+    /// True if no check failed
+    pub fn all_ok(&self) -> bool {
+        self.ok == self.checks.len()
+    }
+
+    /// Get the checks that failed
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the failed checks, in order
+    pub fn failures(&self) -> impl Iterator<Item = &LeafCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.outcome != LeafOutcome::Ok)
+    }
+
+    /// Build a report from the outcomes of the checks
+    fn new(checks: Vec<LeafCheck>, elapsed: Duration) -> Self {
+        let count = |outcome| {
+            checks
+                .iter()
+                .filter(|check| check.outcome == outcome)
+                .count()
+        };
+        BulkReport {
+            ok: count(LeafOutcome::Ok),
+            hash_mismatches: count(LeafOutcome::HashMismatch),
+            out_of_range: count(LeafOutcome::IndexOutOfRange),
+            checks,
+            elapsed,
+        }
+    }
+}
+
+impl fmt::Display for BulkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Checked {} items in {:.3?}: {} ok, {} hash mismatches, {} out of range",
+            self.checks.len(),
+            self.elapsed,
+            self.ok,
+            self.hash_mismatches,
+            self.out_of_range
+        )?;
+        for check in self.failures().take(Self::MAX_LISTED_FAILURES) {
+            let reason = match check.outcome {
+                LeafOutcome::HashMismatch => "hash mismatch",
+                _ => "index out of range",
+            };
+            writeln!(f, "  index {}: {}", check.index, reason)?;
+        }
+        let unlisted = self.checks.len() - self.ok;
+        if unlisted > Self::MAX_LISTED_FAILURES {
+            writeln!(f, "  ... and {} more", unlisted - Self::MAX_LISTED_FAILURES)?;
+        }
+        Ok(())
+    }
+}
+
+impl MerkleTree {
+    /// Check many items against the stored leaf hashes in one pass
+    ///
+    /// Each item is hashed as the leaf at its index and compared with the stored hash,
+    /// so no proofs are generated, and hashing reuses one buffer: memory grows with the
+    /// number of items, for the report, but not with their size. Failures are recorded
+    /// and checking continues.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The index and data of each item
+    ///
+    /// # Returns
+    ///
+    /// The report of every outcome
+    pub fn verify_all_leaves<'a>(
+        &self,
+        items: impl Iterator<Item = (usize, &'a [u8])>,
+    ) -> BulkReport {
+        let start = Instant::now();
+        let mut hash = vec![0; self.params().hasher.digest_len()];
+        let checks = items
+            .map(|(index, data)| self.check_leaf(index, data, &mut hash))
+            .collect();
+        BulkReport::new(checks, start.elapsed())
+    }
+
+    /// Check many items against the stored leaf hashes on several threads
+    ///
+    /// The report is the one `verify_all_leaves` gives, with the checks in the order the
+    /// items were given; only the elapsed time differs.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The index and data of each item
+    /// * `threads` - The number of threads to check on (at least one is used)
+    ///
+    /// # Returns
+    ///
+    /// The report of every outcome
+    pub fn verify_all_leaves_parallel<'a>(
+        &self,
+        items: impl Iterator<Item = (usize, &'a [u8])> + Send,
+        threads: usize,
+    ) -> BulkReport {
+        let start = Instant::now();
+        // Workers take the next item under the lock, then hash it without holding it
+        let items = Mutex::new(items.enumerate());
+        let mut checks: Vec<(usize, LeafCheck)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.max(1))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut hash = vec![0; self.params().hasher.digest_len()];
+                        let mut checks = Vec::new();
+                        loop {
+                            let next = items.lock().unwrap_or_else(|e| e.into_inner()).next();
+                            let Some((position, (index, data))) = next else {
+                                return checks;
+                            };
+                            checks.push((position, self.check_leaf(index, data, &mut hash)));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("a verification thread panicked"))
+                .collect()
+        });
+        checks.sort_unstable_by_key(|(position, _)| *position);
+        BulkReport::new(
+            checks.into_iter().map(|(_, check)| check).collect(),
+            start.elapsed(),
+        )
+    }
+
+    /// Check one item against the stored hash of its leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index the item was given for
+    /// * `data` - The item's data
+    /// * `hash` - A buffer of the tree's digest length to hash into
+    ///
+    /// # Returns
+    ///
+    /// The outcome of the check
+    fn check_leaf(&self, index: usize, data: &[u8], hash: &mut [u8]) -> LeafCheck {
+        let outcome = match self.levels().first().filter(|_| index < self.leaf_count()) {
+            None => LeafOutcome::IndexOutOfRange,
+            Some(leaves) => {
+                let params = self.params();
+                let position = (index as u64).to_le_bytes();
+                let prefix: &[u8] = if params.positional { &position } else { &[] };
+                params.hasher.hash_prefixed_leaf_into(prefix, data, hash);
+                if leaves[index] == *hash {
+                    LeafOutcome::Ok
+                } else {
+                    LeafOutcome::HashMismatch
+                }
+            }
+        };
+        LeafCheck { index, outcome }
+    }
+}
@@ -0,0 +1,411 @@
+//! Canonical JSON for proofs and proof bundles, for embedding in signed documents
+//!
+//! The canonical form follows RFC 8785 (the JSON Canonicalization Scheme): no whitespace,
+//! object members sorted by key, strings escaped minimally, and numbers written as plain
+//! integers (the schema has no floating-point fields). Hashes are lowercase hexadecimal.
+//! A proof is an object with a `"steps"` array of `{"is_left", "sibling"}` objects:
+//!
+//! ```json
+//! {"steps":[{"is_left":true,"sibling":"abab"},{"is_left":false,"sibling":"0102"}]}
+//! ```
+//!
+//! A bundle has `"index"`, `"leaf_count"`, `"params"` (as `TreeParams` serializes) and
+//! `"proof"` members, plus `"unauthenticated_metadata"` (hexadecimal) and `"version"`
+//! when present. Parsing accepts any JSON for the same value (whitespace, unsorted keys,
+//! uppercase hex) unless the strict parser is used, which only accepts the canonical
+//! bytes, so a signature over a document can be checked against exactly what was signed.
+
+use crate::error::CanonicalJsonError;
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::params::{TreeParams, TreeVersion};
+use crate::proof::{MerkleProof, ProofBundle};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The canonical schema of a proof
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CanonicalProof {
+    /// The steps from the leaf up to the root
+    steps: Vec<CanonicalStep>,
+}
+
+/// The canonical schema of a proof step
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CanonicalStep {
+    /// Whether the sibling is on the left
+    is_left: bool,
+    /// The sibling hash
+    #[serde(with = "crate::serde_hex")]
+    sibling: Vec<u8>,
+}
+
+/// The canonical schema of a proof bundle
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CanonicalBundle {
+    /// The index of the proven leaf
+    index: usize,
+    /// The number of leaves in the tree
+    leaf_count: usize,
+    /// The parameters of the tree
+    params: TreeParams,
+    /// The proof
+    proof: CanonicalProof,
+    /// Metadata attached to the leaf, which the proof does not authenticate
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_hex::option"
+    )]
+    unauthenticated_metadata: Option<Vec<u8>>,
+    /// The version of the tree, if its parameters are versioned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<TreeVersion>,
+}
+
+impl From<&MerkleProof> for CanonicalProof {
+    fn from(proof: &MerkleProof) -> Self {
+        CanonicalProof {
+            steps: proof
+                .iter()
+                .map(|(sibling, is_left)| CanonicalStep {
+                    is_left: *is_left,
+                    sibling: sibling.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<CanonicalProof> for MerkleProof {
+    fn from(proof: CanonicalProof) -> Self {
+        proof
+            .steps
+            .into_iter()
+            .map(|step| (step.sibling, step.is_left))
+            .collect()
+    }
+}
+
+impl From<&ProofBundle> for CanonicalBundle {
+    fn from(bundle: &ProofBundle) -> Self {
+        CanonicalBundle {
+            index: bundle.index,
+            leaf_count: bundle.leaf_count,
+            params: bundle.params,
+            proof: CanonicalProof::from(&bundle.proof),
+            unauthenticated_metadata: bundle.unauthenticated_metadata.clone(),
+            version: bundle.version,
+        }
+    }
+}
+
+impl From<CanonicalBundle> for ProofBundle {
+    fn from(bundle: CanonicalBundle) -> Self {
+        ProofBundle {
+            version: bundle.version,
+            params: bundle.params,
+            leaf_count: bundle.leaf_count,
+            index: bundle.index,
+            proof: bundle.proof.into(),
+            unauthenticated_metadata: bundle.unauthenticated_metadata,
+        }
+    }
+}
+
+impl MerkleProof {
+    /// Encode the proof as canonical JSON
+    ///
+    /// # Returns
+    ///
+    /// The canonical JSON (see the module documentation for the schema)
+    pub fn to_canonical_json(&self) -> String {
+        canonicalize(&CanonicalProof::from(self))
+    }
+
+    /// Decode a proof from JSON in the canonical schema
+    ///
+    /// Any JSON encoding of the same value is accepted; `to_canonical_json` gives its
+    /// canonical form back.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The JSON
+    ///
+    /// # Returns
+    ///
+    /// The proof, or `CanonicalJsonError::Json` if the JSON does not match the schema
+    pub fn from_canonical_json(json: &str) -> Result<Self, CanonicalJsonError> {
+        Self::from_canonical_json_with(json, &DecodeLimits::default())
+    }
+
+    /// Decode a proof from JSON in the canonical schema within limits
+    ///
+    /// The JSON's length is checked against `max_total_bytes` before it is parsed, and
+    /// the number of steps against `max_proof_depth`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The JSON
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The proof, `CanonicalJsonError::LimitExceeded` if the JSON exceeds a limit, or
+    /// `CanonicalJsonError::Json` if it does not match the schema
+    pub fn from_canonical_json_with(
+        json: &str,
+        limits: &DecodeLimits,
+    ) -> Result<Self, CanonicalJsonError> {
+        let proof = parse::<CanonicalProof>(json, false, limits)?;
+        check(limits, DecodeLimit::ProofDepth, proof.steps.len())?;
+        Ok(proof.into())
+    }
+
+    /// Decode a proof from canonical JSON, rejecting any other encoding
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The canonical JSON
+    ///
+    /// # Returns
+    ///
+    /// The proof, `CanonicalJsonError::Json` if the JSON does not match the schema, or
+    /// `CanonicalJsonError::NotCanonical` if it is not in canonical form
+    pub fn from_canonical_json_strict(json: &str) -> Result<Self, CanonicalJsonError> {
+        let limits = DecodeLimits::default();
+        let proof = parse::<CanonicalProof>(json, true, &limits)?;
+        check(&limits, DecodeLimit::ProofDepth, proof.steps.len())?;
+        Ok(proof.into())
+    }
+
+    /// Get the bytes a JWS signature over the proof signs
+    ///
+    /// These are `BASE64URL(protected_header) || '.' || BASE64URL(to_canonical_json())`,
+    /// the JWS signing input of RFC 7515. With a detached payload the serialized JWS
+    /// omits the middle part, and a verifier recomputes it from the proof.
+    ///
+    /// # Arguments
+    ///
+    /// * `protected_header` - The JWS protected header, as JSON
+    ///
+    /// # Returns
+    ///
+    /// The signing input
+    pub fn signing_input(&self, protected_header: &str) -> Vec<u8> {
+        signing_input(protected_header, &self.to_canonical_json())
+    }
+}
+
+impl ProofBundle {
+    /// Encode the bundle as canonical JSON
+    ///
+    /// # Returns
+    ///
+    /// The canonical JSON (see the module documentation for the schema)
+    pub fn to_canonical_json(&self) -> String {
+        canonicalize(&CanonicalBundle::from(self))
+    }
+
+    /// Decode a bundle from JSON in the canonical schema
+    ///
+    /// Any JSON encoding of the same value is accepted; `to_canonical_json` gives its
+    /// canonical form back.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The JSON
+    ///
+    /// # Returns
+    ///
+    /// The bundle, or `CanonicalJsonError::Json` if the JSON does not match the schema
+    pub fn from_canonical_json(json: &str) -> Result<Self, CanonicalJsonError> {
+        Self::from_canonical_json_with(json, &DecodeLimits::default())
+    }
+
+    /// Decode a bundle from JSON in the canonical schema within limits
+    ///
+    /// The JSON's length is checked against `max_total_bytes` before it is parsed, the
+    /// bundle's leaf count against `max_leaves`, and its proof's steps against
+    /// `max_proof_depth`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The JSON
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The bundle, `CanonicalJsonError::LimitExceeded` if the JSON exceeds a limit, or
+    /// `CanonicalJsonError::Json` if it does not match the schema
+    pub fn from_canonical_json_with(
+        json: &str,
+        limits: &DecodeLimits,
+    ) -> Result<Self, CanonicalJsonError> {
+        let bundle = parse::<CanonicalBundle>(json, false, limits)?;
+        check_bundle(&bundle, limits)?;
+        Ok(bundle.into())
+    }
+
+    /// Decode a bundle from canonical JSON, rejecting any other encoding
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The canonical JSON
+    ///
+    /// # Returns
+    ///
+    /// The bundle, `CanonicalJsonError::Json` if the JSON does not match the schema, or
+    /// `CanonicalJsonError::NotCanonical` if it is not in canonical form
+    pub fn from_canonical_json_strict(json: &str) -> Result<Self, CanonicalJsonError> {
+        let limits = DecodeLimits::default();
+        let bundle = parse::<CanonicalBundle>(json, true, &limits)?;
+        check_bundle(&bundle, &limits)?;
+        Ok(bundle.into())
+    }
+
+    /// Get the bytes a JWS signature over the bundle signs
+    ///
+    /// See `MerkleProof::signing_input`.
+    ///
+    /// # Arguments
+    ///
+    /// * `protected_header` - The JWS protected header, as JSON
+    ///
+    /// # Returns
+    ///
+    /// The signing input
+    pub fn signing_input(&self, protected_header: &str) -> Vec<u8> {
+        signing_input(protected_header, &self.to_canonical_json())
+    }
+}
+
+/// Encode a value of the canonical schema as canonical JSON
+fn canonicalize<T: Serialize>(value: &T) -> String {
+    let value = serde_json::to_value(value).expect("the canonical schema serializes");
+    let mut json = String::new();
+    write_canonical(&value, &mut json);
+    json
+}
+
+/// Decode a value of the canonical schema, optionally requiring canonical form
+///
+/// # Arguments
+///
+/// * `json` - The JSON
+/// * `strict` - Whether anything but the canonical encoding is rejected
+/// * `limits` - The limits the JSON's length is checked against
+///
+/// # Returns
+///
+/// The value, or the reason it cannot be decoded
+fn parse<T: Serialize + DeserializeOwned>(
+    json: &str,
+    strict: bool,
+    limits: &DecodeLimits,
+) -> Result<T, CanonicalJsonError> {
+    check(limits, DecodeLimit::TotalBytes, json.len())?;
+    let value: T = serde_json::from_str(json).map_err(CanonicalJsonError::Json)?;
+    if strict && canonicalize(&value) != json {
+        return Err(CanonicalJsonError::NotCanonical);
+    }
+    Ok(value)
+}
+
+/// Check an amount against a limit
+///
+/// # Arguments
+///
+/// * `limits` - The limits
+/// * `which` - The resource
+/// * `requested` - The amount the JSON asks for
+///
+/// # Returns
+///
+/// Ok, or `CanonicalJsonError::LimitExceeded` if the amount is over the limit
+fn check(
+    limits: &DecodeLimits,
+    which: DecodeLimit,
+    requested: usize,
+) -> Result<(), CanonicalJsonError> {
+    limits
+        .check(which, requested)
+        .map_err(CanonicalJsonError::LimitExceeded)
+}
+
+/// Check a decoded bundle's leaf count and proof depth against limits
+///
+/// # Arguments
+///
+/// * `bundle` - The bundle
+/// * `limits` - The limits
+///
+/// # Returns
+///
+/// Ok, or `CanonicalJsonError::LimitExceeded` if the bundle is over a limit
+fn check_bundle(bundle: &CanonicalBundle, limits: &DecodeLimits) -> Result<(), CanonicalJsonError> {
+    check(limits, DecodeLimit::Leaves, bundle.leaf_count)?;
+    check(limits, DecodeLimit::ProofDepth, bundle.proof.steps.len())
+}
+
+/// Append the RFC 8785 encoding of a JSON value
+///
+/// # Arguments
+///
+/// * `value` - The value, which must not hold floating-point numbers
+/// * `json` - The string to append to
+fn write_canonical(value: &Value, json: &mut String) {
+    match value {
+        Value::Object(members) => {
+            // RFC 8785 sorts members by the UTF-16 code units of their keys
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            json.push('{');
+            for (position, (key, member)) in members.into_iter().enumerate() {
+                if position > 0 {
+                    json.push(',');
+                }
+                json.push_str(&Value::String(key.clone()).to_string());
+                json.push(':');
+                write_canonical(member, json);
+            }
+            json.push('}');
+        }
+        Value::Array(values) => {
+            json.push('[');
+            for (position, value) in values.iter().enumerate() {
+                if position > 0 {
+                    json.push(',');
+                }
+                write_canonical(value, json);
+            }
+            json.push(']');
+        }
+        // serde_json writes literals, integers and minimally escaped strings as RFC 8785 does
+        other => json.push_str(&other.to_string()),
+    }
+}
+
+/// Build a JWS signing input
+///
+/// # Arguments
+///
+/// * `protected_header` - The protected header, as JSON
+/// * `payload` - The payload
+///
+/// # Returns
+///
+/// The base64url encodings of the header and payload, joined by a period
+fn signing_input(protected_header: &str, payload: &str) -> Vec<u8> {
+    format!(
+        "{}.{}",
+        BASE64URL.encode(protected_header),
+        BASE64URL.encode(payload)
+    )
+    .into_bytes()
+}
@@ -0,0 +1,254 @@
+use crate::complete::empty_subtrees;
+use crate::error::AuditError;
+use crate::merkle_tree::{path_in_levels, MerkleTree};
+use crate::params::{PaddingStrategy, TreeParams, TreeVersion};
+use crate::proof::MerkleProof;
+use crate::shared_vec::SharedVec;
+use crate::verify::level_sizes;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The top levels of a tree, from which the upper part of any proof can be assembled
+///
+/// A server holding the canopy and the leaves under one of its lowest nodes can produce
+/// full proofs for those leaves without the rest of the tree: it proves a leaf up to the
+/// canopy node from its own leaves (see `subtree_leaves`) and completes the proof with
+/// `complete_proof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Canopy {
+    /// The version of the tree, if its parameters are versioned
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    version: Option<TreeVersion>,
+    /// The parameters of the tree
+    params: TreeParams,
+    /// The number of leaves in the tree, excluding padding
+    leaf_count: usize,
+    /// The tree level of the lowest canopy level
+    base_level: usize,
+    /// The hashes of the canopy levels, from the lowest up to the root
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::levels"))]
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl Canopy {
+    /// Get the parameters of the tree
+    ///
+    /// # Returns
+    ///
+    /// The parameters the tree was built with
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get the version of the tree
+    ///
+    /// # Returns
+    ///
+    /// The version the tree was built with, or None if it is unversioned
+    pub fn version(&self) -> Option<TreeVersion> {
+        self.version
+    }
+
+    /// Get the number of leaves in the tree
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves, excluding padding
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the tree level of the canopy's lowest level
+    ///
+    /// # Returns
+    ///
+    /// The level, counted from the leaves (level 0)
+    pub fn base_level(&self) -> usize {
+        self.base_level
+    }
+
+    /// Get the number of canopy levels
+    ///
+    /// # Returns
+    ///
+    /// The number of levels, including the root's
+    pub fn height(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Get the root hash of the tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or None if the tree is empty
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.levels.last()?.first().cloned()
+    }
+
+    /// Get the range of leaves under the same lowest canopy node as a leaf
+    ///
+    /// A tree built from the data of these leaves, with the tree's parameters, has the
+    /// canopy node as its root, and its proofs are the lower parts of the full proofs.
+    /// Under `PaddingStrategy::DuplicateLast`, a range holding only the last leaf of a
+    /// larger tree is the exception: that leaf must be paired with a copy of itself, as
+    /// `MerkleTree::split` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaf indices, or None if the index is out of range
+    pub fn subtree_leaves(&self, leaf_index: usize) -> Option<std::ops::Range<usize>> {
+        if leaf_index >= self.leaf_count {
+            return None;
+        }
+        let width = 1usize.checked_shl(self.base_level.try_into().ok()?)?;
+        let start = leaf_index / width * width;
+        Some(start..start.saturating_add(width).min(self.leaf_count))
+    }
+
+    /// Get the upper steps of a leaf's proof, from its lowest canopy node to the root
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof steps, or None if the index is out of range
+    pub fn proof_suffix(&self, leaf_index: usize) -> Option<MerkleProof> {
+        // A deserialized canopy may be malformed, so its shape is checked before use
+        if leaf_index >= self.leaf_count || self.levels.is_empty() {
+            return None;
+        }
+        Some(path_in_levels(
+            &self.levels,
+            0,
+            leaf_index.checked_shr(self.base_level.try_into().ok()?)?,
+            self.params.hasher.ordering,
+        ))
+    }
+
+    /// Splice a lower proof with the canopy into a full proof
+    ///
+    /// # Arguments
+    ///
+    /// * `lower` - The proof from the leaf up to its lowest canopy node
+    /// * `leaf_index` - The index of the leaf in the tree
+    ///
+    /// # Returns
+    ///
+    /// The full proof against the tree's root, or None if the index is out of range
+    pub fn complete_proof(&self, lower: &MerkleProof, leaf_index: usize) -> Option<MerkleProof> {
+        let suffix = self.proof_suffix(leaf_index)?;
+        Some(lower.iter().cloned().chain(suffix).collect())
+    }
+
+    /// Get the hashes of the canopy levels
+    ///
+    /// # Returns
+    ///
+    /// The levels, from the lowest up to the root
+    pub(crate) fn levels(&self) -> &[Vec<Vec<u8>>] {
+        &self.levels
+    }
+
+    /// Recompute every canopy node from the level below and check it against its hash
+    ///
+    /// A deserialized canopy is otherwise used as is, so one from untrusted storage should
+    /// be audited before it completes proofs. Nodes are checked as by `MerkleTree::audit`,
+    /// except that the lowest level has no children to recompute it from, so only its
+    /// padding is checked. Levels and node indices in errors are those of the tree.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the canopy is consistent, or an error describing the first inconsistency
+    pub fn audit(&self) -> Result<(), AuditError> {
+        let sizes = level_sizes(self.leaf_count, self.params.padding);
+        let expected = sizes.get(self.base_level..).unwrap_or_default();
+        if expected.len() != self.levels.len() {
+            return Err(AuditError::HeightMismatch {
+                expected: expected.len(),
+                actual: self.levels.len(),
+            });
+        }
+        for (offset, (nodes, &expected)) in self.levels.iter().zip(expected).enumerate() {
+            if nodes.len() != expected {
+                return Err(AuditError::LevelSizeMismatch {
+                    level: self.base_level + offset,
+                    expected,
+                    actual: nodes.len(),
+                });
+            }
+        }
+
+        let hasher = &self.params.hasher;
+        let empty = match self.params.padding {
+            PaddingStrategy::Complete { depth } => empty_subtrees(hasher, depth),
+            _ => Vec::new(),
+        };
+        for (offset, nodes) in self.levels.iter().enumerate() {
+            let level = self.base_level + offset;
+            for (index, stored) in nodes.iter().enumerate() {
+                let padding = index
+                    .checked_shl(level as u32)
+                    .is_none_or(|first| first >= self.leaf_count);
+                let expected = if let Some(empty) = empty.get(level).filter(|_| padding) {
+                    empty.clone()
+                } else if offset > 0 {
+                    let children = &self.levels[offset - 1];
+                    match children.get(index * 2 + 1) {
+                        Some(right) => hasher.hash_pair(&children[index * 2], right),
+                        None => children[index * 2].clone(),
+                    }
+                } else if level == 0 && padding {
+                    nodes[self.leaf_count - 1].clone()
+                } else {
+                    continue;
+                };
+                if *stored != expected {
+                    return Err(AuditError::NodeMismatch {
+                        level,
+                        index,
+                        expected,
+                        stored: stored.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MerkleTree {
+    /// Export the top levels of the tree
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - The number of levels to export, including the root's (all of them
+    ///   if the tree is not that tall)
+    ///
+    /// # Returns
+    ///
+    /// The canopy
+    pub fn canopy(&self, levels: usize) -> Canopy {
+        let tree_levels = self.levels();
+        let base_level = tree_levels.len().saturating_sub(levels);
+        Canopy {
+            version: self.version(),
+            params: self.params(),
+            leaf_count: self.leaf_count(),
+            base_level,
+            levels: tree_levels[base_level..]
+                .iter()
+                .map(SharedVec::to_vec)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::hashing::HashConfig;
+use crate::proof::MerkleProof;
+use crate::verify::{fold_proof, siblings_fit, MAX_PROOF_STEPS};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A proof through two nested trees
+///
+/// When shard trees are committed to by a top-level tree whose leaves are the shard
+/// roots, a record is proven in two steps: the inner proof leads from the record to its
+/// shard's root, and the outer proof leads from that shard root, as a leaf of the
+/// top-level tree, to the global root.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChainedProof {
+    /// The proof from the record to its shard's root
+    pub inner: MerkleProof,
+    /// The proof from the shard root to the global root
+    pub outer: MerkleProof,
+}
+
+impl ChainedProof {
+    /// Chain a proof within a shard to a proof of the shard's root
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The proof from the record to its shard's root
+    /// * `outer` - The proof from the shard root to the global root
+    ///
+    /// # Returns
+    ///
+    /// The chained proof
+    pub fn new(inner: MerkleProof, outer: MerkleProof) -> Self {
+        ChainedProof { inner, outer }
+    }
+
+    /// Compute the root of the shard the record is proven to be in
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) both trees were built with
+    /// * `data` - The record's data
+    ///
+    /// # Returns
+    ///
+    /// The shard root the inner proof leads to, or None if a sibling hash is not a
+    /// digest of the hasher's length or the inner proof has more than `MAX_PROOF_STEPS`
+    /// steps
+    pub fn shard_root(&self, hasher: impl Into<HashConfig>, data: &[u8]) -> Option<Vec<u8>> {
+        let hasher = hasher.into();
+        (self.inner.len() <= MAX_PROOF_STEPS && siblings_fit(&self.inner, hasher.digest_len()))
+            .then(|| fold_proof(&hasher, hasher.hash_leaf(data), &self.inner))
+    }
+
+    /// Verify the chained proof against the global root
+    ///
+    /// The inner proof is folded into a shard root, which is then hashed as a leaf of the
+    /// top-level tree and folded along the outer proof. Either proof having more than
+    /// `MAX_PROOF_STEPS` steps is rejected without hashing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) both trees were built with
+    /// * `data` - The record's data
+    /// * `global_root` - The root of the top-level tree
+    ///
+    /// # Returns
+    ///
+    /// True if the record is in a shard committed to by the global root, false otherwise
+    pub fn verify(&self, hasher: impl Into<HashConfig>, data: &[u8], global_root: &[u8]) -> bool {
+        let hasher = hasher.into();
+        match self.shard_root(hasher, data) {
+            Some(shard_root) => {
+                self.outer.len() <= MAX_PROOF_STEPS
+                    && siblings_fit(&self.outer, hasher.digest_len())
+                    && fold_proof(&hasher, hasher.hash_leaf(&shard_root), &self.outer)
+                        == global_root
+            }
+            None => false,
+        }
+    }
+}
+
+impl MerkleProof {
+    /// Chain this proof within a shard to a proof of the shard's root
+    ///
+    /// # Arguments
+    ///
+    /// * `outer` - The proof from the shard root to the global root
+    ///
+    /// # Returns
+    ///
+    /// The chained proof
+    pub fn chain(self, outer: MerkleProof) -> ChainedProof {
+        ChainedProof::new(self, outer)
+    }
+}
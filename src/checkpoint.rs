@@ -0,0 +1,369 @@
+use crate::error::MerkleError;
+use crate::hashing::{
+    ByteOrder, EmptyRootPolicy, HashAlgorithm, HashConfig, HashScheme, PairOrdering,
+};
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::stream::RootAccumulator;
+
+/// The bytes every checkpoint starts with
+const MAGIC: &[u8; 4] = b"MTCP";
+
+/// The version of the checkpoint format
+const FORMAT_VERSION: u8 = 2;
+
+/// The length of the checksum ending every checkpoint
+const CHECKSUM_LEN: usize = 32;
+
+/// Computes a tree's root from a stream of leaf hashes that can be stopped and resumed
+///
+/// The builder wraps a `RootAccumulator`. `checkpoint` saves the accumulator's state —
+/// the tree's parameters, the leaf count and the frontier of pending subtree roots — in
+/// a few hundred bytes, and `resume` restores it, so a long ingestion can continue
+/// exactly where it stopped and produce the same root as an uninterrupted one.
+///
+/// A checkpoint holds `"MTCP"`, a format version byte, one byte each for the hash
+/// algorithm, scheme, pair ordering, byte order (absent from version 1 checkpoints,
+/// which always use the natural order), padding (followed by the depth for
+/// `PaddingStrategy::Complete`) and positional flag, the leaf count as a little-endian
+/// `u64`, the number of frontier levels, a presence byte and hash for each level and for
+/// the last leaf, and a SHA-256 checksum of everything before it.
+#[derive(Clone, Debug)]
+pub struct CheckpointedBuilder {
+    accumulator: RootAccumulator,
+}
+
+impl CheckpointedBuilder {
+    /// Create a builder with no leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters of the tree (or just its hash configuration or scheme)
+    ///
+    /// # Returns
+    ///
+    /// A new builder
+    pub fn new(params: impl Into<TreeParams>) -> Self {
+        CheckpointedBuilder {
+            accumulator: RootAccumulator::new(params),
+        }
+    }
+
+    /// Add the next leaf by its hash
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The leaf's hash as the tree stores it (committing to the leaf's index
+    ///   as well in positional trees)
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `MerkleError::InvalidHashLength` if the hash is not as long as the tree's
+    /// digest
+    pub fn push_leaf_hash(&mut self, hash: Vec<u8>) -> Result<(), MerkleError> {
+        let expected = self.accumulator.params().hasher.digest_len();
+        if hash.len() != expected {
+            return Err(MerkleError::InvalidHashLength {
+                expected,
+                actual: hash.len(),
+            });
+        }
+        self.accumulator.push_leaf_hash(hash);
+        Ok(())
+    }
+
+    /// Get the number of leaves added so far
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.accumulator.leaf_count()
+    }
+
+    /// Compute the root of the leaves added so far
+    ///
+    /// # Arguments
+    ///
+    /// * `empty_root` - The policy deciding the root when no leaves were added
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or the root given by the policy if there are no leaves
+    pub fn root(&self, empty_root: EmptyRootPolicy) -> Option<Vec<u8>> {
+        self.accumulator.root(empty_root)
+    }
+
+    /// Take the underlying accumulator
+    ///
+    /// # Returns
+    ///
+    /// The accumulator holding the leaves added so far
+    pub fn into_accumulator(self) -> RootAccumulator {
+        self.accumulator
+    }
+
+    /// Save the builder's state
+    ///
+    /// # Returns
+    ///
+    /// The checkpoint, to be passed to `resume`
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let params = self.accumulator.params();
+        let (frontier, last_leaf) = self.accumulator.parts();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        write_params(&mut bytes, &params);
+        bytes.extend_from_slice(&(self.leaf_count() as u64).to_le_bytes());
+        // A frontier has at most one level per bit of the leaf count
+        bytes.push(frontier.len() as u8);
+        for node in frontier.iter().map(Option::as_deref).chain([last_leaf]) {
+            match node {
+                Some(hash) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(hash);
+                }
+                None => bytes.push(0),
+            }
+        }
+        let checksum = HashAlgorithm::Sha256.digest(&[&bytes]);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+
+    /// Restore a builder from a checkpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - A checkpoint returned by `checkpoint`
+    ///
+    /// # Returns
+    ///
+    /// The builder, or `MerkleError::InvalidCheckpoint` if the checkpoint is corrupted,
+    /// truncated, inconsistent, or uses a hash algorithm this build does not support
+    pub fn resume(bytes: &[u8]) -> Result<Self, MerkleError> {
+        Self::resume_with(bytes, &DecodeLimits::default())
+    }
+
+    /// Restore a builder from a checkpoint within limits
+    ///
+    /// The checkpoint's size is checked against `max_total_bytes` before it is parsed,
+    /// and its leaf count against `max_leaves` once it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - A checkpoint returned by `checkpoint`
+    /// * `limits` - The limits to restore within
+    ///
+    /// # Returns
+    ///
+    /// The builder, `MerkleError::LimitExceeded` if the checkpoint exceeds a limit, or
+    /// `MerkleError::InvalidCheckpoint` if it is corrupted, truncated, inconsistent, or
+    /// uses a hash algorithm this build does not support
+    pub fn resume_with(bytes: &[u8], limits: &DecodeLimits) -> Result<Self, MerkleError> {
+        limits.check(DecodeLimit::TotalBytes, bytes.len())?;
+        let builder =
+            Self::parse(bytes).map_err(|reason| MerkleError::InvalidCheckpoint { reason })?;
+        limits.check(DecodeLimit::Leaves, builder.leaf_count())?;
+        Ok(builder)
+    }
+
+    /// Parse a checkpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The checkpoint
+    ///
+    /// # Returns
+    ///
+    /// The builder, or the reason the checkpoint is invalid
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let body_len = bytes
+            .len()
+            .checked_sub(CHECKSUM_LEN)
+            .ok_or_else(|| String::from("truncated"))?;
+        let (body, checksum) = bytes.split_at(body_len);
+        if HashAlgorithm::Sha256.digest(&[body]) != checksum {
+            return Err(String::from("checksum mismatch"));
+        }
+
+        let mut reader = Reader(body);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(String::from("not a checkpoint"));
+        }
+        let version = reader.byte()?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(format!("unsupported format version {}", version));
+        }
+        // Version 1 predates the byte order field
+        let params = read_params(&mut reader, version > 1)?;
+
+        let leaf_count = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+        let leaf_count =
+            usize::try_from(leaf_count).map_err(|_| String::from("leaf count too large"))?;
+        let levels = usize::from(reader.byte()?);
+        let digest_len = params.hasher.digest_len();
+        let mut frontier = Vec::with_capacity(levels);
+        for _ in 0..levels {
+            frontier.push(reader.node(digest_len)?);
+        }
+        let last_leaf = reader.node(digest_len)?;
+        if !reader.0.is_empty() {
+            return Err(String::from("trailing bytes"));
+        }
+
+        // The frontier holds a subtree of 2^h leaves exactly where bit h of the count is set
+        let consistent = levels == (usize::BITS - leaf_count.leading_zeros()) as usize
+            && frontier
+                .iter()
+                .enumerate()
+                .all(|(height, node)| node.is_some() == (leaf_count >> height & 1 == 1))
+            && last_leaf.is_some() == (leaf_count > 0);
+        if !consistent {
+            return Err(String::from("frontier does not match the leaf count"));
+        }
+
+        Ok(CheckpointedBuilder {
+            accumulator: RootAccumulator::from_parts(params, frontier, last_leaf, leaf_count),
+        })
+    }
+}
+
+/// Reads the fields of a checkpoint or snapshot in order
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Read a number of bytes
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < len {
+            return Err(String::from("truncated"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    /// Read one byte
+    pub(crate) fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read a presence byte and, if present, a hash
+    pub(crate) fn node(&mut self, digest_len: usize) -> Result<Option<Vec<u8>>, String> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.take(digest_len)?.to_vec())),
+            _ => Err(String::from("invalid presence byte")),
+        }
+    }
+}
+
+/// Get the checkpoint code of a hash algorithm
+fn algorithm_code(algorithm: HashAlgorithm) -> u8 {
+    match algorithm {
+        HashAlgorithm::Sha256 => 0,
+        HashAlgorithm::Sha512 => 1,
+        #[cfg(feature = "keccak")]
+        HashAlgorithm::Keccak256 => 2,
+        #[cfg(feature = "ripemd")]
+        HashAlgorithm::Ripemd160 => 3,
+    }
+}
+
+/// Get the hash algorithm with a checkpoint code
+fn algorithm_from_code(code: u8) -> Result<HashAlgorithm, String> {
+    match code {
+        0 => Ok(HashAlgorithm::Sha256),
+        1 => Ok(HashAlgorithm::Sha512),
+        #[cfg(feature = "keccak")]
+        2 => Ok(HashAlgorithm::Keccak256),
+        #[cfg(feature = "ripemd")]
+        3 => Ok(HashAlgorithm::Ripemd160),
+        _ => Err(format!("unsupported hash algorithm {}", code)),
+    }
+}
+
+/// Append the encoding of a tree's parameters
+///
+/// Checkpoints and snapshots share this encoding: one byte each for the hash algorithm,
+/// scheme, pair ordering, byte order, padding (followed by the depth for
+/// `PaddingStrategy::Complete`) and positional flag.
+///
+/// # Arguments
+///
+/// * `bytes` - The buffer to append to
+/// * `params` - The parameters
+pub(crate) fn write_params(bytes: &mut Vec<u8>, params: &TreeParams) {
+    bytes.push(algorithm_code(params.hasher.algorithm));
+    bytes.push(match params.hasher.scheme {
+        HashScheme::Plain => 0,
+        HashScheme::DomainSeparated => 1,
+    });
+    bytes.push(match params.hasher.ordering {
+        PairOrdering::Positional => 0,
+        PairOrdering::Sorted => 1,
+    });
+    bytes.push(match params.hasher.byte_order {
+        ByteOrder::Natural => 0,
+        ByteOrder::Reversed => 1,
+    });
+    match params.padding {
+        PaddingStrategy::DuplicateLast => bytes.push(0),
+        PaddingStrategy::CarryUp => bytes.push(1),
+        PaddingStrategy::Complete { depth } => bytes.extend_from_slice(&[2, depth]),
+    }
+    bytes.push(u8::from(params.positional));
+}
+
+/// Read parameters encoded by `write_params`
+///
+/// # Arguments
+///
+/// * `reader` - The reader, positioned at the parameters
+/// * `has_byte_order` - Whether the byte order field is present (it is absent from
+///   version 1 checkpoints, which always use the natural order)
+///
+/// # Returns
+///
+/// The parameters, or the reason they cannot be read
+pub(crate) fn read_params(reader: &mut Reader, has_byte_order: bool) -> Result<TreeParams, String> {
+    let algorithm = algorithm_from_code(reader.byte()?)?;
+    let scheme = match reader.byte()? {
+        0 => HashScheme::Plain,
+        1 => HashScheme::DomainSeparated,
+        _ => return Err(String::from("unknown hash scheme")),
+    };
+    let ordering = match reader.byte()? {
+        0 => PairOrdering::Positional,
+        1 => PairOrdering::Sorted,
+        _ => return Err(String::from("unknown pair ordering")),
+    };
+    let byte_order = if has_byte_order {
+        match reader.byte()? {
+            0 => ByteOrder::Natural,
+            1 => ByteOrder::Reversed,
+            _ => return Err(String::from("unknown byte order")),
+        }
+    } else {
+        ByteOrder::Natural
+    };
+    let padding = match reader.byte()? {
+        0 => PaddingStrategy::DuplicateLast,
+        1 => PaddingStrategy::CarryUp,
+        2 => PaddingStrategy::Complete {
+            depth: reader.byte()?,
+        },
+        _ => return Err(String::from("unknown padding")),
+    };
+    let positional = match reader.byte()? {
+        0 => false,
+        1 => true,
+        _ => return Err(String::from("invalid positional flag")),
+    };
+    Ok(TreeParams {
+        hasher: HashConfig::new(algorithm, scheme, ordering).with_byte_order(byte_order),
+        padding,
+        positional,
+    })
+}
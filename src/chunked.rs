@@ -0,0 +1,296 @@
+//! Trees over the fixed-size chunks of a file
+//!
+//! A file is split into chunks of a chosen size, every chunk but the last being full,
+//! and each chunk is a leaf. The tree records the chunk size and the file's length, so
+//! a byte offset can be mapped to its chunk and every chunk's expected length is known.
+
+use crate::hashing::{HashConfig, HashScheme};
+use crate::merkle_tree::MerkleTree;
+use crate::params::TreeParams;
+use crate::proof::MerkleProof;
+use crate::verify::{fold_proof, proof_fits_shape, proof_leaf_index, siblings_fit};
+use std::io::Read;
+
+/// A Merkle tree whose leaves are the chunks of a file
+///
+/// Chunks are hashed with SHA-256 and domain separation, like `MerkleTree::builder()`,
+/// but only their hashes are kept, so building the tree of a large file needs memory
+/// for one chunk and the tree's hashes.
+#[derive(Debug)]
+pub struct ChunkedFileTree {
+    tree: MerkleTree,
+    chunk_size: usize,
+    file_len: u64,
+}
+
+impl ChunkedFileTree {
+    /// Build the tree of a file read to its end
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The file's contents
+    /// * `chunk_size` - The length of every chunk but the last
+    ///
+    /// # Returns
+    ///
+    /// The tree, or the error that interrupted reading
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0
+    pub fn from_reader(reader: impl Read, chunk_size: usize) -> std::io::Result<Self> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        let (leaf_hashes, file_len) =
+            read_chunk_hashes(reader, chunk_size, &Self::params().hasher)?;
+        Ok(ChunkedFileTree {
+            tree: MerkleTree::from_leaf_hashes(leaf_hashes, Self::params()),
+            chunk_size,
+            file_len,
+        })
+    }
+
+    /// Build the tree of a file held in memory
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The file's contents
+    /// * `chunk_size` - The length of every chunk but the last
+    ///
+    /// # Returns
+    ///
+    /// The tree
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0
+    pub fn from_bytes(bytes: &[u8], chunk_size: usize) -> Self {
+        Self::from_reader(bytes, chunk_size).expect("reading from a slice cannot fail")
+    }
+
+    /// The parameters chunk trees are built with
+    fn params() -> TreeParams {
+        TreeParams::from(HashScheme::DomainSeparated)
+    }
+
+    /// Get the root hash of the tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or None for an empty file
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.tree.root_hash()
+    }
+
+    /// Get the length of every chunk but the last
+    ///
+    /// # Returns
+    ///
+    /// The chunk size
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Get the length of the file
+    ///
+    /// # Returns
+    ///
+    /// The file's length in bytes
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// Get the number of chunks
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks, and so of leaves
+    pub fn chunk_count(&self) -> usize {
+        self.tree.leaf_count()
+    }
+
+    /// Get the tree of chunk hashes
+    ///
+    /// # Returns
+    ///
+    /// The underlying tree, whose leaves hold no data
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Get the expected length of a chunk
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the chunk
+    ///
+    /// # Returns
+    ///
+    /// The chunk's length, shorter than the chunk size only for a partial last chunk,
+    /// or None if the index is out of range
+    pub fn chunk_len(&self, index: usize) -> Option<usize> {
+        let start = self.chunk_start(index)?;
+        let remaining = self.file_len - start;
+        Some(remaining.min(self.chunk_size as u64) as usize)
+    }
+
+    /// Get the offset of a chunk's first byte
+    fn chunk_start(&self, index: usize) -> Option<u64> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+        Some(index as u64 * self.chunk_size as u64)
+    }
+
+    /// Generate a proof for the chunk holding a byte
+    ///
+    /// # Arguments
+    ///
+    /// * `byte_offset` - The offset of the byte in the file
+    ///
+    /// # Returns
+    ///
+    /// The chunk's index and its proof, or None if the offset is past the end of the file
+    pub fn proof_for_offset(&self, byte_offset: u64) -> Option<(usize, MerkleProof)> {
+        if byte_offset >= self.file_len {
+            return None;
+        }
+        let index = (byte_offset / self.chunk_size as u64) as usize;
+        Some((index, self.tree.proof_by_index(index)?))
+    }
+
+    /// Verify one chunk read from a reader against a root
+    ///
+    /// The chunk is hashed a buffer at a time, and reading stops one byte past the
+    /// chunk's expected length, so a reader yielding extra bytes is rejected without
+    /// reading the rest.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the chunk
+    /// * `reader` - The chunk's bytes
+    /// * `proof` - The chunk's proof
+    /// * `root` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// Whether the chunk has its expected length and its proof leads to the root, or the
+    /// error that interrupted reading
+    pub fn verify_chunk_from_reader(
+        &self,
+        index: usize,
+        reader: impl Read,
+        proof: &MerkleProof,
+        root: &[u8],
+    ) -> std::io::Result<bool> {
+        let Some(len) = self.chunk_len(index) else {
+            return Ok(false);
+        };
+        let leaf_count = self.chunk_count();
+        let params = self.tree.params();
+        if !proof_fits_shape(proof, leaf_count, params.padding, params.hasher.ordering)
+            || proof_leaf_index(proof, leaf_count, params.padding) != Some(index)
+        {
+            return Ok(false);
+        }
+
+        let (leaf_hash, read) = params
+            .hasher
+            .hash_leaf_reader_counted(reader.take(len as u64 + 1))?;
+        Ok(read == len as u64
+            && siblings_fit(proof, params.hasher.digest_len())
+            && fold_proof(&params.hasher, leaf_hash, proof) == root)
+    }
+
+    /// Verify a run of whole chunks against a root
+    ///
+    /// `bytes` must be the chunks holding `byte_start` and the bytes after it, whole,
+    /// so `byte_start` is rounded down to the start of its chunk. Each chunk must have
+    /// its expected length, so a partial last chunk cannot be extended with padding, and
+    /// each proof must be for its chunk's index in this file's tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `byte_start` - The offset of a byte in the first chunk
+    /// * `bytes` - The chunks, concatenated
+    /// * `proofs` - The proof of each chunk, in order
+    /// * `root` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if every chunk has its expected length and its proof leads to the root
+    pub fn verify_range(
+        &self,
+        byte_start: u64,
+        bytes: &[u8],
+        proofs: &[MerkleProof],
+        root: &[u8],
+    ) -> bool {
+        if byte_start >= self.file_len || bytes.is_empty() {
+            return false;
+        }
+        let first = (byte_start / self.chunk_size as u64) as usize;
+        let leaf_count = self.chunk_count();
+        let padding = self.tree.params().padding;
+
+        let mut rest = bytes;
+        for (index, proof) in (first..).zip(proofs) {
+            let Some(len) = self.chunk_len(index) else {
+                return false;
+            };
+            if rest.len() < len
+                || proof_leaf_index(proof, leaf_count, padding) != Some(index)
+                || !self
+                    .tree
+                    .verify_against(&rest[..len], proof, root, leaf_count)
+            {
+                return false;
+            }
+            rest = &rest[len..];
+        }
+        // Every byte must be covered by a proof
+        rest.is_empty()
+    }
+}
+
+/// Hash the chunks of a file read to its end
+///
+/// # Arguments
+///
+/// * `reader` - The file's contents
+/// * `chunk_size` - The length of every chunk but the last, which must be positive
+/// * `hasher` - The hash configuration the chunks are hashed with as leaves
+///
+/// # Returns
+///
+/// The leaf hash of every chunk and the file's length, or the error that interrupted
+/// reading
+pub(crate) fn read_chunk_hashes(
+    mut reader: impl Read,
+    chunk_size: usize,
+    hasher: &HashConfig,
+) -> std::io::Result<(Vec<Vec<u8>>, u64)> {
+    let mut chunk = vec![0; chunk_size];
+    let mut leaf_hashes = Vec::new();
+    let mut file_len = 0;
+    loop {
+        // Fill the chunk, since a reader may return fewer bytes than requested
+        let mut filled = 0;
+        while filled < chunk_size {
+            match reader.read(&mut chunk[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        leaf_hashes.push(hasher.hash_leaf(&chunk[..filled]));
+        file_len += filled as u64;
+        if filled < chunk_size {
+            break;
+        }
+    }
+    Ok((leaf_hashes, file_len))
+}
@@ -0,0 +1,198 @@
+//! Committing to a set now and revealing chosen items later
+//!
+//! One party publishes a `Commitment` to a tree and later hands over `Reveal`s of some
+//! of its leaves. The other party accepts each reveal at most once per index, so a
+//! replayed reveal is rejected however the reveals are ordered.
+
+use crate::error::{RevealError, VerificationError};
+use crate::hashing::PairOrdering;
+use crate::merkle_tree::MerkleTree;
+use crate::params::TreeParams;
+use crate::proof::MerkleProof;
+use crate::verify::{
+    fold_proof, positional_leaf, proof_fits_shape, proof_leaf_index, siblings_fit,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A commitment to a tree, and the indices revealed against it so far
+///
+/// With the `serde` feature the commitment serializes with the indices already
+/// accepted, so a verifier can persist its state between reveals.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Commitment {
+    /// The committed root hash
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    root: Vec<u8>,
+    /// The number of leaves committed to
+    leaf_count: usize,
+    /// The parameters the tree was built with
+    params: TreeParams,
+    /// The indices accepted so far
+    #[cfg_attr(feature = "serde", serde(default))]
+    revealed: BTreeSet<usize>,
+}
+
+/// A leaf revealed against a commitment: its index, data and proof
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Reveal {
+    /// The index of the leaf
+    pub index: usize,
+    /// The leaf's data
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub data: Vec<u8>,
+    /// The proof of the leaf
+    pub proof: MerkleProof,
+}
+
+impl Commitment {
+    /// Commit to a tree
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The tree to commit to
+    ///
+    /// # Returns
+    ///
+    /// A commitment to the tree's root, leaf count and parameters with nothing revealed,
+    /// or None if the tree is empty
+    pub fn new(tree: &MerkleTree) -> Option<Self> {
+        if tree.is_empty() {
+            return None;
+        }
+        Some(Commitment {
+            root: tree.root_hash()?,
+            leaf_count: tree.leaf_count(),
+            params: tree.params(),
+            revealed: BTreeSet::new(),
+        })
+    }
+
+    /// Verify a reveal and record its index
+    ///
+    /// Under `PairOrdering::Sorted` a proof carries no directions, so the index is bound
+    /// to the data only in positional trees; otherwise the same leaf can be revealed under
+    /// different indices.
+    ///
+    /// # Arguments
+    ///
+    /// * `reveal` - The reveal to verify
+    ///
+    /// # Returns
+    ///
+    /// Ok if the reveal is valid and its index was not revealed before, or the reason it
+    /// was rejected (a rejected reveal is not recorded)
+    pub fn accept(&mut self, reveal: &Reveal) -> Result<(), RevealError> {
+        if reveal.index >= self.leaf_count {
+            return Err(RevealError::IndexOutOfRange {
+                index: reveal.index,
+                leaf_count: self.leaf_count,
+            });
+        }
+        if self.revealed.contains(&reveal.index) {
+            return Err(RevealError::AlreadyRevealed {
+                index: reveal.index,
+            });
+        }
+        self.check(reveal).map_err(RevealError::InvalidProof)?;
+        self.revealed.insert(reveal.index);
+        Ok(())
+    }
+
+    /// Check that a reveal's proof leads from its data at its index to the root
+    fn check(&self, reveal: &Reveal) -> Result<(), VerificationError> {
+        let hasher = self.params.hasher;
+        let proof = &reveal.proof;
+        if !proof_fits_shape(proof, self.leaf_count, self.params.padding, hasher.ordering)
+            || !siblings_fit(proof, hasher.digest_len())
+        {
+            return Err(VerificationError::ShapeMismatch {
+                steps: proof.len(),
+                leaf_count: self.leaf_count,
+            });
+        }
+        if hasher.ordering == PairOrdering::Positional {
+            let index =
+                proof_leaf_index(proof, self.leaf_count, self.params.padding).unwrap_or(usize::MAX);
+            if index != reveal.index {
+                return Err(VerificationError::IndexMismatch {
+                    expected: reveal.index,
+                    actual: index,
+                });
+            }
+        }
+
+        let leaf_hash = if self.params.positional {
+            hasher.hash_leaf(&positional_leaf(reveal.index, &reveal.data))
+        } else {
+            hasher.hash_leaf(&reveal.data)
+        };
+        if fold_proof(&hasher, leaf_hash, proof) != self.root {
+            return Err(VerificationError::RootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Get the committed root hash
+    ///
+    /// # Returns
+    ///
+    /// The root hash
+    pub fn root(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// Get the number of leaves committed to
+    ///
+    /// # Returns
+    ///
+    /// The leaf count
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the parameters the committed tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The tree's parameters
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get the indices accepted so far
+    ///
+    /// # Returns
+    ///
+    /// The revealed indices, in ascending order
+    pub fn revealed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.revealed.iter().copied()
+    }
+}
+
+/// Reveal leaves of a tree
+///
+/// # Arguments
+///
+/// * `tree` - The committed tree
+/// * `indices` - The indices of the leaves to reveal
+///
+/// # Returns
+///
+/// A reveal of each leaf in the order of `indices`, or None if an index is out of range
+/// or its data was cleared
+pub fn reveal(tree: &MerkleTree, indices: &[usize]) -> Option<Vec<Reveal>> {
+    indices
+        .iter()
+        .map(|&index| {
+            Some(Reveal {
+                index,
+                data: tree.get_leaf(index)?.to_vec(),
+                proof: tree.proof_by_index(index)?,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+
+/// A scheme committing to a sequence of items that can prove and verify membership by index
+///
+/// This lets code holding commitments, such as a storage layer picking a `MerkleTree`
+/// for large datasets and a `HashChain` for small, strictly ordered ones, work with
+/// either scheme without branching on the concrete type. Proofs are only meaningful for
+/// the scheme that produced them, so each scheme has its own proof type.
+pub trait CommitmentScheme {
+    /// The proof that an item is committed to
+    type Proof;
+
+    /// Get the root the commitment publishes
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or None if nothing is committed to
+    fn root(&self) -> Option<Vec<u8>>;
+
+    /// Prove the item at an index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the item
+    ///
+    /// # Returns
+    ///
+    /// The proof, or None if the index is out of range
+    fn prove(&self, index: usize) -> Option<Self::Proof>;
+
+    /// Verify that a proof leads from an item to a root
+    ///
+    /// The commitment supplies the scheme's parameters; the root may be one it published
+    /// earlier, or another party's.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The item
+    /// * `proof` - The proof
+    /// * `root` - The root the proof should lead to
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    fn verify(&self, data: &[u8], proof: &Self::Proof, root: &[u8]) -> bool;
+}
+
+/// Proofs are `generate_proof_at` proofs, checked against roots with as many
+/// leaves as the tree has
+impl CommitmentScheme for MerkleTree {
+    type Proof = MerkleProof;
+
+    fn root(&self) -> Option<Vec<u8>> {
+        self.root_hash()
+    }
+
+    fn prove(&self, index: usize) -> Option<MerkleProof> {
+        self.proof_by_index(index)
+    }
+
+    fn verify(&self, data: &[u8], proof: &MerkleProof, root: &[u8]) -> bool {
+        self.verify_against(data, proof, root, self.leaf_count())
+    }
+}
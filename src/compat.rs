@@ -0,0 +1,181 @@
+//! Presets for interoperating with other Merkle tree libraries
+//!
+//! A preset gives the parameters and leaf layout under which this crate's trees have the
+//! same roots as another library's, and converts proofs between the two representations.
+
+use crate::error::ProofDecodeError;
+use crate::hashing::HashScheme;
+#[cfg(feature = "build")]
+use crate::merkle_tree::{BuildOptions, MerkleTree};
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::proof::MerkleProof;
+use crate::verify::MAX_PROOF_STEPS;
+
+/// Another Merkle tree library whose roots and proofs this crate can reproduce
+///
+/// A tree built with `MerkleTree::new_compat` holds the padding leaves as well as the
+/// real ones, so its `leaf_count` and `get_leaf` describe the padded tree. Keep the
+/// number of real leaves alongside it; the real leaves are its first ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Compat {
+    /// `github.com/cbergoon/merkletree` for Go
+    ///
+    /// Leaves are hashed as SHA-256 of their content, like the package's example
+    /// `CalculateHash`, and branches as SHA-256 of the two child hashes concatenated.
+    /// Unlike `PaddingStrategy::DuplicateLast`, the Go package pairs the last node of
+    /// every odd level with itself, not just the last leaf, and hashes a single leaf with
+    /// a copy of itself. A proof is a list of sibling hashes from the leaf up and a list
+    /// of directions, 1 for a sibling on the right and 0 for one on the left.
+    ///
+    /// Byte parity with the package itself is not yet confirmed: the fixture test is
+    /// ignored until `fixtures/cbergoon/vectors.json` is generated by the Go program next
+    /// to it.
+    CbergoonGo,
+}
+
+impl Compat {
+    /// Get the parameters of a tree compatible with the library
+    ///
+    /// The tree must also be built over `pad_leaves`, as `MerkleTree::new_compat` does.
+    ///
+    /// # Returns
+    ///
+    /// The tree parameters
+    pub fn params(self) -> TreeParams {
+        match self {
+            Compat::CbergoonGo => TreeParams {
+                padding: PaddingStrategy::DuplicateLast,
+                ..TreeParams::new(HashScheme::Plain, false)
+            },
+        }
+    }
+
+    /// Pad leaves the way the library pads the levels above them
+    ///
+    /// The Go package pairs the last node of an odd level with itself, which gives the
+    /// same hashes as repeating the leaves below that node. Padding the leaves this way
+    /// takes their number to a power of two (at least 2), and a tree built over the
+    /// padded leaves with `params` has the library's root; each real leaf keeps its index
+    /// and its proof. Use this with `MerkleTree::from_leaf_hashes` when the Go side's
+    /// `CalculateHash` is not plain SHA-256 of the content.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The leaves, or their hashes, in order
+    ///
+    /// # Returns
+    ///
+    /// The padded leaves, or no leaves if there were none
+    pub fn pad_leaves<T: Clone>(self, mut leaves: Vec<T>) -> Vec<T> {
+        match self {
+            Compat::CbergoonGo => {
+                if leaves.len() == 1 {
+                    leaves.push(leaves[0].clone());
+                }
+                // Every level's nodes cover `span` leaves each
+                let mut span = 1;
+                while leaves.len() > span {
+                    if (leaves.len() / span) % 2 == 1 {
+                        leaves.extend_from_within(leaves.len() - span..);
+                    }
+                    span *= 2;
+                }
+                leaves
+            }
+        }
+    }
+
+    /// Convert a proof from the library's representation
+    ///
+    /// The proof verifies with the hash configuration of `params`, for the leaf's data
+    /// and the library's root, using any of the verification functions that do not
+    /// check the proof's shape against a leaf count of the unpadded tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The sibling hashes, from the leaf up, as returned by `GetMerklePath`
+    /// * `directions` - The direction of each sibling, as returned by `GetMerklePath`
+    ///
+    /// # Returns
+    ///
+    /// The proof, or an error if the lists differ in length, a direction is neither 0
+    /// nor 1, or the proof is too long to verify
+    pub fn import_proof(
+        self,
+        path: Vec<Vec<u8>>,
+        directions: &[i64],
+    ) -> Result<MerkleProof, ProofDecodeError> {
+        match self {
+            Compat::CbergoonGo => {
+                if path.len() != directions.len() {
+                    return Err(ProofDecodeError::DirectionCountMismatch {
+                        steps: path.len(),
+                        directions: directions.len(),
+                    });
+                }
+                if path.len() > MAX_PROOF_STEPS {
+                    return Err(ProofDecodeError::TooDeep {
+                        max: MAX_PROOF_STEPS,
+                    });
+                }
+                let steps = path
+                    .into_iter()
+                    .zip(directions)
+                    .map(|(sibling, &direction)| match direction {
+                        0 => Ok((sibling, true)),
+                        1 => Ok((sibling, false)),
+                        value => Err(ProofDecodeError::InvalidDirectionValue { value }),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(MerkleProof::new(steps))
+            }
+        }
+    }
+
+    /// Convert a proof to the library's representation
+    ///
+    /// Where a node and its sibling have the same hash, the Go package reports the
+    /// sibling on the right whatever its position; the exported proof gives its real
+    /// position, and both lead to the same root.
+    ///
+    /// # Arguments
+    ///
+    /// * `proof` - The proof, from a tree built with `MerkleTree::new_compat`
+    ///
+    /// # Returns
+    ///
+    /// The sibling hashes from the leaf up and the direction of each
+    pub fn export_proof(self, proof: &MerkleProof) -> (Vec<Vec<u8>>, Vec<i64>) {
+        match self {
+            Compat::CbergoonGo => proof
+                .iter()
+                .map(|(sibling, is_left)| (sibling.clone(), if *is_left { 0 } else { 1 }))
+                .unzip(),
+        }
+    }
+}
+
+#[cfg(feature = "build")]
+impl MerkleTree {
+    /// Create a tree with the same root as another library's tree over the same leaves
+    ///
+    /// The tree is built over `compat.pad_leaves(data_items)` with `compat.params()`, so
+    /// its leaves include the padding: `leaf_count` is a power of two rather than the
+    /// number of data items, `get_leaf` returns padding leaves past them, and the real
+    /// leaves keep their indices. Proofs for the real leaves are the library's proofs,
+    /// converted with `Compat::export_proof`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - The content of the leaves, in order
+    /// * `compat` - The library to be compatible with
+    ///
+    /// # Returns
+    ///
+    /// The tree, which is empty if there are no leaves
+    pub fn new_compat(data_items: Vec<Vec<u8>>, compat: Compat) -> Self {
+        let leaves = compat.pad_leaves(data_items);
+        MerkleTree::build_with(leaves, compat.params(), BuildOptions::default(), None)
+            .unwrap_or_else(|_| unreachable!("duplicate leaves are only rejected on request"))
+    }
+}
@@ -0,0 +1,109 @@
+//! Complete trees of a fixed depth, padded with a well-known empty leaf
+//!
+//! Circuit verifiers usually expect a complete binary tree of a fixed depth in which the
+//! missing leaves are a constant. `MerkleTree::new_complete` builds such a tree with
+//! `PaddingStrategy::Complete`: every odd-sized level below the root is padded with the
+//! root of an empty subtree of that height, so every proof has exactly `depth` steps.
+//! The empty subtree roots are precomputed, so padding costs one hash per level rather
+//! than one per missing leaf.
+
+use crate::error::MerkleError;
+use crate::hashing::{HashConfig, HashScheme, NodeHasher};
+use crate::merkle_tree::{BuildOptions, MerkleTree};
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::scheme::EMPTY_LEAF_DATA;
+use crate::verify::MAX_PROOF_STEPS;
+
+/// Compute the root of an empty subtree of every height up to a depth
+///
+/// The empty subtree of height 0 is the leaf hash of `EMPTY_LEAF_DATA`, and each taller
+/// one is the hash of two copies of the one below it. A circuit verifying proofs from a
+/// complete tree needs exactly these constants.
+///
+/// # Arguments
+///
+/// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees)
+/// * `depth` - The greatest height to compute
+///
+/// # Returns
+///
+/// `depth + 1` hashes, indexed by height
+pub fn empty_subtree_hashes(hasher: impl Into<HashConfig>, depth: u8) -> Vec<Vec<u8>> {
+    empty_subtrees(&hasher.into(), depth)
+}
+
+/// Compute the root of an empty subtree of every height up to a depth
+///
+/// # Arguments
+///
+/// * `hasher` - The hasher of the tree
+/// * `depth` - The greatest height to compute
+///
+/// # Returns
+///
+/// `depth + 1` hashes, indexed by height
+pub(crate) fn empty_subtrees(hasher: &impl NodeHasher, depth: u8) -> Vec<Vec<u8>> {
+    let mut hashes = Vec::with_capacity(usize::from(depth) + 1);
+    hashes.push(hasher.hash_leaf(&EMPTY_LEAF_DATA));
+    for height in 0..usize::from(depth) {
+        hashes.push(hasher.hash_pair(&hashes[height], &hashes[height]));
+    }
+    hashes
+}
+
+/// Check that a number of leaves fits a tree with the given padding
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves
+/// * `padding` - The padding strategy of the tree
+///
+/// # Returns
+///
+/// Ok unless the tree is complete and its depth is too large to verify or too small to
+/// hold the leaves
+pub(crate) fn check_capacity(
+    leaf_count: usize,
+    padding: PaddingStrategy,
+) -> Result<(), MerkleError> {
+    let PaddingStrategy::Complete { depth } = padding else {
+        return Ok(());
+    };
+    if usize::from(depth) > MAX_PROOF_STEPS {
+        return Err(MerkleError::DepthTooLarge {
+            depth,
+            max: MAX_PROOF_STEPS,
+        });
+    }
+    if u32::from(depth) < usize::BITS && leaf_count > 1 << depth {
+        return Err(MerkleError::TooManyLeaves { leaf_count, depth });
+    }
+    Ok(())
+}
+
+impl MerkleTree {
+    /// Create a complete tree of a fixed depth, padded with `EMPTY_LEAF`
+    ///
+    /// Leaves are hashed like `MerkleTree::new` hashes them, and the tree has
+    /// `PaddingStrategy::Complete` padding. Its root is the root of a tree of `2^depth`
+    /// leaves whose missing leaves are all `EMPTY_LEAF`, and every proof has `depth`
+    /// steps. An empty complete tree has the root of an empty subtree of height `depth`.
+    /// A light client checks its proofs with a `MerkleVerifier` given the tree's
+    /// parameters (`MerkleVerifier::with_params`), which include the depth.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - The data of the real leaves, at most `2^depth` of them
+    /// * `depth` - The depth of the tree, at most `MAX_PROOF_STEPS`
+    ///
+    /// # Returns
+    ///
+    /// The tree, or `MerkleError::TooManyLeaves` or `MerkleError::DepthTooLarge`
+    pub fn new_complete(data_items: Vec<Vec<u8>>, depth: u8) -> Result<Self, MerkleError> {
+        let params = TreeParams {
+            padding: PaddingStrategy::Complete { depth },
+            ..TreeParams::new(HashScheme::Plain, false)
+        };
+        MerkleTree::build_with(data_items, params, BuildOptions::default(), None)
+    }
+}
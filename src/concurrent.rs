@@ -0,0 +1,197 @@
+use crate::error::MerkleError;
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// The number of versions kept by default for `proof_at_version`
+const DEFAULT_HISTORY: usize = 16;
+
+/// A Merkle tree shared between one or more writers and many readers
+///
+/// Readers take an `Arc` snapshot of the current tree and generate proofs from it
+/// without holding any lock. Writers clone the current tree, apply their change to the
+/// clone (rehashing only the changed leaf's path) and publish the result as a new
+/// version, so a reader always sees either the old tree or the new one, never a mix.
+/// Writers are serialized with each other but never wait for readers.
+///
+/// A tree's leaf data, level hashes and hash index are shared between its clones and
+/// copied only along the paths a write touches, so a write costs `O(log n)` rather than
+/// a copy of the tree. An operation log (see `MerkleTreeBuilder::operation_log`), leaf metadata
+/// and a deduplicating leaf pool are still copied in full.
+///
+/// Recent versions are retained so proofs can be generated against a root that a client
+/// has already been given.
+pub struct ConcurrentMerkleTree {
+    /// Serializes writers so that no update is lost
+    writer: Mutex<()>,
+    /// The retained versions, oldest first
+    versions: RwLock<Versions>,
+    /// The maximum number of versions retained
+    history: usize,
+}
+
+/// The retained snapshots of a concurrent tree
+struct Versions {
+    /// The version number of the oldest retained snapshot
+    first: u64,
+    /// The retained snapshots, oldest first
+    snapshots: VecDeque<Arc<MerkleTree>>,
+}
+
+impl ConcurrentMerkleTree {
+    /// Create a concurrent tree starting from an existing tree
+    ///
+    /// The initial tree is version 0. The last 16 versions are retained.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The initial tree
+    ///
+    /// # Returns
+    ///
+    /// The concurrent tree
+    pub fn new(tree: MerkleTree) -> Self {
+        Self::with_history(tree, DEFAULT_HISTORY)
+    }
+
+    /// Create a concurrent tree retaining a given number of versions
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The initial tree
+    /// * `history` - The number of versions to retain, including the current one (at
+    ///   least 1)
+    ///
+    /// # Returns
+    ///
+    /// The concurrent tree
+    pub fn with_history(tree: MerkleTree, history: usize) -> Self {
+        ConcurrentMerkleTree {
+            writer: Mutex::new(()),
+            versions: RwLock::new(Versions {
+                first: 0,
+                snapshots: VecDeque::from([Arc::new(tree)]),
+            }),
+            history: history.max(1),
+        }
+    }
+
+    /// Get the current tree
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of the current version that is unaffected by later writes
+    pub fn snapshot(&self) -> Arc<MerkleTree> {
+        let versions = self.versions.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(versions.snapshots.back().unwrap())
+    }
+
+    /// Get the current version number
+    ///
+    /// # Returns
+    ///
+    /// The number of writes applied since the tree was created
+    pub fn version(&self) -> u64 {
+        let versions = self.versions.read().unwrap_or_else(PoisonError::into_inner);
+        versions.first + versions.snapshots.len() as u64 - 1
+    }
+
+    /// Get a retained version of the tree
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version number
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of the version, or None if it is not retained
+    pub fn snapshot_at_version(&self, version: u64) -> Option<Arc<MerkleTree>> {
+        let versions = self.versions.read().unwrap_or_else(PoisonError::into_inner);
+        let offset = usize::try_from(version.checked_sub(versions.first)?).ok()?;
+        versions.snapshots.get(offset).cloned()
+    }
+
+    /// Generate a proof for a leaf as it was in a retained version
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version number
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof against that version's root, or an error if the version is not retained
+    /// or the index is out of range for it
+    pub fn proof_at_version(&self, version: u64, index: usize) -> Result<MerkleProof, MerkleError> {
+        let tree = self
+            .snapshot_at_version(version)
+            .ok_or(MerkleError::VersionUnavailable { version })?;
+        tree.proof_by_index(index)
+            .ok_or(MerkleError::IndexOutOfRange {
+                index,
+                leaf_count: tree.leaf_count(),
+            })
+    }
+
+    /// Append a data item as a new leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// The version number that includes the new leaf
+    pub fn append(&self, data: Vec<u8>) -> u64 {
+        self.write(|tree| {
+            tree.push(data);
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    /// Replace the data of an existing leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The new data of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The version number that includes the update, or an error if the index is out of
+    /// range (in which case no new version is created)
+    pub fn update(&self, index: usize, data: Vec<u8>) -> Result<u64, MerkleError> {
+        self.write(|tree| tree.update_by_index(index, data))
+    }
+
+    /// Apply a change to a clone of the current tree and publish it as a new version
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change to apply
+    ///
+    /// # Returns
+    ///
+    /// The new version number, or the change's error
+    fn write(
+        &self,
+        change: impl FnOnce(&mut MerkleTree) -> Result<(), MerkleError>,
+    ) -> Result<u64, MerkleError> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut tree = MerkleTree::clone(&self.snapshot());
+        change(&mut tree)?;
+
+        let mut versions = self
+            .versions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        versions.snapshots.push_back(Arc::new(tree));
+        while versions.snapshots.len() > self.history {
+            versions.snapshots.pop_front();
+            versions.first += 1;
+        }
+        Ok(versions.first + versions.snapshots.len() as u64 - 1)
+    }
+}
@@ -0,0 +1,325 @@
+//! A machine-readable conformance suite for implementations in other languages
+//!
+//! `generate` emits the suite as JSON and `check` runs a suite file against this crate.
+//! The repository commits the generated suite as `conformance.json`, and the crate's
+//! own tests check it, so the file is an authoritative record of the tree's semantics.
+//!
+//! Each case lists the tree's parameters (as in a `ProofBundle`), its leaves in
+//! hexadecimal, the expected root, and the expected proof for every leaf, with siblings
+//! in hexadecimal:
+//!
+//! ```json
+//! {"version": 1, "cases": [
+//!   {"name": "v2 3 leaves",
+//!    "params": {"algorithm": "Sha256", "scheme": "DomainSeparated",
+//!               "ordering": "Positional", "padding": "DuplicateLast", "positional": false},
+//!    "leaves": ["6c6561662030", "…"], "root": "…",
+//!    "proofs": [{"index": 0, "steps": [{"sibling": "…", "is_left": false}, …]}, …]}
+//! ]}
+//! ```
+
+use crate::error::ConformanceError;
+use crate::hashing::{ByteOrder, HashAlgorithm, HashConfig, HashScheme, PairOrdering};
+use crate::merkle_tree::{BuildOptions, MerkleTree};
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::proof::MerkleProof;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The version of the suite format written by `generate`
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The leaf counts every configuration is exercised with
+const LEAF_COUNTS: [usize; 5] = [1, 2, 3, 5, 8];
+
+/// A set of conformance cases
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConformanceSuite {
+    /// The version of the suite format
+    pub version: u32,
+    /// The cases
+    pub cases: Vec<ConformanceCase>,
+}
+
+/// A fixed set of leaves together with its expected root and proofs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConformanceCase {
+    /// A short description of the case
+    pub name: String,
+    /// The parameters the tree is built with
+    pub params: TreeParams,
+    /// The leaf data, in order
+    #[serde(with = "crate::serde_hex::list")]
+    pub leaves: Vec<Vec<u8>>,
+    /// The expected root hash
+    #[serde(with = "crate::serde_hex")]
+    pub root: Vec<u8>,
+    /// The expected proofs
+    pub proofs: Vec<ExpectedProof>,
+}
+
+/// The expected proof for one leaf
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedProof {
+    /// The index of the leaf
+    pub index: usize,
+    /// The proof's steps, from the leaf up
+    pub steps: Vec<ExpectedStep>,
+}
+
+/// One step of an expected proof
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedStep {
+    /// The sibling hash
+    #[serde(with = "crate::serde_hex")]
+    pub sibling: Vec<u8>,
+    /// Whether the sibling is on the left
+    pub is_left: bool,
+}
+
+impl ExpectedProof {
+    /// Get the expected proof as a `MerkleProof`
+    ///
+    /// # Returns
+    ///
+    /// The proof
+    pub fn to_proof(&self) -> MerkleProof {
+        MerkleProof::new(
+            self.steps
+                .iter()
+                .map(|step| (step.sibling.clone(), step.is_left))
+                .collect(),
+        )
+    }
+}
+
+impl ConformanceCase {
+    /// Compute a case from this crate's implementation
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A short description of the case
+    /// * `params` - The parameters to build the tree with
+    /// * `leaves` - The leaf data, which must not be empty
+    ///
+    /// # Returns
+    ///
+    /// The case, with a proof for every leaf
+    ///
+    /// # Panics
+    ///
+    /// Panics if the leaves are empty or do not fit the parameters' padding
+    pub fn compute(name: impl Into<String>, params: TreeParams, leaves: Vec<Vec<u8>>) -> Self {
+        let tree = MerkleTree::build_with(leaves.clone(), params, BuildOptions::default(), None)
+            .unwrap_or_else(|error| panic!("{}", error));
+        let proofs = (0..tree.leaf_count())
+            .map(|index| ExpectedProof {
+                index,
+                steps: tree
+                    .proof_by_index(index)
+                    .expect("index is in range")
+                    .iter()
+                    .map(|(sibling, is_left)| ExpectedStep {
+                        sibling: sibling.clone(),
+                        is_left: *is_left,
+                    })
+                    .collect(),
+            })
+            .collect();
+        ConformanceCase {
+            name: name.into(),
+            params,
+            root: tree.root_hash().expect("the tree has leaves"),
+            leaves,
+            proofs,
+        }
+    }
+
+    /// Check the case against this crate's implementation
+    ///
+    /// # Returns
+    ///
+    /// Ok if the tree built from the case's leaves has the expected root and proofs, and
+    /// every expected proof verifies, or the first difference found
+    pub fn check(&self) -> Result<(), ConformanceError> {
+        let tree = MerkleTree::build_with(
+            self.leaves.clone(),
+            self.params,
+            BuildOptions::default(),
+            None,
+        )
+        .map_err(|error| ConformanceError::Build {
+            case: self.name.clone(),
+            error,
+        })?;
+        let root = tree.root_hash().unwrap_or_default();
+        if root != self.root {
+            return Err(ConformanceError::RootMismatch {
+                case: self.name.clone(),
+                expected: self.root.clone(),
+                actual: root,
+            });
+        }
+        for expected in &self.proofs {
+            let proof = expected.to_proof();
+            let matches = tree.proof_by_index(expected.index).as_ref() == Some(&proof);
+            if !matches || !tree.verify(&self.leaves[expected.index], &proof) {
+                return Err(ConformanceError::ProofMismatch {
+                    case: self.name.clone(),
+                    index: expected.index,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ConformanceSuite {
+    /// Check every case against this crate's implementation
+    ///
+    /// # Returns
+    ///
+    /// The number of cases checked, or the first failure
+    pub fn check(&self) -> Result<usize, ConformanceError> {
+        if self.version != FORMAT_VERSION {
+            return Err(ConformanceError::UnsupportedVersion {
+                version: self.version,
+            });
+        }
+        for case in &self.cases {
+            case.check()?;
+        }
+        Ok(self.cases.len())
+    }
+}
+
+/// The configurations covered by the suite
+fn configurations() -> Vec<(&'static str, TreeParams)> {
+    let plain = HashConfig::new(
+        HashAlgorithm::Sha256,
+        HashScheme::Plain,
+        PairOrdering::Positional,
+    );
+    let separated = HashConfig::new(
+        HashAlgorithm::Sha256,
+        HashScheme::DomainSeparated,
+        PairOrdering::Positional,
+    );
+    let with_padding = |padding| TreeParams {
+        padding,
+        ..TreeParams::from(separated)
+    };
+    vec![
+        ("v1", plain.into()),
+        ("v2", separated.into()),
+        (
+            "sha512",
+            HashConfig {
+                algorithm: HashAlgorithm::Sha512,
+                ..separated
+            }
+            .into(),
+        ),
+        (
+            "sorted",
+            HashConfig {
+                ordering: PairOrdering::Sorted,
+                ..separated
+            }
+            .into(),
+        ),
+        (
+            "reversed byte order",
+            separated.with_byte_order(ByteOrder::Reversed).into(),
+        ),
+        ("carry up", with_padding(PaddingStrategy::CarryUp)),
+        (
+            "complete depth 3",
+            with_padding(PaddingStrategy::Complete { depth: 3 }),
+        ),
+        ("positional leaves", TreeParams::new(separated, true)),
+        (
+            "keccak sorted",
+            HashConfig::new(
+                HashAlgorithm::Keccak256,
+                HashScheme::Plain,
+                PairOrdering::Sorted,
+            )
+            .into(),
+        ),
+        (
+            "ripemd160",
+            HashConfig {
+                algorithm: HashAlgorithm::Ripemd160,
+                ..separated
+            }
+            .into(),
+        ),
+    ]
+}
+
+/// Compute the suite's cases from this crate's implementation
+///
+/// Every configuration is built over 1, 2, 3, 5 and 8 leaves `"leaf 0"`, `"leaf 1"`, …,
+/// and over a set of awkward leaves: empty, binary and longer than a hash block.
+///
+/// # Returns
+///
+/// The cases
+pub fn cases() -> Vec<ConformanceCase> {
+    let awkward = vec![Vec::new(), vec![0x00, 0xff, 0x01], vec![b'x'; 200]];
+    let mut cases = Vec::new();
+    for (name, params) in configurations() {
+        for count in LEAF_COUNTS {
+            let leaves = (0..count)
+                .map(|i| format!("leaf {}", i).into_bytes())
+                .collect();
+            cases.push(ConformanceCase::compute(
+                format!(
+                    "{} {} {}",
+                    name,
+                    count,
+                    if count == 1 { "leaf" } else { "leaves" }
+                ),
+                params,
+                leaves,
+            ));
+        }
+        cases.push(ConformanceCase::compute(
+            format!("{} awkward leaves", name),
+            params,
+            awkward.clone(),
+        ));
+    }
+    cases
+}
+
+/// Generate the conformance suite as JSON
+///
+/// # Returns
+///
+/// The pretty-printed suite, ending in a newline
+pub fn generate() -> String {
+    let suite = ConformanceSuite {
+        version: FORMAT_VERSION,
+        cases: cases(),
+    };
+    let mut json = serde_json::to_string_pretty(&suite).expect("the suite serializes");
+    json.push('\n');
+    json
+}
+
+/// Run a conformance suite file against this crate's implementation
+///
+/// # Arguments
+///
+/// * `path` - The path of the JSON suite, such as the repository's `conformance.json`
+///
+/// # Returns
+///
+/// The number of cases checked, or the first failure
+pub fn check(path: impl AsRef<Path>) -> Result<usize, ConformanceError> {
+    let json = std::fs::read_to_string(path).map_err(ConformanceError::Io)?;
+    let suite: ConformanceSuite = serde_json::from_str(&json).map_err(ConformanceError::Json)?;
+    suite.check()
+}
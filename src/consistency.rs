@@ -0,0 +1,321 @@
+//! Consistency proofs between two sizes of an append-only tree (RFC 6962)
+
+use crate::error::MerkleError;
+use crate::hashing::{HashConfig, PairOrdering};
+use crate::params::PaddingStrategy;
+use crate::proof::MerkleProof;
+use crate::verify::proof_leaf_index;
+#[cfg(feature = "build")]
+use crate::MerkleTree;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A proof that a tree of one size is the same tree as another, larger one with leaves
+/// appended
+///
+/// These are the consistency proofs of RFC 6962, so they exist only for trees with
+/// `PaddingStrategy::CarryUp` padding, which have the RFC's shape. Each hash is the root
+/// of a range of leaves, in the order of the RFC's `SUBPROOF`. The two sizes are not
+/// part of the proof; the verifier supplies them.
+///
+/// With the `serde` feature the proof serializes as an object with its hashes as arrays
+/// of bytes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConsistencyProof {
+    /// The roots of the ranges of leaves the proof reveals
+    pub hashes: Vec<Vec<u8>>,
+}
+
+/// A half-open range of leaf indices whose root is known or wanted
+type LeafRange = (usize, usize);
+
+impl ConsistencyProof {
+    /// Verify that one root extends another
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) the tree was built with
+    /// * `old_size` - The number of leaves the old root commits to (at least 1)
+    /// * `new_size` - The number of leaves the new root commits to
+    /// * `old_root` - The old root
+    /// * `new_root` - The new root
+    ///
+    /// # Returns
+    ///
+    /// True if the first `old_size` leaves under the new root are the leaves under the
+    /// old root, false otherwise
+    pub fn verify(
+        &self,
+        hasher: impl Into<HashConfig>,
+        old_size: usize,
+        new_size: usize,
+        old_root: &[u8],
+        new_root: &[u8],
+    ) -> bool {
+        let hasher = hasher.into();
+        let digest_len = hasher.digest_len();
+        if old_size == 0
+            || old_size > new_size
+            || old_root.len() != digest_len
+            || new_root.len() != digest_len
+            || self.hashes.iter().any(|hash| hash.len() != digest_len)
+        {
+            return false;
+        }
+        if old_size == new_size {
+            return self.hashes.is_empty() && old_root == new_root;
+        }
+        let ranges = consistency_ranges(old_size, new_size);
+        if ranges.len() != self.hashes.len() {
+            return false;
+        }
+
+        let mut known: HashMap<LeafRange, &[u8]> = ranges
+            .into_iter()
+            .zip(self.hashes.iter().map(Vec::as_slice))
+            .collect();
+        // A power-of-two old tree is a subtree of the new one, so its root is not repeated
+        // in the proof
+        if old_size.is_power_of_two() {
+            known.insert((0, old_size), old_root);
+        }
+        range_hash(&hasher, &known, (0, old_size)).is_some_and(|root| root == old_root)
+            && range_hash(&hasher, &known, (0, new_size)).is_some_and(|root| root == new_root)
+    }
+}
+
+impl MerkleProof {
+    /// Turn an inclusion proof for an old size of a tree into one for a new size
+    ///
+    /// Siblings inside the old tree are taken from this proof, and those covering newly
+    /// appended leaves from the consistency proof, hashing them together where the new
+    /// tree joins them. Verify the consistency proof first: the result is only as good as
+    /// the hashes it is made of. The upgraded proof is not stamped.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) the tree was built with
+    /// * `consistency` - A consistency proof from `old_size` to `new_size`
+    /// * `old_size` - The number of leaves in the tree this proof was made for
+    /// * `new_size` - The number of leaves in the tree to prove the leaf in
+    ///
+    /// # Returns
+    ///
+    /// The proof of the same leaf in the tree of `new_size` leaves, or
+    /// `MerkleError::ConsistencyUnavailable` if the proofs do not determine it: the sizes
+    /// are out of order, the tree's pairs are sorted (so the proof does not say which
+    /// leaf it is for), or either proof has the wrong shape
+    pub fn upgrade(
+        &self,
+        hasher: impl Into<HashConfig>,
+        consistency: &ConsistencyProof,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<MerkleProof, MerkleError> {
+        let hasher = hasher.into();
+        let unavailable = |reason: String| MerkleError::ConsistencyUnavailable { reason };
+        if old_size == 0 || old_size > new_size {
+            return Err(unavailable(format!(
+                "cannot extend a tree of {} leaves to {} leaves",
+                old_size, new_size
+            )));
+        }
+        if hasher.ordering == PairOrdering::Sorted {
+            return Err(unavailable(
+                "a proof for sorted pairs does not say which leaf it is for".to_string(),
+            ));
+        }
+        let index =
+            proof_leaf_index(self, old_size, PaddingStrategy::CarryUp).ok_or_else(|| {
+                unavailable(format!(
+                    "the proof does not fit a tree of {} leaves",
+                    old_size
+                ))
+            })?;
+        let ranges = consistency_ranges(old_size, new_size);
+        if ranges.len() != consistency.hashes.len() {
+            return Err(unavailable(format!(
+                "the consistency proof has {} hashes, expected {}",
+                consistency.hashes.len(),
+                ranges.len()
+            )));
+        }
+
+        let mut known: HashMap<LeafRange, &[u8]> = HashMap::new();
+        let old_siblings = path_ranges(index, old_size)
+            .into_iter()
+            .zip(self.iter().map(|(hash, _)| hash.as_slice()));
+        for (range, hash) in old_siblings.chain(
+            ranges
+                .into_iter()
+                .zip(consistency.hashes.iter().map(Vec::as_slice)),
+        ) {
+            if known.insert(range, hash).is_some_and(|other| other != hash) {
+                return Err(unavailable(format!(
+                    "the proofs disagree on the root of leaves {}..{}",
+                    range.0, range.1
+                )));
+            }
+        }
+
+        path_ranges(index, new_size)
+            .into_iter()
+            .map(|range| {
+                let hash = range_hash(&hasher, &known, range).ok_or_else(|| {
+                    unavailable(format!(
+                        "the proofs do not determine the root of leaves {}..{}",
+                        range.0, range.1
+                    ))
+                })?;
+                Ok((hash, range.0 < index))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "build")]
+impl MerkleTree {
+    /// Generate a proof that the tree extends its first `old_size` leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `old_size` - The number of leaves in the earlier tree (at least 1)
+    ///
+    /// # Returns
+    ///
+    /// The proof, or `MerkleError::ConsistencyUnavailable` if the tree is not padded with
+    /// `PaddingStrategy::CarryUp` or `old_size` is 0 or more than the tree's leaf count
+    pub fn generate_consistency_proof(
+        &self,
+        old_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError> {
+        if self.params().padding != PaddingStrategy::CarryUp {
+            return Err(MerkleError::ConsistencyUnavailable {
+                reason: "consistency proofs need CarryUp padding".to_string(),
+            });
+        }
+        if old_size == 0 || old_size > self.leaf_count() {
+            return Err(MerkleError::ConsistencyUnavailable {
+                reason: format!(
+                    "cannot extend a tree of {} leaves to {} leaves",
+                    old_size,
+                    self.leaf_count()
+                ),
+            });
+        }
+        // Every range in the proof is a node of this tree: its start is aligned to its
+        // size rounded up to a power of two, which gives its level
+        let hashes = consistency_ranges(old_size, self.leaf_count())
+            .into_iter()
+            .map(|(start, end)| {
+                let level = (end - start).next_power_of_two().trailing_zeros();
+                self.levels()[level as usize][start >> level].clone()
+            })
+            .collect();
+        Ok(ConsistencyProof { hashes })
+    }
+}
+
+/// Get the size of the left subtree of a range of leaves
+///
+/// # Arguments
+///
+/// * `len` - The number of leaves in the range (at least 2)
+///
+/// # Returns
+///
+/// The largest power of two less than `len`
+fn split_point(len: usize) -> usize {
+    1 << (usize::BITS - 1 - (len - 1).leading_zeros())
+}
+
+/// List the ranges of leaves whose roots make up a consistency proof
+///
+/// # Arguments
+///
+/// * `old_size` - The number of leaves in the old tree (at least 1)
+/// * `new_size` - The number of leaves in the new tree (at least `old_size`)
+///
+/// # Returns
+///
+/// The ranges, in the order of RFC 6962's `SUBPROOF(old_size, D[0:new_size], true)`
+fn consistency_ranges(old_size: usize, new_size: usize) -> Vec<LeafRange> {
+    /// `SUBPROOF(m, D[start:end], complete)`
+    fn subproof(m: usize, (start, end): LeafRange, complete: bool, ranges: &mut Vec<LeafRange>) {
+        if m == end - start {
+            if !complete {
+                ranges.push((start, end));
+            }
+            return;
+        }
+        let k = split_point(end - start);
+        if m <= k {
+            subproof(m, (start, start + k), complete, ranges);
+            ranges.push((start + k, end));
+        } else {
+            subproof(m - k, (start + k, end), false, ranges);
+            ranges.push((start, start + k));
+        }
+    }
+
+    let mut ranges = Vec::new();
+    subproof(old_size, (0, new_size), true, &mut ranges);
+    ranges
+}
+
+/// List the ranges of leaves whose roots are a leaf's siblings
+///
+/// # Arguments
+///
+/// * `index` - The index of the leaf
+/// * `size` - The number of leaves in the tree
+///
+/// # Returns
+///
+/// The ranges, from the leaf up to the root
+fn path_ranges(index: usize, size: usize) -> Vec<LeafRange> {
+    let (mut start, mut end) = (0, size);
+    let mut ranges = Vec::new();
+    while end - start > 1 {
+        let k = split_point(end - start);
+        if index < start + k {
+            ranges.push((start + k, end));
+            end = start + k;
+        } else {
+            ranges.push((start, start + k));
+            start += k;
+        }
+    }
+    ranges.reverse();
+    ranges
+}
+
+/// Compute the root of a range of leaves from the roots of ranges that are known
+///
+/// # Arguments
+///
+/// * `hasher` - The hash configuration the tree was built with
+/// * `known` - The known roots
+/// * `range` - The range
+///
+/// # Returns
+///
+/// The root, or None if some part of the range has no known root
+fn range_hash(
+    hasher: &HashConfig,
+    known: &HashMap<LeafRange, &[u8]>,
+    (start, end): LeafRange,
+) -> Option<Vec<u8>> {
+    if let Some(hash) = known.get(&(start, end)) {
+        return Some(hash.to_vec());
+    }
+    if end - start < 2 {
+        return None;
+    }
+    let k = split_point(end - start);
+    let left = range_hash(hasher, known, (start, start + k))?;
+    let right = range_hash(hasher, known, (start + k, end))?;
+    Some(hasher.hash_pair(&left, &right))
+}
@@ -0,0 +1,167 @@
+use crate::error::VerificationError;
+use crate::hashing::{HashConfig, HashScheme};
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Tracks which leaves of a published tree have been verified
+///
+/// Each leaf is checked with `MerkleTree::verify_proof_checked`, including that the
+/// proof addresses the recorded index. Under `PairOrdering::Sorted` proofs carry no
+/// directions, so a proof only shows that the data is some leaf of the tree and the
+/// recorded index is taken on trust.
+///
+/// With the `serde` feature the tracker can be persisted to resume an audit later.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CoverageTracker {
+    /// The published root hash
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    root: Vec<u8>,
+    /// The number of leaves committed to by the root
+    leaf_count: usize,
+    /// The configuration the tree was built with
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    hasher: HashConfig,
+    /// A bitmap of the verified leaves, least significant bit first
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    verified: Vec<u8>,
+}
+
+impl CoverageTracker {
+    /// Create a tracker for a published root
+    ///
+    /// The tracker assumes the defaults of `MerkleTree::builder()`: SHA-256,
+    /// domain-separated hashing and positional pairs.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The published root hash
+    /// * `leaf_count` - The number of leaves committed to by the root
+    ///
+    /// # Returns
+    ///
+    /// A tracker with no leaves verified
+    pub fn new(root: impl Into<Vec<u8>>, leaf_count: usize) -> Self {
+        CoverageTracker {
+            root: root.into(),
+            leaf_count,
+            hasher: HashScheme::DomainSeparated.into(),
+            verified: vec![0; leaf_count.div_ceil(8)],
+        }
+    }
+
+    /// Set the hash configuration the tree was built with
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme)
+    ///
+    /// # Returns
+    ///
+    /// The updated tracker
+    pub fn with_hash_config(mut self, hasher: impl Into<HashConfig>) -> Self {
+        self.hasher = hasher.into();
+        self
+    }
+
+    /// Verify a leaf and mark it as covered
+    ///
+    /// Recording an index that is already covered verifies the proof again but has no
+    /// further effect. A proof that is rejected leaves the coverage unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The leaf's data
+    /// * `proof` - The proof for the leaf
+    ///
+    /// # Returns
+    ///
+    /// Ok if the proof is valid, or the reason it was rejected
+    pub fn record(
+        &mut self,
+        index: usize,
+        data: &[u8],
+        proof: &MerkleProof,
+    ) -> Result<(), VerificationError> {
+        if index >= self.leaf_count {
+            return Err(VerificationError::IndexOutOfRange {
+                index,
+                leaf_count: self.leaf_count,
+            });
+        }
+        MerkleTree::verify_proof_checked(
+            self.hasher,
+            data,
+            proof,
+            &self.root,
+            Some(self.leaf_count),
+            Some(index),
+        )?;
+
+        // A persisted bitmap may be shorter than expected; missing bytes mean unverified
+        self.verified.resize(self.leaf_count.div_ceil(8), 0);
+        self.verified[index / 8] |= 1 << (index % 8);
+        Ok(())
+    }
+
+    /// Check whether a leaf has been verified
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// True if the leaf has been recorded, false otherwise
+    pub fn is_verified(&self, index: usize) -> bool {
+        index < self.leaf_count
+            && self
+                .verified
+                .get(index / 8)
+                .is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+    }
+
+    /// Get the number of verified leaves
+    ///
+    /// # Returns
+    ///
+    /// The number of distinct leaves recorded
+    pub fn verified_count(&self) -> usize {
+        (0..self.leaf_count)
+            .filter(|&index| self.is_verified(index))
+            .count()
+    }
+
+    /// Get the fraction of leaves that have been verified
+    ///
+    /// # Returns
+    ///
+    /// A value between 0.0 and 1.0 (1.0 for a tree with no leaves)
+    pub fn coverage(&self) -> f64 {
+        if self.leaf_count == 0 {
+            return 1.0;
+        }
+        self.verified_count() as f64 / self.leaf_count as f64
+    }
+
+    /// Get the leaves that have not been verified yet
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the missing indices in ascending order
+    pub fn missing(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.leaf_count).filter(|&index| !self.is_verified(index))
+    }
+
+    /// Check whether every leaf has been verified
+    ///
+    /// # Returns
+    ///
+    /// True if no leaf is missing, false otherwise
+    pub fn is_complete(&self) -> bool {
+        self.verified_count() == self.leaf_count
+    }
+}
@@ -0,0 +1,229 @@
+//! Diagnostics for proofs that are close to valid, for debugging integrations
+
+use crate::error::VerificationError;
+use crate::hashing::{HashConfig, PairOrdering};
+use crate::proof::MerkleProof;
+use crate::verify::fold_proof;
+use crate::MerkleTree;
+use std::fmt;
+
+/// A systematic mistake that, once undone, makes a proof verify
+///
+/// See `MerkleTree::diagnose_proof`. Each variant names what the proof's producer most
+/// likely got wrong, not a change the verifier should make.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProofRepair {
+    /// Every direction flag is inverted
+    FlippedDirections,
+    /// The steps run from the root down to the leaf instead of up from the leaf
+    ReversedSteps,
+    /// The steps run from the root down, and every direction flag is inverted
+    ReversedAndFlippedSteps,
+    /// The direction flag of one step is inverted
+    SwappedAtStep {
+        /// The index of the step
+        step: usize,
+    },
+    /// The proof's producer sorts each pair of hashes before hashing them
+    SortedPairs,
+    /// The proof's producer hashes pairs in tree order rather than sorted
+    PositionalPairs,
+    /// The proof has one step too many at the end
+    ExtraFinalStep,
+    /// A step pairing a node with itself, as when the last node of an odd level is
+    /// duplicated, is missing
+    MissingDuplicateStep {
+        /// The index at which the step is missing
+        step: usize,
+    },
+}
+
+/// The result of diagnosing a proof
+///
+/// Returned by `MerkleTree::diagnose_proof`. The `Display` implementation renders an
+/// actionable, human-readable explanation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnosis {
+    /// The proof verifies as it is
+    Valid,
+    /// The proof does not verify, but would if a mistake were undone
+    Repairable(ProofRepair),
+    /// The proof is rejected before any hashing, so no repair applies
+    Malformed(VerificationError),
+    /// The proof does not verify, and none of the repairs tried makes it verify
+    Unrecognized,
+}
+
+impl MerkleTree {
+    /// Work out why a proof that should verify does not
+    ///
+    /// This is a debugging aid for integrations, not a verifier: a proof that only
+    /// verifies after a repair is still invalid, and no repaired proof is returned. The
+    /// proof is first checked as by `verify_proof_checked` without a leaf count. If it
+    /// only fails to reach the root, a bounded set of repairs is tried in turn — flipped
+    /// directions, reversed steps, the other pair ordering, a single swapped step, an
+    /// extra final step and a missing self-pairing step — and the first one under which
+    /// the proof verifies is reported. Positional leaves are not supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) the tree was built with
+    /// * `data` - The data the proof is for
+    /// * `proof` - The proof
+    /// * `root_hash` - The root the proof should lead to
+    ///
+    /// # Returns
+    ///
+    /// The diagnosis
+    pub fn diagnose_proof(
+        hasher: impl Into<HashConfig>,
+        data: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+    ) -> Diagnosis {
+        let hasher = hasher.into();
+        match MerkleTree::verify_proof_checked(hasher, data, proof, root_hash, None, None) {
+            Ok(()) => return Diagnosis::Valid,
+            Err(VerificationError::RootMismatch) => {}
+            Err(error) => return Diagnosis::Malformed(error),
+        }
+
+        let leaf_hash = hasher.hash_leaf(data);
+        let verifies = |hasher: &HashConfig, steps: Vec<(Vec<u8>, bool)>| {
+            fold_proof(hasher, leaf_hash.clone(), &MerkleProof::new(steps)) == root_hash
+        };
+        let steps = || proof.iter().cloned();
+        let flipped = |(hash, is_left): (Vec<u8>, bool)| (hash, !is_left);
+
+        // Directions carry no meaning when pairs are sorted
+        if hasher.ordering == PairOrdering::Positional {
+            if verifies(&hasher, steps().map(flipped).collect()) {
+                return Diagnosis::Repairable(ProofRepair::FlippedDirections);
+            }
+            if verifies(&hasher, steps().rev().collect()) {
+                return Diagnosis::Repairable(ProofRepair::ReversedSteps);
+            }
+            if verifies(&hasher, steps().rev().map(flipped).collect()) {
+                return Diagnosis::Repairable(ProofRepair::ReversedAndFlippedSteps);
+            }
+        }
+
+        let (other, repair) = match hasher.ordering {
+            PairOrdering::Positional => (PairOrdering::Sorted, ProofRepair::SortedPairs),
+            PairOrdering::Sorted => (PairOrdering::Positional, ProofRepair::PositionalPairs),
+        };
+        let reordered = HashConfig {
+            ordering: other,
+            ..hasher
+        };
+        if verifies(&reordered, steps().collect()) {
+            return Diagnosis::Repairable(repair);
+        }
+        // A sorted pair can look like a single swapped step, so this comes second
+        if hasher.ordering == PairOrdering::Positional {
+            for step in 0..proof.len() {
+                let mut swapped: Vec<_> = steps().collect();
+                swapped[step].1 = !swapped[step].1;
+                if verifies(&hasher, swapped) {
+                    return Diagnosis::Repairable(ProofRepair::SwappedAtStep { step });
+                }
+            }
+        }
+
+        if !proof.is_empty() && verifies(&hasher, steps().take(proof.len() - 1).collect()) {
+            return Diagnosis::Repairable(ProofRepair::ExtraFinalStep);
+        }
+        // A self-pairing step needs the node's hash at that point, so fold step by step
+        let mut current = leaf_hash.clone();
+        for step in 0..=proof.len() {
+            let duplicated = hasher.hash_pair(&current, &current);
+            let rest = MerkleProof::new(steps().skip(step).collect());
+            if fold_proof(&hasher, duplicated, &rest) == root_hash {
+                return Diagnosis::Repairable(ProofRepair::MissingDuplicateStep { step });
+            }
+            if let Some((sibling, is_left)) = proof.get(step) {
+                current = if *is_left {
+                    hasher.hash_pair(sibling, &current)
+                } else {
+                    hasher.hash_pair(&current, sibling)
+                };
+            }
+        }
+        Diagnosis::Unrecognized
+    }
+}
+
+impl fmt::Display for ProofRepair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofRepair::FlippedDirections => write!(
+                f,
+                "the proof verifies if every direction is inverted; check your is_left \
+                 convention (true means the sibling is the left child)"
+            ),
+            ProofRepair::ReversedSteps => write!(
+                f,
+                "the proof verifies if its steps are reversed; list siblings from the \
+                 leaf up to the root"
+            ),
+            ProofRepair::ReversedAndFlippedSteps => write!(
+                f,
+                "the proof verifies if its steps are reversed and every direction is \
+                 inverted; list siblings from the leaf up to the root, and check your \
+                 is_left convention (true means the sibling is the left child)"
+            ),
+            ProofRepair::SwappedAtStep { step } => write!(
+                f,
+                "the proof verifies if the direction of step {} is inverted; check how \
+                 that level orders the pair, for example whether it sorts the two hashes",
+                step
+            ),
+            ProofRepair::SortedPairs => write!(
+                f,
+                "the proof verifies if each pair is sorted before hashing; the tree \
+                 hashes pairs in tree order, so stop sorting pairs (or build the tree \
+                 with PairOrdering::Sorted)"
+            ),
+            ProofRepair::PositionalPairs => write!(
+                f,
+                "the proof verifies if pairs are hashed in tree order; the tree sorts \
+                 each pair before hashing, so sort pairs (or build the tree with \
+                 PairOrdering::Positional)"
+            ),
+            ProofRepair::ExtraFinalStep => write!(
+                f,
+                "the proof verifies without its last step; check that the proof stops \
+                 below the root"
+            ),
+            ProofRepair::MissingDuplicateStep { step } => write!(
+                f,
+                "the proof verifies if a step pairing the node with itself is inserted \
+                 at step {}; the tree duplicates the last node of an odd leaf level \
+                 instead of carrying it up",
+                step
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnosis::Valid => write!(f, "the proof verifies"),
+            Diagnosis::Repairable(repair) => write!(
+                f,
+                "the proof is invalid, but {} (a debugging hint only: the proof is \
+                 still rejected)",
+                repair
+            ),
+            Diagnosis::Malformed(error) => {
+                write!(f, "the proof is malformed, so no repair applies: {}", error)
+            }
+            Diagnosis::Unrecognized => write!(
+                f,
+                "the proof is invalid and no known mistake explains it; check the leaf \
+                 data, the hash configuration and the root"
+            ),
+        }
+    }
+}
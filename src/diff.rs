@@ -0,0 +1,369 @@
+//! Diffs between two versions of a tree, for updating replicas without full snapshots
+//!
+//! A `SnapshotDiff` holds only the leaves that differ between an older and a newer tree,
+//! the newer tree's leaf count and the roots of both. A replica holding the older tree
+//! applies it with `MerkleTree::apply_diff`, which checks the replica's root against the
+//! diff's base root first and the resulting root against the diff's new root last.
+
+use crate::checkpoint::Reader;
+use crate::error::MerkleError;
+use crate::leaf_data::LeafData;
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::merkle_tree::MerkleTree;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The bytes every encoded diff starts with
+const MAGIC: &[u8; 4] = b"MTDF";
+
+/// The version of the diff format
+const FORMAT_VERSION: u8 = 1;
+
+/// The new content of a changed leaf
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DiffLeaf {
+    /// The leaf's data, from which the replica hashes the leaf itself
+    Data(#[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))] Vec<u8>),
+    /// The leaf's hash, when the newer tree does not keep the leaf's data
+    Hash(#[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))] Vec<u8>),
+}
+
+/// The changes that turn one version of a tree into another
+///
+/// Made with `MerkleTree::snapshot_diff` and applied with `MerkleTree::apply_diff`. Both
+/// trees must have the same parameters. A leaf appears in `changes` if its hash differs
+/// between the two trees or it is past the older tree's last leaf; leaves past the newer
+/// tree's last leaf are dropped. `to_bytes` gives a compact binary encoding: indices are
+/// delta-encoded and counts and lengths are variable-length integers, so a diff costs
+/// little more than the changed leaves themselves.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SnapshotDiff {
+    /// The root of the older tree, or None if it had no leaves
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    pub base_root: Option<Vec<u8>>,
+    /// The root of the newer tree, or None if it has no leaves
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    pub new_root: Option<Vec<u8>>,
+    /// The number of leaves in the newer tree
+    pub leaf_count: usize,
+    /// The new content of each changed leaf, by index
+    pub changes: BTreeMap<usize, DiffLeaf>,
+}
+
+impl MerkleTree {
+    /// Compute the diff that turns an older version of the tree into this one
+    ///
+    /// Leaf hashes are compared, so no hashing is done. A changed leaf carries its data
+    /// if this tree keeps it and its hash otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `older` - The older version, with the same parameters
+    ///
+    /// # Returns
+    ///
+    /// The diff
+    pub fn snapshot_diff(&self, older: &MerkleTree) -> SnapshotDiff {
+        let leaf_count = self.leaf_count();
+        let hashes = self.levels().first().into_iter().flatten();
+        let older_hashes = older.levels().first();
+        let changes = hashes
+            .take(leaf_count)
+            .enumerate()
+            .filter(|(index, hash)| {
+                older_hashes.and_then(|older_hashes| older_hashes.get(*index)) != Some(*hash)
+                    || *index >= older.leaf_count()
+            })
+            .map(|(index, hash)| {
+                let leaf = match self.get_leaf(index) {
+                    Some(data) => DiffLeaf::Data(data.to_vec()),
+                    None => DiffLeaf::Hash(hash.clone()),
+                };
+                (index, leaf)
+            })
+            .collect();
+        SnapshotDiff {
+            base_root: older.root_hash_ref().map(<[u8]>::to_vec),
+            new_root: self.root_hash_ref().map(<[u8]>::to_vec),
+            leaf_count,
+            changes,
+        }
+    }
+
+    /// Apply a diff made against a tree with this tree's root
+    ///
+    /// The diff is applied to a copy of the tree's hashes first, rehashing only the
+    /// changed leaves' paths (or every level, if the diff drops leaves), and the tree is
+    /// only changed if the result has the diff's new root. The change starts one epoch
+    /// and notifies observers once.
+    ///
+    /// # Arguments
+    ///
+    /// * `diff` - The diff, from `snapshot_diff` on the newer tree
+    ///
+    /// # Returns
+    ///
+    /// Ok if the tree now has the diff's new root, or an error if the tree's root is not
+    /// the diff's base root, the diff is malformed or would overfill a complete tree,
+    /// a hash has the wrong length, the result does not have the diff's new root, or the
+    /// tree keeps an operation log, which cannot record a diff
+    pub fn apply_diff(&mut self, diff: SnapshotDiff) -> Result<(), MerkleError> {
+        let base_root = self.root_hash_ref().map(<[u8]>::to_vec);
+        if base_root != diff.base_root {
+            return Err(MerkleError::DiffBaseMismatch {
+                expected: diff.base_root,
+                actual: base_root,
+            });
+        }
+        if self.operation_log().is_some() {
+            return Err(MerkleError::InvalidDiff {
+                reason: String::from("the tree keeps an operation log"),
+            });
+        }
+        let old_count = self.leaf_count();
+        if let Some((&index, _)) = diff.changes.range(diff.leaf_count..).next() {
+            return Err(MerkleError::InvalidDiff {
+                reason: format!("change to leaf {} past the last leaf", index),
+            });
+        }
+        if old_count < diff.leaf_count
+            && diff.changes.range(old_count..).count() < diff.leaf_count - old_count
+        {
+            return Err(MerkleError::InvalidDiff {
+                reason: String::from("appended leaves are missing"),
+            });
+        }
+
+        let mut applied = if diff.leaf_count < old_count {
+            MerkleTree::from_leaf_hashes(
+                self.levels()[0]
+                    .range(0..diff.leaf_count)
+                    .cloned()
+                    .collect(),
+                self.params(),
+            )
+        } else {
+            self.hashes_only()
+        };
+        let digest_len = self.params().hasher.digest_len();
+        let mut data = Vec::with_capacity(diff.changes.len());
+        let leaves = match (
+            diff.changes.first_key_value(),
+            diff.changes.last_key_value(),
+        ) {
+            (Some((&first, _)), Some((&last, _))) => first..last + 1,
+            _ => diff.leaf_count..diff.leaf_count,
+        };
+        for (index, leaf) in diff.changes {
+            let (hash, leaf_data) = match leaf {
+                DiffLeaf::Data(leaf_data) => (
+                    self.leaf_hash_at(index, &leaf_data),
+                    LeafData::Owned(leaf_data),
+                ),
+                DiffLeaf::Hash(hash) if hash.len() != digest_len => {
+                    return Err(MerkleError::InvalidHashLength {
+                        expected: digest_len,
+                        actual: hash.len(),
+                    })
+                }
+                DiffLeaf::Hash(hash) => (hash, LeafData::None),
+            };
+            if index < applied.leaf_count() {
+                applied.update_leaf_hash(index, hash)?;
+            } else {
+                applied.try_push_leaf_hash(hash)?;
+            }
+            data.push((index, leaf_data));
+        }
+
+        let new_root = applied.root_hash_ref().map(<[u8]>::to_vec);
+        if new_root != diff.new_root {
+            return Err(MerkleError::DiffRootMismatch {
+                expected: diff.new_root,
+                actual: new_root,
+            });
+        }
+        self.commit_diff(applied, data, leaves);
+        Ok(())
+    }
+}
+
+impl SnapshotDiff {
+    /// Encode the diff in its compact binary form
+    ///
+    /// The form is `"MTDF"`, a format version byte, the leaf count, each root as a
+    /// presence byte and, if present, a length byte and the hash, the number of changes,
+    /// and for each change the gap since the previous changed index (the index itself
+    /// for the first), a byte that is 0 for data and 1 for a hash, the length and the
+    /// bytes. Counts, gaps and lengths are unsigned LEB128.
+    ///
+    /// # Returns
+    ///
+    /// The encoded diff
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        write_varint(&mut bytes, self.leaf_count as u64);
+        for root in [&self.base_root, &self.new_root] {
+            match root {
+                Some(root) => {
+                    bytes.push(1);
+                    bytes.push(root.len() as u8);
+                    bytes.extend_from_slice(root);
+                }
+                None => bytes.push(0),
+            }
+        }
+        write_varint(&mut bytes, self.changes.len() as u64);
+        let mut next = 0;
+        for (&index, leaf) in &self.changes {
+            write_varint(&mut bytes, (index - next) as u64);
+            next = index + 1;
+            let (kind, content) = match leaf {
+                DiffLeaf::Data(data) => (0, data),
+                DiffLeaf::Hash(hash) => (1, hash),
+            };
+            bytes.push(kind);
+            write_varint(&mut bytes, content.len() as u64);
+            bytes.extend_from_slice(content);
+        }
+        bytes
+    }
+
+    /// Decode a diff from its compact binary form (see `to_bytes`)
+    ///
+    /// The default `DecodeLimits` apply; see `from_bytes_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded diff
+    ///
+    /// # Returns
+    ///
+    /// The diff, or `MerkleError::InvalidDiff` if the bytes are not a valid encoding
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        Self::from_bytes_with(bytes, &DecodeLimits::default())
+    }
+
+    /// Decode a diff from its compact binary form within limits
+    ///
+    /// The input's length is checked against `max_total_bytes`, the leaf count and the
+    /// number of changes against `max_leaves`, and each leaf's length against
+    /// `max_leaf_size`, each before anything is allocated for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded diff
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The diff, `MerkleError::LimitExceeded` if the input exceeds a limit, or
+    /// `MerkleError::InvalidDiff` if the bytes are not a valid encoding
+    pub fn from_bytes_with(bytes: &[u8], limits: &DecodeLimits) -> Result<Self, MerkleError> {
+        let invalid = |reason: String| MerkleError::InvalidDiff { reason };
+        limits.check(DecodeLimit::TotalBytes, bytes.len())?;
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len()).map_err(invalid)? != MAGIC {
+            return Err(invalid(String::from("not a diff")));
+        }
+        let version = reader.byte().map_err(invalid)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!("unsupported format version {}", version)));
+        }
+        let leaf_count = read_varint(&mut reader).map_err(invalid)?;
+        limits.check_u64(DecodeLimit::Leaves, leaf_count)?;
+        let leaf_count = usize::try_from(leaf_count)
+            .map_err(|_| invalid(String::from("leaf count too large")))?;
+        let mut roots = [None, None];
+        for root in &mut roots {
+            *root = match reader.byte().map_err(invalid)? {
+                0 => None,
+                1 => {
+                    let len = usize::from(reader.byte().map_err(invalid)?);
+                    Some(reader.take(len).map_err(invalid)?.to_vec())
+                }
+                _ => return Err(invalid(String::from("invalid presence byte"))),
+            };
+        }
+        let [base_root, new_root] = roots;
+
+        let count = read_varint(&mut reader).map_err(invalid)?;
+        limits.check_u64(DecodeLimit::Leaves, count)?;
+        let mut changes = BTreeMap::new();
+        let mut next = 0usize;
+        for _ in 0..count {
+            let gap = read_varint(&mut reader).map_err(invalid)?;
+            let index = usize::try_from(gap)
+                .ok()
+                .and_then(|gap| next.checked_add(gap))
+                .filter(|&index| index < leaf_count)
+                .ok_or_else(|| invalid(String::from("change past the last leaf")))?;
+            next = index + 1;
+            let kind = reader.byte().map_err(invalid)?;
+            let len = read_varint(&mut reader).map_err(invalid)?;
+            limits.check_u64(DecodeLimit::LeafSize, len)?;
+            // Taken from the input before copying, so a forged length cannot allocate
+            let len = usize::try_from(len).map_err(|_| invalid(String::from("truncated")))?;
+            let content = reader.take(len).map_err(invalid)?.to_vec();
+            let leaf = match kind {
+                0 => DiffLeaf::Data(content),
+                1 => DiffLeaf::Hash(content),
+                _ => return Err(invalid(format!("invalid change kind {}", kind))),
+            };
+            changes.insert(index, leaf);
+        }
+        if !reader.0.is_empty() {
+            return Err(invalid(String::from("trailing bytes")));
+        }
+        Ok(SnapshotDiff {
+            base_root,
+            new_root,
+            leaf_count,
+            changes,
+        })
+    }
+}
+
+/// Append an unsigned LEB128 integer
+///
+/// # Arguments
+///
+/// * `bytes` - The buffer to append to
+/// * `value` - The integer
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Read an unsigned LEB128 integer
+///
+/// # Arguments
+///
+/// * `reader` - The reader
+///
+/// # Returns
+///
+/// The integer, or the reason it is invalid
+fn read_varint(reader: &mut Reader) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.byte()?;
+        let bits = u64::from(byte & 0x7f);
+        if shift == 63 && bits > 1 {
+            return Err(String::from("integer too large"));
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(String::from("integer too large"))
+}
@@ -0,0 +1,170 @@
+//! Formatting adapters that write hashes as hexadecimal without allocating
+//!
+//! Each adapter implements `Display` (lowercase), `LowerHex` and `UpperHex`. The
+//! alternate flag adds a `0x` prefix to every hash, so `{:#X}` writes `0xABCD...`.
+
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+use std::fmt;
+
+const LOWER_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const UPPER_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// A byte slice formatted as hexadecimal
+///
+/// ```
+/// use merkleproof::HexDisplay;
+///
+/// let hash = [0xab, 0x01];
+/// assert_eq!(format!("{}", HexDisplay(&hash)), "ab01");
+/// assert_eq!(format!("{:#X}", HexDisplay(&hash)), "0xAB01");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HexDisplay<'a>(pub &'a [u8]);
+
+/// The root hash of a tree formatted as hexadecimal
+///
+/// Returned by `MerkleTree::root_hex_display`. An empty tree is written as its empty
+/// root, or as "Empty tree" if it has none, like `MerkleTree::root_hash_hex`.
+#[derive(Clone, Copy, Debug)]
+pub struct RootHexDisplay<'a> {
+    tree: &'a MerkleTree,
+}
+
+/// The steps of a proof formatted as hexadecimal
+///
+/// Returned by `MerkleProof::hex_display`. Each step is written as `L:` or `R:`, for a
+/// sibling on the left or right, followed by the sibling hash, with steps separated by
+/// spaces.
+#[derive(Clone, Copy, Debug)]
+pub struct ProofHexDisplay<'a> {
+    proof: &'a MerkleProof,
+}
+
+/// Write bytes as hexadecimal, prefixed with `0x` under the alternate flag
+///
+/// # Arguments
+///
+/// * `f` - The formatter to write to
+/// * `bytes` - The bytes to write
+/// * `digits` - The hexadecimal digits to use
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8], digits: &[u8; 16]) -> fmt::Result {
+    if f.alternate() {
+        f.write_str("0x")?;
+    }
+    // Encode through a small stack buffer so long inputs need few writes
+    let mut buffer = [0u8; 128];
+    for chunk in bytes.chunks(buffer.len() / 2) {
+        for (i, byte) in chunk.iter().enumerate() {
+            buffer[2 * i] = digits[usize::from(byte >> 4)];
+            buffer[2 * i + 1] = digits[usize::from(byte & 0x0f)];
+        }
+        let encoded = &buffer[..2 * chunk.len()];
+        f.write_str(std::str::from_utf8(encoded).expect("hex digits are ASCII"))?;
+    }
+    Ok(())
+}
+
+impl fmt::LowerHex for HexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, self.0, LOWER_DIGITS)
+    }
+}
+
+impl fmt::UpperHex for HexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, self.0, UPPER_DIGITS)
+    }
+}
+
+impl fmt::Display for HexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl RootHexDisplay<'_> {
+    /// Write the root, or the text used for an empty tree without a root
+    fn write(&self, f: &mut fmt::Formatter<'_>, digits: &[u8; 16]) -> fmt::Result {
+        match self.tree.levels().last() {
+            Some(level) => write_hex(f, &level[0], digits),
+            // Only an empty tree computes its root, which is never on a hot path
+            None => match self.tree.root_hash() {
+                Some(root) => write_hex(f, &root, digits),
+                None => f.write_str("Empty tree"),
+            },
+        }
+    }
+}
+
+impl fmt::LowerHex for RootHexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, LOWER_DIGITS)
+    }
+}
+
+impl fmt::UpperHex for RootHexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, UPPER_DIGITS)
+    }
+}
+
+impl fmt::Display for RootHexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, LOWER_DIGITS)
+    }
+}
+
+impl ProofHexDisplay<'_> {
+    /// Write every step of the proof
+    fn write(&self, f: &mut fmt::Formatter<'_>, digits: &[u8; 16]) -> fmt::Result {
+        for (step, (hash, is_left)) in self.proof.iter().enumerate() {
+            if step > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(if *is_left { "L:" } else { "R:" })?;
+            write_hex(f, hash, digits)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::LowerHex for ProofHexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, LOWER_DIGITS)
+    }
+}
+
+impl fmt::UpperHex for ProofHexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, UPPER_DIGITS)
+    }
+}
+
+impl fmt::Display for ProofHexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, LOWER_DIGITS)
+    }
+}
+
+impl MerkleTree {
+    /// Get an adapter that formats the root hash as hexadecimal without allocating
+    ///
+    /// # Returns
+    ///
+    /// The adapter, see `RootHexDisplay`
+    pub fn root_hex_display(&self) -> RootHexDisplay<'_> {
+        RootHexDisplay { tree: self }
+    }
+}
+
+impl MerkleProof {
+    /// Get an adapter that formats the proof's steps as hexadecimal without allocating
+    ///
+    /// # Returns
+    ///
+    /// The adapter, see `ProofHexDisplay`
+    pub fn hex_display(&self) -> ProofHexDisplay<'_> {
+        ProofHexDisplay { proof: self }
+    }
+}
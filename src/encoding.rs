@@ -0,0 +1,119 @@
+use crate::error::MerkleError;
+
+/// A byte layout for turning typed items into leaf data
+///
+/// Every party computing a root must encode leaves identically, so each encoder pins its
+/// layout exactly. The encoder's `ID` is recorded in trees built with it, and typed
+/// lookups on such a tree check that the same encoder is used.
+pub trait LeafEncoder {
+    /// The type of item the encoder accepts
+    type Item;
+
+    /// A stable name identifying the encoder and its layout
+    const ID: &'static str;
+
+    /// Encode an item as leaf data
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to encode
+    ///
+    /// # Returns
+    ///
+    /// The leaf data, or an error if the layout cannot represent the item
+    fn encode(item: &Self::Item) -> Result<Vec<u8>, MerkleError>;
+}
+
+/// Leaf data is the item's bytes, unchanged
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RawBytes;
+
+impl LeafEncoder for RawBytes {
+    type Item = Vec<u8>;
+    const ID: &'static str = "raw-bytes";
+
+    fn encode(item: &Self::Item) -> Result<Vec<u8>, MerkleError> {
+        Ok(item.clone())
+    }
+}
+
+/// Leaf data is the string's UTF-8 bytes, with no terminator
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Utf8String;
+
+impl LeafEncoder for Utf8String {
+    type Item = String;
+    const ID: &'static str = "utf8-string";
+
+    fn encode(item: &Self::Item) -> Result<Vec<u8>, MerkleError> {
+        Ok(item.as_bytes().to_vec())
+    }
+}
+
+/// Leaf data is Solidity's `abi.encodePacked(address, uint256)`
+///
+/// Items are a 20-byte address and a 32-byte big-endian amount, encoded as the 52 bytes
+/// `address || amount`, as used by Ethereum airdrop claim contracts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AbiPacked;
+
+impl AbiPacked {
+    /// Build an item from an address and an amount that fits in 128 bits
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The 20-byte address
+    /// * `amount` - The amount
+    ///
+    /// # Returns
+    ///
+    /// The item, with the amount widened to a big-endian `uint256`
+    pub fn item(address: [u8; 20], amount: u128) -> ([u8; 20], [u8; 32]) {
+        let mut uint256 = [0; 32];
+        uint256[16..].copy_from_slice(&amount.to_be_bytes());
+        (address, uint256)
+    }
+}
+
+impl LeafEncoder for AbiPacked {
+    type Item = ([u8; 20], [u8; 32]);
+    const ID: &'static str = "abi-packed-address-uint256";
+
+    fn encode((address, amount): &Self::Item) -> Result<Vec<u8>, MerkleError> {
+        Ok([&address[..], &amount[..]].concat())
+    }
+}
+
+/// Leaf data is a sequence of fields, each prefixed with its length
+///
+/// Each field is encoded as its length as a 4-byte big-endian integer followed by its
+/// bytes, so no choice of field contents can make two different sequences collide.
+/// Encoding fails with `MerkleError::FieldTooLarge` if a field is longer than
+/// `u32::MAX` bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LengthPrefixed;
+
+impl LeafEncoder for LengthPrefixed {
+    type Item = Vec<Vec<u8>>;
+    const ID: &'static str = "length-prefixed-u32be";
+
+    fn encode(item: &Self::Item) -> Result<Vec<u8>, MerkleError> {
+        // Every length is checked before anything is copied
+        let lens = item
+            .iter()
+            .enumerate()
+            .map(|(field, bytes)| {
+                u32::try_from(bytes.len()).map_err(|_| MerkleError::FieldTooLarge {
+                    field,
+                    size: bytes.len() as u64,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut encoded = Vec::with_capacity(item.iter().map(|field| field.len() + 4).sum());
+        for (len, field) in lens.iter().zip(item) {
+            encoded.extend_from_slice(&len.to_be_bytes());
+            encoded.extend_from_slice(field);
+        }
+        Ok(encoded)
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+/// The number of epochs retained by default for `MerkleTree::verify_at_epoch`
+pub(crate) const DEFAULT_EPOCH_HISTORY: usize = 16;
+
+/// The root of a tree as it was at a past epoch
+#[derive(Clone, Debug)]
+pub(crate) struct PastRoot {
+    /// The epoch the root belongs to
+    pub(crate) epoch: u64,
+    /// The root hash
+    pub(crate) root: Vec<u8>,
+    /// The number of leaves the root committed to
+    pub(crate) leaf_count: usize,
+}
+
+/// A tree's current epoch and the roots of recent past epochs
+///
+/// Every mutation of a tree starts a new epoch. The roots of the epochs before the current
+/// one are kept in a ring buffer, oldest first; an epoch is evicted once it is `capacity`
+/// or more epochs older than the current one, regardless of when it was last used.
+#[derive(Clone, Debug)]
+pub(crate) struct EpochHistory {
+    /// The current epoch
+    pub(crate) epoch: u64,
+    /// The number of epochs retained, including the current one
+    capacity: usize,
+    /// The roots of retained past epochs, oldest first
+    past: VecDeque<PastRoot>,
+}
+
+impl EpochHistory {
+    /// Create the history of a new tree, which is at epoch 0
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of epochs to retain, including the current one (at
+    ///   least 1)
+    ///
+    /// # Returns
+    ///
+    /// The history
+    pub(crate) fn new(capacity: usize) -> Self {
+        EpochHistory {
+            epoch: 0,
+            capacity: capacity.max(1),
+            past: VecDeque::new(),
+        }
+    }
+
+    /// Get the number of epochs retained
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Start a new epoch, retaining the root of the one that ends
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root at the end of the current epoch, if the tree had one
+    /// * `leaf_count` - The number of leaves at the end of the current epoch
+    pub(crate) fn advance(&mut self, root: Option<Vec<u8>>, leaf_count: usize) {
+        if let Some(root) = root {
+            self.past.push_back(PastRoot {
+                epoch: self.epoch,
+                root,
+                leaf_count,
+            });
+        }
+        self.epoch += 1;
+        while self
+            .past
+            .front()
+            .is_some_and(|past| self.epoch - past.epoch >= self.capacity as u64)
+        {
+            self.past.pop_front();
+        }
+    }
+
+    /// Find the root of a retained past epoch
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The epoch, which must be before the current one
+    ///
+    /// # Returns
+    ///
+    /// The epoch's root, or None if it was evicted or the tree had no root then
+    pub(crate) fn past(&self, epoch: u64) -> Option<&PastRoot> {
+        self.past.iter().find(|past| past.epoch == epoch)
+    }
+}
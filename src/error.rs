@@ -0,0 +1,1142 @@
+#[cfg(feature = "build")]
+use crate::index::NodeCoord;
+use crate::limits::DecodeLimit;
+use crate::params::{TreeParams, TreeVersion};
+use crate::proof::RootId;
+use std::fmt;
+
+/// The reason a proof failed detailed verification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The root hash is not a digest of the expected length
+    InvalidRootLength {
+        /// The digest length of the hasher
+        expected: usize,
+        /// The length of the supplied root
+        actual: usize,
+    },
+    /// A sibling hash in the proof is not a digest of the expected length
+    InvalidSiblingLength {
+        /// The position of the offending step in the proof
+        step: usize,
+        /// The digest length of the hasher
+        expected: usize,
+        /// The length of the supplied sibling hash
+        actual: usize,
+    },
+    /// The proof has more steps than any tree could require
+    TooDeep {
+        /// The number of steps in the proof
+        steps: usize,
+        /// The maximum number of steps accepted
+        max: usize,
+    },
+    /// The proof's length or directions do not fit a tree with the given number of leaves
+    ShapeMismatch {
+        /// The number of steps in the proof
+        steps: usize,
+        /// The number of leaves in the tree
+        leaf_count: usize,
+    },
+    /// The proof's directions address a different leaf than the one claimed
+    IndexMismatch {
+        /// The index the proof was expected to address
+        expected: usize,
+        /// The index the proof's directions address
+        actual: usize,
+    },
+    /// The proof is for a tree with a different number of leaves
+    LeafCountMismatch {
+        /// The number of leaves the verifier expects
+        expected: usize,
+        /// The number of leaves the proof is for
+        actual: usize,
+    },
+    /// The multiproof's indices, leaves and sibling hashes do not fit together
+    InvalidMultiProof,
+    /// The leaf index is not within the tree
+    IndexOutOfRange {
+        /// The claimed index
+        index: usize,
+        /// The number of leaves in the tree
+        leaf_count: usize,
+    },
+    /// The proof is well-formed but does not lead to the root hash
+    RootMismatch,
+    /// The balances of a summed proof overflow 64 bits
+    SumOverflow {
+        /// The index of the proof step whose sum overflows
+        step: usize,
+    },
+    /// The proof was made at an epoch whose root is no longer retained
+    EpochUnavailable {
+        /// The epoch the proof was made at
+        epoch: u64,
+    },
+    /// The proof was made for a tree of a different version
+    VersionMismatch {
+        /// The version the verifier expects, or None if it is unversioned
+        expected: Option<TreeVersion>,
+        /// The version the proof was made for
+        actual: TreeVersion,
+    },
+    /// The proof does not lead to the root, and was made from a different state of the
+    /// tree than the verifier's root: the root is most likely stale
+    WrongRootEpoch {
+        /// The fingerprint the proof was stamped with
+        proof_root_id: RootId,
+        /// The fingerprint of the verifier's root
+        verifier_root_id: RootId,
+    },
+    /// The proof was made for a tree built with different parameters
+    ParamsMismatch {
+        /// The parameters the verifier uses
+        expected: TreeParams,
+        /// The parameters the proof was made with
+        actual: TreeParams,
+    },
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationError::InvalidRootLength { expected, actual } => {
+                write!(f, "root hash is {} bytes, expected {}", actual, expected)
+            }
+            VerificationError::InvalidSiblingLength {
+                step,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "sibling hash at step {} is {} bytes, expected {}",
+                step, actual, expected
+            ),
+            VerificationError::TooDeep { steps, max } => {
+                write!(f, "proof has {} steps, at most {} allowed", steps, max)
+            }
+            VerificationError::ShapeMismatch { steps, leaf_count } => write!(
+                f,
+                "a proof of {} steps does not fit a tree of {} leaves",
+                steps, leaf_count
+            ),
+            VerificationError::IndexMismatch { expected, actual } => write!(
+                f,
+                "proof addresses leaf {}, expected leaf {}",
+                actual, expected
+            ),
+            VerificationError::LeafCountMismatch { expected, actual } => write!(
+                f,
+                "proof is for a tree of {} leaves, expected {}",
+                actual, expected
+            ),
+            VerificationError::InvalidMultiProof => {
+                write!(
+                    f,
+                    "multiproof indices, leaves and hashes do not fit together"
+                )
+            }
+            VerificationError::IndexOutOfRange { index, leaf_count } => write!(
+                f,
+                "leaf index {} is out of range for a tree of {} leaves",
+                index, leaf_count
+            ),
+            VerificationError::RootMismatch => write!(f, "proof does not lead to the root hash"),
+            VerificationError::SumOverflow { step } => {
+                write!(f, "sum at proof step {} overflows 64 bits", step)
+            }
+            VerificationError::EpochUnavailable { epoch } => {
+                write!(f, "the root of epoch {} is no longer retained", epoch)
+            }
+            VerificationError::VersionMismatch { expected, actual } => match expected {
+                Some(expected) => write!(
+                    f,
+                    "proof is for tree version {}, expected version {}",
+                    actual, expected
+                ),
+                None => write!(
+                    f,
+                    "proof is for tree version {}, expected an unversioned tree",
+                    actual
+                ),
+            },
+            VerificationError::WrongRootEpoch {
+                proof_root_id,
+                verifier_root_id,
+            } => write!(
+                f,
+                "proof was made from tree state {}, but the root is of state {}",
+                proof_root_id, verifier_root_id
+            ),
+            VerificationError::ParamsMismatch { expected, actual } => write!(
+                f,
+                "proof was made with {:?}, expected {:?}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+/// An error returned by tree operations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    /// A leaf index is not within the tree
+    IndexOutOfRange {
+        /// The requested index
+        index: usize,
+        /// The number of leaves in the tree
+        leaf_count: usize,
+    },
+    /// A node coordinate is not within the tree
+    NodeOutOfRange {
+        /// The requested level
+        level: usize,
+        /// The requested index within the level
+        index: usize,
+    },
+    /// A leaf was not kept when the tree was pruned
+    LeafNotRetained {
+        /// The requested index
+        index: usize,
+    },
+    /// No leaf has the requested hash or data
+    LeafNotFound,
+    /// A leaf's data is needed but the tree stores only its hash
+    LeafDataUnavailable {
+        /// The index of the leaf
+        index: usize,
+    },
+    /// A shard size is not a power of two of at least 2
+    InvalidShardSize {
+        /// The requested number of leaves per shard
+        shard_leaves: usize,
+    },
+    /// A positional tree cannot be split, as its leaf hashes commit to their index in
+    /// the whole tree rather than in a shard
+    PositionalSplit,
+    /// `EmptyRootPolicy::HashOfEmpty` was paired with `HashScheme::Plain`, under which
+    /// the empty root is also the root of a single empty leaf
+    AmbiguousEmptyRoot,
+    /// A version of a concurrent tree is no longer (or not yet) retained
+    VersionUnavailable {
+        /// The requested version
+        version: u64,
+    },
+    /// The same key was given for more than one entry
+    DuplicateKey {
+        /// The repeated key
+        key: String,
+    },
+    /// A key contains a NUL character, which separates keys from values in leaf data
+    InvalidKey {
+        /// The offending key
+        key: String,
+    },
+    /// Two leaves have the same hash when duplicates are rejected
+    DuplicateLeaf {
+        /// The index of the first leaf with the hash
+        first: usize,
+        /// The index of the leaf repeating it
+        second: usize,
+        /// The hex-encoded leaf hash
+        hash: String,
+    },
+    /// A record size is zero or does not divide the length of the buffer of records
+    InvalidRecordSize {
+        /// The requested size of each record
+        record_size: usize,
+        /// The length of the buffer
+        buffer_len: usize,
+    },
+    /// A leaf's hash is not greater than the hash of the leaf before it
+    UnsortedLeaf {
+        /// The index of the leaf
+        index: usize,
+    },
+    /// An exported tree structure cannot be turned back into a tree
+    InvalidStructure {
+        /// What is wrong with the structure
+        reason: String,
+    },
+    /// A leaf hash is not as long as the tree's digest
+    InvalidHashLength {
+        /// The tree's digest length
+        expected: usize,
+        /// The length of the hash
+        actual: usize,
+    },
+    /// An encoded proof would exceed the size allowed for it
+    ProofTooLarge {
+        /// The size of the encoded proof in bytes
+        size: usize,
+        /// The largest size allowed
+        max: usize,
+    },
+    /// A checkpoint is corrupted, truncated or from an unsupported configuration
+    InvalidCheckpoint {
+        /// What is wrong with the checkpoint
+        reason: String,
+    },
+    /// A complete tree is too shallow to hold its leaves
+    TooManyLeaves {
+        /// The number of leaves
+        leaf_count: usize,
+        /// The depth of the tree
+        depth: u8,
+    },
+    /// A leaf is bigger than the builder's `max_leaf_size`
+    LeafTooLarge {
+        /// The index of the leaf
+        index: usize,
+        /// The size of the leaf in bytes, or the number read before reading stopped
+        size: u64,
+        /// The largest size allowed
+        max: u64,
+    },
+    /// A field is too long for `LengthPrefixed` to encode its length
+    FieldTooLarge {
+        /// The position of the field within its item
+        field: usize,
+        /// The size of the field in bytes
+        size: u64,
+    },
+    /// A complete tree is deeper than the longest proof that can be verified
+    DepthTooLarge {
+        /// The depth of the tree
+        depth: u8,
+        /// The largest supported depth
+        max: usize,
+    },
+    /// No consistency proof applies, or the proofs given do not determine an upgraded one
+    ConsistencyUnavailable {
+        /// Why not
+        reason: String,
+    },
+    /// The balances of a summed tree overflow 64 bits
+    SumOverflow {
+        /// The level of the node whose sum overflows
+        level: usize,
+        /// The index of the node within its level
+        index: usize,
+    },
+    /// A slotted tree was asked for zero slots
+    NoSlots,
+    /// A slot of a slotted tree is already filled and overwriting is not allowed
+    SlotOccupied {
+        /// The slot
+        slot: usize,
+    },
+    /// A slot's data is the data of an empty slot, which would leave it looking empty
+    EmptyLeafData {
+        /// The slot
+        slot: usize,
+    },
+    /// Decoding input would use more of a resource than its `DecodeLimits` allow
+    LimitExceeded {
+        /// The resource
+        which: DecodeLimit,
+        /// The largest amount allowed
+        limit: u64,
+        /// The amount the input asks for
+        requested: u64,
+    },
+    /// A diff was made against a tree with a different root than the one it is applied to
+    DiffBaseMismatch {
+        /// The root the diff was made against, or None for a tree without leaves
+        expected: Option<Vec<u8>>,
+        /// The root of the tree it is applied to
+        actual: Option<Vec<u8>>,
+    },
+    /// Applying a diff does not give the root recorded in it
+    DiffRootMismatch {
+        /// The root recorded in the diff
+        expected: Option<Vec<u8>>,
+        /// The root applying it gives
+        actual: Option<Vec<u8>>,
+    },
+    /// A diff is malformed, or cannot be applied to a tree with an operation log
+    InvalidDiff {
+        /// What is wrong with the diff
+        reason: String,
+    },
+    /// The store holding a tree's levels failed to read or write a node
+    Storage {
+        /// The store's error
+        reason: String,
+    },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let root = |root: &Option<Vec<u8>>| match root {
+            Some(root) => hex::encode(root),
+            None => "none".to_string(),
+        };
+        match self {
+            MerkleError::IndexOutOfRange { index, leaf_count } => write!(
+                f,
+                "leaf index {} is out of range for a tree of {} leaves",
+                index, leaf_count
+            ),
+            MerkleError::NodeOutOfRange { level, index } => {
+                write!(f, "the tree has no node {} on level {}", index, level)
+            }
+            MerkleError::LeafNotRetained { index } => {
+                write!(f, "leaf {} was not kept when the tree was pruned", index)
+            }
+            MerkleError::LeafNotFound => write!(f, "no leaf has the requested hash or data"),
+            MerkleError::LeafDataUnavailable { index } => {
+                write!(f, "the data of leaf {} is not stored", index)
+            }
+            MerkleError::InvalidShardSize { shard_leaves } => write!(
+                f,
+                "shards of {} leaves are not a power of two of at least 2",
+                shard_leaves
+            ),
+            MerkleError::PositionalSplit => {
+                write!(f, "a tree of positional leaves cannot be split")
+            }
+            MerkleError::AmbiguousEmptyRoot => write!(
+                f,
+                "the hash of empty is also the root of an empty leaf under plain hashing"
+            ),
+            MerkleError::VersionUnavailable { version } => {
+                write!(f, "version {} is not retained", version)
+            }
+            MerkleError::DuplicateKey { key } => write!(f, "key {:?} appears more than once", key),
+            MerkleError::InvalidKey { key } => {
+                write!(f, "key {:?} contains a NUL character", key)
+            }
+            MerkleError::DuplicateLeaf {
+                first,
+                second,
+                hash,
+            } => write!(
+                f,
+                "leaves {} and {} have the same hash {}",
+                first, second, hash
+            ),
+            MerkleError::InvalidRecordSize {
+                record_size,
+                buffer_len,
+            } => write!(
+                f,
+                "a buffer of {} bytes cannot be split into records of {} bytes",
+                buffer_len, record_size
+            ),
+            MerkleError::UnsortedLeaf { index } => write!(
+                f,
+                "the hash of leaf {} is not greater than the hash of the leaf before it",
+                index
+            ),
+            MerkleError::InvalidStructure { reason } => {
+                write!(f, "invalid tree structure: {}", reason)
+            }
+            MerkleError::InvalidHashLength { expected, actual } => {
+                write!(f, "leaf hash is {} bytes, expected {}", actual, expected)
+            }
+            MerkleError::ProofTooLarge { size, max } => write!(
+                f,
+                "encoded proof is {} bytes, at most {} allowed",
+                size, max
+            ),
+            MerkleError::InvalidCheckpoint { reason } => {
+                write!(f, "invalid checkpoint: {}", reason)
+            }
+            MerkleError::ConsistencyUnavailable { reason } => {
+                write!(f, "no consistency proof applies: {}", reason)
+            }
+            MerkleError::TooManyLeaves { leaf_count, depth } => write!(
+                f,
+                "{} leaves do not fit a complete tree of depth {}",
+                leaf_count, depth
+            ),
+            MerkleError::LeafTooLarge { index, size, max } => write!(
+                f,
+                "leaf {} is {} bytes, more than the limit of {}",
+                index, size, max
+            ),
+            MerkleError::FieldTooLarge { field, size } => write!(
+                f,
+                "field {} is {} bytes, too long for a 4-byte length prefix",
+                field, size
+            ),
+            MerkleError::DepthTooLarge { depth, max } => {
+                write!(f, "depth {} exceeds the maximum of {}", depth, max)
+            }
+            MerkleError::SumOverflow { level, index } => write!(
+                f,
+                "sum of node {} on level {} overflows 64 bits",
+                index, level
+            ),
+            MerkleError::NoSlots => write!(f, "a slotted tree needs at least one slot"),
+            MerkleError::SlotOccupied { slot } => write!(f, "slot {} is already filled", slot),
+            MerkleError::EmptyLeafData { slot } => {
+                write!(f, "the data for slot {} is the data of an empty slot", slot)
+            }
+            MerkleError::LimitExceeded {
+                which,
+                limit,
+                requested,
+            } => write!(
+                f,
+                "input asks for {} of {}, more than the limit of {}",
+                requested, which, limit
+            ),
+            MerkleError::DiffBaseMismatch { expected, actual } => write!(
+                f,
+                "diff applies to root {}, tree has {}",
+                root(expected),
+                root(actual)
+            ),
+            MerkleError::DiffRootMismatch { expected, actual } => write!(
+                f,
+                "diff records root {}, applying it gives {}",
+                root(expected),
+                root(actual)
+            ),
+            MerkleError::InvalidDiff { reason } => write!(f, "invalid diff: {}", reason),
+            MerkleError::Storage { reason } => write!(f, "level store failed: {}", reason),
+        }
+    }
+}
+
+impl MerkleError {
+    /// The HTTP status code a service should answer with when a request fails with
+    /// this error
+    ///
+    /// Almost every error is caused by the request: a leaf or version that does not exist
+    /// is 404, data that was not kept is 410, malformed input is 400, a leaf over the
+    /// size limit is 413, and other well-formed input the tree cannot accept is 422. A
+    /// failing level store is the server's fault, and is 500.
+    ///
+    /// # Returns
+    ///
+    /// The status code
+    pub fn status_code(&self) -> u16 {
+        match self {
+            MerkleError::IndexOutOfRange { .. }
+            | MerkleError::NodeOutOfRange { .. }
+            | MerkleError::LeafNotFound
+            | MerkleError::VersionUnavailable { .. } => 404,
+            MerkleError::LeafNotRetained { .. } | MerkleError::LeafDataUnavailable { .. } => 410,
+            MerkleError::InvalidShardSize { .. }
+            | MerkleError::InvalidKey { .. }
+            | MerkleError::InvalidRecordSize { .. }
+            | MerkleError::InvalidStructure { .. }
+            | MerkleError::InvalidHashLength { .. }
+            | MerkleError::InvalidCheckpoint { .. }
+            | MerkleError::InvalidDiff { .. }
+            | MerkleError::NoSlots
+            | MerkleError::DepthTooLarge { .. } => 400,
+            MerkleError::LeafTooLarge { .. }
+            | MerkleError::FieldTooLarge { .. }
+            | MerkleError::LimitExceeded { .. } => 413,
+            MerkleError::DuplicateKey { .. }
+            | MerkleError::DuplicateLeaf { .. }
+            | MerkleError::UnsortedLeaf { .. }
+            | MerkleError::PositionalSplit
+            | MerkleError::AmbiguousEmptyRoot
+            | MerkleError::ProofTooLarge { .. }
+            | MerkleError::TooManyLeaves { .. }
+            | MerkleError::SumOverflow { .. }
+            | MerkleError::ConsistencyUnavailable { .. }
+            | MerkleError::SlotOccupied { .. }
+            | MerkleError::EmptyLeafData { .. }
+            | MerkleError::DiffBaseMismatch { .. }
+            | MerkleError::DiffRootMismatch { .. } => 422,
+            MerkleError::Storage { .. } => 500,
+        }
+    }
+}
+
+impl std::error::Error for MerkleError {}
+
+/// The reason an encoded proof could not be decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofDecodeError {
+    /// The string is not strictly valid hexadecimal
+    InvalidHex,
+    /// The string is not strictly valid padded base64
+    InvalidBase64,
+    /// A step's direction byte is neither 0 nor 1
+    InvalidDirection {
+        /// The offending byte
+        byte: u8,
+    },
+    /// The encoding ends in the middle of a step
+    Truncated,
+    /// A stamped proof's version byte is not one this crate reads
+    UnsupportedVersion {
+        /// The offending byte
+        version: u8,
+    },
+    /// A multiproof's padding byte is not one of the known strategies
+    InvalidPadding {
+        /// The offending byte
+        byte: u8,
+    },
+    /// The encoding has, or is too long not to have, more than the maximum number of steps
+    TooDeep {
+        /// The maximum number of steps accepted
+        max: usize,
+    },
+    /// A proof's sibling hashes and directions are given separately, and their numbers
+    /// differ
+    DirectionCountMismatch {
+        /// The number of sibling hashes
+        steps: usize,
+        /// The number of directions
+        directions: usize,
+    },
+    /// A direction given as a number is neither 0 nor 1
+    InvalidDirectionValue {
+        /// The offending value
+        value: i64,
+    },
+    /// Decoding would exceed the decoder's limits; always `MerkleError::LimitExceeded`
+    LimitExceeded(MerkleError),
+}
+
+impl fmt::Display for ProofDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofDecodeError::InvalidHex => write!(f, "proof is not valid hexadecimal"),
+            ProofDecodeError::InvalidBase64 => write!(f, "proof is not valid base64"),
+            ProofDecodeError::InvalidDirection { byte } => {
+                write!(f, "direction byte {:#04x} is neither 0 nor 1", byte)
+            }
+            ProofDecodeError::Truncated => write!(f, "proof ends in the middle of a step"),
+            ProofDecodeError::UnsupportedVersion { version } => {
+                write!(f, "stamped proof version {} is not supported", version)
+            }
+            ProofDecodeError::InvalidPadding { byte } => {
+                write!(
+                    f,
+                    "padding byte {:#04x} is not a known padding strategy",
+                    byte
+                )
+            }
+            ProofDecodeError::TooDeep { max } => {
+                write!(f, "proof has more than the maximum of {} steps", max)
+            }
+            ProofDecodeError::DirectionCountMismatch { steps, directions } => write!(
+                f,
+                "proof has {} sibling hashes but {} directions",
+                steps, directions
+            ),
+            ProofDecodeError::InvalidDirectionValue { value } => {
+                write!(f, "direction {} is neither 0 nor 1", value)
+            }
+            ProofDecodeError::LimitExceeded(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProofDecodeError {}
+
+/// The reason a streamed dataset failed verification against a root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamVerifyError {
+    /// The root computed from the stream differs from the expected root
+    RootMismatch {
+        /// The number of leaves read from the stream
+        leaves_consumed: usize,
+    },
+    /// The stream is empty and the empty root policy gives no root
+    NoEmptyRoot,
+}
+
+impl fmt::Display for StreamVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamVerifyError::RootMismatch { leaves_consumed } => write!(
+                f,
+                "the root of the {} streamed leaves does not match",
+                leaves_consumed
+            ),
+            StreamVerifyError::NoEmptyRoot => {
+                write!(f, "the stream is empty and an empty tree has no root")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StreamVerifyError {}
+
+/// The first inconsistency found when auditing a tree or checking an audit sample
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditError {
+    /// The tree has the wrong number of levels for its leaf count
+    HeightMismatch {
+        /// The number of levels the leaf count requires
+        expected: usize,
+        /// The number of levels stored
+        actual: usize,
+    },
+    /// A level has the wrong number of nodes for the tree's leaf count
+    LevelSizeMismatch {
+        /// The level
+        level: usize,
+        /// The number of nodes the leaf count requires
+        expected: usize,
+        /// The number of nodes stored
+        actual: usize,
+    },
+    /// A stored hash differs from the one recomputed from the node's data or children
+    NodeMismatch {
+        /// The level of the node
+        level: usize,
+        /// The index of the node within its level
+        index: usize,
+        /// The recomputed hash
+        expected: Vec<u8>,
+        /// The stored hash
+        stored: Vec<u8>,
+    },
+    /// An audit sample reveals a different number of leaves than the root requires
+    SampleCountMismatch {
+        /// The number of leaves the root requires
+        expected: usize,
+        /// The number of leaves revealed
+        actual: usize,
+    },
+    /// An audit sample reveals a different leaf than the root chose
+    SampleIndexMismatch {
+        /// The position of the leaf in the sample
+        position: usize,
+        /// The index the root chose
+        expected: usize,
+        /// The index revealed
+        actual: usize,
+    },
+    /// The data of a leaf chosen for an audit sample was cleared, so it cannot be revealed
+    SampleDataUnavailable {
+        /// The index of the leaf
+        index: usize,
+    },
+    /// The proof of a sampled leaf is invalid
+    SampleProofInvalid {
+        /// The index of the leaf
+        index: usize,
+        /// Why the proof was rejected
+        error: VerificationError,
+    },
+    /// The tree's proofs do not fix the index of their leaf (`PairOrdering::Sorted`
+    /// without positional leaves), so a sample cannot show that the chosen leaves were
+    /// revealed
+    SampleIndexUnbound,
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::HeightMismatch { expected, actual } => {
+                write!(f, "tree has {} levels, expected {}", actual, expected)
+            }
+            AuditError::LevelSizeMismatch {
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "level {} has {} nodes, expected {}",
+                level, actual, expected
+            ),
+            AuditError::NodeMismatch {
+                level,
+                index,
+                expected,
+                stored,
+            } => write!(
+                f,
+                "node {} on level {} stores {}, expected {}",
+                index,
+                level,
+                hex::encode(stored),
+                hex::encode(expected)
+            ),
+            AuditError::SampleCountMismatch { expected, actual } => write!(
+                f,
+                "audit sample reveals {} leaves, expected {}",
+                actual, expected
+            ),
+            AuditError::SampleIndexMismatch {
+                position,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "audit sample reveals leaf {} at position {}, expected leaf {}",
+                actual, position, expected
+            ),
+            AuditError::SampleDataUnavailable { index } => {
+                write!(f, "the data of sampled leaf {} is not stored", index)
+            }
+            AuditError::SampleProofInvalid { index, error } => {
+                write!(f, "proof of sampled leaf {} is invalid: {}", index, error)
+            }
+            AuditError::SampleIndexUnbound => write!(
+                f,
+                "proofs under sorted pair ordering do not bind the sampled indices"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+/// The reason a commitment rejected a reveal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RevealError {
+    /// The revealed index is not within the committed tree
+    IndexOutOfRange {
+        /// The revealed index
+        index: usize,
+        /// The number of leaves committed to
+        leaf_count: usize,
+    },
+    /// The index was already revealed
+    AlreadyRevealed {
+        /// The revealed index
+        index: usize,
+    },
+    /// The reveal's proof does not prove its data at its index
+    InvalidProof(VerificationError),
+}
+
+impl fmt::Display for RevealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevealError::IndexOutOfRange { index, leaf_count } => write!(
+                f,
+                "revealed index {} is out of range for a commitment to {} leaves",
+                index, leaf_count
+            ),
+            RevealError::AlreadyRevealed { index } => {
+                write!(f, "leaf {} was already revealed", index)
+            }
+            RevealError::InvalidProof(e) => write!(f, "invalid reveal: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RevealError {}
+
+/// The reason canonical JSON could not be decoded
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum CanonicalJsonError {
+    /// The JSON is invalid or does not match the canonical schema
+    Json(serde_json::Error),
+    /// The JSON is valid but not in canonical form, and strict parsing was requested
+    NotCanonical,
+    /// Decoding would exceed the decoder's limits; always `MerkleError::LimitExceeded`
+    LimitExceeded(MerkleError),
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for CanonicalJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanonicalJsonError::Json(e) => write!(f, "invalid proof json: {}", e),
+            CanonicalJsonError::NotCanonical => write!(f, "proof json is not in canonical form"),
+            CanonicalJsonError::LimitExceeded(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for CanonicalJsonError {}
+
+/// Errors that can occur when reading a tree snapshot
+#[cfg(feature = "build")]
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The snapshot file could not be read
+    Io(std::io::Error),
+    /// The snapshot is truncated, inconsistent or from an unsupported configuration
+    Malformed {
+        /// What is wrong with the snapshot
+        reason: String,
+    },
+    /// A proof was requested for a leaf the tree does not have
+    IndexOutOfRange {
+        /// The requested index
+        index: usize,
+        /// The number of leaves in the tree
+        leaf_count: usize,
+    },
+    /// Opening the snapshot would exceed the reader's limits; always
+    /// `MerkleError::LimitExceeded`
+    LimitExceeded(MerkleError),
+    /// The loaded tree failed its audit (see `MerkleTree::from_snapshot_audited`)
+    Audit(AuditError),
+}
+
+#[cfg(feature = "build")]
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "cannot read snapshot: {}", e),
+            SnapshotError::Malformed { reason } => write!(f, "invalid snapshot: {}", reason),
+            SnapshotError::IndexOutOfRange { index, leaf_count } => write!(
+                f,
+                "leaf index {} is out of range for a tree of {} leaves",
+                index, leaf_count
+            ),
+            SnapshotError::LimitExceeded(e) => write!(f, "{}", e),
+            SnapshotError::Audit(e) => write!(f, "snapshot failed its audit: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "build")]
+impl std::error::Error for SnapshotError {}
+
+/// Errors that can occur when building a tree from readers
+#[cfg(feature = "build")]
+#[derive(Debug)]
+pub enum IngestError {
+    /// A leaf could not be read
+    Io(std::io::Error),
+    /// The tree could not be built, such as when a leaf is over the size limit
+    Build(MerkleError),
+}
+
+#[cfg(feature = "build")]
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestError::Io(e) => write!(f, "cannot read leaf: {}", e),
+            IngestError::Build(e) => write!(f, "cannot build tree: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "build")]
+impl std::error::Error for IngestError {}
+
+/// The reason leaf data from an untrusted source does not build the trusted tree
+#[cfg(feature = "build")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildVerifyError {
+    /// The leaves could not be built into a tree
+    Build(MerkleError),
+    /// There are no leaves, but the trusted tree is not empty
+    Empty,
+    /// The number of leaves is not the trusted tree's
+    LeafCountMismatch {
+        /// The number of leaves in the trusted tree
+        expected: usize,
+        /// The number of leaves received
+        actual: usize,
+    },
+    /// A node of the built tree is not the trusted node, and no lower node is known to be
+    Mismatch {
+        /// The lowest trusted node that differs
+        node: NodeCoord,
+        /// The leaves under that node, at least one of which is corrupted
+        leaves: std::ops::Range<usize>,
+        /// The trusted hash of the node
+        expected: Vec<u8>,
+        /// The hash the leaves give the node
+        actual: Vec<u8>,
+    },
+}
+
+#[cfg(feature = "build")]
+impl fmt::Display for BuildVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildVerifyError::Build(e) => write!(f, "cannot build tree: {}", e),
+            BuildVerifyError::Empty => write!(f, "no leaves, but the trusted tree has a root"),
+            BuildVerifyError::LeafCountMismatch { expected, actual } => write!(
+                f,
+                "received {} leaves, the trusted tree has {}",
+                actual, expected
+            ),
+            BuildVerifyError::Mismatch {
+                node,
+                leaves,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} is {}, trusted tree has {}; a leaf in {}..{} is corrupted",
+                node,
+                hex::encode(actual),
+                hex::encode(expected),
+                leaves.start,
+                leaves.end
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "build")]
+impl std::error::Error for BuildVerifyError {}
+
+/// The first point at which an operation log and the tree replayed from it diverge
+#[cfg(feature = "build")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The initial leaves could not be built into a tree
+    Build(MerkleError),
+    /// The initial leaves do not give the root the log starts from
+    InitialRootMismatch {
+        /// The root the log starts from
+        expected: Option<Vec<u8>>,
+        /// The root of the initial leaves
+        actual: Option<Vec<u8>>,
+    },
+    /// The replayed tree's root is not the root an entry records before it
+    RootBeforeMismatch {
+        /// The position of the entry in the log
+        entry: usize,
+        /// The root the entry records before it
+        expected: Option<Vec<u8>>,
+        /// The replayed tree's root
+        actual: Option<Vec<u8>>,
+    },
+    /// Applying an entry does not give the root the entry records after it
+    RootAfterMismatch {
+        /// The position of the entry in the log
+        entry: usize,
+        /// The root the entry records after it
+        expected: Vec<u8>,
+        /// The replayed tree's root
+        actual: Option<Vec<u8>>,
+    },
+    /// An entry cannot be applied to the replayed tree
+    InvalidEntry {
+        /// The position of the entry in the log
+        entry: usize,
+        /// Why the entry cannot be applied
+        error: MerkleError,
+    },
+}
+
+#[cfg(feature = "build")]
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let root = |root: &Option<Vec<u8>>| match root {
+            Some(root) => hex::encode(root),
+            None => "none".to_string(),
+        };
+        match self {
+            ReplayError::Build(e) => write!(f, "cannot build initial tree: {}", e),
+            ReplayError::InitialRootMismatch { expected, actual } => write!(
+                f,
+                "initial leaves have root {}, log starts from {}",
+                root(actual),
+                root(expected)
+            ),
+            ReplayError::RootBeforeMismatch {
+                entry,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "log entry {} starts from root {}, replayed tree has {}",
+                entry,
+                root(expected),
+                root(actual)
+            ),
+            ReplayError::RootAfterMismatch {
+                entry,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "log entry {} ends at root {}, replayed tree has {}",
+                entry,
+                hex::encode(expected),
+                root(actual)
+            ),
+            ReplayError::InvalidEntry { entry, error } => {
+                write!(f, "log entry {} cannot be applied: {}", entry, error)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "build")]
+impl std::error::Error for ReplayError {}
+
+/// The reason a conformance suite failed
+#[cfg(feature = "conformance")]
+#[derive(Debug)]
+pub enum ConformanceError {
+    /// The suite file could not be read
+    Io(std::io::Error),
+    /// The suite file is not a valid suite
+    Json(serde_json::Error),
+    /// The suite's format version is not supported
+    UnsupportedVersion {
+        /// The suite's version
+        version: u32,
+    },
+    /// A case's tree could not be built
+    Build {
+        /// The name of the case
+        case: String,
+        /// The reason the tree could not be built
+        error: MerkleError,
+    },
+    /// A case's root differs from the one computed
+    RootMismatch {
+        /// The name of the case
+        case: String,
+        /// The root listed in the suite
+        expected: Vec<u8>,
+        /// The root computed
+        actual: Vec<u8>,
+    },
+    /// A case's proof differs from the one computed, or does not verify
+    ProofMismatch {
+        /// The name of the case
+        case: String,
+        /// The index of the proven leaf
+        index: usize,
+    },
+}
+
+#[cfg(feature = "conformance")]
+impl fmt::Display for ConformanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConformanceError::Io(e) => write!(f, "cannot read conformance suite: {}", e),
+            ConformanceError::Json(e) => write!(f, "invalid conformance suite: {}", e),
+            ConformanceError::UnsupportedVersion { version } => {
+                write!(f, "unsupported conformance suite version {}", version)
+            }
+            ConformanceError::Build { case, error } => {
+                write!(f, "case \"{}\" cannot be built: {}", case, error)
+            }
+            ConformanceError::RootMismatch {
+                case,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "case \"{}\" has root {}, expected {}",
+                case,
+                hex::encode(actual),
+                hex::encode(expected)
+            ),
+            ConformanceError::ProofMismatch { case, index } => {
+                write!(
+                    f,
+                    "case \"{}\" has a different proof for leaf {}",
+                    case, index
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "conformance")]
+impl std::error::Error for ConformanceError {}
@@ -0,0 +1,388 @@
+//! Two-level trees over the chunks of a large file
+//!
+//! A flat tree over a large file's chunks gives deep proofs, and listing every chunk hash
+//! gives a large manifest. A two-level tree commits consecutive groups of chunks into
+//! group roots and the group roots into the file root. A chunk's proof is a
+//! `ChainedProof`: the inner proof needs only the sibling hashes within the chunk's
+//! group, and the outer proof leads from the group root to the file root. The manifest
+//! lists the group roots only.
+
+use crate::chained::ChainedProof;
+use crate::chunked::read_chunk_hashes;
+use crate::hashing::HashScheme;
+use crate::merkle_tree::MerkleTree;
+use crate::params::TreeParams;
+use crate::proof::ProofEncoding;
+use crate::verify::{proof_fits_shape, proof_leaf_index};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// The smallest chunk size `FileTreeConfig::recommend` suggests
+pub const MIN_RECOMMENDED_CHUNK_SIZE: usize = 4096;
+
+/// The chunk and group sizes of a two-level file tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileTreeConfig {
+    /// The length of every chunk but the last
+    pub chunk_size: usize,
+    /// The number of chunks in every group but the last
+    pub group_size: usize,
+}
+
+impl FileTreeConfig {
+    /// Create a configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_size` - The length of every chunk but the last
+    /// * `group_size` - The number of chunks in every group but the last
+    ///
+    /// # Returns
+    ///
+    /// The configuration
+    ///
+    /// # Panics
+    ///
+    /// Panics if either size is 0
+    pub fn new(chunk_size: usize, group_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        assert!(group_size > 0, "group size must be positive");
+        FileTreeConfig {
+            chunk_size,
+            group_size,
+        }
+    }
+
+    /// Recommend chunk and group sizes for a file and a proof size budget
+    ///
+    /// A chunk's proof has about one step per doubling of the chunk count, however the
+    /// chunks are grouped, so the chunk size is the smallest power of two (at least
+    /// `MIN_RECOMMENDED_CHUNK_SIZE`) whose chunk count fits the budget. The steps are then
+    /// split evenly between the levels, with groups of about the square root of the chunk
+    /// count, which keeps both the group trees and the manifest of group roots small.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_len` - The length of the file
+    /// * `target_proof_bytes` - The largest binary size (see `MerkleProof::to_bytes`) of
+    ///   the inner and outer proofs together
+    ///
+    /// # Returns
+    ///
+    /// The configuration; if even a single chunk exceeds the budget, the whole file is
+    /// one chunk and proofs are empty
+    pub fn recommend(file_len: u64, target_proof_bytes: usize) -> Self {
+        let step_len = ProofEncoding::Binary.max_size(1, FileTree::params().hasher.digest_len());
+        let max_steps = (target_proof_bytes / step_len).min(62) as u32;
+        let mut chunk_size = MIN_RECOMMENDED_CHUNK_SIZE as u64;
+        while file_len.div_ceil(chunk_size) > 1 << max_steps {
+            chunk_size *= 2;
+        }
+        let chunk_count = file_len.div_ceil(chunk_size).max(1);
+        let steps = chunk_count.next_power_of_two().trailing_zeros();
+        FileTreeConfig::new(
+            usize::try_from(chunk_size).unwrap_or(usize::MAX),
+            1 << steps.div_ceil(2),
+        )
+    }
+
+    /// Compute the largest binary size of a chunk's proof in a file of a given length
+    ///
+    /// # Arguments
+    ///
+    /// * `file_len` - The length of the file
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes of the longest inner and outer proofs together
+    pub fn max_proof_size(&self, file_len: u64) -> usize {
+        let chunk_count = file_len.div_ceil(self.chunk_size as u64) as usize;
+        let group_count = chunk_count.div_ceil(self.group_size);
+        let steps = steps_for(chunk_count.min(self.group_size)) + steps_for(group_count);
+        ProofEncoding::Binary.max_size(steps, FileTree::params().hasher.digest_len())
+    }
+}
+
+/// Get the most steps a proof in a tree of a number of leaves has
+fn steps_for(leaf_count: usize) -> usize {
+    leaf_count.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+/// A two-level Merkle tree over the chunks of a file
+///
+/// Chunks are hashed with SHA-256 and domain separation, like `ChunkedFileTree`. Each
+/// group of chunks forms a tree, and the group roots are the leaves of the top-level
+/// tree, whose root is the file root.
+#[derive(Clone, Debug)]
+pub struct FileTree {
+    config: FileTreeConfig,
+    file_len: u64,
+    groups: Vec<MerkleTree>,
+    top: MerkleTree,
+}
+
+impl FileTree {
+    /// Build the tree of a file read to its end
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The file's contents
+    /// * `config` - The chunk and group sizes
+    ///
+    /// # Returns
+    ///
+    /// The tree, or the error that interrupted reading
+    ///
+    /// # Panics
+    ///
+    /// Panics if either size in the configuration is 0
+    pub fn from_reader(reader: impl Read, config: FileTreeConfig) -> std::io::Result<Self> {
+        let config = FileTreeConfig::new(config.chunk_size, config.group_size);
+        let params = Self::params();
+        let (chunk_hashes, file_len) =
+            read_chunk_hashes(reader, config.chunk_size, &params.hasher)?;
+        let groups: Vec<MerkleTree> = chunk_hashes
+            .chunks(config.group_size)
+            .map(|group| MerkleTree::from_leaf_hashes(group.to_vec(), params))
+            .collect();
+        let top = MerkleTree::from_leaf_hashes(
+            groups
+                .iter()
+                .map(|group| params.hasher.hash_leaf(group.root_hash_ref().unwrap()))
+                .collect(),
+            params,
+        );
+        Ok(FileTree {
+            config,
+            file_len,
+            groups,
+            top,
+        })
+    }
+
+    /// Build the tree of a file held in memory
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The file's contents
+    /// * `config` - The chunk and group sizes
+    ///
+    /// # Returns
+    ///
+    /// The tree
+    ///
+    /// # Panics
+    ///
+    /// Panics if either size in the configuration is 0
+    pub fn from_bytes(bytes: &[u8], config: FileTreeConfig) -> Self {
+        Self::from_reader(bytes, config).expect("reading from a slice cannot fail")
+    }
+
+    /// The parameters the group and top-level trees are built with
+    fn params() -> TreeParams {
+        TreeParams::from(HashScheme::DomainSeparated)
+    }
+
+    /// Get the root hash of the file
+    ///
+    /// # Returns
+    ///
+    /// The root of the top-level tree, or None for an empty file
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.top.root_hash()
+    }
+
+    /// Get the chunk and group sizes
+    ///
+    /// # Returns
+    ///
+    /// The configuration the tree was built with
+    pub fn config(&self) -> FileTreeConfig {
+        self.config
+    }
+
+    /// Get the length of the file
+    ///
+    /// # Returns
+    ///
+    /// The file's length in bytes
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// Get the number of chunks
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks
+    pub fn chunk_count(&self) -> usize {
+        self.groups.iter().map(MerkleTree::leaf_count).sum()
+    }
+
+    /// Get the number of groups
+    ///
+    /// # Returns
+    ///
+    /// The number of groups, and so of leaves of the top-level tree
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Generate a proof for a chunk
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the chunk in the file
+    ///
+    /// # Returns
+    ///
+    /// The proof from the chunk to its group's root and from the group root to the file
+    /// root, or None if the index is out of range
+    pub fn prove_chunk(&self, index: usize) -> Option<ChainedProof> {
+        let group = index / self.config.group_size;
+        let inner = self
+            .groups
+            .get(group)?
+            .proof_by_index(index % self.config.group_size)?;
+        let outer = self.top.proof_by_index(group)?;
+        Some(inner.chain(outer))
+    }
+
+    /// Get the manifest of the file
+    ///
+    /// # Returns
+    ///
+    /// The configuration, the file's length and the group roots
+    pub fn manifest(&self) -> FileManifest {
+        FileManifest {
+            config: self.config,
+            file_len: self.file_len,
+            group_roots: self
+                .groups
+                .iter()
+                .map(|group| group.root_hash().unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Build the two-level tree of a file on disk
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    /// * `config` - The chunk and group sizes (see `FileTreeConfig::recommend`)
+    ///
+    /// # Returns
+    ///
+    /// The tree, or the error that prevented reading the file
+    ///
+    /// # Panics
+    ///
+    /// Panics if either size in the configuration is 0
+    pub fn from_file_two_level(
+        path: impl AsRef<Path>,
+        config: FileTreeConfig,
+    ) -> std::io::Result<FileTree> {
+        FileTree::from_reader(BufReader::new(File::open(path)?), config)
+    }
+}
+
+/// What a verifier needs to know about a two-level file tree
+///
+/// The manifest lists the root of each group, not of each chunk, so its size grows with
+/// the number of groups only. With the `serde` feature the group roots serialize as
+/// hexadecimal strings.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileManifest {
+    /// The chunk and group sizes
+    pub config: FileTreeConfig,
+    /// The length of the file
+    pub file_len: u64,
+    /// The root of each group's tree
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::list"))]
+    pub group_roots: Vec<Vec<u8>>,
+}
+
+impl FileManifest {
+    /// Compute the file root the group roots commit to
+    ///
+    /// # Returns
+    ///
+    /// The root of the top-level tree, or None for an empty file
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        let params = FileTree::params();
+        MerkleTree::from_leaf_hashes(
+            self.group_roots
+                .iter()
+                .map(|root| params.hasher.hash_leaf(root))
+                .collect(),
+            params,
+        )
+        .root_hash()
+    }
+
+    /// Get the number of chunks
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks the file splits into (0 for a manifest with no chunk size)
+    pub fn chunk_count(&self) -> usize {
+        match self.config.chunk_size {
+            0 => 0,
+            chunk_size => self.file_len.div_ceil(chunk_size as u64) as usize,
+        }
+    }
+
+    /// Verify a chunk against a file root
+    ///
+    /// Only the chunk's proof is needed: the inner proof's siblings within its group and
+    /// the outer proof's path to the root. The chunk must have its expected length, and
+    /// both proofs must have the shape of the chunk's position, so a proof for another
+    /// chunk is rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the chunk in the file
+    /// * `chunk` - The chunk's bytes
+    /// * `proof` - The chunk's proof, from `FileTree::prove_chunk`
+    /// * `root` - The file root to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the chunk is the file's chunk at `index`
+    pub fn verify_chunk(
+        &self,
+        index: usize,
+        chunk: &[u8],
+        proof: &ChainedProof,
+        root: &[u8],
+    ) -> bool {
+        let (chunk_size, group_size) = (self.config.chunk_size, self.config.group_size);
+        let chunk_count = self.chunk_count();
+        if index >= chunk_count || group_size == 0 {
+            return false;
+        }
+        let expected_len =
+            (self.file_len - index as u64 * chunk_size as u64).min(chunk_size as u64);
+        let group = index / group_size;
+        let group_len = (chunk_count - group * group_size).min(group_size);
+        let group_count = chunk_count.div_ceil(group_size);
+        let params = FileTree::params();
+        let fits = |proof, leaf_count, index| {
+            proof_fits_shape(proof, leaf_count, params.padding, params.hasher.ordering)
+                && proof_leaf_index(proof, leaf_count, params.padding) == Some(index)
+        };
+
+        chunk.len() as u64 == expected_len
+            && fits(&proof.inner, group_len, index % group_size)
+            && fits(&proof.outer, group_count, group)
+            && proof.verify(params.hasher, chunk, root)
+    }
+}
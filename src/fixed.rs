@@ -0,0 +1,292 @@
+//! Complete trees whose depth is fixed at compile time
+//!
+//! `FixedDepthTree` keeps every node of a complete SHA-256 tree in a single array, so it
+//! never allocates and its size is known at compile time, which suits embedded targets
+//! where the depth is part of the protocol. Unset leaves hold `EMPTY_LEAF`, so the root
+//! is the one `PaddingStrategy::Complete` gives for the same leaf hashes.
+//!
+//! This module needs neither std nor an allocator, and is the only one compiled without
+//! the `std` feature.
+
+use core::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::scheme::{HashScheme, EMPTY_LEAF_DATA, LEAF_PREFIX, NODE_PREFIX};
+
+/// The greatest depth of a `FixedDepthTree`
+///
+/// A tree of depth `D` is a `2^(D + 6)`-byte value, 4 MiB at this depth, and `new`
+/// builds it on the stack before it is moved into place, so deep trees need a thread
+/// with a large enough stack.
+pub const MAX_FIXED_DEPTH: usize = 16;
+
+/// The number of nodes a `FixedDepthTree` of a given depth stores
+///
+/// The nodes are stored from index 1, so this is one more than the number of nodes in
+/// a complete tree of the depth.
+///
+/// # Arguments
+///
+/// * `depth` - The depth of the tree
+///
+/// # Returns
+///
+/// `2^(depth + 1)`
+pub const fn fixed_depth_nodes(depth: usize) -> usize {
+    2 << depth
+}
+
+/// A complete tree of depth `D` in a fixed-size array
+///
+/// Const generics cannot yet size an array by an expression of `D`, so the array length
+/// `N` is a second parameter and must be `fixed_depth_nodes(D)`; any other `N`, or a `D`
+/// above `MAX_FIXED_DEPTH`, fails to compile:
+///
+/// ```
+/// use merkleproof::{fixed_depth_nodes, FixedDepthTree, HashScheme};
+///
+/// let mut tree = FixedDepthTree::<3, { fixed_depth_nodes(3) }>::new(HashScheme::Plain);
+/// tree.set_leaf_data(5, b"five").unwrap();
+/// let proof = tree.proof(5).unwrap();
+/// assert!(merkleproof::verify_fixed_proof(
+///     HashScheme::Plain,
+///     tree.leaf(5).unwrap(),
+///     &proof,
+///     &tree.root()
+/// ));
+/// ```
+///
+/// ```compile_fail
+/// use merkleproof::{FixedDepthTree, HashScheme};
+///
+/// let tree = FixedDepthTree::<3, 8>::new(HashScheme::Plain);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixedDepthTree<const D: usize, const N: usize> {
+    scheme: HashScheme,
+    /// The root at index 1 and the children of node `k` at `2k` and `2k + 1`, so the
+    /// leaves start at `N / 2`
+    nodes: [[u8; 32]; N],
+}
+
+impl<const D: usize, const N: usize> FixedDepthTree<D, N> {
+    /// Rejects unsupported depths and array lengths at compile time
+    const SHAPE: () = {
+        assert!(D <= MAX_FIXED_DEPTH, "depth exceeds MAX_FIXED_DEPTH");
+        assert!(N == fixed_depth_nodes(D), "N must be fixed_depth_nodes(D)");
+    };
+
+    /// Create a tree whose leaves are all empty
+    ///
+    /// # Arguments
+    ///
+    /// * `scheme` - The hash scheme (the hash function is always SHA-256, with
+    ///   positional pairs)
+    ///
+    /// # Returns
+    ///
+    /// The tree, whose root is the empty subtree root of depth `D`
+    pub fn new(scheme: HashScheme) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::SHAPE;
+        let mut nodes = [[0; 32]; N];
+        let mut empty = hash_leaf(scheme, &EMPTY_LEAF_DATA);
+        for height in 0..=D {
+            nodes[N >> (height + 1)..N >> height].fill(empty);
+            empty = hash_pair(scheme, &empty, &empty);
+        }
+        FixedDepthTree { scheme, nodes }
+    }
+
+    /// Get the number of leaves
+    ///
+    /// # Returns
+    ///
+    /// `2^D`
+    pub const fn capacity() -> usize {
+        1 << D
+    }
+
+    /// Set a leaf's hash and rehash its path to the root
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `hash` - The leaf's hash
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `FixedIndexError` if the index is not below `2^D`
+    pub fn set_leaf(&mut self, index: usize, hash: [u8; 32]) -> Result<(), FixedIndexError> {
+        if index >= Self::capacity() {
+            return Err(FixedIndexError {
+                index,
+                capacity: Self::capacity(),
+            });
+        }
+        let mut node = N / 2 + index;
+        self.nodes[node] = hash;
+        while node > 1 {
+            node /= 2;
+            self.nodes[node] = hash_pair(
+                self.scheme,
+                &self.nodes[2 * node],
+                &self.nodes[2 * node + 1],
+            );
+        }
+        Ok(())
+    }
+
+    /// Hash a leaf's data and set the leaf to it
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The leaf's data
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `FixedIndexError` if the index is not below `2^D`
+    pub fn set_leaf_data(&mut self, index: usize, data: &[u8]) -> Result<(), FixedIndexError> {
+        self.set_leaf(index, hash_leaf(self.scheme, data))
+    }
+
+    /// Get a leaf's hash
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaf's hash (`EMPTY_LEAF` under `HashScheme::Plain` for an unset leaf), or
+    /// None if the index is not below `2^D`
+    pub fn leaf(&self, index: usize) -> Option<&[u8; 32]> {
+        if index >= Self::capacity() {
+            return None;
+        }
+        Some(&self.nodes[N / 2 + index])
+    }
+
+    /// Get the root hash
+    ///
+    /// # Returns
+    ///
+    /// The root hash
+    pub fn root(&self) -> [u8; 32] {
+        self.nodes[1]
+    }
+
+    /// Generate a proof for a leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The sibling hash of each level from the leaves up, with whether it is on the
+    /// left, or None if the index is not below `2^D`
+    pub fn proof(&self, index: usize) -> Option<[([u8; 32], bool); D]> {
+        if index >= Self::capacity() {
+            return None;
+        }
+        let mut proof = [([0; 32], false); D];
+        let mut node = N / 2 + index;
+        for step in proof.iter_mut() {
+            let sibling = node ^ 1;
+            *step = (self.nodes[sibling], sibling < node);
+            node /= 2;
+        }
+        Some(proof)
+    }
+}
+
+/// Verify a proof from a `FixedDepthTree`
+///
+/// Like the tree itself, this never allocates.
+///
+/// # Arguments
+///
+/// * `scheme` - The hash scheme the tree was built with
+/// * `leaf_hash` - The hash of the leaf
+/// * `proof` - The proof, as returned by `FixedDepthTree::proof`
+/// * `root` - The root hash to verify against
+///
+/// # Returns
+///
+/// True if the proof leads from the leaf hash to the root
+pub fn verify_fixed_proof<const D: usize>(
+    scheme: HashScheme,
+    leaf_hash: &[u8; 32],
+    proof: &[([u8; 32], bool); D],
+    root: &[u8; 32],
+) -> bool {
+    let mut node = *leaf_hash;
+    for (sibling, is_left) in proof {
+        node = if *is_left {
+            hash_pair(scheme, sibling, &node)
+        } else {
+            hash_pair(scheme, &node, sibling)
+        };
+    }
+    node == *root
+}
+
+/// A leaf index that is not below a `FixedDepthTree`'s capacity
+///
+/// `MerkleError` needs an allocator, so `FixedDepthTree` reports this instead; with the
+/// `verify` feature it converts into `MerkleError::IndexOutOfRange`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedIndexError {
+    /// The requested index
+    pub index: usize,
+    /// The number of leaves in the tree
+    pub capacity: usize,
+}
+
+impl fmt::Display for FixedIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "leaf index {} is out of range for a tree of {} leaves",
+            self.index, self.capacity
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FixedIndexError {}
+
+#[cfg(feature = "verify")]
+impl From<FixedIndexError> for crate::error::MerkleError {
+    fn from(error: FixedIndexError) -> Self {
+        crate::error::MerkleError::IndexOutOfRange {
+            index: error.index,
+            leaf_count: error.capacity,
+        }
+    }
+}
+
+/// Hash a leaf's data with SHA-256 under a scheme, as `HashConfig::hash_leaf` does
+fn hash_leaf(scheme: HashScheme, data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    if scheme == HashScheme::DomainSeparated {
+        hasher.update([LEAF_PREFIX]);
+    }
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Hash a pair of child hashes with SHA-256 under a scheme, as `HashConfig::hash_pair`
+/// does with positional pairs
+fn hash_pair(scheme: HashScheme, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    if scheme == HashScheme::DomainSeparated {
+        hasher.update([NODE_PREFIX]);
+    }
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
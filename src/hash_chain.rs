@@ -0,0 +1,162 @@
+use crate::commitment_scheme::CommitmentScheme;
+use crate::hashing::{HashConfig, HashScheme, PairOrdering};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A proof that an item is in a hash chain
+///
+/// Verification hashes the item onto the predecessor's link and then hashes on each
+/// successor in turn, so the proof of the item at index `i` of an `n`-item chain holds
+/// `n - i - 1` successor hashes: proofs are O(n − i), and proofs of recent items are the
+/// shortest.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChainProof {
+    /// The link of the previous item, or None for the first item
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    pub predecessor: Option<Vec<u8>>,
+    /// The leaf hashes of the items after the proven one, in order
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::list"))]
+    pub successors: Vec<Vec<u8>>,
+}
+
+/// A hash chain over a strictly ordered sequence of items
+///
+/// A simpler alternative to a `MerkleTree` for small, append-only streams. Each item is
+/// hashed as a leaf, and its link is the hash of the previous link and the leaf hash,
+/// computed as a branch with the left child first whatever the configured pair ordering,
+/// since the order of a chain is part of what it commits to. The first item's link is
+/// its leaf hash, and the root is the last link. Both types implement `CommitmentScheme`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashChain {
+    /// The hash configuration
+    hasher: HashConfig,
+    /// The leaf hash of each item
+    leaf_hashes: Vec<Vec<u8>>,
+    /// The link of each item
+    links: Vec<Vec<u8>>,
+}
+
+impl HashChain {
+    /// Build a chain with plain SHA-256 hashing
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The items, in order
+    ///
+    /// # Returns
+    ///
+    /// A new chain
+    pub fn new<T: AsRef<[u8]>>(data: impl IntoIterator<Item = T>) -> Self {
+        Self::with_hasher(data, HashScheme::Plain)
+    }
+
+    /// Build a chain with a hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The items, in order
+    /// * `hasher` - The hash configuration (or just the scheme)
+    ///
+    /// # Returns
+    ///
+    /// A new chain
+    pub fn with_hasher<T: AsRef<[u8]>>(
+        data: impl IntoIterator<Item = T>,
+        hasher: impl Into<HashConfig>,
+    ) -> Self {
+        let mut chain = HashChain {
+            hasher: HashConfig {
+                ordering: PairOrdering::Positional,
+                ..hasher.into()
+            },
+            leaf_hashes: Vec::new(),
+            links: Vec::new(),
+        };
+        for item in data {
+            chain.push(item.as_ref());
+        }
+        chain
+    }
+
+    /// Append an item
+    ///
+    /// Proofs of earlier items gain the new item as a successor and must be regenerated.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The item
+    ///
+    /// # Returns
+    ///
+    /// The new root
+    pub fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        let leaf_hash = self.hasher.hash_leaf(data);
+        let link = match self.links.last() {
+            Some(previous) => self.hasher.hash_pair(previous, &leaf_hash),
+            None => leaf_hash.clone(),
+        };
+        self.leaf_hashes.push(leaf_hash);
+        self.links.push(link.clone());
+        link
+    }
+
+    /// Get the number of items
+    ///
+    /// # Returns
+    ///
+    /// The number of items in the chain
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Check if the chain is empty
+    ///
+    /// # Returns
+    ///
+    /// True if the chain has no items
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Get the hash configuration
+    ///
+    /// # Returns
+    ///
+    /// The configuration items and links are hashed with
+    pub fn hash_config(&self) -> HashConfig {
+        self.hasher
+    }
+}
+
+impl CommitmentScheme for HashChain {
+    type Proof = ChainProof;
+
+    fn root(&self) -> Option<Vec<u8>> {
+        self.links.last().cloned()
+    }
+
+    fn prove(&self, index: usize) -> Option<ChainProof> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(ChainProof {
+            predecessor: index
+                .checked_sub(1)
+                .map(|previous| self.links[previous].clone()),
+            successors: self.leaf_hashes[index + 1..].to_vec(),
+        })
+    }
+
+    fn verify(&self, data: &[u8], proof: &ChainProof, root: &[u8]) -> bool {
+        let leaf_hash = self.hasher.hash_leaf(data);
+        let mut link = match &proof.predecessor {
+            Some(previous) => self.hasher.hash_pair(previous, &leaf_hash),
+            None => leaf_hash,
+        };
+        for successor in &proof.successors {
+            link = self.hasher.hash_pair(&link, successor);
+        }
+        link == root
+    }
+}
@@ -0,0 +1,169 @@
+//! A map from leaf hash to the lowest index holding it
+//!
+//! `HashIndex` answers "which leaf has this hash" in expected constant time. It is kept
+//! in step by `push` and `update_leaf`, which each change one entry. Its buckets live in
+//! a `SharedVec`, so like the rest of the tree a clone shares them and a write copies
+//! only the path to one bucket rather than the whole map.
+
+use crate::shared_vec::SharedVec;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// The number of buckets an empty map starts with
+const MIN_BUCKETS: usize = 32;
+
+/// The leaves holding one hash
+#[derive(Clone, Debug)]
+struct Entry {
+    /// The leaf hash
+    hash: Vec<u8>,
+    /// The lowest index of a leaf with the hash
+    lowest: usize,
+    /// The number of leaves with the hash
+    count: usize,
+}
+
+/// A map from leaf hash to the lowest index holding it
+#[derive(Clone, Debug)]
+pub(crate) struct HashIndex {
+    /// The seed for choosing a hash's bucket, shared by clones
+    state: RandomState,
+    /// The entries, in the bucket their hash selects; always a power of two long
+    buckets: SharedVec<Vec<Entry>>,
+    /// The number of distinct hashes
+    len: usize,
+}
+
+impl HashIndex {
+    /// Index the leaves of a tree
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The leaf level
+    /// * `leaf_count` - The number of leaves, excluding padding
+    ///
+    /// # Returns
+    ///
+    /// The map from each hash to the lowest index holding it
+    pub(crate) fn new(leaves: &SharedVec<Vec<u8>>, leaf_count: usize) -> Self {
+        let bucket_count = leaf_count.next_power_of_two().max(MIN_BUCKETS);
+        let state = RandomState::new();
+        let mut buckets = vec![Vec::new(); bucket_count];
+        let mut len = 0;
+        for (index, hash) in leaves.iter().take(leaf_count).enumerate() {
+            let bucket: &mut Vec<Entry> = &mut buckets[bucket_of(&state, bucket_count, hash)];
+            match bucket.iter_mut().find(|entry| entry.hash == *hash) {
+                Some(entry) => entry.count += 1,
+                None => {
+                    bucket.push(Entry {
+                        hash: hash.clone(),
+                        lowest: index,
+                        count: 1,
+                    });
+                    len += 1;
+                }
+            }
+        }
+        HashIndex {
+            state,
+            buckets: buckets.into(),
+            len,
+        }
+    }
+
+    /// Find the lowest index holding a hash
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The leaf hash
+    ///
+    /// # Returns
+    ///
+    /// The lowest index, or None if no leaf has the hash
+    pub(crate) fn get(&self, hash: &[u8]) -> Option<usize> {
+        self.buckets[self.bucket(hash)]
+            .iter()
+            .find(|entry| entry.hash == hash)
+            .map(|entry| entry.lowest)
+    }
+
+    /// Record a leaf with a hash
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The leaf hash
+    /// * `index` - The index of the leaf
+    pub(crate) fn insert(&mut self, hash: &[u8], index: usize) {
+        let bucket = self.bucket(hash);
+        let entries = &mut self.buckets[bucket];
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.hash == hash) {
+            entry.count += 1;
+            entry.lowest = entry.lowest.min(index);
+            return;
+        }
+        entries.push(Entry {
+            hash: hash.to_vec(),
+            lowest: index,
+            count: 1,
+        });
+        self.len += 1;
+        if self.len > self.buckets.len() {
+            self.grow();
+        }
+    }
+
+    /// Forget a leaf with a hash
+    ///
+    /// If the leaf was the lowest holding the hash and others remain, the next is found
+    /// by scanning the leaves after it. Padding follows every leaf, so the scan finds a
+    /// leaf before any padding.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The leaf hash
+    /// * `index` - The index of the leaf
+    /// * `leaves` - The leaf level, with the leaf not yet replaced
+    pub(crate) fn remove(&mut self, hash: &[u8], index: usize, leaves: &SharedVec<Vec<u8>>) {
+        let bucket = self.bucket(hash);
+        let entries = &mut self.buckets[bucket];
+        let Some(position) = entries.iter().position(|entry| entry.hash == hash) else {
+            return;
+        };
+        let entry = &mut entries[position];
+        entry.count -= 1;
+        if entry.count == 0 {
+            entries.swap_remove(position);
+            self.len -= 1;
+        } else if entry.lowest == index {
+            entry.lowest = (index + 1..leaves.len())
+                .find(|&next| leaves[next] == hash)
+                .unwrap_or_else(|| unreachable!("a remaining leaf holds the hash"));
+        }
+    }
+
+    /// Get the bucket a hash belongs in
+    fn bucket(&self, hash: &[u8]) -> usize {
+        bucket_of(&self.state, self.buckets.len(), hash)
+    }
+
+    /// Double the number of buckets, moving every entry to its new bucket
+    fn grow(&mut self) {
+        let bucket_count = self.buckets.len() * 2;
+        let mut buckets = vec![Vec::new(); bucket_count];
+        for entry in self.buckets.iter().flatten() {
+            buckets[bucket_of(&self.state, bucket_count, &entry.hash)].push(entry.clone());
+        }
+        self.buckets = buckets.into();
+    }
+}
+
+/// Get the bucket a hash belongs in
+///
+/// # Arguments
+///
+/// * `state` - The seed for choosing buckets
+/// * `bucket_count` - The number of buckets, a power of two
+/// * `hash` - The leaf hash
+fn bucket_of(state: &RandomState, bucket_count: usize, hash: &[u8]) -> usize {
+    state.hash_one(hash) as usize & (bucket_count - 1)
+}
@@ -0,0 +1,717 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::digest::generic_array::GenericArray;
+use sha2::{Digest, Sha256, Sha512};
+#[cfg(feature = "build")]
+use std::cell::RefCell;
+#[cfg(feature = "build")]
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+pub use crate::scheme::{HashScheme, LEAF_PREFIX, NODE_PREFIX};
+
+/// The hash function used to compute leaf and branch hashes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum HashAlgorithm {
+    /// SHA-256 with 32-byte digests
+    Sha256,
+    /// SHA-512 with 64-byte digests
+    Sha512,
+    /// Keccak-256 (as used by Ethereum, not NIST SHA3-256) with 32-byte digests
+    #[cfg(feature = "keccak")]
+    Keccak256,
+    /// RIPEMD-160 with 20-byte digests, for legacy systems
+    #[cfg(feature = "ripemd")]
+    Ripemd160,
+}
+
+/// How two child hashes are ordered before being hashed together
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PairOrdering {
+    /// The left child's hash comes first (the `MerkleTree::new` behavior)
+    Positional,
+    /// The smaller hash comes first, so proofs do not depend on sibling directions
+    Sorted,
+}
+
+/// The byte order child hashes are concatenated in
+///
+/// Some systems store digests in one byte order and hash branches over the other, as
+/// Bitcoin does with its "internal byte order". Under `Reversed` each child hash is
+/// reversed before the two are concatenated and hashed; the resulting parent hash is
+/// stored as the hash function returns it. Leaf hashes are not affected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ByteOrder {
+    /// Child hashes are concatenated as stored (the `MerkleTree::new` behavior)
+    #[default]
+    Natural,
+    /// Each child hash is byte-reversed before concatenation
+    Reversed,
+}
+
+impl ByteOrder {
+    /// Whether this is the natural byte order
+    ///
+    /// # Returns
+    ///
+    /// True for `ByteOrder::Natural`
+    pub fn is_natural(&self) -> bool {
+        *self == ByteOrder::Natural
+    }
+}
+
+/// The root hash reported by a tree with no leaves
+///
+/// Some protocols need "empty" to be a commitment like any other, so the empty tree is
+/// given a fixed root. No leaf hashes to such a root (short of a preimage of it), so no
+/// proof verifies against it.
+///
+/// The one exception is `HashOfEmpty` under `HashScheme::Plain`, where `H("")` is also
+/// the leaf hash, and so the root, of a single empty leaf. Trees refuse that pairing
+/// with `MerkleError::AmbiguousEmptyRoot`, and it gives no empty root.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum EmptyRootPolicy {
+    /// An empty tree has no root (the `MerkleTree::new` behavior)
+    #[default]
+    None,
+    /// The root of an empty tree is the digest of the empty string, `H("")`
+    HashOfEmpty,
+    /// The root of an empty tree is 32 zero bytes
+    Zero32,
+}
+
+impl EmptyRootPolicy {
+    /// The root hash of an empty tree under this policy
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration the tree uses
+    ///
+    /// # Returns
+    ///
+    /// The empty root, or None under `EmptyRootPolicy::None` or when the policy is
+    /// ambiguous under the configuration's scheme
+    pub fn root(&self, hasher: &HashConfig) -> Option<Vec<u8>> {
+        match self {
+            _ if self.is_ambiguous(hasher.scheme) => None,
+            EmptyRootPolicy::None => None,
+            EmptyRootPolicy::HashOfEmpty => Some(hasher.algorithm.digest(&[])),
+            EmptyRootPolicy::Zero32 => Some(vec![0; 32]),
+        }
+    }
+
+    /// Whether the empty root is also the root of a tree with leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `scheme` - The hashing scheme the tree uses
+    ///
+    /// # Returns
+    ///
+    /// True for `HashOfEmpty` under `HashScheme::Plain`, where it is the root of a single
+    /// empty leaf
+    pub fn is_ambiguous(&self, scheme: HashScheme) -> bool {
+        *self == EmptyRootPolicy::HashOfEmpty && scheme == HashScheme::Plain
+    }
+}
+
+/// The complete configuration for hashing leaves and branches
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct HashConfig {
+    /// The hash function
+    pub algorithm: HashAlgorithm,
+    /// Whether leaves and branches are domain-separated
+    pub scheme: HashScheme,
+    /// How child hashes are ordered before hashing
+    pub ordering: PairOrdering,
+    /// The byte order child hashes are concatenated in
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "ByteOrder::is_natural")
+    )]
+    pub byte_order: ByteOrder,
+}
+
+impl HashScheme {
+    /// The length in bytes of the digests produced under this scheme
+    ///
+    /// # Returns
+    ///
+    /// The digest length (32 for SHA-256)
+    pub fn digest_len(&self) -> usize {
+        HashConfig::from(*self).digest_len()
+    }
+
+    /// Hash a leaf's data under this scheme with SHA-256
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf data
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash
+    pub fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        HashConfig::from(*self).hash_leaf(data)
+    }
+
+    /// Hash a pair of child hashes under this scheme with SHA-256
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The hash of the left child
+    /// * `right` - The hash of the right child
+    ///
+    /// # Returns
+    ///
+    /// The hash of the parent node
+    pub fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        HashConfig::from(*self).hash_pair(left, right)
+    }
+}
+
+impl HashAlgorithm {
+    /// The length in bytes of the digests produced by this algorithm
+    ///
+    /// # Returns
+    ///
+    /// The digest length
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha512 => 64,
+            #[cfg(feature = "keccak")]
+            HashAlgorithm::Keccak256 => 32,
+            #[cfg(feature = "ripemd")]
+            HashAlgorithm::Ripemd160 => 20,
+        }
+    }
+
+    /// Hash a sequence of byte slices as if they were concatenated
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The byte slices to hash
+    ///
+    /// # Returns
+    ///
+    /// The digest of the concatenated parts
+    pub fn digest(&self, parts: &[&[u8]]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => digest_parts::<Sha256>(parts).to_vec(),
+            HashAlgorithm::Sha512 => digest_parts::<Sha512>(parts).to_vec(),
+            #[cfg(feature = "keccak")]
+            HashAlgorithm::Keccak256 => digest_parts::<sha3::Keccak256>(parts).to_vec(),
+            #[cfg(feature = "ripemd")]
+            HashAlgorithm::Ripemd160 => digest_parts::<ripemd::Ripemd160>(parts).to_vec(),
+        }
+    }
+
+    /// Hash a sequence of byte slices into a caller-provided buffer without allocating
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The byte slices to hash
+    /// * `out` - The buffer to write the digest to, which must be exactly `digest_len`
+    ///   bytes long
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is not `digest_len` bytes long
+    pub fn digest_into(&self, parts: &[&[u8]], out: &mut [u8]) {
+        match self {
+            HashAlgorithm::Sha256 => digest_parts_into::<Sha256>(parts, out),
+            HashAlgorithm::Sha512 => digest_parts_into::<Sha512>(parts, out),
+            #[cfg(feature = "keccak")]
+            HashAlgorithm::Keccak256 => digest_parts_into::<sha3::Keccak256>(parts, out),
+            #[cfg(feature = "ripemd")]
+            HashAlgorithm::Ripemd160 => digest_parts_into::<ripemd::Ripemd160>(parts, out),
+        }
+    }
+
+    /// Hash a prefix followed by everything a reader yields, a buffer at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The bytes to hash before the reader's
+    /// * `reader` - The reader, read to its end
+    ///
+    /// # Returns
+    ///
+    /// The digest and the number of bytes read, or the error that interrupted reading
+    pub(crate) fn digest_reader(
+        &self,
+        prefix: &[u8],
+        reader: impl Read,
+    ) -> io::Result<(Vec<u8>, u64)> {
+        match self {
+            HashAlgorithm::Sha256 => digest_reader::<Sha256>(prefix, reader),
+            HashAlgorithm::Sha512 => digest_reader::<Sha512>(prefix, reader),
+            #[cfg(feature = "keccak")]
+            HashAlgorithm::Keccak256 => digest_reader::<sha3::Keccak256>(prefix, reader),
+            #[cfg(feature = "ripemd")]
+            HashAlgorithm::Ripemd160 => digest_reader::<ripemd::Ripemd160>(prefix, reader),
+        }
+    }
+}
+
+/// Hash a sequence of byte slices with a specific hash function
+///
+/// # Arguments
+///
+/// * `parts` - The byte slices to hash
+///
+/// # Returns
+///
+/// The digest of the concatenated parts
+fn digest_parts<D: Digest>(parts: &[&[u8]]) -> sha2::digest::Output<D> {
+    let mut hasher = D::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+/// Hash a sequence of byte slices with a specific hash function into a buffer
+///
+/// # Arguments
+///
+/// * `parts` - The byte slices to hash
+/// * `out` - The buffer to write the digest to
+fn digest_parts_into<D: Digest>(parts: &[&[u8]], out: &mut [u8]) {
+    let mut hasher = D::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize_into(GenericArray::from_mut_slice(out));
+}
+
+/// Hash a prefix followed by everything a reader yields with a specific hash function
+///
+/// # Arguments
+///
+/// * `prefix` - The bytes to hash before the reader's
+/// * `reader` - The reader, read to its end
+///
+/// # Returns
+///
+/// The digest and the number of bytes read, or the error that interrupted reading
+fn digest_reader<D: Digest>(prefix: &[u8], mut reader: impl Read) -> io::Result<(Vec<u8>, u64)> {
+    let mut hasher = D::new();
+    hasher.update(prefix);
+    let mut buffer = [0; 8192];
+    let mut total = 0;
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => {
+                hasher.update(&buffer[..read]);
+                total += read as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((hasher.finalize().to_vec(), total))
+}
+
+impl HashConfig {
+    /// Create a hash configuration with the natural byte order
+    ///
+    /// # Arguments
+    ///
+    /// * `algorithm` - The hash function
+    /// * `scheme` - Whether leaves and branches are domain-separated
+    /// * `ordering` - How child hashes are ordered before hashing
+    ///
+    /// # Returns
+    ///
+    /// The hash configuration
+    pub const fn new(algorithm: HashAlgorithm, scheme: HashScheme, ordering: PairOrdering) -> Self {
+        HashConfig {
+            algorithm,
+            scheme,
+            ordering,
+            byte_order: ByteOrder::Natural,
+        }
+    }
+
+    /// Set the byte order child hashes are concatenated in
+    ///
+    /// # Arguments
+    ///
+    /// * `byte_order` - The byte order
+    ///
+    /// # Returns
+    ///
+    /// The updated hash configuration
+    pub const fn with_byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// The length in bytes of the digests produced under this configuration
+    ///
+    /// # Returns
+    ///
+    /// The digest length
+    pub fn digest_len(&self) -> usize {
+        self.algorithm.digest_len()
+    }
+
+    /// Hash a leaf's data
+    ///
+    /// Empty data is hashed like any other: `H()` under `HashScheme::Plain` and
+    /// `H(0x00)` under `HashScheme::DomainSeparated`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf data
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash
+    pub fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        match self.scheme {
+            HashScheme::Plain => self.algorithm.digest(&[data]),
+            HashScheme::DomainSeparated => self.algorithm.digest(&[&[LEAF_PREFIX], data]),
+        }
+    }
+
+    /// Hash a leaf's data as a reader yields it
+    ///
+    /// The data is hashed a buffer at a time, so memory use does not grow with the
+    /// leaf's size. The hash is the one `hash_leaf` gives for the same bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The leaf data, read to its end
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash, or the error that interrupted reading
+    pub fn hash_leaf_reader(&self, reader: impl Read) -> io::Result<Vec<u8>> {
+        Ok(self.hash_leaf_reader_counted(reader)?.0)
+    }
+
+    /// Hash a leaf's data as a reader yields it, counting its bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The leaf data, read to its end
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash and the length of the data, or the error that interrupted reading
+    pub(crate) fn hash_leaf_reader_counted(&self, reader: impl Read) -> io::Result<(Vec<u8>, u64)> {
+        match self.scheme {
+            HashScheme::Plain => self.algorithm.digest_reader(&[], reader),
+            HashScheme::DomainSeparated => self.algorithm.digest_reader(&[LEAF_PREFIX], reader),
+        }
+    }
+
+    /// Hash a leaf's data into a caller-provided buffer without allocating
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf data
+    /// * `out` - The buffer to write the hash to, exactly `digest_len` bytes long
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is not `digest_len` bytes long
+    pub fn hash_leaf_into(&self, data: &[u8], out: &mut [u8]) {
+        match self.scheme {
+            HashScheme::Plain => self.algorithm.digest_into(&[data], out),
+            HashScheme::DomainSeparated => self.algorithm.digest_into(&[&[LEAF_PREFIX], data], out),
+        }
+    }
+
+    /// Hash a leaf's data preceded by a prefix into a caller-provided buffer, without
+    /// allocating or copying the data
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The bytes hashed before the data, such as a positional leaf's index
+    /// * `data` - The leaf data
+    /// * `out` - The buffer to write the hash to, exactly `digest_len` bytes long
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is not `digest_len` bytes long
+    #[cfg(feature = "build")]
+    pub(crate) fn hash_prefixed_leaf_into(&self, prefix: &[u8], data: &[u8], out: &mut [u8]) {
+        match self.scheme {
+            HashScheme::Plain => self.algorithm.digest_into(&[prefix, data], out),
+            HashScheme::DomainSeparated => self
+                .algorithm
+                .digest_into(&[&[LEAF_PREFIX], prefix, data], out),
+        }
+    }
+
+    /// Hash a pair of child hashes into a caller-provided buffer without allocating
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The hash of the left child
+    /// * `right` - The hash of the right child
+    /// * `out` - The buffer to write the hash to, exactly `digest_len` bytes long
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is not `digest_len` bytes long, or under `ByteOrder::Reversed` if
+    /// a child hash is longer than 64 bytes
+    pub fn hash_pair_into(&self, left: &[u8], right: &[u8], out: &mut [u8]) {
+        let (left, right) = match self.ordering {
+            PairOrdering::Sorted if right < left => (right, left),
+            _ => (left, right),
+        };
+        let (mut left_buffer, mut right_buffer) = ([0; MAX_REVERSED_LEN], [0; MAX_REVERSED_LEN]);
+        let (left, right) = match self.byte_order {
+            ByteOrder::Natural => (left, right),
+            ByteOrder::Reversed => (
+                reversed(left, &mut left_buffer),
+                reversed(right, &mut right_buffer),
+            ),
+        };
+        match self.scheme {
+            HashScheme::Plain => self.algorithm.digest_into(&[left, right], out),
+            HashScheme::DomainSeparated => self
+                .algorithm
+                .digest_into(&[&[NODE_PREFIX], left, right], out),
+        }
+    }
+
+    /// Hash a pair of child hashes
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The hash of the left child
+    /// * `right` - The hash of the right child
+    ///
+    /// # Returns
+    ///
+    /// The hash of the parent node
+    pub fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        let (left, right) = match self.ordering {
+            PairOrdering::Sorted if right < left => (right, left),
+            _ => (left, right),
+        };
+        if self.byte_order == ByteOrder::Reversed {
+            let reverse = |hash: &[u8]| hash.iter().rev().copied().collect::<Vec<u8>>();
+            let (left, right) = (reverse(left), reverse(right));
+            return HashConfig::new(self.algorithm, self.scheme, PairOrdering::Positional)
+                .hash_pair(&left, &right);
+        }
+        match self.scheme {
+            HashScheme::Plain => self.algorithm.digest(&[left, right]),
+            HashScheme::DomainSeparated => self.algorithm.digest(&[&[NODE_PREFIX], left, right]),
+        }
+    }
+}
+
+/// The longest hash `hash_pair_into` can reverse without allocating (a SHA-512 digest)
+const MAX_REVERSED_LEN: usize = 64;
+
+/// Copy a hash into a buffer in reverse byte order
+///
+/// # Arguments
+///
+/// * `hash` - The hash to reverse
+/// * `buffer` - The buffer to reverse it into
+///
+/// # Returns
+///
+/// The reversed hash, borrowed from the buffer
+///
+/// # Panics
+///
+/// Panics if the hash is longer than the buffer
+fn reversed<'a>(hash: &[u8], buffer: &'a mut [u8; MAX_REVERSED_LEN]) -> &'a [u8] {
+    let reversed = &mut buffer[..hash.len()];
+    reversed.copy_from_slice(hash);
+    reversed.reverse();
+    reversed
+}
+
+/// Something that can hash leaves and pairs of child hashes
+///
+/// Implemented by `HashConfig`, by the counting wrapper trees use to record metrics, by
+/// the memoizing wrapper used for repetitive data and by the parallel wrapper.
+pub(crate) trait NodeHasher {
+    /// Hash a leaf's data
+    #[cfg_attr(not(feature = "build"), allow(dead_code))]
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Hash a pair of child hashes
+    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8>;
+
+    /// Hash each pair of nodes on a level into the level above
+    ///
+    /// An unpaired last node is carried up unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The hashes of the level's nodes
+    ///
+    /// # Returns
+    ///
+    /// The hashes of the nodes on the level above, in order
+    #[cfg(feature = "build")]
+    fn hash_level(&self, nodes: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        nodes
+            .chunks(2)
+            .map(|chunk| hash_chunk(self, chunk))
+            .collect()
+    }
+}
+
+/// Hash a pair of nodes, or carry an unpaired node up to the next level
+#[cfg(feature = "build")]
+fn hash_chunk(hasher: &(impl NodeHasher + ?Sized), chunk: &[Vec<u8>]) -> Vec<u8> {
+    match chunk {
+        [left, right] => hasher.hash_pair(left, right),
+        _ => chunk[0].clone(),
+    }
+}
+
+impl NodeHasher for HashConfig {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        HashConfig::hash_leaf(self, data)
+    }
+
+    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        HashConfig::hash_pair(self, left, right)
+    }
+}
+
+/// A hasher that remembers the hash of every pair of children it has hashed
+///
+/// Runs of identical leaves produce the same pairs over and over; each distinct pair is
+/// only hashed once.
+#[cfg(feature = "build")]
+pub(crate) struct MemoizingHasher<'a, H> {
+    /// The hasher to hash with
+    inner: &'a H,
+    /// The parent hash of each pair seen, keyed by the concatenated child hashes
+    pairs: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+#[cfg(feature = "build")]
+impl<'a, H: NodeHasher> MemoizingHasher<'a, H> {
+    /// Wrap a hasher with an empty memo
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The hasher to hash with
+    ///
+    /// # Returns
+    ///
+    /// The memoizing hasher
+    pub(crate) fn new(inner: &'a H) -> Self {
+        MemoizingHasher {
+            inner,
+            pairs: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+#[cfg(feature = "build")]
+impl<H: NodeHasher> NodeHasher for MemoizingHasher<'_, H> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.inner.hash_leaf(data)
+    }
+
+    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        // Child hashes are digests of one fixed length, so the concatenation is unambiguous
+        let key = [left, right].concat();
+        self.pairs
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| self.inner.hash_pair(left, right))
+            .clone()
+    }
+}
+
+/// A hasher that hashes each level on rayon's thread pool
+///
+/// Every parent is written to the slot of its pair, so the result never depends on how
+/// the pool schedules the work or on how many threads it has.
+#[cfg(feature = "parallel")]
+pub(crate) struct ParallelHasher<'a, H> {
+    /// The hasher to hash with
+    inner: &'a H,
+}
+
+#[cfg(feature = "parallel")]
+impl<'a, H: NodeHasher + Sync> ParallelHasher<'a, H> {
+    /// Wrap a hasher
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The hasher to hash with
+    ///
+    /// # Returns
+    ///
+    /// The parallel hasher
+    pub(crate) fn new(inner: &'a H) -> Self {
+        ParallelHasher { inner }
+    }
+
+    /// Hash each leaf on the thread pool
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The leaves to hash
+    /// * `hash` - Hashes the leaf at an index with the wrapped hasher
+    /// * `hashes` - The vector to collect the hashes into, in leaf order
+    pub(crate) fn hash_leaves<D: Sync>(
+        &self,
+        leaves: &[D],
+        hash: impl Fn(&H, usize, &D) -> Vec<u8> + Sync + Send,
+        hashes: &mut Vec<Vec<u8>>,
+    ) {
+        leaves
+            .par_iter()
+            .enumerate()
+            .map(|(index, leaf)| hash(self.inner, index, leaf))
+            .collect_into_vec(hashes);
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<H: NodeHasher + Sync> NodeHasher for ParallelHasher<'_, H> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.inner.hash_leaf(data)
+    }
+
+    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.inner.hash_pair(left, right)
+    }
+
+    fn hash_level(&self, nodes: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        nodes
+            .par_chunks(2)
+            .map(|chunk| hash_chunk(self.inner, chunk))
+            .collect()
+    }
+}
+
+/// SHA-256 hashing with positional pairs under the given scheme
+impl From<HashScheme> for HashConfig {
+    fn from(scheme: HashScheme) -> Self {
+        HashConfig::new(HashAlgorithm::Sha256, scheme, PairOrdering::Positional)
+    }
+}
@@ -0,0 +1,253 @@
+//! Typed indices for leaves and nodes
+//!
+//! Plain `usize`s make it easy to pass a node index where a leaf index was meant, so the
+//! by-index methods of `MerkleTree` take `LeafIndex` and `NodeCoord` instead. Both are
+//! checked against a tree when they are made (`MerkleTree::leaf_index`,
+//! `MerkleTree::node_coord`). The `usize` methods they replace remain as deprecated
+//! wrappers.
+
+use crate::error::MerkleError;
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+use std::fmt;
+use std::num::TryFromIntError;
+
+/// The index of a leaf, excluding padding
+///
+/// Made by `MerkleTree::leaf_index`, which checks it against a tree, or from a `usize`
+/// with `TryFrom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeafIndex(u64);
+
+/// The coordinate of a node: its level (0 for leaves) and its index within the level
+///
+/// Made by `MerkleTree::node_coord`, which checks it against a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeCoord {
+    /// The level of the node, counted from the leaves
+    level: u32,
+    /// The index of the node within its level
+    index: u64,
+}
+
+impl LeafIndex {
+    /// Get the index
+    ///
+    /// # Returns
+    ///
+    /// The index of the leaf
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Get the index as a `usize`, if it fits
+    fn to_usize(self) -> Option<usize> {
+        usize::try_from(self.0).ok()
+    }
+}
+
+impl NodeCoord {
+    /// Create a coordinate without checking it against a tree
+    pub(crate) fn new(level: u32, index: u64) -> Self {
+        NodeCoord { level, index }
+    }
+
+    /// Get the level of the node
+    ///
+    /// # Returns
+    ///
+    /// The level, counted from the leaves
+    pub fn level(self) -> u32 {
+        self.level
+    }
+
+    /// Get the index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The index of the node
+    pub fn index(self) -> u64 {
+        self.index
+    }
+
+    /// Get the level and index as `usize`s, if they fit
+    fn to_usizes(self) -> Option<(usize, usize)> {
+        Some((
+            usize::try_from(self.level).ok()?,
+            usize::try_from(self.index).ok()?,
+        ))
+    }
+}
+
+impl TryFrom<usize> for LeafIndex {
+    type Error = TryFromIntError;
+
+    fn try_from(index: usize) -> Result<Self, Self::Error> {
+        Ok(LeafIndex(u64::try_from(index)?))
+    }
+}
+
+impl TryFrom<LeafIndex> for usize {
+    type Error = TryFromIntError;
+
+    fn try_from(index: LeafIndex) -> Result<Self, Self::Error> {
+        usize::try_from(index.0)
+    }
+}
+
+impl fmt::Display for LeafIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "leaf {}", self.0)
+    }
+}
+
+impl fmt::Display for NodeCoord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node {} on level {}", self.index, self.level)
+    }
+}
+
+impl MerkleTree {
+    /// Check a leaf index against the tree
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The typed index, or `MerkleError::IndexOutOfRange` if the tree has no such leaf
+    pub fn leaf_index(&self, index: usize) -> Result<LeafIndex, MerkleError> {
+        if index >= self.leaf_count() {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+        // A leaf index always fits a u64 on supported targets
+        LeafIndex::try_from(index).map_err(|_| MerkleError::IndexOutOfRange {
+            index,
+            leaf_count: self.leaf_count(),
+        })
+    }
+
+    /// Check a node coordinate against the tree
+    ///
+    /// As with `node_hash_at`, only real leaves are addressable on the leaf level.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The typed coordinate, or `MerkleError::NodeOutOfRange` if the tree has no such node
+    pub fn node_coord(&self, level: usize, index: usize) -> Result<NodeCoord, MerkleError> {
+        let out_of_range = MerkleError::NodeOutOfRange { level, index };
+        if self.node_hash_by_index(level, index).is_none() {
+            return Err(out_of_range);
+        }
+        Ok(NodeCoord {
+            level: u32::try_from(level).map_err(|_| out_of_range.clone())?,
+            index: u64::try_from(index).map_err(|_| out_of_range)?,
+        })
+    }
+
+    /// Generate a proof for a leaf
+    ///
+    /// The proof has one step for every level at which the leaf's ancestor has a sibling.
+    /// The duplicate added to pad the leaf level is not a real leaf and cannot be proven.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// A proof for the leaf, or None if the tree has no such leaf
+    pub fn generate_proof_at(&self, leaf: LeafIndex) -> Option<MerkleProof> {
+        self.proof_by_index(leaf.to_usize()?)
+    }
+
+    /// Get the hash of a node
+    ///
+    /// On the leaf level only real leaves are addressable, not the duplicate added for
+    /// padding.
+    ///
+    /// # Arguments
+    ///
+    /// * `coord` - The coordinate of the node
+    ///
+    /// # Returns
+    ///
+    /// The node's hash, or None if the tree has no such node
+    pub fn node_hash_at(&self, coord: NodeCoord) -> Option<Vec<u8>> {
+        let (level, index) = coord.to_usizes()?;
+        self.node_hash_by_index(level, index)
+    }
+
+    /// Replace the data of a leaf
+    ///
+    /// Only the nodes on the leaf's path to the root are rehashed. With the `zeroize`
+    /// feature enabled the old data is wiped before being freed. Observers are notified
+    /// once the leaf is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The index of the leaf
+    /// * `data` - The new data of the leaf
+    ///
+    /// # Returns
+    ///
+    /// Ok if the leaf was updated, or `MerkleError::IndexOutOfRange` if the tree has no
+    /// such leaf
+    pub fn update_leaf(&mut self, leaf: LeafIndex, data: Vec<u8>) -> Result<(), MerkleError> {
+        // An index too large for a usize is out of range of any tree
+        self.update_by_index(leaf.to_usize().unwrap_or(usize::MAX), data)
+    }
+
+    /// Generate a proof for the leaf at a specific index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf, as supplied at construction
+    ///
+    /// # Returns
+    ///
+    /// A proof for the leaf, or None if the index is out of range
+    #[deprecated(note = "use `generate_proof_at` with an index from `leaf_index`")]
+    pub fn generate_proof_by_index(&self, index: usize) -> Option<MerkleProof> {
+        self.proof_by_index(index)
+    }
+
+    /// Get the hash of the node at a specific coordinate
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The node's hash, or None if there is no such node
+    #[deprecated(note = "use `node_hash_at` with a coordinate from `node_coord`")]
+    pub fn node_hash(&self, level: usize, index: usize) -> Option<Vec<u8>> {
+        self.node_hash_by_index(level, index)
+    }
+
+    /// Replace the data of an existing leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The new data of the leaf
+    ///
+    /// # Returns
+    ///
+    /// Ok if the leaf was updated, or an error if the index is out of range
+    #[deprecated(note = "use `update_leaf` with an index from `leaf_index`")]
+    pub fn update(&mut self, index: usize, data: Vec<u8>) -> Result<(), MerkleError> {
+        self.update_by_index(index, data)
+    }
+}
@@ -0,0 +1,370 @@
+//! Export of a tree's structure as nested JSON objects, for visualization tools
+//!
+//! Every node is an object with a `"hash"` member holding its hexadecimal hash:
+//!
+//! - A branch has `"left"` and `"right"` members holding its children. A node carried up
+//!   unpaired from an odd-sized level appears once, at its original level.
+//! - A leaf has an `"index"` member, and a `"data_preview"` member with up to
+//!   `PREVIEW_BYTES` bytes of its data (lossily decoded as UTF-8) unless previews are
+//!   disabled or the data was cleared. The duplicate padding an odd leaf level also has
+//!   `"padding": true`. A leaf with metadata has a `"metadata"` member holding it in
+//!   hexadecimal when metadata export is enabled.
+//! - An empty subtree padding a complete tree has `"padding": true` and no children or
+//!   data preview (and an `"index"` at the leaf level).
+//! - A branch below the depth limit has `"truncated": true` instead of children.
+//! - A node whose subtree is labeled (see `MerkleTree::label_subtree`) has a `"label"`
+//!   member holding the name, unless label export is disabled.
+//!
+//! An empty tree exports as `null`. For example, the three-leaf tree
+//!
+//! ```json
+//! {"hash": "…", "left":  {"hash": "…", "left":  {"hash": "…", "index": 0, "data_preview": "a"},
+//!                                      "right": {"hash": "…", "index": 1, "data_preview": "b"}},
+//!               "right": {"hash": "…", "left":  {"hash": "…", "index": 2, "data_preview": "c"},
+//!                                      "right": {"hash": "…", "index": 3, "padding": true,
+//!                                                "data_preview": "c"}}}
+//! ```
+
+use crate::error::MerkleError;
+use crate::merkle_tree::MerkleTree;
+use crate::params::TreeParams;
+use serde_json::{Map, Value};
+
+/// The largest number of bytes of leaf data included in a preview
+pub const PREVIEW_BYTES: usize = 32;
+
+/// Options controlling what `MerkleTree::to_json_structure_with` includes
+///
+/// Further options may be added, so start from `JsonExportOptions::default()` and set
+/// the fields to change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct JsonExportOptions {
+    /// Whether leaves include a preview of their data
+    pub data_previews: bool,
+    /// The number of levels below the root to expand (None for all)
+    pub max_depth: Option<usize>,
+    /// Whether leaves include their unauthenticated metadata
+    pub metadata: bool,
+    /// Whether nodes include their subtree labels
+    pub labels: bool,
+}
+
+impl Default for JsonExportOptions {
+    /// Everything expanded, with data previews and labels and without metadata
+    fn default() -> Self {
+        JsonExportOptions {
+            data_previews: true,
+            max_depth: None,
+            metadata: false,
+            labels: true,
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Export the whole tree as nested JSON objects (see the `json` module for the schema)
+    ///
+    /// # Returns
+    ///
+    /// The root node's object, or `null` for an empty tree
+    pub fn to_json_structure(&self) -> Value {
+        self.to_json_structure_with(&JsonExportOptions::default())
+    }
+
+    /// Export the tree as nested JSON objects with the given options
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - What to include
+    ///
+    /// # Returns
+    ///
+    /// The root node's object, or `null` for an empty tree
+    pub fn to_json_structure_with(&self, opts: &JsonExportOptions) -> Value {
+        if self.is_empty() {
+            return Value::Null;
+        }
+        self.json_node(self.height() - 1, 0, 0, opts)
+    }
+
+    /// Rebuild a tree from an exported structure
+    ///
+    /// Only the hashes, leaf indices, metadata and labels are read, so structures exported
+    /// without data previews round-trip. The leaf data is not known, so `get_leaf`
+    /// returns None for every leaf.
+    ///
+    /// The tree is built from the leaf hashes and every other hash in the structure is
+    /// compared with the built one, so a structure that loads has passed the checks of
+    /// `audit` and needs no separate audit; the leaf hashes themselves cannot be checked
+    /// without the data.
+    ///
+    /// # Arguments
+    ///
+    /// * `structure` - The exported structure, expanded to full depth
+    /// * `params` - The parameters (or hash configuration) the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The tree, or `MerkleError::InvalidStructure` if the structure is malformed,
+    /// truncated, or its hashes do not match a tree built from its leaves
+    pub fn from_json_structure(
+        structure: &Value,
+        params: impl Into<TreeParams>,
+    ) -> Result<Self, MerkleError> {
+        let params = params.into();
+        let mut leaves = Vec::new();
+        if !structure.is_null() {
+            collect_leaves(structure, &mut leaves)?;
+        }
+        for (position, (index, hash, _)) in leaves.iter().enumerate() {
+            if *index != position {
+                return Err(invalid(format!(
+                    "leaf {} appears at position {}",
+                    index, position
+                )));
+            }
+            if hash.len() != params.hasher.digest_len() {
+                return Err(invalid(format!(
+                    "leaf {} has a {}-byte hash, expected {} bytes",
+                    index,
+                    hash.len(),
+                    params.hasher.digest_len()
+                )));
+            }
+        }
+
+        let mut metadata = Vec::new();
+        let leaf_hashes = leaves
+            .into_iter()
+            .map(|(index, hash, leaf_metadata)| {
+                metadata.extend(leaf_metadata.map(|leaf_metadata| (index, leaf_metadata)));
+                hash
+            })
+            .collect();
+        let mut tree = MerkleTree::from_leaf_hashes(leaf_hashes, params);
+        for (index, leaf_metadata) in metadata {
+            tree.attach_metadata(index, leaf_metadata)?;
+        }
+        if !tree.is_empty() {
+            tree.read_labels(structure, tree.height() - 1, 0)?;
+        }
+        let opts = JsonExportOptions {
+            data_previews: false,
+            max_depth: None,
+            metadata: true,
+            labels: true,
+        };
+        if tree.to_json_structure_with(&opts) != strip_previews(structure) {
+            return Err(invalid(String::from(
+                "hashes do not match a tree built from the leaves",
+            )));
+        }
+        Ok(tree)
+    }
+
+    /// Export a node and, depth permitting, its descendants
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    /// * `depth` - The number of levels exported above the node
+    /// * `opts` - What to include
+    ///
+    /// # Returns
+    ///
+    /// The node's object
+    fn json_node(
+        &self,
+        level: usize,
+        index: usize,
+        depth: usize,
+        opts: &JsonExportOptions,
+    ) -> Value {
+        let empty = self.empty_subtree_at(level, index).is_some();
+        if level > 0 && empty {
+            let mut node = Map::new();
+            node.insert(
+                String::from("hash"),
+                Value::from(hex::encode(&self.levels()[level][index])),
+            );
+            node.insert(String::from("padding"), Value::from(true));
+            self.insert_label(&mut node, level, index, opts);
+            return Value::Object(node);
+        }
+        if level > 0 && index * 2 + 1 >= self.levels()[level - 1].len() {
+            // A node carried up unpaired appears once, at its original level
+            return self.json_node(level - 1, index * 2, depth, opts);
+        }
+
+        let mut node = Map::new();
+        node.insert(
+            String::from("hash"),
+            Value::from(hex::encode(&self.levels()[level][index])),
+        );
+        self.insert_label(&mut node, level, index, opts);
+        if level == 0 {
+            node.insert(String::from("index"), Value::from(index));
+            let leaf_count = self.leaf_count();
+            if index >= leaf_count {
+                node.insert(String::from("padding"), Value::from(true));
+            }
+            if let Some(metadata) = self.metadata(index).filter(|_| opts.metadata) {
+                node.insert(String::from("metadata"), Value::from(hex::encode(metadata)));
+            }
+            if opts.data_previews && !empty {
+                if let Some(data) = self.get_leaf(index.min(leaf_count - 1)) {
+                    let preview = &data[..data.len().min(PREVIEW_BYTES)];
+                    node.insert(
+                        String::from("data_preview"),
+                        Value::from(String::from_utf8_lossy(preview)),
+                    );
+                }
+            }
+        } else if opts.max_depth.is_some_and(|max| depth >= max) {
+            node.insert(String::from("truncated"), Value::from(true));
+        } else {
+            node.insert(
+                String::from("left"),
+                self.json_node(level - 1, index * 2, depth + 1, opts),
+            );
+            node.insert(
+                String::from("right"),
+                self.json_node(level - 1, index * 2 + 1, depth + 1, opts),
+            );
+        }
+        Value::Object(node)
+    }
+
+    /// Add a node's label to its object, if it has one and labels are exported
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node's object
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    /// * `opts` - What to include
+    fn insert_label(
+        &self,
+        node: &mut Map<String, Value>,
+        level: usize,
+        index: usize,
+        opts: &JsonExportOptions,
+    ) {
+        if let Some(label) = self.subtree_label(level, index).filter(|_| opts.labels) {
+            node.insert(String::from("label"), Value::from(label));
+        }
+    }
+
+    /// Label the subtrees named in a structure, walking it as `json_node` exports it
+    ///
+    /// Parts of the structure that do not match the tree's shape are skipped; the caller
+    /// rejects such structures when it compares them with the tree's own export.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The object exported for the node
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `MerkleError::InvalidStructure` if a label is not a string
+    fn read_labels(&mut self, node: &Value, level: usize, index: usize) -> Result<(), MerkleError> {
+        if level > 0
+            && self.empty_subtree_at(level, index).is_none()
+            && index * 2 + 1 >= self.levels()[level - 1].len()
+        {
+            return self.read_labels(node, level - 1, index * 2);
+        }
+        if let Some(label) = node.get("label") {
+            let label = label
+                .as_str()
+                .ok_or_else(|| invalid(format!("label {} is not a string", label)))?;
+            if level > 0 || index < self.leaf_count() {
+                self.label_subtree(level, index, label)?;
+            }
+        }
+        if level > 0 && self.empty_subtree_at(level, index).is_none() {
+            if let (Some(left), Some(right)) = (node.get("left"), node.get("right")) {
+                self.read_labels(left, level - 1, index * 2)?;
+                self.read_labels(right, level - 1, index * 2 + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The index, hash and metadata of a leaf read from a structure
+type ExportedLeaf = (usize, Vec<u8>, Option<Vec<u8>>);
+
+/// Collect the index, hash and metadata of every real leaf of a structure, left to right
+///
+/// # Arguments
+///
+/// * `node` - The node to collect from
+/// * `leaves` - The leaves collected so far
+///
+/// # Returns
+///
+/// Ok, or `MerkleError::InvalidStructure` describing the first malformed node
+fn collect_leaves(node: &Value, leaves: &mut Vec<ExportedLeaf>) -> Result<(), MerkleError> {
+    let hash = node
+        .get("hash")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid(String::from("a node has no hash")))?;
+    let hash = hex::decode(hash).map_err(|_| invalid(format!("hash {:?} is not hex", hash)))?;
+
+    if let Some(index) = node.get("index") {
+        let index = index
+            .as_u64()
+            .and_then(|index| usize::try_from(index).ok())
+            .ok_or_else(|| invalid(format!("leaf index {} is not an index", index)))?;
+        let metadata = match node.get("metadata") {
+            Some(metadata) => Some(
+                metadata
+                    .as_str()
+                    .and_then(|metadata| hex::decode(metadata).ok())
+                    .ok_or_else(|| invalid(format!("leaf {} has invalid metadata", index)))?,
+            ),
+            None => None,
+        };
+        if node.get("padding") != Some(&Value::Bool(true)) {
+            leaves.push((index, hash, metadata));
+        }
+        return Ok(());
+    }
+    if node.get("padding") == Some(&Value::Bool(true)) {
+        // An empty subtree of a complete tree holds no leaves
+        return Ok(());
+    }
+    match (node.get("left"), node.get("right")) {
+        (Some(left), Some(right)) => {
+            collect_leaves(left, leaves)?;
+            collect_leaves(right, leaves)
+        }
+        _ => Err(invalid(String::from(
+            "a branch is truncated or missing a child",
+        ))),
+    }
+}
+
+/// Remove every data preview from a structure
+fn strip_previews(node: &Value) -> Value {
+    match node {
+        Value::Object(members) => Value::Object(
+            members
+                .iter()
+                .filter(|(key, _)| *key != "data_preview")
+                .map(|(key, value)| (key.clone(), strip_previews(value)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Create an invalid structure error
+fn invalid(reason: String) -> MerkleError {
+    MerkleError::InvalidStructure { reason }
+}
@@ -0,0 +1,279 @@
+use crate::error::MerkleError;
+use crate::hashing::{HashConfig, HashScheme};
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A Merkle tree whose leaves are addressed by string keys
+///
+/// Entries are committed in sorted key order, so the root depends only on the set of
+/// entries and not on the order they were supplied in. Each leaf's data is
+/// `H(key || 0x00 || value)`, binding the key into the commitment; keys therefore may not
+/// contain a NUL character.
+///
+/// With the `serde` feature the entries and hash configuration are persisted, and the
+/// tree is rebuilt (and its keys re-validated) when deserialized.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "KeyedEntries", try_from = "KeyedEntries")
+)]
+pub struct KeyedMerkleTree {
+    /// The entries in ascending key order
+    entries: Vec<(String, Vec<u8>)>,
+    /// The tree over the entries' leaf data
+    tree: MerkleTree,
+}
+
+/// The persisted form of a keyed tree
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct KeyedEntries {
+    /// The configuration the tree is built with
+    #[serde(flatten)]
+    hasher: HashConfig,
+    /// The entries in ascending key order
+    entries: Vec<KeyedEntry>,
+}
+
+/// A persisted entry of a keyed tree
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct KeyedEntry {
+    /// The entry's key
+    key: String,
+    /// The entry's value
+    #[serde(with = "crate::serde_hex")]
+    value: Vec<u8>,
+}
+
+impl KeyedMerkleTree {
+    /// Create a keyed tree with the defaults of `MerkleTree::builder()`
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The key-value pairs to include, in any order
+    ///
+    /// # Returns
+    ///
+    /// The keyed tree, or an error if a key is repeated or contains a NUL character
+    pub fn new(entries: Vec<(String, Vec<u8>)>) -> Result<Self, MerkleError> {
+        Self::with_hash_config(entries, HashScheme::DomainSeparated)
+    }
+
+    /// Create a keyed tree using the given hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The key-value pairs to include, in any order
+    /// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees)
+    ///
+    /// # Returns
+    ///
+    /// The keyed tree, or an error if a key is repeated or contains a NUL character
+    pub fn with_hash_config(
+        mut entries: Vec<(String, Vec<u8>)>,
+        hasher: impl Into<HashConfig>,
+    ) -> Result<Self, MerkleError> {
+        let hasher = hasher.into();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some((key, _)) = entries.iter().find(|(key, _)| key.contains('\0')) {
+            return Err(MerkleError::InvalidKey { key: key.clone() });
+        }
+        if let Some(pair) = entries.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(MerkleError::DuplicateKey {
+                key: pair[0].0.clone(),
+            });
+        }
+
+        let leaves = entries
+            .iter()
+            .map(|(key, value)| keyed_leaf(&hasher, key, value))
+            .collect();
+
+        Ok(KeyedMerkleTree {
+            entries,
+            tree: MerkleTree::with_config(leaves, hasher),
+        })
+    }
+
+    /// Get the value stored under a key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up
+    ///
+    /// # Returns
+    ///
+    /// The value, or None if the key is not in the tree
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.position(key)
+            .map(|index| self.entries[index].1.as_slice())
+    }
+
+    /// Generate a proof for the entry stored under a key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to prove
+    ///
+    /// # Returns
+    ///
+    /// The proof, or None if the key is not in the tree
+    pub fn proof_for_key(&self, key: &str) -> Option<MerkleProof> {
+        self.tree.proof_by_index(self.position(key)?)
+    }
+
+    /// Verify a keyed proof against a root hash built with the default configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the entry
+    /// * `value` - The value of the entry
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof shows the key maps to the value under the root, false otherwise
+    pub fn verify_for_key(key: &str, value: &[u8], proof: &MerkleProof, root_hash: &[u8]) -> bool {
+        Self::verify_for_key_with(HashScheme::DomainSeparated, key, value, proof, root_hash)
+    }
+
+    /// Verify a keyed proof against a root hash using the given hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees) the
+    ///   tree was built with
+    /// * `key` - The key of the entry
+    /// * `value` - The value of the entry
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof shows the key maps to the value under the root, false otherwise
+    pub fn verify_for_key_with(
+        hasher: impl Into<HashConfig>,
+        key: &str,
+        value: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+    ) -> bool {
+        let hasher = hasher.into();
+        !key.contains('\0')
+            && MerkleTree::verify_proof_with(
+                hasher,
+                &keyed_leaf(&hasher, key, value),
+                proof,
+                root_hash,
+                None,
+            )
+    }
+
+    /// Get the root hash of the tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or None if the tree has no entries
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.tree.root_hash()
+    }
+
+    /// Get the keys in the order they are committed
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the keys in ascending order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// Get the underlying tree
+    ///
+    /// # Returns
+    ///
+    /// The tree over the entries' leaf data
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Get the number of entries
+    ///
+    /// # Returns
+    ///
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the tree has no entries
+    ///
+    /// # Returns
+    ///
+    /// True if the tree has no entries, false otherwise
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Find the leaf index of a key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up
+    ///
+    /// # Returns
+    ///
+    /// The index, or None if the key is not in the tree
+    fn position(&self, key: &str) -> Option<usize> {
+        self.entries
+            .binary_search_by(|(entry_key, _)| entry_key.as_str().cmp(key))
+            .ok()
+    }
+}
+
+/// Compute the leaf data committing to a key-value pair
+///
+/// # Arguments
+///
+/// * `hasher` - The hash configuration whose algorithm is used
+/// * `key` - The entry's key
+/// * `value` - The entry's value
+///
+/// # Returns
+///
+/// `H(key || 0x00 || value)`
+fn keyed_leaf(hasher: &HashConfig, key: &str, value: &[u8]) -> Vec<u8> {
+    hasher.algorithm.digest(&[key.as_bytes(), &[0x00], value])
+}
+
+#[cfg(feature = "serde")]
+impl From<KeyedMerkleTree> for KeyedEntries {
+    fn from(tree: KeyedMerkleTree) -> Self {
+        KeyedEntries {
+            hasher: tree.tree.hash_config(),
+            entries: tree
+                .entries
+                .into_iter()
+                .map(|(key, value)| KeyedEntry { key, value })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<KeyedEntries> for KeyedMerkleTree {
+    type Error = MerkleError;
+
+    fn try_from(persisted: KeyedEntries) -> Result<Self, Self::Error> {
+        let entries = persisted
+            .entries
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect();
+        KeyedMerkleTree::with_hash_config(entries, persisted.hasher)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::merkle_tree::wipe;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The payload a tree keeps for one leaf
+///
+/// Only the storage differs between the variants: leaves are hashed from the same bytes
+/// either way, so roots and proofs do not depend on it.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum LeafData {
+    /// Data owned by the tree, shared with its clones until one of them replaces it
+    Owned(Vec<u8>),
+    /// Data shared with the application, so cloning the tree only bumps a reference count
+    Shared(Arc<[u8]>),
+    /// No data, because it was never kept or has been cleared
+    #[default]
+    None,
+}
+
+impl LeafData {
+    /// Get the leaf's data
+    ///
+    /// # Returns
+    ///
+    /// The data, or None if no data is kept
+    pub(crate) fn as_deref(&self) -> Option<&[u8]> {
+        match self {
+            LeafData::Owned(data) => Some(data),
+            LeafData::Shared(data) => Some(data),
+            LeafData::None => None,
+        }
+    }
+
+    /// Get the shared data
+    ///
+    /// # Returns
+    ///
+    /// Another reference to the data, or None if the data is not shared
+    pub(crate) fn shared(&self) -> Option<Arc<[u8]>> {
+        match self {
+            LeafData::Shared(data) => Some(Arc::clone(data)),
+            _ => None,
+        }
+    }
+
+    /// Drop the data, wiping it first if the tree owns it
+    ///
+    /// Shared data belongs to the application as well and is left intact.
+    pub(crate) fn clear(&mut self) {
+        if let LeafData::Owned(mut data) = std::mem::take(self) {
+            wipe(&mut data);
+        }
+    }
+}
+
+impl From<Vec<u8>> for LeafData {
+    fn from(data: Vec<u8>) -> Self {
+        LeafData::Owned(data)
+    }
+}
+
+impl From<Arc<[u8]>> for LeafData {
+    fn from(data: Arc<[u8]>) -> Self {
+        LeafData::Shared(data)
+    }
+}
+
+/// A content-addressed store of leaf payloads, so identical leaves share one copy
+///
+/// Each distinct payload is kept once with the number of leaves referencing it, and
+/// leaves hold `LeafData::Shared` references to it. An entry is dropped when its last
+/// leaf releases it. The counts are the pool's own, so clones of a tree sharing the
+/// payloads do not disturb them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LeafPool {
+    /// The payloads and the number of leaves referencing each
+    entries: HashMap<Arc<[u8]>, usize>,
+}
+
+impl LeafPool {
+    /// Store a leaf's data in the pool
+    ///
+    /// Owned data is copied into the pool unless an identical payload is there already,
+    /// and then wiped as by `LeafData::clear`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf's data
+    ///
+    /// # Returns
+    ///
+    /// A reference to the pooled payload, or `LeafData::None` if there is no data
+    pub(crate) fn intern(&mut self, data: LeafData) -> LeafData {
+        let payload = match data {
+            LeafData::None => return LeafData::None,
+            LeafData::Owned(mut data) => {
+                let payload = match self.entries.get_key_value(&data[..]) {
+                    Some((payload, _)) => Arc::clone(payload),
+                    None => Arc::from(&data[..]),
+                };
+                wipe(&mut data);
+                payload
+            }
+            LeafData::Shared(data) => data,
+        };
+        match self.entries.entry(payload) {
+            Entry::Occupied(mut entry) => {
+                *entry.get_mut() += 1;
+                LeafData::Shared(Arc::clone(entry.key()))
+            }
+            Entry::Vacant(entry) => {
+                let payload = Arc::clone(entry.key());
+                entry.insert(1);
+                LeafData::Shared(payload)
+            }
+        }
+    }
+
+    /// Drop a leaf's reference to its payload, freeing the payload if it was the last
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data the leaf held
+    pub(crate) fn release(&mut self, data: &LeafData) {
+        if let LeafData::Shared(payload) = data {
+            if let Some(count) = self.entries.get_mut(&payload[..]) {
+                *count -= 1;
+                if *count == 0 {
+                    self.entries.remove(&payload[..]);
+                }
+            }
+        }
+    }
+
+    /// Drop every payload
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Get the number of distinct payloads
+    ///
+    /// # Returns
+    ///
+    /// The number of entries in the pool
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
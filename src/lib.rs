@@ -2,20 +2,256 @@
 //
 // This crate provides a complete implementation of a Merkle tree data structure,
 // which is a fundamental component in many blockchain and distributed systems.
+//
+// Without the `std` feature only the fixed-depth trees are compiled, and the crate is
+// `no_std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "build")]
+mod audit;
+#[cfg(all(feature = "build", feature = "serde"))]
+mod batch;
+#[cfg(feature = "build")]
+mod builder;
+#[cfg(feature = "build")]
+mod bulk;
+#[cfg(feature = "serde")]
+mod canonical_json;
+#[cfg(feature = "build")]
+mod canopy;
+#[cfg(feature = "build")]
+mod chained;
+#[cfg(feature = "build")]
+mod checkpoint;
+#[cfg(feature = "build")]
+mod chunked;
+#[cfg(feature = "build")]
+mod commit_reveal;
+#[cfg(feature = "build")]
+mod commitment_scheme;
+#[cfg(feature = "verify")]
+mod compat;
+#[cfg(feature = "build")]
+mod complete;
+#[cfg(feature = "build")]
+mod concurrent;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(feature = "verify")]
+mod consistency;
+#[cfg(feature = "build")]
+mod coverage;
+#[cfg(feature = "verify")]
+mod diagnose;
+#[cfg(feature = "build")]
+mod diff;
+#[cfg(feature = "build")]
+mod display;
+#[cfg(feature = "build")]
+mod encoding;
+#[cfg(feature = "build")]
+mod epoch;
+#[cfg(feature = "verify")]
+mod error;
+#[cfg(feature = "build")]
+mod file_tree;
+mod fixed;
+#[cfg(feature = "build")]
+mod hash_chain;
+#[cfg(feature = "build")]
+mod hash_index;
+#[cfg(feature = "verify")]
+mod hashing;
+#[cfg(feature = "build")]
+mod index;
+#[cfg(all(feature = "build", feature = "serde"))]
+pub mod json;
+#[cfg(feature = "build")]
+mod keyed;
+#[cfg(feature = "build")]
+mod leaf_data;
+#[cfg(feature = "verify")]
+mod limits;
+#[cfg(feature = "build")]
 mod merkle_node;
+#[cfg(feature = "build")]
 mod merkle_tree;
+#[cfg(feature = "build")]
+mod metrics;
+#[cfg(feature = "build")]
+mod mismatch;
+#[cfg(feature = "verify")]
+mod multiproof;
+#[cfg(feature = "build")]
+mod observer;
+#[cfg(feature = "build")]
+mod operation_log;
+#[cfg(feature = "verify")]
+mod params;
+#[cfg(feature = "build")]
+mod progress;
+#[cfg(feature = "verify")]
+mod proof;
+#[cfg(feature = "build")]
+mod proof_cache;
+#[cfg(feature = "build")]
+mod pruned;
+#[cfg(feature = "build")]
+mod reconcile;
+#[cfg(all(test, feature = "build"))]
+mod reference;
+#[cfg(feature = "build")]
+mod render;
+#[cfg(feature = "verify")]
+mod sample;
+mod scheme;
+#[cfg(feature = "serde")]
+mod serde_hex;
+#[cfg(all(feature = "build", feature = "serde"))]
+mod service;
+#[cfg(feature = "build")]
+mod shared_vec;
+#[cfg(feature = "build")]
+mod slotted;
+#[cfg(feature = "build")]
+mod snapshot;
+#[cfg(feature = "build")]
+mod store;
+#[cfg(feature = "build")]
+mod stream;
+#[cfg(feature = "build")]
+mod sum;
+#[cfg(any(all(test, feature = "build"), feature = "test-vectors"))]
+pub mod test_vectors;
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub mod testing;
+#[cfg(feature = "verify")]
+mod verified;
+#[cfg(feature = "build")]
+mod verified_build;
+#[cfg(feature = "verify")]
+mod verifier;
+#[cfg(feature = "verify")]
+mod verify;
 
 // Re-export the main types and functions for external use
+#[cfg(all(feature = "build", feature = "serde"))]
+pub use batch::{Batch, TypedProof};
+#[cfg(feature = "build")]
+pub use builder::MerkleTreeBuilder;
+#[cfg(feature = "build")]
+pub use bulk::{BulkReport, LeafCheck, LeafOutcome};
+#[cfg(feature = "build")]
+pub use canopy::Canopy;
+#[cfg(feature = "build")]
+pub use chained::ChainedProof;
+#[cfg(feature = "build")]
+pub use checkpoint::CheckpointedBuilder;
+#[cfg(feature = "build")]
+pub use chunked::ChunkedFileTree;
+#[cfg(feature = "build")]
+pub use commit_reveal::{reveal, Commitment, Reveal};
+#[cfg(feature = "build")]
+pub use commitment_scheme::CommitmentScheme;
+#[cfg(feature = "verify")]
+pub use compat::Compat;
+#[cfg(feature = "build")]
+pub use complete::empty_subtree_hashes;
+#[cfg(feature = "build")]
+pub use concurrent::ConcurrentMerkleTree;
+#[cfg(feature = "verify")]
+pub use consistency::ConsistencyProof;
+#[cfg(feature = "build")]
+pub use coverage::CoverageTracker;
+#[cfg(feature = "verify")]
+pub use diagnose::{Diagnosis, ProofRepair};
+#[cfg(feature = "build")]
+pub use diff::{DiffLeaf, SnapshotDiff};
+#[cfg(feature = "build")]
+pub use display::{HexDisplay, ProofHexDisplay, RootHexDisplay};
+#[cfg(feature = "build")]
+pub use encoding::{AbiPacked, LeafEncoder, LengthPrefixed, RawBytes, Utf8String};
+#[cfg(feature = "serde")]
+pub use error::CanonicalJsonError;
+#[cfg(feature = "conformance")]
+pub use error::ConformanceError;
+#[cfg(feature = "verify")]
+pub use error::{
+    AuditError, MerkleError, ProofDecodeError, RevealError, StreamVerifyError, VerificationError,
+};
+#[cfg(feature = "build")]
+pub use error::{BuildVerifyError, IngestError, ReplayError, SnapshotError};
+#[cfg(feature = "build")]
+pub use file_tree::{FileManifest, FileTree, FileTreeConfig, MIN_RECOMMENDED_CHUNK_SIZE};
+pub use fixed::{
+    fixed_depth_nodes, verify_fixed_proof, FixedDepthTree, FixedIndexError, MAX_FIXED_DEPTH,
+};
+#[cfg(feature = "build")]
+pub use hash_chain::{ChainProof, HashChain};
+#[cfg(feature = "verify")]
+pub use hashing::{ByteOrder, EmptyRootPolicy, HashAlgorithm, HashConfig, PairOrdering};
+#[cfg(feature = "build")]
+pub use index::{LeafIndex, NodeCoord};
+#[cfg(all(feature = "build", feature = "serde"))]
+pub use json::JsonExportOptions;
+#[cfg(feature = "build")]
+pub use keyed::KeyedMerkleTree;
+#[cfg(feature = "verify")]
+pub use limits::{DecodeLimit, DecodeLimits};
+#[cfg(feature = "build")]
 pub use merkle_node::MerkleNode;
-pub use merkle_tree::MerkleTree;
+#[cfg(feature = "build")]
+pub use merkle_tree::{proof_from_leaf_hashes, MerkleTree, PushOutcome};
+#[cfg(feature = "build")]
+pub use metrics::{HashMetrics, ProofCacheStats, StorageStats};
+#[cfg(feature = "build")]
+pub use mismatch::{LeafDifference, MismatchReport, TreeSummary};
+#[cfg(feature = "verify")]
+pub use multiproof::MultiProof;
+#[cfg(feature = "build")]
+pub use observer::{MutationKind, ObserverHandle, RootChange};
+#[cfg(feature = "build")]
+pub use operation_log::{LogEntry, Operation, OperationLog};
+#[cfg(feature = "verify")]
+pub use params::{PaddingStrategy, TreeParams, TreeVersion};
+#[cfg(feature = "build")]
+pub use progress::ProgressEvent;
+#[cfg(feature = "verify")]
+pub use proof::{
+    EpochProof, MerkleProof, ProofBundle, ProofEncoding, RootId, RootStamp, ROOT_ID_LEN,
+};
+#[cfg(feature = "build")]
+pub use pruned::PrunedMerkleTree;
+#[cfg(feature = "build")]
+pub use reconcile::reconcile;
+#[cfg(feature = "build")]
+pub use render::TreePrintOptions;
+#[cfg(feature = "verify")]
+pub use sample::audit_sample_indices;
+pub use scheme::{HashScheme, EMPTY_LEAF, EMPTY_LEAF_DATA, LEAF_PREFIX, NODE_PREFIX};
+#[cfg(all(feature = "build", feature = "serde"))]
+pub use service::{LeafSelector, ProofRequest, ProofResponse};
+#[cfg(feature = "build")]
+pub use slotted::SlottedMerkleTree;
+#[cfg(feature = "build")]
+pub use snapshot::{SnapshotReader, SnapshotSource};
+#[cfg(feature = "build")]
+pub use store::{FileLevelStore, LevelStore, MemoryLevelStore, StoredMerkleTree};
+#[cfg(feature = "build")]
+pub use stream::{verify_stream, verify_stream_with, RootAccumulator};
+#[cfg(feature = "build")]
+pub use sum::{SumMerkleTree, SumProof, SumProofStep};
+#[cfg(feature = "verify")]
+pub use verified::{LeafBundle, VerifiedLeaf};
+#[cfg(feature = "verify")]
+pub use verifier::{MerkleVerifier, MultiVerifier};
+#[cfg(all(feature = "verify", not(feature = "build")))]
+pub use verify::MerkleTree;
+#[cfg(feature = "verify")]
+pub use verify::{expected_proof_len, expected_proof_len_for_index, MAX_PROOF_STEPS};
 
-#[cfg(test)]
+#[cfg(all(test, feature = "build"))]
 mod tests;
-
-/// A proof that a piece of data exists in a Merkle tree
-///
-/// Each element in the proof is:
-/// - A hash value (sibling hash)
-/// - A boolean flag indicating whether the sibling is on the left side
-pub type MerkleProof = Vec<(Vec<u8>, bool)>;
+#[cfg(all(test, feature = "verify"))]
+mod verify_tests;
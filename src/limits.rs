@@ -0,0 +1,148 @@
+//! Bounds on the resources decoding untrusted input may use
+//!
+//! Every decoder of proofs, snapshots and checkpoints takes a `DecodeLimits`, through its
+//! `_with` variant, and checks each count or length it reads against it before
+//! allocating for it. The variants without limits use `DecodeLimits::default()`.
+
+use crate::error::MerkleError;
+use crate::verify::MAX_PROOF_STEPS;
+use std::fmt;
+
+/// A resource bounded by `DecodeLimits`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DecodeLimit {
+    /// The number of leaves a decoded tree or multiproof covers, or the number of
+    /// indices a multiproof proves
+    Leaves,
+    /// The size of one leaf in bytes
+    LeafSize,
+    /// The number of steps of a proof, or the height of a decoded tree
+    ProofDepth,
+    /// The size of the encoded input in bytes
+    TotalBytes,
+}
+
+/// Bounds on what a decoder accepts
+///
+/// Exceeding any of them gives `MerkleError::LimitExceeded`, wrapped in the decoder's
+/// own error type where it has one. The default allows any tree of up to `2^32` leaves
+/// and 16 MiB per leaf; `strict` suits decoding requests from the network, and `relaxed`
+/// suits loading files the application wrote itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecodeLimits {
+    /// The most leaves a tree or multiproof may cover
+    pub max_leaves: u64,
+    /// The largest leaf, in bytes
+    pub max_leaf_size: u64,
+    /// The most steps a proof may have, which `MAX_PROOF_STEPS` caps in any case
+    pub max_proof_depth: usize,
+    /// The largest encoded input, in bytes
+    pub max_total_bytes: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_leaves: 1 << 32,
+            max_leaf_size: 1 << 24,
+            max_proof_depth: MAX_PROOF_STEPS,
+            max_total_bytes: 1 << 38,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Limits for input from untrusted peers
+    ///
+    /// # Returns
+    ///
+    /// Limits of `2^20` leaves, 64 KiB per leaf, 32 proof steps and 128 MiB of input
+    pub fn strict() -> Self {
+        DecodeLimits {
+            max_leaves: 1 << 20,
+            max_leaf_size: 1 << 16,
+            max_proof_depth: 32,
+            max_total_bytes: 1 << 27,
+        }
+    }
+
+    /// Limits for input the application produced itself
+    ///
+    /// # Returns
+    ///
+    /// Limits of `2^48` leaves, 1 GiB per leaf, `MAX_PROOF_STEPS` proof steps and 1 PiB
+    /// of input
+    pub fn relaxed() -> Self {
+        DecodeLimits {
+            max_leaves: 1 << 48,
+            max_leaf_size: 1 << 30,
+            max_proof_depth: MAX_PROOF_STEPS,
+            max_total_bytes: 1 << 50,
+        }
+    }
+
+    /// Get the bound on a resource
+    ///
+    /// # Arguments
+    ///
+    /// * `which` - The resource
+    ///
+    /// # Returns
+    ///
+    /// The largest amount allowed
+    pub fn limit(&self, which: DecodeLimit) -> u64 {
+        match which {
+            DecodeLimit::Leaves => self.max_leaves,
+            DecodeLimit::LeafSize => self.max_leaf_size,
+            DecodeLimit::ProofDepth => self.max_proof_depth as u64,
+            DecodeLimit::TotalBytes => self.max_total_bytes,
+        }
+    }
+
+    /// Check an amount against the bound on a resource
+    ///
+    /// # Arguments
+    ///
+    /// * `which` - The resource
+    /// * `requested` - The amount the input asks for
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `MerkleError::LimitExceeded` if the amount is over the bound
+    pub(crate) fn check(&self, which: DecodeLimit, requested: usize) -> Result<(), MerkleError> {
+        self.check_u64(which, requested as u64)
+    }
+
+    /// Check an amount read as a `u64` against the bound on a resource
+    ///
+    /// # Arguments
+    ///
+    /// * `which` - The resource
+    /// * `requested` - The amount the input asks for
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `MerkleError::LimitExceeded` if the amount is over the bound
+    pub(crate) fn check_u64(&self, which: DecodeLimit, requested: u64) -> Result<(), MerkleError> {
+        let limit = self.limit(which);
+        if requested > limit {
+            return Err(MerkleError::LimitExceeded {
+                which,
+                limit,
+                requested,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DecodeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeLimit::Leaves => write!(f, "leaves"),
+            DecodeLimit::LeafSize => write!(f, "leaf size"),
+            DecodeLimit::ProofDepth => write!(f, "proof depth"),
+            DecodeLimit::TotalBytes => write!(f, "total bytes"),
+        }
+    }
+}
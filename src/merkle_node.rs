@@ -1,4 +1,4 @@
-use sha2::{Digest, Sha256};
+use crate::hashing::{HashConfig, HashScheme};
 use std::fmt;
 
 /// Represents a node in the Merkle tree
@@ -6,6 +6,8 @@ use std::fmt;
 /// A node can be either:
 /// - A leaf node containing data and its hash
 /// - A branch node containing left and right children, and the hash of their combined hashes
+///
+/// Nodes are `Send` and `Sync`, so a node tree can be shared between threads.
 #[derive(Clone)]
 pub enum MerkleNode {
     /// A leaf node contains the original data and its hash
@@ -26,6 +28,12 @@ pub enum MerkleNode {
     },
 }
 
+// Nodes are shared between threads as freely as trees are
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MerkleNode>();
+};
+
 impl MerkleNode {
     /// Create a new leaf node from data
     ///
@@ -39,7 +47,21 @@ impl MerkleNode {
     ///
     /// A new leaf node containing the data and its hash
     pub fn new_leaf(data: Vec<u8>) -> Self {
-        let hash = Sha256::digest(&data).to_vec();
+        Self::new_leaf_with(data, HashScheme::Plain)
+    }
+
+    /// Create a new leaf node from data using the given hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to be stored in the leaf node
+    /// * `hasher` - The hash configuration (or scheme) used to hash the data
+    ///
+    /// # Returns
+    ///
+    /// A new leaf node containing the data and its hash
+    pub fn new_leaf_with(data: Vec<u8>, hasher: impl Into<HashConfig>) -> Self {
+        let hash = hasher.into().hash_leaf(&data);
         MerkleNode::Leaf { data, hash }
     }
 
@@ -56,10 +78,26 @@ impl MerkleNode {
     ///
     /// A new branch node containing the child nodes and the combined hash
     pub fn new_branch(left: MerkleNode, right: MerkleNode) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(left.hash());
-        hasher.update(right.hash());
-        let hash = hasher.finalize().to_vec();
+        Self::new_branch_with(left, right, HashScheme::Plain)
+    }
+
+    /// Create a new branch node from two child nodes using the given hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The left child node
+    /// * `right` - The right child node
+    /// * `hasher` - The hash configuration (or scheme) used to hash the child hashes together
+    ///
+    /// # Returns
+    ///
+    /// A new branch node containing the child nodes and the combined hash
+    pub fn new_branch_with(
+        left: MerkleNode,
+        right: MerkleNode,
+        hasher: impl Into<HashConfig>,
+    ) -> Self {
+        let hash = hasher.into().hash_pair(&left.hash(), &right.hash());
 
         MerkleNode::Branch {
             left: Box::new(left),
@@ -105,3 +143,13 @@ impl fmt::Debug for MerkleNode {
         }
     }
 }
+
+/// Wipe a leaf's data when the node is dropped
+#[cfg(feature = "zeroize")]
+impl Drop for MerkleNode {
+    fn drop(&mut self) {
+        if let MerkleNode::Leaf { data, .. } = self {
+            crate::merkle_tree::wipe(data);
+        }
+    }
+}
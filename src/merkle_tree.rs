@@ -1,6 +1,41 @@
-use crate::merkle_node::MerkleNode;
-use crate::MerkleProof;
-use sha2::{Digest, Sha256};
+use crate::builder::MerkleTreeBuilder;
+use crate::complete::{check_capacity, empty_subtrees};
+use crate::encoding::{LeafEncoder, Utf8String};
+use crate::epoch::{EpochHistory, DEFAULT_EPOCH_HISTORY};
+use crate::error::{IngestError, MerkleError, VerificationError};
+use crate::hash_index::HashIndex;
+#[cfg(feature = "parallel")]
+use crate::hashing::ParallelHasher;
+use crate::hashing::{
+    EmptyRootPolicy, HashAlgorithm, HashConfig, HashScheme, MemoizingHasher, NodeHasher,
+    PairOrdering, LEAF_PREFIX,
+};
+use crate::leaf_data::{LeafData, LeafPool};
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::metrics::{CountingHasher, HashCounter, HashMetrics, ProofCacheStats, StorageStats};
+use crate::multiproof::{EncodedSize, MultiProof};
+use crate::observer::{MutationKind, Observers, RootChange};
+use crate::operation_log::{LogEntry, Operation, OperationLog};
+use crate::params::{PaddingStrategy, TreeParams, TreeVersion};
+use crate::progress::ProgressEvent;
+use crate::proof::{EpochProof, MerkleProof, ProofBundle, ProofEncoding, RootId, RootStamp};
+use crate::proof_cache::ProofCache;
+use crate::pruned::PrunedMerkleTree;
+use crate::shared_vec::SharedVec;
+use crate::verify::{
+    fold_proof, is_padded_lone_leaf, level_sizes, positional_leaf, proof_fits_shape,
+    proof_leaf_index, siblings_fit,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+
+/// A tree's root under its current parameters and under the parameters migrated to
+type MigrationRoots = (Option<Vec<u8>>, Option<Vec<u8>>);
 
 /// The main Merkle tree structure
 ///
@@ -8,17 +43,132 @@ use sha2::{Digest, Sha256};
 /// - Each leaf node contains the hash of a data block
 /// - Each non-leaf node contains the hash of its two children
 /// - The root node represents a cryptographic summary of all data in the tree
+///
+/// Empty leaves are ordinary data: an empty leaf hashes to the hash of zero bytes, or of
+/// the leaf prefix alone under `HashScheme::DomainSeparated`, and in positional trees to
+/// the hash of its index. Like any repeated data, several empty leaves share one hash
+/// (except in positional trees), and `generate_proof(&[])` proves the lowest of them.
+///
+/// A tree is `Send` and `Sync`, and every query takes `&self`, so one tree can be
+/// shared between threads behind an `Arc` without locking; only `push`, `update_leaf` and
+/// the other mutating methods need exclusive access. This is part of the API contract,
+/// not an accident of the fields: state a query updates, such as the hash metrics and
+/// the proof cache, uses atomics or a mutex, and an assertion below fails the build if a
+/// field ever stops being `Send` or `Sync`.
 #[derive(Clone)]
 pub struct MerkleTree {
-    /// The root node of the tree (None if the tree is empty)
-    root: Option<MerkleNode>,
-    /// A vector of all leaf nodes for easier proof generation
-    leaves: Vec<MerkleNode>,
+    /// The data of each leaf supplied at construction, excluding padding
+    data: SharedVec<LeafData>,
+    /// The hashes of every level, from the (padded) leaf level up to the root
+    levels: Vec<SharedVec<Vec<u8>>>,
+    /// The parameters the tree was built with
+    params: TreeParams,
+    /// The number of hashes computed by this tree
+    metrics: HashCounter,
+    /// The `LeafEncoder::ID` of the encoder the leaves were built with, if any
+    encoder: Option<&'static str>,
+    /// The root reported while the tree has no leaves
+    empty_root: EmptyRootPolicy,
+    /// The original index of each leaf, if the leaves were sorted by hash
+    order: Option<SharedVec<usize>>,
+    /// Whether the leaf hashes are strictly increasing, so lookups can binary search
+    sorted_unique: bool,
+    /// The lowest index of each leaf hash, built by the first lookup that needs it
+    hash_index: OnceLock<HashIndex>,
+    /// The current epoch and the roots of recent past epochs
+    epochs: EpochHistory,
+    /// Unauthenticated metadata attached to leaves, keyed by leaf index
+    metadata: BTreeMap<usize, Vec<u8>>,
+    /// Names given to subtrees for debugging, keyed by the `(level, index)` of the node
+    /// drawn for the subtree
+    labels: BTreeMap<(usize, usize), String>,
+    /// Recently generated proofs, if proof caching is enabled
+    proof_cache: ProofCache,
+    /// The record of every mutation, if operation logging is enabled
+    log: Option<OperationLog>,
+    /// The shared copies of distinct leaf payloads, if storage is deduplicated
+    pool: Option<LeafPool>,
+    /// The callbacks run on every root change
+    observers: Observers,
+}
+
+// Trees are shared between threads (see the thread-safety contract above), so interior
+// mutability must use thread-safe primitives such as atomics and mutexes
+/// The most leaves `MerkleTree::new` builds on the stack (see `build_small`)
+const SMALL_TREE_LEAVES: usize = 32;
+
+/// The most nodes a tree of `SMALL_TREE_LEAVES` leaves has, counting padding
+const SMALL_TREE_NODES: usize = 2 * SMALL_TREE_LEAVES - 1;
+
+/// The most levels a tree of `SMALL_TREE_LEAVES` leaves has
+const SMALL_TREE_HEIGHT: usize = SMALL_TREE_LEAVES.trailing_zeros() as usize + 1;
+
+/// The digest length `build_small` stores nodes at
+const SMALL_TREE_DIGEST_LEN: usize = 32;
+
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MerkleTree>();
+};
+
+/// What appending a leaf changed
+///
+/// Appending always changes the root, so every proof issued before the push needs the
+/// new root to verify against the new tree, and the sibling covering the new leaf
+/// changes in every proof. Beyond that, most proofs keep their shape: the same steps in
+/// the same directions, with the same siblings everywhere else. `invalidated_indices`
+/// holds the leaves whose earlier proofs do not, either because a step was added (a
+/// node on the leaf's path gained a sibling, or the tree grew a level) or because a
+/// sibling was padding standing where the new leaf now is, such as the duplicate the
+/// last of an odd number of leaves was paired with. A service keeping proofs up to
+/// date can patch the others in place but should re-issue these.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PushOutcome {
+    /// The root of the tree after the push
+    pub new_root: Vec<u8>,
+    /// The earlier leaves whose proofs changed shape or depended on padding
+    pub invalidated_indices: Range<usize>,
+}
+
+/// Construction options that do not change how leaves and branches are hashed
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct BuildOptions {
+    /// Whether building fails when two leaves have the same hash
+    pub(crate) reject_duplicates: bool,
+    /// Whether leaves are sorted by hash before building
+    pub(crate) sort_leaves: bool,
+    /// Whether each distinct pair of child hashes is hashed only once while building
+    pub(crate) memoize_pairs: bool,
+    /// Whether building fails unless the leaf hashes are strictly increasing
+    pub(crate) sorted_unique: bool,
+    /// The root reported while the tree has no leaves
+    pub(crate) empty_root: EmptyRootPolicy,
+    /// The number of epochs whose roots are retained, if not the default
+    pub(crate) epoch_history: Option<usize>,
+    /// The number of proofs cached (0 disables the cache)
+    pub(crate) proof_cache: usize,
+    /// The largest leaf accepted, in bytes, if limited
+    pub(crate) max_leaf_size: Option<u64>,
+    /// Whether every mutation is recorded in an `OperationLog`
+    pub(crate) operation_log: bool,
+    /// Whether identical leaf payloads are stored once in a `LeafPool`
+    pub(crate) dedup_storage: bool,
+    /// Whether leaves and levels are hashed on rayon's thread pool
+    #[cfg(feature = "parallel")]
+    pub(crate) parallel: bool,
 }
 
 impl MerkleTree {
     /// Create a new Merkle tree from a list of data items
     ///
+    /// This uses `HashScheme::Plain` hashing for compatibility with existing roots. New
+    /// deployments should prefer `MerkleTree::builder()`, which defaults to domain-separated
+    /// hashing and is not vulnerable to second-preimage attacks.
+    ///
+    /// Trees of up to 32 leaves, such as per-block transaction batches, are hashed in a
+    /// stack-allocated array rather than level by level on the heap. They have the same
+    /// root and proofs either way.
+    ///
     /// # Arguments
     ///
     /// * `data_items` - A vector of data items to include in the tree
@@ -27,230 +177,3285 @@ impl MerkleTree {
     ///
     /// A new Merkle tree containing the data items
     pub fn new(data_items: Vec<Vec<u8>>) -> Self {
-        if data_items.is_empty() {
-            return MerkleTree {
-                root: None,
-                leaves: Vec::new(),
-            };
-        }
+        Self::with_config(data_items, HashScheme::Plain.into())
+    }
 
-        // Create leaf nodes
-        let mut leaves: Vec<MerkleNode> =
-            data_items.into_iter().map(MerkleNode::new_leaf).collect();
+    /// Create a new Merkle tree over data shared with the application
+    ///
+    /// The tree keeps a reference to each payload instead of a copy, so cloning the tree
+    /// does not copy any leaf data, and `shared_leaf` hands the payloads back. The root
+    /// and proofs are those of `new` over the same bytes. See
+    /// `MerkleTreeBuilder::build_shared` for other parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - The payloads to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree referencing the payloads
+    pub fn new_shared(data_items: Vec<Arc<[u8]>>) -> Self {
+        Self::build_with(
+            data_items,
+            HashScheme::Plain.into(),
+            BuildOptions::default(),
+            None,
+        )
+        .unwrap_or_else(|_| unreachable!("duplicate leaves are only rejected on request"))
+    }
 
-        // Special case for single node - don't duplicate it
-        if leaves.len() == 1 {
-            let leaf_copy = leaves[0].clone();
-            return MerkleTree {
-                root: Some(leaf_copy),
-                leaves,
-            };
-        }
+    /// Create a new Merkle tree, rejecting duplicate leaves
+    ///
+    /// This builds the same tree as `new`, but fails if two leaves have the same hash
+    /// instead of committing to the same data twice. The failure is reported as
+    /// `MerkleError::DuplicateLeaf` rather than a dedicated error type, so it travels with
+    /// `?` alongside the other build errors (such as those of `try_build` with
+    /// `reject_duplicates`, which reports it the same way).
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - A vector of data items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree containing the data items, or `MerkleError::DuplicateLeaf` naming
+    /// the first repeated leaf
+    pub fn new_unique(data_items: Vec<Vec<u8>>) -> Result<Self, MerkleError> {
+        let options = BuildOptions {
+            reject_duplicates: true,
+            ..BuildOptions::default()
+        };
+        Self::build_with(data_items, HashScheme::Plain.into(), options, None)
+    }
 
-        // If odd number of leaves, duplicate the last one
-        if leaves.len() % 2 == 1 {
-            leaves.push(leaves.last().unwrap().clone());
+    /// Create a new Merkle tree over a buffer of fixed-size records
+    ///
+    /// Each record is hashed straight from the buffer, so the tree stores only hashes and
+    /// `get_leaf` returns None for every record, as after `clear_leaf_data`. Like `new`,
+    /// this uses `HashScheme::Plain` hashing, and gives the same root as `new` over the
+    /// records copied into their own vectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - The records, back to back
+    /// * `record_size` - The size of each record in bytes
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree with one leaf per record, or `MerkleError::InvalidRecordSize` if
+    /// `record_size` is zero or does not divide the buffer's length
+    pub fn from_fixed_records(buf: &[u8], record_size: usize) -> Result<Self, MerkleError> {
+        Self::from_fixed_records_with(buf, record_size, &DecodeLimits::default())
+    }
+
+    /// Create a new Merkle tree over a buffer of fixed-size records within limits
+    ///
+    /// The buffer's length, the record size and the number of records are checked
+    /// against `max_total_bytes`, `max_leaf_size` and `max_leaves` before anything is
+    /// hashed.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - The records, back to back
+    /// * `record_size` - The size of each record in bytes
+    /// * `limits` - The limits to build within
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree with one leaf per record, `MerkleError::LimitExceeded` if the
+    /// buffer exceeds a limit, or `MerkleError::InvalidRecordSize` if `record_size` is
+    /// zero or does not divide the buffer's length
+    pub fn from_fixed_records_with(
+        buf: &[u8],
+        record_size: usize,
+        limits: &DecodeLimits,
+    ) -> Result<Self, MerkleError> {
+        limits.check(DecodeLimit::TotalBytes, buf.len())?;
+        limits.check(DecodeLimit::LeafSize, record_size)?;
+        if record_size == 0 || !buf.len().is_multiple_of(record_size) {
+            return Err(MerkleError::InvalidRecordSize {
+                record_size,
+                buffer_len: buf.len(),
+            });
         }
 
-        let leaves_copy = leaves.clone();
-        let root = Some(MerkleTree::build_tree(leaves));
+        let hasher = HashScheme::Plain.into();
+        let metrics = HashCounter::default();
+        let counting = metrics.counting(&hasher);
+        let record_count = buf.len() / record_size;
+        limits.check(DecodeLimit::Leaves, record_count)?;
+        let mut leaf_hashes = Vec::with_capacity(record_count + 1);
+        leaf_hashes.extend(
+            buf.chunks_exact(record_size)
+                .map(|record| counting.hash_leaf(record)),
+        );
+        let levels =
+            MerkleTree::build_levels(leaf_hashes, PaddingStrategy::DuplicateLast, &counting, None);
+
+        let mut tree = MerkleTree::from_parts(
+            vec![LeafData::None; record_count],
+            levels,
+            TreeParams::new(hasher, false),
+        );
+        tree.metrics = metrics;
+        Ok(tree)
+    }
+
+    /// Create a Merkle tree from the hashes of its leaves
+    ///
+    /// Together with `into_leaf_hashes` this lets a writer keep only the leaf hashes and
+    /// rebuild the tree later: a tree rebuilt with the same parameters has the identical
+    /// root and gives identical proofs. The leaf data is not known, so `get_leaf`
+    /// returns None for every leaf, as after `clear_leaf_data`.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hashes` - The hashes of the leaves, excluding padding
+    /// * `params` - The parameters (or hash configuration) the leaves were hashed with
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree over the leaf hashes
+    pub fn from_leaf_hashes(leaf_hashes: Vec<Vec<u8>>, params: impl Into<TreeParams>) -> Self {
+        let params = params.into();
+        let metrics = HashCounter::default();
+        let leaf_count = leaf_hashes.len();
+        let levels = MerkleTree::build_levels(
+            leaf_hashes,
+            params.padding,
+            &metrics.counting(&params.hasher),
+            None,
+        );
+
+        let mut tree = MerkleTree::from_parts(vec![LeafData::None; leaf_count], levels, params);
+        tree.metrics = metrics;
+        tree
+    }
+
+    /// Create a Merkle tree from levels computed earlier, such as those of a snapshot
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - The hashes of every level, from the (padded) leaf level up to the root
+    /// * `leaf_count` - The number of leaves, excluding padding
+    /// * `params` - The parameters the levels were computed with
+    /// * `empty_root` - The root reported while the tree has no leaves
+    ///
+    /// # Returns
+    ///
+    /// A tree over the levels, without leaf data
+    pub(crate) fn from_levels(
+        levels: Vec<Vec<Vec<u8>>>,
+        leaf_count: usize,
+        params: TreeParams,
+        empty_root: EmptyRootPolicy,
+    ) -> Self {
+        let mut tree = MerkleTree::from_parts(vec![LeafData::None; leaf_count], levels, params);
+        tree.empty_root = empty_root;
+        tree
+    }
 
+    /// Create a tree from its data, levels and parameters, with every other field at its
+    /// default
+    ///
+    /// Constructors set the fields they know on the returned tree (struct update syntax
+    /// cannot move out of a type with `Drop`), so a field added to the tree needs a
+    /// default here only.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of each leaf, excluding padding
+    /// * `levels` - The hashes of every level, from the (padded) leaf level up to the root
+    /// * `params` - The parameters the levels were computed with
+    ///
+    /// # Returns
+    ///
+    /// A tree without metrics, metadata, labels, history beyond the default, caching,
+    /// logging, pooling or observers
+    fn from_parts(data: Vec<LeafData>, levels: Vec<Vec<Vec<u8>>>, params: TreeParams) -> Self {
         MerkleTree {
-            root,
-            leaves: leaves_copy,
+            data: data.into(),
+            levels: levels.into_iter().map(SharedVec::from).collect(),
+            params,
+            metrics: HashCounter::default(),
+            encoder: None,
+            empty_root: EmptyRootPolicy::default(),
+            order: None,
+            sorted_unique: false,
+            hash_index: OnceLock::new(),
+            epochs: EpochHistory::new(DEFAULT_EPOCH_HISTORY),
+            metadata: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            proof_cache: ProofCache::default(),
+            log: None,
+            pool: None,
+            observers: Observers::default(),
+        }
+    }
+
+    /// Create a new Merkle tree from typed items using a leaf encoder
+    ///
+    /// Like `new`, this uses `HashScheme::Plain` hashing. The encoder is recorded so that
+    /// proofs can later be generated from typed items with `generate_proof_for`.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree over the encoded items
+    pub fn new_encoded<E: LeafEncoder>(items: Vec<E::Item>) -> Self {
+        Self::with_encoder::<E>(items, HashScheme::Plain.into())
+    }
+
+    /// Create a new Merkle tree from strings
+    ///
+    /// Each leaf is the string's UTF-8 bytes with no trailing separator, so the root is
+    /// identical to `MerkleTree::new` over the strings' bytes. Like `new`, this uses
+    /// `HashScheme::Plain` hashing.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The strings to include in the tree
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree containing the strings
+    pub fn from_strings(items: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::new_encoded::<Utf8String>(items.into_iter().map(Into::into).collect())
+    }
+
+    /// Create a new Merkle tree from typed items using the given hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items to include in the tree
+    /// * `hasher` - The configuration used to hash leaves and branches
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree over the encoded items
+    pub(crate) fn with_encoder<E: LeafEncoder>(items: Vec<E::Item>, hasher: HashConfig) -> Self {
+        Self::build_encoded_with::<E>(items, hasher.into(), BuildOptions::default(), None)
+            .unwrap_or_else(|_| unreachable!("duplicate leaves are only rejected on request"))
+    }
+
+    /// Create a new Merkle tree from typed items with the given parameters and options
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items to include in the tree
+    /// * `params` - The parameters used to hash leaves and branches
+    /// * `options` - The construction options
+    /// * `progress` - The callback to report progress to, if any
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree over the encoded items, or the error encoding an item or
+    /// building the tree
+    pub(crate) fn build_encoded_with<E: LeafEncoder>(
+        items: Vec<E::Item>,
+        params: TreeParams,
+        options: BuildOptions,
+        progress: Option<&dyn Fn(&ProgressEvent)>,
+    ) -> Result<Self, MerkleError> {
+        // Each item is checked as it is encoded, so no oversized encodings accumulate
+        let data_items = items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let data = E::encode(item)?;
+                check_leaf_size(index, data.len() as u64, options.max_leaf_size)?;
+                Ok(data)
+            })
+            .collect::<Result<_, MerkleError>>()?;
+        let mut tree = Self::build_with(data_items, params, options, progress)?;
+        tree.encoder = Some(E::ID);
+        Ok(tree)
+    }
+
+    /// Create a builder for configuring how a Merkle tree is constructed
+    ///
+    /// # Returns
+    ///
+    /// A new builder using domain-separated hashing
+    pub fn builder() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::new()
+    }
+
+    /// Create a new Merkle tree from a list of data items using the given hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - A vector of data items to include in the tree
+    /// * `hasher` - The configuration used to hash leaves and branches
+    ///
+    /// # Returns
+    ///
+    /// A new Merkle tree containing the data items
+    pub(crate) fn with_config(data_items: Vec<Vec<u8>>, hasher: HashConfig) -> Self {
+        if data_items.len() <= SMALL_TREE_LEAVES && hasher.digest_len() == SMALL_TREE_DIGEST_LEN {
+            return Self::build_small(data_items, hasher);
         }
+        Self::build_with(data_items, hasher.into(), BuildOptions::default(), None)
+            .unwrap_or_else(|_| unreachable!("duplicate leaves are only rejected on request"))
     }
 
-    /// Build the tree recursively
+    /// Create a tree of a few leaves as `with_config` does, hashing on the stack
+    ///
+    /// Every node is hashed into one fixed array with the `_into` hash functions, so no
+    /// buffer is allocated per hash, no level grows while it is built, and none of the
+    /// generic path's sorting, checking and progress machinery runs. Each level is then
+    /// copied into storage allocated at its final size. The levels, and so the root and
+    /// every proof, are those the generic path builds, and the hash counts match.
     ///
     /// # Arguments
     ///
-    /// * `nodes` - A vector of nodes to build the tree from
+    /// * `data_items` - At most `SMALL_TREE_LEAVES` data items
+    /// * `hasher` - A configuration with `SMALL_TREE_DIGEST_LEN`-byte digests
     ///
     /// # Returns
     ///
-    /// The root node of the tree
-    fn build_tree(nodes: Vec<MerkleNode>) -> MerkleNode {
-        if nodes.len() == 1 {
-            return nodes[0].clone();
+    /// A new Merkle tree containing the data items
+    fn build_small(data_items: Vec<Vec<u8>>, hasher: HashConfig) -> Self {
+        let params = TreeParams::from(hasher);
+        // The level sizes of `level_sizes`, kept on the stack too
+        let mut sizes = [0; SMALL_TREE_HEIGHT];
+        let mut height = 0;
+        let mut size = data_items.len();
+        if params.padding.duplicates_last(size) {
+            size += 1;
         }
+        while size > 0 {
+            sizes[height] = size;
+            height += 1;
+            size = if size == 1 { 0 } else { size.div_ceil(2) };
+        }
+        let sizes = &sizes[..height];
 
-        let mut next_level = Vec::new();
+        let mut nodes = [[0; SMALL_TREE_DIGEST_LEN]; SMALL_TREE_NODES];
+        for (node, data) in nodes.iter_mut().zip(&data_items) {
+            hasher.hash_leaf_into(data, node);
+        }
+        // If odd number of leaves, duplicate the last one
+        if sizes
+            .first()
+            .is_some_and(|&leaves| leaves > data_items.len())
+        {
+            nodes[data_items.len()] = nodes[data_items.len() - 1];
+        }
 
-        // Process pairs of nodes
-        for chunk in nodes.chunks(2) {
-            if chunk.len() == 2 {
-                let branch = MerkleNode::new_branch(chunk[0].clone(), chunk[1].clone());
-                next_level.push(branch);
-            } else {
-                // Should not happen if we handle odd number of leaves correctly
-                next_level.push(chunk[0].clone());
+        let mut start = 0;
+        let mut node_hashes = 0;
+        for pair in sizes.windows(2) {
+            let (below, above) = nodes.split_at_mut(start + pair[0]);
+            let children = &below[start..];
+            for (index, parent) in above[..pair[1]].iter_mut().enumerate() {
+                match children.get(index * 2 + 1) {
+                    Some(right) => {
+                        hasher.hash_pair_into(&children[index * 2], right, parent);
+                        node_hashes += 1;
+                    }
+                    // Unpaired nodes are carried up to the next level
+                    None => *parent = children[index * 2],
+                }
             }
+            start += pair[0];
         }
+        let metrics = HashCounter::default();
+        metrics.record_hashes(data_items.len() as u64, node_hashes);
 
-        // Recurse to the next level
-        MerkleTree::build_tree(next_level)
+        let mut start = 0;
+        let levels = sizes
+            .iter()
+            .map(|&size| {
+                let level = nodes[start..start + size]
+                    .iter()
+                    .map(|node| node.to_vec())
+                    .collect();
+                start += size;
+                level
+            })
+            .collect();
+        let mut tree = MerkleTree::from_parts(
+            data_items.into_iter().map(Into::into).collect(),
+            levels,
+            params,
+        );
+        tree.metrics = metrics;
+        tree
     }
 
-    /// Get the root hash of the tree
+    /// Create a new Merkle tree from a list of data items with the given parameters and
+    /// options
+    ///
+    /// Leaf sizes are checked before anything is hashed or copied, and duplicates are
+    /// detected with a hash set once the leaves are hashed, before any branch is built.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - A vector of data items to include in the tree
+    /// * `params` - The parameters used to hash leaves and branches
+    /// * `options` - The construction options
+    /// * `progress` - The callback to report progress to, if any
     ///
     /// # Returns
     ///
-    /// The root hash of the tree, or None if the tree is empty
-    pub fn root_hash(&self) -> Option<Vec<u8>> {
-        self.root.as_ref().map(|node| node.hash())
+    /// A new Merkle tree containing the data items, or `MerkleError::LeafTooLarge` or
+    /// `MerkleError::DuplicateLeaf`
+    pub(crate) fn build_with<D: AsRef<[u8]> + Into<LeafData> + Sync>(
+        mut data_items: Vec<D>,
+        params: TreeParams,
+        options: BuildOptions,
+        progress: Option<&dyn Fn(&ProgressEvent)>,
+    ) -> Result<Self, MerkleError> {
+        check_capacity(data_items.len(), params.padding)?;
+        for (index, data) in data_items.iter().enumerate() {
+            check_leaf_size(index, data.as_ref().len() as u64, options.max_leaf_size)?;
+        }
+        let metrics = HashCounter::default();
+        let counting = metrics.counting(&params.hasher);
+        let total = data_items.len();
+
+        // Sort by the plain leaf hash, which does not depend on the leaf's position. Unless
+        // the tree is positional those hashes are the leaf hashes and are reused below.
+        let mut order = None;
+        let mut sorted_hashes = None;
+        if options.sort_leaves {
+            let keys = hash_leaves(&data_items, options, &counting, None, |hasher, _, data| {
+                hasher.hash_leaf(data)
+            });
+            let mut permutation: Vec<usize> = (0..total).collect();
+            // A stable sort keeps duplicate leaves in their original order
+            permutation.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+            data_items = permute(data_items, &permutation);
+            if !params.positional {
+                sorted_hashes = Some(permute(keys, &permutation));
+            }
+            order = Some(permutation);
+        }
+
+        let leaf_hashes = match sorted_hashes {
+            Some(hashes) => {
+                if let Some(progress) = progress {
+                    for index in 0..total {
+                        progress(&ProgressEvent::LeafHashed { index, total });
+                    }
+                }
+                hashes
+            }
+            None => hash_leaves(
+                &data_items,
+                options,
+                &counting,
+                progress,
+                |hasher, index, data| {
+                    if params.positional {
+                        hasher.hash_leaf(&positional_leaf(index, data))
+                    } else {
+                        hasher.hash_leaf(data)
+                    }
+                },
+            ),
+        };
+        let data = data_items.into_iter().map(Into::into).collect();
+        Self::assemble(data, leaf_hashes, params, options, metrics, order, progress)
     }
 
-    /// Get the root hash of the tree as a hexadecimal string
+    /// Build two trees over the same leaves, hashed with two algorithms in one pass
+    ///
+    /// Each leaf is read once and hashed with both algorithms before the next, then its
+    /// data is moved into shared storage referenced by both trees. When sorting is
+    /// enabled each tree is sorted by its own leaf hashes; a sorted positional tree
+    /// hashes its leaves again once their final indices are known, as `build_with` does.
+    /// Progress is reported once per leaf, then for the levels of each tree in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - A vector of data items to include in the trees
+    /// * `params` - The parameters used to hash leaves and branches of the first tree
+    /// * `second` - The hash function of the second tree, which otherwise has `params`
+    /// * `options` - The construction options
+    /// * `progress` - The callback to report progress to, if any
     ///
     /// # Returns
     ///
-    /// The root hash as a hexadecimal string, or "Empty tree" if the tree is empty
-    pub fn root_hash_hex(&self) -> String {
-        match self.root_hash() {
-            Some(hash) => hex::encode(&hash),
-            None => String::from("Empty tree"),
+    /// The two trees, or `MerkleError::LeafTooLarge` or `MerkleError::DuplicateLeaf`
+    pub(crate) fn build_dual_with(
+        data_items: Vec<Vec<u8>>,
+        params: TreeParams,
+        second: HashAlgorithm,
+        options: BuildOptions,
+        progress: Option<&dyn Fn(&ProgressEvent)>,
+    ) -> Result<(Self, Self), MerkleError> {
+        check_capacity(data_items.len(), params.padding)?;
+        for (index, data) in data_items.iter().enumerate() {
+            check_leaf_size(index, data.len() as u64, options.max_leaf_size)?;
+        }
+        let params = [
+            params,
+            TreeParams {
+                hasher: HashConfig {
+                    algorithm: second,
+                    ..params.hasher
+                },
+                ..params
+            },
+        ];
+        let metrics = [HashCounter::default(), HashCounter::default()];
+        let total = data_items.len();
+
+        // Sorted trees are keyed by the plain leaf hash, as in `build_with`
+        let positional = params[0].positional && !options.sort_leaves;
+        let mut leaf_hashes = [Vec::with_capacity(total + 1), Vec::with_capacity(total + 1)];
+        let mut shared: Vec<Arc<[u8]>> = Vec::with_capacity(total);
+        {
+            let counting = [
+                metrics[0].counting(&params[0].hasher),
+                metrics[1].counting(&params[1].hasher),
+            ];
+            for (index, data) in data_items.into_iter().enumerate() {
+                for (hasher, hashes) in counting.iter().zip(&mut leaf_hashes) {
+                    hashes.push(if positional {
+                        hasher.hash_leaf(&positional_leaf(index, &data))
+                    } else {
+                        hasher.hash_leaf(&data)
+                    });
+                }
+                if let Some(progress) = progress {
+                    progress(&ProgressEvent::LeafHashed { index, total });
+                }
+                shared.push(data.into());
+            }
         }
+
+        let mut trees = params.into_iter().zip(metrics).zip(leaf_hashes).map(
+            |((params, metrics), mut leaf_hashes)| {
+                let mut data = shared.clone();
+                let mut order = None;
+                if options.sort_leaves {
+                    let mut permutation: Vec<usize> = (0..total).collect();
+                    // A stable sort keeps duplicate leaves in their original order
+                    permutation.sort_by(|&a, &b| leaf_hashes[a].cmp(&leaf_hashes[b]));
+                    data = permute(data, &permutation);
+                    leaf_hashes = permute(leaf_hashes, &permutation);
+                    if params.positional {
+                        let counting = metrics.counting(&params.hasher);
+                        leaf_hashes = data
+                            .iter()
+                            .enumerate()
+                            .map(|(index, data)| counting.hash_leaf(&positional_leaf(index, data)))
+                            .collect();
+                    }
+                    order = Some(permutation);
+                }
+                let data = data.into_iter().map(LeafData::Shared).collect();
+                Self::assemble(data, leaf_hashes, params, options, metrics, order, progress)
+            },
+        );
+        let first = trees.next().expect("two trees are built")?;
+        let second = trees.next().expect("two trees are built")?;
+        Ok((first, second))
     }
 
-    /// Generate a proof for a specific data item
+    /// Create a new Merkle tree from the hashes of leaves read from readers
     ///
-    /// A proof consists of a list of sibling hashes and their positions
-    /// (left or right) along the path from the leaf node to the root.
+    /// Each reader is hashed a buffer at a time and read at most one byte past the size
+    /// limit, so an oversized leaf is rejected without being read to its end, and no
+    /// leaf data is kept. Unless the tree is positional, leaves are sorted by hash when
+    /// sorting is enabled; the leaves of a positional tree keep the readers' order, since
+    /// sorting them would mean reading each leaf twice.
     ///
     /// # Arguments
     ///
-    /// * `data` - The data to generate a proof for
+    /// * `readers` - The readers yielding each leaf's data
+    /// * `params` - The parameters used to hash leaves and branches
+    /// * `options` - The construction options
+    /// * `progress` - The callback to report progress to, if any
     ///
     /// # Returns
     ///
-    /// A proof that the data exists in the tree, or None if the data is not found
-    pub fn generate_proof(&self, data: &[u8]) -> Option<MerkleProof> {
-        // Find the leaf node
-        let target_hash = Sha256::digest(data).to_vec();
-        let leaf_index = self.leaves.iter().position(|node| match node {
-            MerkleNode::Leaf { hash, .. } => hash == &target_hash,
-            _ => false,
-        })?;
-
-        let mut proof = Vec::new();
-        let mut index = leaf_index;
-        let mut level_size = self.leaves.len();
-        let mut level_nodes = self.leaves.clone();
-
-        while level_size > 1 {
-            let is_left = index % 2 == 0;
-            let sibling_idx = if is_left { index + 1 } else { index - 1 };
-
-            // Handle edge case where we duplicated the last leaf
-            if sibling_idx < level_nodes.len() {
-                proof.push((level_nodes[sibling_idx].hash(), !is_left));
-            }
+    /// A new Merkle tree without leaf data, or the error that stopped construction
+    pub(crate) fn build_from_readers<R: Read>(
+        readers: impl IntoIterator<Item = R>,
+        params: TreeParams,
+        options: BuildOptions,
+        progress: Option<&dyn Fn(&ProgressEvent)>,
+    ) -> Result<Self, IngestError> {
+        let readers: Vec<R> = readers.into_iter().collect();
+        check_capacity(readers.len(), params.padding).map_err(IngestError::Build)?;
+        let metrics = HashCounter::default();
+        let total = readers.len();
 
-            // Move to parent level
-            index /= 2;
-            level_size = (level_size + 1) / 2;
-
-            // Build the next level
-            let mut next_level = Vec::new();
-            for chunk in level_nodes.chunks(2) {
-                if chunk.len() == 2 {
-                    let branch = MerkleNode::new_branch(chunk[0].clone(), chunk[1].clone());
-                    next_level.push(branch);
-                } else {
-                    next_level.push(chunk[0].clone());
-                }
+        let mut leaf_hashes = Vec::with_capacity(total + 1);
+        for (index, reader) in readers.into_iter().enumerate() {
+            let mut prefix = Vec::new();
+            if params.hasher.scheme == HashScheme::DomainSeparated {
+                prefix.push(LEAF_PREFIX);
+            }
+            if params.positional {
+                prefix.extend_from_slice(&(index as u64).to_le_bytes());
+            }
+            let limit = options
+                .max_leaf_size
+                .map_or(u64::MAX, |max| max.saturating_add(1));
+            let (hash, size) = params
+                .hasher
+                .algorithm
+                .digest_reader(&prefix, reader.take(limit))
+                .map_err(IngestError::Io)?;
+            check_leaf_size(index, size, options.max_leaf_size).map_err(IngestError::Build)?;
+            metrics.record_leaf_hash();
+            leaf_hashes.push(hash);
+            if let Some(progress) = progress {
+                progress(&ProgressEvent::LeafHashed { index, total });
             }
-            level_nodes = next_level;
         }
 
-        Some(proof)
+        let mut order = None;
+        if options.sort_leaves && !params.positional {
+            let mut permutation: Vec<usize> = (0..total).collect();
+            // A stable sort keeps duplicate leaves in their original order
+            permutation.sort_by(|&a, &b| leaf_hashes[a].cmp(&leaf_hashes[b]));
+            leaf_hashes = permute(leaf_hashes, &permutation);
+            order = Some(permutation);
+        }
+        let data = vec![LeafData::None; total];
+        Self::assemble(data, leaf_hashes, params, options, metrics, order, progress)
+            .map_err(IngestError::Build)
     }
 
-    /// Verify a proof against the root hash
+    /// Build the levels over hashed leaves and assemble the tree
     ///
     /// # Arguments
     ///
-    /// * `data` - The data to verify
-    /// * `proof` - The proof to verify
-    /// * `root_hash` - The root hash to verify against
+    /// * `data` - The data of each leaf, in tree order
+    /// * `leaf_hashes` - The hash of each leaf, in tree order
+    /// * `params` - The parameters used to hash leaves and branches
+    /// * `options` - The construction options
+    /// * `metrics` - The counter the leaves were hashed with
+    /// * `order` - The original index of each leaf, if the leaves were sorted
+    /// * `progress` - The callback to report progress to, if any
     ///
     /// # Returns
     ///
-    /// True if the proof is valid, false otherwise
-    pub fn verify_proof(data: &[u8], proof: &MerkleProof, root_hash: &[u8]) -> bool {
-        let mut current_hash = Sha256::digest(data).to_vec();
+    /// The tree, or `MerkleError::DuplicateLeaf` or `MerkleError::UnsortedLeaf` if the
+    /// options reject the leaves
+    fn assemble(
+        data: Vec<LeafData>,
+        leaf_hashes: Vec<Vec<u8>>,
+        params: TreeParams,
+        options: BuildOptions,
+        metrics: HashCounter,
+        order: Option<Vec<usize>>,
+        progress: Option<&dyn Fn(&ProgressEvent)>,
+    ) -> Result<Self, MerkleError> {
+        if options.reject_duplicates {
+            check_unique(&leaf_hashes)?;
+        }
+        if options.sorted_unique {
+            check_sorted_unique(&leaf_hashes)?;
+        }
+        if options.empty_root.is_ambiguous(params.hasher.scheme) {
+            return Err(MerkleError::AmbiguousEmptyRoot);
+        }
+        let counting = metrics.counting(&params.hasher);
+        let levels = match options {
+            // Memoizing is inherently sequential, so it takes precedence
+            BuildOptions {
+                memoize_pairs: true,
+                ..
+            } => MerkleTree::build_levels(
+                leaf_hashes,
+                params.padding,
+                &MemoizingHasher::new(&counting),
+                progress,
+            ),
+            #[cfg(feature = "parallel")]
+            BuildOptions { parallel: true, .. } => MerkleTree::build_levels(
+                leaf_hashes,
+                params.padding,
+                &ParallelHasher::new(&counting),
+                progress,
+            ),
+            _ => MerkleTree::build_levels(leaf_hashes, params.padding, &counting, progress),
+        };
 
-        for (sibling_hash, is_left) in proof {
-            let mut hasher = Sha256::new();
+        let mut data = data;
+        let pool = options.dedup_storage.then(|| {
+            let mut pool = LeafPool::default();
+            for leaf in &mut data {
+                *leaf = pool.intern(std::mem::take(leaf));
+            }
+            pool
+        });
+        let mut tree = MerkleTree::from_parts(data, levels, params);
+        tree.metrics = metrics;
+        tree.empty_root = options.empty_root;
+        tree.order = order.map(SharedVec::from);
+        tree.sorted_unique = options.sorted_unique;
+        tree.epochs = EpochHistory::new(options.epoch_history.unwrap_or(DEFAULT_EPOCH_HISTORY));
+        tree.proof_cache = ProofCache::new(options.proof_cache);
+        tree.pool = pool;
+        if options.operation_log {
+            tree.log = Some(OperationLog::new(&tree, &options));
+        }
+        Ok(tree)
+    }
 
-            if *is_left {
-                hasher.update(sibling_hash);
-                hasher.update(&current_hash);
-            } else {
-                hasher.update(&current_hash);
-                hasher.update(sibling_hash);
+    /// Build every level of the tree from the leaf hashes
+    ///
+    /// A single leaf is its own root. Otherwise an odd number of leaves is padded by
+    /// duplicating the last one (unless the padding strategy is `CarryUp`), and an
+    /// unpaired node on a higher level is carried up unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hashes` - The hashes of the leaves, excluding padding
+    /// * `padding` - How an odd leaf level is padded
+    /// * `hasher` - The configuration used to hash branches
+    /// * `progress` - The callback to report each completed level to, if any
+    ///
+    /// # Returns
+    ///
+    /// The hashes of each level from the leaves up to the root (empty for no leaves)
+    fn build_levels(
+        mut leaf_hashes: Vec<Vec<u8>>,
+        padding: PaddingStrategy,
+        hasher: &impl NodeHasher,
+        progress: Option<&dyn Fn(&ProgressEvent)>,
+    ) -> Vec<Vec<Vec<u8>>> {
+        if leaf_hashes.is_empty() {
+            return Vec::new();
+        }
+
+        // If odd number of leaves, duplicate the last one
+        if padding.duplicates_last(leaf_hashes.len()) {
+            leaf_hashes.push(leaf_hashes.last().unwrap().clone());
+        }
+
+        let report = |level: usize, nodes: &Vec<Vec<u8>>| {
+            if let Some(progress) = progress {
+                progress(&ProgressEvent::LevelCompleted {
+                    level,
+                    nodes: nodes.len(),
+                });
+            }
+        };
+
+        // Complete trees pad every odd level below the root with an empty subtree
+        let (depth, empty) = match padding {
+            PaddingStrategy::Complete { depth } => {
+                (usize::from(depth), empty_subtrees(hasher, depth))
             }
+            _ => (0, Vec::new()),
+        };
+        let pad = |level: usize, nodes: &mut Vec<Vec<u8>>| {
+            if level < depth && nodes.len() % 2 == 1 {
+                nodes.push(empty[level].clone());
+            }
+        };
 
-            current_hash = hasher.finalize().to_vec();
+        pad(0, &mut leaf_hashes);
+        report(0, &leaf_hashes);
+        // The padded leaf count rounds up to a power of two with one level per doubling
+        let height = leaf_hashes.len().next_power_of_two().trailing_zeros() as usize + 1;
+        let mut levels = Vec::with_capacity(height.max(depth + 1));
+        levels.push(leaf_hashes);
+        while levels.last().unwrap().len() > 1 || levels.len() <= depth {
+            // Unpaired nodes are carried up to the next level
+            let mut next_level = hasher.hash_level(levels.last().unwrap());
+            pad(levels.len(), &mut next_level);
+            report(levels.len(), &next_level);
+            levels.push(next_level);
         }
 
-        current_hash == root_hash
+        levels
     }
 
-    /// Get the number of leaves in the tree
+    /// Get the root hash of the tree
     ///
     /// # Returns
     ///
-    /// The number of leaf nodes in the tree
-    pub fn len(&self) -> usize {
-        self.leaves.len()
+    /// The root hash of the tree, or the root given by the tree's `EmptyRootPolicy` if
+    /// the tree is empty (None by default)
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        match (self.levels.last(), self.params.padding) {
+            (Some(level), _) => Some(level[0].clone()),
+            (None, PaddingStrategy::Complete { depth }) => {
+                empty_subtrees(&self.params.hasher, depth).pop()
+            }
+            (None, _) => self.empty_root.root(&self.params.hasher),
+        }
     }
 
-    /// Check if the tree is empty
+    /// Borrow the root hash of the tree
+    ///
+    /// Unlike `root_hash` this does not copy the root, but an empty tree has no stored
+    /// root to borrow, even when its `EmptyRootPolicy` or padding defines one.
     ///
     /// # Returns
     ///
-    /// True if the tree has no nodes, false otherwise
-    pub fn is_empty(&self) -> bool {
-        self.leaves.is_empty()
+    /// The root hash, or None if the tree is empty
+    pub fn root_hash_ref(&self) -> Option<&[u8]> {
+        Some(&self.levels.last()?[0])
     }
 
-    /// Print the tree structure for debugging
+    /// Get the policy deciding the root of an empty tree
     ///
-    /// This function prints the tree structure to standard output.
-    pub fn print_tree(&self) {
-        if let Some(root) = &self.root {
-            println!("Merkle Tree Structure:");
-            Self::print_node(root, 0);
-        } else {
-            println!("Empty tree");
+    /// # Returns
+    ///
+    /// The empty root policy the tree was built with
+    pub fn empty_root_policy(&self) -> EmptyRootPolicy {
+        self.empty_root
+    }
+
+    /// Get the root hash of the tree as a hexadecimal string
+    ///
+    /// See `root_hex_display` for formatting the root without allocating a string.
+    ///
+    /// # Returns
+    ///
+    /// The root hash as a hexadecimal string, or "Empty tree" if the tree is empty and
+    /// has no root
+    pub fn root_hash_hex(&self) -> String {
+        self.root_hex_display().to_string()
+    }
+
+    /// Get the root hash of the tree as a standard padded base64 string
+    ///
+    /// # Returns
+    ///
+    /// The root hash in base64, or "Empty tree" if the tree is empty and has no root
+    pub fn root_hash_base64(&self) -> String {
+        match self.root_hash() {
+            Some(hash) => BASE64.encode(hash),
+            None => String::from("Empty tree"),
         }
     }
 
-    /// Helper function to print a node and its children recursively
+    /// Check the root against an expected root given either as raw bytes or as hex
+    ///
+    /// Input exactly as long as the tree's digest is taken as the raw root. Otherwise it
+    /// is taken as hexadecimal text of twice that length, in either case and optionally
+    /// prefixed with `0x`. Anything else, such as 64 raw bytes for a SHA-256 tree that
+    /// are not valid hex, does not match. Use `root_matches_bytes` or `root_matches_hex`
+    /// to avoid the guessing.
     ///
     /// # Arguments
     ///
-    /// * `node` - The node to print
-    /// * `indent` - The indentation level (for pretty-printing)
-    fn print_node(node: &MerkleNode, indent: usize) {
-        let indent_str = " ".repeat(indent * 2);
-
-        match node {
-            MerkleNode::Leaf { data, hash } => {
-                println!(
-                    "{}Leaf: data={:?}, hash={}",
-                    indent_str,
-                    String::from_utf8_lossy(data),
-                    hex::encode(&hash[0..4])
-                ); // Print just the start of the hash
-            }
-            MerkleNode::Branch { left, right, hash } => {
-                println!("{}Branch: hash={}", indent_str, hex::encode(&hash[0..4]));
-                Self::print_node(left, indent + 1);
-                Self::print_node(right, indent + 1);
-            }
+    /// * `expected` - The expected root, raw or hex-encoded
+    ///
+    /// # Returns
+    ///
+    /// True if the tree has a root and it equals the expected one
+    pub fn root_matches(&self, expected: impl AsRef<[u8]>) -> bool {
+        let expected = expected.as_ref();
+        if expected.len() == self.params.hasher.digest_len() {
+            return self.root_matches_bytes(expected);
         }
+        std::str::from_utf8(expected).is_ok_and(|hex| self.root_matches_hex(hex))
+    }
+
+    /// Check the root against an expected raw root
+    ///
+    /// The bytes are compared in constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The expected root hash
+    ///
+    /// # Returns
+    ///
+    /// True if the tree has a root and it equals the expected one
+    pub fn root_matches_bytes(&self, expected: &[u8]) -> bool {
+        self.root_hash()
+            .is_some_and(|root| constant_time_eq(&root, expected))
+    }
+
+    /// Check the root against an expected hex-encoded root
+    ///
+    /// The hex may be in either case and may be prefixed with `0x`; the decoded bytes
+    /// are compared in constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The expected root hash as hexadecimal text
+    ///
+    /// # Returns
+    ///
+    /// True if the text is valid hex, the tree has a root, and it equals the expected one
+    pub fn root_matches_hex(&self, expected: &str) -> bool {
+        let digits = expected.strip_prefix("0x").unwrap_or(expected);
+        hex::decode(digits).is_ok_and(|bytes| self.root_matches_bytes(&bytes))
+    }
+
+    /// Compute the root a fresh tree over a contiguous range of the leaves would have
+    ///
+    /// The fresh tree has the same parameters and empty root policy as this one. Its leaf
+    /// hashes are reused from this tree, except in positional trees, where each leaf is
+    /// rehashed at its position within the range and must still hold its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The index of the first leaf in the range
+    /// * `end` - The index one past the last leaf in the range
+    ///
+    /// # Returns
+    ///
+    /// The root of the range, or None if the range is inverted or out of bounds, the
+    /// range is empty and the empty root policy gives no root, or a positional tree's
+    /// leaf data in the range was cleared
+    pub fn subrange_root(&self, start: usize, end: usize) -> Option<Vec<u8>> {
+        if start > end || end > self.leaf_count() {
+            return None;
+        }
+
+        let leaf_hashes = if self.params.positional {
+            (start..end)
+                .map(|index| Some(self.leaf_hash_at(index - start, self.get_leaf(index)?)))
+                .collect::<Option<Vec<_>>>()?
+        } else {
+            self.levels.first().map_or_else(Vec::new, |leaves| {
+                leaves.range(start..end).cloned().collect()
+            })
+        };
+        let counting = self.metrics.counting(&self.params.hasher);
+        match MerkleTree::build_levels(leaf_hashes, self.params.padding, &counting, None).pop() {
+            Some(mut root) => root.pop(),
+            None => match self.params.padding {
+                PaddingStrategy::Complete { depth } => {
+                    empty_subtrees(&self.params.hasher, depth).pop()
+                }
+                _ => self.empty_root.root(&self.params.hasher),
+            },
+        }
+    }
+
+    /// Get the number of levels in the tree, including the leaf level
+    ///
+    /// # Returns
+    ///
+    /// The number of levels (0 for an empty tree, 1 for a single leaf)
+    pub fn height(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Get the hash of the node at a specific coordinate
+    ///
+    /// Levels are numbered from the leaves (level 0) up to the root. On the leaf level
+    /// only real leaves are addressable, not the duplicate added for padding.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The node's hash, or None if there is no such node
+    pub(crate) fn node_hash_by_index(&self, level: usize, index: usize) -> Option<Vec<u8>> {
+        if level == 0 && index >= self.leaf_count() {
+            return None;
+        }
+        self.levels.get(level)?.get(index).cloned()
+    }
+
+    /// Get the hashes of every level, from the (padded) leaf level up to the root
+    ///
+    /// # Returns
+    ///
+    /// The levels (empty for an empty tree)
+    pub(crate) fn levels(&self) -> &[SharedVec<Vec<u8>>] {
+        &self.levels
+    }
+
+    /// Get mutable access to the observers, so they can be registered and removed
+    pub(crate) fn observers_mut(&mut self) -> &mut Observers {
+        &mut self.observers
+    }
+
+    /// Get mutable access to the stored hashes, so tests can corrupt them
+    #[cfg(test)]
+    pub(crate) fn levels_mut(&mut self) -> &mut Vec<SharedVec<Vec<u8>>> {
+        self.proof_cache.clear();
+        self.hash_index = OnceLock::new();
+        &mut self.levels
+    }
+
+    /// Get the hashes of the children of the node at a specific coordinate
+    ///
+    /// This lets a peer walk down the tree one level at a time during reconciliation.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (must be above the leaf level)
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The left child's hash and the right child's hash (None for a node carried up
+    /// unpaired from an odd-sized level), or None if there is no such branch
+    pub fn children_digest(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Option<(Vec<u8>, Option<Vec<u8>>)> {
+        if level == 0 || index >= self.levels.get(level)?.len() {
+            return None;
+        }
+        let children = &self.levels[level - 1];
+        Some((
+            children[index * 2].clone(),
+            children.get(index * 2 + 1).cloned(),
+        ))
+    }
+
+    /// Generate a proof for a specific data item
+    ///
+    /// A proof consists of a list of sibling hashes and their positions
+    /// (left or right) along the path from the leaf node to the root.
+    ///
+    /// If the data appears more than once, the proof is for its lowest index.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to generate a proof for
+    ///
+    /// # Returns
+    ///
+    /// A proof that the data exists in the tree, or None if the data is not found
+    pub fn generate_proof(&self, data: &[u8]) -> Option<MerkleProof> {
+        let index = self.index_of(data)?;
+        self.proof_by_index(index)
+    }
+
+    /// Generate a proof for the leaf at a specific index, bundled with the tree's
+    /// parameters and leaf count
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof bundle, or None if the index is out of range
+    pub fn generate_bundle(&self, index: usize) -> Option<ProofBundle> {
+        Some(ProofBundle {
+            version: self.version(),
+            params: self.params,
+            leaf_count: self.committed_leaf_count(),
+            index,
+            proof: self.proof_by_index(index)?,
+            unauthenticated_metadata: None,
+        })
+    }
+
+    /// Generate a proof bundle that also carries the leaf's metadata
+    ///
+    /// The metadata is not committed to by the root, so the bundle holds it in its
+    /// `unauthenticated_metadata` field, which verification ignores.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof bundle, or None if the index is out of range
+    pub fn generate_bundle_with_metadata(&self, index: usize) -> Option<ProofBundle> {
+        let mut bundle = self.generate_bundle(index)?;
+        bundle.unauthenticated_metadata = self.metadata(index).map(<[u8]>::to_vec);
+        Some(bundle)
+    }
+
+    /// Attach metadata to a leaf, replacing any it had
+    ///
+    /// Metadata is kept in a side table and does not affect any hash, so it is not
+    /// authenticated by proofs: whoever hands it over can change it freely.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `metadata` - The metadata, such as a timestamp or source id
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `MerkleError::IndexOutOfRange` if the index is out of range
+    pub fn attach_metadata(
+        &mut self,
+        index: usize,
+        metadata: impl Into<Vec<u8>>,
+    ) -> Result<(), MerkleError> {
+        if index >= self.leaf_count() {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+        self.metadata.insert(index, metadata.into());
+        Ok(())
+    }
+
+    /// Get the metadata attached to a leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaf's metadata, or None if it has none
+    pub fn metadata(&self, index: usize) -> Option<&[u8]> {
+        self.metadata.get(&index).map(Vec::as_slice)
+    }
+
+    /// Name the subtree below a node, replacing any name it had
+    ///
+    /// Labels are kept in a side table for debugging, such as naming the halves of the
+    /// leaf space different teams own; they are not hashed and do not affect any root.
+    /// They are shown by `print_tree`, `render_with` and the JSON structure export, which
+    /// also restores them, and `explain_mismatch` names the subtree a mismatch is inside.
+    /// A node carried up unpaired from an odd-sized level covers the same leaves as the
+    /// node it carries, so labeling either labels the lower one.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    /// * `name` - The name of the subtree
+    ///
+    /// # Returns
+    ///
+    /// Ok, or `MerkleError::NodeOutOfRange` if the tree has no such node
+    pub fn label_subtree(
+        &mut self,
+        level: usize,
+        index: usize,
+        name: impl Into<String>,
+    ) -> Result<(), MerkleError> {
+        let node = self
+            .drawn_node(level, index)
+            .ok_or(MerkleError::NodeOutOfRange { level, index })?;
+        self.labels.insert(node, name.into());
+        Ok(())
+    }
+
+    /// Get the name given to the subtree below a node
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The subtree's label, or None if it has none
+    pub fn subtree_label(&self, level: usize, index: usize) -> Option<&str> {
+        let node = self.drawn_node(level, index)?;
+        self.labels.get(&node).map(String::as_str)
+    }
+
+    /// Get the name of the smallest labeled subtree containing a node
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The label of the node or of its closest labeled ancestor, or None if neither the
+    /// node nor any of its ancestors is labeled
+    pub fn enclosing_label(&self, level: usize, index: usize) -> Option<&str> {
+        (level..self.levels.len())
+            .find_map(|ancestor| self.subtree_label(ancestor, index >> (ancestor - level)))
+    }
+
+    /// Find the node drawn for the subtree below a node
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The `(level, index)` of the node, or of the node it was carried up from, or None
+    /// if the tree has no such node
+    fn drawn_node(&self, mut level: usize, mut index: usize) -> Option<(usize, usize)> {
+        if index >= self.levels.get(level)?.len() || (level == 0 && index >= self.leaf_count()) {
+            return None;
+        }
+        while level > 0 && index * 2 + 1 >= self.levels[level - 1].len() {
+            level -= 1;
+            index *= 2;
+        }
+        Some((level, index))
+    }
+
+    /// Generate a proof for a leaf identified by its hash
+    ///
+    /// This lets a proof service look up a leaf without receiving its data. The leaf hash
+    /// is the value stored on the leaf level (see `node_hash_at`), so for a
+    /// positional tree it already commits to the leaf's index. If the hash appears more
+    /// than once, the proof is for its lowest index. The lookup takes logarithmic time in
+    /// trees built with `MerkleTreeBuilder::leaves_sorted_unique`. Other trees keep a map
+    /// from each leaf hash to the lowest index holding it, built by the first lookup in
+    /// O(n) and kept up to date by `push` and `update_leaf`, so lookups take expected
+    /// constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hash` - The hash of the leaf to generate a proof for
+    ///
+    /// # Returns
+    ///
+    /// A proof that the leaf exists in the tree, or None if no leaf has the hash
+    pub fn generate_proof_for_hash(&self, leaf_hash: &[u8]) -> Option<MerkleProof> {
+        self.proof_by_index(self.index_of_hash(leaf_hash)?)
+    }
+
+    /// Find the index of a leaf by its hash
+    ///
+    /// Trees whose leaf hashes are strictly increasing are binary searched, taking
+    /// O(log n) comparisons; other trees use the hash index, taking expected O(1) time.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hash` - The hash stored on the leaf level
+    ///
+    /// # Returns
+    ///
+    /// The lowest index with the hash, or None if no leaf has it
+    pub(crate) fn index_of_hash(&self, leaf_hash: &[u8]) -> Option<usize> {
+        let leaves = self.levels.first()?;
+        if self.sorted_unique {
+            return leaves
+                .binary_search_by(|hash| hash.as_slice().cmp(leaf_hash))
+                .ok()
+                .filter(|&index| index < self.leaf_count());
+        }
+        self.hash_index
+            .get_or_init(|| HashIndex::new(leaves, self.leaf_count()))
+            .get(leaf_hash)
+    }
+
+    /// Generate a proof for a typed item
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to generate a proof for
+    ///
+    /// # Returns
+    ///
+    /// A proof for the item's lowest index, or None if the tree was not built with the
+    /// encoder `E` or the item is not found (as an item that cannot be encoded is not)
+    pub fn generate_proof_for<E: LeafEncoder>(&self, item: &E::Item) -> Option<MerkleProof> {
+        if self.encoder != Some(E::ID) {
+            return None;
+        }
+        self.generate_proof(&E::encode(item).ok()?)
+    }
+
+    /// Generate a proof for a leaf of a positional tree, checking its data
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The data expected at that index
+    ///
+    /// # Returns
+    ///
+    /// A proof for the leaf, or None if the index is out of range or does not hold the data
+    pub fn generate_proof_positional(&self, index: usize, data: &[u8]) -> Option<MerkleProof> {
+        if index >= self.leaf_count() || self.leaf_hash_at(index, data) != self.levels[0][index] {
+            return None;
+        }
+        self.proof_by_index(index)
+    }
+
+    /// Generate a proof for a string
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The string to generate a proof for
+    ///
+    /// # Returns
+    ///
+    /// A proof for the string's UTF-8 bytes, or None if they are not found
+    pub fn generate_proof_str(&self, s: &str) -> Option<MerkleProof> {
+        self.generate_proof(s.as_bytes())
+    }
+
+    /// Get the identity of the encoder the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The encoder's `LeafEncoder::ID`, or None if the tree was built from raw data
+    pub fn encoder_id(&self) -> Option<&'static str> {
+        self.encoder
+    }
+
+    /// Generate a proof for the leaf at a specific index
+    ///
+    /// The proof has one step for every level at which the leaf's ancestor has a sibling.
+    /// With an even number of leaves on every level this is the tree's depth; a node
+    /// carried up unpaired from an odd-sized upper level contributes no step. The
+    /// duplicate added to pad the leaf level is not a real leaf and cannot be proven.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf, as supplied at construction
+    ///
+    /// # Returns
+    ///
+    /// A proof for the leaf, or None if the index is out of range
+    pub(crate) fn proof_by_index(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        Some(
+            self.proof_cache
+                .get_or_insert(index, || self.path_to_root(0, index)),
+        )
+    }
+
+    /// Generate a proof for a leaf, failing if its encoding would be too large
+    ///
+    /// The size is computed from the proof's steps, so nothing is encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `encoding` - The encoding the proof will be sent in
+    /// * `max_bytes` - The largest encoded size allowed
+    ///
+    /// # Returns
+    ///
+    /// The proof, `MerkleError::IndexOutOfRange` if the index is out of range, or
+    /// `MerkleError::ProofTooLarge` if the encoded proof would exceed `max_bytes`
+    pub fn generate_proof_bounded(
+        &self,
+        index: usize,
+        encoding: ProofEncoding,
+        max_bytes: usize,
+    ) -> Result<MerkleProof, MerkleError> {
+        let proof = self
+            .proof_by_index(index)
+            .ok_or(MerkleError::IndexOutOfRange {
+                index,
+                leaf_count: self.leaf_count(),
+            })?;
+        let size = proof.serialized_size(encoding);
+        if size > max_bytes {
+            return Err(MerkleError::ProofTooLarge {
+                size,
+                max: max_bytes,
+            });
+        }
+        Ok(proof)
+    }
+
+    /// Compute the largest encoded size of a proof for any leaf of the tree
+    ///
+    /// The first leaf has a sibling on every level, so its proof has the most steps.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The encoding
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes that no proof from this tree exceeds
+    pub fn max_proof_size(&self, encoding: ProofEncoding) -> usize {
+        let steps = self.height().saturating_sub(1);
+        encoding.max_size(steps, self.params.hasher.digest_len())
+    }
+
+    /// Generate a proof for the internal node at a specific coordinate
+    ///
+    /// The proof authenticates the root of the subtree at `(level, index)` up to the tree's
+    /// root, so a whole aligned block of `2^level` leaves can be shown to be included by
+    /// rebuilding the subtree locally and checking its root with `verify_subtree_proof`.
+    /// At level 0 this is the same as `generate_proof_at`, and the root itself has
+    /// an empty proof.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the subtree's root (0 for leaves)
+    /// * `index` - The index of the subtree's root within its level
+    ///
+    /// # Returns
+    ///
+    /// A proof for the subtree's root, or None if there is no such node
+    pub fn generate_subtree_proof(&self, level: usize, index: usize) -> Option<MerkleProof> {
+        self.node_hash_by_index(level, index)?;
+        Some(self.path_to_root(level, index))
+    }
+
+    /// Collect the sibling hashes on the path from a node up to the root
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the starting node
+    /// * `index` - The index of the starting node within its level
+    ///
+    /// # Returns
+    ///
+    /// The proof steps from the node to the root
+    fn path_to_root(&self, level: usize, index: usize) -> MerkleProof {
+        path_in_levels(&self.levels, level, index, self.params.hasher.ordering)
+    }
+
+    /// Generate a single proof for several leaves at once
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The indices of the leaves to prove, in any order
+    ///
+    /// # Returns
+    ///
+    /// A multiproof for the leaves (with their indices sorted and deduplicated), or None if
+    /// no index was given or any index is out of range
+    pub fn generate_multiproof(&self, indices: &[usize]) -> Option<MultiProof> {
+        let mut proven = indices.to_vec();
+        proven.sort_unstable();
+        proven.dedup();
+        if proven.is_empty() || *proven.last().unwrap() >= self.leaf_count() {
+            return None;
+        }
+
+        Some(MultiProof {
+            leaf_count: self.committed_leaf_count(),
+            padding: self.params.padding,
+            hashes: self
+                .multiproof_siblings(&proven)
+                .into_iter()
+                .map(|(level, index)| self.levels[level][index].clone())
+                .collect(),
+            indices: proven,
+        })
+    }
+
+    /// Generate a multiproof for as many of the requested leaves as fit in a byte budget
+    ///
+    /// Leaves are added greedily: each round adds the requested leaf that grows the
+    /// encoded multiproof least, which favors leaves whose paths share siblings with the
+    /// leaves already added (a leaf whose sibling is already proven even removes a hash).
+    /// Ties go to the lowest index. Selection stops when no remaining leaf fits. Sizes
+    /// are computed exactly as `MultiProof::serialized_size` does, without building or
+    /// encoding the candidates, so the cost is O(k² log n) lookups for k requested
+    /// leaves.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The indices of the requested leaves, in any order; duplicates and
+    ///   indices out of range are ignored
+    /// * `max_bytes` - The largest encoded size allowed
+    /// * `encoding` - The encoding the multiproof will be sent in
+    ///
+    /// # Returns
+    ///
+    /// The multiproof and the indices it proves, in ascending order. If not even one leaf
+    /// fits, both are empty: the multiproof has no indices and no hashes.
+    pub fn generate_multiproof_bounded(
+        &self,
+        indices: &[usize],
+        max_bytes: usize,
+        encoding: ProofEncoding,
+    ) -> (MultiProof, Vec<usize>) {
+        let mut remaining: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&index| index < self.leaf_count())
+            .collect();
+        remaining.sort_unstable();
+        remaining.dedup();
+
+        // The nodes computable from the chosen leaves, and the siblings they still need
+        let top = self.levels.len().saturating_sub(1);
+        let mut known = HashSet::new();
+        let mut needed = HashSet::new();
+        let mut size = EncodedSize::default();
+        let mut chosen = Vec::new();
+        let total =
+            |size: &EncodedSize| size.total(self.leaf_count(), self.params.padding, encoding);
+
+        // Walk a leaf's path until it meets a known node, reporting each node added
+        let path = |index: usize, known: &HashSet<(usize, usize)>| -> Vec<(usize, usize)> {
+            (0..top)
+                .map(|level| (level, index >> level))
+                .take_while(|node| !known.contains(node))
+                .collect()
+        };
+        let grown = |mut size: EncodedSize, index, known: &HashSet<_>, needed: &HashSet<_>| {
+            size.add_index(index);
+            for (level, node) in path(index, known) {
+                let sibling = node ^ 1;
+                if needed.contains(&(level, node)) {
+                    size.remove_hash(&self.levels[level][node]);
+                } else if let Some(hash) = self.levels[level].get(sibling) {
+                    size.add_hash(hash);
+                }
+            }
+            size
+        };
+
+        while !remaining.is_empty() {
+            let (position, best) = remaining
+                .iter()
+                .map(|&index| grown(size, index, &known, &needed))
+                .enumerate()
+                .min_by_key(|(_, size)| total(size))
+                .unwrap();
+            if total(&best) > max_bytes {
+                break;
+            }
+            let index = remaining.remove(position);
+            for (level, node) in path(index, &known) {
+                if !needed.remove(&(level, node)) && node ^ 1 < self.levels[level].len() {
+                    needed.insert((level, node ^ 1));
+                }
+                known.insert((level, node));
+            }
+            size = best;
+            chosen.push(index);
+        }
+
+        chosen.sort_unstable();
+        let proof = self
+            .generate_multiproof(&chosen)
+            .unwrap_or_else(|| MultiProof {
+                leaf_count: self.committed_leaf_count(),
+                padding: self.params.padding,
+                indices: Vec::new(),
+                hashes: Vec::new(),
+            });
+        (proof, chosen)
+    }
+
+    /// Find the sibling nodes needed to authenticate several leaves at once
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The indices of the leaves, sorted, deduplicated and in range
+    ///
+    /// # Returns
+    ///
+    /// The `(level, index)` coordinates of the siblings that cannot be computed from the
+    /// leaves, level by level from the leaves up and left to right
+    fn multiproof_siblings(&self, indices: &[usize]) -> Vec<(usize, usize)> {
+        let mut known = indices.to_vec();
+        let mut siblings = Vec::new();
+        for (level, nodes) in self.levels[..self.levels.len() - 1].iter().enumerate() {
+            let mut next = Vec::with_capacity(known.len());
+            let mut i = 0;
+            while i < known.len() {
+                let index = known[i];
+                let sibling = index ^ 1;
+                if sibling < nodes.len() {
+                    if index.is_multiple_of(2) && known.get(i + 1) == Some(&sibling) {
+                        // Both children are known, so no hash is needed
+                        i += 1;
+                    } else {
+                        siblings.push((level, sibling));
+                    }
+                }
+                next.push(index / 2);
+                i += 1;
+            }
+            known = next;
+        }
+
+        siblings
+    }
+
+    /// Keep only what is needed to prove a watch-set of leaves
+    ///
+    /// The pruned tree holds the hashes of the kept leaves, the root, and the minimal set
+    /// of sibling hashes from which every kept leaf's authentication path can be rebuilt,
+    /// so its size grows with `keep.len() * log n` rather than with the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - The indices of the leaves to keep, in any order; indices past the last
+    ///   leaf are ignored
+    ///
+    /// # Returns
+    ///
+    /// The pruned tree
+    pub fn prune(&self, keep: &[usize]) -> PrunedMerkleTree {
+        let mut kept: Vec<usize> = keep
+            .iter()
+            .copied()
+            .filter(|&index| index < self.leaf_count())
+            .collect();
+        kept.sort_unstable();
+        kept.dedup();
+        let siblings = self.multiproof_siblings(&kept);
+        let nodes = kept
+            .iter()
+            .map(|&index| (0, index))
+            .chain(siblings)
+            .map(|(level, index)| (level, index, self.levels[level][index].clone()))
+            .collect();
+
+        PrunedMerkleTree::new(
+            self.root_hash(),
+            self.committed_leaf_count(),
+            self.params.hasher,
+            self.params.padding,
+            kept,
+            nodes,
+        )
+    }
+
+    /// Split the tree into aligned shards of `shard_leaves` leaves each
+    ///
+    /// Shard `j` holds leaves `[j * shard_leaves, (j + 1) * shard_leaves)`, and its root
+    /// equals the node covering that block in this tree, so `combine_roots_with` turns the
+    /// shard roots back into this tree's root. The final shard may be partial. When it
+    /// holds a single leaf of a `DuplicateLast` tree with more than one leaf, the shard
+    /// keeps the duplicate this tree pads the leaf with, as padding rather than a second
+    /// leaf: its root and proofs are those of the subtree it covers, and it checks them
+    /// as a tree of two leaves. Rebuilding such a shard, as `rehash_with` does, gives an
+    /// ordinary tree of one leaf. Leaf hashes are copied rather than recomputed, so trees
+    /// whose data was cleared can be split too.
+    /// Trees of positional leaves cannot be split, since their leaf hashes commit to
+    /// their index in this tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `shard_leaves` - The number of leaves per shard, a power of two of at least 2
+    ///
+    /// # Returns
+    ///
+    /// The shards in order (none for an empty tree), an error if the shard size is
+    /// invalid, or `MerkleError::PositionalSplit` for a tree of positional leaves
+    pub fn split(&self, shard_leaves: usize) -> Result<Vec<MerkleTree>, MerkleError> {
+        if shard_leaves < 2 || !shard_leaves.is_power_of_two() {
+            return Err(MerkleError::InvalidShardSize { shard_leaves });
+        }
+        if self.params.positional {
+            return Err(MerkleError::PositionalSplit);
+        }
+
+        let leaf_count = self.leaf_count();
+        let shards = (0..leaf_count)
+            .step_by(shard_leaves)
+            .map(|start| {
+                let end = (start + shard_leaves).min(leaf_count);
+                let data: Vec<LeafData> = self.data.range(start..end).cloned().collect();
+                let mut leaf_hashes: Vec<Vec<u8>> =
+                    self.levels[0].range(start..end).cloned().collect();
+                let order = self
+                    .order
+                    .as_ref()
+                    .map(|order| order.range(start..end).copied().collect());
+                let padding = match self.params.padding {
+                    // A complete tree's shards are complete trees of the shard size
+                    PaddingStrategy::Complete { depth } => PaddingStrategy::Complete {
+                        depth: depth.min(shard_leaves.trailing_zeros() as u8),
+                    },
+                    padding => padding,
+                };
+                // A lone final leaf is still paired with its duplicate
+                if padding.duplicates_last(leaf_count) && data.len() == 1 {
+                    leaf_hashes.push(leaf_hashes[0].clone());
+                }
+                let params = TreeParams {
+                    padding,
+                    ..self.params
+                };
+                let levels =
+                    MerkleTree::build_levels(leaf_hashes, params.padding, &params.hasher, None);
+                let mut tree = MerkleTree::from_parts(data, levels, params);
+                tree.encoder = self.encoder;
+                tree.empty_root = self.empty_root;
+                tree.order = order;
+                tree.sorted_unique = self.sorted_unique;
+                tree.epochs = EpochHistory::new(self.epochs.capacity());
+                tree.metadata = self
+                    .metadata
+                    .range(start..end)
+                    .map(|(index, metadata)| (index - start, metadata.clone()))
+                    .collect();
+                tree.proof_cache = ProofCache::new(self.proof_cache.capacity());
+                tree
+            })
+            .collect();
+
+        Ok(shards)
+    }
+
+    /// Rebuild the tree under new parameters, for migrating to a new construction
+    ///
+    /// The leaves are rehashed from the stored data, in the tree's current order. When
+    /// the new parameters hash leaves exactly as the old ones (the same algorithm, scheme
+    /// and positional setting) the stored leaf hashes are reused, so trees without leaf
+    /// data can still change, for example, their pair ordering or padding. Leaf data,
+    /// metadata and options such as the empty root policy carry over; the new tree starts
+    /// at epoch 0. Subtree labels carry over only when every level keeps its size, since
+    /// they name nodes by position. The original tree is untouched, so both roots can be
+    /// published while clients migrate (see `migration_roots`).
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or hash configuration or scheme) to rebuild with
+    ///
+    /// # Returns
+    ///
+    /// The rebuilt tree, `MerkleError::LeafDataUnavailable` if a leaf must be rehashed but
+    /// its data is not stored, `MerkleError::TooManyLeaves` or
+    /// `MerkleError::DepthTooLarge` if the leaves do not fit the new padding, or
+    /// `MerkleError::AmbiguousEmptyRoot` if the empty root policy does not suit the new
+    /// scheme
+    pub fn rehash_with(&self, params: impl Into<TreeParams>) -> Result<MerkleTree, MerkleError> {
+        let params = params.into();
+        let leaf_count = self.leaf_count();
+        check_capacity(leaf_count, params.padding)?;
+        if self.empty_root.is_ambiguous(params.hasher.scheme) {
+            return Err(MerkleError::AmbiguousEmptyRoot);
+        }
+        let metrics = HashCounter::default();
+        let counting = metrics.counting(&params.hasher);
+
+        let same_leaves = params.hasher.algorithm == self.params.hasher.algorithm
+            && params.hasher.scheme == self.params.hasher.scheme
+            && params.positional == self.params.positional;
+        let leaf_hashes = if same_leaves {
+            self.levels
+                .first()
+                .map(|leaves| leaves.range(0..leaf_count).cloned().collect())
+                .unwrap_or_default()
+        } else {
+            self.data
+                .iter()
+                .enumerate()
+                .map(|(index, data)| {
+                    let data = data
+                        .as_deref()
+                        .ok_or(MerkleError::LeafDataUnavailable { index })?;
+                    Ok(if params.positional {
+                        counting.hash_leaf(&positional_leaf(index, data))
+                    } else {
+                        counting.hash_leaf(data)
+                    })
+                })
+                .collect::<Result<Vec<_>, MerkleError>>()?
+        };
+        let sorted_unique =
+            self.sorted_unique && leaf_hashes.windows(2).all(|pair| pair[0] < pair[1]);
+
+        let levels = MerkleTree::build_levels(leaf_hashes, params.padding, &counting, None);
+        let same_shape = levels
+            .iter()
+            .map(Vec::len)
+            .eq(self.levels.iter().map(SharedVec::len));
+        let mut tree = MerkleTree::from_parts(Vec::new(), levels, params);
+        tree.data = self.data.clone();
+        tree.metrics = metrics;
+        tree.encoder = self.encoder;
+        tree.empty_root = self.empty_root;
+        tree.order = self.order.clone();
+        tree.sorted_unique = sorted_unique;
+        tree.epochs = EpochHistory::new(self.epochs.capacity());
+        tree.metadata = self.metadata.clone();
+        if same_shape {
+            tree.labels = self.labels.clone();
+        }
+        tree.proof_cache = ProofCache::new(self.proof_cache.capacity());
+        tree.pool = self.pool.clone();
+        Ok(tree)
+    }
+
+    /// Compute the tree's root under its current and under new parameters
+    ///
+    /// This is `rehash_with` for services that dual-publish both roots during a
+    /// migration and have no use for the rebuilt tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or hash configuration or scheme) being migrated to
+    ///
+    /// # Returns
+    ///
+    /// The current root and the root under `params` (None for an empty tree without an
+    /// empty root), or the error `rehash_with` gives
+    pub fn migration_roots(
+        &self,
+        params: impl Into<TreeParams>,
+    ) -> Result<MigrationRoots, MerkleError> {
+        Ok((self.root_hash(), self.rehash_with(params)?.root_hash()))
+    }
+
+    /// Combine the roots of a tree's shards into the tree's root
+    ///
+    /// This uses `HashScheme::Plain` hashing, matching trees built by `MerkleTree::new`.
+    ///
+    /// # Arguments
+    ///
+    /// * `shard_roots` - The roots of the shards returned by `split`, in order
+    ///
+    /// # Returns
+    ///
+    /// The root of the original tree, or None if there are no shards
+    pub fn combine_roots(shard_roots: &[Vec<u8>]) -> Option<Vec<u8>> {
+        Self::combine_roots_with(HashScheme::Plain, shard_roots)
+    }
+
+    /// Combine the roots of a tree's shards into the tree's root using the given hash
+    /// configuration
+    ///
+    /// Shard roots are paired left to right and an unpaired root is carried up, exactly
+    /// as the nodes above the shards are computed in the original tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees) the
+    ///   tree was built with
+    /// * `shard_roots` - The roots of the shards returned by `split`, in order
+    ///
+    /// # Returns
+    ///
+    /// The root of the original tree, or None if there are no shards
+    pub fn combine_roots_with(
+        hasher: impl Into<HashConfig>,
+        shard_roots: &[Vec<u8>],
+    ) -> Option<Vec<u8>> {
+        let hasher = hasher.into();
+        let mut level = shard_roots.to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|chunk| match chunk {
+                    [left, right] => hasher.hash_pair(left, right),
+                    _ => chunk[0].clone(),
+                })
+                .collect();
+        }
+        level.pop()
+    }
+
+    /// Find the index of a data item in the tree
+    ///
+    /// Trees built with `MerkleTreeBuilder::leaves_sorted_unique` are searched in
+    /// logarithmic time, and other trees in expected constant time (see
+    /// `generate_proof_for_hash`). Positional trees hash the data differently at each
+    /// index, so their leaves are scanned in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to look for
+    ///
+    /// # Returns
+    ///
+    /// The lowest index holding the data, or None if the data is not found
+    pub fn index_of(&self, data: &[u8]) -> Option<usize> {
+        if self.params.positional {
+            // Every position hashes the data differently, so each must be tried
+            return (0..self.leaf_count())
+                .find(|&index| self.leaf_hash_at(index, data) == self.levels[0][index]);
+        }
+
+        let target_hash = self.metrics.counting(&self.params.hasher).hash_leaf(data);
+        self.index_of_hash(&target_hash)
+    }
+
+    /// Verify a proof against this tree's root hash
+    ///
+    /// Uses the tree's hash scheme and checks the proof's length against the tree's
+    /// leaf count (see `verify_proof_with`).
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid for this tree, false otherwise
+    pub fn verify(&self, data: &[u8], proof: &MerkleProof) -> bool {
+        match self.root_hash() {
+            Some(root_hash) => {
+                self.verify_against(data, proof, &root_hash, self.committed_leaf_count())
+            }
+            None => false,
+        }
+    }
+
+    /// Verify a proof against a root of this tree with a given number of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root the proof should lead to
+    /// * `leaf_count` - The number of leaves the root committed to
+    ///
+    /// # Returns
+    ///
+    /// True if the proof leads from the data to the root, false otherwise
+    pub(crate) fn verify_against(
+        &self,
+        data: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        leaf_count: usize,
+    ) -> bool {
+        if !proof_fits_shape(
+            proof,
+            leaf_count,
+            self.params.padding,
+            self.params.hasher.ordering,
+        ) || !siblings_fit(proof, self.params.hasher.digest_len())
+        {
+            return false;
+        }
+
+        let leaf_hash = if self.params.positional {
+            // The leaf's index is read from the proof's directions
+            match (
+                self.params.hasher.ordering,
+                proof_leaf_index(proof, leaf_count, self.params.padding),
+            ) {
+                (PairOrdering::Positional, Some(index)) => self.leaf_hash_at(index, data),
+                _ => return false,
+            }
+        } else {
+            self.metrics.counting(&self.params.hasher).hash_leaf(data)
+        };
+        fold_proof(
+            &self.metrics.counting(&self.params.hasher),
+            leaf_hash,
+            proof,
+        ) == root_hash
+    }
+
+    /// Get the current epoch
+    ///
+    /// A new tree is at epoch 0, and every `push` or `update_leaf` starts a new epoch.
+    ///
+    /// # Returns
+    ///
+    /// The current epoch
+    pub fn epoch(&self) -> u64 {
+        self.epochs.epoch
+    }
+
+    /// Get the fingerprint of the tree's current state
+    ///
+    /// Proofs from `generate_stamped_proof` carry the state it is computed from. Clients
+    /// caching the root should cache the fingerprint with it and hand both to
+    /// `MerkleVerifier`, so that stamped proofs from a newer state are reported as
+    /// `VerificationError::WrongRootEpoch` rather than as invalid.
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint of the root, leaf count and epoch, or None if the tree has no root
+    pub fn root_id(&self) -> Option<RootId> {
+        self.root_stamp().as_ref().map(RootStamp::root_id)
+    }
+
+    /// Get the tree's current state, for stamping proofs with
+    ///
+    /// # Returns
+    ///
+    /// The root, leaf count and epoch, or None if the tree has no root
+    pub fn root_stamp(&self) -> Option<RootStamp> {
+        Some(RootStamp {
+            root: self.root_hash()?,
+            leaf_count: self.leaf_count() as u64,
+            epoch: self.epoch(),
+        })
+    }
+
+    /// Generate a proof stamped with the tree's current state
+    ///
+    /// Stamping is opt-in: the stamp is only encoded by `MerkleProof::to_stamped_bytes`,
+    /// and lets a verifier holding an older root report it as stale (see
+    /// `MerkleVerifier::with_root_id`).
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The stamped proof, or None if the index is out of range
+    pub fn generate_stamped_proof(&self, index: usize) -> Option<MerkleProof> {
+        let proof = self.proof_by_index(index)?;
+        Some(proof.with_stamp(self.root_stamp()?))
+    }
+
+    /// Get the root the tree had at a retained epoch
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The epoch
+    ///
+    /// # Returns
+    ///
+    /// The root at that epoch, or None if the epoch is in the future, has been evicted
+    /// or the tree had no root then
+    pub fn root_at_epoch(&self, epoch: u64) -> Option<Vec<u8>> {
+        if epoch == self.epochs.epoch {
+            self.root_hash()
+        } else {
+            self.epochs.past(epoch).map(|past| past.root.clone())
+        }
+    }
+
+    /// Generate a proof for a leaf that records the current epoch and root
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof, or None if the index is out of range
+    pub fn generate_epoch_proof(&self, index: usize) -> Option<EpochProof> {
+        Some(EpochProof {
+            epoch: self.epochs.epoch,
+            root: self.root_hash()?,
+            proof: self.proof_by_index(index)?,
+        })
+    }
+
+    /// Verify a proof against the root of the epoch it was generated in
+    ///
+    /// Proofs handed out before the tree changed keep verifying as long as their epoch
+    /// is retained (see `MerkleTreeBuilder::epoch_history`).
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    ///
+    /// # Returns
+    ///
+    /// Ok if the proof is valid for the tree as it was at the proof's epoch, or an error
+    /// if the epoch is not retained or the proof does not lead to that epoch's root
+    pub fn verify_at_epoch(
+        &self,
+        data: &[u8],
+        proof: &EpochProof,
+    ) -> Result<(), VerificationError> {
+        let (root, leaf_count) = if proof.epoch == self.epochs.epoch {
+            (self.root_hash(), self.leaf_count())
+        } else {
+            match self.epochs.past(proof.epoch) {
+                Some(past) => (Some(past.root.clone()), past.leaf_count),
+                None => (None, 0),
+            }
+        };
+        let root = root.ok_or(VerificationError::EpochUnavailable { epoch: proof.epoch })?;
+        if root != proof.root || !self.verify_against(data, &proof.proof, &root, leaf_count) {
+            return Err(VerificationError::RootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Hash a leaf's data as it would be stored at a given index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The leaf's data
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash, which commits to the index as well for positional trees
+    pub(crate) fn leaf_hash_at(&self, index: usize, data: &[u8]) -> Vec<u8> {
+        let counting = self.metrics.counting(&self.params.hasher);
+        if self.params.positional {
+            counting.hash_leaf(&positional_leaf(index, data))
+        } else {
+            counting.hash_leaf(data)
+        }
+    }
+
+    /// Hash a pair of child hashes as the tree does
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The hash of the left child
+    /// * `right` - The hash of the right child
+    ///
+    /// # Returns
+    ///
+    /// The parent's hash
+    pub(crate) fn pair_hash(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.metrics
+            .counting(&self.params.hasher)
+            .hash_pair(left, right)
+    }
+
+    /// Get the empty subtree hash a node holds, if it is padding in a complete tree
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The hash of an empty subtree of the node's height, or None if the tree is not
+    /// complete or the node covers a real leaf
+    pub(crate) fn empty_subtree_at(&self, level: usize, index: usize) -> Option<Vec<u8>> {
+        match self.params.padding {
+            PaddingStrategy::Complete { depth }
+                if index.checked_shl(level as u32).unwrap_or(usize::MAX) >= self.leaf_count() =>
+            {
+                empty_subtrees(&self.params.hasher, depth)
+                    .into_iter()
+                    .nth(level)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether the tree commits to each leaf's index
+    ///
+    /// # Returns
+    ///
+    /// True if leaves are hashed as `H(le64(index) || data)`, false otherwise
+    pub fn is_positional(&self) -> bool {
+        self.params.positional
+    }
+
+    /// Get the number of hashes this tree has computed
+    ///
+    /// Counts accumulate over the tree's lifetime: construction, `push`, `update_leaf`,
+    /// `generate_proof` (which hashes the data to find its leaf) and `verify` all add to
+    /// them. Use `reset_metrics`
+    /// to measure a single operation. Cloning a tree copies its counts.
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of the leaf and pair hash counts
+    pub fn metrics(&self) -> HashMetrics {
+        self.metrics.snapshot()
+    }
+
+    /// Reset the tree's hash counts to zero
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+        self.proof_cache.reset_stats();
+    }
+
+    /// Get the counters of the tree's proof cache
+    ///
+    /// Hits and misses accumulate until `reset_metrics`; every `push` or `update_leaf`
+    /// empties the cache but keeps the counters.
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of the cache's counters, all zero if the tree has no proof cache (see
+    /// `MerkleTreeBuilder::proof_cache`)
+    pub fn proof_cache_stats(&self) -> ProofCacheStats {
+        self.proof_cache.stats()
+    }
+
+    /// Measure the memory taken by the tree's leaf data
+    ///
+    /// Payloads referenced by several leaves, whether through the pool of
+    /// `MerkleTreeBuilder::dedup_storage` or shared with `new_shared`, count once towards
+    /// the physical size.
+    ///
+    /// # Returns
+    ///
+    /// The logical and physical sizes of the leaf data
+    pub fn storage_stats(&self) -> StorageStats {
+        let mut stats = StorageStats {
+            pooled_payloads: self.pool.as_ref().map_or(0, LeafPool::len),
+            ..StorageStats::default()
+        };
+        let mut seen = HashSet::new();
+        for data in &self.data {
+            let Some(bytes) = data.as_deref() else {
+                continue;
+            };
+            stats.logical_bytes += bytes.len() as u64;
+            let distinct = match data {
+                LeafData::Shared(payload) => seen.insert(Arc::as_ptr(payload)),
+                _ => true,
+            };
+            if distinct {
+                stats.physical_bytes += bytes.len() as u64;
+            }
+        }
+        stats
+    }
+
+    /// Get the hash scheme used by the tree
+    ///
+    /// # Returns
+    ///
+    /// The scheme used to hash leaves and branches
+    pub fn hash_scheme(&self) -> HashScheme {
+        self.params.hasher.scheme
+    }
+
+    /// Get the full hash configuration used by the tree
+    ///
+    /// # Returns
+    ///
+    /// The hash algorithm, scheme and pair ordering the tree was built with
+    pub fn hash_config(&self) -> HashConfig {
+        self.params.hasher
+    }
+
+    /// Get the parameters the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The hash configuration, padding strategy and positional flag
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get the version of the semantics the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The version matching the tree's parameters, or None if they are unversioned
+    pub fn version(&self) -> Option<TreeVersion> {
+        TreeVersion::of(self.params)
+    }
+
+    /// Get the number of leaves supplied when the tree was built
+    ///
+    /// Unlike `len`, this does not count the duplicate added to pad an odd number of leaves.
+    ///
+    /// # Returns
+    ///
+    /// The number of data items committed to by the tree
+    pub fn leaf_count(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Get the number of leaves the tree's root commits to
+    ///
+    /// This is the leaf count, except in the final shard of a split tree that holds a
+    /// single leaf paired with its duplicate (see `split`), whose root is that of a tree
+    /// of two leaves.
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves proofs are checked against
+    pub(crate) fn committed_leaf_count(&self) -> usize {
+        let leaf_count = self.leaf_count();
+        let leaf_level_len = self.levels.first().map_or(0, SharedVec::len);
+        if is_padded_lone_leaf(leaf_count, self.params.padding, leaf_level_len) {
+            2
+        } else {
+            leaf_count
+        }
+    }
+
+    /// Get the data of the leaf at a specific index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaf's data, or None if the index is out of range or the data was cleared
+    pub fn get_leaf(&self, index: usize) -> Option<&[u8]> {
+        self.data.get(index)?.as_deref()
+    }
+
+    /// Get a shared reference to the data of the leaf at a specific index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaf's payload, or None if the index is out of range or the tree does not
+    /// share the leaf's data (see `new_shared`)
+    pub fn shared_leaf(&self, index: usize) -> Option<Arc<[u8]>> {
+        self.data.get(index)?.shared()
+    }
+
+    /// Get the position a leaf had in the input before the leaves were sorted
+    ///
+    /// For a tree built without `MerkleTreeBuilder::sort_leaves` every leaf is at its
+    /// original position. Leaves appended with `push` are numbered after the original
+    /// input.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf in the tree
+    ///
+    /// # Returns
+    ///
+    /// The leaf's original index, or None if the index is out of range
+    pub fn original_index(&self, index: usize) -> Option<usize> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        match &self.order {
+            Some(order) => order.get(index).copied(),
+            None => Some(index),
+        }
+    }
+
+    /// Take the hashes of the leaves, discarding the rest of the tree
+    ///
+    /// # Returns
+    ///
+    /// The hashes of the leaves, excluding padding
+    pub fn into_leaf_hashes(mut self) -> Vec<Vec<u8>> {
+        let leaf_count = self.leaf_count();
+        let mut levels = std::mem::take(&mut self.levels);
+        if levels.is_empty() {
+            return Vec::new();
+        }
+        let mut leaf_hashes = levels.swap_remove(0);
+        leaf_hashes.truncate(leaf_count);
+        leaf_hashes.to_vec()
+    }
+
+    /// Drop the data of every leaf while keeping the hashes
+    ///
+    /// The root is unchanged and proofs can still be generated by index (or by data, which
+    /// is hashed and looked up). With the `zeroize` feature enabled the data buffers the
+    /// tree owns are wiped before being freed; shared data (see `new_shared`) is only
+    /// released, as the application may still hold it, and so is data a clone of the
+    /// tree still holds.
+    pub fn clear_leaf_data(&mut self) {
+        self.data.for_each_unique(LeafData::clear);
+        self.data = (0..self.data.len()).map(|_| LeafData::None).collect();
+        if let Some(pool) = &mut self.pool {
+            pool.clear();
+        }
+    }
+
+    /// Append a data item as a new leaf
+    ///
+    /// Only the nodes on the new leaf's path to the root are rehashed. Observers are
+    /// notified once the leaf is appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// The new root and the earlier leaves whose proofs should be re-issued (see
+    /// `PushOutcome`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has `PaddingStrategy::Complete` padding and is full
+    pub fn push(&mut self, data: Vec<u8>) -> PushOutcome {
+        if let Err(error) = check_capacity(self.leaf_count() + 1, self.params.padding) {
+            panic!("{}", error);
+        }
+        let index = self.leaf_count();
+        let old_root = self.root_hash_ref().map(<[u8]>::to_vec);
+        let hash = self.leaf_hash_at(index, &data);
+        let outcome = self.push_hashed(hash, LeafData::Owned(data));
+        self.notify_root_change(old_root, MutationKind::Push, index..index + 1);
+        outcome
+    }
+
+    /// Append several data items as new leaves
+    ///
+    /// The leaves are appended one by one as by `push`, each starting a new epoch, but
+    /// observers are notified once, after the last leaf is appended. Appending no leaves
+    /// changes nothing and notifies no one.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_items` - The data of the new leaves, in order
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has `PaddingStrategy::Complete` padding and the leaves do not
+    /// fit, in which case none of them is appended
+    pub fn extend(&mut self, data_items: impl IntoIterator<Item = Vec<u8>>) {
+        let data_items: Vec<Vec<u8>> = data_items.into_iter().collect();
+        let start = self.leaf_count();
+        if let Err(error) = check_capacity(start + data_items.len(), self.params.padding) {
+            panic!("{}", error);
+        }
+        if data_items.is_empty() {
+            return;
+        }
+        let old_root = self.root_hash_ref().map(<[u8]>::to_vec);
+        for data in data_items {
+            let hash = self.leaf_hash_at(self.leaf_count(), &data);
+            self.push_hashed(hash, LeafData::Owned(data));
+        }
+        let end = self.leaf_count();
+        self.notify_root_change(old_root, MutationKind::Extend, start..end);
+    }
+
+    /// Append a leaf by its hash, as when replaying an operation log
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// What the push changed, or `MerkleError::TooManyLeaves` if the tree is full
+    pub(crate) fn try_push_leaf_hash(&mut self, hash: Vec<u8>) -> Result<PushOutcome, MerkleError> {
+        check_capacity(self.leaf_count() + 1, self.params.padding)?;
+        Ok(self.push_hashed(hash, LeafData::None))
+    }
+
+    /// Append a leaf whose hash has been computed
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the new leaf
+    /// * `data` - The data of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// What the push changed
+    fn push_hashed(&mut self, hash: Vec<u8>, data: LeafData) -> PushOutcome {
+        let root_before = self.root_hash_ref().map(<[u8]>::to_vec);
+        self.epochs.advance(self.root_hash(), self.leaf_count());
+        self.proof_cache.clear();
+        if let Some(order) = &mut self.order {
+            order.push(order.len());
+        }
+        let data = match &mut self.pool {
+            Some(pool) => pool.intern(data),
+            None => data,
+        };
+        self.data.push(data);
+        let leaf_count = self.data.len();
+        let leaf_hash = self.log.is_some().then(|| hash.clone());
+
+        if self.levels.is_empty() {
+            self.levels.push(SharedVec::new());
+        }
+        if let Some(hash_index) = self.hash_index.get_mut() {
+            hash_index.insert(&hash, leaf_count - 1);
+        }
+        // Replace any padding duplicate, then pad again if the count is odd
+        let leaves = &mut self.levels[0];
+        leaves.truncate(leaf_count - 1);
+        // Appending out of order falls back to map lookups
+        self.sorted_unique &= leaves.last().is_none_or(|last| *last < hash);
+        leaves.push(hash);
+        match self.params.padding {
+            padding if padding.duplicates_last(leaf_count) => {
+                leaves.push(leaves[leaf_count - 1].clone());
+            }
+            PaddingStrategy::Complete { depth } if depth > 0 && leaf_count % 2 == 1 => {
+                leaves.push(empty_subtrees(&self.params.hasher, 0).remove(0));
+            }
+            _ => {}
+        }
+
+        self.rehash_path(leaf_count - 1);
+        let new_root = self.levels.last().unwrap()[0].clone();
+        if let (Some(log), Some(leaf_hash)) = (&mut self.log, leaf_hash) {
+            log.record(LogEntry {
+                operation: Operation::Push,
+                index: leaf_count - 1,
+                leaf_hash,
+                root_before,
+                root_after: new_root.clone(),
+            });
+        }
+        PushOutcome {
+            new_root,
+            invalidated_indices: invalidated_by_push(leaf_count - 1, self.params.padding),
+        }
+    }
+
+    /// Append a data item as a new leaf and prove it
+    ///
+    /// The proof's siblings are read off the right edge the append has just rehashed,
+    /// in the same `&mut self` call, so no other append can come between the root and
+    /// the proof, and the proof cache is not consulted. See `push` for the cost of the
+    /// append and `latest_proof` for proving the last leaf later.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// The new root and the new leaf's proof against it
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has `PaddingStrategy::Complete` padding and is full
+    pub fn push_with_proof(&mut self, data: Vec<u8>) -> (Vec<u8>, MerkleProof) {
+        let outcome = self.push(data);
+        let proof = self.path_to_root(0, self.leaf_count() - 1);
+        (outcome.new_root, proof)
+    }
+
+    /// Generate a proof for the most recently appended leaf
+    ///
+    /// This is the last real leaf, never a padding duplicate or empty slot after it.
+    ///
+    /// # Returns
+    ///
+    /// The proof for the last leaf, or None if the tree is empty
+    pub fn latest_proof(&self) -> Option<MerkleProof> {
+        self.proof_by_index(self.leaf_count().checked_sub(1)?)
+    }
+
+    /// Replace the data of an existing leaf
+    ///
+    /// Only the nodes on the leaf's path to the root are rehashed, so the update costs
+    /// one leaf hash and one pair hash per proof step. With the `zeroize` feature enabled
+    /// the old data is wiped before being freed. Observers are notified once the leaf is
+    /// replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The new data of the leaf
+    ///
+    /// # Returns
+    ///
+    /// Ok if the leaf was updated, or an error if the index is out of range
+    pub(crate) fn update_by_index(
+        &mut self,
+        index: usize,
+        data: Vec<u8>,
+    ) -> Result<(), MerkleError> {
+        let leaf_count = self.leaf_count();
+        if index >= leaf_count {
+            return Err(MerkleError::IndexOutOfRange { index, leaf_count });
+        }
+        let old_root = self.root_hash_ref().map(<[u8]>::to_vec);
+        let hash = self.leaf_hash_at(index, &data);
+        self.replace_hashed(index, hash, LeafData::Owned(data));
+        self.notify_root_change(old_root, MutationKind::Update, index..index + 1);
+        Ok(())
+    }
+
+    /// Copy the tree's hashes without its leaf data, to try mutations on
+    ///
+    /// # Returns
+    ///
+    /// A tree with the same levels and parameters whose leaves have no data
+    pub(crate) fn hashes_only(&self) -> MerkleTree {
+        let mut tree = MerkleTree::from_parts(
+            vec![LeafData::None; self.data.len()],
+            Vec::new(),
+            self.params,
+        );
+        tree.levels = self.levels.clone();
+        tree.empty_root = self.empty_root;
+        tree
+    }
+
+    /// Take over the levels of a copy that a diff was applied to, with the diff's data
+    ///
+    /// The whole change is one mutation: it starts one epoch and notifies the observers
+    /// once. Leaves past the copy's leaf count are dropped with their metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `applied` - The copy from `hashes_only` with the diff applied
+    /// * `data` - The new data of each changed leaf, by index
+    /// * `leaves` - The leaves the diff appended or replaced
+    pub(crate) fn commit_diff(
+        &mut self,
+        mut applied: MerkleTree,
+        data: Vec<(usize, LeafData)>,
+        leaves: Range<usize>,
+    ) {
+        let old_root = self.root_hash_ref().map(<[u8]>::to_vec);
+        self.epochs.advance(self.root_hash(), self.leaf_count());
+        self.proof_cache.clear();
+
+        let leaf_count = applied.leaf_count();
+        let removed = self.data.split_off(leaf_count.min(self.data.len()));
+        for mut old in removed {
+            if let Some(pool) = &mut self.pool {
+                pool.release(&old);
+            }
+            old.clear();
+        }
+        self.data.resize_with(leaf_count, LeafData::default);
+        for (index, data) in data {
+            let data = match &mut self.pool {
+                Some(pool) => pool.intern(data),
+                None => data,
+            };
+            let mut old = std::mem::replace(&mut self.data[index], data);
+            if let Some(pool) = &mut self.pool {
+                pool.release(&old);
+            }
+            old.clear();
+        }
+        if let Some(order) = &mut self.order {
+            order.truncate(leaf_count);
+            while order.len() < leaf_count {
+                order.push(order.len());
+            }
+        }
+        self.metadata.split_off(&leaf_count);
+
+        // Taken rather than moved, as trees implement `Drop` with the `zeroize` feature
+        self.levels = std::mem::take(&mut applied.levels);
+        self.hash_index = OnceLock::new();
+        self.sorted_unique &= self
+            .levels
+            .first()
+            .is_none_or(|leaves| (1..leaf_count).all(|index| leaves[index - 1] < leaves[index]));
+        self.notify_root_change(old_root, MutationKind::Diff, leaves);
+    }
+
+    /// Report a completed mutation to the observers
+    ///
+    /// # Arguments
+    ///
+    /// * `old_root` - The root before the mutation, or None if the tree had no leaves
+    /// * `kind` - The kind of mutation
+    /// * `leaves` - The leaves appended or replaced
+    fn notify_root_change(
+        &self,
+        old_root: Option<Vec<u8>>,
+        kind: MutationKind,
+        leaves: Range<usize>,
+    ) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let Some(new_root) = self.root_hash_ref() else {
+            return;
+        };
+        self.observers.notify(RootChange {
+            old_root,
+            new_root: new_root.to_vec(),
+            epoch: self.epoch(),
+            kind,
+            leaves,
+        });
+    }
+
+    /// Replace a leaf by its hash, as when replaying an operation log
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `hash` - The new hash of the leaf
+    ///
+    /// # Returns
+    ///
+    /// Ok if the leaf was replaced, or an error if the index is out of range
+    pub(crate) fn update_leaf_hash(
+        &mut self,
+        index: usize,
+        hash: Vec<u8>,
+    ) -> Result<(), MerkleError> {
+        let leaf_count = self.leaf_count();
+        if index >= leaf_count {
+            return Err(MerkleError::IndexOutOfRange { index, leaf_count });
+        }
+        self.replace_hashed(index, hash, LeafData::None);
+        Ok(())
+    }
+
+    /// Replace an existing leaf with one whose hash has been computed
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf (less than the leaf count)
+    /// * `hash` - The new hash of the leaf
+    /// * `data` - The new data of the leaf
+    fn replace_hashed(&mut self, index: usize, hash: Vec<u8>, data: LeafData) {
+        let leaf_count = self.leaf_count();
+        let root_before = self.root_hash_ref().map(<[u8]>::to_vec);
+        self.epochs.advance(self.root_hash(), leaf_count);
+        self.proof_cache.clear();
+        let data = match &mut self.pool {
+            Some(pool) => pool.intern(data),
+            None => data,
+        };
+        let mut old = std::mem::replace(&mut self.data[index], data);
+        if let Some(pool) = &mut self.pool {
+            pool.release(&old);
+        }
+        old.clear();
+        let leaf_hash = self.log.is_some().then(|| hash.clone());
+        let duplicated = self.params.padding.duplicates_last(leaf_count)
+            || self.committed_leaf_count() > leaf_count;
+        let leaves = &mut self.levels[0];
+        if let Some(hash_index) = self.hash_index.get_mut() {
+            hash_index.remove(&leaves[index], index, leaves);
+            hash_index.insert(&hash, index);
+        }
+        // Replacing a leaf out of order falls back to map lookups
+        self.sorted_unique &= (index == 0 || leaves[index - 1] < hash)
+            && (index + 1 == leaf_count || hash < leaves[index + 1]);
+        if index + 1 < leaves.len() && index + 1 == leaf_count && duplicated {
+            // Keep the padding duplicate in step with the last leaf
+            leaves[index + 1] = hash.clone();
+        }
+        leaves[index] = hash;
+
+        self.rehash_path(index);
+        if let (Some(log), Some(leaf_hash)) = (&mut self.log, leaf_hash) {
+            log.record(LogEntry {
+                operation: Operation::Update,
+                index,
+                leaf_hash,
+                root_before,
+                root_after: self.levels.last().unwrap()[0].clone(),
+            });
+        }
+    }
+
+    /// Compute the root the tree would have if a leaf's data were replaced
+    ///
+    /// Only the leaf's path is hashed, from the siblings in the cached levels, so this
+    /// costs what `update_leaf` does without changing the tree. The result is the root
+    /// `update_leaf` would produce, including the padding duplicate of a last odd leaf.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `new_data` - The data the leaf would have
+    ///
+    /// # Returns
+    ///
+    /// The hypothetical root, or `MerkleError::IndexOutOfRange`
+    pub fn root_after_update(&self, index: usize, new_data: &[u8]) -> Result<Vec<u8>, MerkleError> {
+        let leaf_count = self.leaf_count();
+        if index >= leaf_count {
+            return Err(MerkleError::IndexOutOfRange { index, leaf_count });
+        }
+        let duplicated = self.params.padding.duplicates_last(leaf_count)
+            || self.committed_leaf_count() > leaf_count;
+        let mut hash = self.leaf_hash_at(index, new_data);
+        let mut node = index;
+        for (level, nodes) in self.levels[..self.levels.len() - 1].iter().enumerate() {
+            let sibling = node ^ 1;
+            // The padding duplicate of the last leaf follows it
+            let duplicate = level == 0 && sibling == leaf_count && duplicated;
+            hash = match nodes.get(sibling) {
+                Some(_) if duplicate => self.pair_hash(&hash, &hash),
+                Some(other) if node % 2 == 1 => self.pair_hash(other, &hash),
+                Some(other) => self.pair_hash(&hash, other),
+                // Carry an unpaired node up to the next level
+                None => hash,
+            };
+            node /= 2;
+        }
+        Ok(hash)
+    }
+
+    /// Compute the root the tree would have if a leaf were appended
+    ///
+    /// Every node left of the new leaf's path is already cached, and everything right of
+    /// it is padding, so only the path is hashed and the tree is not changed. The result
+    /// is the root `push` would produce.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// The hypothetical root
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has `PaddingStrategy::Complete` padding and is full
+    pub fn root_after_append(&self, data: &[u8]) -> Vec<u8> {
+        let index = self.leaf_count();
+        if let Err(error) = check_capacity(index + 1, self.params.padding) {
+            panic!("{}", error);
+        }
+        let sizes = level_sizes(index + 1, self.params.padding);
+        let empty = match self.params.padding {
+            PaddingStrategy::Complete { depth } => empty_subtrees(&self.params.hasher, depth),
+            _ => Vec::new(),
+        };
+        let mut hash = self.leaf_hash_at(index, data);
+        let mut node = index;
+        for (level, &size) in sizes[..sizes.len() - 1].iter().enumerate() {
+            hash = if node % 2 == 1 {
+                // The left sibling covers earlier leaves only, so it is unchanged
+                self.pair_hash(&self.levels[level][node - 1], &hash)
+            } else if node + 1 < size {
+                // The right sibling is an empty subtree, or the padding duplicate of the
+                // new leaf
+                let padding = empty.get(level).unwrap_or(&hash);
+                self.pair_hash(&hash, padding)
+            } else {
+                hash
+            };
+            node /= 2;
+        }
+        hash
+    }
+
+    /// Get the record of every mutation since the tree was built
+    ///
+    /// # Returns
+    ///
+    /// The log, or None unless the tree was built with
+    /// `MerkleTreeBuilder::operation_log`
+    pub fn operation_log(&self) -> Option<&OperationLog> {
+        self.log.as_ref()
+    }
+
+    /// Start recording mutations in a log
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The log to continue
+    pub(crate) fn attach_log(&mut self, log: OperationLog) {
+        self.log = Some(log);
+    }
+
+    /// Recompute the ancestors of a leaf after it has changed
+    ///
+    /// Levels grow (and a new root level is added) as needed to fit the current leaf
+    /// count. Every other node depends only on leaves outside the changed one's blocks,
+    /// so it keeps its hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the changed leaf
+    fn rehash_path(&mut self, index: usize) {
+        let sizes = level_sizes(self.committed_leaf_count(), self.params.padding);
+        let counting = self.metrics.counting(&self.params.hasher);
+        let mut index = index;
+        let mut empty = None;
+
+        for (level, &size) in sizes.iter().enumerate().skip(1) {
+            index /= 2;
+            let children = &self.levels[level - 1];
+            let hash = match children.get(index * 2 + 1) {
+                Some(right) => counting.hash_pair(&children[index * 2], right),
+                // Carry an unpaired node up to the next level
+                None => children[index * 2].clone(),
+            };
+
+            if level == self.levels.len() {
+                self.levels.push(SharedVec::new());
+            }
+            let nodes = &mut self.levels[level];
+            if nodes.len() < size {
+                // New slots of a complete tree start out as empty subtrees
+                let fill = match self.params.padding {
+                    PaddingStrategy::Complete { depth } => empty
+                        .get_or_insert_with(|| empty_subtrees(&self.params.hasher, depth))[level]
+                        .clone(),
+                    _ => Vec::new(),
+                };
+                nodes.resize(size, fill);
+            }
+            nodes[index] = hash;
+        }
+    }
+
+    /// Get the number of leaves in the tree
+    ///
+    /// # Returns
+    ///
+    /// The number of leaf nodes in the tree, including the duplicate added to pad an
+    /// odd number of leaves
+    pub fn len(&self) -> usize {
+        self.levels.first().map_or(0, SharedVec::len)
+    }
+
+    /// Check if the tree is empty
+    ///
+    /// # Returns
+    ///
+    /// True if the tree has no nodes, false otherwise
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Print the tree structure for debugging
+    ///
+    /// This function prints the tree structure to standard output.
+    pub fn print_tree(&self) {
+        if self.levels.is_empty() {
+            println!("Empty tree");
+        } else {
+            println!("Merkle Tree Structure:");
+            self.print_node(self.levels.len() - 1, 0, 0);
+        }
+    }
+
+    /// Helper function to print a node and its children recursively
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    /// * `indent` - The indentation level (for pretty-printing)
+    fn print_node(&self, level: usize, index: usize, indent: usize) {
+        let indent_str = " ".repeat(indent * 2);
+        let hash = &self.levels[level][index];
+        let label = self
+            .labels
+            .get(&(level, index))
+            .map_or_else(String::new, |label| format!(", label={:?}", label));
+
+        if level == 0 {
+            // The padding duplicate shows the data of the leaf it copies
+            match self.data[index.min(self.data.len() - 1)].as_deref() {
+                Some(data) => println!(
+                    "{}Leaf: data={:?}, hash={}{}",
+                    indent_str,
+                    String::from_utf8_lossy(data),
+                    hex::encode(&hash[0..4]),
+                    label
+                ), // Print just the start of the hash
+                None => println!(
+                    "{}Leaf: data=<cleared>, hash={}{}",
+                    indent_str,
+                    hex::encode(&hash[0..4]),
+                    label
+                ),
+            }
+        } else if index * 2 + 1 < self.levels[level - 1].len() {
+            println!(
+                "{}Branch: hash={}{}",
+                indent_str,
+                hex::encode(&hash[0..4]),
+                label
+            );
+            self.print_node(level - 1, index * 2, indent + 1);
+            self.print_node(level - 1, index * 2 + 1, indent + 1);
+        } else {
+            // A node carried up unpaired is printed once, at its original level
+            self.print_node(level - 1, index * 2, indent);
+        }
+    }
+}
+
+/// Builds the tree with `MerkleTree::from_strings`
+impl From<Vec<String>> for MerkleTree {
+    fn from(items: Vec<String>) -> Self {
+        MerkleTree::new_encoded::<Utf8String>(items)
+    }
+}
+
+impl fmt::Debug for MerkleTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MerkleTree")
+            .field("leaf_count", &self.leaf_count())
+            .field("root", &self.root_hash_hex())
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for MerkleTree {
+    fn drop(&mut self) {
+        // Data shared with a clone is wiped when the last tree holding it is dropped
+        self.data.for_each_unique(LeafData::clear);
+    }
+}
+
+/// Overwrite a leaf's data before it is freed when the `zeroize` feature is enabled
+///
+/// # Arguments
+///
+/// * `bytes` - The data buffer to wipe
+#[cfg(feature = "zeroize")]
+pub(crate) fn wipe(bytes: &mut Vec<u8>) {
+    zeroize::Zeroize::zeroize(bytes);
+}
+
+/// Overwrite a leaf's data before it is freed when the `zeroize` feature is enabled
+///
+/// # Arguments
+///
+/// * `_bytes` - The data buffer to wipe
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn wipe(_bytes: &mut Vec<u8>) {}
+
+/// Hash each leaf, on rayon's thread pool if construction is parallel
+///
+/// Each hash is written to its leaf's slot, so a parallel build gives the hashes a
+/// sequential one does, in the same order.
+///
+/// # Arguments
+///
+/// * `data_items` - The data of each leaf
+/// * `options` - The construction options
+/// * `hasher` - The counting hasher to hash with
+/// * `progress` - The callback to report each hashed leaf to, if any
+/// * `hash` - Hashes the data of the leaf at an index
+///
+/// # Returns
+///
+/// The hash of each leaf, with room for a padding duplicate
+#[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+fn hash_leaves<D: AsRef<[u8]> + Sync>(
+    data_items: &[D],
+    options: BuildOptions,
+    hasher: &CountingHasher,
+    progress: Option<&dyn Fn(&ProgressEvent)>,
+    hash: impl Fn(&CountingHasher, usize, &[u8]) -> Vec<u8> + Sync + Send,
+) -> Vec<Vec<u8>> {
+    let total = data_items.len();
+    let report = |index| {
+        if let Some(progress) = progress {
+            progress(&ProgressEvent::LeafHashed { index, total });
+        }
+    };
+    // Leave room for the padding duplicate so that padding does not reallocate
+    let mut hashes = Vec::with_capacity(total + 1);
+    #[cfg(feature = "parallel")]
+    if options.parallel {
+        ParallelHasher::new(hasher).hash_leaves(
+            data_items,
+            |hasher, index, data| hash(hasher, index, data.as_ref()),
+            &mut hashes,
+        );
+        (0..total).for_each(report);
+        return hashes;
+    }
+    hashes.extend(data_items.iter().enumerate().map(|(index, data)| {
+        let leaf_hash = hash(hasher, index, data.as_ref());
+        report(index);
+        leaf_hash
+    }));
+    hashes
+}
+
+/// Check a leaf's size against a limit
+///
+/// # Arguments
+///
+/// * `index` - The index of the leaf
+/// * `size` - The size of the leaf in bytes
+/// * `max` - The largest size allowed, if limited
+///
+/// # Returns
+///
+/// Ok, or `MerkleError::LeafTooLarge` if the leaf is over the limit
+pub(crate) fn check_leaf_size(
+    index: usize,
+    size: u64,
+    max: Option<u64>,
+) -> Result<(), MerkleError> {
+    match max {
+        Some(max) if size > max => Err(MerkleError::LeafTooLarge { index, size, max }),
+        _ => Ok(()),
+    }
+}
+
+/// Compare two byte strings in time that depends only on their lengths
+///
+/// # Arguments
+///
+/// * `a` - The first byte string
+/// * `b` - The second byte string
+///
+/// # Returns
+///
+/// True if the byte strings are equal
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The hashes of one level of a tree, whether a tree's own or a copy such as a canopy's
+pub(crate) trait LevelNodes: std::ops::Index<usize, Output = Vec<u8>> {
+    /// Get the number of nodes on the level
+    fn node_count(&self) -> usize;
+}
+
+impl LevelNodes for Vec<Vec<u8>> {
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl LevelNodes for SharedVec<Vec<u8>> {
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Collect the sibling hashes on the path from a node up to the root of a tree's levels
+///
+/// # Arguments
+///
+/// * `levels` - The hashes of every level, from the (padded) leaf level up to the root
+/// * `level` - The level of the starting node
+/// * `index` - The index of the starting node within its level
+/// * `ordering` - How child hashes are ordered before hashing
+///
+/// # Returns
+///
+/// The proof steps from the node to the root
+pub(crate) fn path_in_levels<L: LevelNodes>(
+    levels: &[L],
+    level: usize,
+    index: usize,
+    ordering: PairOrdering,
+) -> MerkleProof {
+    let mut proof = MerkleProof::default();
+    let mut index = index;
+    // Directions are ignored under sorted ordering, so canonical proofs leave them unset
+    let positional = ordering == PairOrdering::Positional;
+
+    for level in &levels[level..levels.len() - 1] {
+        let is_left = index.is_multiple_of(2);
+        let sibling_idx = if is_left { index + 1 } else { index - 1 };
+
+        // The last node of an odd-sized level has no sibling and is carried up
+        if sibling_idx < level.node_count() {
+            proof.push((level[sibling_idx].clone(), !is_left && positional));
+        }
+
+        // Move to parent level
+        index /= 2;
+    }
+
+    proof
+}
+
+/// Find the leaves whose proofs change shape or lose padding when a leaf is appended
+///
+/// The new leaf's ancestor at each level is the only node that can give an earlier leaf
+/// a new step or take the place of padding, so only the leaves under its sibling are
+/// affected. Those blocks all end just before the new leaf, so together they form one
+/// range.
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves before the push
+/// * `padding` - The padding strategy of the tree
+///
+/// # Returns
+///
+/// The range of earlier leaves whose proofs should be re-issued
+fn invalidated_by_push(leaf_count: usize, padding: PaddingStrategy) -> Range<usize> {
+    let old_sizes = level_sizes(leaf_count, padding);
+    let new_sizes = level_sizes(leaf_count + 1, padding);
+    let mut start = leaf_count;
+    for level in 0..new_sizes.len().saturating_sub(1) {
+        let ancestor = leaf_count >> level;
+        // A left child's sibling lies after the new leaf and covers no earlier leaves
+        if ancestor.is_multiple_of(2) {
+            continue;
+        }
+        let added = ancestor >= old_sizes.get(level).copied().unwrap_or(0);
+        // A node starting at the new leaf's position covered only padding before
+        let was_padding = leaf_count.trailing_zeros() as usize >= level;
+        if added || was_padding {
+            start = start.min((ancestor ^ 1) << level);
+        }
+    }
+    start..leaf_count
+}
+
+/// Generate a proof straight from a list of leaf hashes
+///
+/// This is for one-shot use when only the leaf hashes were kept (see
+/// `MerkleTree::into_leaf_hashes`): the levels are computed exactly as when building a
+/// tree, so the proof is identical to the one the original tree would give, but no tree
+/// is kept afterwards.
+///
+/// # Arguments
+///
+/// * `params` - The parameters (or just the hash configuration or scheme) the tree was
+///   built with
+/// * `leaf_hashes` - The hashes of the leaves, excluding padding
+/// * `index` - The index of the leaf to prove
+///
+/// # Returns
+///
+/// A proof for the leaf, or None if the index is out of range
+pub fn proof_from_leaf_hashes(
+    params: impl Into<TreeParams>,
+    leaf_hashes: &[Vec<u8>],
+    index: usize,
+) -> Option<MerkleProof> {
+    if index >= leaf_hashes.len() {
+        return None;
+    }
+    let params = params.into();
+    let levels =
+        MerkleTree::build_levels(leaf_hashes.to_vec(), params.padding, &params.hasher, None);
+    Some(path_in_levels(&levels, 0, index, params.hasher.ordering))
+}
+
+/// Reorder items by a permutation
+///
+/// # Arguments
+///
+/// * `items` - The items to reorder
+/// * `permutation` - For each new position, the item's old position
+///
+/// # Returns
+///
+/// The reordered items
+fn permute<T>(items: Vec<T>, permutation: &[usize]) -> Vec<T> {
+    let mut items: Vec<_> = items.into_iter().map(Some).collect();
+    permutation
+        .iter()
+        .map(|&index| {
+            items[index]
+                .take()
+                .expect("a permutation visits each item once")
+        })
+        .collect()
+}
+
+/// Check that no two leaves have the same hash
+///
+/// # Arguments
+///
+/// * `leaf_hashes` - The leaf hashes, in order
+///
+/// # Returns
+///
+/// `MerkleError::DuplicateLeaf` for the first leaf whose hash appeared earlier
+fn check_unique(leaf_hashes: &[Vec<u8>]) -> Result<(), MerkleError> {
+    let mut seen = HashMap::with_capacity(leaf_hashes.len());
+    for (index, hash) in leaf_hashes.iter().enumerate() {
+        if let Some(&first) = seen.get(hash.as_slice()) {
+            return Err(MerkleError::DuplicateLeaf {
+                first,
+                second: index,
+                hash: hex::encode(hash),
+            });
+        }
+        seen.insert(hash.as_slice(), index);
+    }
+    Ok(())
+}
+
+/// Check that leaf hashes are strictly increasing
+///
+/// # Arguments
+///
+/// * `leaf_hashes` - The leaf hashes, excluding padding
+///
+/// # Returns
+///
+/// Ok if every hash is greater than the one before it, or `MerkleError::UnsortedLeaf`
+/// naming the first leaf that is not
+fn check_sorted_unique(leaf_hashes: &[Vec<u8>]) -> Result<(), MerkleError> {
+    match leaf_hashes.windows(2).position(|pair| pair[0] >= pair[1]) {
+        Some(previous) => Err(MerkleError::UnsortedLeaf {
+            index: previous + 1,
+        }),
+        None => Ok(()),
     }
 }
@@ -0,0 +1,143 @@
+use crate::hashing::{HashConfig, NodeHasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the number of hash invocations a tree has performed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HashMetrics {
+    /// The number of leaf hashes computed
+    pub leaf_hashes: u64,
+    /// The number of pair (branch) hashes computed
+    pub node_hashes: u64,
+}
+
+impl HashMetrics {
+    /// The total number of hash invocations
+    ///
+    /// # Returns
+    ///
+    /// The sum of leaf and pair hashes
+    pub fn total(&self) -> u64 {
+        self.leaf_hashes + self.node_hashes
+    }
+}
+
+/// A snapshot of the counters of a tree's proof cache
+///
+/// See `MerkleTreeBuilder::proof_cache`. A tree without a cache reports all zeros.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ProofCacheStats {
+    /// The number of proofs served from the cache
+    pub hits: u64,
+    /// The number of proofs computed because they were not cached
+    pub misses: u64,
+    /// The number of proofs currently cached
+    pub entries: usize,
+}
+
+/// A snapshot of the memory a tree's leaf data takes
+///
+/// See `MerkleTreeBuilder::dedup_storage`. Leaves whose data was cleared or never kept
+/// count towards neither total.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StorageStats {
+    /// The total size of every leaf's data, counting repeated payloads each time
+    pub logical_bytes: u64,
+    /// The size of the distinct payload buffers the leaves reference
+    pub physical_bytes: u64,
+    /// The number of distinct payloads in the tree's deduplicating pool, 0 without one
+    pub pooled_payloads: usize,
+}
+
+/// Running hash counters owned by a tree
+///
+/// The counters are atomic so that operations taking `&self`, such as proof generation
+/// and verification, can be counted without requiring `&mut self`.
+#[derive(Debug, Default)]
+pub(crate) struct HashCounter {
+    /// The number of leaf hashes computed
+    leaf_hashes: AtomicU64,
+    /// The number of pair hashes computed
+    node_hashes: AtomicU64,
+}
+
+impl HashCounter {
+    /// Take a snapshot of the counters
+    ///
+    /// # Returns
+    ///
+    /// The current counts
+    pub(crate) fn snapshot(&self) -> HashMetrics {
+        HashMetrics {
+            leaf_hashes: self.leaf_hashes.load(Ordering::Relaxed),
+            node_hashes: self.node_hashes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count a leaf hash computed without a counting hasher, such as one read from a stream
+    pub(crate) fn record_leaf_hash(&self) {
+        self.leaf_hashes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count hashes computed in bulk without a counting hasher, such as hashed in place
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hashes` - The number of leaf hashes computed
+    /// * `node_hashes` - The number of pair hashes computed
+    pub(crate) fn record_hashes(&self, leaf_hashes: u64, node_hashes: u64) {
+        self.leaf_hashes.fetch_add(leaf_hashes, Ordering::Relaxed);
+        self.node_hashes.fetch_add(node_hashes, Ordering::Relaxed);
+    }
+
+    /// Reset both counters to zero
+    pub(crate) fn reset(&self) {
+        self.leaf_hashes.store(0, Ordering::Relaxed);
+        self.node_hashes.store(0, Ordering::Relaxed);
+    }
+
+    /// Wrap a hash configuration so that every hash it computes is counted
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The configuration to hash with
+    ///
+    /// # Returns
+    ///
+    /// A hasher that records its invocations in these counters
+    pub(crate) fn counting<'a>(&'a self, hasher: &'a HashConfig) -> CountingHasher<'a> {
+        CountingHasher {
+            hasher,
+            counter: self,
+        }
+    }
+}
+
+impl Clone for HashCounter {
+    fn clone(&self) -> Self {
+        let metrics = self.snapshot();
+        HashCounter {
+            leaf_hashes: AtomicU64::new(metrics.leaf_hashes),
+            node_hashes: AtomicU64::new(metrics.node_hashes),
+        }
+    }
+}
+
+/// A hash configuration that records each invocation in a `HashCounter`
+pub(crate) struct CountingHasher<'a> {
+    /// The configuration to hash with
+    hasher: &'a HashConfig,
+    /// The counters to record invocations in
+    counter: &'a HashCounter,
+}
+
+impl NodeHasher for CountingHasher<'_> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.counter.leaf_hashes.fetch_add(1, Ordering::Relaxed);
+        self.hasher.hash_leaf(data)
+    }
+
+    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.counter.node_hashes.fetch_add(1, Ordering::Relaxed);
+        self.hasher.hash_pair(left, right)
+    }
+}
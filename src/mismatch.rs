@@ -0,0 +1,265 @@
+use crate::hashing::HashConfig;
+use crate::merkle_tree::MerkleTree;
+use std::fmt;
+
+/// The hashes of a leaf that differs between two trees
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafDifference {
+    /// The index of the leaf
+    pub index: usize,
+    /// The leaf's hash in our tree
+    pub ours: Vec<u8>,
+    /// The leaf's hash in their tree
+    pub theirs: Vec<u8>,
+}
+
+/// The parameters and root of one side of a comparison
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeSummary {
+    /// The root hash, or None for an empty tree
+    pub root: Option<Vec<u8>>,
+    /// The number of leaves, excluding padding
+    pub leaf_count: usize,
+    /// The hash configuration the tree was built with
+    pub hash_config: HashConfig,
+    /// Whether leaves commit to their index
+    pub positional: bool,
+    /// The ID of the leaf encoder the tree was built with, if recorded
+    pub encoder: Option<&'static str>,
+}
+
+impl TreeSummary {
+    fn of(tree: &MerkleTree) -> Self {
+        TreeSummary {
+            root: tree.root_hash(),
+            leaf_count: tree.leaf_count(),
+            hash_config: tree.hash_config(),
+            positional: tree.is_positional(),
+            encoder: tree.encoder_id(),
+        }
+    }
+}
+
+/// An explanation of why the roots of two trees differ
+///
+/// Returned by `MerkleTree::explain_mismatch`. The `Display` implementation renders a
+/// multi-line, human-readable report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MismatchReport {
+    /// Our tree
+    pub ours: TreeSummary,
+    /// Their tree
+    pub theirs: TreeSummary,
+    /// The `(level, index)` of the lowest node on the leftmost path of differing nodes,
+    /// or None if the roots match
+    pub first_divergence: Option<(usize, usize)>,
+    /// The label of the smallest labeled subtree containing the first divergent node, in
+    /// our tree or else in theirs (see `MerkleTree::label_subtree`)
+    pub divergence_label: Option<String>,
+    /// The leaves present in both trees whose hashes differ, up to
+    /// `MismatchReport::MAX_LISTED_LEAVES` of them
+    pub differing_leaves: Vec<LeafDifference>,
+    /// The total number of leaves present in both trees whose hashes differ
+    pub differing_leaf_count: usize,
+}
+
+impl MismatchReport {
+    /// The maximum number of differing leaves listed in a report
+    pub const MAX_LISTED_LEAVES: usize = 10;
+
+    /// Whether the two roots are the same
+    ///
+    /// # Returns
+    ///
+    /// True if the roots match
+    pub fn roots_match(&self) -> bool {
+        self.ours.root == self.theirs.root
+    }
+
+    /// Whether the trees were built with different recorded construction parameters
+    ///
+    /// # Returns
+    ///
+    /// True if the hash configuration, positional flag or leaf encoder differ
+    pub fn parameters_differ(&self) -> bool {
+        self.ours.hash_config != self.theirs.hash_config
+            || self.ours.positional != self.theirs.positional
+            || self.ours.encoder != self.theirs.encoder
+    }
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.roots_match() {
+            return writeln!(f, "Roots match: {}", root_hex(&self.ours.root));
+        }
+
+        writeln!(f, "Roots differ")?;
+        writeln!(
+            f,
+            "  ours:   {} ({} leaves)",
+            root_hex(&self.ours.root),
+            self.ours.leaf_count
+        )?;
+        writeln!(
+            f,
+            "  theirs: {} ({} leaves)",
+            root_hex(&self.theirs.root),
+            self.theirs.leaf_count
+        )?;
+
+        if self.ours.leaf_count != self.theirs.leaf_count {
+            writeln!(
+                f,
+                "Leaf counts differ: {} vs {}",
+                self.ours.leaf_count, self.theirs.leaf_count
+            )?;
+        }
+        if self.ours.hash_config != self.theirs.hash_config {
+            writeln!(
+                f,
+                "Hash configurations differ: {:?} vs {:?}",
+                self.ours.hash_config, self.theirs.hash_config
+            )?;
+        }
+        if self.ours.positional != self.theirs.positional {
+            writeln!(
+                f,
+                "Positional leaves differ: {} vs {}",
+                self.ours.positional, self.theirs.positional
+            )?;
+        }
+        if self.ours.encoder != self.theirs.encoder {
+            writeln!(
+                f,
+                "Leaf encoders differ: {} vs {}",
+                self.ours.encoder.unwrap_or("none"),
+                self.theirs.encoder.unwrap_or("none")
+            )?;
+        }
+        if let Some((level, index)) = self.first_divergence {
+            write!(f, "First divergence at level {}, index {}", level, index)?;
+            match &self.divergence_label {
+                Some(label) => writeln!(f, ", inside {:?} subtree", label)?,
+                None => writeln!(f)?,
+            }
+        }
+
+        if self.differing_leaf_count > 0 {
+            writeln!(f, "{} shared leaves differ:", self.differing_leaf_count)?;
+            for leaf in &self.differing_leaves {
+                writeln!(
+                    f,
+                    "  leaf {}: ours {}, theirs {}",
+                    leaf.index,
+                    hex::encode(&leaf.ours),
+                    hex::encode(&leaf.theirs)
+                )?;
+            }
+            let unlisted = self.differing_leaf_count - self.differing_leaves.len();
+            if unlisted > 0 {
+                writeln!(f, "  ... and {} more", unlisted)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MerkleTree {
+    /// Explain why this tree's root differs from another tree's
+    ///
+    /// When the roots match the report says so without comparing anything else.
+    /// Otherwise it records the leaf counts and construction parameters of both trees,
+    /// finds the first divergent node by descending from the highest level the trees
+    /// share, names the labeled subtree it is inside, and lists the shared leaves whose
+    /// hashes differ.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The tree to compare against
+    ///
+    /// # Returns
+    ///
+    /// The comparison report
+    pub fn explain_mismatch(&self, other: &MerkleTree) -> MismatchReport {
+        let mut report = MismatchReport {
+            ours: TreeSummary::of(self),
+            theirs: TreeSummary::of(other),
+            first_divergence: None,
+            divergence_label: None,
+            differing_leaves: Vec::new(),
+            differing_leaf_count: 0,
+        };
+        if report.roots_match() {
+            return report;
+        }
+
+        report.first_divergence = first_divergence(self, other);
+        report.divergence_label = report.first_divergence.and_then(|(level, index)| {
+            self.enclosing_label(level, index)
+                .or_else(|| other.enclosing_label(level, index))
+                .map(String::from)
+        });
+
+        let common = self.leaf_count().min(other.leaf_count());
+        for index in 0..common {
+            let ours = &self.levels()[0][index];
+            let theirs = &other.levels()[0][index];
+            if ours != theirs {
+                report.differing_leaf_count += 1;
+                if report.differing_leaves.len() < MismatchReport::MAX_LISTED_LEAVES {
+                    report.differing_leaves.push(LeafDifference {
+                        index,
+                        ours: ours.clone(),
+                        theirs: theirs.clone(),
+                    });
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Find the lowest node on the leftmost path of differing nodes
+///
+/// The walk starts on the highest level both trees have, at the first node that differs
+/// or exists in only one of the trees, and follows the leftmost differing child down.
+/// It stops at a leaf, at a node present in only one tree, or at a node whose children
+/// all match (which happens when the trees hash pairs differently).
+///
+/// # Arguments
+///
+/// * `ours` - Our tree
+/// * `theirs` - Their tree
+///
+/// # Returns
+///
+/// The `(level, index)` of the divergent node, or None if no node differs
+fn first_divergence(ours: &MerkleTree, theirs: &MerkleTree) -> Option<(usize, usize)> {
+    let (ours, theirs) = (ours.levels(), theirs.levels());
+    if ours.is_empty() || theirs.is_empty() {
+        return Some((0, 0));
+    }
+
+    let mut level = ours.len().min(theirs.len()) - 1;
+    let width = ours[level].len().max(theirs[level].len());
+    let mut index = (0..width).find(|&index| ours[level].get(index) != theirs[level].get(index))?;
+
+    while level > 0 && index < ours[level].len() && index < theirs[level].len() {
+        let child = match (index * 2..index * 2 + 2)
+            .find(|&child| ours[level - 1].get(child) != theirs[level - 1].get(child))
+        {
+            Some(child) => child,
+            None => break,
+        };
+        level -= 1;
+        index = child;
+    }
+    Some((level, index))
+}
+
+/// Format an optional root hash as hex
+fn root_hex(root: &Option<Vec<u8>>) -> String {
+    root.as_ref()
+        .map_or_else(|| "empty tree".to_string(), hex::encode)
+}
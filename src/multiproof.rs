@@ -0,0 +1,502 @@
+use crate::error::{ProofDecodeError, VerificationError};
+use crate::hashing::HashConfig;
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::params::PaddingStrategy;
+use crate::proof::{base64_decoded_len, ProofEncoding};
+use crate::verify::level_sizes;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A proof that several leaves exist in a Merkle tree
+///
+/// Instead of one path per leaf, a multiproof holds every sibling hash needed by any of
+/// the proven leaves exactly once, and omits hashes that the verifier can compute from
+/// the proven leaves themselves.
+///
+/// With the `serde` feature the multiproof serializes as an object of its fields, with
+/// each hash as an array of bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiProof {
+    /// The number of leaves in the tree, which determines its shape
+    pub leaf_count: usize,
+    /// How the tree's odd leaf level is padded, which also determines its shape
+    pub padding: PaddingStrategy,
+    /// The indices of the proven leaves, in ascending order
+    pub indices: Vec<usize>,
+    /// The sibling hashes, level by level from the leaves up and left to right within a level
+    pub hashes: Vec<Vec<u8>>,
+}
+
+impl MultiProof {
+    /// Verify the multiproof against a root hash
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) the tree was built with
+    /// * `leaves` - The data of the proven leaves, in the order of `indices`
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the multiproof is valid, false otherwise
+    pub fn verify(
+        &self,
+        hasher: impl Into<HashConfig>,
+        leaves: &[impl AsRef<[u8]>],
+        root_hash: &[u8],
+    ) -> bool {
+        self.compute_root(&hasher.into(), leaves)
+            .is_ok_and(|root| root == root_hash)
+    }
+
+    /// Recompute the root hash from the proven leaves and the sibling hashes
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration the tree was built with
+    /// * `leaves` - The data of the proven leaves, in the order of `indices`
+    ///
+    /// # Returns
+    ///
+    /// The computed root hash, or an error if the multiproof is malformed
+    pub(crate) fn compute_root(
+        &self,
+        hasher: &HashConfig,
+        leaves: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<u8>, VerificationError> {
+        let well_formed = !self.indices.is_empty()
+            && leaves.len() == self.indices.len()
+            && self.indices.windows(2).all(|pair| pair[0] < pair[1])
+            && self
+                .indices
+                .last()
+                .is_some_and(|&last| last < self.leaf_count);
+        if !well_formed {
+            return Err(VerificationError::InvalidMultiProof);
+        }
+        for (step, hash) in self.hashes.iter().enumerate() {
+            if hash.len() != hasher.digest_len() {
+                return Err(VerificationError::InvalidSiblingLength {
+                    step,
+                    expected: hasher.digest_len(),
+                    actual: hash.len(),
+                });
+            }
+        }
+
+        let mut current: Vec<(usize, Vec<u8>)> = self
+            .indices
+            .iter()
+            .zip(leaves)
+            .map(|(&index, data)| (index, hasher.hash_leaf(data.as_ref())))
+            .collect();
+        let mut hashes = self.hashes.iter();
+
+        let sizes = level_sizes(self.leaf_count, self.padding);
+        for &size in &sizes[..sizes.len() - 1] {
+            let mut next = Vec::with_capacity(current.len());
+            let mut i = 0;
+            while i < current.len() {
+                let (index, hash) = &current[i];
+                let sibling = index ^ 1;
+
+                let parent = if sibling >= size {
+                    // The last node of an odd-sized level is carried up
+                    hash.clone()
+                } else if index.is_multiple_of(2)
+                    && current.get(i + 1).map(|next| next.0) == Some(sibling)
+                {
+                    i += 1;
+                    hasher.hash_pair(hash, &current[i].1)
+                } else {
+                    let sibling_hash = hashes.next().ok_or(VerificationError::InvalidMultiProof)?;
+                    if index.is_multiple_of(2) {
+                        hasher.hash_pair(hash, sibling_hash)
+                    } else {
+                        hasher.hash_pair(sibling_hash, hash)
+                    }
+                };
+
+                next.push((index / 2, parent));
+                i += 1;
+            }
+            current = next;
+        }
+
+        if hashes.next().is_some() {
+            return Err(VerificationError::InvalidMultiProof);
+        }
+        Ok(current.swap_remove(0).1)
+    }
+
+    /// Encode the multiproof in its compact binary form
+    ///
+    /// The leaf count comes first as a little-endian u64, then the padding as one byte
+    /// (0 for `DuplicateLast`, 1 for `CarryUp`, 2 for `Complete` followed by a byte
+    /// holding the depth), then the number of indices as a little-endian u32 and each
+    /// index as a little-endian u64. The hashes follow, each as one byte holding its
+    /// length and the hash itself, up to the end.
+    ///
+    /// # Returns
+    ///
+    /// The encoded multiproof
+    ///
+    /// # Panics
+    ///
+    /// Panics if a hash is longer than 255 bytes, which no supported hash function
+    /// produces, or if there are more than `u32::MAX` indices
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.serialized_size(ProofEncoding::Binary));
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        match self.padding {
+            PaddingStrategy::DuplicateLast => bytes.push(0),
+            PaddingStrategy::CarryUp => bytes.push(1),
+            PaddingStrategy::Complete { depth } => bytes.extend_from_slice(&[2, depth]),
+        }
+        let count = u32::try_from(self.indices.len()).expect("more than u32::MAX indices");
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for &index in &self.indices {
+            bytes.extend_from_slice(&(index as u64).to_le_bytes());
+        }
+        for hash in &self.hashes {
+            bytes.push(u8::try_from(hash.len()).expect("hash longer than 255 bytes"));
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    /// Decode a multiproof from its compact binary form (see `to_bytes`)
+    ///
+    /// The default `DecodeLimits` apply; see `from_bytes_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded multiproof
+    ///
+    /// # Returns
+    ///
+    /// The multiproof, or the reason the bytes are not a valid encoding
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofDecodeError> {
+        Self::from_bytes_with(bytes, &DecodeLimits::default())
+    }
+
+    /// Decode a multiproof from its compact binary form within limits
+    ///
+    /// The input's length is checked against `max_total_bytes`, the leaf count and the
+    /// number of indices against `max_leaves`, and the height of the tree against
+    /// `max_proof_depth`, each before anything is allocated for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded multiproof
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The multiproof, `ProofDecodeError::LimitExceeded` if the input exceeds a limit, or
+    /// the reason the bytes are not a valid encoding
+    pub fn from_bytes_with(bytes: &[u8], limits: &DecodeLimits) -> Result<Self, ProofDecodeError> {
+        let limit = |which, requested| {
+            limits
+                .check_u64(which, requested)
+                .map_err(ProofDecodeError::LimitExceeded)
+        };
+        limit(DecodeLimit::TotalBytes, bytes.len() as u64)?;
+        let (leaf_count, rest) = split_u64(bytes)?;
+        limit(DecodeLimit::Leaves, leaf_count)?;
+        let (padding, mut rest) = match rest {
+            [0, rest @ ..] => (PaddingStrategy::DuplicateLast, rest),
+            [1, rest @ ..] => (PaddingStrategy::CarryUp, rest),
+            [2, depth, rest @ ..] => (PaddingStrategy::Complete { depth: *depth }, rest),
+            [byte, ..] if *byte > 2 => {
+                return Err(ProofDecodeError::InvalidPadding { byte: *byte })
+            }
+            _ => return Err(ProofDecodeError::Truncated),
+        };
+        let height = match padding {
+            PaddingStrategy::Complete { depth } => u64::from(depth),
+            _ => u64::from(u64::BITS - leaf_count.saturating_sub(1).leading_zeros()),
+        };
+        limit(DecodeLimit::ProofDepth, height)?;
+        let count = match rest {
+            [a, b, c, d, tail @ ..] => {
+                rest = tail;
+                u32::from_le_bytes([*a, *b, *c, *d]) as usize
+            }
+            _ => return Err(ProofDecodeError::Truncated),
+        };
+        limit(DecodeLimit::Leaves, count as u64)?;
+        // Checked before allocating, so a forged count cannot reserve more than the input
+        if rest.len() / 8 < count {
+            return Err(ProofDecodeError::Truncated);
+        }
+        let mut indices = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (index, tail) = split_u64(rest)?;
+            indices.push(usize::try_from(index).map_err(|_| ProofDecodeError::Truncated)?);
+            rest = tail;
+        }
+        let mut hashes = Vec::new();
+        while let [len, tail @ ..] = rest {
+            let len = usize::from(*len);
+            if tail.len() < len {
+                return Err(ProofDecodeError::Truncated);
+            }
+            hashes.push(tail[..len].to_vec());
+            rest = &tail[len..];
+        }
+        Ok(MultiProof {
+            leaf_count: usize::try_from(leaf_count).map_err(|_| ProofDecodeError::Truncated)?,
+            padding,
+            indices,
+            hashes,
+        })
+    }
+
+    /// Encode the multiproof's compact binary form as lowercase hexadecimal
+    ///
+    /// # Returns
+    ///
+    /// The hex-encoded multiproof
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    /// Decode a multiproof from the hexadecimal form of its compact encoding
+    ///
+    /// The default `DecodeLimits` apply; see `from_hex_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The hex-encoded multiproof
+    ///
+    /// # Returns
+    ///
+    /// The multiproof, or the reason the string is not a valid encoding
+    pub fn from_hex(encoded: &str) -> Result<Self, ProofDecodeError> {
+        Self::from_hex_with(encoded, &DecodeLimits::default())
+    }
+
+    /// Decode a multiproof from the hexadecimal form of its compact encoding within limits
+    ///
+    /// The length of the decoded bytes is checked against `max_total_bytes` before they
+    /// are decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The hex-encoded multiproof
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The multiproof, `ProofDecodeError::LimitExceeded` if the input exceeds a limit, or
+    /// the reason the string is not a valid encoding
+    pub fn from_hex_with(encoded: &str, limits: &DecodeLimits) -> Result<Self, ProofDecodeError> {
+        limits
+            .check(DecodeLimit::TotalBytes, encoded.len() / 2)
+            .map_err(ProofDecodeError::LimitExceeded)?;
+        Self::from_bytes_with(
+            &hex::decode(encoded).map_err(|_| ProofDecodeError::InvalidHex)?,
+            limits,
+        )
+    }
+
+    /// Encode the multiproof's compact binary form as standard padded base64
+    ///
+    /// # Returns
+    ///
+    /// The base64-encoded multiproof
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(self.to_bytes())
+    }
+
+    /// Decode a multiproof from the standard padded base64 form of its compact encoding
+    ///
+    /// The default `DecodeLimits` apply; see `from_base64_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The base64-encoded multiproof
+    ///
+    /// # Returns
+    ///
+    /// The multiproof, or the reason the string is not a valid encoding
+    pub fn from_base64(encoded: &str) -> Result<Self, ProofDecodeError> {
+        Self::from_base64_with(encoded, &DecodeLimits::default())
+    }
+
+    /// Decode a multiproof from the standard padded base64 form of its compact encoding
+    /// within limits
+    ///
+    /// The length of the decoded bytes is checked against `max_total_bytes` before they
+    /// are decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The base64-encoded multiproof
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The multiproof, `ProofDecodeError::LimitExceeded` if the input exceeds a limit, or
+    /// the reason the string is not a valid encoding
+    pub fn from_base64_with(
+        encoded: &str,
+        limits: &DecodeLimits,
+    ) -> Result<Self, ProofDecodeError> {
+        limits
+            .check(DecodeLimit::TotalBytes, base64_decoded_len(encoded))
+            .map_err(ProofDecodeError::LimitExceeded)?;
+        Self::from_bytes_with(
+            &BASE64
+                .decode(encoded)
+                .map_err(|_| ProofDecodeError::InvalidBase64)?,
+            limits,
+        )
+    }
+
+    /// Compute the size of the multiproof in an encoding without encoding it
+    ///
+    /// For `ProofEncoding::Json` this is the size of the compact JSON `serde_json::to_vec`
+    /// writes.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The encoding
+    ///
+    /// # Returns
+    ///
+    /// The exact size of the encoded multiproof in bytes
+    pub fn serialized_size(&self, encoding: ProofEncoding) -> usize {
+        let mut size = EncodedSize::default();
+        for &index in &self.indices {
+            size.add_index(index);
+        }
+        for hash in &self.hashes {
+            size.add_hash(hash);
+        }
+        size.total(self.leaf_count, self.padding, encoding)
+    }
+}
+
+/// Running totals from which the size of a multiproof's encodings is computed
+///
+/// Lets the size of a multiproof be updated as indices and hashes are added or removed,
+/// without building or encoding it.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct EncodedSize {
+    /// The number of indices
+    indices: usize,
+    /// The total number of decimal digits of the indices
+    index_digits: usize,
+    /// The number of hashes
+    hashes: usize,
+    /// The total length of the hashes
+    hash_bytes: usize,
+    /// The total size of the hashes in compact JSON
+    hash_json: usize,
+}
+
+impl EncodedSize {
+    /// Count a proven index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index
+    pub(crate) fn add_index(&mut self, index: usize) {
+        self.indices += 1;
+        self.index_digits += decimal_digits(index);
+    }
+
+    /// Count a hash
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash
+    pub(crate) fn add_hash(&mut self, hash: &[u8]) {
+        self.hashes += 1;
+        self.hash_bytes += hash.len();
+        self.hash_json += json_bytes_size(hash);
+    }
+
+    /// Stop counting a hash counted before
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash
+    #[cfg(feature = "build")]
+    pub(crate) fn remove_hash(&mut self, hash: &[u8]) {
+        self.hashes -= 1;
+        self.hash_bytes -= hash.len();
+        self.hash_json -= json_bytes_size(hash);
+    }
+
+    /// Compute the size of the encoded multiproof
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_count` - The number of leaves in the tree
+    /// * `padding` - How the tree is padded
+    /// * `encoding` - The encoding
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes
+    #[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+    pub(crate) fn total(
+        &self,
+        leaf_count: usize,
+        padding: PaddingStrategy,
+        encoding: ProofEncoding,
+    ) -> usize {
+        let padding_bytes = match padding {
+            PaddingStrategy::Complete { .. } => 2,
+            _ => 1,
+        };
+        let binary = 8 + padding_bytes + 4 + 8 * self.indices + self.hashes + self.hash_bytes;
+        match encoding {
+            ProofEncoding::Binary => binary,
+            ProofEncoding::Hex => 2 * binary,
+            ProofEncoding::Base64 => binary.div_ceil(3) * 4,
+            #[cfg(feature = "serde")]
+            ProofEncoding::Json => {
+                let padding_json = match padding {
+                    PaddingStrategy::DuplicateLast => r#""DuplicateLast""#.len(),
+                    PaddingStrategy::CarryUp => r#""CarryUp""#.len(),
+                    PaddingStrategy::Complete { depth } => {
+                        r#"{"Complete":{"depth":}}"#.len() + decimal_digits(depth.into())
+                    }
+                };
+                r#"{"leaf_count":,"padding":,"indices":[],"hashes":[]}"#.len()
+                    + decimal_digits(leaf_count)
+                    + padding_json
+                    + self.index_digits
+                    + self.indices.saturating_sub(1)
+                    + self.hash_json
+                    + self.hashes.saturating_sub(1)
+            }
+        }
+    }
+}
+
+/// Count the decimal digits of a number
+fn decimal_digits(n: usize) -> usize {
+    n.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+/// Compute the size of a byte string written as a compact JSON array of numbers
+fn json_bytes_size(bytes: &[u8]) -> usize {
+    let digits: usize = bytes.iter().map(|&byte| decimal_digits(byte.into())).sum();
+    2 + digits + bytes.len().saturating_sub(1)
+}
+
+/// Split a little-endian u64 off the front of some bytes
+fn split_u64(bytes: &[u8]) -> Result<(u64, &[u8]), ProofDecodeError> {
+    match bytes.split_first_chunk::<8>() {
+        Some((head, rest)) => Ok((u64::from_le_bytes(*head), rest)),
+        None => Err(ProofDecodeError::Truncated),
+    }
+}
@@ -0,0 +1,163 @@
+//! Callbacks run whenever a tree's root changes
+//!
+//! Observers are registered with `MerkleTree::on_root_change` and run once for every
+//! call that mutates the tree, after the mutation is complete, so an application can
+//! publish each new root from one place instead of after every call site.
+
+use crate::merkle_tree::MerkleTree;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+/// The kind of call that changed a tree's root
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+    /// A leaf was appended with `MerkleTree::push` or `MerkleTree::push_with_proof`
+    Push,
+    /// A leaf was replaced with `MerkleTree::update_leaf`
+    Update,
+    /// Leaves were appended with `MerkleTree::extend`
+    Extend,
+    /// A diff was applied with `MerkleTree::apply_diff`
+    Diff,
+}
+
+/// A change of a tree's root, as reported to its observers
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RootChange {
+    /// The root before the call, or None if the tree had no leaves
+    pub old_root: Option<Vec<u8>>,
+    /// The root after the call
+    pub new_root: Vec<u8>,
+    /// The tree's epoch after the call
+    pub epoch: u64,
+    /// The kind of call
+    pub kind: MutationKind,
+    /// The leaves the call appended or replaced (empty if a diff only removed leaves)
+    pub leaves: Range<usize>,
+}
+
+/// A registration of an observer, for removing it with `MerkleTree::remove_observer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverHandle(u64);
+
+/// A callback run on every root change
+type Callback = Arc<dyn Fn(RootChange) + Send + Sync>;
+
+/// The observers registered with a tree
+///
+/// A clone of a tree has none: its roots diverge from the original's as soon as either
+/// is mutated, and the observers were registered for the original.
+#[derive(Default)]
+pub(crate) struct Observers {
+    /// The observers in the order they were registered
+    callbacks: Vec<(ObserverHandle, Callback)>,
+    /// The handle of the next observer
+    next: u64,
+}
+
+impl Observers {
+    /// Register an observer
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - The callback to run on every root change
+    ///
+    /// # Returns
+    ///
+    /// The observer's handle
+    pub(crate) fn add(&mut self, callback: Callback) -> ObserverHandle {
+        let handle = ObserverHandle(self.next);
+        self.next += 1;
+        self.callbacks.push((handle, callback));
+        handle
+    }
+
+    /// Remove an observer
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle returned when the observer was registered
+    ///
+    /// # Returns
+    ///
+    /// True if the observer was registered, false if it had already been removed
+    pub(crate) fn remove(&mut self, handle: ObserverHandle) -> bool {
+        let before = self.callbacks.len();
+        self.callbacks
+            .retain(|(registered, _)| *registered != handle);
+        self.callbacks.len() < before
+    }
+
+    /// Check whether any observer is registered
+    pub(crate) fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    /// Run every observer on a root change
+    ///
+    /// Every observer runs even if an earlier one panics; the first panic is resumed once
+    /// they all have.
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change to report
+    pub(crate) fn notify(&self, change: RootChange) {
+        let mut first_panic = None;
+        for (_, callback) in &self.callbacks {
+            let change = change.clone();
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(change))) {
+                first_panic.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = first_panic {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Register a callback to run whenever the tree's root changes
+    ///
+    /// The callback runs exactly once for each call of `push`, `push_with_proof`,
+    /// `update_leaf`, `extend` or `apply_diff`, after the tree has been changed,
+    /// however many leaves the call appends. A diff that empties the tree has no new
+    /// root, and is not reported. Observers run in the order they were registered. A
+    /// panicking observer cannot corrupt the tree, which is complete by then; the
+    /// remaining observers still run, and the first panic then propagates to the caller
+    /// of the mutating method. Clones of the tree have no observers.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - The callback, given the old and new roots, the epoch and the kind
+    ///   of mutation
+    ///
+    /// # Returns
+    ///
+    /// A handle for removing the observer with `remove_observer`
+    pub fn on_root_change(
+        &mut self,
+        callback: impl Fn(RootChange) + Send + Sync + 'static,
+    ) -> ObserverHandle {
+        self.observers_mut().add(Arc::new(callback))
+    }
+
+    /// Remove an observer registered with `on_root_change`
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle `on_root_change` returned
+    ///
+    /// # Returns
+    ///
+    /// True if the observer was removed, false if it had already been
+    pub fn remove_observer(&mut self, handle: ObserverHandle) -> bool {
+        self.observers_mut().remove(handle)
+    }
+}
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
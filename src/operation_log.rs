@@ -0,0 +1,251 @@
+use crate::error::{MerkleError, ReplayError};
+use crate::hashing::EmptyRootPolicy;
+use crate::merkle_tree::{BuildOptions, MerkleTree};
+use crate::params::TreeParams;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The kind of mutation a log entry records
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Operation {
+    /// A leaf was appended with `MerkleTree::push`
+    Push,
+    /// A leaf was replaced with `MerkleTree::update_leaf`
+    Update,
+}
+
+/// One mutation of a tree and the roots on either side of it
+///
+/// Roots are those of the tree's leaves: the root before the first push to an empty tree
+/// is None, whatever the tree's `EmptyRootPolicy`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LogEntry {
+    /// The kind of mutation
+    pub operation: Operation,
+    /// The index of the appended or replaced leaf
+    pub index: usize,
+    /// The hash of the leaf's new data
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub leaf_hash: Vec<u8>,
+    /// The root before the mutation, or None if the tree had no leaves
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    pub root_before: Option<Vec<u8>>,
+    /// The root after the mutation
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub root_after: Vec<u8>,
+}
+
+/// A record of every mutation applied to a tree since it was built
+///
+/// Enabled with `MerkleTreeBuilder::operation_log`. Each entry chains to the one before
+/// it through its roots, so the log shows that a sequence of published roots follows
+/// from the initial leaves by the recorded operations, and `MerkleTree::replay` rebuilds
+/// the final tree from the initial leaves and the log, checking every root on the way.
+///
+/// With the `serde` feature the log serializes as an object with hex-encoded hashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OperationLog {
+    /// The parameters of the tree
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    params: TreeParams,
+    /// Whether the tree's leaves were sorted by hash before building
+    #[cfg_attr(feature = "serde", serde(default))]
+    sort_leaves: bool,
+    /// The root the tree reports while it has no leaves
+    #[cfg_attr(feature = "serde", serde(default))]
+    empty_root: EmptyRootPolicy,
+    /// The number of leaves the tree was built with
+    initial_leaf_count: usize,
+    /// The root the tree was built with, or None if it had no leaves
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    initial_root: Option<Vec<u8>>,
+    /// The mutations, oldest first
+    pub(crate) entries: Vec<LogEntry>,
+}
+
+impl OperationLog {
+    /// Start the log of a newly built tree
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The tree, before any mutation
+    /// * `options` - The options the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// A log with no entries
+    pub(crate) fn new(tree: &MerkleTree, options: &BuildOptions) -> Self {
+        OperationLog {
+            params: tree.params(),
+            sort_leaves: options.sort_leaves,
+            empty_root: options.empty_root,
+            initial_leaf_count: tree.leaf_count(),
+            initial_root: tree.root_hash_ref().map(<[u8]>::to_vec),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Append an entry
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The mutation just applied
+    pub(crate) fn record(&mut self, entry: LogEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Get the parameters of the tree the log belongs to
+    ///
+    /// # Returns
+    ///
+    /// The tree's parameters
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get whether the tree's leaves were sorted by hash before building
+    ///
+    /// # Returns
+    ///
+    /// True if the tree was built with `MerkleTreeBuilder::sort_leaves`
+    pub fn sort_leaves(&self) -> bool {
+        self.sort_leaves
+    }
+
+    /// Get the root the tree reports while it has no leaves
+    ///
+    /// # Returns
+    ///
+    /// The tree's empty root policy
+    pub fn empty_root(&self) -> EmptyRootPolicy {
+        self.empty_root
+    }
+
+    /// Get the number of leaves the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The initial leaf count
+    pub fn initial_leaf_count(&self) -> usize {
+        self.initial_leaf_count
+    }
+
+    /// Get the root the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The initial root, or None if the tree was built without leaves
+    pub fn initial_root(&self) -> Option<&[u8]> {
+        self.initial_root.as_deref()
+    }
+
+    /// Get the recorded mutations
+    ///
+    /// # Returns
+    ///
+    /// The entries, oldest first
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    /// Get the root after the last recorded mutation
+    ///
+    /// # Returns
+    ///
+    /// The latest root, or the initial root if nothing was recorded
+    pub fn final_root(&self) -> Option<&[u8]> {
+        match self.entries.last() {
+            Some(entry) => Some(&entry.root_after),
+            None => self.initial_root(),
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Rebuild a tree from its initial leaves and its operation log
+    ///
+    /// The initial leaves are built with the log's parameters, and sorted by hash or
+    /// given an empty root policy if the tree was, and must give the log's initial root.
+    /// Each entry is then applied by its leaf hash, checking
+    /// that the tree's root is the entry's root before it and after it. The log only
+    /// holds leaf hashes, so the rebuilt tree has no data for leaves pushed or updated
+    /// by the log. It keeps logging, starting from a copy of `log`.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_leaves` - The data of the leaves the tree was built with
+    /// * `log` - The tree's operation log
+    ///
+    /// # Returns
+    ///
+    /// The tree after the last entry, or the first point at which the log and the
+    /// replayed tree diverge
+    pub fn replay(
+        initial_leaves: Vec<Vec<u8>>,
+        log: &OperationLog,
+    ) -> Result<MerkleTree, ReplayError> {
+        let options = BuildOptions {
+            sort_leaves: log.sort_leaves,
+            empty_root: log.empty_root,
+            ..BuildOptions::default()
+        };
+        let mut tree = MerkleTree::build_with(initial_leaves, log.params, options, None)
+            .map_err(ReplayError::Build)?;
+        if tree.leaf_count() != log.initial_leaf_count || tree.root_hash_ref() != log.initial_root()
+        {
+            return Err(ReplayError::InitialRootMismatch {
+                expected: log.initial_root.clone(),
+                actual: tree.root_hash_ref().map(<[u8]>::to_vec),
+            });
+        }
+
+        for (position, entry) in log.entries.iter().enumerate() {
+            if tree.root_hash_ref() != entry.root_before.as_deref() {
+                return Err(ReplayError::RootBeforeMismatch {
+                    entry: position,
+                    expected: entry.root_before.clone(),
+                    actual: tree.root_hash_ref().map(<[u8]>::to_vec),
+                });
+            }
+            let invalid = |error| ReplayError::InvalidEntry {
+                entry: position,
+                error,
+            };
+            let digest_len = log.params.hasher.digest_len();
+            if entry.leaf_hash.len() != digest_len {
+                return Err(invalid(MerkleError::InvalidHashLength {
+                    expected: digest_len,
+                    actual: entry.leaf_hash.len(),
+                }));
+            }
+            match entry.operation {
+                Operation::Push => {
+                    let leaf_count = tree.leaf_count();
+                    if entry.index != leaf_count {
+                        return Err(invalid(MerkleError::IndexOutOfRange {
+                            index: entry.index,
+                            leaf_count,
+                        }));
+                    }
+                    tree.try_push_leaf_hash(entry.leaf_hash.clone())
+                        .map_err(invalid)?;
+                }
+                Operation::Update => tree
+                    .update_leaf_hash(entry.index, entry.leaf_hash.clone())
+                    .map_err(invalid)?,
+            }
+            if tree.root_hash_ref() != Some(&entry.root_after[..]) {
+                return Err(ReplayError::RootAfterMismatch {
+                    entry: position,
+                    expected: entry.root_after.clone(),
+                    actual: tree.root_hash_ref().map(<[u8]>::to_vec),
+                });
+            }
+        }
+        tree.attach_log(log.clone());
+        Ok(tree)
+    }
+}
@@ -0,0 +1,207 @@
+use crate::hashing::{HashAlgorithm, HashConfig, HashScheme, PairOrdering, LEAF_PREFIX};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How a level is completed when it holds an odd number of nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PaddingStrategy {
+    /// The last leaf is duplicated (the `MerkleTree::new` behavior)
+    #[default]
+    DuplicateLast,
+    /// The last leaf is carried up to the next level unpaired, like an unpaired node on
+    /// any higher level
+    ///
+    /// This gives the tree shape of Certificate Transparency (RFC 6962) and of many
+    /// proof-of-reserves schemes. A proof has no step at a level where the node has no
+    /// sibling, so proofs for the last leaves can be shorter than the tree's depth.
+    CarryUp,
+    /// The tree is complete with a fixed depth, and the missing leaves are `EMPTY_LEAF`
+    ///
+    /// Every odd-sized level below the root is padded with the root of an empty subtree
+    /// (see `empty_subtree_hashes`), so every proof has exactly `depth` steps. Trees with
+    /// this padding hold at most `2^depth` leaves; see `MerkleTree::new_complete`.
+    Complete {
+        /// The number of levels above the leaves
+        depth: u8,
+    },
+}
+
+impl PaddingStrategy {
+    /// Check whether a leaf level is padded with a duplicate of its last leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_count` - The number of leaves, excluding padding
+    ///
+    /// # Returns
+    ///
+    /// True under `DuplicateLast` with an odd number of leaves greater than one
+    pub(crate) fn duplicates_last(self, leaf_count: usize) -> bool {
+        self == PaddingStrategy::DuplicateLast && leaf_count > 1 && leaf_count % 2 == 1
+    }
+}
+
+/// The parameters a tree was built with
+///
+/// Two parties can only agree on roots and proofs if they use the same parameters, so
+/// trees record them and proof bundles carry them. With the `serde` feature the hash
+/// configuration is flattened, for example:
+///
+/// ```json
+/// {"algorithm": "Sha256", "scheme": "DomainSeparated", "ordering": "Sorted",
+///  "padding": "DuplicateLast", "positional": false}
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct TreeParams {
+    /// The hash function, leaf and branch domain separation, and pair ordering
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub hasher: HashConfig,
+    /// How an odd leaf level is padded
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub padding: PaddingStrategy,
+    /// Whether each leaf's index is hashed together with its data
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub positional: bool,
+}
+
+impl TreeParams {
+    /// Create tree parameters with the default padding
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees)
+    /// * `positional` - Whether each leaf's index is hashed together with its data
+    ///
+    /// # Returns
+    ///
+    /// The tree parameters
+    pub fn new(hasher: impl Into<HashConfig>, positional: bool) -> Self {
+        TreeParams {
+            hasher: hasher.into(),
+            padding: PaddingStrategy::default(),
+            positional,
+        }
+    }
+
+    /// The byte prepended to leaf data before hashing, if any
+    ///
+    /// # Returns
+    ///
+    /// `LEAF_PREFIX` under domain-separated hashing, None otherwise
+    pub fn leaf_prefix(&self) -> Option<u8> {
+        match self.hasher.scheme {
+            HashScheme::Plain => None,
+            HashScheme::DomainSeparated => Some(LEAF_PREFIX),
+        }
+    }
+}
+
+impl From<HashConfig> for TreeParams {
+    fn from(hasher: HashConfig) -> Self {
+        TreeParams::new(hasher, false)
+    }
+}
+
+impl From<HashScheme> for TreeParams {
+    fn from(scheme: HashScheme) -> Self {
+        TreeParams::new(scheme, false)
+    }
+}
+
+/// A frozen, numbered set of tree semantics
+///
+/// Each version pins every parameter that affects roots and proofs. Versions are never
+/// redefined: any future change in semantics, such as a different padding, gets a new
+/// version, so a tagged proof can never silently change meaning. Trees built with
+/// parameters outside every version are unversioned. With the `serde` feature a version
+/// serializes as its number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "u32", try_from = "u32")
+)]
+pub enum TreeVersion {
+    /// SHA-256, plain (unprefixed) hashing, positional pairs, the last leaf duplicated
+    /// and no leaf indices: the behavior of `MerkleTree::new`
+    V1,
+    /// Like `V1`, but with domain-separated hashing: the defaults of
+    /// `MerkleTree::builder()`
+    V2,
+}
+
+impl TreeVersion {
+    /// Every version, oldest first
+    pub const ALL: [TreeVersion; 2] = [TreeVersion::V1, TreeVersion::V2];
+
+    /// Get the version's number
+    ///
+    /// # Returns
+    ///
+    /// The number, starting from 1
+    pub const fn number(self) -> u32 {
+        match self {
+            TreeVersion::V1 => 1,
+            TreeVersion::V2 => 2,
+        }
+    }
+
+    /// Get the parameters the version pins
+    ///
+    /// # Returns
+    ///
+    /// The tree parameters
+    pub fn params(self) -> TreeParams {
+        let scheme = match self {
+            TreeVersion::V1 => HashScheme::Plain,
+            TreeVersion::V2 => HashScheme::DomainSeparated,
+        };
+        TreeParams::new(
+            HashConfig::new(HashAlgorithm::Sha256, scheme, PairOrdering::Positional),
+            false,
+        )
+    }
+
+    /// Find the version matching a set of parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters
+    ///
+    /// # Returns
+    ///
+    /// The version, or None if the parameters are unversioned
+    pub fn of(params: TreeParams) -> Option<Self> {
+        TreeVersion::ALL
+            .into_iter()
+            .find(|version| version.params() == params)
+    }
+}
+
+impl fmt::Display for TreeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.number())
+    }
+}
+
+impl From<TreeVersion> for u32 {
+    fn from(version: TreeVersion) -> Self {
+        version.number()
+    }
+}
+
+impl TryFrom<u32> for TreeVersion {
+    type Error = String;
+
+    fn try_from(number: u32) -> Result<Self, Self::Error> {
+        TreeVersion::ALL
+            .into_iter()
+            .find(|version| version.number() == number)
+            .ok_or_else(|| format!("unknown tree version {}", number))
+    }
+}
@@ -0,0 +1,21 @@
+/// An event reported to a builder's `on_progress` callback during construction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A leaf's data has been hashed
+    LeafHashed {
+        /// The index of the leaf
+        index: usize,
+        /// The number of leaves being hashed
+        total: usize,
+    },
+    /// Every node on a level has been computed
+    ///
+    /// Level 0 is the leaf level (including any padding duplicate) and is reported once all
+    /// leaves are hashed; the last event is for the root's level.
+    LevelCompleted {
+        /// The level, counted from the leaves
+        level: usize,
+        /// The number of nodes on the level
+        nodes: usize,
+    },
+}
@@ -0,0 +1,844 @@
+use crate::error::ProofDecodeError;
+use crate::hashing::PairOrdering;
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::params::{PaddingStrategy, TreeParams, TreeVersion};
+use crate::verify::{expected_proof_len_for_index, MAX_PROOF_STEPS};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+
+/// A proof that a piece of data exists in a Merkle tree
+///
+/// Each step in the proof is:
+/// - A hash value (sibling hash)
+/// - A boolean flag indicating whether the sibling is on the left side
+///
+/// The proof dereferences to its list of steps. Equality and hashing are defined over
+/// the compact encoding (see `to_bytes`) of the canonical form (see `canonicalize`), so
+/// two proofs are equal exactly when they encode to the same bytes. Every proof returned
+/// by `MerkleTree::generate_proof` and its siblings is canonical.
+///
+/// A proof from `MerkleTree::generate_stamped_proof` also carries a `RootStamp` of the
+/// tree state it was made from, so that a verifier holding a different root can report
+/// the root as stale (see `MerkleVerifier::with_root_id`). The root does not commit to
+/// the stamp, so it is ignored by equality, hashing and every check of the proof itself.
+/// The stamp is only encoded by `to_stamped_bytes`.
+///
+/// With the `serde` feature the proof serializes as its list of steps, without the
+/// stamp.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct MerkleProof {
+    /// The sibling hashes and direction flags from the leaf up to the root
+    steps: Vec<(Vec<u8>, bool)>,
+    /// The state of the tree the proof was made from, if known
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stamp: Option<RootStamp>,
+}
+
+/// The length of a `RootId` in bytes
+pub const ROOT_ID_LEN: usize = 8;
+
+/// The version byte that starts the stamped binary form of a proof
+const STAMPED_FORMAT_VERSION: u8 = 1;
+
+/// A short fingerprint of a tree's state: its root, leaf count and epoch
+///
+/// Two states of the same tree have different fingerprints unless nothing about them
+/// differs, so a client comparing the fingerprint of its cached root with the one a proof
+/// was stamped with can tell a stale cache from a bad proof. Fingerprints are for
+/// reporting only: they are short, unkeyed and never make a proof valid.
+///
+/// Displays, and with the `serde` feature serializes, as lowercase hexadecimal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RootId([u8; ROOT_ID_LEN]);
+
+impl RootId {
+    /// Compute the fingerprint of a tree's state
+    ///
+    /// The fingerprint is the first `ROOT_ID_LEN` bytes of
+    /// `SHA-256("merkleproof root id" || le64(leaf_count) || le64(epoch) || root)`,
+    /// whatever hash function the tree uses.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root hash
+    /// * `leaf_count` - The number of leaves
+    /// * `epoch` - The epoch (see `MerkleTree::epoch`)
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint
+    pub fn new(root: &[u8], leaf_count: usize, epoch: u64) -> Self {
+        Self::of_state(root, leaf_count as u64, epoch)
+    }
+
+    /// Compute the fingerprint of a tree's state with a 64-bit leaf count
+    fn of_state(root: &[u8], leaf_count: u64, epoch: u64) -> Self {
+        let digest = Sha256::new()
+            .chain_update(b"merkleproof root id")
+            .chain_update(leaf_count.to_le_bytes())
+            .chain_update(epoch.to_le_bytes())
+            .chain_update(root)
+            .finalize();
+        let mut id = [0; ROOT_ID_LEN];
+        id.copy_from_slice(&digest[..ROOT_ID_LEN]);
+        RootId(id)
+    }
+
+    /// Create a fingerprint from its bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The fingerprint, as returned by `to_bytes`
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint
+    pub fn from_bytes(bytes: [u8; ROOT_ID_LEN]) -> Self {
+        RootId(bytes)
+    }
+
+    /// Get the bytes of the fingerprint
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint's bytes
+    pub fn to_bytes(self) -> [u8; ROOT_ID_LEN] {
+        self.0
+    }
+}
+
+impl fmt::Display for RootId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for RootId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::serde_hex::serialize(&self.0, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for RootId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = crate::serde_hex::deserialize(deserializer)?;
+        let bytes = <[u8; ROOT_ID_LEN]>::try_from(bytes.as_slice()).map_err(|_| {
+            serde::de::Error::invalid_length(bytes.len(), &"an 8-byte root fingerprint")
+        })?;
+        Ok(RootId(bytes))
+    }
+}
+
+/// The state of the tree a proof was made from: its root, leaf count and epoch
+///
+/// Unlike a bare `RootId`, a stamp can be checked: a proof that leads to the stamped
+/// root really was made from a tree with that root, so a verifier holding another root
+/// can report its root as stale rather than the proof as invalid. The stamp does not make
+/// the proof any more trustworthy; anyone can build a tree and stamp its proofs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RootStamp {
+    /// The root hash of the tree
+    pub root: Vec<u8>,
+    /// The number of leaves in the tree
+    pub leaf_count: u64,
+    /// The epoch of the tree (see `MerkleTree::epoch`)
+    pub epoch: u64,
+}
+
+impl RootStamp {
+    /// Compute the fingerprint of the stamped state
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint, as `MerkleTree::root_id` returns it for that state
+    pub fn root_id(&self) -> RootId {
+        RootId::of_state(&self.root, self.leaf_count, self.epoch)
+    }
+
+    /// Compute the size of the stamp in a proof's stamped binary form
+    fn binary_size(&self) -> usize {
+        2 + self.root.len() + 16
+    }
+}
+
+/// The longest compact binary encoding of a proof with `MAX_PROOF_STEPS` steps
+const MAX_ENCODED_LEN: usize = MAX_PROOF_STEPS * (2 + u8::MAX as usize);
+
+/// A serialized form of a proof, for computing its size
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProofEncoding {
+    /// The compact binary form of `MerkleProof::to_bytes`
+    Binary,
+    /// The hexadecimal form of `MerkleProof::to_hex`
+    Hex,
+    /// The base64 form of `MerkleProof::to_base64`
+    Base64,
+    /// The compact JSON that `serde_json::to_vec` writes: an array of
+    /// `[[byte, …], is_left]` steps
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl ProofEncoding {
+    /// Compute the size of an encoded proof from the sizes of its steps
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - The number of steps
+    /// * `binary` - The total size of the steps in the binary form
+    /// * `json` - The total size of the steps in JSON, without separating commas
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes
+    #[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+    fn size(self, steps: usize, binary: usize, json: usize) -> usize {
+        match self {
+            ProofEncoding::Binary => binary,
+            ProofEncoding::Hex => 2 * binary,
+            ProofEncoding::Base64 => binary.div_ceil(3) * 4,
+            #[cfg(feature = "serde")]
+            ProofEncoding::Json => 2 + json + steps.saturating_sub(1),
+        }
+    }
+
+    /// Compute the largest size of a proof of a given number of steps
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - The number of steps
+    /// * `digest_len` - The length of every sibling hash
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes, reached in JSON when every byte has three digits and every
+    /// sibling is on the right
+    #[cfg(feature = "build")]
+    pub(crate) fn max_size(self, steps: usize, digest_len: usize) -> usize {
+        let json = json_step_size(digest_len, 3 * digest_len, false);
+        let binary = steps * (2 + digest_len);
+        self.size(steps, binary, steps * json)
+    }
+}
+
+/// Compute the size of a step in compact JSON
+///
+/// # Arguments
+///
+/// * `len` - The length of the sibling hash
+/// * `digits` - The total number of decimal digits of the hash's bytes
+/// * `is_left` - The step's direction flag
+///
+/// # Returns
+///
+/// The size of `[[byte, …], is_left]` in bytes
+fn json_step_size(len: usize, digits: usize, is_left: bool) -> usize {
+    let flag = if is_left { 4 } else { 5 };
+    1 + (2 + digits + len.saturating_sub(1)) + 1 + flag + 1
+}
+
+/// A proof together with what a verifier needs to interpret it
+///
+/// Bundles carry the parameters of the tree the proof was made for, so a verifier using
+/// different parameters reports `VerificationError::ParamsMismatch` instead of a bare
+/// root mismatch.
+///
+/// A bundle may also carry metadata attached to the leaf for display. The root does not
+/// commit to it and verification ignores it, so it must not be trusted.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProofBundle {
+    /// The version of the tree the proof was made for, if its parameters are versioned
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub version: Option<TreeVersion>,
+    /// The parameters of the tree the proof was made for
+    pub params: TreeParams,
+    /// The number of leaves in the tree
+    pub leaf_count: usize,
+    /// The index of the proven leaf
+    pub index: usize,
+    /// The proof
+    pub proof: MerkleProof,
+    /// Metadata attached to the leaf, which the proof does NOT authenticate
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::serde_hex::option"
+        )
+    )]
+    pub unauthenticated_metadata: Option<Vec<u8>>,
+}
+
+/// A proof together with the epoch and root it was generated against
+///
+/// Returned by `MerkleTree::generate_epoch_proof` and checked by
+/// `MerkleTree::verify_at_epoch`, so that a proof handed out just before the tree changed
+/// is still checked against the root it was made for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EpochProof {
+    /// The epoch of the tree the proof was made at
+    pub epoch: u64,
+    /// The root of the tree at that epoch
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub root: Vec<u8>,
+    /// The proof
+    pub proof: MerkleProof,
+}
+
+impl MerkleProof {
+    /// Create a proof from its steps
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - The sibling hashes and direction flags from the leaf up to the root
+    ///
+    /// # Returns
+    ///
+    /// The proof
+    pub fn new(steps: Vec<(Vec<u8>, bool)>) -> Self {
+        MerkleProof { steps, stamp: None }
+    }
+
+    /// Stamp the proof with the state of the tree it was made from
+    ///
+    /// # Arguments
+    ///
+    /// * `stamp` - The tree's root, leaf count and epoch
+    ///
+    /// # Returns
+    ///
+    /// The stamped proof
+    pub fn with_stamp(mut self, stamp: RootStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Get the state of the tree the proof was made from
+    ///
+    /// # Returns
+    ///
+    /// The stamp, or None if the proof was not stamped
+    pub fn stamp(&self) -> Option<&RootStamp> {
+        self.stamp.as_ref()
+    }
+
+    /// Get the fingerprint of the tree the proof was made from
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint, or None if the proof was not stamped with one
+    pub fn root_id(&self) -> Option<RootId> {
+        self.stamp.as_ref().map(RootStamp::root_id)
+    }
+
+    /// Get the canonical form of a proof for a positionally ordered tree
+    ///
+    /// Under `PairOrdering::Positional` every step is consumed by verification and every
+    /// flag decides the hashing order, so nothing can be dropped: the canonical form is
+    /// the proof itself. See `canonicalize_with` for sorted trees.
+    ///
+    /// # Returns
+    ///
+    /// The canonical proof
+    pub fn canonicalize(&self) -> MerkleProof {
+        self.canonicalize_with(PairOrdering::Positional)
+    }
+
+    /// Get the canonical form of a proof for a tree with the given pair ordering
+    ///
+    /// Under `PairOrdering::Sorted` the direction flags are ignored by verification, so
+    /// two proofs that differ only in their flags prove the same thing. The canonical
+    /// form clears every flag.
+    ///
+    /// # Arguments
+    ///
+    /// * `ordering` - The pair ordering of the tree the proof is for
+    ///
+    /// # Returns
+    ///
+    /// The canonical proof
+    pub fn canonicalize_with(&self, ordering: PairOrdering) -> MerkleProof {
+        match ordering {
+            PairOrdering::Positional => self.clone(),
+            PairOrdering::Sorted => MerkleProof {
+                steps: self.iter().map(|(hash, _)| (hash.clone(), false)).collect(),
+                stamp: self.stamp.clone(),
+            },
+        }
+    }
+
+    /// Encode the proof in its compact binary form
+    ///
+    /// Each step is one byte holding the direction flag (0 or 1), one byte holding the
+    /// length of the sibling hash, and the hash itself. The steps run from the leaf up to
+    /// the root with nothing before or after them, so a stamp is left out (see
+    /// `to_stamped_bytes`).
+    ///
+    /// # Returns
+    ///
+    /// The encoded proof
+    ///
+    /// # Panics
+    ///
+    /// Panics if a sibling hash is longer than 255 bytes, which no supported hash function
+    /// produces
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.binary_size());
+        self.write_steps(&mut bytes);
+        bytes
+    }
+
+    /// Decode a proof from its compact binary form (see `to_bytes`)
+    ///
+    /// Decoding stops as soon as the proof has more than `MAX_PROOF_STEPS` steps, so a
+    /// hostile encoding cannot make the decoder allocate more than a genuine proof needs.
+    /// The default `DecodeLimits` apply; see `from_bytes_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded proof
+    ///
+    /// # Returns
+    ///
+    /// The proof, or the reason the bytes are not a valid encoding
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofDecodeError> {
+        Self::from_bytes_with(bytes, &DecodeLimits::default())
+    }
+
+    /// Decode a proof from its compact binary form within limits
+    ///
+    /// The input's length is checked against `max_total_bytes` before decoding, and
+    /// decoding stops at the first step beyond `max_proof_depth`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded proof
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The proof, `ProofDecodeError::LimitExceeded` if the input exceeds a limit, or the
+    /// reason the bytes are not a valid encoding
+    pub fn from_bytes_with(
+        mut bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<Self, ProofDecodeError> {
+        limits
+            .check(DecodeLimit::TotalBytes, bytes.len())
+            .map_err(ProofDecodeError::LimitExceeded)?;
+        let mut steps = Vec::new();
+        while let [direction, len, rest @ ..] = bytes {
+            if steps.len() == MAX_PROOF_STEPS {
+                return Err(ProofDecodeError::TooDeep {
+                    max: MAX_PROOF_STEPS,
+                });
+            }
+            limits
+                .check(DecodeLimit::ProofDepth, steps.len() + 1)
+                .map_err(ProofDecodeError::LimitExceeded)?;
+            let is_left = match direction {
+                0 => false,
+                1 => true,
+                &byte => return Err(ProofDecodeError::InvalidDirection { byte }),
+            };
+            let len = usize::from(*len);
+            if rest.len() < len {
+                return Err(ProofDecodeError::Truncated);
+            }
+            steps.push((rest[..len].to_vec(), is_left));
+            bytes = &rest[len..];
+        }
+        if !bytes.is_empty() {
+            return Err(ProofDecodeError::Truncated);
+        }
+        Ok(MerkleProof::new(steps))
+    }
+
+    /// Encode a stamped proof in the stamped binary form
+    ///
+    /// The form starts with a version byte (currently 1), then the length of the stamped
+    /// root, the root, and the leaf count and epoch as little-endian 64-bit integers. The
+    /// proof's compact binary form (see `to_bytes`) follows.
+    ///
+    /// # Returns
+    ///
+    /// The encoded proof, or None if the proof is not stamped
+    ///
+    /// # Panics
+    ///
+    /// Panics if a sibling hash or the stamped root is longer than 255 bytes, which no
+    /// supported hash function produces
+    pub fn to_stamped_bytes(&self) -> Option<Vec<u8>> {
+        let stamp = self.stamp.as_ref()?;
+        let len = u8::try_from(stamp.root.len()).expect("root longer than 255 bytes");
+        let mut bytes = Vec::with_capacity(stamp.binary_size() + self.binary_size());
+        bytes.push(STAMPED_FORMAT_VERSION);
+        bytes.push(len);
+        bytes.extend_from_slice(&stamp.root);
+        bytes.extend_from_slice(&stamp.leaf_count.to_le_bytes());
+        bytes.extend_from_slice(&stamp.epoch.to_le_bytes());
+        self.write_steps(&mut bytes);
+        Some(bytes)
+    }
+
+    /// Decode a proof from the stamped binary form (see `to_stamped_bytes`)
+    ///
+    /// The steps are decoded as by `from_bytes`, with the default `DecodeLimits`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded proof
+    ///
+    /// # Returns
+    ///
+    /// The stamped proof, or the reason the bytes are not a valid encoding
+    pub fn from_stamped_bytes(bytes: &[u8]) -> Result<Self, ProofDecodeError> {
+        let [version, len, rest @ ..] = bytes else {
+            return Err(ProofDecodeError::Truncated);
+        };
+        if *version != STAMPED_FORMAT_VERSION {
+            return Err(ProofDecodeError::UnsupportedVersion { version: *version });
+        }
+        let len = usize::from(*len);
+        if rest.len() < len + 16 {
+            return Err(ProofDecodeError::Truncated);
+        }
+        let (root, rest) = rest.split_at(len);
+        let (leaf_count, rest) = rest.split_at(8);
+        let (epoch, steps) = rest.split_at(8);
+        let stamp = RootStamp {
+            root: root.to_vec(),
+            leaf_count: u64::from_le_bytes(leaf_count.try_into().unwrap()),
+            epoch: u64::from_le_bytes(epoch.try_into().unwrap()),
+        };
+        Ok(Self::from_bytes(steps)?.with_stamp(stamp))
+    }
+
+    /// Append the compact binary form of the proof's steps
+    /// Encode the canonical form of the proof for equality and hashing
+    ///
+    /// This is `canonicalize().to_bytes()`, except that a sibling hash of 255 bytes or
+    /// more has its length written as 255 followed by the full length as a little-endian
+    /// `u64`, so that comparing proofs `to_bytes` cannot encode does not panic.
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let canonical = self.canonicalize();
+        let mut bytes = Vec::with_capacity(canonical.binary_size());
+        for (hash, is_left) in canonical.iter() {
+            bytes.push(u8::from(*is_left));
+            match u8::try_from(hash.len()) {
+                Ok(len) if len < u8::MAX => bytes.push(len),
+                _ => {
+                    bytes.push(u8::MAX);
+                    bytes.extend_from_slice(&(hash.len() as u64).to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    fn write_steps(&self, bytes: &mut Vec<u8>) {
+        for (hash, is_left) in self.iter() {
+            let len = u8::try_from(hash.len()).expect("sibling hash longer than 255 bytes");
+            bytes.push(u8::from(*is_left));
+            bytes.push(len);
+            bytes.extend_from_slice(hash);
+        }
+    }
+
+    /// Encode the proof's compact binary form as lowercase hexadecimal
+    ///
+    /// # Returns
+    ///
+    /// The hex-encoded proof
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    /// Decode a proof from the hexadecimal form of its compact encoding
+    ///
+    /// Parsing is strict: whitespace, newlines and a `0x` prefix are all rejected. A
+    /// string too long to encode `MAX_PROOF_STEPS` steps is rejected before it is decoded.
+    /// The default `DecodeLimits` apply; see `from_hex_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The hex-encoded proof
+    ///
+    /// # Returns
+    ///
+    /// The proof, or the reason the string is not a valid encoding
+    pub fn from_hex(encoded: &str) -> Result<Self, ProofDecodeError> {
+        Self::from_hex_with(encoded, &DecodeLimits::default())
+    }
+
+    /// Decode a proof from the hexadecimal form of its compact encoding within limits
+    ///
+    /// The length of the decoded bytes is checked against `max_total_bytes` before they
+    /// are decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The hex-encoded proof
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The proof, `ProofDecodeError::LimitExceeded` if the input exceeds a limit, or the
+    /// reason the string is not a valid encoding
+    pub fn from_hex_with(encoded: &str, limits: &DecodeLimits) -> Result<Self, ProofDecodeError> {
+        if encoded.len() / 2 > MAX_ENCODED_LEN {
+            return Err(ProofDecodeError::TooDeep {
+                max: MAX_PROOF_STEPS,
+            });
+        }
+        limits
+            .check(DecodeLimit::TotalBytes, encoded.len() / 2)
+            .map_err(ProofDecodeError::LimitExceeded)?;
+        Self::from_bytes_with(
+            &hex::decode(encoded).map_err(|_| ProofDecodeError::InvalidHex)?,
+            limits,
+        )
+    }
+
+    /// Encode the proof's compact binary form as standard padded base64
+    ///
+    /// # Returns
+    ///
+    /// The base64-encoded proof
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(self.to_bytes())
+    }
+
+    /// Decode a proof from the standard padded base64 form of its compact encoding
+    ///
+    /// Parsing is strict: whitespace, newlines, missing or excess padding, non-zero
+    /// trailing bits and the URL-safe alphabet are all rejected. A string too long to
+    /// encode `MAX_PROOF_STEPS` steps is rejected before it is decoded. The default
+    /// `DecodeLimits` apply; see `from_base64_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The base64-encoded proof
+    ///
+    /// # Returns
+    ///
+    /// The proof, or the reason the string is not a valid encoding
+    pub fn from_base64(encoded: &str) -> Result<Self, ProofDecodeError> {
+        Self::from_base64_with(encoded, &DecodeLimits::default())
+    }
+
+    /// Decode a proof from the standard padded base64 form of its compact encoding within
+    /// limits
+    ///
+    /// The length of the decoded bytes is checked against `max_total_bytes` before they
+    /// are decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - The base64-encoded proof
+    /// * `limits` - The limits to decode within
+    ///
+    /// # Returns
+    ///
+    /// The proof, `ProofDecodeError::LimitExceeded` if the input exceeds a limit, or the
+    /// reason the string is not a valid encoding
+    pub fn from_base64_with(
+        encoded: &str,
+        limits: &DecodeLimits,
+    ) -> Result<Self, ProofDecodeError> {
+        if encoded.len() / 4 * 3 > MAX_ENCODED_LEN + 2 {
+            return Err(ProofDecodeError::TooDeep {
+                max: MAX_PROOF_STEPS,
+            });
+        }
+        limits
+            .check(DecodeLimit::TotalBytes, base64_decoded_len(encoded))
+            .map_err(ProofDecodeError::LimitExceeded)?;
+        Self::from_bytes_with(
+            &BASE64
+                .decode(encoded)
+                .map_err(|_| ProofDecodeError::InvalidBase64)?,
+            limits,
+        )
+    }
+
+    /// Compute the size of the proof in an encoding without encoding it
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The encoding
+    ///
+    /// # Returns
+    ///
+    /// The exact size of the encoded proof in bytes
+    pub fn serialized_size(&self, encoding: ProofEncoding) -> usize {
+        let binary = self.binary_size();
+        let json = self
+            .iter()
+            .map(|(hash, is_left)| {
+                let digits = hash
+                    .iter()
+                    .map(|&byte| match byte {
+                        0..=9 => 1,
+                        10..=99 => 2,
+                        _ => 3,
+                    })
+                    .sum();
+                json_step_size(hash.len(), digits, *is_left)
+            })
+            .sum();
+        encoding.size(self.len(), binary, json)
+    }
+
+    /// Check that the proof's length fits a leaf of a tree with the default padding
+    ///
+    /// This is a cheap sanity check of a claimed leaf count before any hashing; see
+    /// `consistent_with_padding`.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_count` - The claimed number of leaves
+    /// * `index` - The index of the leaf the proof is for
+    ///
+    /// # Returns
+    ///
+    /// True if the index is in range and the proof has the number of steps its leaf's
+    /// proof has
+    pub fn consistent_with(&self, leaf_count: usize, index: usize) -> bool {
+        self.consistent_with_padding(leaf_count, index, PaddingStrategy::default())
+    }
+
+    /// Check that the proof's length fits a leaf of a tree with the given padding
+    ///
+    /// Only the length is checked. Directions are checked against the index when a
+    /// proof is verified with a known leaf count and index, for example by
+    /// `MerkleVerifier::verify_bundle`.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_count` - The claimed number of leaves
+    /// * `index` - The index of the leaf the proof is for
+    /// * `padding` - How the tree's odd levels are padded
+    ///
+    /// # Returns
+    ///
+    /// True if the index is in range and the proof has the number of steps its leaf's
+    /// proof has
+    pub fn consistent_with_padding(
+        &self,
+        leaf_count: usize,
+        index: usize,
+        padding: PaddingStrategy,
+    ) -> bool {
+        expected_proof_len_for_index(leaf_count, padding, index) == Some(self.len())
+    }
+
+    /// Take the proof's steps
+    ///
+    /// # Returns
+    ///
+    /// The sibling hashes and direction flags from the leaf up to the root
+    pub fn into_steps(self) -> Vec<(Vec<u8>, bool)> {
+        self.steps
+    }
+
+    /// Compute the size of the proof's compact binary form
+    fn binary_size(&self) -> usize {
+        self.iter().map(|(hash, _)| 2 + hash.len()).sum()
+    }
+}
+
+impl PartialEq for MerkleProof {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical_bytes() == other.canonical_bytes()
+    }
+}
+
+impl Eq for MerkleProof {}
+
+impl Hash for MerkleProof {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_bytes().hash(state);
+    }
+}
+
+impl Deref for MerkleProof {
+    type Target = Vec<(Vec<u8>, bool)>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.steps
+    }
+}
+
+impl DerefMut for MerkleProof {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.steps
+    }
+}
+
+impl From<Vec<(Vec<u8>, bool)>> for MerkleProof {
+    fn from(steps: Vec<(Vec<u8>, bool)>) -> Self {
+        MerkleProof::new(steps)
+    }
+}
+
+impl From<MerkleProof> for Vec<(Vec<u8>, bool)> {
+    fn from(proof: MerkleProof) -> Self {
+        proof.steps
+    }
+}
+
+impl FromIterator<(Vec<u8>, bool)> for MerkleProof {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, bool)>>(iter: I) -> Self {
+        MerkleProof::new(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for MerkleProof {
+    type Item = (Vec<u8>, bool);
+    type IntoIter = std::vec::IntoIter<(Vec<u8>, bool)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.steps.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MerkleProof {
+    type Item = &'a (Vec<u8>, bool);
+    type IntoIter = std::slice::Iter<'a, (Vec<u8>, bool)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.steps.iter()
+    }
+}
+
+/// Compute the number of bytes a padded base64 string decodes to, if it is valid
+///
+/// # Arguments
+///
+/// * `encoded` - The base64 string
+///
+/// # Returns
+///
+/// The decoded length
+pub(crate) fn base64_decoded_len(encoded: &str) -> usize {
+    let padding = encoded.bytes().rev().take(2).filter(|&b| b == b'=').count();
+    (encoded.len() / 4 * 3).saturating_sub(padding)
+}
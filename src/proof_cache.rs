@@ -0,0 +1,141 @@
+use crate::metrics::ProofCacheStats;
+use crate::proof::MerkleProof;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// A least-recently-used cache of proofs by leaf index, owned by a tree
+///
+/// The cache sits behind a mutex so that proof generation can fill it through `&self`
+/// while the tree stays `Sync`. A cache with no capacity is disabled and never locked.
+#[derive(Debug, Default)]
+pub(crate) struct ProofCache {
+    /// The largest number of proofs kept (0 disables the cache)
+    capacity: usize,
+    /// The cached proofs and the counters
+    state: Mutex<CacheState>,
+}
+
+/// The contents of a proof cache
+#[derive(Clone, Debug, Default)]
+struct CacheState {
+    /// The cached proof of each leaf, with the tick it was last used at
+    entries: HashMap<usize, (MerkleProof, u64)>,
+    /// The leaf last used at each tick, least recent first
+    recency: BTreeMap<u64, usize>,
+    /// The next tick
+    tick: u64,
+    /// The number of lookups answered from the cache
+    hits: u64,
+    /// The number of lookups that missed
+    misses: u64,
+}
+
+impl ProofCache {
+    /// Create a cache
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The largest number of proofs kept (0 disables the cache)
+    ///
+    /// # Returns
+    ///
+    /// An empty cache
+    pub(crate) fn new(capacity: usize) -> Self {
+        ProofCache {
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Get the largest number of proofs kept
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the proof of a leaf, computing and caching it on a miss
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `compute` - Computes the proof on a miss
+    ///
+    /// # Returns
+    ///
+    /// The proof
+    pub(crate) fn get_or_insert(
+        &self,
+        index: usize,
+        compute: impl FnOnce() -> MerkleProof,
+    ) -> MerkleProof {
+        if self.capacity == 0 {
+            return compute();
+        }
+        let mut state = self.lock();
+        let tick = state.tick;
+        state.tick += 1;
+        if let Some((proof, used)) = state.entries.get_mut(&index) {
+            let (proof, last) = (proof.clone(), std::mem::replace(used, tick));
+            state.recency.remove(&last);
+            state.recency.insert(tick, index);
+            state.hits += 1;
+            return proof;
+        }
+
+        state.misses += 1;
+        drop(state);
+
+        // Compute outside the lock, so that misses on different leaves do not serialize
+        let proof = compute();
+        let mut state = self.lock();
+        if !state.entries.contains_key(&index) {
+            if state.entries.len() == self.capacity {
+                if let Some((_, evicted)) = state.recency.pop_first() {
+                    state.entries.remove(&evicted);
+                }
+            }
+            let tick = state.tick;
+            state.tick += 1;
+            state.entries.insert(index, (proof.clone(), tick));
+            state.recency.insert(tick, index);
+        }
+        proof
+    }
+
+    /// Drop every cached proof, keeping the counters
+    pub(crate) fn clear(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    /// Take a snapshot of the counters
+    pub(crate) fn stats(&self) -> ProofCacheStats {
+        let state = self.lock();
+        ProofCacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len(),
+        }
+    }
+
+    /// Reset the hit and miss counters to zero
+    pub(crate) fn reset_stats(&self) {
+        let mut state = self.lock();
+        state.hits = 0;
+        state.misses = 0;
+    }
+
+    /// Lock the cache's state, recovering it if a thread panicked while holding the lock
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clone for ProofCache {
+    fn clone(&self) -> Self {
+        ProofCache {
+            capacity: self.capacity,
+            state: Mutex::new(self.lock().clone()),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::error::MerkleError;
+use crate::hashing::{HashConfig, PairOrdering};
+use crate::params::PaddingStrategy;
+use crate::proof::MerkleProof;
+use crate::verify::level_sizes;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A Merkle tree reduced to the nodes needed to prove a watch-set of leaves
+///
+/// Created by `MerkleTree::prune`. Proofs for the kept leaves are rebuilt from the stored
+/// hashes, recomputing any sibling that is itself an ancestor of another kept leaf.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PrunedMerkleTree {
+    /// The root hash of the original tree (None if it was empty)
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    root: Option<Vec<u8>>,
+    /// The number of leaves in the original tree
+    leaf_count: usize,
+    /// The configuration the original tree was built with
+    hasher: HashConfig,
+    /// How the original tree's odd leaf level was padded
+    #[cfg_attr(feature = "serde", serde(default))]
+    padding: PaddingStrategy,
+    /// The indices of the kept leaves in ascending order
+    kept: Vec<usize>,
+    /// The retained nodes, sorted by level and then index
+    nodes: Vec<PrunedNode>,
+}
+
+/// A node retained by a pruned tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct PrunedNode {
+    /// The level of the node (0 for leaves)
+    level: usize,
+    /// The index of the node within its level
+    index: usize,
+    /// The hash of the node
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    hash: Vec<u8>,
+}
+
+impl PrunedMerkleTree {
+    /// Create a pruned tree from the retained nodes
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root hash of the original tree
+    /// * `leaf_count` - The number of leaves in the original tree
+    /// * `hasher` - The configuration the original tree was built with
+    /// * `padding` - How the original tree's odd leaf level was padded
+    /// * `kept` - The indices of the kept leaves in ascending order
+    /// * `nodes` - The retained `(level, index, hash)` nodes, including the kept leaves
+    ///
+    /// # Returns
+    ///
+    /// The pruned tree
+    pub(crate) fn new(
+        root: Option<Vec<u8>>,
+        leaf_count: usize,
+        hasher: HashConfig,
+        padding: PaddingStrategy,
+        kept: Vec<usize>,
+        nodes: Vec<(usize, usize, Vec<u8>)>,
+    ) -> Self {
+        let mut nodes: Vec<PrunedNode> = nodes
+            .into_iter()
+            .map(|(level, index, hash)| PrunedNode { level, index, hash })
+            .collect();
+        nodes.sort_by_key(|node| (node.level, node.index));
+
+        PrunedMerkleTree {
+            root,
+            leaf_count,
+            hasher,
+            padding,
+            kept,
+            nodes,
+        }
+    }
+
+    /// Get the root hash of the original tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or None if the original tree was empty
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.root.clone()
+    }
+
+    /// Get the number of leaves in the original tree
+    ///
+    /// # Returns
+    ///
+    /// The leaf count
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the indices of the kept leaves
+    ///
+    /// # Returns
+    ///
+    /// The kept leaf indices in ascending order
+    pub fn kept_indices(&self) -> &[usize] {
+        &self.kept
+    }
+
+    /// Get the number of hashes the pruned tree stores
+    ///
+    /// # Returns
+    ///
+    /// The number of retained node hashes, including the root
+    pub fn stored_hashes(&self) -> usize {
+        self.nodes.len() + usize::from(self.root.is_some())
+    }
+
+    /// Generate a proof for a kept leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf in the original tree
+    ///
+    /// # Returns
+    ///
+    /// A proof identical to the one the original tree would generate, or an error if the
+    /// index is out of range or the leaf was not kept
+    pub fn generate_proof_by_index(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        if index >= self.leaf_count {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                leaf_count: self.leaf_count,
+            });
+        }
+        if self.kept.binary_search(&index).is_err() {
+            return Err(MerkleError::LeafNotRetained { index });
+        }
+
+        let sizes = level_sizes(self.leaf_count, self.padding);
+        let mut proof = MerkleProof::default();
+        let positional = self.hasher.ordering == PairOrdering::Positional;
+        let mut index = index;
+        for (level, &size) in sizes[..sizes.len() - 1].iter().enumerate() {
+            let sibling = index ^ 1;
+            if sibling < size {
+                let hash = self
+                    .node(level, sibling, &sizes)
+                    .ok_or(MerkleError::LeafNotRetained { index })?;
+                proof.push((hash, sibling < index && positional));
+            }
+            index /= 2;
+        }
+
+        Ok(proof)
+    }
+
+    /// Look up a retained node
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The node's hash, or None if it was not retained
+    fn stored(&self, level: usize, index: usize) -> Option<&Vec<u8>> {
+        self.nodes
+            .binary_search_by_key(&(level, index), |node| (node.level, node.index))
+            .ok()
+            .map(|position| &self.nodes[position].hash)
+    }
+
+    /// Get a node's hash, recomputing it from retained descendants if necessary
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    /// * `sizes` - The number of nodes on each level of the original tree
+    ///
+    /// # Returns
+    ///
+    /// The node's hash, or None if it cannot be derived from the retained nodes
+    fn node(&self, level: usize, index: usize, sizes: &[usize]) -> Option<Vec<u8>> {
+        if let Some(hash) = self.stored(level, index) {
+            return Some(hash.clone());
+        }
+        if level == 0 {
+            return None;
+        }
+
+        let left = self.node(level - 1, index * 2, sizes)?;
+        if index * 2 + 1 < sizes[level - 1] {
+            let right = self.node(level - 1, index * 2 + 1, sizes)?;
+            Some(self.hasher.hash_pair(&left, &right))
+        } else {
+            Some(left)
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::merkle_tree::MerkleTree;
+
+/// Find the leaves that differ between a local tree and a remote peer's tree
+///
+/// The remote tree is only reachable through `remote`, which must return the peer's
+/// `MerkleTree::node_hash_at` for a level and index. Starting from the top, the walk only
+/// queries nodes whose subtree is complete in both trees (such a node's hash depends on
+/// nothing but its leaves) and only descends where the hashes differ, so `d` differing
+/// leaves cost O(d·log n) remote lookups. The remote leaf count is found first with O(log
+/// n) lookups on the leaf level.
+///
+/// Both trees must use the same hash scheme.
+///
+/// # Arguments
+///
+/// * `local` - The local tree
+/// * `remote` - A function returning the hash of the remote node at `(level, index)`
+///
+/// # Returns
+///
+/// The sorted indices of the leaves that differ, followed by the range of leaves present
+/// in only one of the trees
+pub fn reconcile(
+    local: &MerkleTree,
+    remote: impl Fn(usize, usize) -> Option<Vec<u8>>,
+) -> Vec<usize> {
+    let local_count = local.leaf_count();
+    let remote_count = remote_leaf_count(&remote);
+    let common = local_count.min(remote_count);
+
+    let mut divergent = Vec::new();
+    if local_count == remote_count && local_count > 0 {
+        let top = local.height() - 1;
+        if remote(top, 0) == local.node_hash_by_index(top, 0) {
+            return divergent;
+        }
+    }
+
+    if common > 0 {
+        // The smallest level whose first node covers every common leaf
+        let mut level = 0;
+        while (1usize << level) < common {
+            level += 1;
+        }
+        descend(local, &remote, common, level, 0, &mut divergent);
+    }
+
+    divergent.extend(common..local_count.max(remote_count));
+    divergent
+}
+
+/// Compare the subtree at `(level, index)`, recording the leaves that differ
+///
+/// # Arguments
+///
+/// * `local` - The local tree
+/// * `remote` - A function returning the hash of a remote node
+/// * `common` - The number of leaves present in both trees
+/// * `level` - The level of the subtree's root
+/// * `index` - The index of the subtree's root within its level
+/// * `divergent` - The list of differing leaf indices to append to
+fn descend(
+    local: &MerkleTree,
+    remote: &impl Fn(usize, usize) -> Option<Vec<u8>>,
+    common: usize,
+    level: usize,
+    index: usize,
+    divergent: &mut Vec<usize>,
+) {
+    let start = index << level;
+    if start >= common {
+        return;
+    }
+
+    // Only a subtree complete in both trees has a hash determined by its leaves alone
+    if start + (1 << level) <= common
+        && remote(level, index) == local.node_hash_by_index(level, index)
+    {
+        return;
+    }
+
+    if level == 0 {
+        divergent.push(index);
+    } else {
+        descend(local, remote, common, level - 1, index * 2, divergent);
+        descend(local, remote, common, level - 1, index * 2 + 1, divergent);
+    }
+}
+
+/// Find the number of leaves in the remote tree
+///
+/// # Arguments
+///
+/// * `remote` - A function returning the hash of a remote node
+///
+/// # Returns
+///
+/// The number of real leaves the remote tree has
+fn remote_leaf_count(remote: &impl Fn(usize, usize) -> Option<Vec<u8>>) -> usize {
+    if remote(0, 0).is_none() {
+        return 0;
+    }
+
+    // Exponential search for an upper bound, then binary search for the first gap
+    let mut high = 1;
+    while remote(0, high).is_some() {
+        high *= 2;
+    }
+    let mut low = high / 2;
+    while low + 1 < high {
+        let mid = low + (high - low) / 2;
+        if remote(0, mid).is_some() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    high
+}
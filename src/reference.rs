@@ -0,0 +1,171 @@
+//! A deliberately naive Merkle tree to check `MerkleTree` against
+//!
+//! Nothing is cached: every root and proof is recomputed from the leaves, and a complete
+//! tree is materialized with every empty leaf. Only the hash primitives are shared with
+//! the crate, so a bug in how `MerkleTree` builds, pads, updates or walks its levels
+//! shows up as a disagreement with this implementation.
+
+use crate::hashing::PairOrdering;
+use crate::merkle_tree::MerkleTree;
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::proof::MerkleProof;
+use crate::scheme::EMPTY_LEAF_DATA;
+
+/// Something whose root and every proof can be listed for comparison
+pub(crate) trait ProofSource {
+    /// The root, or None if there is none
+    fn root(&self) -> Option<Vec<u8>>;
+
+    /// The proof of every leaf, in index order
+    fn all_proofs(&self) -> Vec<MerkleProof>;
+}
+
+impl ProofSource for MerkleTree {
+    fn root(&self) -> Option<Vec<u8>> {
+        self.root_hash()
+    }
+
+    fn all_proofs(&self) -> Vec<MerkleProof> {
+        (0..self.leaf_count())
+            .map(|index| self.proof_by_index(index).expect("every leaf has a proof"))
+            .collect()
+    }
+}
+
+/// A Merkle tree that keeps only its leaves' data
+pub(crate) struct ReferenceTree {
+    /// The parameters the tree is hashed with
+    params: TreeParams,
+    /// The data of each leaf
+    leaves: Vec<Vec<u8>>,
+}
+
+impl ReferenceTree {
+    /// Create a tree
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters to hash with
+    /// * `leaves` - The data of each leaf
+    ///
+    /// # Returns
+    ///
+    /// The tree
+    pub(crate) fn new(params: TreeParams, leaves: Vec<Vec<u8>>) -> Self {
+        ReferenceTree { params, leaves }
+    }
+
+    /// Get the data of each leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaves, in order
+    pub(crate) fn leaves(&self) -> &[Vec<u8>] {
+        &self.leaves
+    }
+
+    /// Append a leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the new leaf
+    pub(crate) fn push(&mut self, data: Vec<u8>) {
+        self.leaves.push(data);
+    }
+
+    /// Replace the data of a leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    /// * `data` - The new data
+    pub(crate) fn update(&mut self, index: usize, data: Vec<u8>) {
+        self.leaves[index] = data;
+    }
+
+    /// Remove a leaf, moving every later leaf down one index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    pub(crate) fn remove(&mut self, index: usize) {
+        self.leaves.remove(index);
+    }
+
+    /// Compute every level from the leaves up
+    ///
+    /// # Returns
+    ///
+    /// The levels, ending with the root's (empty for a tree without a root)
+    fn levels(&self) -> Vec<Vec<Vec<u8>>> {
+        let hasher = self.params.hasher;
+        let mut leaves: Vec<Vec<u8>> = self
+            .leaves
+            .iter()
+            .enumerate()
+            .map(|(index, data)| {
+                if self.params.positional {
+                    let mut input = (index as u64).to_le_bytes().to_vec();
+                    input.extend_from_slice(data);
+                    hasher.hash_leaf(&input)
+                } else {
+                    hasher.hash_leaf(data)
+                }
+            })
+            .collect();
+        match self.params.padding {
+            // Every slot past the last leaf holds an empty leaf
+            PaddingStrategy::Complete { depth } => {
+                leaves.resize(1 << depth, hasher.hash_leaf(&EMPTY_LEAF_DATA));
+            }
+            padding if padding.duplicates_last(leaves.len()) => {
+                leaves.push(leaves.last().unwrap().clone());
+            }
+            _ => {}
+        }
+        if leaves.is_empty() {
+            return Vec::new();
+        }
+
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let below = levels.last().unwrap();
+            let mut level = Vec::new();
+            for pair in 0..below.len().div_ceil(2) {
+                match below.get(2 * pair + 1) {
+                    Some(right) => level.push(hasher.hash_pair(&below[2 * pair], right)),
+                    // A node without a sibling moves up unchanged
+                    None => level.push(below[2 * pair].clone()),
+                }
+            }
+            levels.push(level);
+        }
+        levels
+    }
+}
+
+impl ProofSource for ReferenceTree {
+    fn root(&self) -> Option<Vec<u8>> {
+        Some(self.levels().pop()?.remove(0))
+    }
+
+    fn all_proofs(&self) -> Vec<MerkleProof> {
+        let levels = self.levels();
+        (0..self.leaves.len())
+            .map(|leaf| {
+                let mut steps = Vec::new();
+                let mut index = leaf;
+                for level in &levels[..levels.len() - 1] {
+                    let sibling = index ^ 1;
+                    if let Some(hash) = level.get(sibling) {
+                        let sibling_is_left = sibling < index
+                            && self.params.hasher.ordering == PairOrdering::Positional;
+                        steps.push((hash.clone(), sibling_is_left));
+                    }
+                    index /= 2;
+                }
+                MerkleProof::new(steps)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,254 @@
+use crate::merkle_tree::MerkleTree;
+use std::fmt::Write;
+
+/// Options controlling how `MerkleTree::render_with` draws a tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreePrintOptions {
+    /// The number of levels below the root to expand (None for all)
+    pub max_depth: Option<usize>,
+    /// The number of leaves to draw before the rest are summarized (None for all)
+    pub max_leaves: Option<usize>,
+    /// The number of leading hash bytes to show (None for the whole digest)
+    pub hash_bytes: Option<usize>,
+    /// Whether to show each leaf's data
+    pub show_data: bool,
+    /// Whether to color branches, leaves and the padding leaf with ANSI escape codes
+    /// (only has an effect with the `cli` feature)
+    pub color: bool,
+}
+
+impl Default for TreePrintOptions {
+    /// Everything expanded, 4-byte hashes, data shown and no color, like `print_tree`
+    fn default() -> Self {
+        TreePrintOptions {
+            max_depth: None,
+            max_leaves: None,
+            hash_bytes: Some(4),
+            show_data: true,
+            color: false,
+        }
+    }
+}
+
+/// The kind of node being drawn, which selects its color
+#[derive(Clone, Copy)]
+enum NodeKind {
+    /// An internal node
+    Branch,
+    /// A real leaf
+    Leaf,
+    /// The duplicate added to pad an odd number of leaves
+    Padding,
+}
+
+/// The state of a rendering in progress
+struct Renderer<'a> {
+    /// The tree being drawn
+    tree: &'a MerkleTree,
+    /// The options to draw it with
+    opts: &'a TreePrintOptions,
+    /// The output so far
+    out: String,
+    /// The number of leaves drawn so far
+    leaves_shown: usize,
+    /// The number of leaves skipped because of `max_leaves`
+    leaves_skipped: usize,
+}
+
+impl MerkleTree {
+    /// Draw the tree as text
+    ///
+    /// Children are connected to their parents with box-drawing characters. A node carried
+    /// up unpaired from an odd-sized level is drawn once, at its original level, and the
+    /// duplicate padding the leaf level is marked as such. The empty subtrees padding a
+    /// complete tree are drawn as single nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - What to include and how to format it
+    ///
+    /// # Returns
+    ///
+    /// The drawing, one node per line
+    pub fn render_with(&self, opts: &TreePrintOptions) -> String {
+        if self.is_empty() {
+            return String::from("Empty tree\n");
+        }
+
+        let mut renderer = Renderer {
+            tree: self,
+            opts,
+            out: String::from("Merkle Tree\n"),
+            leaves_shown: 0,
+            leaves_skipped: 0,
+        };
+        renderer.node(self.height() - 1, 0, "", true, 0);
+        if renderer.leaves_skipped > 0 {
+            let _ = writeln!(
+                renderer.out,
+                "… {} more leaves not shown",
+                renderer.leaves_skipped
+            );
+        }
+        renderer.out
+    }
+}
+
+impl Renderer<'_> {
+    /// Draw a node and, depth and leaf limits permitting, its descendants
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node (0 for leaves)
+    /// * `index` - The index of the node within its level
+    /// * `prefix` - The connectors drawn in front of the node's line
+    /// * `last` - Whether the node is its parent's last child
+    /// * `depth` - The number of levels drawn above the node
+    fn node(&mut self, level: usize, index: usize, prefix: &str, last: bool, depth: usize) {
+        let padded_len = self.tree.len();
+        if self.tree.empty_subtree_at(level, index).is_some() {
+            // The padding of a complete tree is drawn without its (empty) descendants
+            let connector = if last { "└── " } else { "├── " };
+            let hash = self.hash(level, index);
+            let line = if level == 0 {
+                format!("Leaf {} (empty): hash={}", index, hash)
+            } else {
+                format!("Empty subtree: hash={}{}", hash, self.label(level, index))
+            };
+            let line = self.paint(NodeKind::Padding, line);
+            let _ = writeln!(self.out, "{}{}{}", prefix, connector, line);
+            return;
+        }
+        if level > 0 && index * 2 + 1 >= self.tree.levels()[level - 1].len() {
+            // A node carried up unpaired is drawn once, at its original level
+            return self.node(level - 1, index * 2, prefix, last, depth);
+        }
+        if level == 0
+            && self
+                .opts
+                .max_leaves
+                .is_some_and(|max| self.leaves_shown >= max)
+        {
+            self.leaves_skipped += 1;
+            return;
+        }
+
+        let connector = if last { "└── " } else { "├── " };
+        let hash = self.hash(level, index);
+        let line = if level == 0 {
+            self.leaves_shown += 1;
+            let data = if !self.opts.show_data {
+                String::new()
+            } else {
+                match self.tree.get_leaf(index.min(self.tree.leaf_count() - 1)) {
+                    Some(data) => format!(" data={:?}", String::from_utf8_lossy(data)),
+                    None => String::from(" data=<cleared>"),
+                }
+            };
+            if index >= self.tree.leaf_count() {
+                self.paint(
+                    NodeKind::Padding,
+                    format!("Leaf {} (padding):{} hash={}", index, data, hash),
+                )
+            } else {
+                self.paint(
+                    NodeKind::Leaf,
+                    format!(
+                        "Leaf {}:{} hash={}{}",
+                        index,
+                        data,
+                        hash,
+                        self.label(level, index)
+                    ),
+                )
+            }
+        } else {
+            let line = format!("Branch: hash={}{}", hash, self.label(level, index));
+            self.paint(NodeKind::Branch, line)
+        };
+        let _ = writeln!(self.out, "{}{}{}", prefix, connector, line);
+
+        if level == 0 {
+            return;
+        }
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        if self.opts.max_depth.is_some_and(|max| depth >= max) {
+            let first = index << level;
+            let covered = ((index + 1) << level).min(padded_len) - first;
+            let _ = writeln!(self.out, "{}└── … {} leaves below", child_prefix, covered);
+            return;
+        }
+        self.node(level - 1, index * 2, &child_prefix, false, depth + 1);
+        self.node(level - 1, index * 2 + 1, &child_prefix, true, depth + 1);
+    }
+
+    /// Format a node's hash according to the options
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The hexadecimal hash, truncated to `hash_bytes` (or the whole digest if shorter)
+    fn hash(&self, level: usize, index: usize) -> String {
+        let hash = &self.tree.levels()[level][index];
+        let shown = self.opts.hash_bytes.unwrap_or(hash.len()).min(hash.len());
+        hex::encode(&hash[..shown])
+    }
+
+    /// Format a node's label, if it has one
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The label as a ` label="…"` suffix, or an empty string
+    fn label(&self, level: usize, index: usize) -> String {
+        self.tree
+            .subtree_label(level, index)
+            .map_or_else(String::new, |label| format!(" label={:?}", label))
+    }
+
+    /// Color a line if colors are enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of node the line describes
+    /// * `line` - The line's text
+    ///
+    /// # Returns
+    ///
+    /// The line, wrapped in ANSI escape codes when coloring
+    #[cfg(feature = "cli")]
+    fn paint(&self, kind: NodeKind, line: String) -> String {
+        if !self.opts.color {
+            return line;
+        }
+        let code = match kind {
+            NodeKind::Branch => "34",
+            NodeKind::Leaf => "32",
+            NodeKind::Padding => "33",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, line)
+    }
+
+    /// Color a line if colors are enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `_kind` - The kind of node the line describes
+    /// * `line` - The line's text
+    ///
+    /// # Returns
+    ///
+    /// The line, unchanged without the `cli` feature
+    #[cfg(not(feature = "cli"))]
+    fn paint(&self, _kind: NodeKind, line: String) -> String {
+        line
+    }
+}
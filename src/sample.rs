@@ -0,0 +1,226 @@
+//! Audit samples: leaves chosen pseudo-randomly by the root they are proven against
+
+use crate::error::{AuditError, VerificationError};
+use crate::hashing::PairOrdering;
+use crate::params::TreeParams;
+use crate::proof::MerkleProof;
+use crate::verify::{
+    fold_proof, positional_leaf, proof_fits_shape, proof_leaf_index, siblings_fit,
+};
+use crate::MerkleTree;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// The block size of SHA-256, which HMAC pads its key to
+const HMAC_BLOCK_LEN: usize = 64;
+
+/// Choose the leaves an audit sample must reveal
+///
+/// The choice depends only on the root, the leaf count and `k`, so a prover cannot pick
+/// which leaves to reveal without changing the root. For each counter `c = 0, 1, 2, …`
+/// a draw `r` is the first 8 bytes, read as little-endian, of
+/// `HMAC-SHA256(key = root, message = le64(c))`. With `n` leaves, a draw of at least
+/// `2^64 - (2^64 mod n)` is rejected, so that every index is equally likely; otherwise
+/// `r mod n` is the next index unless it was already chosen. Drawing stops once
+/// `min(k, n)` distinct indices have been chosen.
+///
+/// # Arguments
+///
+/// * `root` - The root the sample is taken for
+/// * `leaf_count` - The number of leaves in the tree
+/// * `k` - The number of leaves to sample
+///
+/// # Returns
+///
+/// The indices of the sampled leaves, in the order they were drawn
+pub fn audit_sample_indices(root: &[u8], leaf_count: usize, k: usize) -> Vec<usize> {
+    let n = leaf_count as u64;
+    let wanted = k.min(leaf_count);
+    let mut chosen = HashSet::with_capacity(wanted);
+    let mut indices = Vec::with_capacity(wanted);
+    let mut counter = 0u64;
+    while indices.len() < wanted {
+        let mac = hmac_sha256(root, &counter.to_le_bytes());
+        let draw = u64::from_le_bytes(mac[..8].try_into().expect("a digest has 8 bytes"));
+        counter += 1;
+        if let Some(index) = unbiased_index(draw, n) {
+            if chosen.insert(index) {
+                indices.push(index as usize);
+            }
+        }
+    }
+    indices
+}
+
+/// Reduce a uniform 64-bit draw to an index without modulo bias
+///
+/// # Arguments
+///
+/// * `draw` - The draw
+/// * `n` - The number of indices (at least 1)
+///
+/// # Returns
+///
+/// `draw mod n`, or None if the draw falls in the incomplete last block of `n` values
+/// and must be rejected
+pub(crate) fn unbiased_index(draw: u64, n: u64) -> Option<u64> {
+    // 2^64 mod n, computed without 128-bit arithmetic
+    let excess = (u64::MAX % n + 1) % n;
+    (draw <= u64::MAX - excess).then_some(draw % n)
+}
+
+/// Compute HMAC-SHA256 (RFC 2104)
+///
+/// # Arguments
+///
+/// * `key` - The key, hashed first if it is longer than a block
+/// * `message` - The message
+///
+/// # Returns
+///
+/// The 32-byte tag
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; HMAC_BLOCK_LEN];
+    if key.len() > HMAC_BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+impl MerkleTree {
+    /// Verify an audit sample against a root
+    ///
+    /// The expected indices are recomputed from the root (see `audit_sample_indices`), so
+    /// the sample must reveal exactly those leaves, in that order, each with a proof that
+    /// fits the tree's shape and leads to the root.
+    ///
+    /// Under `PairOrdering::Sorted` without positional leaves a proof does not fix the
+    /// index of its leaf, so a sample could reveal any leaves in place of the chosen
+    /// ones. Such parameters are rejected with `AuditError::SampleIndexUnbound`.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters the tree was built with
+    /// * `root` - The root the sample was taken for
+    /// * `leaf_count` - The number of leaves in the tree
+    /// * `k` - The number of leaves the sample should have
+    /// * `samples` - The index, data and proof of each sampled leaf, as returned by
+    ///   `MerkleTree::audit_sample`
+    ///
+    /// # Returns
+    ///
+    /// Ok if the sample is complete and every proof is valid, or the first problem found
+    pub fn verify_audit_sample(
+        params: impl Into<TreeParams>,
+        root: &[u8],
+        leaf_count: usize,
+        k: usize,
+        samples: &[(usize, Vec<u8>, MerkleProof)],
+    ) -> Result<(), AuditError> {
+        let params = params.into();
+        if !binds_index(params) {
+            return Err(AuditError::SampleIndexUnbound);
+        }
+        let expected = audit_sample_indices(root, leaf_count, k);
+        if samples.len() != expected.len() {
+            return Err(AuditError::SampleCountMismatch {
+                expected: expected.len(),
+                actual: samples.len(),
+            });
+        }
+        for (position, ((index, data, proof), &expected)) in
+            samples.iter().zip(&expected).enumerate()
+        {
+            if *index != expected {
+                return Err(AuditError::SampleIndexMismatch {
+                    position,
+                    expected,
+                    actual: *index,
+                });
+            }
+            verify_sampled_leaf(params, root, leaf_count, *index, data, proof).map_err(
+                |error| AuditError::SampleProofInvalid {
+                    index: *index,
+                    error,
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether a tree's proofs fix the index of their leaf
+///
+/// # Arguments
+///
+/// * `params` - The parameters the tree was built with
+///
+/// # Returns
+///
+/// True if the leaves are positional or pairs are ordered by position
+pub(crate) fn binds_index(params: TreeParams) -> bool {
+    params.positional || params.hasher.ordering == PairOrdering::Positional
+}
+
+/// Verify the proof of one sampled leaf
+///
+/// # Arguments
+///
+/// * `params` - The parameters the tree was built with
+/// * `root` - The root
+/// * `leaf_count` - The number of leaves in the tree
+/// * `index` - The index of the leaf
+/// * `data` - The leaf's data
+/// * `proof` - The leaf's proof
+///
+/// # Returns
+///
+/// Ok if the proof is valid for the leaf at the index, or the reason it is not
+fn verify_sampled_leaf(
+    params: TreeParams,
+    root: &[u8],
+    leaf_count: usize,
+    index: usize,
+    data: &[u8],
+    proof: &MerkleProof,
+) -> Result<(), VerificationError> {
+    let hasher = params.hasher;
+    if !siblings_fit(proof, hasher.digest_len())
+        || !proof_fits_shape(proof, leaf_count, params.padding, hasher.ordering)
+    {
+        return Err(VerificationError::ShapeMismatch {
+            steps: proof.len(),
+            leaf_count,
+        });
+    }
+    if hasher.ordering == PairOrdering::Positional {
+        let actual = proof_leaf_index(proof, leaf_count, params.padding).unwrap_or(usize::MAX);
+        if actual != index {
+            return Err(VerificationError::IndexMismatch {
+                expected: index,
+                actual,
+            });
+        }
+    }
+    let leaf_hash = if params.positional {
+        hasher.hash_leaf(&positional_leaf(index, data))
+    } else {
+        hasher.hash_leaf(data)
+    };
+    if fold_proof(&hasher, leaf_hash, proof) == root {
+        Ok(())
+    } else {
+        Err(VerificationError::RootMismatch)
+    }
+}
@@ -0,0 +1,39 @@
+//! The hash schemes, which are shared with the `no_std` fixed-depth trees
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Prefix byte prepended to leaf data under domain-separated hashing
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix byte prepended to the concatenated child hashes under domain-separated hashing
+pub const NODE_PREFIX: u8 = 0x01;
+
+/// The data of an empty leaf: 32 zero bytes
+pub const EMPTY_LEAF_DATA: [u8; 32] = [0; 32];
+
+/// The hash of an empty leaf in a tree built by `MerkleTree::new_complete`
+///
+/// This is `SHA-256([0u8; 32])`, the leaf hash of `EMPTY_LEAF_DATA` under
+/// `HashScheme::Plain`.
+pub const EMPTY_LEAF: [u8; 32] = [
+    0x66, 0x68, 0x7a, 0xad, 0xf8, 0x62, 0xbd, 0x77, 0x6c, 0x8f, 0xc1, 0x8b, 0x8e, 0x9f, 0x8e, 0x20,
+    0x08, 0x97, 0x14, 0x85, 0x6e, 0xe2, 0x33, 0xb3, 0x90, 0x2a, 0x59, 0x1d, 0x0d, 0x5f, 0x29, 0x25,
+];
+
+/// The scheme used to compute leaf and branch hashes
+///
+/// With `Plain` hashing a leaf is `H(data)` and a branch is `H(left || right)`, so a
+/// 64-byte leaf is indistinguishable from a branch. Anyone who knows two sibling hashes
+/// can present their concatenation as a "leaf" together with a shorter proof that
+/// verifies against the same root (a second-preimage attack). `DomainSeparated` hashing
+/// closes this by prefixing leaves and branches with different bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum HashScheme {
+    /// Leaves are `H(data)`, branches are `H(left || right)` (the `MerkleTree::new` behavior)
+    Plain,
+    /// Leaves are `H(0x00 || data)`, branches are `H(0x01 || left || right)`
+    DomainSeparated,
+}
@@ -0,0 +1,162 @@
+//! Serde helpers that encode byte vectors as lowercase hexadecimal strings
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Serialize bytes as a hexadecimal string
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to serialize
+/// * `serializer` - The serde serializer
+///
+/// # Returns
+///
+/// The serializer's result
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+/// Deserialize bytes from a hexadecimal string
+///
+/// # Arguments
+///
+/// * `deserializer` - The serde deserializer
+///
+/// # Returns
+///
+/// The decoded bytes, or an error if the string is not valid hexadecimal
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    hex::decode(encoded).map_err(serde::de::Error::custom)
+}
+
+/// Serde helpers for optional byte vectors, encoded as a hexadecimal string or null
+pub mod option {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serialize optional bytes as a hexadecimal string or null
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes to serialize
+    /// * `serializer` - The serde serializer
+    ///
+    /// # Returns
+    ///
+    /// The serializer's result
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&hex::encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserialize optional bytes from a hexadecimal string or null
+    ///
+    /// # Arguments
+    ///
+    /// * `deserializer` - The serde deserializer
+    ///
+    /// # Returns
+    ///
+    /// The decoded bytes, or an error if the string is not valid hexadecimal
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|encoded| hex::decode(encoded).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Serde helpers for the hashes of tree levels, encoded as lists of hexadecimal strings
+#[cfg(feature = "build")]
+pub mod levels {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize each level as a list of hexadecimal strings
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - The hashes of each level
+    /// * `serializer` - The serde serializer
+    ///
+    /// # Returns
+    ///
+    /// The serializer's result
+    pub fn serialize<S: Serializer>(
+        levels: &[Vec<Vec<u8>>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let encoded: Vec<Vec<String>> = levels
+            .iter()
+            .map(|level| level.iter().map(hex::encode).collect())
+            .collect();
+        encoded.serialize(serializer)
+    }
+
+    /// Deserialize levels from lists of hexadecimal strings
+    ///
+    /// # Arguments
+    ///
+    /// * `deserializer` - The serde deserializer
+    ///
+    /// # Returns
+    ///
+    /// The decoded hashes, or an error if a string is not valid hexadecimal
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<Vec<u8>>>, D::Error> {
+        Vec::<Vec<String>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|level| {
+                level
+                    .into_iter()
+                    .map(|encoded| hex::decode(encoded).map_err(serde::de::Error::custom))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Serde helpers for lists of byte vectors, encoded as lists of hexadecimal strings
+#[cfg(feature = "build")]
+pub mod list {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize each byte vector as a hexadecimal string
+    ///
+    /// # Arguments
+    ///
+    /// * `list` - The byte vectors to serialize
+    /// * `serializer` - The serde serializer
+    ///
+    /// # Returns
+    ///
+    /// The serializer's result
+    pub fn serialize<S: Serializer>(list: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded: Vec<String> = list.iter().map(hex::encode).collect();
+        encoded.serialize(serializer)
+    }
+
+    /// Deserialize byte vectors from a list of hexadecimal strings
+    ///
+    /// # Arguments
+    ///
+    /// * `deserializer` - The serde deserializer
+    ///
+    /// # Returns
+    ///
+    /// The decoded bytes, or an error if a string is not valid hexadecimal
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|encoded| hex::decode(encoded).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
@@ -0,0 +1,116 @@
+//! Request and response types for serving proofs across a service boundary
+
+use crate::error::MerkleError;
+use crate::merkle_tree::MerkleTree;
+use crate::params::TreeParams;
+use crate::proof::{MerkleProof, ProofBundle};
+use serde::{Deserialize, Serialize};
+
+/// How a proof request identifies its leaf
+///
+/// Serializes as an object with one member named after the variant, such as
+/// `{"ByIndex": 2}` or `{"ByHash": "9f86…"}`; hashes and data are hexadecimal.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LeafSelector {
+    /// The leaf at an index
+    ByIndex(u64),
+    /// The first leaf with a hash, as stored on the leaf level (see
+    /// `MerkleTree::generate_proof_for_hash`)
+    ByHash(#[serde(with = "crate::serde_hex")] Vec<u8>),
+    /// The first leaf with some data
+    ByData(#[serde(with = "crate::serde_hex")] Vec<u8>),
+}
+
+/// A request for a proof, as a client sends it to a proof service
+///
+/// Answered by `MerkleTree::handle_proof_request`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProofRequest {
+    /// The leaf to prove
+    pub leaf: LeafSelector,
+    /// Whether the response should also carry a proof bundle (false if left out)
+    #[serde(default)]
+    pub want_bundle: bool,
+}
+
+/// A proof service's answer to a `ProofRequest`
+///
+/// The wire shape is stable: members are only ever added, and only as optional ones.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProofResponse {
+    /// The root the proof leads to
+    #[serde(with = "crate::serde_hex")]
+    pub root: Vec<u8>,
+    /// The index of the proven leaf
+    pub leaf_index: u64,
+    /// The proof
+    pub proof: MerkleProof,
+    /// The proof bundle, if one was requested (left out otherwise)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<ProofBundle>,
+    /// The parameters of the tree
+    pub params: TreeParams,
+}
+
+impl MerkleTree {
+    /// Answer a proof request
+    ///
+    /// This is the body of a typical proof service's request handler: the request is
+    /// deserialized, answered here, and the response or the error's
+    /// `MerkleError::status_code` is sent back.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request
+    ///
+    /// # Returns
+    ///
+    /// The response, `MerkleError::IndexOutOfRange` if the index is out of range,
+    /// `MerkleError::InvalidHashLength` if the hash is not a digest of the tree's length,
+    /// or `MerkleError::LeafNotFound` if no leaf has the hash or data
+    pub fn handle_proof_request(
+        &self,
+        request: &ProofRequest,
+    ) -> Result<ProofResponse, MerkleError> {
+        let index = match &request.leaf {
+            LeafSelector::ByIndex(index) => {
+                let index = usize::try_from(*index).unwrap_or(usize::MAX);
+                if index >= self.leaf_count() {
+                    return Err(MerkleError::IndexOutOfRange {
+                        index,
+                        leaf_count: self.leaf_count(),
+                    });
+                }
+                index
+            }
+            LeafSelector::ByHash(hash) => {
+                let expected = self.params().hasher.digest_len();
+                if hash.len() != expected {
+                    return Err(MerkleError::InvalidHashLength {
+                        expected,
+                        actual: hash.len(),
+                    });
+                }
+                self.index_of_hash(hash).ok_or(MerkleError::LeafNotFound)?
+            }
+            LeafSelector::ByData(data) => self.index_of(data).ok_or(MerkleError::LeafNotFound)?,
+        };
+
+        // The index is in range, so the tree has a root and the leaf has a proof
+        let root = self.root_hash().expect("a tree with leaves has a root");
+        let bundle = request
+            .want_bundle
+            .then(|| self.generate_bundle(index).expect("the index is in range"));
+        let proof = match &bundle {
+            Some(bundle) => bundle.proof.clone(),
+            None => self.proof_by_index(index).expect("the index is in range"),
+        };
+        Ok(ProofResponse {
+            root,
+            leaf_index: index as u64,
+            proof,
+            bundle,
+            params: self.params(),
+        })
+    }
+}
@@ -0,0 +1,493 @@
+//! A vector whose clones share storage until they are written to
+//!
+//! `SharedVec` keeps its elements in a trie of reference-counted nodes, 32 wide, so
+//! cloning it is one reference count increment and writing an element copies only the
+//! nodes on that element's path, `O(log n)` of them. Trees store their leaf data and
+//! level hashes this way, so a snapshot of a tree (see `ConcurrentMerkleTree`) shares
+//! everything but the paths later writes touch.
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// The number of bits of an index consumed by each level of the trie
+const BITS: usize = 5;
+
+/// The number of children of a branch, and of elements of a leaf node
+const WIDTH: usize = 1 << BITS;
+
+/// A node of the trie
+enum Node<T> {
+    /// Up to `WIDTH` elements
+    Leaf(Arc<Vec<T>>),
+    /// Up to `WIDTH` subtries, all full except the last
+    Branch(Arc<Vec<Node<T>>>),
+}
+
+impl<T> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Node::Leaf(elements) => Node::Leaf(Arc::clone(elements)),
+            Node::Branch(children) => Node::Branch(Arc::clone(children)),
+        }
+    }
+}
+
+impl<T> Node<T> {
+    /// Create an empty node
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - The number of branch levels in the node (0 for a leaf node)
+    fn empty(height: usize) -> Self {
+        if height == 0 {
+            Node::Leaf(Arc::new(Vec::with_capacity(WIDTH)))
+        } else {
+            Node::Branch(Arc::new(Vec::with_capacity(WIDTH)))
+        }
+    }
+
+    /// Check whether the node holds no elements
+    fn is_empty(&self) -> bool {
+        match self {
+            Node::Leaf(elements) => elements.is_empty(),
+            Node::Branch(children) => children.is_empty(),
+        }
+    }
+
+    /// Apply a function to every element held only through this node
+    fn for_each_unique(&mut self, f: &mut impl FnMut(&mut T)) {
+        match self {
+            Node::Leaf(elements) => {
+                if let Some(elements) = Arc::get_mut(elements) {
+                    elements.iter_mut().for_each(f);
+                }
+            }
+            Node::Branch(children) => {
+                if let Some(children) = Arc::get_mut(children) {
+                    for child in children {
+                        child.for_each_unique(f);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Append the node's leaf nodes, in order, to a list
+    fn collect_chunks<'a>(&'a self, chunks: &mut Vec<&'a [T]>) {
+        match self {
+            Node::Leaf(elements) => chunks.push(elements),
+            Node::Branch(children) => {
+                for child in children.iter() {
+                    child.collect_chunks(chunks);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Clone> Node<T> {
+    /// Get mutable access to an element, copying the nodes on its path that are shared
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - The number of branch levels in the node
+    /// * `index` - The index of the element, which must be in the node
+    fn get_mut(&mut self, height: usize, index: usize) -> &mut T {
+        match self {
+            Node::Leaf(elements) => &mut Arc::make_mut(elements)[index & (WIDTH - 1)],
+            Node::Branch(children) => {
+                let slot = (index >> (BITS * height)) & (WIDTH - 1);
+                Arc::make_mut(children)[slot].get_mut(height - 1, index)
+            }
+        }
+    }
+
+    /// Append an element, which must belong in the node
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - The number of branch levels in the node
+    /// * `index` - The index the element will have
+    /// * `value` - The element
+    fn push(&mut self, height: usize, index: usize, value: T) {
+        match self {
+            Node::Leaf(elements) => Arc::make_mut(elements).push(value),
+            Node::Branch(children) => {
+                let children = Arc::make_mut(children);
+                let slot = (index >> (BITS * height)) & (WIDTH - 1);
+                if slot == children.len() {
+                    children.push(Node::empty(height - 1));
+                }
+                children[slot].push(height - 1, index, value);
+            }
+        }
+    }
+
+    /// Remove the last element of a non-empty node, dropping any node it leaves empty
+    fn pop(&mut self) -> T {
+        match self {
+            Node::Leaf(elements) => Arc::make_mut(elements)
+                .pop()
+                .expect("a node on the path to the last element is not empty"),
+            Node::Branch(children) => {
+                let children = Arc::make_mut(children);
+                let last = children
+                    .last_mut()
+                    .expect("a node on the path to the last element is not empty");
+                let value = last.pop();
+                if last.is_empty() {
+                    children.pop();
+                }
+                value
+            }
+        }
+    }
+}
+
+/// A vector whose clones share storage until they are written to
+pub(crate) struct SharedVec<T> {
+    /// The trie, or None if the vector is empty
+    root: Option<Node<T>>,
+    /// The number of branch levels above the leaf nodes
+    height: usize,
+    /// The number of elements
+    len: usize,
+}
+
+impl<T> SharedVec<T> {
+    /// Create an empty vector
+    pub(crate) const fn new() -> Self {
+        SharedVec {
+            root: None,
+            height: 0,
+            len: 0,
+        }
+    }
+
+    /// Get the number of elements
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the vector has no elements
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get an element
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the element
+    ///
+    /// # Returns
+    ///
+    /// The element, or None if the index is out of range
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        let mut node = self.root.as_ref()?;
+        let mut height = self.height;
+        loop {
+            match node {
+                Node::Leaf(elements) => return elements.get(index & (WIDTH - 1)),
+                Node::Branch(children) => {
+                    node = &children[(index >> (BITS * height)) & (WIDTH - 1)];
+                    height -= 1;
+                }
+            }
+        }
+    }
+
+    /// Get the last element, or None if the vector is empty
+    pub(crate) fn last(&self) -> Option<&T> {
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    /// Iterate over the elements in order
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        let mut chunks = Vec::with_capacity(self.len.div_ceil(WIDTH));
+        if let Some(root) = &self.root {
+            root.collect_chunks(&mut chunks);
+        }
+        chunks.into_iter().flatten()
+    }
+
+    /// Apply a function to every element no clone shares
+    ///
+    /// Shared elements are skipped, as a clone still holds them; the last vector to hold
+    /// an element reaches it.
+    pub(crate) fn for_each_unique(&mut self, mut f: impl FnMut(&mut T)) {
+        if let Some(root) = &mut self.root {
+            root.for_each_unique(&mut f);
+        }
+    }
+
+    /// Binary search a sorted vector with a comparator
+    ///
+    /// # Returns
+    ///
+    /// `Ok` with the index of a matching element, or `Err` with the index where one
+    /// could be inserted, like `slice::binary_search_by`
+    pub(crate) fn binary_search_by(
+        &self,
+        mut compare: impl FnMut(&T) -> std::cmp::Ordering,
+    ) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match compare(&self[middle]) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(middle),
+            }
+        }
+        Err(low)
+    }
+
+    /// Iterate over a range of the elements in order
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is not within the vector
+    pub(crate) fn range(
+        &self,
+        range: std::ops::Range<usize>,
+    ) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range {:?} out of bounds for a vector of {} elements",
+            range,
+            self.len
+        );
+        range.map(move |index| &self[index])
+    }
+}
+
+impl<T: Clone> SharedVec<T> {
+    /// Get mutable access to an element, copying the nodes on its path that are shared
+    /// with a clone
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the element
+    ///
+    /// # Returns
+    ///
+    /// The element, or None if the index is out of range
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        let height = self.height;
+        Some(self.root.as_mut()?.get_mut(height, index))
+    }
+
+    /// Append an element
+    pub(crate) fn push(&mut self, value: T) {
+        let root = self.root.get_or_insert_with(|| Node::empty(0));
+        if self.len == WIDTH << (BITS * self.height) {
+            let full = std::mem::replace(root, Node::Branch(Arc::new(Vec::with_capacity(WIDTH))));
+            if let Node::Branch(children) = root {
+                Arc::make_mut(children).push(full);
+            }
+            self.height += 1;
+        }
+        root.push(self.height, self.len, value);
+        self.len += 1;
+    }
+
+    /// Remove the last element
+    ///
+    /// # Returns
+    ///
+    /// The element, or None if the vector is empty
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        let root = self.root.as_mut()?;
+        let value = root.pop();
+        self.len -= 1;
+        if self.len == 0 {
+            self.root = None;
+            self.height = 0;
+        } else {
+            self.shrink();
+        }
+        Some(value)
+    }
+
+    /// Remove branch levels that have a single child
+    fn shrink(&mut self) {
+        while self.height > 0 {
+            let Some(Node::Branch(children)) = &self.root else {
+                break;
+            };
+            if children.len() > 1 {
+                break;
+            }
+            self.root = children.first().cloned();
+            self.height -= 1;
+        }
+    }
+
+    /// Shorten the vector to a length, dropping the rest
+    pub(crate) fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            self.pop();
+        }
+    }
+
+    /// Remove the elements from an index on
+    ///
+    /// # Returns
+    ///
+    /// The removed elements, in order
+    pub(crate) fn split_off(&mut self, at: usize) -> Vec<T> {
+        let mut removed = Vec::with_capacity(self.len.saturating_sub(at));
+        while self.len > at {
+            removed.extend(self.pop());
+        }
+        removed.reverse();
+        removed
+    }
+
+    /// Resize the vector, filling new slots with values from a function
+    pub(crate) fn resize_with(&mut self, len: usize, mut fill: impl FnMut() -> T) {
+        self.truncate(len);
+        while self.len < len {
+            self.push(fill());
+        }
+    }
+
+    /// Resize the vector, filling new slots with clones of a value
+    pub(crate) fn resize(&mut self, len: usize, value: T) {
+        self.resize_with(len, || value.clone());
+    }
+
+    /// Copy the elements into a `Vec`
+    pub(crate) fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Clone for SharedVec<T> {
+    fn clone(&self) -> Self {
+        SharedVec {
+            root: self.root.clone(),
+            height: self.height,
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Default for SharedVec<T> {
+    fn default() -> Self {
+        SharedVec::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SharedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for SharedVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for SharedVec<T> {}
+
+impl<T> Index<usize> for SharedVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(value) => value,
+            None => panic!(
+                "index {} out of bounds for a vector of {} elements",
+                index, self.len
+            ),
+        }
+    }
+}
+
+impl<T: Clone> IndexMut<usize> for SharedVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len;
+        match self.get_mut(index) {
+            Some(value) => value,
+            None => panic!(
+                "index {} out of bounds for a vector of {} elements",
+                index, len
+            ),
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for SharedVec<T> {
+    fn from(values: Vec<T>) -> Self {
+        // Fill leaf nodes in order, then group each level into full branches
+        let len = values.len();
+        let mut nodes = group(values.into_iter(), |chunk| Node::Leaf(Arc::new(chunk)));
+        let mut height = 0;
+        while nodes.len() > 1 {
+            nodes = group(nodes.into_iter(), |children| {
+                Node::Branch(Arc::new(children))
+            });
+            height += 1;
+        }
+        SharedVec {
+            root: nodes.pop(),
+            height,
+            len,
+        }
+    }
+}
+
+impl<T> FromIterator<T> for SharedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        SharedVec::from(values.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl<T: Clone> Extend<T> for SharedVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            self.push(value);
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SharedVec<T> {
+    type Item = &'a T;
+    type IntoIter = Box<dyn DoubleEndedIterator<Item = &'a T> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+/// Split values into runs of `WIDTH` and make a node of each
+///
+/// # Arguments
+///
+/// * `values` - The values, in order
+/// * `node` - Makes a node from a run of values
+///
+/// # Returns
+///
+/// The nodes, all full except the last
+fn group<V, T>(
+    mut values: impl ExactSizeIterator<Item = V>,
+    node: impl Fn(Vec<V>) -> Node<T>,
+) -> Vec<Node<T>> {
+    let mut nodes = Vec::with_capacity(values.len().div_ceil(WIDTH));
+    while values.len() > 0 {
+        nodes.push(node(values.by_ref().take(WIDTH).collect()));
+    }
+    nodes
+}
@@ -0,0 +1,242 @@
+//! Trees with a fixed number of slots that are filled in any order
+//!
+//! A `SlottedMerkleTree` is a `MerkleTree` of `capacity` leaves whose every leaf starts as
+//! the leaf hash of `EMPTY_LEAF_DATA`. Filling a slot replaces one leaf and rehashes its
+//! path, so records can be committed as they arrive rather than once all have.
+
+use crate::complete::check_capacity;
+use crate::error::MerkleError;
+use crate::hashing::HashScheme;
+use crate::merkle_tree::MerkleTree;
+use crate::params::TreeParams;
+use crate::proof::{MerkleProof, ProofBundle};
+use crate::scheme::EMPTY_LEAF_DATA;
+use crate::verify::positional_leaf;
+
+/// A Merkle tree of a fixed number of slots, each empty until it is set
+///
+/// The root is always that of a tree of `capacity` leaves in which each empty slot holds
+/// `EMPTY_LEAF_DATA`, built with the same parameters. So a slot's proof, filled or not,
+/// verifies like any proof from such a tree, and an empty slot's proof proves the slot
+/// empty: it is a proof of `EMPTY_LEAF_DATA` at that slot. For the same reason a slot
+/// cannot be set to `EMPTY_LEAF_DATA`.
+///
+/// The capacity need not be a power of two, and the tree's padding applies to it as to
+/// any leaf count:
+///
+/// * `PaddingStrategy::DuplicateLast` duplicates the last slot of an odd level, and the
+///   duplicate follows the slot as it is filled.
+/// * `PaddingStrategy::CarryUp` carries the last node of an odd level up unchanged.
+/// * `PaddingStrategy::Complete` pads up to `2^depth` leaves with empty subtrees. Unless
+///   leaves are positional, these are the same as empty slots, so the root does not
+///   depend on the capacity, only on the depth and the filled slots.
+#[derive(Clone, Debug)]
+pub struct SlottedMerkleTree {
+    /// The tree over the slots
+    tree: MerkleTree,
+    /// Whether each slot has been set
+    filled: Vec<bool>,
+    /// Whether a filled slot may be set again
+    overwrite: bool,
+}
+
+impl SlottedMerkleTree {
+    /// Create a slotted tree with the defaults of `MerkleTree::builder()`
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of slots
+    ///
+    /// # Returns
+    ///
+    /// A tree whose slots are all empty
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero, as a tree has at least one slot; use `with_params`
+    /// to get `MerkleError::NoSlots` instead
+    pub fn new(capacity: usize) -> Self {
+        Self::with_params(
+            capacity,
+            TreeParams::new(HashScheme::DomainSeparated, false),
+        )
+        .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a slotted tree with the given parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of slots
+    /// * `params` - The parameters (or hash configuration) of the tree
+    ///
+    /// # Returns
+    ///
+    /// A tree whose slots are all empty, `MerkleError::NoSlots` if `capacity` is zero, or
+    /// `MerkleError::TooManyLeaves` or `MerkleError::DepthTooLarge` if the tree is
+    /// complete and its depth does not fit
+    pub fn with_params(
+        capacity: usize,
+        params: impl Into<TreeParams>,
+    ) -> Result<Self, MerkleError> {
+        if capacity == 0 {
+            return Err(MerkleError::NoSlots);
+        }
+        let params = params.into();
+        check_capacity(capacity, params.padding)?;
+        let leaf_hashes = if params.positional {
+            (0..capacity)
+                .map(|slot| {
+                    params
+                        .hasher
+                        .hash_leaf(&positional_leaf(slot, &EMPTY_LEAF_DATA))
+                })
+                .collect()
+        } else {
+            vec![params.hasher.hash_leaf(&EMPTY_LEAF_DATA); capacity]
+        };
+        Ok(SlottedMerkleTree {
+            tree: MerkleTree::from_leaf_hashes(leaf_hashes, params),
+            filled: vec![false; capacity],
+            overwrite: false,
+        })
+    }
+
+    /// Allow or forbid setting a slot that is already filled
+    ///
+    /// # Arguments
+    ///
+    /// * `overwrite` - Whether a filled slot may be set again (forbidden by default)
+    ///
+    /// # Returns
+    ///
+    /// The tree
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Fill a slot
+    ///
+    /// Only the slot's path is rehashed: one leaf hash and one pair hash per level.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot to fill
+    /// * `data` - The data of the slot
+    ///
+    /// # Returns
+    ///
+    /// Ok if the slot was filled, `MerkleError::IndexOutOfRange` if there is no such
+    /// slot, `MerkleError::SlotOccupied` if it is filled and overwriting is not allowed,
+    /// or `MerkleError::EmptyLeafData` if the data is `EMPTY_LEAF_DATA`
+    pub fn set(&mut self, slot: usize, data: Vec<u8>) -> Result<(), MerkleError> {
+        let capacity = self.capacity();
+        if slot >= capacity {
+            return Err(MerkleError::IndexOutOfRange {
+                index: slot,
+                leaf_count: capacity,
+            });
+        }
+        if self.filled[slot] && !self.overwrite {
+            return Err(MerkleError::SlotOccupied { slot });
+        }
+        if data == EMPTY_LEAF_DATA {
+            return Err(MerkleError::EmptyLeafData { slot });
+        }
+        self.tree.update_by_index(slot, data)?;
+        self.filled[slot] = true;
+        Ok(())
+    }
+
+    /// Get the data of a slot
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot
+    ///
+    /// # Returns
+    ///
+    /// The data, or None if the slot is empty or out of range
+    pub fn get(&self, slot: usize) -> Option<&[u8]> {
+        self.tree.get_leaf(slot)
+    }
+
+    /// Check whether a slot has been filled
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot
+    ///
+    /// # Returns
+    ///
+    /// True if the slot is filled, false if it is empty or out of range
+    pub fn is_filled(&self, slot: usize) -> bool {
+        self.filled.get(slot).copied().unwrap_or(false)
+    }
+
+    /// Get the number of slots
+    ///
+    /// # Returns
+    ///
+    /// The capacity
+    pub fn capacity(&self) -> usize {
+        self.filled.len()
+    }
+
+    /// Get the number of filled slots
+    ///
+    /// # Returns
+    ///
+    /// The number of slots that have been set
+    pub fn filled_count(&self) -> usize {
+        self.filled.iter().filter(|filled| **filled).count()
+    }
+
+    /// Get the root of the tree
+    ///
+    /// # Returns
+    ///
+    /// The root, which is defined even while every slot is empty
+    pub fn root(&self) -> Vec<u8> {
+        self.tree
+            .root_hash()
+            .expect("a slotted tree has at least one slot")
+    }
+
+    /// Generate a proof for a slot, filled or empty
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot
+    ///
+    /// # Returns
+    ///
+    /// The proof of the slot's data, or of `EMPTY_LEAF_DATA` if it is empty, or None if
+    /// the slot is out of range
+    pub fn generate_proof(&self, slot: usize) -> Option<MerkleProof> {
+        self.tree.proof_by_index(slot)
+    }
+
+    /// Generate a proof bundle for a slot, filled or empty
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot
+    ///
+    /// # Returns
+    ///
+    /// The bundle, or None if the slot is out of range
+    pub fn generate_bundle(&self, slot: usize) -> Option<ProofBundle> {
+        self.tree.generate_bundle(slot)
+    }
+
+    /// Get the tree over the slots
+    ///
+    /// # Returns
+    ///
+    /// The tree, whose leaf count is the capacity
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+}
@@ -0,0 +1,537 @@
+//! A binary snapshot of a tree's levels, readable without loading the tree
+//!
+//! `MerkleTree::to_snapshot` writes the tree's parameters and every level of hashes.
+//! `MerkleTree::from_snapshot` loads a whole snapshot back into a tree. `SnapshotReader`
+//! instead parses only the header and the table of level offsets, then reads the few
+//! hashes each proof needs straight from a byte slice or a file. That suits a proof
+//! service, which would otherwise pay for a full load at startup and keep two copies
+//! of the levels in memory.
+
+use crate::checkpoint::{read_params, write_params, Reader};
+use crate::complete::empty_subtrees;
+use crate::error::SnapshotError;
+use crate::hashing::{EmptyRootPolicy, PairOrdering};
+use crate::limits::{DecodeLimit, DecodeLimits};
+use crate::merkle_tree::MerkleTree;
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::proof::MerkleProof;
+use crate::verify::{is_padded_lone_leaf, level_sizes};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The bytes every snapshot starts with
+const MAGIC: &[u8; 4] = b"MTSN";
+
+/// The version of the snapshot format
+const FORMAT_VERSION: u8 = 1;
+
+/// The longest possible header before the level table: the magic, the version, up to
+/// seven bytes of parameters, the empty root policy, the leaf count and the level count
+const MAX_HEADER_LEN: usize = 4 + 1 + 7 + 1 + 8 + 4;
+
+/// The length of each entry of the level table: an offset and a node count
+const TABLE_ENTRY_LEN: usize = 16;
+
+/// The most levels a tree can have: one per bit of the leaf count, plus the root
+const MAX_LEVELS: usize = usize::BITS as usize + 1;
+
+impl MerkleTree {
+    /// Write the tree to a snapshot
+    ///
+    /// A snapshot holds `"MTSN"`, a format version byte, the tree's parameters (encoded as
+    /// in a checkpoint), its empty root policy, the leaf count as a little-endian `u64`,
+    /// the number of levels as a little-endian `u32`, a table with the offset and node
+    /// count of each level (little-endian `u64`s), and then the hashes of each level from
+    /// the leaves up. Leaf data and metadata are not included. There is no checksum, since
+    /// `SnapshotReader` never reads the whole snapshot; a corrupted hash gives proofs that
+    /// fail verification against the tree's root.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot, to be loaded with `from_snapshot` or read with `SnapshotReader`
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        let params = self.params();
+        let levels = self.levels();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        write_params(&mut bytes, &params);
+        bytes.push(empty_root_code(self.empty_root_policy()));
+        bytes.extend_from_slice(&(self.leaf_count() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+
+        let digest_len = params.hasher.digest_len();
+        let mut offset = bytes.len() + levels.len() * TABLE_ENTRY_LEN;
+        for level in levels {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            offset += level.len() * digest_len;
+        }
+        for hash in levels.iter().flatten() {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    /// Load a tree from a snapshot
+    ///
+    /// The tree has the root and gives the proofs of the tree the snapshot was written
+    /// from, but has no leaf data, as after `clear_leaf_data`. The default `DecodeLimits`
+    /// apply; see `from_snapshot_with`, and `from_snapshot_audited` to also check the
+    /// stored hashes.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - A snapshot returned by `to_snapshot`
+    ///
+    /// # Returns
+    ///
+    /// The tree, or `SnapshotError::Malformed` if the snapshot is truncated, inconsistent
+    /// or uses a hash algorithm this build does not support
+    pub fn from_snapshot(bytes: &[u8]) -> Result<MerkleTree, SnapshotError> {
+        MerkleTree::from_snapshot_with(bytes, &DecodeLimits::default())
+    }
+
+    /// Load a tree from a snapshot within limits
+    ///
+    /// The snapshot is checked as by `SnapshotReader::open_with` before any level is
+    /// loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - A snapshot returned by `to_snapshot`
+    /// * `limits` - The limits to load within
+    ///
+    /// # Returns
+    ///
+    /// The tree, `SnapshotError::LimitExceeded` if the snapshot exceeds a limit, or
+    /// `SnapshotError::Malformed` if it is truncated, inconsistent or uses a hash
+    /// algorithm this build does not support
+    pub fn from_snapshot_with(
+        bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<MerkleTree, SnapshotError> {
+        let reader = SnapshotReader::open_with(bytes, limits)?;
+        let levels = (0..reader.levels.len())
+            .map(|level| {
+                (0..reader.levels[level].count)
+                    .map(|index| reader.node(level, index))
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<Vec<u8>>>, SnapshotError>>()?;
+        Ok(MerkleTree::from_levels(
+            levels,
+            reader.leaf_count,
+            reader.params,
+            reader.empty_root,
+        ))
+    }
+
+    /// Load a tree from a snapshot within limits and audit it
+    ///
+    /// The snapshot's checks only cover its layout, so a snapshot from untrusted storage
+    /// may hold branch hashes that are not the hashes of their children. This recomputes
+    /// every branch and padding node with `audit` before the tree is returned, at the cost
+    /// of one hash per node. The leaves themselves cannot be checked, as a snapshot holds
+    /// no leaf data.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - A snapshot returned by `to_snapshot`
+    /// * `limits` - The limits to load within
+    ///
+    /// # Returns
+    ///
+    /// The tree, `SnapshotError::Audit` if a stored hash is inconsistent, or any error
+    /// of `from_snapshot_with`
+    pub fn from_snapshot_audited(
+        bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<MerkleTree, SnapshotError> {
+        let tree = MerkleTree::from_snapshot_with(bytes, limits)?;
+        tree.audit().map_err(SnapshotError::Audit)?;
+        Ok(tree)
+    }
+}
+
+/// Where a `SnapshotReader` reads a snapshot from
+#[derive(Debug)]
+pub enum SnapshotSource<'a> {
+    /// A snapshot held in memory
+    Bytes(&'a [u8]),
+    /// A snapshot file, opened by the reader
+    Path(PathBuf),
+    /// An open snapshot file
+    File(File),
+}
+
+impl<'a> From<&'a [u8]> for SnapshotSource<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        SnapshotSource::Bytes(bytes)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for SnapshotSource<'a> {
+    fn from(bytes: &'a Vec<u8>) -> Self {
+        SnapshotSource::Bytes(bytes)
+    }
+}
+
+impl From<&Path> for SnapshotSource<'_> {
+    fn from(path: &Path) -> Self {
+        SnapshotSource::Path(path.to_path_buf())
+    }
+}
+
+impl From<PathBuf> for SnapshotSource<'_> {
+    fn from(path: PathBuf) -> Self {
+        SnapshotSource::Path(path)
+    }
+}
+
+impl From<File> for SnapshotSource<'_> {
+    fn from(file: File) -> Self {
+        SnapshotSource::File(file)
+    }
+}
+
+/// The bytes a reader reads from
+#[derive(Debug)]
+enum Source<'a> {
+    /// A snapshot held in memory
+    Bytes(&'a [u8]),
+    /// A snapshot file and its length, locked while seeking and reading
+    File(Mutex<File>, u64),
+}
+
+impl Source<'_> {
+    /// Get the length of the snapshot
+    fn len(&self) -> u64 {
+        match self {
+            Source::Bytes(bytes) => bytes.len() as u64,
+            Source::File(_, len) => *len,
+        }
+    }
+
+    /// Read bytes at an offset
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset of the first byte
+    /// * `len` - The number of bytes
+    ///
+    /// # Returns
+    ///
+    /// The bytes, or an error if they lie past the end of the snapshot
+    fn read_at(&self, offset: u64, len: usize) -> Result<Vec<u8>, SnapshotError> {
+        let in_bounds = offset
+            .checked_add(len as u64)
+            .is_some_and(|end| end <= self.len());
+        if !in_bounds {
+            return Err(malformed("truncated"));
+        }
+        match self {
+            Source::Bytes(bytes) => {
+                let start = offset as usize;
+                Ok(bytes[start..start + len].to_vec())
+            }
+            Source::File(file, _) => {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                let mut buf = vec![0; len];
+                file.seek(SeekFrom::Start(offset))
+                    .and_then(|_| file.read_exact(&mut buf))
+                    .map_err(SnapshotError::Io)?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// The position of one level within a snapshot
+#[derive(Clone, Copy, Debug)]
+struct LevelEntry {
+    /// The offset of the level's first hash
+    offset: u64,
+    /// The number of hashes on the level
+    count: usize,
+}
+
+/// Serves a tree's root and proofs straight from a snapshot
+///
+/// Opening a reader parses only the snapshot's header and level table, checking that
+/// every level lies within the snapshot and has the size the leaf count implies. Each
+/// proof then reads one sibling hash per level from the slice or file, so the levels are
+/// never loaded as a whole. Proofs are identical to those of the tree loaded with
+/// `MerkleTree::from_snapshot`.
+///
+/// A reader over a file locks it for each read, so one reader can serve proofs from
+/// several threads.
+#[derive(Debug)]
+pub struct SnapshotReader<'a> {
+    /// The snapshot
+    source: Source<'a>,
+    /// The parameters of the tree
+    params: TreeParams,
+    /// The root reported while the tree has no leaves
+    empty_root: EmptyRootPolicy,
+    /// The number of leaves, excluding padding
+    leaf_count: usize,
+    /// The position of each level, from the leaves up to the root
+    levels: Vec<LevelEntry>,
+}
+
+impl<'a> SnapshotReader<'a> {
+    /// Open a snapshot
+    ///
+    /// The default `DecodeLimits` apply; see `open_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The snapshot's bytes, the path of a snapshot file, or an open file
+    ///
+    /// # Returns
+    ///
+    /// The reader, `SnapshotError::Io` if the file cannot be read, or
+    /// `SnapshotError::Malformed` if the header or level table is invalid
+    pub fn open(source: impl Into<SnapshotSource<'a>>) -> Result<Self, SnapshotError> {
+        Self::open_with(source, &DecodeLimits::default())
+    }
+
+    /// Open a snapshot within limits
+    ///
+    /// The snapshot's size is checked against `max_total_bytes` before anything is read,
+    /// and its leaf count against `max_leaves` and its height against `max_proof_depth`
+    /// before the level table is read.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The snapshot's bytes, the path of a snapshot file, or an open file
+    /// * `limits` - The limits to open within
+    ///
+    /// # Returns
+    ///
+    /// The reader, `SnapshotError::LimitExceeded` if the snapshot exceeds a limit,
+    /// `SnapshotError::Io` if the file cannot be read, or `SnapshotError::Malformed` if
+    /// the header or level table is invalid
+    pub fn open_with(
+        source: impl Into<SnapshotSource<'a>>,
+        limits: &DecodeLimits,
+    ) -> Result<Self, SnapshotError> {
+        let limit = |which, requested| {
+            limits
+                .check_u64(which, requested)
+                .map_err(SnapshotError::LimitExceeded)
+        };
+        let source = match source.into() {
+            SnapshotSource::Bytes(bytes) => Source::Bytes(bytes),
+            SnapshotSource::Path(path) => {
+                let file = File::open(path).map_err(SnapshotError::Io)?;
+                let len = file.metadata().map_err(SnapshotError::Io)?.len();
+                Source::File(Mutex::new(file), len)
+            }
+            SnapshotSource::File(file) => {
+                let len = file.metadata().map_err(SnapshotError::Io)?.len();
+                Source::File(Mutex::new(file), len)
+            }
+        };
+
+        limit(DecodeLimit::TotalBytes, source.len())?;
+        let header_len = source.len().min(MAX_HEADER_LEN as u64) as usize;
+        let header = source.read_at(0, header_len)?;
+        let mut reader = Reader(&header);
+        let (params, empty_root, leaf_count, level_count) =
+            read_header(&mut reader).map_err(|reason| SnapshotError::Malformed { reason })?;
+        let table_offset = (header_len - reader.0.len()) as u64;
+        limit(DecodeLimit::Leaves, leaf_count as u64)?;
+        limit(
+            DecodeLimit::ProofDepth,
+            level_count.saturating_sub(1) as u64,
+        )?;
+
+        let table = source.read_at(table_offset, level_count * TABLE_ENTRY_LEN)?;
+        let digest_len = params.hasher.digest_len() as u64;
+        let levels = table
+            .chunks_exact(TABLE_ENTRY_LEN)
+            .map(|entry| {
+                let offset = u64::from_le_bytes(entry[..8].try_into().unwrap());
+                let count = u64::from_le_bytes(entry[8..].try_into().unwrap());
+                let in_bounds = count
+                    .checked_mul(digest_len)
+                    .and_then(|len| offset.checked_add(len))
+                    .is_some_and(|end| end <= source.len());
+                if !in_bounds {
+                    return Err(malformed("level lies outside the snapshot"));
+                }
+                let count = usize::try_from(count)
+                    .map_err(|_| malformed("level lies outside the snapshot"))?;
+                Ok(LevelEntry { offset, count })
+            })
+            .collect::<Result<Vec<_>, SnapshotError>>()?;
+
+        // Every level must have the size a tree with this many leaves has
+        let leaf_level_len = levels.first().map_or(0, |level| level.count);
+        let committed = if is_padded_lone_leaf(leaf_count, params.padding, leaf_level_len) {
+            2
+        } else {
+            leaf_count
+        };
+        let consistent = levels
+            .iter()
+            .map(|level| level.count)
+            .eq(level_sizes(committed, params.padding));
+        if !consistent {
+            return Err(malformed("levels do not match the leaf count"));
+        }
+
+        Ok(SnapshotReader {
+            source,
+            params,
+            empty_root,
+            leaf_count,
+            levels,
+        })
+    }
+
+    /// Get the parameters of the tree
+    ///
+    /// # Returns
+    ///
+    /// The parameters the tree was built with
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get the number of leaves in the tree
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves, excluding padding
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the root hash of the tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash as `MerkleTree::root_hash` gives it (None for an empty tree without
+    /// an empty root), or an error if it cannot be read
+    pub fn root(&self) -> Result<Option<Vec<u8>>, SnapshotError> {
+        match (self.levels.len().checked_sub(1), self.params.padding) {
+            (Some(top), _) => self.node(top, 0).map(Some),
+            (None, PaddingStrategy::Complete { depth }) => {
+                Ok(empty_subtrees(&self.params.hasher, depth).pop())
+            }
+            (None, _) => Ok(self.empty_root.root(&self.params.hasher)),
+        }
+    }
+
+    /// Generate a proof for the leaf at a specific index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof `MerkleTree::generate_proof_at` gives,
+    /// `SnapshotError::IndexOutOfRange` if the index is out of range, or an error if a
+    /// hash cannot be read
+    pub fn proof(&self, index: usize) -> Result<MerkleProof, SnapshotError> {
+        if index >= self.leaf_count {
+            return Err(SnapshotError::IndexOutOfRange {
+                index,
+                leaf_count: self.leaf_count,
+            });
+        }
+
+        // The same walk as `path_in_levels`, reading each sibling from the snapshot
+        let mut proof = MerkleProof::default();
+        let mut index = index;
+        let positional = self.params.hasher.ordering == PairOrdering::Positional;
+        for level in 0..self.levels.len() - 1 {
+            let is_left = index.is_multiple_of(2);
+            let sibling_idx = if is_left { index + 1 } else { index - 1 };
+            if sibling_idx < self.levels[level].count {
+                proof.push((self.node(level, sibling_idx)?, !is_left && positional));
+            }
+            index /= 2;
+        }
+        Ok(proof)
+    }
+
+    /// Read the hash of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The hash, or an error if it cannot be read
+    fn node(&self, level: usize, index: usize) -> Result<Vec<u8>, SnapshotError> {
+        let digest_len = self.params.hasher.digest_len();
+        // In bounds, since `open` checked that the whole level lies within the snapshot
+        let offset = self.levels[level].offset + (index * digest_len) as u64;
+        self.source.read_at(offset, digest_len)
+    }
+}
+
+/// Read a snapshot's header, up to the level table
+///
+/// # Arguments
+///
+/// * `reader` - The reader, positioned at the start of the snapshot
+///
+/// # Returns
+///
+/// The parameters, empty root policy, leaf count and number of levels, or the reason the
+/// header is invalid
+fn read_header(reader: &mut Reader) -> Result<(TreeParams, EmptyRootPolicy, usize, usize), String> {
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(String::from("not a snapshot"));
+    }
+    let version = reader.byte()?;
+    if version != FORMAT_VERSION {
+        return Err(format!("unsupported format version {}", version));
+    }
+    let params = read_params(reader, true)?;
+    let empty_root = match reader.byte()? {
+        0 => EmptyRootPolicy::None,
+        1 => EmptyRootPolicy::HashOfEmpty,
+        2 => EmptyRootPolicy::Zero32,
+        _ => return Err(String::from("unknown empty root policy")),
+    };
+    if empty_root.is_ambiguous(params.hasher.scheme) {
+        return Err(String::from(
+            "empty root policy is ambiguous under plain hashing",
+        ));
+    }
+    let leaf_count = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+    let leaf_count =
+        usize::try_from(leaf_count).map_err(|_| String::from("leaf count too large"))?;
+    let level_count = u32::from_le_bytes(reader.take(4)?.try_into().unwrap()) as usize;
+    if level_count > MAX_LEVELS {
+        return Err(String::from("too many levels"));
+    }
+    Ok((params, empty_root, leaf_count, level_count))
+}
+
+/// Get the snapshot code of an empty root policy
+fn empty_root_code(policy: EmptyRootPolicy) -> u8 {
+    match policy {
+        EmptyRootPolicy::None => 0,
+        EmptyRootPolicy::HashOfEmpty => 1,
+        EmptyRootPolicy::Zero32 => 2,
+    }
+}
+
+/// Build a malformed-snapshot error
+fn malformed(reason: &str) -> SnapshotError {
+    SnapshotError::Malformed {
+        reason: String::from(reason),
+    }
+}
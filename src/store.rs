@@ -0,0 +1,632 @@
+//! Trees whose levels live in a pluggable store
+//!
+//! `MerkleTree` keeps every level in memory. `StoredMerkleTree` instead reads and writes
+//! node hashes through a `LevelStore`, so the levels of a tree bigger than memory can
+//! live in a database or on disk while this crate handles the hashing, padding and
+//! proofs. Roots and proofs are identical to those of a `MerkleTree` with the same
+//! parameters.
+
+use crate::complete::{check_capacity, empty_subtrees};
+use crate::error::MerkleError;
+use crate::hashing::PairOrdering;
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::proof::MerkleProof;
+use crate::verify::{
+    fold_proof, level_sizes, positional_leaf, proof_fits_shape, proof_leaf_index, siblings_fit,
+};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Storage for the node hashes of a tree, addressed by level and index
+///
+/// Level 0 holds the leaf hashes (including padding) and the last level the root. A
+/// `StoredMerkleTree` only ever writes each level from the start without gaps, so a
+/// level's length is one past the highest index written to it. Every method can fail,
+/// for a store backed by a disk or a database; the tree passes the error on as
+/// `MerkleError::Storage`.
+pub trait LevelStore {
+    /// Get a node's hash
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level
+    ///
+    /// # Returns
+    ///
+    /// The hash, None if the node was never written, or the error that prevented
+    /// reading it
+    fn get(&self, level: usize, index: usize) -> io::Result<Option<Vec<u8>>>;
+
+    /// Write a node's hash, replacing any previous one
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the node
+    /// * `index` - The index of the node within its level, at most the level's length
+    /// * `hash` - The hash
+    ///
+    /// # Returns
+    ///
+    /// Ok, or the error that prevented writing the hash
+    fn put(&mut self, level: usize, index: usize, hash: Vec<u8>) -> io::Result<()>;
+
+    /// Get the number of nodes written to a level
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level
+    ///
+    /// # Returns
+    ///
+    /// The level's length, 0 for a level never written, or the error that prevented
+    /// reading it
+    fn len(&self, level: usize) -> io::Result<usize>;
+}
+
+/// A level store holding every level in memory
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryLevelStore {
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl MemoryLevelStore {
+    /// Create an empty store
+    ///
+    /// # Returns
+    ///
+    /// A new store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LevelStore for MemoryLevelStore {
+    fn get(&self, level: usize, index: usize) -> io::Result<Option<Vec<u8>>> {
+        Ok(self
+            .levels
+            .get(level)
+            .and_then(|nodes| nodes.get(index))
+            .cloned())
+    }
+
+    fn put(&mut self, level: usize, index: usize, hash: Vec<u8>) -> io::Result<()> {
+        if self.levels.len() <= level {
+            self.levels.resize(level + 1, Vec::new());
+        }
+        let nodes = &mut self.levels[level];
+        if index == nodes.len() {
+            nodes.push(hash);
+        } else {
+            nodes[index] = hash;
+        }
+        Ok(())
+    }
+
+    fn len(&self, level: usize) -> io::Result<usize> {
+        Ok(self.levels.get(level).map_or(0, Vec::len))
+    }
+}
+
+/// A level store keeping each level in a flat file of fixed-size hashes
+///
+/// Level `n` is stored in `level-n.bin` in the store's directory, with the hash of node
+/// `i` at offset `i * hash_len`. Reopening a directory picks up the levels already in it.
+#[derive(Debug)]
+pub struct FileLevelStore {
+    dir: PathBuf,
+    hash_len: usize,
+    files: Vec<File>,
+}
+
+impl FileLevelStore {
+    /// Open a store in a directory, creating the directory if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the level files
+    /// * `hash_len` - The length of every stored hash (the tree's digest length)
+    ///
+    /// # Returns
+    ///
+    /// The store, or the error that prevented opening the directory or its files
+    pub fn open(dir: impl Into<PathBuf>, hash_len: usize) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut store = FileLevelStore {
+            dir,
+            hash_len,
+            files: Vec::new(),
+        };
+        while store.path(store.files.len()).exists() {
+            let file = store.open_level(store.files.len())?;
+            store.files.push(file);
+        }
+        Ok(store)
+    }
+
+    /// Get the path of a level's file
+    fn path(&self, level: usize) -> PathBuf {
+        self.dir.join(format!("level-{}.bin", level))
+    }
+
+    /// Open (or create) a level's file for reading and writing
+    fn open_level(&self, level: usize) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.path(level))
+    }
+}
+
+impl LevelStore for FileLevelStore {
+    fn get(&self, level: usize, index: usize) -> io::Result<Option<Vec<u8>>> {
+        if index >= self.len(level)? {
+            return Ok(None);
+        }
+        let mut file = &self.files[level];
+        let mut hash = vec![0; self.hash_len];
+        file.seek(SeekFrom::Start((index * self.hash_len) as u64))?;
+        file.read_exact(&mut hash)?;
+        Ok(Some(hash))
+    }
+
+    /// A hash of the wrong length is rejected with `io::ErrorKind::InvalidInput`
+    fn put(&mut self, level: usize, index: usize, hash: Vec<u8>) -> io::Result<()> {
+        if hash.len() != self.hash_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "hash of {} bytes in a store of {}-byte hashes",
+                    hash.len(),
+                    self.hash_len
+                ),
+            ));
+        }
+        while self.files.len() <= level {
+            let file = self.open_level(self.files.len())?;
+            self.files.push(file);
+        }
+        let file = &mut self.files[level];
+        file.seek(SeekFrom::Start((index * self.hash_len) as u64))?;
+        file.write_all(&hash)
+    }
+
+    fn len(&self, level: usize) -> io::Result<usize> {
+        match self.files.get(level) {
+            Some(file) => Ok(file.metadata()?.len() as usize / self.hash_len),
+            None => Ok(0),
+        }
+    }
+}
+
+/// A Merkle tree whose node hashes are kept in a `LevelStore`
+///
+/// The tree itself holds only its parameters and leaf count; every hash is read from
+/// and written to the store. Leaves are appended one at a time or in bulk, and each
+/// append rewrites only the new leaf's path to the root. An error from the store is
+/// returned as `MerkleError::Storage`.
+#[derive(Debug)]
+pub struct StoredMerkleTree<S: LevelStore> {
+    store: S,
+    params: TreeParams,
+    leaf_count: usize,
+    /// Whether a failed append could not be undone, so the store no longer holds the tree
+    poisoned: bool,
+}
+
+impl<S: LevelStore> StoredMerkleTree<S> {
+    /// Create an empty tree on an empty store
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store, which must not hold any levels
+    /// * `params` - The parameters of the tree (or just its hash configuration or scheme)
+    ///
+    /// # Returns
+    ///
+    /// The tree
+    pub fn new(store: S, params: impl Into<TreeParams>) -> Self {
+        StoredMerkleTree {
+            store,
+            params: params.into(),
+            leaf_count: 0,
+            poisoned: false,
+        }
+    }
+
+    /// Reopen a tree previously written to a store
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store holding the tree's levels
+    /// * `params` - The parameters the tree was built with
+    /// * `leaf_count` - The number of leaves in the tree, excluding padding
+    ///
+    /// # Returns
+    ///
+    /// The tree, `MerkleError::InvalidStructure` if the store's levels do not have the
+    /// sizes of a tree of `leaf_count` leaves, or `MerkleError::Storage`
+    pub fn open(
+        store: S,
+        params: impl Into<TreeParams>,
+        leaf_count: usize,
+    ) -> Result<Self, MerkleError> {
+        let params = params.into();
+        check_capacity(leaf_count, params.padding)?;
+        let sizes = level_sizes(leaf_count, params.padding);
+        for (level, &size) in sizes.iter().chain([&0]).enumerate() {
+            let len = store.len(level).map_err(storage)?;
+            if len != size {
+                return Err(MerkleError::InvalidStructure {
+                    reason: format!("level {} holds {} nodes, expected {}", level, len, size),
+                });
+            }
+        }
+        Ok(StoredMerkleTree {
+            store,
+            params,
+            leaf_count,
+            poisoned: false,
+        })
+    }
+
+    /// Build a tree from data items on an empty store
+    ///
+    /// Each level is written in full before the next one is computed from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store, which must not hold any levels
+    /// * `params` - The parameters of the tree (or just its hash configuration or scheme)
+    /// * `data_items` - The data of each leaf
+    ///
+    /// # Returns
+    ///
+    /// The tree, `MerkleError::TooManyLeaves` or `MerkleError::DepthTooLarge` for a
+    /// complete tree that cannot hold the leaves, or `MerkleError::Storage`
+    pub fn build(
+        store: S,
+        params: impl Into<TreeParams>,
+        data_items: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    ) -> Result<Self, MerkleError> {
+        let mut tree = StoredMerkleTree::new(store, params);
+        for (index, data) in data_items.into_iter().enumerate() {
+            let hash = tree.leaf_hash(index, data.as_ref());
+            tree.store.put(0, index, hash).map_err(storage)?;
+        }
+        tree.leaf_count = tree.store.len(0).map_err(storage)?;
+        check_capacity(tree.leaf_count, tree.params.padding)?;
+
+        let sizes = level_sizes(tree.leaf_count, tree.params.padding);
+        for level in 0..sizes.len() {
+            let real = tree.real_nodes(level);
+            if level > 0 {
+                for index in 0..real {
+                    let hash = tree.parent(&sizes, level, index)?;
+                    tree.store.put(level, index, hash).map_err(storage)?;
+                }
+            }
+            if let Some(hash) = tree.padding(&sizes, level, real)? {
+                tree.store.put(level, real, hash).map_err(storage)?;
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Append a data item as a new leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the new leaf
+    ///
+    /// # Returns
+    ///
+    /// Ok, `MerkleError::TooManyLeaves` if the tree is complete and full, or
+    /// `MerkleError::Storage` (see `push_leaf_hash`)
+    pub fn push(&mut self, data: &[u8]) -> Result<(), MerkleError> {
+        let hash = self.leaf_hash(self.leaf_count, data);
+        self.push_leaf_hash(hash)
+    }
+
+    /// Append a leaf by its hash
+    ///
+    /// If the store fails part way, the nodes already overwritten are restored and the
+    /// tree keeps its previous leaf count, though the store may hold new nodes past the
+    /// end of its levels, so reopening it fails with `MerkleError::InvalidStructure`. If
+    /// the restore fails too, the tree is poisoned: every later call returns
+    /// `MerkleError::Storage` rather than serving proofs from a half-written path.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The leaf's hash as the tree stores it (committing to the leaf's index
+    ///   as well in positional trees)
+    ///
+    /// # Returns
+    ///
+    /// Ok, `MerkleError::TooManyLeaves` if the tree is complete and full, or
+    /// `MerkleError::Storage`
+    pub fn push_leaf_hash(&mut self, hash: Vec<u8>) -> Result<(), MerkleError> {
+        self.check_poisoned()?;
+        let leaf_count = self.leaf_count + 1;
+        check_capacity(leaf_count, self.params.padding)?;
+        let mut overwritten = Vec::new();
+        match self.write_path(leaf_count, hash, &mut overwritten) {
+            Ok(()) => {
+                self.leaf_count = leaf_count;
+                Ok(())
+            }
+            Err(error) => {
+                // Undo the writes newest first, so each slot gets back its oldest value
+                for (level, index, old) in overwritten.into_iter().rev() {
+                    if self.store.put(level, index, old).is_err() {
+                        self.poisoned = true;
+                        break;
+                    }
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Write the path of a new last leaf, and the padding beside it, to the store
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_count` - The leaf count including the new leaf
+    /// * `hash` - The new leaf's hash
+    /// * `overwritten` - Receives the level, index and previous hash of every node
+    ///   overwritten, in the order written
+    ///
+    /// # Returns
+    ///
+    /// Ok, or the first error from the store
+    fn write_path(
+        &mut self,
+        leaf_count: usize,
+        hash: Vec<u8>,
+        overwritten: &mut Vec<(usize, usize, Vec<u8>)>,
+    ) -> Result<(), MerkleError> {
+        let sizes = level_sizes(leaf_count, self.params.padding);
+        let last = leaf_count - 1;
+        let mut write = |tree: &mut Self, level, index, hash| {
+            if let Some(old) = tree.store.get(level, index).map_err(storage)? {
+                overwritten.push((level, index, old));
+            }
+            tree.store.put(level, index, hash).map_err(storage)
+        };
+        for level in 0..sizes.len() {
+            let index = last >> level;
+            let node = match level {
+                0 => hash.clone(),
+                _ => self.parent(&sizes, level, index)?,
+            };
+            write(self, level, index, node)?;
+            if let Some(padding) = self.padding(&sizes, level, index + 1)? {
+                write(self, level, index + 1, padding)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the root hash of the tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash, None if the tree is empty (an empty complete tree has the root of
+    /// an empty subtree of its depth), or `MerkleError::Storage`
+    pub fn root_hash(&self) -> Result<Option<Vec<u8>>, MerkleError> {
+        self.check_poisoned()?;
+        match (self.leaf_count, self.params.padding) {
+            (0, PaddingStrategy::Complete { depth }) => {
+                Ok(empty_subtrees(&self.params.hasher, depth).pop())
+            }
+            (0, _) => Ok(None),
+            (leaf_count, padding) => {
+                let top = level_sizes(leaf_count, padding).len() - 1;
+                self.store.get(top, 0).map_err(storage)
+            }
+        }
+    }
+
+    /// Generate a proof for the leaf at a specific index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof, None if the index is out of range or the store is missing a node, or
+    /// `MerkleError::Storage`
+    pub fn generate_proof_by_index(
+        &self,
+        index: usize,
+    ) -> Result<Option<MerkleProof>, MerkleError> {
+        self.check_poisoned()?;
+        if index >= self.leaf_count {
+            return Ok(None);
+        }
+
+        let sizes = level_sizes(self.leaf_count, self.params.padding);
+        // Directions are ignored under sorted ordering, so canonical proofs leave them unset
+        let positional = self.params.hasher.ordering == PairOrdering::Positional;
+        let mut proof = MerkleProof::default();
+        let mut index = index;
+        for (level, &size) in sizes[..sizes.len() - 1].iter().enumerate() {
+            let sibling = index ^ 1;
+            // The last node of an odd-sized level has no sibling and is carried up
+            if sibling < size {
+                let Some(hash) = self.store.get(level, sibling).map_err(storage)? else {
+                    return Ok(None);
+                };
+                proof.push((hash, sibling < index && positional));
+            }
+            index /= 2;
+        }
+        Ok(Some(proof))
+    }
+
+    /// Verify a proof against the tree's root, as `MerkleTree::verify` does
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the leaf
+    /// * `proof` - The proof to verify
+    ///
+    /// # Returns
+    ///
+    /// Whether the proof fits the tree's shape and leads to its root, or
+    /// `MerkleError::Storage` if the root cannot be read
+    pub fn verify(&self, data: &[u8], proof: &MerkleProof) -> Result<bool, MerkleError> {
+        let Some(root) = self.root_hash()? else {
+            return Ok(false);
+        };
+        let hasher = &self.params.hasher;
+        if !proof_fits_shape(proof, self.leaf_count, self.params.padding, hasher.ordering)
+            || !siblings_fit(proof, hasher.digest_len())
+        {
+            return Ok(false);
+        }
+
+        let leaf_hash = if self.params.positional {
+            // The leaf's index is read from the proof's directions
+            match (
+                hasher.ordering,
+                proof_leaf_index(proof, self.leaf_count, self.params.padding),
+            ) {
+                (PairOrdering::Positional, Some(index)) => self.leaf_hash(index, data),
+                _ => return Ok(false),
+            }
+        } else {
+            hasher.hash_leaf(data)
+        };
+        Ok(fold_proof(hasher, leaf_hash, proof) == root)
+    }
+
+    /// Get the number of leaves in the tree
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves, excluding padding
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the parameters the tree was built with
+    ///
+    /// # Returns
+    ///
+    /// The tree's parameters
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get the store holding the tree's levels
+    ///
+    /// # Returns
+    ///
+    /// The store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Take the store holding the tree's levels
+    ///
+    /// # Returns
+    ///
+    /// The store, which `open` can reopen with the tree's parameters and leaf count
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Hash a leaf's data as it is stored at a given index
+    fn leaf_hash(&self, index: usize, data: &[u8]) -> Vec<u8> {
+        if self.params.positional {
+            self.params.hasher.hash_leaf(&positional_leaf(index, data))
+        } else {
+            self.params.hasher.hash_leaf(data)
+        }
+    }
+
+    /// Get the number of nodes on a level that cover at least one real leaf
+    fn real_nodes(&self, level: usize) -> usize {
+        match 1usize.checked_shl(level as u32) {
+            Some(width) => self.leaf_count.div_ceil(width),
+            None => usize::from(self.leaf_count > 0),
+        }
+    }
+
+    /// Compute a branch from its children in the store
+    fn parent(&self, sizes: &[usize], level: usize, index: usize) -> Result<Vec<u8>, MerkleError> {
+        let child = |index| self.node(level - 1, index);
+        if index * 2 + 1 < sizes[level - 1] {
+            Ok(self
+                .params
+                .hasher
+                .hash_pair(&child(index * 2)?, &child(index * 2 + 1)?))
+        } else {
+            // Carry an unpaired node up to the next level
+            child(index * 2)
+        }
+    }
+
+    /// Compute the padding that follows the real nodes of a level, if the level has any
+    fn padding(
+        &self,
+        sizes: &[usize],
+        level: usize,
+        real: usize,
+    ) -> Result<Option<Vec<u8>>, MerkleError> {
+        if real >= sizes[level] {
+            return Ok(None);
+        }
+        let hash = match self.params.padding {
+            // If odd number of leaves, duplicate the last one
+            PaddingStrategy::DuplicateLast => self.node(level, real - 1)?,
+            PaddingStrategy::Complete { depth } => {
+                empty_subtrees(&self.params.hasher, depth).swap_remove(level)
+            }
+            PaddingStrategy::CarryUp => return Ok(None),
+        };
+        Ok(Some(hash))
+    }
+
+    /// Refuse to use the store after an append that could not be undone
+    fn check_poisoned(&self) -> Result<(), MerkleError> {
+        if self.poisoned {
+            return Err(MerkleError::Storage {
+                reason: String::from("an append failed and could not be undone"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Read a node the tree has already written
+    fn node(&self, level: usize, index: usize) -> Result<Vec<u8>, MerkleError> {
+        self.store
+            .get(level, index)
+            .map_err(storage)?
+            .ok_or_else(|| MerkleError::InvalidStructure {
+                reason: format!("the store is missing node {} of level {}", index, level),
+            })
+    }
+}
+
+/// Report an error from a level store
+///
+/// # Arguments
+///
+/// * `error` - The store's error
+///
+/// # Returns
+///
+/// The error as `MerkleError::Storage`
+fn storage(error: io::Error) -> MerkleError {
+    MerkleError::Storage {
+        reason: error.to_string(),
+    }
+}
@@ -0,0 +1,267 @@
+use crate::complete::{check_capacity, empty_subtrees};
+use crate::error::StreamVerifyError;
+use crate::hashing::{EmptyRootPolicy, HashScheme};
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::verify::positional_leaf;
+
+/// Computes a tree's root from its leaves one at a time
+///
+/// Only the frontier is kept: the root of each complete subtree that has not yet been
+/// paired, at most one per level, so memory grows with the logarithm of the number of
+/// leaves. The root is the one `MerkleTree` construction gives for the same leaves and
+/// parameters, including the padding of an odd leaf level and unpaired nodes carried up
+/// unchanged.
+#[derive(Clone, Debug)]
+pub struct RootAccumulator {
+    params: TreeParams,
+    /// The pending subtree root of each level, indexed by height
+    frontier: Vec<Option<Vec<u8>>>,
+    /// The hash of the most recent leaf, needed to pad an odd leaf level
+    last_leaf: Option<Vec<u8>>,
+    leaf_count: usize,
+}
+
+impl RootAccumulator {
+    /// Create an accumulator with no leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters of the tree (or just its hash configuration or scheme)
+    ///
+    /// # Returns
+    ///
+    /// A new accumulator
+    pub fn new(params: impl Into<TreeParams>) -> Self {
+        RootAccumulator {
+            params: params.into(),
+            frontier: Vec::new(),
+            last_leaf: None,
+            leaf_count: 0,
+        }
+    }
+
+    /// Add the next leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf's data
+    pub fn push(&mut self, data: &[u8]) {
+        let hasher = self.params.hasher;
+        let hash = if self.params.positional {
+            hasher.hash_leaf(&positional_leaf(self.leaf_count, data))
+        } else {
+            hasher.hash_leaf(data)
+        };
+        self.push_leaf_hash(hash);
+    }
+
+    /// Add the next leaf by its hash
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The leaf's hash as the tree stores it (committing to the leaf's index
+    ///   as well in positional trees)
+    pub fn push_leaf_hash(&mut self, hash: Vec<u8>) {
+        self.leaf_count += 1;
+        self.last_leaf = Some(hash.clone());
+        insert(&self.params, &mut self.frontier, hash);
+    }
+
+    /// Get the parameters of the tree
+    ///
+    /// # Returns
+    ///
+    /// The parameters the accumulator hashes with
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Create an accumulator from its saved state
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters of the tree
+    /// * `frontier` - The pending subtree root of each level, indexed by height
+    /// * `last_leaf` - The hash of the most recent leaf
+    /// * `leaf_count` - The number of leaves added so far
+    ///
+    /// # Returns
+    ///
+    /// The accumulator
+    pub(crate) fn from_parts(
+        params: TreeParams,
+        frontier: Vec<Option<Vec<u8>>>,
+        last_leaf: Option<Vec<u8>>,
+        leaf_count: usize,
+    ) -> Self {
+        RootAccumulator {
+            params,
+            frontier,
+            last_leaf,
+            leaf_count,
+        }
+    }
+
+    /// Get the accumulator's state
+    ///
+    /// # Returns
+    ///
+    /// The frontier and the hash of the most recent leaf
+    pub(crate) fn parts(&self) -> (&[Option<Vec<u8>>], Option<&[u8]>) {
+        (&self.frontier, self.last_leaf.as_deref())
+    }
+
+    /// Get the number of leaves added so far
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Compute the root of the leaves added so far
+    ///
+    /// Under `PaddingStrategy::Complete` the policy is not consulted: no leaves give the
+    /// root of an empty subtree of the tree's depth, and too many leaves give no root.
+    ///
+    /// # Arguments
+    ///
+    /// * `empty_root` - The policy deciding the root when no leaves were added
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or the root given by the policy if there are no leaves
+    pub fn root(&self, empty_root: EmptyRootPolicy) -> Option<Vec<u8>> {
+        if let PaddingStrategy::Complete { depth } = self.params.padding {
+            return self.complete_root(depth);
+        }
+        if self.leaf_count == 0 {
+            return empty_root.root(&self.params.hasher);
+        }
+
+        let mut frontier = self.frontier.clone();
+        if self.params.padding.duplicates_last(self.leaf_count) {
+            // If odd number of leaves, duplicate the last one
+            insert(&self.params, &mut frontier, self.last_leaf.clone().unwrap());
+        }
+
+        // Pending subtrees are folded from the lowest level up, each being the right
+        // child of the next larger one
+        frontier
+            .into_iter()
+            .flatten()
+            .reduce(|right, left| self.params.hasher.hash_pair(&left, &right))
+    }
+
+    /// Compute the root of a complete tree of the leaves added so far
+    ///
+    /// Each pending subtree is paired with the empty subtree of its height, from the
+    /// lowest level up.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The depth of the complete tree
+    ///
+    /// # Returns
+    ///
+    /// The root hash, or None if more than `2^depth` leaves were added
+    fn complete_root(&self, depth: u8) -> Option<Vec<u8>> {
+        check_capacity(self.leaf_count, self.params.padding).ok()?;
+        let empty = empty_subtrees(&self.params.hasher, depth);
+        let depth = usize::from(depth);
+        if let Some(Some(full)) = self.frontier.get(depth) {
+            return Some(full.clone());
+        }
+
+        let mut node: Option<Vec<u8>> = None;
+        for (height, empty) in empty.iter().enumerate().take(depth) {
+            let pending = self.frontier.get(height).and_then(Option::as_ref);
+            node = match (pending, node) {
+                (Some(left), right) => Some(
+                    self.params
+                        .hasher
+                        .hash_pair(left, right.as_deref().unwrap_or(empty)),
+                ),
+                (None, Some(left)) => Some(self.params.hasher.hash_pair(&left, empty)),
+                (None, None) => None,
+            };
+        }
+        Some(node.unwrap_or_else(|| empty[depth].clone()))
+    }
+}
+
+/// Place a leaf hash in a frontier, pairing complete subtrees as they form
+///
+/// # Arguments
+///
+/// * `params` - The parameters of the tree
+/// * `frontier` - The pending subtree root of each level, indexed by height
+/// * `hash` - The leaf hash
+fn insert(params: &TreeParams, frontier: &mut Vec<Option<Vec<u8>>>, mut hash: Vec<u8>) {
+    for slot in frontier.iter_mut() {
+        match slot.take() {
+            Some(left) => hash = params.hasher.hash_pair(&left, &hash),
+            None => {
+                *slot = Some(hash);
+                return;
+            }
+        }
+    }
+    frontier.push(Some(hash));
+}
+
+/// Verify a complete dataset against a root in a single pass
+///
+/// Like `MerkleTree::verify_proof`, this checks roots from `MerkleTree::new`: SHA-256
+/// with `HashScheme::Plain`, positional pairs and no leaf indices. An empty stream has
+/// no root. Use `verify_stream_with` for trees built with `MerkleTree::builder()`, which
+/// defaults to `HashScheme::DomainSeparated`.
+///
+/// # Arguments
+///
+/// * `root` - The published root hash
+/// * `leaves` - Every leaf of the dataset, in order
+///
+/// # Returns
+///
+/// Ok if the leaves produce the root, or an error with the number of leaves consumed
+pub fn verify_stream(
+    root: &[u8],
+    leaves: impl Iterator<Item = impl AsRef<[u8]>>,
+) -> Result<(), StreamVerifyError> {
+    verify_stream_with(HashScheme::Plain, EmptyRootPolicy::None, root, leaves)
+}
+
+/// Verify a complete dataset against a root in a single pass with the given parameters
+///
+/// Only the frontier of a `RootAccumulator` is kept in memory.
+///
+/// # Arguments
+///
+/// * `params` - The parameters the tree was built with
+/// * `empty_root` - The policy deciding the root of an empty dataset
+/// * `root` - The published root hash
+/// * `leaves` - Every leaf of the dataset, in order
+///
+/// # Returns
+///
+/// Ok if the leaves produce the root, or an error with the number of leaves consumed
+pub fn verify_stream_with(
+    params: impl Into<TreeParams>,
+    empty_root: EmptyRootPolicy,
+    root: &[u8],
+    leaves: impl Iterator<Item = impl AsRef<[u8]>>,
+) -> Result<(), StreamVerifyError> {
+    let mut accumulator = RootAccumulator::new(params);
+    for leaf in leaves {
+        accumulator.push(leaf.as_ref());
+    }
+
+    let leaves_consumed = accumulator.leaf_count();
+    match accumulator.root(empty_root) {
+        Some(computed) if computed == root => Ok(()),
+        Some(_) => Err(StreamVerifyError::RootMismatch { leaves_consumed }),
+        None => Err(StreamVerifyError::NoEmptyRoot),
+    }
+}
@@ -0,0 +1,209 @@
+use crate::error::{MerkleError, VerificationError};
+use crate::hashing::{HashAlgorithm, LEAF_PREFIX};
+use crate::params::PaddingStrategy;
+use crate::verify::level_sizes;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A node of a summed tree: a hash and the total balance below it
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SumNode {
+    hash: Vec<u8>,
+    sum: u64,
+}
+
+/// A Merkle tree whose nodes also commit to the sum of the balances below them
+///
+/// This is the structure of exchange proofs of reserves: each leaf is a user's hash and
+/// balance, and the root commits to every leaf and to the total. Leaves are hashed as
+/// `SHA-256(0x00 || data || le64(balance))` and branches as
+/// `SHA-256(left.hash || right.hash || le64(left.sum + right.sum))`. A node without a
+/// sibling is carried up unchanged on every level, including the leaf level, since a
+/// duplicated leaf would count its balance twice.
+#[derive(Clone, Debug)]
+pub struct SumMerkleTree {
+    /// The nodes of every level, from the leaves up to the root
+    levels: Vec<Vec<SumNode>>,
+}
+
+/// A step of a summed proof: a sibling's hash and sum, and which side it is on
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SumProofStep {
+    /// The sibling's hash
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub hash: Vec<u8>,
+    /// The total balance below the sibling
+    pub sum: u64,
+    /// Whether the sibling is the left child
+    pub is_left: bool,
+}
+
+/// A proof that a leaf and its balance are included in a summed tree
+pub type SumProof = Vec<SumProofStep>;
+
+impl SumMerkleTree {
+    /// Create a summed tree from leaves and their balances
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The data (for example a hash of the user's ID) and balance of each
+    ///   leaf
+    ///
+    /// # Returns
+    ///
+    /// The tree, or `MerkleError::SumOverflow` if the balances do not fit in 64 bits
+    pub fn new(leaves: Vec<(Vec<u8>, u64)>) -> Result<Self, MerkleError> {
+        let sizes = level_sizes(leaves.len(), PaddingStrategy::CarryUp);
+        let mut levels = Vec::with_capacity(sizes.len());
+        if leaves.is_empty() {
+            return Ok(SumMerkleTree { levels });
+        }
+
+        levels.push(
+            leaves
+                .iter()
+                .map(|(data, balance)| SumNode {
+                    hash: leaf_hash(data, *balance),
+                    sum: *balance,
+                })
+                .collect::<Vec<_>>(),
+        );
+        for level in 1..sizes.len() {
+            let next = levels[level - 1]
+                .chunks(2)
+                .enumerate()
+                .map(|(index, chunk)| match chunk {
+                    [left, right] => branch(&left.hash, left.sum, &right.hash, right.sum)
+                        .ok_or(MerkleError::SumOverflow { level, index }),
+                    // Carry an unpaired node up to the next level
+                    _ => Ok(chunk[0].clone()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            levels.push(next);
+        }
+        Ok(SumMerkleTree { levels })
+    }
+
+    /// Get the root hash and the total balance
+    ///
+    /// # Returns
+    ///
+    /// The root hash and total, or None if the tree is empty
+    pub fn root(&self) -> Option<(Vec<u8>, u64)> {
+        let root = &self.levels.last()?[0];
+        Some((root.hash.clone(), root.sum))
+    }
+
+    /// Get the number of leaves
+    ///
+    /// # Returns
+    ///
+    /// The number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.levels.first().map_or(0, Vec::len)
+    }
+
+    /// Generate a proof for the leaf at a specific index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The sibling hashes and sums from the leaf up to the root, or None if the index is
+    /// out of range
+    pub fn generate_proof(&self, index: usize) -> Option<SumProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut proof = SumProof::new();
+        let mut index = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            // The last node of an odd-sized level has no sibling and is carried up
+            if let Some(node) = level.get(sibling) {
+                proof.push(SumProofStep {
+                    hash: node.hash.clone(),
+                    sum: node.sum,
+                    is_left: sibling < index,
+                });
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+
+    /// Verify a leaf's balance against a root hash and total
+    ///
+    /// Both channels are checked: the hashes must chain up to the root hash, and the
+    /// sums must add up to the total without overflowing 64 bits at any step.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The leaf's data
+    /// * `balance` - The leaf's balance
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `total` - The total balance the root commits to
+    ///
+    /// # Returns
+    ///
+    /// Ok if the proof is valid, `VerificationError::SumOverflow` naming the first step
+    /// whose sum overflows, `VerificationError::InvalidSiblingLength` for a malformed
+    /// sibling, or `VerificationError::RootMismatch` if the hash or total differ
+    pub fn verify(
+        leaf: &[u8],
+        balance: u64,
+        proof: &SumProof,
+        root_hash: &[u8],
+        total: u64,
+    ) -> Result<(), VerificationError> {
+        let digest_len = HashAlgorithm::Sha256.digest_len();
+        let mut hash = leaf_hash(leaf, balance);
+        let mut sum = balance;
+        for (step, sibling) in proof.iter().enumerate() {
+            if sibling.hash.len() != digest_len {
+                return Err(VerificationError::InvalidSiblingLength {
+                    step,
+                    expected: digest_len,
+                    actual: sibling.hash.len(),
+                });
+            }
+            let node = if sibling.is_left {
+                branch(&sibling.hash, sibling.sum, &hash, sum)
+            } else {
+                branch(&hash, sum, &sibling.hash, sibling.sum)
+            }
+            .ok_or(VerificationError::SumOverflow { step })?;
+            hash = node.hash;
+            sum = node.sum;
+        }
+
+        if hash == root_hash && sum == total {
+            Ok(())
+        } else {
+            Err(VerificationError::RootMismatch)
+        }
+    }
+}
+
+/// Hash a leaf's data and balance
+fn leaf_hash(data: &[u8], balance: u64) -> Vec<u8> {
+    HashAlgorithm::Sha256.digest(&[&[LEAF_PREFIX], data, &balance.to_le_bytes()])
+}
+
+/// Combine two child nodes into their parent
+///
+/// # Returns
+///
+/// The parent, or None if the sum of the children's balances overflows
+fn branch(left: &[u8], left_sum: u64, right: &[u8], right_sum: u64) -> Option<SumNode> {
+    let sum = left_sum.checked_add(right_sum)?;
+    Some(SumNode {
+        hash: HashAlgorithm::Sha256.digest(&[left, right, &sum.to_le_bytes()]),
+        sum,
+    })
+}
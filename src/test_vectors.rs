@@ -0,0 +1,290 @@
+//! Known-answer test vectors for porting the tree to other implementations
+//!
+//! Each vector lists a fixed set of leaves, the hash configuration they are committed
+//! under, the expected root and the expected proof for one leaf. Hashes are lowercase
+//! hexadecimal and proof steps are `(sibling, is_left)` as in `MerkleProof`.
+//!
+//! The crate's own tests call `assert_all`, so any change to hashing order or padding
+//! shows up here first.
+
+use crate::hashing::{HashAlgorithm, HashConfig, HashScheme, PairOrdering};
+use crate::merkle_tree::MerkleTree;
+
+/// A fixed input together with its expected root and proof
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestVector {
+    /// A short description of the vector
+    pub name: &'static str,
+    /// The configuration the tree is built with
+    pub config: HashConfig,
+    /// The leaf data, in order
+    pub leaves: &'static [&'static [u8]],
+    /// The expected root hash
+    pub root: &'static str,
+    /// The leaf the expected proof is for
+    pub proof_index: usize,
+    /// The expected proof for `proof_index`
+    pub proof: &'static [(&'static str, bool)],
+}
+
+/// `MerkleTree::new`: SHA-256 without domain separation, positional pairs, and an odd
+/// number of leaves padded by duplicating the last one
+pub const SHA256_DUPLICATE_PADDING: &[TestVector] = &[
+    TestVector {
+        name: "sha256 single leaf",
+        config: HashConfig::new(
+            HashAlgorithm::Sha256,
+            HashScheme::Plain,
+            PairOrdering::Positional,
+        ),
+        leaves: &[b"a"],
+        root: "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb",
+        proof_index: 0,
+        proof: &[],
+    },
+    TestVector {
+        name: "sha256 three leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Sha256,
+            HashScheme::Plain,
+            PairOrdering::Positional,
+        ),
+        leaves: &[b"a", b"b", b"c"],
+        root: "d31a37ef6ac14a2db1470c4316beb5592e6afd4465022339adafda76a18ffabe",
+        proof_index: 2,
+        proof: &[
+            (
+                "2e7d2c03a9507ae265ecf5b5356885a53393a2029d241394997265a1a25aefc6",
+                false,
+            ),
+            (
+                "e5a01fee14e0ed5c48714f22180f25ad8365b53f9779f79dc4a3d7e93963f94a",
+                true,
+            ),
+        ],
+    },
+    TestVector {
+        name: "sha256 five leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Sha256,
+            HashScheme::Plain,
+            PairOrdering::Positional,
+        ),
+        leaves: &[b"a", b"b", b"c", b"d", b"e"],
+        root: "9c116bdaad06d1dfd18be9f099c6ddea3db1ae09892711f41e79130b7db3e97f",
+        proof_index: 4,
+        proof: &[
+            (
+                "3f79bb7b435b05321651daefd374cdc681dc06faa65e374e38337b88ca046dea",
+                false,
+            ),
+            (
+                "14ede5e8e97ad9372327728f5099b95604a39593cac3bd38a343ad76205213e7",
+                true,
+            ),
+        ],
+    },
+];
+
+/// Keccak-256 without domain separation and with sorted pairs, so proofs carry no
+/// directions
+#[cfg(feature = "keccak")]
+pub const SORTED_KECCAK: &[TestVector] = &[
+    TestVector {
+        name: "sorted keccak three leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Keccak256,
+            HashScheme::Plain,
+            PairOrdering::Sorted,
+        ),
+        leaves: &[b"a", b"b", b"c"],
+        root: "905b17edcf8b6fb1415b32cdbab3e02c2c93f80a345de80ea2bbf9feba9f5a55",
+        proof_index: 2,
+        proof: &[
+            (
+                "0b42b6393c1f53060fe3ddbfcd7aadcca894465a5a438f69c87d790b2299b9b2",
+                false,
+            ),
+            (
+                "805b21d846b189efaeb0377d6bb0d201b3872a363e607c25088f025b0c6ae1f8",
+                false,
+            ),
+        ],
+    },
+    TestVector {
+        name: "sorted keccak five leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Keccak256,
+            HashScheme::Plain,
+            PairOrdering::Sorted,
+        ),
+        leaves: &[b"a", b"b", b"c", b"d", b"e"],
+        root: "659fdc489808987cbf63c2845b7da5fd3e831f09d8142ba035d0e375f3fa8b77",
+        proof_index: 4,
+        proof: &[
+            (
+                "a8982c89d80987fb9a510e25981ee9170206be21af3c8e0eb312ef1d3382e761",
+                false,
+            ),
+            (
+                "68203f90e9d07dc5859259d7536e87a6ba9d345f2552b5b9de2999ddce9ce1bf",
+                false,
+            ),
+        ],
+    },
+];
+
+/// Domain-separated SHA-256 over the inputs of the RFC 6962 (Certificate Transparency)
+/// reference tests, whose published roots these match
+///
+/// RFC 6962 never duplicates leaves, so only even leaf counts (where no padding is added
+/// and an unpaired upper node is carried up exactly as RFC 6962 splits the tree) are
+/// compatible with the default padding. Trees built with `PaddingStrategy::CarryUp`
+/// match RFC 6962 for every leaf count.
+pub const RFC6962: &[TestVector] = &[
+    TestVector {
+        name: "rfc6962 two leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Sha256,
+            HashScheme::DomainSeparated,
+            PairOrdering::Positional,
+        ),
+        leaves: &[b"", b"\x00"],
+        root: "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        proof_index: 0,
+        proof: &[(
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            false,
+        )],
+    },
+    TestVector {
+        name: "rfc6962 six leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Sha256,
+            HashScheme::DomainSeparated,
+            PairOrdering::Positional,
+        ),
+        leaves: &[
+            b"",
+            b"\x00",
+            b"\x10",
+            b"\x20\x21",
+            b"\x30\x31",
+            b"\x40\x41\x42\x43",
+        ],
+        root: "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        proof_index: 2,
+        proof: &[
+            (
+                "07506a85fd9dd2f120eb694f86011e5bb4662e5c415a62917033d4a9624487e7",
+                false,
+            ),
+            (
+                "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+                true,
+            ),
+            (
+                "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+                false,
+            ),
+        ],
+    },
+    TestVector {
+        name: "rfc6962 eight leaves",
+        config: HashConfig::new(
+            HashAlgorithm::Sha256,
+            HashScheme::DomainSeparated,
+            PairOrdering::Positional,
+        ),
+        leaves: &[
+            b"",
+            b"\x00",
+            b"\x10",
+            b"\x20\x21",
+            b"\x30\x31",
+            b"\x40\x41\x42\x43",
+            b"\x50\x51\x52\x53\x54\x55\x56\x57",
+            b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+        ],
+        root: "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        proof_index: 0,
+        proof: &[
+            (
+                "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+                false,
+            ),
+            (
+                "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                false,
+            ),
+            (
+                "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+                false,
+            ),
+        ],
+    },
+];
+
+impl TestVector {
+    /// Re-derive the vector's root and proof and check them
+    ///
+    /// # Returns
+    ///
+    /// Ok if the tree reproduces the vector, or a description of the first difference
+    pub fn check(&self) -> Result<(), String> {
+        let leaves = self.leaves.iter().map(|leaf| leaf.to_vec()).collect();
+        let tree = MerkleTree::with_config(leaves, self.config);
+
+        let root = tree.root_hash_hex();
+        if root != self.root {
+            return Err(format!(
+                "{}: root is {}, expected {}",
+                self.name, root, self.root
+            ));
+        }
+        let proof: Vec<(String, bool)> = tree
+            .proof_by_index(self.proof_index)
+            .ok_or_else(|| format!("{}: no proof for leaf {}", self.name, self.proof_index))?
+            .iter()
+            .map(|(hash, is_left)| (hex::encode(hash), *is_left))
+            .collect();
+        let expected: Vec<(String, bool)> = self
+            .proof
+            .iter()
+            .map(|&(hash, is_left)| (hash.to_string(), is_left))
+            .collect();
+        if proof != expected {
+            return Err(format!(
+                "{}: proof for leaf {} is {:?}, expected {:?}",
+                self.name, self.proof_index, proof, expected
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Get every test vector enabled by the crate's features
+///
+/// # Returns
+///
+/// The vectors of every mode
+pub fn all() -> Vec<TestVector> {
+    let mut vectors = SHA256_DUPLICATE_PADDING.to_vec();
+    #[cfg(feature = "keccak")]
+    vectors.extend_from_slice(SORTED_KECCAK);
+    vectors.extend_from_slice(RFC6962);
+    vectors
+}
+
+/// Re-derive every test vector and assert that the tree reproduces it
+///
+/// # Panics
+///
+/// Panics with the name of the first vector that does not match
+pub fn assert_all() {
+    for vector in all() {
+        if let Err(message) = vector.check() {
+            panic!("test vector mismatch: {}", message);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Random trees and proofs for property-based testing
+//!
+//! With the `arbitrary` feature `MerkleTree`, `MerkleProof`, `ProofCase` and `Corruption`
+//! implement `arbitrary::Arbitrary`; with the `proptest` feature the `strategy` module
+//! offers equivalent proptest strategies. Trees have between 1 and `MAX_LEAVES` leaves
+//! of at most `MAX_LEAF_LEN` bytes and a random hash configuration.
+
+use crate::hashing::HashConfig;
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+
+/// The largest number of leaves in a generated tree
+pub const MAX_LEAVES: usize = 64;
+
+/// The largest size in bytes of a generated leaf
+pub const MAX_LEAF_LEN: usize = 64;
+
+/// A tree together with one of its leaves and that leaf's proof
+#[derive(Clone, Debug)]
+pub struct ProofCase {
+    /// The tree the proof is for
+    pub tree: MerkleTree,
+    /// The index of the proven leaf
+    pub index: usize,
+    /// The data of the proven leaf
+    pub data: Vec<u8>,
+    /// The proof for the leaf
+    pub proof: MerkleProof,
+}
+
+impl ProofCase {
+    /// Create a proof case for a leaf of a tree
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The tree, which must still hold its leaf data
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The proof case, or None if the index is out of range or the data was cleared
+    pub fn new(tree: MerkleTree, index: usize) -> Option<Self> {
+        let data = tree.get_leaf(index)?.to_vec();
+        let proof = tree.proof_by_index(index)?;
+        Some(ProofCase {
+            tree,
+            index,
+            data,
+            proof,
+        })
+    }
+
+    /// Check the proof against the tree with `MerkleTree::verify`
+    ///
+    /// # Returns
+    ///
+    /// True if the proof verifies, false otherwise
+    pub fn verifies(&self) -> bool {
+        self.tree.verify(&self.data, &self.proof)
+    }
+
+    /// Apply a corruption to the case
+    ///
+    /// # Arguments
+    ///
+    /// * `corruption` - The corruption to apply
+    ///
+    /// # Returns
+    ///
+    /// The corrupted case, which `verifies` always rejects
+    pub fn corrupt(mut self, corruption: Corruption) -> Self {
+        match corruption {
+            Corruption::FlipSiblingBit { step, bit } if !self.proof.is_empty() => {
+                let step = step % self.proof.len();
+                let sibling = &mut self.proof[step].0;
+                let bit = bit % (sibling.len() * 8);
+                sibling[bit / 8] ^= 1 << (bit % 8);
+            }
+            Corruption::DropStep { step } if !self.proof.is_empty() => {
+                let step = step % self.proof.len();
+                self.proof.remove(step);
+            }
+            Corruption::ExtraStep => {
+                let digest_len = self.tree.hash_config().digest_len();
+                self.proof.push((vec![0; digest_len], false));
+            }
+            // Without proof steps to corrupt, the data is corrupted instead
+            Corruption::FlipDataBit { bit }
+            | Corruption::FlipSiblingBit { bit, .. }
+            | Corruption::DropStep { step: bit } => {
+                if self.data.is_empty() {
+                    self.data.push(0);
+                } else {
+                    let bit = bit % (self.data.len() * 8);
+                    self.data[bit / 8] ^= 1 << (bit % 8);
+                }
+            }
+        }
+        self
+    }
+}
+
+/// A way of corrupting a valid proof case so that it no longer verifies
+///
+/// Positions are taken modulo the size of what they index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Corruption {
+    /// Flip one bit of the leaf data (or append a byte to empty data)
+    FlipDataBit {
+        /// The bit to flip
+        bit: usize,
+    },
+    /// Flip one bit of a sibling hash
+    FlipSiblingBit {
+        /// The proof step whose sibling to corrupt
+        step: usize,
+        /// The bit to flip
+        bit: usize,
+    },
+    /// Remove a step from the proof
+    DropStep {
+        /// The step to remove
+        step: usize,
+    },
+    /// Append a step to the proof
+    ExtraStep,
+}
+
+/// Build a tree from a hash configuration and raw leaves, enforcing the size bounds
+///
+/// # Arguments
+///
+/// * `hasher` - The hash configuration
+/// * `leaves` - The leaf data
+///
+/// # Returns
+///
+/// The tree, with at least one leaf
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+fn bounded_tree(hasher: HashConfig, mut leaves: Vec<Vec<u8>>) -> MerkleTree {
+    leaves.truncate(MAX_LEAVES);
+    for leaf in &mut leaves {
+        leaf.truncate(MAX_LEAF_LEN);
+    }
+    if leaves.is_empty() {
+        leaves.push(Vec::new());
+    }
+    MerkleTree::with_config(leaves, hasher)
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impls {
+    use super::{bounded_tree, ProofCase};
+    use crate::hashing::HashConfig;
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof::MerkleProof;
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    impl<'a> Arbitrary<'a> for MerkleTree {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let hasher = HashConfig::arbitrary(u)?;
+            let leaves = Vec::<Vec<u8>>::arbitrary(u)?;
+            Ok(bounded_tree(hasher, leaves))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for ProofCase {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let tree = MerkleTree::arbitrary(u)?;
+            let index = u.choose_index(tree.leaf_count())?;
+            Ok(ProofCase::new(tree, index).unwrap())
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for MerkleProof {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(ProofCase::arbitrary(u)?.proof)
+        }
+    }
+}
+
+/// Proptest strategies for trees, proofs and corrupted proofs
+#[cfg(feature = "proptest")]
+pub mod strategy {
+    use super::{bounded_tree, Corruption, ProofCase, MAX_LEAF_LEN, MAX_LEAVES};
+    use crate::hashing::{HashAlgorithm, HashConfig, HashScheme, PairOrdering};
+    use crate::merkle_tree::MerkleTree;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    /// A strategy for hash algorithms
+    ///
+    /// # Returns
+    ///
+    /// Every algorithm enabled by the crate's features
+    fn algorithms() -> impl Strategy<Value = HashAlgorithm> {
+        proptest::sample::select(vec![
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            #[cfg(feature = "keccak")]
+            HashAlgorithm::Keccak256,
+            #[cfg(feature = "ripemd")]
+            HashAlgorithm::Ripemd160,
+        ])
+    }
+
+    /// A strategy for hash configurations
+    ///
+    /// # Returns
+    ///
+    /// Every combination of algorithm, scheme and pair ordering
+    pub fn hash_configs() -> impl Strategy<Value = HashConfig> {
+        (
+            algorithms(),
+            prop_oneof![Just(HashScheme::Plain), Just(HashScheme::DomainSeparated)],
+            prop_oneof![Just(PairOrdering::Positional), Just(PairOrdering::Sorted)],
+        )
+            .prop_map(|(algorithm, scheme, ordering)| HashConfig::new(algorithm, scheme, ordering))
+    }
+
+    /// A strategy for non-empty trees
+    ///
+    /// # Returns
+    ///
+    /// Trees of 1 to `MAX_LEAVES` leaves of up to `MAX_LEAF_LEN` bytes each
+    pub fn trees() -> impl Strategy<Value = MerkleTree> {
+        (
+            hash_configs(),
+            vec(vec(any::<u8>(), 0..=MAX_LEAF_LEN), 1..=MAX_LEAVES),
+        )
+            .prop_map(|(hasher, leaves)| bounded_tree(hasher, leaves))
+    }
+
+    /// A strategy for valid proofs
+    ///
+    /// # Returns
+    ///
+    /// A random tree together with a random leaf and its proof
+    pub fn proof_cases() -> impl Strategy<Value = ProofCase> {
+        trees().prop_flat_map(|tree| {
+            (0..tree.leaf_count())
+                .prop_map(move |index| ProofCase::new(tree.clone(), index).unwrap())
+        })
+    }
+
+    /// A strategy for corruptions
+    ///
+    /// # Returns
+    ///
+    /// Any kind of corruption at any position
+    pub fn corruptions() -> impl Strategy<Value = Corruption> {
+        prop_oneof![
+            any::<usize>().prop_map(|bit| Corruption::FlipDataBit { bit }),
+            (any::<usize>(), any::<usize>())
+                .prop_map(|(step, bit)| Corruption::FlipSiblingBit { step, bit }),
+            any::<usize>().prop_map(|step| Corruption::DropStep { step }),
+            Just(Corruption::ExtraStep),
+        ]
+    }
+
+    /// A strategy for proofs that must be rejected
+    ///
+    /// # Returns
+    ///
+    /// A valid proof case with a random corruption applied
+    pub fn corrupted_proof_cases() -> impl Strategy<Value = ProofCase> {
+        (proof_cases(), corruptions()).prop_map(|(case, corruption)| case.corrupt(corruption))
+    }
+}
@@ -1,6 +1,49 @@
 #[cfg(test)]
-use crate::{MerkleProof, MerkleTree};
+use crate::{
+    proof_from_leaf_hashes, reconcile, verify_stream, verify_stream_with, AbiPacked, AuditError,
+    ChainedProof, CommitmentScheme, ConcurrentMerkleTree, CoverageTracker, EmptyRootPolicy,
+    EpochProof, HashAlgorithm, HashChain, HashConfig, HashMetrics, HashScheme, KeyedMerkleTree,
+    LeafCheck, LeafEncoder, LeafOutcome, LengthPrefixed, MerkleError, MerkleProof, MerkleTree,
+    MerkleVerifier, MismatchReport, PaddingStrategy, PairOrdering, ProgressEvent, ProofDecodeError,
+    RawBytes, RootAccumulator, StreamVerifyError, TreeParams, TreePrintOptions, TreeVersion,
+    Utf8String, VerificationError, MAX_PROOF_STEPS,
+};
 use sha2::{Digest, Sha256};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+// Counts heap allocations made by the current thread, so tests can check that a code path
+// does not allocate without being disturbed by tests running on other threads
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
 
 // Helper function to create test data
 fn create_test_data(count: usize) -> Vec<Vec<u8>> {
@@ -247,3 +290,7467 @@ fn test_merkle_proof_type() {
     let is_valid = MerkleTree::verify_proof(&data[0], &proof.unwrap(), &root_hash);
     assert!(is_valid);
 }
+
+#[test]
+fn test_second_preimage_forgery_with_plain_hashing() {
+    let data = create_test_data(4);
+    let tree = MerkleTree::new(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+
+    // Present the concatenation of two leaf hashes as a 64-byte "leaf"
+    let hash0 = Sha256::digest(&data[0]).to_vec();
+    let hash1 = Sha256::digest(&data[1]).to_vec();
+    let forged_leaf = [hash0, hash1].concat();
+
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(&data[2]));
+    hasher.update(Sha256::digest(&data[3]));
+    let forged_proof = MerkleProof::new(vec![(hasher.finalize().to_vec(), false)]);
+
+    // The unchecked plain verifier accepts the forgery
+    assert!(MerkleTree::verify_proof(
+        &forged_leaf,
+        &forged_proof,
+        &root_hash
+    ));
+
+    // Knowing the leaf count rejects the short proof
+    assert!(!tree.verify(&forged_leaf, &forged_proof));
+    assert!(!MerkleTree::verify_proof_with(
+        HashScheme::Plain,
+        &forged_leaf,
+        &forged_proof,
+        &root_hash,
+        Some(4)
+    ));
+}
+
+#[test]
+fn test_second_preimage_forgery_fails_with_domain_separation() {
+    let data = create_test_data(4);
+    let tree = MerkleTree::builder().build(data.clone());
+    assert_eq!(tree.hash_scheme(), HashScheme::DomainSeparated);
+    let root_hash = tree.root_hash().unwrap();
+
+    let scheme = HashScheme::DomainSeparated;
+    let forged_leaf = [scheme.hash_leaf(&data[0]), scheme.hash_leaf(&data[1])].concat();
+    let hash23 = scheme.hash_pair(&scheme.hash_leaf(&data[2]), &scheme.hash_leaf(&data[3]));
+    let forged_proof = MerkleProof::new(vec![(hash23, false)]);
+
+    // Even without the leaf count the forgery no longer verifies
+    assert!(!MerkleTree::verify_proof_with(
+        scheme,
+        &forged_leaf,
+        &forged_proof,
+        &root_hash,
+        None
+    ));
+    assert!(!tree.verify(&forged_leaf, &forged_proof));
+
+    // Genuine proofs still verify
+    for item in &data {
+        let proof = tree.generate_proof(item).unwrap();
+        assert!(tree.verify(item, &proof));
+    }
+}
+
+#[test]
+fn test_domain_separated_root_differs_from_plain() {
+    let data = create_test_data(5);
+    let plain = MerkleTree::new(data.clone());
+    let separated = MerkleTree::builder().build(data.clone());
+    let explicit_plain = MerkleTree::builder()
+        .hash_scheme(HashScheme::Plain)
+        .build(data);
+
+    assert_ne!(plain.root_hash(), separated.root_hash());
+    assert_eq!(plain.root_hash(), explicit_plain.root_hash());
+}
+
+#[test]
+fn test_verify_with_leaf_count_accepts_every_real_leaf() {
+    for count in 1..=9 {
+        let data = create_test_data(count);
+        let tree = MerkleTree::new(data.clone());
+        assert_eq!(tree.leaf_count(), count);
+
+        for item in &data {
+            let proof = tree.generate_proof(item).unwrap();
+            assert!(tree.verify(item, &proof), "count {}", count);
+        }
+    }
+}
+
+#[test]
+fn test_proof_lengths_for_odd_shaped_trees() {
+    // Expected proof length per real leaf; leaves under a node carried up from an
+    // odd-sized level have one step fewer than the tree's depth
+    let cases: [(usize, &[usize]); 4] = [
+        (3, &[2, 2, 2]),
+        (5, &[3, 3, 3, 3, 2]),
+        (6, &[3, 3, 3, 3, 2, 2]),
+        (7, &[3, 3, 3, 3, 3, 3, 3]),
+    ];
+
+    for (count, lengths) in cases {
+        let data = create_test_data(count);
+        let tree = MerkleTree::new(data.clone());
+        let root_hash = tree.root_hash().unwrap();
+
+        for (index, item) in data.iter().enumerate() {
+            let proof = tree.proof_by_index(index).unwrap();
+            assert_eq!(
+                proof.len(),
+                lengths[index],
+                "count {} index {}",
+                count,
+                index
+            );
+            assert_eq!(tree.generate_proof(item), Some(proof.clone()));
+            assert!(MerkleTree::verify_proof(item, &proof, &root_hash));
+            assert!(tree.verify(item, &proof));
+        }
+    }
+}
+
+#[test]
+fn test_padding_duplicate_is_not_provable() {
+    let data = create_test_data(5);
+    let tree = MerkleTree::new(data.clone());
+
+    assert_eq!(tree.len(), 6);
+    assert_eq!(tree.leaf_count(), 5);
+    assert!(tree.proof_by_index(5).is_none());
+    assert_eq!(tree.index_of(&data[4]), Some(4));
+
+    // A proof addressed at the duplicate's position verifies the same data against the
+    // root, but is rejected once the leaf count is known
+    let last_proof = tree.proof_by_index(4).unwrap();
+    let mut duplicate_proof = last_proof.clone();
+    duplicate_proof[0].1 = true;
+    assert!(MerkleTree::verify_proof(
+        &data[4],
+        &duplicate_proof,
+        &tree.root_hash().unwrap()
+    ));
+    assert!(!tree.verify(&data[4], &duplicate_proof));
+}
+
+#[test]
+fn test_proof_for_repeated_data_uses_lowest_index() {
+    let data = vec![b"a".to_vec(), b"b".to_vec(), b"a".to_vec()];
+    let tree = MerkleTree::new(data);
+
+    assert_eq!(tree.index_of(b"a"), Some(0));
+    assert_eq!(tree.generate_proof(b"a"), tree.proof_by_index(0));
+}
+
+#[test]
+fn test_clear_leaf_data_keeps_proofs_valid() {
+    let data = create_test_data(5);
+    let mut tree = MerkleTree::new(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+    assert_eq!(tree.get_leaf(2), Some(&data[2][..]));
+
+    tree.clear_leaf_data();
+
+    assert_eq!(tree.root_hash().unwrap(), root_hash);
+    assert_eq!(tree.leaf_count(), 5);
+    for (index, item) in data.iter().enumerate() {
+        assert!(tree.get_leaf(index).is_none());
+
+        let proof = tree.proof_by_index(index).unwrap();
+        assert!(MerkleTree::verify_proof(item, &proof, &root_hash));
+        assert_eq!(tree.generate_proof(item), Some(proof));
+    }
+}
+
+// Small deterministic generator for fuzz-style tests
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+#[test]
+fn test_checked_verification_rejects_malformed_proofs() {
+    let data = create_test_data(8);
+    let tree = MerkleTree::new(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+    let proof = tree.proof_by_index(3).unwrap();
+    let check = |proof: &MerkleProof, root: &[u8], count, index| {
+        MerkleTree::verify_proof_checked(HashScheme::Plain, &data[3], proof, root, count, index)
+    };
+
+    assert_eq!(check(&proof, &root_hash, Some(8), Some(3)), Ok(()));
+    assert_eq!(
+        check(&proof, &root_hash[..31], None, None),
+        Err(VerificationError::InvalidRootLength {
+            expected: 32,
+            actual: 31
+        })
+    );
+
+    for len in [0, 7, 4096] {
+        let mut bad = proof.clone();
+        bad[1].0 = vec![0; len];
+        assert_eq!(
+            check(&bad, &root_hash, None, None),
+            Err(VerificationError::InvalidSiblingLength {
+                step: 1,
+                expected: 32,
+                actual: len
+            })
+        );
+    }
+
+    let deep = MerkleProof::new(vec![(vec![0; 32], false); MAX_PROOF_STEPS + 1]);
+    assert_eq!(
+        check(&deep, &root_hash, None, None),
+        Err(VerificationError::TooDeep {
+            steps: MAX_PROOF_STEPS + 1,
+            max: MAX_PROOF_STEPS
+        })
+    );
+
+    assert_eq!(
+        check(
+            &MerkleProof::new(proof[1..].to_vec()),
+            &root_hash,
+            Some(8),
+            None
+        ),
+        Err(VerificationError::ShapeMismatch {
+            steps: 2,
+            leaf_count: 8
+        })
+    );
+    assert_eq!(
+        check(&proof, &root_hash, Some(8), Some(2)),
+        Err(VerificationError::IndexMismatch {
+            expected: 2,
+            actual: 3
+        })
+    );
+
+    let mut flipped = proof.clone();
+    flipped[0].0[0] ^= 1;
+    assert_eq!(
+        check(&flipped, &root_hash, Some(8), Some(3)),
+        Err(VerificationError::RootMismatch)
+    );
+}
+
+#[test]
+fn test_checked_verification_never_accepts_garbage() {
+    let data = create_test_data(13);
+    let tree = MerkleTree::new(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+    let mut rng = XorShift(0x9e3779b97f4a7c15);
+
+    for _ in 0..2000 {
+        let steps = (rng.next() % 80) as usize;
+        let proof: MerkleProof = (0..steps)
+            .map(|_| {
+                let len = match rng.next() % 4 {
+                    0 => (rng.next() % 100) as usize,
+                    _ => 32,
+                };
+                (rng.bytes(len), rng.next() & 1 == 0)
+            })
+            .collect();
+        let leaf_len = (rng.next() % 70) as usize;
+        let leaf = rng.bytes(leaf_len);
+        let count = Some((rng.next() % 20) as usize);
+        let index = Some((rng.next() % 20) as usize);
+
+        for (count, index) in [(None, None), (count, None), (count, index)] {
+            let result = MerkleTree::verify_proof_checked(
+                HashScheme::Plain,
+                &leaf,
+                &proof,
+                &root_hash,
+                count,
+                index,
+            );
+            assert!(result.is_err());
+        }
+    }
+}
+
+#[test]
+fn test_children_digest_and_node_hash() {
+    let data = create_test_data(6);
+    let tree = MerkleTree::new(data.clone());
+
+    assert_eq!(tree.height(), 4);
+    assert_eq!(tree.node_hash_by_index(3, 0), tree.root_hash());
+    assert_eq!(
+        tree.node_hash_by_index(0, 5),
+        Some(Sha256::digest(&data[5]).to_vec())
+    );
+    assert!(tree.node_hash_by_index(0, 6).is_none());
+
+    let (left, right) = tree.children_digest(1, 0).unwrap();
+    assert_eq!(left, tree.node_hash_by_index(0, 0).unwrap());
+    assert_eq!(right, tree.node_hash_by_index(0, 1));
+
+    // The node holding leaves 4 and 5 is carried up from the odd-sized level 1
+    let (left, right) = tree.children_digest(2, 1).unwrap();
+    assert_eq!(left, tree.node_hash_by_index(1, 2).unwrap());
+    assert!(right.is_none());
+
+    assert!(tree.children_digest(0, 0).is_none());
+    assert!(tree.children_digest(1, 3).is_none());
+}
+
+#[test]
+fn test_reconcile_finds_injected_differences() {
+    let data = create_test_data(1024);
+    let local = MerkleTree::new(data.clone());
+
+    let mut remote_data = data.clone();
+    let changed = [3, 200, 201, 777, 1023];
+    for &index in &changed {
+        remote_data[index] = format!("Changed {}", index).into_bytes();
+    }
+    let remote = MerkleTree::new(remote_data);
+
+    let queries = std::cell::Cell::new(0);
+    let divergent = reconcile(&local, |level, index| {
+        queries.set(queries.get() + 1);
+        remote.node_hash_by_index(level, index)
+    });
+
+    assert_eq!(divergent, changed);
+    // Every difference costs at most two lookups per level, plus finding the leaf count
+    let depth = 10;
+    assert!(queries.get() <= changed.len() * 2 * (depth + 1) + 2 * (depth + 2));
+
+    queries.set(0);
+    assert!(reconcile(&local, |level, index| {
+        queries.set(queries.get() + 1);
+        local.node_hash_by_index(level, index)
+    })
+    .is_empty());
+    assert!(queries.get() <= 2 * (depth + 2) + 1);
+}
+
+#[test]
+fn test_reconcile_trees_of_different_sizes() {
+    let data = create_test_data(11);
+    let local = MerkleTree::new(data[..7].to_vec());
+    let mut remote_data = data.clone();
+    remote_data[2] = b"Changed".to_vec();
+    let remote = MerkleTree::new(remote_data);
+
+    assert_eq!(
+        reconcile(&local, |level, index| remote
+            .node_hash_by_index(level, index)),
+        vec![2, 7, 8, 9, 10]
+    );
+    assert_eq!(
+        reconcile(&remote, |level, index| local
+            .node_hash_by_index(level, index)),
+        vec![2, 7, 8, 9, 10]
+    );
+
+    let empty = MerkleTree::new(Vec::new());
+    assert_eq!(
+        reconcile(&empty, |level, index| local
+            .node_hash_by_index(level, index)),
+        (0..7).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_subtree_proof_for_shard() {
+    let data = create_test_data(16);
+    let tree = MerkleTree::new(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+
+    // Each shard of 4 leaves rebuilds its own subtree and proves its root
+    for shard in 0..4 {
+        let shard_tree = MerkleTree::new(data[shard * 4..shard * 4 + 4].to_vec());
+        let shard_root = shard_tree.root_hash().unwrap();
+        assert_eq!(tree.node_hash_by_index(2, shard), Some(shard_root.clone()));
+
+        let proof = tree.generate_subtree_proof(2, shard).unwrap();
+        assert_eq!(proof.len(), 2);
+        assert!(MerkleTree::verify_subtree_proof(
+            &shard_root,
+            &proof,
+            &root_hash
+        ));
+        assert!(!MerkleTree::verify_subtree_proof(
+            &tree.node_hash_by_index(2, (shard + 1) % 4).unwrap(),
+            &proof,
+            &root_hash
+        ));
+    }
+
+    assert!(tree.generate_subtree_proof(2, 4).is_none());
+    assert!(tree.generate_subtree_proof(5, 0).is_none());
+}
+
+#[test]
+fn test_subtree_proof_edge_levels() {
+    let data = create_test_data(6);
+    let tree = MerkleTree::builder().build(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+    let scheme = tree.hash_scheme();
+
+    // Level 0 is a leaf proof
+    for index in 0..6 {
+        assert_eq!(
+            tree.generate_subtree_proof(0, index),
+            tree.proof_by_index(index)
+        );
+    }
+    assert!(tree.generate_subtree_proof(0, 6).is_none());
+
+    // The root has an empty proof
+    let top = tree.height() - 1;
+    let proof = tree.generate_subtree_proof(top, 0).unwrap();
+    assert!(proof.is_empty());
+    assert!(MerkleTree::verify_subtree_proof_with(
+        scheme, &root_hash, &proof, &root_hash
+    ));
+
+    // A node carried up from an odd-sized level proves like any other
+    let proof = tree.generate_subtree_proof(1, 2).unwrap();
+    assert!(MerkleTree::verify_subtree_proof_with(
+        scheme,
+        &tree.node_hash_by_index(1, 2).unwrap(),
+        &proof,
+        &root_hash
+    ));
+}
+
+#[test]
+fn test_verifier_checks_proofs_without_the_tree() {
+    let data = create_test_data(7);
+    let tree = MerkleTree::builder().build(data.clone());
+    let verifier = MerkleVerifier::from_hex(&tree.root_hash_hex())
+        .unwrap()
+        .with_leaf_count(7);
+    assert_eq!(Some(verifier.clone()), MerkleVerifier::for_tree(&tree));
+
+    for (index, item) in data.iter().enumerate() {
+        let proof = tree.proof_by_index(index).unwrap();
+        assert_eq!(verifier.verify(item, &proof), Ok(()));
+        assert_eq!(
+            verifier.verify(b"Not in the tree", &proof),
+            Err(VerificationError::RootMismatch)
+        );
+    }
+
+    let proof = tree.proof_by_index(0).unwrap();
+    assert_eq!(
+        verifier.verify(&data[0], &MerkleProof::new(proof[1..].to_vec())),
+        Err(VerificationError::ShapeMismatch {
+            steps: 2,
+            leaf_count: 7
+        })
+    );
+
+    // A verifier configured for plain hashing rejects domain-separated proofs
+    let plain = verifier.clone().with_hash_config(HashScheme::Plain);
+    assert_eq!(
+        plain.verify(&data[0], &proof),
+        Err(VerificationError::RootMismatch)
+    );
+    assert!(MerkleVerifier::from_hex("not hex").is_err());
+}
+
+#[test]
+fn test_verifier_follows_the_tree_padding() {
+    use crate::PaddingStrategy;
+
+    for padding in [PaddingStrategy::DuplicateLast, PaddingStrategy::CarryUp] {
+        for count in [1, 3, 5, 7, 11] {
+            let data = create_test_data(count);
+            let tree = MerkleTree::builder().padding(padding).build(data.clone());
+            let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+            assert_eq!(verifier.params(), tree.params());
+
+            for (index, item) in data.iter().enumerate() {
+                let proof = tree.proof_by_index(index).unwrap();
+                assert_eq!(
+                    verifier.verify(item, &proof),
+                    Ok(()),
+                    "{:?}, {} of {} leaves",
+                    padding,
+                    index,
+                    count
+                );
+                let bundle = tree.generate_bundle(index).unwrap();
+                assert_eq!(verifier.verify_bundle(item, &bundle), Ok(()));
+                assert!(MerkleTree::verify_proof_with(
+                    tree.params(),
+                    item,
+                    &proof,
+                    &tree.root_hash().unwrap(),
+                    Some(count)
+                ));
+            }
+        }
+    }
+
+    // A 5-leaf carried-up tree proves its last leaf in one step fewer than its depth
+    let data = create_test_data(5);
+    let tree = MerkleTree::builder()
+        .padding(PaddingStrategy::CarryUp)
+        .build(data.clone());
+    let proof = tree.proof_by_index(4).unwrap();
+    assert_eq!(proof.len(), 1);
+    let duplicated = MerkleVerifier::new(tree.root_hash().unwrap()).with_leaf_count(5);
+    assert_eq!(
+        duplicated.verify(&data[4], &proof),
+        Err(VerificationError::ShapeMismatch {
+            steps: 1,
+            leaf_count: 5
+        })
+    );
+    let bundle = tree.generate_bundle(4).unwrap();
+    assert!(matches!(
+        duplicated.verify_bundle(&data[4], &bundle),
+        Err(VerificationError::ParamsMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_verifier_hashes_positional_leaves_with_their_index() {
+    use crate::PaddingStrategy;
+
+    for padding in [PaddingStrategy::DuplicateLast, PaddingStrategy::CarryUp] {
+        let data = create_test_data(7);
+        let tree = MerkleTree::builder()
+            .positional(true)
+            .padding(padding)
+            .build(data.clone());
+        let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+        assert!(verifier.params().positional);
+
+        for (index, item) in data.iter().enumerate() {
+            let proof = tree.proof_by_index(index).unwrap();
+            assert!(tree.verify(item, &proof));
+            assert_eq!(verifier.verify(item, &proof), Ok(()), "{:?}", padding);
+            let bundle = tree.generate_bundle(index).unwrap();
+            assert_eq!(verifier.verify_bundle(item, &bundle), Ok(()));
+            assert!(MerkleTree::verify_proof_from_reader_with(
+                tree.params(),
+                &item[..],
+                &proof,
+                &tree.root_hash().unwrap(),
+                Some(7)
+            )
+            .unwrap());
+        }
+
+        // The same data under another leaf's proof hashes with the wrong index
+        let proof = tree.proof_by_index(1).unwrap();
+        assert_eq!(
+            verifier.verify(&data[0], &proof),
+            Err(VerificationError::RootMismatch)
+        );
+        // Without the leaf count the index cannot be read from the proof
+        assert!(!MerkleTree::verify_proof_with(
+            tree.params(),
+            &data[1],
+            &proof,
+            &tree.root_hash().unwrap(),
+            None
+        ));
+    }
+}
+
+#[test]
+fn test_verifier_round_trip_for_complete_trees() {
+    // 11 of 16 leaves, so the last leaf is paired with a padding leaf
+    let data = create_test_data(11);
+    let tree = MerkleTree::new_complete(data.clone(), 4).unwrap();
+    let verifier = MerkleVerifier::new(tree.root_hash().unwrap())
+        .with_leaf_count(11)
+        .with_params(tree.params());
+    assert_eq!(verifier.version(), None);
+
+    for (index, item) in data.iter().enumerate() {
+        let bundle = tree.generate_bundle(index).unwrap();
+        assert_eq!(bundle.proof.len(), 4);
+        assert_eq!(
+            verifier.verify_bundle(item, &bundle),
+            Ok(()),
+            "leaf {}",
+            index
+        );
+        assert_eq!(verifier.verify(item, &bundle.proof), Ok(()));
+    }
+
+    // Padding leaves are not provable, and another depth is another tree
+    let proof = tree.proof_by_index(10).unwrap();
+    let mut padding = vec![(tree.levels()[0][10].clone(), true)];
+    padding.extend_from_slice(&proof[1..]);
+    assert_eq!(
+        verifier.verify(&crate::EMPTY_LEAF_DATA, &MerkleProof::new(padding)),
+        Err(VerificationError::ShapeMismatch {
+            steps: 4,
+            leaf_count: 11
+        })
+    );
+    let deeper = MerkleTree::new_complete(data.clone(), 5).unwrap();
+    assert!(matches!(
+        verifier.verify_bundle(&data[0], &deeper.generate_bundle(0).unwrap()),
+        Err(VerificationError::ParamsMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_sorted_pair_ordering_ignores_directions() {
+    let data = create_test_data(6);
+    let tree = MerkleTree::builder()
+        .pair_ordering(PairOrdering::Sorted)
+        .build(data.clone());
+    let positional = MerkleTree::builder().build(data.clone());
+    assert_ne!(tree.root_hash(), positional.root_hash());
+
+    let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+    for (index, item) in data.iter().enumerate() {
+        let mut proof = tree.proof_by_index(index).unwrap();
+        assert!(tree.verify(item, &proof));
+        for step in proof.iter_mut() {
+            step.1 = !step.1;
+        }
+        assert_eq!(verifier.verify(item, &proof), Ok(()));
+    }
+}
+
+#[test]
+fn test_multiproof_generation_and_verification() {
+    let data = create_test_data(11);
+    for tree in [
+        MerkleTree::new(data.clone()),
+        MerkleTree::builder()
+            .pair_ordering(PairOrdering::Sorted)
+            .build(data.clone()),
+    ] {
+        let root_hash = tree.root_hash().unwrap();
+        let config = tree.hash_config();
+
+        for indices in [
+            vec![0],
+            vec![10],
+            vec![9, 10],
+            vec![0, 1, 2, 3],
+            vec![4, 0, 7, 10, 4],
+        ] {
+            let proof = tree.generate_multiproof(&indices).unwrap();
+            let leaves: Vec<&Vec<u8>> = proof.indices.iter().map(|&i| &data[i]).collect();
+            assert!(proof.verify(config, &leaves, &root_hash), "{:?}", indices);
+
+            // Shared siblings are only included once
+            let separate: usize = proof
+                .indices
+                .iter()
+                .map(|&i| tree.proof_by_index(i).unwrap().len())
+                .sum();
+            assert!(proof.hashes.len() <= separate);
+
+            let mut tampered = leaves.clone();
+            let other = b"Not in the tree".to_vec();
+            tampered[0] = &other;
+            assert!(!proof.verify(config, &tampered, &root_hash));
+        }
+
+        assert!(tree.generate_multiproof(&[]).is_none());
+        assert!(tree.generate_multiproof(&[11]).is_none());
+    }
+}
+
+#[test]
+fn test_verifier_rejects_malformed_multiproofs() {
+    let data = create_test_data(8);
+    let tree = MerkleTree::builder().build(data.clone());
+    let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+    let proof = tree.generate_multiproof(&[1, 5]).unwrap();
+    let leaves = [&data[1], &data[5]];
+    assert_eq!(verifier.verify_multiproof(&leaves, &proof), Ok(()));
+
+    let mut extra = proof.clone();
+    extra.hashes.push(vec![0; 32]);
+    assert_eq!(
+        verifier.verify_multiproof(&leaves, &extra),
+        Err(VerificationError::InvalidMultiProof)
+    );
+
+    let mut unsorted = proof.clone();
+    unsorted.indices.reverse();
+    assert_eq!(
+        verifier.verify_multiproof(&leaves, &unsorted),
+        Err(VerificationError::InvalidMultiProof)
+    );
+    assert_eq!(
+        verifier.verify_multiproof(&leaves[..1], &proof),
+        Err(VerificationError::InvalidMultiProof)
+    );
+
+    let mut resized = proof.clone();
+    resized.leaf_count = 9;
+    assert_eq!(
+        verifier.verify_multiproof(&leaves, &resized),
+        Err(VerificationError::LeafCountMismatch {
+            expected: 8,
+            actual: 9
+        })
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_verifier_policy_json_round_trip() {
+    let data = create_test_data(5);
+    let tree = MerkleTree::builder().build(data.clone());
+    let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+
+    let json = serde_json::to_string(&verifier).unwrap();
+    assert!(json.contains(&format!("\"root\":\"{}\"", tree.root_hash_hex())));
+    assert!(json.contains("\"scheme\":\"DomainSeparated\""));
+    assert_eq!(
+        serde_json::from_str::<MerkleVerifier>(&json).unwrap(),
+        verifier
+    );
+
+    let policy = format!(
+        r#"{{"root": "{}", "algorithm": "Sha256", "scheme": "DomainSeparated", "ordering": "Positional"}}"#,
+        tree.root_hash_hex()
+    );
+    let loaded: MerkleVerifier = serde_json::from_str(&policy).unwrap();
+    assert_eq!(loaded.leaf_count(), None);
+    let proof = tree.proof_by_index(3).unwrap();
+    assert_eq!(loaded.verify(&data[3], &proof), Ok(()));
+}
+
+#[test]
+fn test_pruned_tree_proofs() {
+    for count in [1, 2, 5, 7, 12, 33] {
+        let tree = MerkleTree::builder().build(create_test_data(count));
+        let keep: Vec<usize> = (0..count).filter(|i| i % 3 != 1).collect();
+        let pruned = tree.prune(&keep);
+
+        assert_eq!(pruned.root_hash(), tree.root_hash());
+        assert_eq!(pruned.kept_indices(), keep);
+        for index in 0..count {
+            if keep.contains(&index) {
+                assert_eq!(
+                    pruned.generate_proof_by_index(index),
+                    Ok(tree.proof_by_index(index).unwrap())
+                );
+            } else {
+                assert_eq!(
+                    pruned.generate_proof_by_index(index),
+                    Err(MerkleError::LeafNotRetained { index })
+                );
+            }
+        }
+        assert_eq!(
+            pruned.generate_proof_by_index(count),
+            Err(MerkleError::IndexOutOfRange {
+                index: count,
+                leaf_count: count
+            })
+        );
+    }
+}
+
+#[test]
+fn test_pruned_tree_size_scales_with_watch_set() {
+    let count = 1 << 12;
+    let tree = MerkleTree::new(create_test_data(count));
+    let keep: Vec<usize> = (0..20).map(|i| i * 197 + 3).collect();
+    let pruned = tree.prune(&keep);
+
+    // Each kept leaf needs its own hash and at most one sibling per level, plus the root
+    let bound = keep.len() * (tree.height() + 1) + 1;
+    assert!(pruned.stored_hashes() <= bound);
+    assert!(pruned.stored_hashes() * 20 < count);
+    for &index in &keep {
+        let proof = pruned.generate_proof_by_index(index).unwrap();
+        assert!(tree.verify(&create_test_data(count)[index], &proof));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_pruned_tree_json_round_trip() {
+    let tree = MerkleTree::builder().build(create_test_data(9));
+    let pruned = tree.prune(&[2, 8]);
+
+    let json = serde_json::to_string(&pruned).unwrap();
+    let restored: crate::PrunedMerkleTree = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, pruned);
+    assert_eq!(
+        restored.generate_proof_by_index(8),
+        Ok(tree.proof_by_index(8).unwrap())
+    );
+}
+
+#[test]
+fn test_hash_metrics() {
+    // 5 leaves are padded to 6: 3 pair hashes, then 1 (the third node is carried up), then 1
+    let data = create_test_data(5);
+    let tree = MerkleTree::new(data.clone());
+    assert_eq!(
+        tree.metrics(),
+        HashMetrics {
+            leaf_hashes: 5,
+            node_hashes: 5
+        }
+    );
+
+    tree.reset_metrics();
+    let proof = tree.generate_proof(&data[2]).unwrap();
+    assert_eq!(tree.metrics().total(), 1);
+    tree.proof_by_index(4).unwrap();
+    assert_eq!(tree.metrics().total(), 1);
+
+    tree.reset_metrics();
+    assert!(tree.verify(&data[2], &proof));
+    assert_eq!(
+        tree.metrics(),
+        HashMetrics {
+            leaf_hashes: 1,
+            node_hashes: proof.len() as u64
+        }
+    );
+
+    // A proof rejected by its shape is not hashed at all
+    tree.reset_metrics();
+    assert!(!tree.verify(&data[2], &MerkleProof::new(proof[..1].to_vec())));
+    assert_eq!(tree.metrics(), HashMetrics::default());
+
+    let copy = tree.clone();
+    tree.verify(&data[2], &proof);
+    assert_eq!(copy.metrics().total(), 0);
+}
+
+fn keyed_entries() -> Vec<(String, Vec<u8>)> {
+    ["carol", "alice", "dave", "bob", "erin"]
+        .iter()
+        .map(|name| (name.to_string(), format!("record of {}", name).into_bytes()))
+        .collect()
+}
+
+#[test]
+fn test_keyed_tree_proofs() {
+    let tree = KeyedMerkleTree::new(keyed_entries()).unwrap();
+    let mut reversed = keyed_entries();
+    reversed.reverse();
+    assert_eq!(
+        KeyedMerkleTree::new(reversed).unwrap().root_hash(),
+        tree.root_hash()
+    );
+    assert_eq!(
+        tree.keys().collect::<Vec<_>>(),
+        ["alice", "bob", "carol", "dave", "erin"]
+    );
+
+    let root = tree.root_hash().unwrap();
+    assert_eq!(tree.get("dave"), Some(&b"record of dave"[..]));
+    assert_eq!(tree.get("frank"), None);
+    assert!(tree.proof_for_key("frank").is_none());
+
+    let proof = tree.proof_for_key("dave").unwrap();
+    assert!(KeyedMerkleTree::verify_for_key(
+        "dave",
+        b"record of dave",
+        &proof,
+        &root
+    ));
+    // The key is bound into the leaf, so the value cannot be claimed for another key
+    assert!(!KeyedMerkleTree::verify_for_key(
+        "carol",
+        b"record of dave",
+        &proof,
+        &root
+    ));
+    assert!(!KeyedMerkleTree::verify_for_key(
+        "dave",
+        b"record of carol",
+        &proof,
+        &root
+    ));
+}
+
+#[test]
+fn test_keyed_tree_rejects_bad_keys() {
+    let mut entries = keyed_entries();
+    entries.push(("bob".to_string(), b"impostor".to_vec()));
+    assert_eq!(
+        KeyedMerkleTree::new(entries).err(),
+        Some(MerkleError::DuplicateKey {
+            key: "bob".to_string()
+        })
+    );
+
+    let entries = vec![("a\0b".to_string(), b"value".to_vec())];
+    assert_eq!(
+        KeyedMerkleTree::new(entries).err(),
+        Some(MerkleError::InvalidKey {
+            key: "a\0b".to_string()
+        })
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_keyed_tree_json_round_trip() {
+    let tree = KeyedMerkleTree::new(keyed_entries()).unwrap();
+    let json = serde_json::to_string(&tree).unwrap();
+    let restored: KeyedMerkleTree = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.root_hash(), tree.root_hash());
+    assert_eq!(restored.get("erin"), tree.get("erin"));
+
+    let duplicated = json.replace("\"bob\"", "\"alice\"");
+    assert!(serde_json::from_str::<KeyedMerkleTree>(&duplicated).is_err());
+}
+
+#[test]
+fn test_coverage_tracker() {
+    let data = create_test_data(5);
+    let tree = MerkleTree::builder().build(data.clone());
+    let mut tracker = CoverageTracker::new(tree.root_hash().unwrap(), 5);
+
+    let proof = tree.proof_by_index(3).unwrap();
+    assert_eq!(tracker.record(3, &data[3], &proof), Ok(()));
+    assert_eq!(tracker.record(3, &data[3], &proof), Ok(()));
+    assert_eq!(tracker.verified_count(), 1);
+    assert_eq!(tracker.coverage(), 0.2);
+
+    // A valid proof recorded under the wrong index, or for the wrong data, marks nothing
+    assert!(tracker.record(2, &data[3], &proof).is_err());
+    assert_eq!(
+        tracker.record(1, &data[1], &proof),
+        Err(VerificationError::IndexMismatch {
+            expected: 1,
+            actual: 3
+        })
+    );
+    assert_eq!(
+        tracker.record(5, &data[3], &proof),
+        Err(VerificationError::IndexOutOfRange {
+            index: 5,
+            leaf_count: 5
+        })
+    );
+    assert_eq!(tracker.missing().collect::<Vec<_>>(), [0, 1, 2, 4]);
+
+    for index in [0, 1, 2, 4] {
+        let proof = tree.proof_by_index(index).unwrap();
+        tracker.record(index, &data[index], &proof).unwrap();
+    }
+    assert!(tracker.is_complete());
+    assert_eq!(tracker.coverage(), 1.0);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_coverage_tracker_json_round_trip() {
+    let data = create_test_data(11);
+    let tree = MerkleTree::builder().build(data.clone());
+    let mut tracker = CoverageTracker::new(tree.root_hash().unwrap(), 11);
+    for index in [0, 9, 10] {
+        let proof = tree.proof_by_index(index).unwrap();
+        tracker.record(index, &data[index], &proof).unwrap();
+    }
+
+    let json = serde_json::to_string(&tracker).unwrap();
+    let restored: CoverageTracker = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, tracker);
+    assert_eq!(restored.missing().count(), 8);
+}
+
+#[test]
+fn test_proof_canonicalization() {
+    let data = create_test_data(6);
+    let tree = MerkleTree::builder()
+        .pair_ordering(PairOrdering::Sorted)
+        .build(data.clone());
+    let root_hash = tree.root_hash().unwrap();
+    let proof = tree.generate_proof(&data[3]).unwrap();
+    assert!(proof.iter().all(|(_, is_left)| !is_left));
+    assert_eq!(proof.canonicalize_with(PairOrdering::Sorted), proof);
+
+    // Direction flags are ignored under sorted ordering, so a flipped proof still verifies
+    let flipped: MerkleProof = proof
+        .iter()
+        .map(|(hash, is_left)| (hash.clone(), !is_left))
+        .collect();
+    assert!(MerkleTree::verify_proof_with(
+        tree.hash_config(),
+        &data[3],
+        &flipped,
+        &root_hash,
+        Some(6)
+    ));
+    assert_ne!(flipped, proof);
+    assert_eq!(flipped.canonicalize_with(PairOrdering::Sorted), proof);
+
+    let unique: std::collections::HashSet<MerkleProof> = [&flipped, &proof]
+        .iter()
+        .map(|proof| proof.canonicalize_with(PairOrdering::Sorted))
+        .collect();
+    assert_eq!(unique.len(), 1);
+
+    let positional = MerkleTree::new(data.clone());
+    let proof = positional.generate_proof(&data[3]).unwrap();
+    assert_eq!(proof.canonicalize(), proof);
+}
+
+#[test]
+fn test_proof_eq_and_hash_follow_canonical_bytes() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash_of(proof: &MerkleProof) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        proof.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let data = create_test_data(6);
+    let tree = MerkleTree::new(data.clone());
+    let proof = tree.generate_proof(&data[3]).unwrap();
+
+    // A decoded proof and a stamped proof encode to the same bytes as the original
+    let decoded = MerkleProof::from_bytes(&proof.to_bytes()).unwrap();
+    let stamped = tree.generate_stamped_proof(3).unwrap();
+    for other in [&decoded, &stamped] {
+        assert_eq!(other.to_bytes(), proof.to_bytes());
+        assert_eq!(*other, proof);
+        assert_eq!(hash_of(other), hash_of(&proof));
+    }
+
+    // Proofs that encode differently are unequal
+    let other = tree.generate_proof(&data[2]).unwrap();
+    assert_ne!(other.to_bytes(), proof.to_bytes());
+    assert_ne!(other, proof);
+
+    // Hashes too long for the compact encoding still compare without panicking, and
+    // the length is part of the comparison
+    let long = MerkleProof::new(vec![(vec![7; 300], true)]);
+    let split = MerkleProof::new(vec![(vec![7; 150], true), (vec![7; 150], true)]);
+    assert_eq!(long, long.clone());
+    assert_eq!(hash_of(&long), hash_of(&long.clone()));
+    assert_ne!(long, split);
+}
+
+#[test]
+fn test_push_and_update_match_rebuild() {
+    let data = create_test_data(13);
+    let mut tree = MerkleTree::builder().build(Vec::new());
+    for (count, item) in data.iter().enumerate() {
+        tree.push(item.clone());
+        let rebuilt = MerkleTree::builder().build(data[..=count].to_vec());
+        assert_eq!(tree.root_hash(), rebuilt.root_hash());
+        assert_eq!(tree.len(), rebuilt.len());
+    }
+
+    let mut data = data;
+    for index in [0, 5, 12] {
+        data[index] = format!("updated {}", index).into_bytes();
+        tree.update_by_index(index, data[index].clone()).unwrap();
+        let rebuilt = MerkleTree::builder().build(data.clone());
+        assert_eq!(tree.root_hash(), rebuilt.root_hash());
+        assert_eq!(tree.get_leaf(index), Some(&data[index][..]));
+    }
+    assert_eq!(
+        tree.update_by_index(13, Vec::new()),
+        Err(MerkleError::IndexOutOfRange {
+            index: 13,
+            leaf_count: 13
+        })
+    );
+}
+
+#[test]
+fn test_update_rehashes_only_the_path() {
+    let mut tree = MerkleTree::new(create_test_data(16));
+    tree.reset_metrics();
+    tree.update_by_index(6, b"changed".to_vec()).unwrap();
+    assert_eq!(
+        tree.metrics(),
+        HashMetrics {
+            leaf_hashes: 1,
+            node_hashes: tree.height() as u64 - 1
+        }
+    );
+}
+
+#[test]
+fn test_concurrent_tree_appends_while_reading() {
+    let tree =
+        ConcurrentMerkleTree::with_history(MerkleTree::builder().build(create_test_data(4)), 64);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..40 {
+                tree.append(format!("appended {}", i).into_bytes());
+            }
+        });
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..40 {
+                    // Every snapshot is a complete tree whose proofs match its own root
+                    let snapshot = tree.snapshot();
+                    let index = snapshot.leaf_count() - 1;
+                    let proof = snapshot.proof_by_index(index).unwrap();
+                    assert!(snapshot.verify(snapshot.get_leaf(index).unwrap(), &proof));
+                }
+            });
+        }
+    });
+
+    assert_eq!(tree.version(), 40);
+    assert_eq!(tree.snapshot().leaf_count(), 44);
+    let old = tree.snapshot_at_version(10).unwrap();
+    assert_eq!(old.leaf_count(), 14);
+    let proof = tree.proof_at_version(10, 3).unwrap();
+    assert!(old.verify(&create_test_data(4)[3], &proof));
+    assert_eq!(
+        tree.proof_at_version(41, 0),
+        Err(MerkleError::VersionUnavailable { version: 41 })
+    );
+
+    assert_eq!(tree.update(2, b"replaced".to_vec()), Ok(41));
+    assert!(tree.update(99, Vec::new()).is_err());
+    assert_eq!(tree.version(), 41);
+    assert_ne!(tree.snapshot().root_hash(), old.root_hash());
+
+    // A write shares every leaf it does not touch with the version before it
+    let before = tree.snapshot();
+    tree.update(40, b"replaced again".to_vec()).unwrap();
+    let after = tree.snapshot();
+    assert_eq!(
+        after.get_leaf(3).unwrap().as_ptr(),
+        before.get_leaf(3).unwrap().as_ptr()
+    );
+    assert_eq!(before.get_leaf(40), Some(&b"appended 36"[..]));
+    assert_eq!(after.get_leaf(40), Some(&b"replaced again"[..]));
+}
+
+#[test]
+fn test_shared_vec_matches_vec() {
+    use crate::shared_vec::SharedVec;
+
+    // Sizes around the trie's node boundaries, written through clones
+    let mut model: Vec<usize> = Vec::new();
+    let mut shared = SharedVec::new();
+    let mut clones = Vec::new();
+    for step in 0..3000 {
+        match step % 7 {
+            0..=3 => {
+                model.push(step);
+                shared.push(step);
+            }
+            4 if !model.is_empty() => {
+                let index = step * 31 % model.len();
+                model[index] = step + 1;
+                shared[index] = step + 1;
+            }
+            5 if step % 5 == 0 => {
+                assert_eq!(shared.pop(), model.pop());
+            }
+            _ => clones.push((model.clone(), shared.clone())),
+        }
+        assert_eq!(shared.len(), model.len());
+    }
+    assert_eq!(shared.to_vec(), model);
+    assert_eq!(shared.iter().rev().copied().collect::<Vec<_>>(), {
+        let mut reversed = model.clone();
+        reversed.reverse();
+        reversed
+    });
+    assert_eq!(shared.get(model.len()), None);
+    let even: SharedVec<usize> = (0..2000).map(|value| value * 2).collect();
+    assert_eq!(even.binary_search_by(|value| value.cmp(&1400)), Ok(700));
+    assert_eq!(even.binary_search_by(|value| value.cmp(&1401)), Err(701));
+    for (expected, clone) in &clones {
+        assert_eq!(&clone.to_vec(), expected);
+    }
+
+    for len in [1056, 1024, 1023, 33, 32, 1, 0] {
+        shared.truncate(len);
+        model.truncate(len);
+        assert_eq!(shared.to_vec(), model);
+    }
+    shared.resize(40, 7);
+    assert_eq!(shared.split_off(35), [7; 5]);
+    assert_eq!(shared.to_vec(), [7; 35]);
+}
+
+#[cfg(feature = "proptest")]
+mod properties {
+    use crate::testing::strategy::{corrupted_proof_cases, proof_cases};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn generated_proofs_verify_and_bit_flips_fail(case in proof_cases(), bit in any::<usize>()) {
+            prop_assert!(case.verifies());
+
+            let mut data = case.data.clone();
+            if data.is_empty() {
+                data.push(0);
+            } else {
+                let bit = bit % (data.len() * 8);
+                data[bit / 8] ^= 1 << (bit % 8);
+            }
+            prop_assert!(!case.tree.verify(&data, &case.proof));
+
+            for step in 0..case.proof.len() {
+                let mut proof = case.proof.clone();
+                let bit = bit % (proof[step].0.len() * 8);
+                proof[step].0[bit / 8] ^= 1 << (bit % 8);
+                prop_assert!(!case.tree.verify(&case.data, &proof));
+            }
+        }
+
+        #[test]
+        fn corrupted_proofs_fail(case in corrupted_proof_cases()) {
+            prop_assert!(!case.verifies());
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_arbitrary_proof_cases_verify() {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    let mut rng = XorShift(0x5eed);
+    for _ in 0..50 {
+        let bytes = rng.bytes(512);
+        let mut u = Unstructured::new(&bytes);
+        let case = crate::testing::ProofCase::arbitrary(&mut u).unwrap();
+        assert!(case.tree.leaf_count() <= crate::testing::MAX_LEAVES);
+        assert!(case.verifies());
+    }
+}
+
+#[test]
+fn test_leaf_encoders_pin_their_layout() {
+    assert_eq!(RawBytes::encode(&vec![0x00, 0xff]).unwrap(), [0x00, 0xff]);
+    assert_eq!(
+        Utf8String::encode(&"héllo".to_string()).unwrap(),
+        b"h\xc3\xa9llo"
+    );
+
+    let (address, amount) = AbiPacked::item([0x11; 20], 1_000);
+    assert_eq!(
+        hex::encode(AbiPacked::encode(&(address, amount)).unwrap()),
+        format!("{}{}03e8", "11".repeat(20), "00".repeat(30))
+    );
+
+    assert_eq!(
+        LengthPrefixed::encode(&vec![b"ab".to_vec(), Vec::new(), b"c".to_vec()]).unwrap(),
+        [0, 0, 0, 2, b'a', b'b', 0, 0, 0, 0, 0, 0, 0, 1, b'c']
+    );
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_length_prefixed_rejects_oversized_fields() {
+    // The zeroed field is never written, so its pages are never touched
+    let size = u64::from(u32::MAX) + 1;
+    let item = vec![b"ok".to_vec(), vec![0; size as usize]];
+    let error = MerkleError::FieldTooLarge { field: 1, size };
+    assert_eq!(LengthPrefixed::encode(&item), Err(error.clone()));
+    assert_eq!(error.status_code(), 413);
+    let tree = MerkleTree::builder().build_encoded::<LengthPrefixed>(vec![vec![b"ok".to_vec()]]);
+    assert!(tree.generate_proof_for::<LengthPrefixed>(&item).is_none());
+    assert_eq!(
+        MerkleTree::builder()
+            .try_build_encoded::<LengthPrefixed>(vec![item])
+            .err(),
+        Some(error)
+    );
+}
+
+#[test]
+fn test_encoded_tree_proves_typed_items() {
+    let names: Vec<String> = ["alice", "bob", "carol"].map(String::from).to_vec();
+    let tree = MerkleTree::new_encoded::<Utf8String>(names.clone());
+    let raw = MerkleTree::new(names.iter().map(|name| name.clone().into_bytes()).collect());
+    assert_eq!(tree.root_hash(), raw.root_hash());
+    assert_eq!(tree.encoder_id(), Some(Utf8String::ID));
+    assert_eq!(raw.encoder_id(), None);
+
+    let proof = tree.generate_proof_for::<Utf8String>(&names[1]).unwrap();
+    assert!(tree.verify(b"bob", &proof));
+    // A different encoder is refused even if it would produce the same bytes
+    assert!(tree
+        .generate_proof_for::<RawBytes>(&b"bob".to_vec())
+        .is_none());
+    assert!(raw.generate_proof_for::<Utf8String>(&names[1]).is_none());
+}
+
+#[test]
+fn test_string_constructors_match_byte_constructors() {
+    let transactions = [
+        "Alice sends 5 BTC to Bob",
+        "Bob sends 3 BTC to Charlie",
+        "Eve",
+    ];
+    let bytes = MerkleTree::new(
+        transactions
+            .iter()
+            .map(|tx| tx.as_bytes().to_vec())
+            .collect(),
+    );
+
+    let from_strs = MerkleTree::from_strings(transactions);
+    let from_vec = MerkleTree::from(transactions.map(String::from).to_vec());
+    assert_eq!(from_strs.root_hash(), bytes.root_hash());
+    assert_eq!(from_vec.root_hash(), bytes.root_hash());
+
+    let root_hash = from_strs.root_hash().unwrap();
+    let proof = from_strs.generate_proof_str("Eve").unwrap();
+    assert_eq!(Some(&proof), bytes.generate_proof(b"Eve").as_ref());
+    assert!(MerkleTree::verify_proof_str("Eve", &proof, &root_hash));
+    assert!(!MerkleTree::verify_proof_str("Eve ", &proof, &root_hash));
+    assert!(from_strs.generate_proof_str("Mallory").is_none());
+}
+
+#[test]
+fn test_render_with_options() {
+    let tree = MerkleTree::new(create_test_data(3));
+    let full = tree.render_with(&TreePrintOptions::default());
+    assert_eq!(full.lines().count(), 8);
+    assert!(full.contains("├── Leaf 0: data=\"Test data 0\""));
+    assert!(full.contains("└── Leaf 3 (padding): data=\"Test data 2\""));
+
+    let opts = TreePrintOptions {
+        max_depth: Some(1),
+        hash_bytes: Some(100),
+        show_data: false,
+        ..TreePrintOptions::default()
+    };
+    let shallow = tree.render_with(&opts);
+    assert_eq!(shallow.lines().count(), 6);
+    assert!(shallow.contains(&tree.root_hash_hex()));
+    assert!(shallow.contains("└── … 2 leaves below"));
+
+    let opts = TreePrintOptions {
+        max_leaves: Some(1),
+        ..TreePrintOptions::default()
+    };
+    assert!(tree
+        .render_with(&opts)
+        .ends_with("… 3 more leaves not shown\n"));
+
+    for count in [0, 1] {
+        let tree = MerkleTree::new(create_test_data(count));
+        assert_eq!(tree.render_with(&opts).lines().count(), count + 1);
+    }
+
+    let opts = TreePrintOptions {
+        color: true,
+        ..TreePrintOptions::default()
+    };
+    let colored = tree.render_with(&opts);
+    assert_eq!(
+        colored.contains("\x1b[33mLeaf 3 (padding)"),
+        cfg!(feature = "cli")
+    );
+}
+
+#[test]
+fn test_progress_events() {
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    let tree = MerkleTree::builder()
+        .on_progress(move |event| sink.lock().unwrap().push(*event))
+        .build(create_test_data(5));
+
+    let events = events.lock().unwrap();
+    let leaves: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::LeafHashed { index, total } => Some((*index, *total)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(leaves, (0..5).map(|index| (index, 5)).collect::<Vec<_>>());
+
+    // 5 leaves are padded to 6, then levels of 3, 2 and 1 nodes
+    let levels: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::LevelCompleted { level, nodes } => Some((*level, *nodes)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(levels, [(0, 6), (1, 3), (2, 2), (3, 1)]);
+    assert_eq!(levels.len(), tree.height());
+}
+
+#[cfg(feature = "keccak")]
+#[test]
+fn test_multi_verifier_during_migration() {
+    use crate::{HashAlgorithm, MultiVerifier};
+
+    assert_eq!(
+        hex::encode(HashAlgorithm::Keccak256.digest(&[])),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+
+    let data = create_test_data(6);
+    let old = MerkleTree::builder().build(data.clone());
+    let new = MerkleTree::builder()
+        .hash_algorithm(HashAlgorithm::Keccak256)
+        .pair_ordering(PairOrdering::Sorted)
+        .build(data.clone());
+    assert_ne!(old.root_hash(), new.root_hash());
+    let verifier = MultiVerifier::new(vec![
+        MerkleVerifier::for_tree(&old).unwrap(),
+        MerkleVerifier::for_tree(&new).unwrap(),
+    ]);
+
+    let old_proof = old.proof_by_index(4).unwrap();
+    let new_proof = new.proof_by_index(4).unwrap();
+    assert_eq!(verifier.verify_any(&data[4], &old_proof), Some(0));
+    assert_eq!(verifier.verify_any(&data[4], &new_proof), Some(1));
+    assert_eq!(verifier.verify_any(&data[3], &new_proof), None);
+    assert!(verifier.verify_all(&data[4], &[old_proof.clone(), new_proof.clone()]));
+    assert!(!verifier.verify_all(&data[4], &[old_proof.clone(), old_proof.clone()]));
+    assert!(!verifier.verify_all(&data[4], &[old_proof]));
+
+    let malformed = [
+        MerkleProof::default(),
+        MerkleProof::new(vec![(vec![1, 2, 3], true); 3]),
+        MerkleProof::new(vec![(vec![0; 32], false); MAX_PROOF_STEPS * 2]),
+    ];
+    for proof in &malformed {
+        assert_eq!(verifier.verify_any(&data[4], proof), None);
+    }
+    assert_eq!(
+        MultiVerifier::default().verify_any(&data[4], &new_proof),
+        None
+    );
+}
+
+#[cfg(all(feature = "keccak", feature = "serde"))]
+#[test]
+fn test_multi_verifier_json_policy() {
+    let data = create_test_data(3);
+    let tree = MerkleTree::builder()
+        .hash_algorithm(crate::HashAlgorithm::Keccak256)
+        .build(data.clone());
+    let policy = format!(
+        r#"{{"verifiers": [
+            {{"root": "{}", "algorithm": "Sha256", "scheme": "Plain", "ordering": "Positional"}},
+            {{"root": "{}", "algorithm": "Keccak256", "scheme": "DomainSeparated", "ordering": "Positional"}}
+        ]}}"#,
+        "00".repeat(32),
+        tree.root_hash_hex()
+    );
+    let verifier: crate::MultiVerifier = serde_json::from_str(&policy).unwrap();
+    let proof = tree.proof_by_index(2).unwrap();
+    assert_eq!(verifier.verify_any(&data[2], &proof), Some(1));
+}
+
+#[test]
+fn test_known_answer_vectors() {
+    crate::test_vectors::assert_all();
+}
+
+#[test]
+fn test_small_trees_match_the_generic_path() {
+    use crate::ByteOrder;
+
+    let configs = [
+        HashConfig::from(HashScheme::Plain),
+        HashConfig::from(HashScheme::DomainSeparated),
+        HashConfig {
+            ordering: PairOrdering::Sorted,
+            ..HashConfig::from(HashScheme::Plain)
+        },
+        HashConfig::from(HashScheme::DomainSeparated).with_byte_order(ByteOrder::Reversed),
+        HashConfig::new(
+            HashAlgorithm::Sha512,
+            HashScheme::Plain,
+            PairOrdering::Positional,
+        ),
+    ];
+    for config in configs {
+        // Counts past 32 take the generic path in both cases
+        for count in 0..=40 {
+            let data = create_test_data(count);
+            let small = MerkleTree::with_config(data.clone(), config);
+            let generic = MerkleTree::builder()
+                .hash_algorithm(config.algorithm)
+                .hash_scheme(config.scheme)
+                .pair_ordering(config.ordering)
+                .byte_order(config.byte_order)
+                .build(data.clone());
+            assert_eq!(small.levels(), generic.levels(), "{} leaves", count);
+            assert_eq!(small.params(), generic.params());
+            assert_eq!(small.metrics(), generic.metrics());
+            assert_eq!(small.audit(), Ok(()));
+            for (index, item) in data.iter().enumerate() {
+                let proof = small.proof_by_index(index).unwrap();
+                assert_eq!(Some(&proof), generic.proof_by_index(index).as_ref());
+                assert!(small.verify(item, &proof));
+            }
+        }
+    }
+
+    // The stack-built tree grows and changes like any other
+    let mut small = MerkleTree::new(create_test_data(5));
+    small.push(b"Test data 5".to_vec());
+    small.update_by_index(0, b"Changed".to_vec()).unwrap();
+    let mut data = create_test_data(6);
+    data[0] = b"Changed".to_vec();
+    assert_eq!(small.root_hash(), MerkleTree::new(data).root_hash());
+}
+
+#[test]
+fn test_split_and_combine_roots() {
+    for count in 1..40 {
+        let data = create_test_data(count);
+        let tree = MerkleTree::builder().build(data.clone());
+        for shard_leaves in [2, 4, 8, 64] {
+            let shards = tree.split(shard_leaves).unwrap();
+            assert_eq!(shards.len(), count.div_ceil(shard_leaves));
+            let roots: Vec<Vec<u8>> = shards
+                .iter()
+                .map(|shard| shard.root_hash().unwrap())
+                .collect();
+            assert_eq!(
+                MerkleTree::combine_roots_with(tree.hash_config(), &roots),
+                tree.root_hash(),
+                "{} leaves in shards of {}",
+                count,
+                shard_leaves
+            );
+        }
+    }
+
+    // A lone final leaf is padded with its duplicate, as the original tree pads it, but
+    // the duplicate is not a leaf of the shard
+    let tree = MerkleTree::new(create_test_data(5));
+    let shards = tree.split(2).unwrap();
+    let lone = &shards[2];
+    assert_eq!(lone.leaf_count(), 1);
+    assert_eq!(lone.get_leaf(1), None);
+    assert!(lone.proof_by_index(1).is_none());
+    assert_eq!(lone.params().padding, PaddingStrategy::DuplicateLast);
+    assert_eq!(lone.audit(), Ok(()));
+    let proof = lone.proof_by_index(0).unwrap();
+    assert!(lone.verify(b"Test data 4", &proof));
+    let verifier = MerkleVerifier::for_tree(lone).unwrap();
+    assert_eq!(verifier.verify(b"Test data 4", &proof), Ok(()));
+    let loaded = MerkleTree::from_snapshot(&lone.to_snapshot()).unwrap();
+    assert_eq!(loaded.root_hash(), lone.root_hash());
+    assert_eq!(loaded.leaf_count(), 1);
+
+    // Updating the lone leaf updates its duplicate with it
+    let mut updated = lone.clone();
+    updated.update_by_index(0, b"New data".to_vec()).unwrap();
+    let mut whole = tree.clone();
+    whole.update_by_index(4, b"New data".to_vec()).unwrap();
+    let mut roots: Vec<Vec<u8>> = shards[..2]
+        .iter()
+        .map(|shard| shard.root_hash().unwrap())
+        .collect();
+    roots.push(updated.root_hash().unwrap());
+    assert_eq!(MerkleTree::combine_roots(&roots), whole.root_hash());
+    assert_eq!(updated.audit(), Ok(()));
+    let roots: Vec<Vec<u8>> = shards
+        .iter()
+        .map(|shard| shard.root_hash().unwrap())
+        .collect();
+    assert_eq!(MerkleTree::combine_roots(&roots), tree.root_hash());
+
+    // Positional leaf hashes commit to their index in the whole tree
+    let positional = MerkleTree::builder()
+        .positional(true)
+        .build(create_test_data(5));
+    assert_eq!(
+        positional.split(2).err(),
+        Some(MerkleError::PositionalSplit)
+    );
+
+    for shard_leaves in [0, 1, 3, 6] {
+        assert_eq!(
+            tree.split(shard_leaves).err(),
+            Some(MerkleError::InvalidShardSize { shard_leaves })
+        );
+    }
+    assert!(MerkleTree::new(Vec::new()).split(4).unwrap().is_empty());
+    assert_eq!(MerkleTree::combine_roots(&[]), None);
+}
+
+#[test]
+fn test_positional_leaves_commit_to_index() {
+    let data = create_test_data(4);
+    let mut swapped = data.clone();
+    swapped.swap(1, 2);
+
+    // Membership alone cannot tell the two orders apart, but their roots differ
+    let plain = MerkleTree::builder().build(data.clone());
+    let plain_swapped = MerkleTree::builder().build(swapped.clone());
+    assert!(swapped.iter().all(|item| plain.index_of(item).is_some()));
+    let positional = MerkleTree::builder().positional(true).build(data.clone());
+    let positional_swapped = MerkleTree::builder().positional(true).build(swapped);
+    assert_ne!(plain.root_hash(), plain_swapped.root_hash());
+    assert_ne!(positional.root_hash(), positional_swapped.root_hash());
+    assert_ne!(positional.root_hash(), plain.root_hash());
+    assert!(positional.is_positional());
+
+    // Equal data at different slots still hashes differently
+    let repeated = MerkleTree::builder()
+        .positional(true)
+        .build(vec![b"same".to_vec(); 2]);
+    assert_ne!(
+        repeated.node_hash_by_index(0, 0),
+        repeated.node_hash_by_index(0, 1)
+    );
+
+    let root_hash = positional.root_hash().unwrap();
+    let proof = positional.generate_proof_positional(2, &data[2]).unwrap();
+    assert!(positional.verify(&data[2], &proof));
+    assert!(MerkleTree::verify_proof_positional(
+        positional.hash_config(),
+        2,
+        &data[2],
+        &proof,
+        &root_hash,
+        Some(4)
+    ));
+    assert!(!MerkleTree::verify_proof_positional(
+        positional.hash_config(),
+        1,
+        &data[2],
+        &proof,
+        &root_hash,
+        None
+    ));
+    assert!(positional.generate_proof_positional(1, &data[2]).is_none());
+    assert_eq!(positional.index_of(&data[3]), Some(3));
+
+    let mut grown = positional.clone();
+    grown.push(b"extra".to_vec());
+    let mut rebuilt = data.clone();
+    rebuilt.push(b"extra".to_vec());
+    assert_eq!(
+        grown.root_hash(),
+        MerkleTree::builder()
+            .positional(true)
+            .build(rebuilt)
+            .root_hash()
+    );
+}
+
+#[test]
+fn test_verify_proof_in_place_does_not_allocate() {
+    let data = create_test_data(7);
+    let tree = MerkleTree::new(data.clone());
+    let root: [u8; 32] = tree.root_hash().unwrap().try_into().unwrap();
+
+    for (index, leaf) in data.iter().enumerate() {
+        let proof = tree.proof_by_index(index).unwrap();
+        let steps = || {
+            proof
+                .iter()
+                .map(|(hash, is_left)| (hash.as_slice(), *is_left))
+        };
+        let (valid, allocations) =
+            count_allocations(|| MerkleTree::verify_proof_in_place(leaf, steps(), &root));
+        assert!(valid);
+        assert_eq!(allocations, 0);
+        assert!(!MerkleTree::verify_proof_in_place(
+            b"Wrong data",
+            steps(),
+            &root
+        ));
+    }
+
+    // Truncated sibling hashes are rejected rather than hashed
+    let proof = tree.proof_by_index(0).unwrap();
+    let truncated = proof.iter().map(|(hash, is_left)| (&hash[..31], *is_left));
+    assert!(!MerkleTree::verify_proof_in_place(
+        &data[0], truncated, &root
+    ));
+
+    let tree = MerkleTree::builder()
+        .hash_scheme(HashScheme::DomainSeparated)
+        .build(data.clone());
+    let root: [u8; 32] = tree.root_hash().unwrap().try_into().unwrap();
+    let proof = tree.proof_by_index(3).unwrap();
+    let steps = proof
+        .iter()
+        .map(|(hash, is_left)| (hash.as_slice(), *is_left));
+    assert!(MerkleTree::verify_proof_in_place_with(
+        HashScheme::DomainSeparated,
+        &data[3],
+        steps,
+        &root
+    ));
+}
+
+#[test]
+fn test_explain_mismatch() {
+    let data = create_test_data(8);
+    let tree = MerkleTree::new(data.clone());
+
+    let report = tree.explain_mismatch(&MerkleTree::new(data.clone()));
+    assert!(report.roots_match());
+    assert_eq!(report.first_divergence, None);
+    assert!(report.to_string().starts_with("Roots match: "));
+
+    let mut changed = data.clone();
+    changed[5] = b"Tampered".to_vec();
+    let report = tree.explain_mismatch(&MerkleTree::new(changed));
+    assert!(!report.roots_match());
+    assert!(!report.parameters_differ());
+    assert_eq!(report.first_divergence, Some((0, 5)));
+    assert_eq!(report.differing_leaf_count, 1);
+    assert_eq!(report.differing_leaves[0].index, 5);
+    assert!(report.to_string().contains("leaf 5: ours "));
+
+    // Extra leaves are reported as a count difference, not as differing content. The
+    // first eight leaves still hash to our root, so the divergence is the subtree holding
+    // the extra leaf, which only they have
+    let mut longer = data.clone();
+    longer.push(b"Extra".to_vec());
+    let report = tree.explain_mismatch(&MerkleTree::new(longer));
+    assert_eq!(report.differing_leaf_count, 0);
+    assert_eq!(report.first_divergence, Some((3, 1)));
+    assert!(report.to_string().contains("Leaf counts differ: 8 vs 9"));
+
+    let reversed: Vec<_> = data.iter().rev().cloned().collect();
+    let report = tree.explain_mismatch(&MerkleTree::new(reversed));
+    assert_eq!(report.differing_leaf_count, 8);
+    assert_eq!(report.differing_leaves.len(), 8);
+
+    let many = create_test_data(32);
+    let shifted: Vec<_> = many.iter().skip(1).chain(&many[..1]).cloned().collect();
+    let report = MerkleTree::new(many).explain_mismatch(&MerkleTree::new(shifted));
+    assert_eq!(report.differing_leaf_count, 32);
+    assert_eq!(
+        report.differing_leaves.len(),
+        MismatchReport::MAX_LISTED_LEAVES
+    );
+    assert!(report.to_string().contains("... and 22 more"));
+
+    let other = MerkleTree::builder()
+        .hash_scheme(HashScheme::DomainSeparated)
+        .build(data);
+    let report = tree.explain_mismatch(&other);
+    assert!(report.parameters_differ());
+    assert!(report.to_string().contains("Hash configurations differ"));
+}
+
+#[test]
+fn test_reject_duplicate_leaves() {
+    let mut data = create_test_data(6);
+    assert_eq!(
+        MerkleTree::new_unique(data.clone()).unwrap().root_hash(),
+        MerkleTree::new(data.clone()).root_hash()
+    );
+
+    data.push(data[2].clone());
+    data.push(data[4].clone());
+    let error = MerkleTree::new_unique(data.clone()).unwrap_err();
+    let hash = hex::encode(Sha256::digest(&data[2]));
+    assert_eq!(
+        error,
+        MerkleError::DuplicateLeaf {
+            first: 2,
+            second: 6,
+            hash: hash.clone()
+        }
+    );
+    assert!(error.to_string().contains(&hash));
+
+    // The default constructor still commits to duplicates
+    assert_eq!(MerkleTree::new(data.clone()).leaf_count(), 8);
+
+    let builder = MerkleTree::builder().reject_duplicates(true);
+    assert!(builder.clone().try_build(data.clone()).is_err());
+    assert!(builder.positional(true).try_build(data.clone()).is_ok());
+    assert!(MerkleTree::builder().try_build(data).is_ok());
+}
+
+#[test]
+fn test_empty_root_policies() {
+    let empty = MerkleTree::builder().build(Vec::new());
+    assert_eq!(empty.empty_root_policy(), EmptyRootPolicy::None);
+    assert_eq!(empty.root_hash(), None);
+
+    let hash_of_empty = Sha256::digest(b"").to_vec();
+    for (policy, root) in [
+        (EmptyRootPolicy::HashOfEmpty, hash_of_empty),
+        (EmptyRootPolicy::Zero32, vec![0; 32]),
+    ] {
+        let mut tree = MerkleTree::builder().empty_root(policy).build(Vec::new());
+        assert_eq!(tree.root_hash(), Some(root.clone()));
+        assert_eq!(tree.root_hash_hex(), hex::encode(&root));
+
+        // Nothing verifies against an empty root, not even an empty proof of empty data
+        assert!(!tree.verify(b"", &MerkleProof::default()));
+        assert!(!MerkleTree::verify_proof_with(
+            HashScheme::DomainSeparated,
+            b"",
+            &MerkleProof::default(),
+            &root,
+            Some(0)
+        ));
+        assert!(MerkleVerifier::for_tree(&tree)
+            .unwrap()
+            .verify(b"", &MerkleProof::default())
+            .is_err());
+
+        // The first append replaces the empty root with the leaf-derived root
+        tree.push(b"First".to_vec());
+        let expected = MerkleTree::builder().build(vec![b"First".to_vec()]);
+        assert_eq!(tree.root_hash(), expected.root_hash());
+        let proof = tree.proof_by_index(0).unwrap();
+        assert!(tree.verify(b"First", &proof));
+
+        let concurrent =
+            ConcurrentMerkleTree::new(MerkleTree::builder().empty_root(policy).build(Vec::new()));
+        assert_eq!(concurrent.snapshot().root_hash(), Some(root));
+        concurrent.append(b"First".to_vec());
+        assert_eq!(concurrent.snapshot().root_hash(), expected.root_hash());
+    }
+
+    // Under plain hashing H("") is the root of a single empty leaf, so an empty proof of
+    // empty data would verify against it; the pairing is refused
+    let hash_of_empty = Sha256::digest(b"").to_vec();
+    assert_eq!(
+        MerkleTree::new(vec![Vec::new()]).root_hash(),
+        Some(hash_of_empty.clone())
+    );
+    assert!(MerkleTree::verify_proof(
+        b"",
+        &MerkleProof::default(),
+        &hash_of_empty
+    ));
+    let plain = MerkleTree::builder()
+        .hash_scheme(HashScheme::Plain)
+        .empty_root(EmptyRootPolicy::HashOfEmpty);
+    let error = plain.clone().try_build(Vec::new()).unwrap_err();
+    assert_eq!(error, MerkleError::AmbiguousEmptyRoot);
+    assert_eq!(error.status_code(), 422);
+    assert!(plain.try_build(vec![Vec::new()]).is_err());
+    let domain_separated = MerkleTree::builder()
+        .empty_root(EmptyRootPolicy::HashOfEmpty)
+        .build(Vec::new());
+    assert_eq!(
+        domain_separated.rehash_with(HashScheme::Plain).unwrap_err(),
+        MerkleError::AmbiguousEmptyRoot
+    );
+    assert_eq!(
+        verify_stream_with(
+            HashScheme::Plain,
+            EmptyRootPolicy::HashOfEmpty,
+            &hash_of_empty,
+            Vec::<Vec<u8>>::new().iter()
+        ),
+        Err(StreamVerifyError::NoEmptyRoot)
+    );
+}
+
+#[test]
+fn test_hash_index_tracks_pushes_and_updates() {
+    fn scan(tree: &MerkleTree, leaf_hash: &[u8]) -> Option<usize> {
+        (0..tree.leaf_count()).find(|&index| tree.levels()[0][index] == leaf_hash)
+    }
+
+    // Few distinct payloads, so most hashes are held by several leaves
+    let payload = |n: usize| vec![(n % 7) as u8];
+    let hashes: Vec<Vec<u8>> = (0..7)
+        .map(|n| Sha256::digest(payload(n)).to_vec())
+        .collect();
+    let mut tree = MerkleTree::new((0..5).map(payload).collect());
+    assert_eq!(tree.index_of_hash(&hashes[3]), Some(3));
+
+    let mut seed = 1u64;
+    for step in 0..300 {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let value = (seed >> 33) as usize;
+        if step % 3 == 0 {
+            tree.push(payload(value));
+        } else {
+            let index = value % tree.leaf_count();
+            tree.update_leaf(tree.leaf_index(index).unwrap(), payload(value / 7))
+                .unwrap();
+        }
+        for leaf_hash in &hashes {
+            assert_eq!(tree.index_of_hash(leaf_hash), scan(&tree, leaf_hash));
+        }
+    }
+
+    // Enough distinct hashes to grow the map, and a clone that diverges from the tree
+    let mut tree = MerkleTree::new(create_test_data(40));
+    assert_eq!(
+        tree.generate_proof(b"Test data 39"),
+        tree.proof_by_index(39)
+    );
+    let clone = tree.clone();
+    tree.update_leaf(tree.leaf_index(39).unwrap(), b"Test data 0".to_vec())
+        .unwrap();
+    assert_eq!(tree.index_of(b"Test data 39"), None);
+    assert_eq!(tree.index_of(b"Test data 0"), Some(0));
+    tree.update_leaf(tree.leaf_index(0).unwrap(), b"other".to_vec())
+        .unwrap();
+    assert_eq!(tree.index_of(b"Test data 0"), Some(39));
+    assert_eq!(clone.index_of(b"Test data 39"), Some(39));
+    assert_eq!(clone.index_of(b"Test data 0"), Some(0));
+
+    // Pushing past the map's initial capacity grows it
+    for item in create_test_data(200).into_iter().skip(40) {
+        tree.push(item);
+    }
+    for (index, item) in create_test_data(200).iter().enumerate().skip(40) {
+        assert_eq!(tree.index_of(item), Some(index));
+    }
+}
+
+#[test]
+fn test_proofs_addressed_by_leaf_hash() {
+    let mut data = create_test_data(5);
+    data.push(data[1].clone());
+    let tree = MerkleTree::new(data.clone());
+    let root = tree.root_hash().unwrap();
+
+    let leaf_hash = Sha256::digest(&data[3]).to_vec();
+    let proof = tree.generate_proof_for_hash(&leaf_hash).unwrap();
+    assert_eq!(proof, tree.generate_proof(&data[3]).unwrap());
+    assert!(MerkleTree::verify_proof_for_hash(&leaf_hash, &proof, &root));
+    assert!(!MerkleTree::verify_proof_for_hash(&root, &proof, &root));
+
+    // A repeated leaf resolves to its lowest index
+    let leaf_hash = Sha256::digest(&data[1]).to_vec();
+    let proof = tree.generate_proof_for_hash(&leaf_hash).unwrap();
+    assert_eq!(proof, tree.proof_by_index(1).unwrap());
+    assert!(tree.generate_proof_for_hash(&[0; 32]).is_none());
+
+    let tree = MerkleTree::builder().positional(true).build(data);
+    let root = tree.root_hash().unwrap();
+    let leaf_hash = tree.node_hash_by_index(0, 5).unwrap();
+    let proof = tree.generate_proof_for_hash(&leaf_hash).unwrap();
+    assert!(MerkleTree::verify_proof_for_hash_with(
+        HashScheme::DomainSeparated,
+        &leaf_hash,
+        &proof,
+        &root,
+        Some(6)
+    ));
+    assert!(!MerkleTree::verify_proof_for_hash_with(
+        HashScheme::DomainSeparated,
+        &leaf_hash,
+        &proof,
+        &root,
+        Some(9)
+    ));
+}
+
+#[test]
+fn test_fixed_records() {
+    let records: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i; 64]).collect();
+    let buf = records.concat();
+    let tree = MerkleTree::from_fixed_records(&buf, 64).unwrap();
+    assert_eq!(tree.leaf_count(), 7);
+    assert_eq!(
+        tree.root_hash(),
+        MerkleTree::new(records.clone()).root_hash()
+    );
+    assert_eq!(tree.get_leaf(0), None);
+
+    let proof = tree.proof_by_index(4).unwrap();
+    assert!(tree.verify(&records[4], &proof));
+    assert_eq!(tree.generate_proof(&records[4]), Some(proof));
+
+    assert!(MerkleTree::from_fixed_records(&[], 64).unwrap().is_empty());
+    assert_eq!(
+        MerkleTree::from_fixed_records(&buf, 0).unwrap_err(),
+        MerkleError::InvalidRecordSize {
+            record_size: 0,
+            buffer_len: 448
+        }
+    );
+    assert!(MerkleTree::from_fixed_records(&buf, 100).is_err());
+}
+
+#[test]
+fn test_params_recorded_and_checked() {
+    let data = create_test_data(6);
+    let tree = MerkleTree::builder()
+        .pair_ordering(PairOrdering::Sorted)
+        .build(data.clone());
+    let params = tree.params();
+    assert_eq!(params.hasher, tree.hash_config());
+    assert_eq!(params.padding, PaddingStrategy::DuplicateLast);
+    assert!(!params.positional);
+    assert_eq!(params.leaf_prefix(), Some(crate::LEAF_PREFIX));
+    assert_eq!(MerkleTree::new(Vec::new()).params().leaf_prefix(), None);
+
+    let bundle = tree.generate_bundle(2).unwrap();
+    let root = tree.root_hash().unwrap();
+    let sorted = MerkleVerifier::for_tree(&tree).unwrap();
+    assert_eq!(sorted.verify_bundle(&data[2], &bundle), Ok(()));
+    assert_eq!(
+        sorted.verify_bundle(&data[3], &bundle),
+        Err(VerificationError::RootMismatch)
+    );
+
+    // A sorted-pairs proof checked by a positional-pairs verifier names the mismatch
+    let unsorted = MerkleVerifier::new(root);
+    assert_eq!(
+        unsorted.verify_bundle(&data[2], &bundle),
+        Err(VerificationError::ParamsMismatch {
+            expected: TreeParams::from(HashScheme::DomainSeparated),
+            actual: params,
+        })
+    );
+    assert!(tree.generate_bundle(6).is_none());
+}
+
+#[test]
+fn test_proof_transport_encodings() {
+    let data = create_test_data(5);
+    let tree = MerkleTree::new(data.clone());
+    let root = tree.root_hash().unwrap();
+    let proof = tree.proof_by_index(4).unwrap();
+
+    let bytes = proof.to_bytes();
+    assert_eq!(bytes.len(), proof.len() * 34);
+    let from_base64 = MerkleProof::from_base64(&proof.to_base64()).unwrap();
+    let from_hex = MerkleProof::from_hex(&from_base64.to_hex()).unwrap();
+    let round_tripped = MerkleProof::from_bytes(&from_hex.to_bytes()).unwrap();
+    assert_eq!(round_tripped, proof);
+    assert!(MerkleTree::verify_proof(&data[4], &round_tripped, &root));
+    assert_eq!(MerkleProof::from_bytes(&[]), Ok(MerkleProof::default()));
+
+    let encoded = proof.to_base64();
+    let (head, tail) = encoded.split_at(8);
+    for malformed in [
+        format!("{} {}", head, tail),
+        format!("{}\n{}", head, tail),
+        format!(" {}", encoded),
+        encoded.trim_end_matches('=').to_string(),
+        format!("{}=", encoded),
+    ] {
+        assert_eq!(
+            MerkleProof::from_base64(&malformed),
+            Err(ProofDecodeError::InvalidBase64)
+        );
+    }
+    assert_eq!(
+        MerkleProof::from_hex(&format!("0x{}", proof.to_hex())),
+        Err(ProofDecodeError::InvalidHex)
+    );
+    assert_eq!(
+        MerkleProof::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(ProofDecodeError::Truncated)
+    );
+    assert_eq!(
+        MerkleProof::from_bytes(&[2, 0]),
+        Err(ProofDecodeError::InvalidDirection { byte: 2 })
+    );
+
+    use base64::Engine;
+    assert_eq!(
+        base64::engine::general_purpose::STANDARD
+            .decode(tree.root_hash_base64())
+            .unwrap(),
+        root
+    );
+}
+
+#[test]
+fn test_verification_trace() {
+    let data = create_test_data(7);
+    let tree = MerkleTree::new(data.clone());
+    let proof = tree.proof_by_index(3).unwrap();
+
+    let trace = MerkleTree::verification_trace(&data[3], &proof);
+    assert_eq!(trace.len(), proof.len() + 1);
+    assert_eq!(trace[0], Sha256::digest(&data[3]).to_vec());
+    assert_eq!(trace[1], tree.node_hash_by_index(1, 1).unwrap());
+    assert_eq!(trace.last(), tree.root_hash().as_ref());
+
+    // Corrupting a sibling only changes the trace from that step on
+    let mut corrupted = proof.clone();
+    corrupted[1].0[0] ^= 1;
+    let other = MerkleTree::verification_trace(&data[3], &corrupted);
+    let first_mismatch = trace.iter().zip(&other).position(|(a, b)| a != b);
+    assert_eq!(first_mismatch, Some(2));
+}
+
+#[test]
+fn test_sorted_leaves_ignore_input_order() {
+    let data = create_test_data(9);
+    let mut shuffled = data.clone();
+    let mut rng = XorShift(7);
+    for i in (1..shuffled.len()).rev() {
+        shuffled.swap(i, rng.next() as usize % (i + 1));
+    }
+    assert_ne!(shuffled, data);
+
+    let sorted = MerkleTree::builder().sort_leaves(true);
+    let tree = sorted.clone().build(data.clone());
+    assert_eq!(
+        tree.root_hash(),
+        sorted.clone().build(shuffled.clone()).root_hash()
+    );
+    assert_ne!(
+        MerkleTree::builder().build(data.clone()).root_hash(),
+        MerkleTree::builder().build(shuffled).root_hash()
+    );
+
+    for (original, leaf) in data.iter().enumerate() {
+        let index = tree.index_of(leaf).unwrap();
+        assert_eq!(tree.original_index(index), Some(original));
+        assert_eq!(tree.get_leaf(index), Some(leaf.as_slice()));
+        let proof = tree.generate_proof(leaf).unwrap();
+        assert!(tree.verify(leaf, &proof));
+    }
+    assert_eq!(tree.original_index(9), None);
+    assert_eq!(MerkleTree::new(data.clone()).original_index(4), Some(4));
+
+    // Duplicates keep their relative order
+    let duplicated = vec![b"b".to_vec(), b"a".to_vec(), b"b".to_vec()];
+    let tree = sorted.clone().build(duplicated);
+    let first_b = tree.index_of(b"b").unwrap();
+    assert_eq!(tree.original_index(first_b), Some(0));
+    assert_eq!(tree.original_index(first_b + 1), Some(2));
+
+    let positional = sorted.positional(true).build(data.clone());
+    let proof = positional.generate_proof(&data[3]).unwrap();
+    assert!(positional.verify(&data[3], &proof));
+}
+
+#[test]
+fn test_sha512_digests() {
+    let data = create_test_data(5);
+    let tree = MerkleTree::builder()
+        .hash_algorithm(HashAlgorithm::Sha512)
+        .build(data.clone());
+    let root = tree.root_hash().unwrap();
+    assert_eq!(root.len(), 64);
+    assert_eq!(tree.node_hash_by_index(0, 0).unwrap().len(), 64);
+
+    let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+    for (index, leaf) in data.iter().enumerate() {
+        let proof = tree.proof_by_index(index).unwrap();
+        assert!(proof.iter().all(|(hash, _)| hash.len() == 64));
+        let decoded = MerkleProof::from_base64(&proof.to_base64()).unwrap();
+        assert!(tree.verify(leaf, &decoded));
+        assert_eq!(verifier.verify(leaf, &decoded), Ok(()));
+    }
+
+    // Siblings of another digest length are rejected even without a leaf count
+    let mut truncated = tree.proof_by_index(0).unwrap();
+    truncated[0].0.truncate(32);
+    assert!(!tree.verify(&data[0], &truncated));
+    assert!(!MerkleTree::verify_proof_with(
+        tree.hash_config(),
+        &data[0],
+        &truncated,
+        &root,
+        None
+    ));
+    assert_eq!(
+        verifier.verify(&data[0], &truncated),
+        Err(VerificationError::InvalidSiblingLength {
+            step: 0,
+            expected: 64,
+            actual: 32
+        })
+    );
+}
+
+#[cfg(feature = "ripemd")]
+#[test]
+fn test_ripemd160_digests() {
+    // RIPEMD-160("abc"), from the algorithm's specification
+    assert_eq!(
+        hex::encode(crate::HashAlgorithm::Ripemd160.digest(&[b"abc"])),
+        "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc"
+    );
+
+    let data = create_test_data(6);
+    let tree = MerkleTree::builder()
+        .hash_algorithm(crate::HashAlgorithm::Ripemd160)
+        .build(data.clone());
+    assert_eq!(tree.root_hash().unwrap().len(), 20);
+
+    let proof = tree.proof_by_index(5).unwrap();
+    assert!(proof.iter().all(|(hash, _)| hash.len() == 20));
+    let decoded = MerkleProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(tree.verify(&data[5], &decoded));
+
+    let mut padded = proof;
+    padded[0].0.extend_from_slice(&[0; 12]);
+    assert!(!tree.verify(&data[5], &padded));
+}
+
+#[test]
+fn test_round_trip_through_leaf_hashes() {
+    let data = create_test_data(11);
+    for tree in [
+        MerkleTree::new(data.clone()),
+        MerkleTree::builder()
+            .pair_ordering(PairOrdering::Sorted)
+            .build(data.clone()),
+        MerkleTree::builder().positional(true).build(data.clone()),
+    ] {
+        let params = tree.params();
+        let root = tree.root_hash();
+        let proofs: Vec<_> = (0..data.len())
+            .map(|index| tree.proof_by_index(index).unwrap())
+            .collect();
+
+        let leaf_hashes = tree.into_leaf_hashes();
+        assert_eq!(leaf_hashes.len(), data.len());
+        let rebuilt = MerkleTree::from_leaf_hashes(leaf_hashes.clone(), params);
+        assert_eq!(rebuilt.root_hash(), root);
+        assert_eq!(rebuilt.params(), params);
+        assert_eq!(rebuilt.get_leaf(0), None);
+        for (index, proof) in proofs.iter().enumerate() {
+            assert_eq!(rebuilt.proof_by_index(index).as_ref(), Some(proof));
+            assert_eq!(
+                proof_from_leaf_hashes(params.hasher, &leaf_hashes, index).as_ref(),
+                Some(proof)
+            );
+            assert!(rebuilt.verify(&data[index], proof));
+        }
+        assert!(proof_from_leaf_hashes(params.hasher, &leaf_hashes, data.len()).is_none());
+    }
+
+    assert!(MerkleTree::new(Vec::new()).into_leaf_hashes().is_empty());
+}
+
+#[test]
+fn test_memoized_pairs_hash_repetitive_data_once() {
+    // 90% of the leaves are identical reserved slots
+    let data: Vec<Vec<u8>> = (0..1000)
+        .map(|i| {
+            if i % 10 == 0 {
+                format!("Entry {}", i).into_bytes()
+            } else {
+                vec![0; 32]
+            }
+        })
+        .collect();
+
+    let plain = MerkleTree::builder().build(data.clone());
+    let memoized = MerkleTree::builder()
+        .memoize_pairs(true)
+        .build(data.clone());
+    assert_eq!(memoized.root_hash(), plain.root_hash());
+    assert_eq!(memoized.metrics().leaf_hashes, plain.metrics().leaf_hashes);
+    assert!(memoized.metrics().node_hashes * 2 < plain.metrics().node_hashes);
+
+    let proof = memoized.proof_by_index(500).unwrap();
+    assert_eq!(Some(proof.clone()), plain.proof_by_index(500));
+    assert!(memoized.verify(&data[500], &proof));
+
+    let random = create_test_data(33);
+    assert_eq!(
+        MerkleTree::builder()
+            .memoize_pairs(true)
+            .build(random.clone())
+            .root_hash(),
+        MerkleTree::builder().build(random).root_hash()
+    );
+}
+
+#[test]
+fn test_chained_proofs_through_nested_trees() {
+    let scheme = HashScheme::DomainSeparated;
+    let records = create_test_data(1000);
+    let shard = MerkleTree::builder().build(records.clone());
+    let shard_root = shard.root_hash().unwrap();
+
+    // The other shards are stood in for by distinct roots
+    let shard_roots: Vec<Vec<u8>> = (0..1000)
+        .map(|i| {
+            if i == 417 {
+                shard_root.clone()
+            } else {
+                Sha256::digest(format!("Shard {}", i)).to_vec()
+            }
+        })
+        .collect();
+    let global = MerkleTree::builder().build(shard_roots);
+    let global_root = global.root_hash().unwrap();
+
+    let inner = shard.proof_by_index(123).unwrap();
+    let outer = global.proof_by_index(417).unwrap();
+    let chained = inner.chain(outer.clone());
+    assert_eq!(chained.shard_root(scheme, &records[123]), Some(shard_root));
+    assert!(chained.verify(scheme, &records[123], &global_root));
+    assert!(!chained.verify(scheme, &records[124], &global_root));
+    assert!(!chained.verify(HashScheme::Plain, &records[123], &global_root));
+
+    // The inner proof must lead to the shard the outer proof is for
+    let wrong_shard = ChainedProof::new(
+        shard.proof_by_index(123).unwrap(),
+        global.proof_by_index(418).unwrap(),
+    );
+    assert!(!wrong_shard.verify(scheme, &records[123], &global_root));
+
+    // Oversized proofs are refused before any hashing
+    let oversized = MerkleProof::new(vec![(vec![0; 32], false); MAX_PROOF_STEPS + 1]);
+    let long_inner = ChainedProof::new(oversized.clone(), outer.clone());
+    assert_eq!(long_inner.shard_root(scheme, &records[123]), None);
+    assert!(!long_inner.verify(scheme, &records[123], &global_root));
+    let long_outer = shard.proof_by_index(123).unwrap().chain(oversized);
+    assert!(long_outer.shard_root(scheme, &records[123]).is_some());
+    assert!(!long_outer.verify(scheme, &records[123], &global_root));
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&chained).unwrap();
+        let decoded: ChainedProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, chained);
+    }
+}
+
+#[test]
+fn test_verify_stream_matches_construction() {
+    for count in 1..=40 {
+        let data = create_test_data(count);
+        let root = MerkleTree::new(data.clone()).root_hash().unwrap();
+        assert_eq!(
+            verify_stream(&root, data.iter()),
+            Ok(()),
+            "{} leaves",
+            count
+        );
+        let root = MerkleTree::builder()
+            .build(data.clone())
+            .root_hash()
+            .unwrap();
+        assert!(verify_stream(&root, data.iter()).is_err());
+        assert_eq!(
+            verify_stream_with(
+                HashScheme::DomainSeparated,
+                EmptyRootPolicy::None,
+                &root,
+                data.iter()
+            ),
+            Ok(())
+        );
+
+        let tree = MerkleTree::builder()
+            .hash_algorithm(HashAlgorithm::Sha512)
+            .hash_scheme(HashScheme::Plain)
+            .pair_ordering(PairOrdering::Sorted)
+            .positional(true)
+            .build(data.clone());
+        let params = tree.params();
+        let root = tree.root_hash().unwrap();
+        let mut accumulator = RootAccumulator::new(params);
+        data.iter().for_each(|leaf| accumulator.push(leaf));
+        assert_eq!(accumulator.root(EmptyRootPolicy::None), Some(root.clone()));
+        assert_eq!(
+            verify_stream_with(params, EmptyRootPolicy::None, &root, data.iter()),
+            Ok(())
+        );
+    }
+}
+
+#[test]
+fn test_verify_stream_rejections() {
+    let data = create_test_data(7);
+    let root = MerkleTree::new(data.clone()).root_hash().unwrap();
+    assert_eq!(
+        verify_stream(&root, data.iter().take(6)),
+        Err(StreamVerifyError::RootMismatch { leaves_consumed: 6 })
+    );
+    assert_eq!(
+        verify_stream(&root, data.iter().rev()),
+        Err(StreamVerifyError::RootMismatch { leaves_consumed: 7 })
+    );
+
+    // An empty stream is checked against the empty root policy
+    let empty = Vec::<Vec<u8>>::new();
+    assert_eq!(
+        verify_stream(&root, empty.iter()),
+        Err(StreamVerifyError::NoEmptyRoot)
+    );
+    assert_eq!(
+        verify_stream_with(
+            HashScheme::DomainSeparated,
+            EmptyRootPolicy::Zero32,
+            &[0; 32],
+            empty.iter()
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_proof_epochs() {
+    let data = create_test_data(5);
+    let mut tree = MerkleTree::builder().epoch_history(3).build(data.clone());
+    assert_eq!(tree.epoch(), 0);
+    let first = tree.generate_epoch_proof(4).unwrap();
+    assert_eq!(first.root, tree.root_hash().unwrap());
+
+    tree.push(b"Data 5".to_vec());
+    tree.update_by_index(0, b"Changed".to_vec()).unwrap();
+    assert_eq!(tree.epoch(), 2);
+    assert!(!tree.verify(&data[4], &first.proof));
+    // The proof still verifies against the root and leaf count of its own epoch
+    assert_eq!(tree.verify_at_epoch(&data[4], &first), Ok(()));
+    assert_eq!(
+        tree.verify_at_epoch(&data[3], &first),
+        Err(VerificationError::RootMismatch)
+    );
+    let current = tree.generate_epoch_proof(5).unwrap();
+    assert_eq!(current.epoch, 2);
+    assert_eq!(tree.verify_at_epoch(b"Data 5", &current), Ok(()));
+
+    // A proof claiming a different root for a retained epoch is rejected
+    let forged = EpochProof {
+        root: current.root.clone(),
+        ..first.clone()
+    };
+    assert_eq!(
+        tree.verify_at_epoch(&data[4], &forged),
+        Err(VerificationError::RootMismatch)
+    );
+
+    // Epoch 0 is evicted once the tree is three epochs further on
+    tree.push(b"Data 6".to_vec());
+    assert_eq!(tree.root_at_epoch(0), None);
+    assert!(tree.root_at_epoch(1).is_some());
+    assert_eq!(
+        tree.verify_at_epoch(&data[4], &first),
+        Err(VerificationError::EpochUnavailable { epoch: 0 })
+    );
+    assert!(tree.update_by_index(10, Vec::new()).is_err());
+    assert_eq!(tree.epoch(), 3);
+}
+
+#[test]
+fn test_canopy_assembles_full_proofs() {
+    // Stand-in leaf hashes keep building a 2^20-leaf tree quick
+    let scheme = HashScheme::DomainSeparated;
+    let hashes: Vec<Vec<u8>> = (0u32..1 << 20)
+        .map(|i| [&i.to_le_bytes()[..], &[0; 28]].concat())
+        .collect();
+    let tree = MerkleTree::from_leaf_hashes(hashes.clone(), scheme);
+    let root = tree.root_hash().unwrap();
+    let canopy = tree.canopy(10);
+    assert_eq!(canopy.height(), 10);
+    assert_eq!(canopy.base_level(), 11);
+    assert_eq!(canopy.root_hash(), Some(root.clone()));
+
+    for index in [0, 123_456, (1 << 20) - 1] {
+        // An edge server rebuilds only the block of leaves under one canopy node
+        let block = canopy.subtree_leaves(index).unwrap();
+        assert_eq!(block.len(), 1 << 11);
+        let local = MerkleTree::from_leaf_hashes(hashes[block.clone()].to_vec(), scheme);
+        let lower = local.proof_by_index(index - block.start).unwrap();
+        let proof = canopy.complete_proof(&lower, index).unwrap();
+        assert_eq!(proof, tree.proof_by_index(index).unwrap());
+        assert!(MerkleTree::verify_proof_for_hash_with(
+            scheme,
+            &hashes[index],
+            &proof,
+            &root,
+            Some(1 << 20)
+        ));
+    }
+    assert_eq!(canopy.proof_suffix(1 << 20), None);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&canopy).unwrap();
+        assert_eq!(
+            serde_json::from_str::<crate::Canopy>(&json).unwrap(),
+            canopy
+        );
+    }
+}
+
+#[test]
+fn test_leaves_sorted_unique_lookups() {
+    let data = create_test_data(9);
+    let tree = MerkleTree::builder()
+        .sort_leaves(true)
+        .leaves_sorted_unique(true)
+        .build(data.clone());
+    let scanned = MerkleTree::builder().sort_leaves(true).build(data.clone());
+    for item in &data {
+        assert_eq!(tree.index_of(item), scanned.index_of(item));
+        assert!(tree.verify(item, &tree.generate_proof(item).unwrap()));
+        let leaf_hash = tree.hash_config().hash_leaf(item);
+        assert_eq!(
+            tree.generate_proof_for_hash(&leaf_hash),
+            scanned.generate_proof_for_hash(&leaf_hash)
+        );
+    }
+    assert_eq!(tree.index_of(b"Missing"), None);
+    assert!(tree.generate_proof_for_hash(&[0; 32]).is_none());
+
+    // Hash order is not insertion order, so the unsorted input is rejected
+    let result = MerkleTree::builder()
+        .leaves_sorted_unique(true)
+        .try_build(data.clone());
+    assert!(matches!(result, Err(MerkleError::UnsortedLeaf { .. })));
+    let mut repeated = data.clone();
+    repeated.push(data[0].clone());
+    assert!(matches!(
+        MerkleTree::builder()
+            .sort_leaves(true)
+            .leaves_sorted_unique(true)
+            .try_build(repeated),
+        Err(MerkleError::UnsortedLeaf { .. })
+    ));
+
+    // Mutations that break the order fall back to scanning
+    let mut tree = tree;
+    tree.update_by_index(0, b"Replacement".to_vec()).unwrap();
+    tree.push(b"Appended".to_vec());
+    assert_eq!(tree.index_of(b"Replacement"), Some(0));
+    assert_eq!(tree.index_of(b"Appended"), Some(9));
+    let leaf_hash = tree.hash_config().hash_leaf(b"Replacement");
+    assert_eq!(
+        tree.generate_proof_for_hash(&leaf_hash),
+        tree.proof_by_index(0)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_structure_snapshot() {
+    use crate::JsonExportOptions;
+    use serde_json::json;
+
+    let tree = MerkleTree::new(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    let c = "2e7d2c03a9507ae265ecf5b5356885a53393a2029d241394997265a1a25aefc6";
+    assert_eq!(
+        tree.to_json_structure(),
+        json!({
+            "hash": "d31a37ef6ac14a2db1470c4316beb5592e6afd4465022339adafda76a18ffabe",
+            "left": {
+                "hash": "e5a01fee14e0ed5c48714f22180f25ad8365b53f9779f79dc4a3d7e93963f94a",
+                "left": {
+                    "hash": "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb",
+                    "index": 0,
+                    "data_preview": "a"
+                },
+                "right": {
+                    "hash": "3e23e8160039594a33894f6564e1b1348bbd7a0088d42c4acb73eeaed59c009d",
+                    "index": 1,
+                    "data_preview": "b"
+                }
+            },
+            "right": {
+                "hash": "a3e333fbee455b9a054cf05077f0f9d45b91bd13db4cd4a3681ec47455af085c",
+                "left": {"hash": c, "index": 2, "data_preview": "c"},
+                "right": {"hash": c, "index": 3, "padding": true, "data_preview": "c"}
+            }
+        })
+    );
+
+    let shallow = tree.to_json_structure_with(&JsonExportOptions {
+        data_previews: false,
+        max_depth: Some(0),
+        metadata: false,
+        ..JsonExportOptions::default()
+    });
+    assert_eq!(
+        shallow,
+        json!({
+            "hash": "d31a37ef6ac14a2db1470c4316beb5592e6afd4465022339adafda76a18ffabe",
+            "truncated": true
+        })
+    );
+    assert!(matches!(
+        MerkleTree::from_json_structure(&shallow, HashScheme::Plain),
+        Err(MerkleError::InvalidStructure { .. })
+    ));
+
+    // Hash-only structures round-trip, including a node carried up from an odd level
+    let tree = MerkleTree::builder().build(create_test_data(5));
+    let structure = tree.to_json_structure();
+    let rebuilt = MerkleTree::from_json_structure(&structure, HashScheme::DomainSeparated).unwrap();
+    assert_eq!(rebuilt.root_hash(), tree.root_hash());
+    let opts = JsonExportOptions {
+        data_previews: false,
+        max_depth: None,
+        metadata: false,
+        ..JsonExportOptions::default()
+    };
+    assert_eq!(
+        rebuilt.to_json_structure_with(&opts),
+        tree.to_json_structure_with(&opts)
+    );
+    assert!(MerkleTree::from_json_structure(&structure, HashScheme::Plain).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_typed_batches() {
+    use crate::{Batch, TypedProof};
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct Transaction {
+        from: String,
+        to: String,
+        amount: u64,
+        tags: HashMap<String, String>,
+    }
+
+    let transaction = |from: &str, to: &str, amount| Transaction {
+        from: from.to_string(),
+        to: to.to_string(),
+        amount,
+        tags: HashMap::from([
+            ("memo".to_string(), format!("{} pays {}", from, to)),
+            ("channel".to_string(), "web".to_string()),
+        ]),
+    };
+    let batch = Batch::new(vec![
+        transaction("Alice", "Bob", 5),
+        transaction("Bob", "Charlie", 3),
+        transaction("David", "Eve", 2),
+    ])
+    .unwrap();
+    let root = batch.commit();
+
+    let proof = batch.prove(1).unwrap();
+    // A freshly built item verifies even though its map iterates in another order
+    assert!(proof.verify(&transaction("Bob", "Charlie", 3), &root));
+    assert!(!proof.verify(&transaction("Bob", "Charlie", 4), &root));
+    assert!(!proof.verify(&batch.items()[0], &root));
+    assert!(batch.prove(3).is_none());
+
+    // The bug class the batch prevents: the same item serialized another way on the
+    // verifying side does not verify
+    let pretty = serde_json::to_vec_pretty(&batch.items()[1]).unwrap();
+    assert!(!MerkleTree::verify_proof_with(
+        HashScheme::DomainSeparated,
+        &pretty,
+        &proof.proof,
+        &root,
+        Some(3)
+    ));
+
+    let json = serde_json::to_string(&proof).unwrap();
+    let decoded: TypedProof<Transaction> = serde_json::from_str(&json).unwrap();
+    assert!(decoded.verify(&transaction("Bob", "Charlie", 3), &root));
+
+    // The index is checked against the proof's directions
+    let mut tampered = batch.prove(1).unwrap();
+    tampered.index = 0;
+    assert!(!tampered.verify(&transaction("Bob", "Charlie", 3), &root));
+    tampered.index = 2;
+    assert!(!tampered.verify(&transaction("Bob", "Charlie", 3), &root));
+
+    let empty = Batch::<Transaction>::new(Vec::new()).unwrap();
+    assert_eq!(empty.commit(), Sha256::digest([]).to_vec());
+}
+
+#[test]
+fn test_audit_finds_first_inconsistency() {
+    for count in [1, 2, 5, 8, 13] {
+        let tree = MerkleTree::builder()
+            .positional(true)
+            .build(create_test_data(count));
+        assert_eq!(tree.audit(), Ok(()));
+        assert_eq!(tree.audit_sampled(20), Ok(()));
+    }
+
+    let tree = MerkleTree::builder().build(create_test_data(5));
+    let mut corrupted = tree.clone();
+    corrupted.levels_mut()[1][2] = vec![0; 32];
+    assert_eq!(
+        corrupted.audit(),
+        Err(AuditError::NodeMismatch {
+            level: 1,
+            index: 2,
+            expected: tree.node_hash_by_index(1, 2).unwrap(),
+            stored: vec![0; 32],
+        })
+    );
+    // A third of the paths cross the corrupted node, so 64 samples all but surely find it
+    assert!(corrupted.audit_sampled(64).is_err());
+
+    let mut corrupted = tree.clone();
+    corrupted.levels_mut()[0][1][0] ^= 1;
+    assert!(matches!(
+        corrupted.audit(),
+        Err(AuditError::NodeMismatch {
+            level: 0,
+            index: 1,
+            ..
+        })
+    ));
+
+    let mut corrupted = tree.clone();
+    corrupted.levels_mut()[1].pop();
+    assert_eq!(
+        corrupted.audit(),
+        Err(AuditError::LevelSizeMismatch {
+            level: 1,
+            expected: 3,
+            actual: 2,
+        })
+    );
+    assert_eq!(MerkleTree::new(Vec::new()).audit(), Ok(()));
+}
+
+#[test]
+fn test_loaded_snapshots_and_canopies_can_be_audited() {
+    use crate::{DecodeLimits, SnapshotError};
+
+    for padding in [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 4 },
+    ] {
+        let tree = MerkleTree::builder()
+            .padding(padding)
+            .build(create_test_data(11));
+        let snapshot = tree.to_snapshot();
+        let loaded = MerkleTree::from_snapshot_audited(&snapshot, &DecodeLimits::default());
+        assert_eq!(loaded.unwrap().root_hash(), tree.root_hash());
+        for levels in [1, 3, usize::MAX] {
+            assert_eq!(tree.canopy(levels).audit(), Ok(()), "{:?}", padding);
+        }
+
+        // A snapshot's layout checks do not cover its hashes, so a forged root loads
+        // unless the audit is asked for
+        let mut forged = snapshot.clone();
+        *forged.last_mut().unwrap() ^= 1;
+        assert!(MerkleTree::from_snapshot(&forged).is_ok());
+        assert!(matches!(
+            MerkleTree::from_snapshot_audited(&forged, &DecodeLimits::default()),
+            Err(SnapshotError::Audit(AuditError::NodeMismatch {
+                index: 0,
+                ..
+            }))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    {
+        let tree = MerkleTree::new(create_test_data(5));
+        let mut canopy = serde_json::to_value(tree.canopy(usize::MAX)).unwrap();
+        canopy["levels"][1][2] = serde_json::Value::from(hex::encode([0; 32]));
+        let canopy: crate::Canopy = serde_json::from_value(canopy).unwrap();
+        assert_eq!(
+            canopy.audit(),
+            Err(AuditError::NodeMismatch {
+                level: 1,
+                index: 2,
+                expected: tree.node_hash_by_index(1, 2).unwrap(),
+                stored: vec![0; 32],
+            })
+        );
+        let mut canopy = serde_json::to_value(tree.canopy(3)).unwrap();
+        canopy["leaf_count"] = serde_json::Value::from(7);
+        let canopy: crate::Canopy = serde_json::from_value(canopy).unwrap();
+        assert_eq!(
+            canopy.audit(),
+            Err(AuditError::LevelSizeMismatch {
+                level: 1,
+                expected: 4,
+                actual: 3,
+            })
+        );
+    }
+}
+
+#[test]
+fn test_carry_up_padding() {
+    for count in [3, 5, 6, 7, 11] {
+        let data = create_test_data(count);
+        let tree = MerkleTree::builder()
+            .padding(PaddingStrategy::CarryUp)
+            .build(data.clone());
+        assert_eq!(tree.params().padding, PaddingStrategy::CarryUp);
+        assert_eq!(tree.levels()[0].len(), count);
+        assert_eq!(tree.audit(), Ok(()));
+        for (index, item) in data.iter().enumerate() {
+            let proof = tree.proof_by_index(index).unwrap();
+            assert!(tree.verify(item, &proof), "{} of {} leaves", index, count);
+            assert!(MerkleTree::verify_proof_with(
+                HashScheme::DomainSeparated,
+                item,
+                &proof,
+                &tree.root_hash().unwrap(),
+                None
+            ));
+        }
+
+        // The last leaf of an odd level skips the step a duplicated tree would have
+        let last = tree.proof_by_index(count - 1).unwrap();
+        let duplicated = MerkleTree::builder().build(data.clone());
+        if count % 2 == 1 {
+            assert!(last.len() < duplicated.proof_by_index(count - 1).unwrap().len());
+            assert_ne!(tree.root_hash(), duplicated.root_hash());
+        } else {
+            assert_eq!(tree.root_hash(), duplicated.root_hash());
+        }
+
+        // Incremental changes and the streaming root follow the same shape
+        let mut grown = MerkleTree::builder()
+            .padding(PaddingStrategy::CarryUp)
+            .build(data[..1].to_vec());
+        data[1..].iter().for_each(|item| {
+            grown.push(item.clone());
+        });
+        assert_eq!(grown.root_hash(), tree.root_hash());
+        assert_eq!(
+            verify_stream_with(
+                tree.params(),
+                EmptyRootPolicy::None,
+                &tree.root_hash().unwrap(),
+                data.iter()
+            ),
+            Ok(())
+        );
+    }
+
+    // Carrying the last leaf up reproduces the RFC 6962 roots for odd sizes as well
+    let inputs: [&[u8]; 7] = [
+        b"",
+        b"\x00",
+        b"\x10",
+        b"\x20\x21",
+        b"\x30\x31",
+        b"\x40\x41\x42\x43",
+        b"\x50\x51\x52\x53\x54\x55\x56\x57",
+    ];
+    for (count, root) in [
+        (
+            3,
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        ),
+        (
+            5,
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        ),
+        (
+            7,
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        ),
+    ] {
+        let tree = MerkleTree::builder()
+            .padding(PaddingStrategy::CarryUp)
+            .build(inputs[..count].iter().map(|leaf| leaf.to_vec()).collect());
+        assert_eq!(tree.root_hash_hex(), root);
+    }
+}
+
+#[test]
+fn test_tree_versions() {
+    let data = create_test_data(5);
+    let v1 = MerkleTree::new(data.clone());
+    let v2 = MerkleTree::builder().build(data.clone());
+    assert_eq!(v1.version(), Some(TreeVersion::V1));
+    assert_eq!(v2.version(), Some(TreeVersion::V2));
+    assert_eq!(
+        MerkleTree::builder()
+            .positional(true)
+            .build(data.clone())
+            .version(),
+        None
+    );
+    for version in TreeVersion::ALL {
+        assert_eq!(TreeVersion::of(version.params()), Some(version));
+        assert_eq!(TreeVersion::try_from(version.number()), Ok(version));
+    }
+    assert!(TreeVersion::try_from(3).is_err());
+
+    // A v1 proof is refused by a v2 verifier with a clear error, and vice versa
+    let v1_bundle = v1.generate_bundle(2).unwrap();
+    let v2_bundle = v2.generate_bundle(2).unwrap();
+    let v2_verifier = MerkleVerifier::for_tree(&v2).unwrap();
+    let v1_verifier = MerkleVerifier::for_tree(&v1).unwrap();
+    assert_eq!(v2_verifier.version(), Some(TreeVersion::V2));
+    assert_eq!(v2_verifier.verify_bundle(&data[2], &v2_bundle), Ok(()));
+    assert_eq!(v1_verifier.verify_bundle(&data[2], &v1_bundle), Ok(()));
+    let error = v2_verifier.verify_bundle(&data[2], &v1_bundle).unwrap_err();
+    assert_eq!(
+        error,
+        VerificationError::VersionMismatch {
+            expected: Some(TreeVersion::V2),
+            actual: TreeVersion::V1,
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "proof is for tree version v1, expected version v2"
+    );
+    assert!(matches!(
+        v1_verifier.verify_bundle(&data[2], &v2_bundle),
+        Err(VerificationError::VersionMismatch { .. })
+    ));
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_value(&v1_bundle).unwrap();
+        assert_eq!(json["version"], 1);
+        let decoded: crate::ProofBundle = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, v1_bundle);
+        let mut unknown = serde_json::to_value(&v1_bundle).unwrap();
+        unknown["version"] = serde_json::json!(9);
+        assert!(serde_json::from_value::<crate::ProofBundle>(unknown).is_err());
+    }
+}
+
+#[test]
+fn test_sum_tree() {
+    use crate::{SumMerkleTree, SumProofStep};
+
+    let accounts: Vec<(Vec<u8>, u64)> = (0..5u64)
+        .map(|i| (format!("user{}", i).into_bytes(), 100 * (i + 1)))
+        .collect();
+    let tree = SumMerkleTree::new(accounts.clone()).unwrap();
+    assert_eq!(tree.leaf_count(), 5);
+    let (root, total) = tree.root().unwrap();
+    assert_eq!(total, 1500);
+
+    // Every leaf proves its balance against the root and the total
+    for (index, (data, balance)) in accounts.iter().enumerate() {
+        let proof = tree.generate_proof(index).unwrap();
+        assert_eq!(
+            SumMerkleTree::verify(data, *balance, &proof, &root, total),
+            Ok(())
+        );
+        // A different balance, or a different total, is rejected
+        assert_eq!(
+            SumMerkleTree::verify(data, balance + 1, &proof, &root, total),
+            Err(VerificationError::RootMismatch)
+        );
+        assert_eq!(
+            SumMerkleTree::verify(data, *balance, &proof, &root, total - 1),
+            Err(VerificationError::RootMismatch)
+        );
+    }
+    assert!(tree.generate_proof(5).is_none());
+
+    // Understating a sibling's sum changes the root hash
+    let mut proof = tree.generate_proof(0).unwrap();
+    proof[0].sum -= 1;
+    assert_eq!(
+        SumMerkleTree::verify(&accounts[0].0, accounts[0].1, &proof, &root, total - 1),
+        Err(VerificationError::RootMismatch)
+    );
+
+    // Sums that overflow are refused when building and when verifying
+    let overflowing = vec![(b"a".to_vec(), u64::MAX), (b"b".to_vec(), 1)];
+    assert_eq!(
+        SumMerkleTree::new(overflowing).unwrap_err(),
+        MerkleError::SumOverflow { level: 1, index: 0 }
+    );
+    let forged = vec![SumProofStep {
+        hash: vec![0; 32],
+        sum: u64::MAX,
+        is_left: true,
+    }];
+    assert_eq!(
+        SumMerkleTree::verify(b"a", 1, &forged, &root, total),
+        Err(VerificationError::SumOverflow { step: 0 })
+    );
+
+    assert!(SumMerkleTree::new(Vec::new()).unwrap().root().is_none());
+}
+
+#[test]
+fn test_hex_display_adapters() {
+    use crate::HexDisplay;
+    use std::fmt::Write;
+
+    let tree = MerkleTree::new(create_test_data(5));
+    let root = tree.root_hash().unwrap();
+    assert_eq!(tree.root_hex_display().to_string(), hex::encode(&root));
+    assert_eq!(
+        format!("{:X}", tree.root_hex_display()),
+        hex::encode_upper(&root)
+    );
+    assert_eq!(
+        format!("{:#x}", HexDisplay(&root)),
+        format!("0x{}", hex::encode(&root))
+    );
+    let long = vec![0xa5; 200];
+    assert_eq!(HexDisplay(&long).to_string(), hex::encode(&long));
+    assert_eq!(HexDisplay(&[]).to_string(), "");
+    assert_eq!(
+        MerkleTree::new(Vec::new()).root_hex_display().to_string(),
+        "Empty tree"
+    );
+
+    let proof = tree.proof_by_index(4).unwrap();
+    let expected = proof
+        .iter()
+        .map(|(hash, is_left)| {
+            format!("{}:{}", if *is_left { "L" } else { "R" }, hex::encode(hash))
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(proof.hex_display().to_string(), expected);
+
+    // Writing into an existing buffer allocates nothing
+    let mut line = String::with_capacity(1024);
+    let (_, allocations) = count_allocations(|| {
+        write!(
+            line,
+            "root={} proof={:#X}",
+            tree.root_hex_display(),
+            proof.hex_display()
+        )
+        .unwrap()
+    });
+    assert_eq!(allocations, 0);
+    assert!(line.starts_with(&format!("root={}", hex::encode(&root))));
+}
+
+#[test]
+fn test_empty_leaves() {
+    // An empty leaf hashes to H() or, under domain separation, H(0x00)
+    let single = MerkleTree::new(vec![Vec::new()]);
+    assert_eq!(single.root_hash().unwrap(), Sha256::digest([]).to_vec());
+    let separated = MerkleTree::builder()
+        .hash_scheme(HashScheme::DomainSeparated)
+        .build(vec![Vec::new()]);
+    assert_eq!(
+        separated.root_hash().unwrap(),
+        Sha256::digest([0x00]).to_vec()
+    );
+
+    // Mixed empty and non-empty leaves: proofs for the empty ones name the lowest index
+    let data = vec![
+        Vec::new(),
+        b"a".to_vec(),
+        Vec::new(),
+        b"b".to_vec(),
+        Vec::new(),
+    ];
+    let tree = MerkleTree::new(data.clone());
+    assert_eq!(tree.index_of(&[]), Some(0));
+    assert_eq!(tree.generate_proof(&[]), tree.proof_by_index(0));
+    for (index, leaf) in data.iter().enumerate() {
+        let proof = tree.proof_by_index(index).unwrap();
+        assert!(tree.verify(leaf, &proof));
+    }
+    assert!(!tree.verify(&[], &tree.proof_by_index(1).unwrap()));
+    assert!(tree
+        .render_with(&TreePrintOptions::default())
+        .contains("Leaf 2: data=\"\""));
+
+    // Positional trees give each empty leaf its own hash
+    let positional = MerkleTree::builder().positional(true).build(data.clone());
+    assert_ne!(positional.levels()[0][0], positional.levels()[0][2]);
+    for (index, leaf) in data.iter().enumerate() {
+        let proof = positional.proof_by_index(index).unwrap();
+        assert!(positional.verify(leaf, &proof));
+    }
+    assert_eq!(positional.index_of(&[]), Some(0));
+}
+
+#[test]
+fn test_complete_tree() {
+    use crate::{empty_subtree_hashes, EMPTY_LEAF, EMPTY_LEAF_DATA};
+
+    // The root of 2^depth leaves, the missing ones being EMPTY_LEAF, built naively
+    fn naive_root(data: &[Vec<u8>], depth: u8) -> Vec<u8> {
+        let mut level: Vec<Vec<u8>> = (0..1usize << depth)
+            .map(|i| match data.get(i) {
+                Some(leaf) => Sha256::digest(leaf).to_vec(),
+                None => EMPTY_LEAF.to_vec(),
+            })
+            .collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| Sha256::digest([&pair[0][..], &pair[1][..]].concat()).to_vec())
+                .collect();
+        }
+        level.remove(0)
+    }
+
+    assert_eq!(
+        EMPTY_LEAF.to_vec(),
+        Sha256::digest(EMPTY_LEAF_DATA).to_vec()
+    );
+    let empty = empty_subtree_hashes(HashScheme::Plain, 4);
+    assert_eq!(empty.len(), 5);
+    assert_eq!(empty[0], EMPTY_LEAF.to_vec());
+    assert_eq!(empty[4], naive_root(&[], 4));
+
+    let data = create_test_data(5);
+    let tree = MerkleTree::new_complete(data.clone(), 4).unwrap();
+    assert_eq!(tree.root_hash().unwrap(), naive_root(&data, 4));
+    assert_eq!(tree.height(), 5);
+    for (index, leaf) in data.iter().enumerate() {
+        let proof = tree.proof_by_index(index).unwrap();
+        assert_eq!(proof.len(), 4);
+        assert!(tree.verify(leaf, &proof));
+    }
+    // The empty leaves pad the tree but cannot be proven
+    assert!(tree.proof_by_index(5).is_none());
+    assert_eq!(tree.audit(), Ok(()));
+    let drawing = tree.render_with(&TreePrintOptions::default());
+    assert!(drawing.contains("Leaf 5 (empty)"));
+    assert!(drawing.contains("Empty subtree"));
+
+    // Padding a nearly empty deep tree costs one hash per level, not one per leaf
+    let deep = MerkleTree::new_complete(create_test_data(3), 20).unwrap();
+    assert!(deep.metrics().total() < 100);
+    let proof = deep.proof_by_index(2).unwrap();
+    assert_eq!(proof.len(), 20);
+    assert!(deep.verify(b"Test data 2", &proof));
+    assert_eq!(
+        MerkleTree::new_complete(Vec::new(), 20)
+            .unwrap()
+            .root_hash(),
+        empty_subtree_hashes(HashScheme::Plain, 20).pop()
+    );
+
+    assert_eq!(
+        MerkleTree::new_complete(create_test_data(17), 4).unwrap_err(),
+        MerkleError::TooManyLeaves {
+            leaf_count: 17,
+            depth: 4
+        }
+    );
+    assert_eq!(
+        MerkleTree::new_complete(Vec::new(), 65).unwrap_err(),
+        MerkleError::DepthTooLarge { depth: 65, max: 64 }
+    );
+
+    // Pushing and updating leaves keeps the tree complete
+    let params = tree.params();
+    let mut grown = MerkleTree::new_complete(Vec::new(), 4).unwrap();
+    let mut accumulator = RootAccumulator::new(params);
+    assert_eq!(accumulator.root(EmptyRootPolicy::None), grown.root_hash());
+    for count in 1..=16 {
+        let leaf = format!("Test data {}", count - 1).into_bytes();
+        accumulator.push(&leaf);
+        grown.push(leaf);
+        let expected = naive_root(&create_test_data(count), 4);
+        assert_eq!(grown.root_hash().unwrap(), expected);
+        assert_eq!(accumulator.root(EmptyRootPolicy::None).unwrap(), expected);
+        assert_eq!(grown.audit(), Ok(()));
+    }
+    grown.update_by_index(3, b"changed".to_vec()).unwrap();
+    let mut changed = create_test_data(16);
+    changed[3] = b"changed".to_vec();
+    assert_eq!(grown.root_hash().unwrap(), naive_root(&changed, 4));
+
+    #[cfg(feature = "serde")]
+    {
+        let structure = tree.to_json_structure();
+        let restored = MerkleTree::from_json_structure(&structure, params).unwrap();
+        assert_eq!(restored.root_hash(), tree.root_hash());
+        assert_eq!(restored.leaf_count(), 5);
+    }
+}
+
+#[test]
+fn test_subrange_root() {
+    let data = create_test_data(11);
+    let tree = MerkleTree::new(data.clone());
+    assert_eq!(tree.subrange_root(0, 11), tree.root_hash());
+    for (start, end) in [(0, 1), (2, 7), (3, 11), (5, 6), (4, 8)] {
+        let fresh = MerkleTree::new(data[start..end].to_vec());
+        assert_eq!(tree.subrange_root(start, end), fresh.root_hash());
+    }
+    assert_eq!(tree.subrange_root(5, 4), None);
+    assert_eq!(tree.subrange_root(3, 12), None);
+    assert_eq!(tree.subrange_root(4, 4), None);
+
+    // Positional trees rehash each leaf at its position within the range
+    let builder = || {
+        MerkleTree::builder()
+            .positional(true)
+            .padding(PaddingStrategy::CarryUp)
+            .empty_root(EmptyRootPolicy::HashOfEmpty)
+    };
+    let mut positional = builder().build(data.clone());
+    assert_eq!(positional.subrange_root(0, 11), positional.root_hash());
+    assert_eq!(
+        positional.subrange_root(3, 9),
+        builder().build(data[3..9].to_vec()).root_hash()
+    );
+    assert_eq!(
+        positional.subrange_root(6, 6),
+        builder().build(Vec::new()).root_hash()
+    );
+    positional.clear_leaf_data();
+    assert_eq!(positional.subrange_root(3, 9), None);
+}
+
+#[test]
+fn test_root_matches() {
+    let tree = MerkleTree::new(create_test_data(5));
+    let root = tree.root_hash().unwrap();
+    let hex = tree.root_hash_hex();
+
+    assert!(tree.root_matches(&root));
+    assert!(tree.root_matches(&hex));
+    assert!(tree.root_matches(format!("0x{}", hex)));
+    assert!(tree.root_matches(hex.to_uppercase()));
+    assert!(tree.root_matches_bytes(&root));
+    assert!(tree.root_matches_hex(&hex));
+    assert!(tree.root_matches_hex(&format!("0x{}", hex)));
+
+    // The strict variants do not guess
+    assert!(!tree.root_matches_bytes(hex.as_bytes()));
+    assert!(!tree.root_matches_hex(&String::from_utf8_lossy(&root)));
+
+    // 64 raw bytes are not a SHA-256 root, and only match as hex
+    let mut doubled = root.clone();
+    doubled.extend_from_slice(&root);
+    assert!(!tree.root_matches(&doubled));
+    assert!(!tree.root_matches(&root[..31]));
+    assert!(!tree.root_matches(&hex[..62]));
+
+    let mut wrong = root.clone();
+    wrong[31] ^= 1;
+    assert!(!tree.root_matches(&wrong));
+    assert!(!tree.root_matches(hex::encode(&wrong)));
+    assert!(!MerkleTree::new(Vec::new()).root_matches(&root));
+
+    // The raw interpretation follows the tree's digest length
+    let sha512 = MerkleTree::builder()
+        .hash_algorithm(HashAlgorithm::Sha512)
+        .build(create_test_data(3));
+    let root = sha512.root_hash().unwrap();
+    assert_eq!(root.len(), 64);
+    assert!(sha512.root_matches(&root));
+    assert!(sha512.root_matches(hex::encode(&root)));
+}
+
+#[test]
+fn test_untrusted_shapes_do_not_panic() {
+    let tree = MerkleTree::new(create_test_data(3));
+    let proof = tree.proof_by_index(0).unwrap();
+    let root = tree.root_hash().unwrap();
+
+    // Leaf counts near usize::MAX used to overflow while padding the leaf level
+    for leaf_count in [usize::MAX, usize::MAX - 1, usize::MAX / 2 + 1] {
+        assert!(!MerkleTree::verify_proof_with(
+            HashScheme::Plain,
+            b"Test data 0",
+            &proof,
+            &root,
+            Some(leaf_count)
+        ));
+        assert!(MerkleTree::verify_proof_checked(
+            HashScheme::Plain,
+            b"Test data 0",
+            &proof,
+            &root,
+            Some(leaf_count),
+            Some(0)
+        )
+        .is_err());
+    }
+
+    // Zero-length and truncated sibling hashes are rejected rather than hashed
+    let empty_siblings = MerkleProof::from_bytes(&[0, 0, 1, 0]).unwrap();
+    assert_eq!(empty_siblings.len(), 2);
+    assert!(!MerkleTree::verify_proof(
+        b"Test data 0",
+        &empty_siblings,
+        &root
+    ));
+    assert_eq!(
+        MerkleProof::from_bytes(&[0, 32, 1]),
+        Err(ProofDecodeError::Truncated)
+    );
+
+    #[cfg(feature = "serde")]
+    {
+        // Canopies with malformed shapes answer None instead of panicking
+        let mut canopy = serde_json::to_value(tree.canopy(2)).unwrap();
+        canopy["base_level"] = serde_json::json!(200);
+        let canopy: crate::Canopy = serde_json::from_value(canopy).unwrap();
+        assert_eq!(canopy.subtree_leaves(0), None);
+        assert_eq!(canopy.proof_suffix(0), None);
+        let mut canopy = serde_json::to_value(tree.canopy(2)).unwrap();
+        canopy["levels"] = serde_json::json!([[]]);
+        let canopy: crate::Canopy = serde_json::from_value(canopy).unwrap();
+        assert_eq!(canopy.root_hash(), None);
+        let mut canopy = serde_json::to_value(tree.canopy(2)).unwrap();
+        canopy["levels"] = serde_json::json!([]);
+        let canopy: crate::Canopy = serde_json::from_value(canopy).unwrap();
+        assert_eq!(canopy.proof_suffix(0), None);
+
+        // Imported structures must hash their leaves to the tree's digest length
+        let structure = serde_json::json!({"hash": "", "index": 0});
+        assert!(matches!(
+            MerkleTree::from_json_structure(&structure, HashScheme::Plain),
+            Err(MerkleError::InvalidStructure { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_checkpointed_builder() {
+    use crate::CheckpointedBuilder;
+
+    let hashes: Vec<Vec<u8>> = (0..100_000u32)
+        .map(|i| Sha256::digest(i.to_le_bytes()).to_vec())
+        .collect();
+    let expected = MerkleTree::from_leaf_hashes(hashes.clone(), HashScheme::Plain)
+        .root_hash()
+        .unwrap();
+
+    // Ingest in three segments, resuming from a checkpoint between them
+    let mut builder = CheckpointedBuilder::new(HashScheme::Plain);
+    for hash in &hashes[..30_001] {
+        builder.push_leaf_hash(hash.clone()).unwrap();
+    }
+    let first = builder.checkpoint();
+    let mut builder = CheckpointedBuilder::resume(&first).unwrap();
+    assert_eq!(builder.leaf_count(), 30_001);
+    for hash in &hashes[30_001..77_777] {
+        builder.push_leaf_hash(hash.clone()).unwrap();
+    }
+    let second = builder.checkpoint();
+    let mut builder = CheckpointedBuilder::resume(&second).unwrap();
+    for hash in &hashes[77_777..] {
+        builder.push_leaf_hash(hash.clone()).unwrap();
+    }
+    assert_eq!(builder.root(EmptyRootPolicy::None).unwrap(), expected);
+
+    // Parameters travel with the checkpoint
+    let params = TreeParams {
+        padding: PaddingStrategy::CarryUp,
+        ..TreeParams::new(HashScheme::DomainSeparated, true)
+    };
+    let empty = CheckpointedBuilder::new(params).checkpoint();
+    let restored = CheckpointedBuilder::resume(&empty).unwrap();
+    assert_eq!(restored.into_accumulator().params(), params);
+
+    // Corrupted, truncated and extended checkpoints are rejected
+    let invalid = |bytes: &[u8]| {
+        matches!(
+            CheckpointedBuilder::resume(bytes),
+            Err(MerkleError::InvalidCheckpoint { .. })
+        )
+    };
+    for position in [0, 5, 12, first.len() / 2, first.len() - 1] {
+        let mut corrupted = first.clone();
+        corrupted[position] ^= 0x01;
+        assert!(invalid(&corrupted));
+    }
+    assert!(invalid(&first[..first.len() - 1]));
+    assert!(invalid(&first[..10]));
+    assert!(invalid(&[]));
+    let mut extended = first.clone();
+    extended.push(0);
+    assert!(invalid(&extended));
+
+    assert_eq!(
+        builder.push_leaf_hash(vec![0; 31]),
+        Err(MerkleError::InvalidHashLength {
+            expected: 32,
+            actual: 31
+        })
+    );
+}
+
+#[test]
+fn test_stored_tree_matches_in_memory_tree() {
+    use crate::{FileLevelStore, MemoryLevelStore, StoredMerkleTree};
+
+    let dir = std::env::temp_dir().join(format!("merkleproof-store-{}", std::process::id()));
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 4 },
+    ];
+    for padding in paddings {
+        for positional in [false, true] {
+            let params = TreeParams {
+                padding,
+                ..TreeParams::new(HashScheme::DomainSeparated, positional)
+            };
+            for count in 0..10 {
+                let data = create_test_data(count + 3);
+                let (initial, appended) = data.split_at(count);
+                let build = |items: &[Vec<u8>]| {
+                    MerkleTree::builder()
+                        .positional(positional)
+                        .padding(padding)
+                        .try_build(items.to_vec())
+                        .unwrap()
+                };
+
+                let level_dir = dir.join(format!("{:?}-{}-{}", padding, positional, count));
+                let file_store = FileLevelStore::open(&level_dir, 32).unwrap();
+                let mut in_memory =
+                    StoredMerkleTree::build(MemoryLevelStore::new(), params, initial).unwrap();
+                let mut on_disk = StoredMerkleTree::build(file_store, params, initial).unwrap();
+
+                // Check against a fresh tree, then append and check again
+                for step in 0..=appended.len() {
+                    let expected = build(&data[..count + step]);
+                    for stored in [in_memory.root_hash(), on_disk.root_hash()] {
+                        assert_eq!(stored, Ok(expected.root_hash()));
+                    }
+                    for (index, item) in data[..count + step].iter().enumerate() {
+                        let proof = expected.proof_by_index(index);
+                        assert_eq!(in_memory.generate_proof_by_index(index), Ok(proof.clone()));
+                        assert_eq!(on_disk.generate_proof_by_index(index), Ok(proof.clone()));
+                        assert_eq!(in_memory.verify(item, &proof.unwrap()), Ok(true));
+                    }
+                    if let Some(item) = appended.get(step) {
+                        in_memory.push(item).unwrap();
+                        on_disk.push(item).unwrap();
+                    }
+                }
+
+                // A reopened store gives the same tree
+                let leaf_count = on_disk.leaf_count();
+                let root = on_disk.root_hash();
+                let store = FileLevelStore::open(&level_dir, 32).unwrap();
+                let reopened = StoredMerkleTree::open(store, params, leaf_count).unwrap();
+                assert_eq!(reopened.root_hash(), root);
+                let store = FileLevelStore::open(&level_dir, 32).unwrap();
+                assert!(matches!(
+                    StoredMerkleTree::open(store, params, 0),
+                    Err(MerkleError::InvalidStructure { .. })
+                ));
+            }
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let full = StoredMerkleTree::build(
+        MemoryLevelStore::new(),
+        TreeParams {
+            padding: PaddingStrategy::Complete { depth: 1 },
+            ..TreeParams::new(HashScheme::Plain, false)
+        },
+        create_test_data(2),
+    );
+    assert_eq!(
+        full.unwrap().push(b"overflow"),
+        Err(MerkleError::TooManyLeaves {
+            leaf_count: 3,
+            depth: 1
+        })
+    );
+}
+
+#[test]
+fn test_stored_tree_returns_store_failures() {
+    use crate::{FileLevelStore, LevelStore, MemoryLevelStore, StoredMerkleTree};
+    use std::io;
+
+    /// A store that runs out of space after a number of writes, and may get space back
+    struct FullDisk {
+        inner: MemoryLevelStore,
+        writes_left: usize,
+        recovers: bool,
+    }
+
+    impl LevelStore for FullDisk {
+        fn get(&self, level: usize, index: usize) -> io::Result<Option<Vec<u8>>> {
+            self.inner.get(level, index)
+        }
+
+        fn put(&mut self, level: usize, index: usize, hash: Vec<u8>) -> io::Result<()> {
+            if self.writes_left == 0 {
+                if self.recovers {
+                    self.writes_left = usize::MAX;
+                }
+                return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+            }
+            self.writes_left -= 1;
+            self.inner.put(level, index, hash)
+        }
+
+        fn len(&self, level: usize) -> io::Result<usize> {
+            self.inner.len(level)
+        }
+    }
+
+    // Building three leaves takes seven writes, so the push fails after its leaf has
+    // overwritten the padding slot; the space comes back in time to undo that write
+    let store = FullDisk {
+        inner: MemoryLevelStore::new(),
+        writes_left: 8,
+        recovers: true,
+    };
+    let params = TreeParams::new(HashScheme::DomainSeparated, false);
+    let data = create_test_data(3);
+    let mut tree = StoredMerkleTree::build(store, params, data.clone()).unwrap();
+    let root = tree.root_hash().unwrap();
+    let error = tree.push(b"Test data 3").unwrap_err();
+    assert_eq!(
+        error,
+        MerkleError::Storage {
+            reason: String::from("disk full")
+        }
+    );
+    assert_eq!(error.status_code(), 500);
+    assert_eq!(tree.leaf_count(), 3);
+    assert_eq!(tree.root_hash().unwrap(), root);
+    for (index, leaf) in data.iter().enumerate() {
+        let proof = tree.generate_proof_by_index(index).unwrap().unwrap();
+        assert!(tree.verify(leaf, &proof).unwrap());
+    }
+    tree.push(b"Test data 3").unwrap();
+    let reference =
+        StoredMerkleTree::build(MemoryLevelStore::new(), params, create_test_data(4)).unwrap();
+    assert_eq!(tree.root_hash(), reference.root_hash());
+
+    // A disk that stays full cannot undo the write, so the tree refuses to serve proofs
+    let store = FullDisk {
+        inner: MemoryLevelStore::new(),
+        writes_left: 8,
+        recovers: false,
+    };
+    let mut tree = StoredMerkleTree::build(store, params, data).unwrap();
+    assert!(tree.push(b"Test data 3").is_err());
+    assert!(matches!(
+        tree.generate_proof_by_index(2),
+        Err(MerkleError::Storage { .. })
+    ));
+    assert!(matches!(tree.root_hash(), Err(MerkleError::Storage { .. })));
+    assert!(matches!(
+        tree.push(b"Test data 3"),
+        Err(MerkleError::Storage { .. })
+    ));
+
+    let dir = std::env::temp_dir().join(format!("merkleproof-store-err-{}", std::process::id()));
+    let mut on_disk = StoredMerkleTree::new(FileLevelStore::open(&dir, 32).unwrap(), params);
+    assert!(matches!(
+        on_disk.push_leaf_hash(vec![0; 31]),
+        Err(MerkleError::Storage { .. })
+    ));
+    assert_eq!(on_disk.leaf_count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_chunked_file_offsets() {
+    use crate::ChunkedFileTree;
+
+    let file: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+    let tree = ChunkedFileTree::from_bytes(&file, 64);
+    let root = tree.root_hash().unwrap();
+    assert_eq!(tree.chunk_count(), 16);
+    assert_eq!(tree.chunk_len(15), Some(1000 - 15 * 64));
+    assert_eq!(tree.chunk_len(16), None);
+
+    // Offsets map to their chunk, and past the end there is none
+    assert_eq!(tree.proof_for_offset(0).unwrap().0, 0);
+    assert_eq!(tree.proof_for_offset(64).unwrap().0, 1);
+    assert_eq!(tree.proof_for_offset(999).unwrap().0, 15);
+    assert!(tree.proof_for_offset(1000).is_none());
+
+    // A range spanning chunks 2 to 4 is given as whole chunks
+    let proofs: Vec<MerkleProof> = (2..5)
+        .map(|chunk| tree.tree().proof_by_index(chunk).unwrap())
+        .collect();
+    assert!(tree.verify_range(150, &file[128..320], &proofs, &root));
+    assert!(!tree.verify_range(150, &file[128..319], &proofs, &root));
+    assert!(!tree.verify_range(150, &file[128..320], &proofs[..2], &root));
+    assert!(!tree.verify_range(250, &file[128..320], &proofs, &root));
+    let mut tampered = file[128..320].to_vec();
+    tampered[100] ^= 1;
+    assert!(!tree.verify_range(150, &tampered, &proofs, &root));
+
+    // The short last chunk cannot carry padding bytes
+    let (index, last) = tree.proof_for_offset(990).unwrap();
+    assert!(tree.verify_range(990, &file[index * 64..], std::slice::from_ref(&last), &root));
+    let mut padded = file[index * 64..].to_vec();
+    padded.push(0);
+    assert!(!tree.verify_range(990, &padded, &[last], &root));
+
+    // Reading in pieces gives the same tree
+    let streamed = ChunkedFileTree::from_reader(std::io::Cursor::new(&file), 64).unwrap();
+    assert_eq!(streamed.root_hash().unwrap(), root);
+    assert_eq!(streamed.file_len(), 1000);
+}
+
+#[test]
+fn test_commit_reveal() {
+    use crate::{reveal, Commitment, RevealError};
+
+    let tree = MerkleTree::new(create_test_data(7));
+    let mut commitment = Commitment::new(&tree).unwrap();
+    let reveals = reveal(&tree, &[5, 0, 3]).unwrap();
+    assert!(reveal(&tree, &[7]).is_none());
+
+    // Reveals are accepted in any order, and each index only once
+    for reveal in reveals.iter().rev() {
+        commitment.accept(reveal).unwrap();
+    }
+    assert_eq!(commitment.revealed().collect::<Vec<_>>(), vec![0, 3, 5]);
+    assert_eq!(
+        commitment.accept(&reveals[0]),
+        Err(RevealError::AlreadyRevealed { index: 5 })
+    );
+
+    // A proof moved to another index, or for other data, is rejected and not recorded
+    let mut moved = reveal(&tree, &[1]).unwrap().remove(0);
+    moved.index = 2;
+    assert_eq!(
+        commitment.accept(&moved),
+        Err(RevealError::InvalidProof(
+            VerificationError::IndexMismatch {
+                expected: 2,
+                actual: 1
+            }
+        ))
+    );
+    let mut forged = reveal(&tree, &[2]).unwrap().remove(0);
+    forged.data = b"forged".to_vec();
+    assert_eq!(
+        commitment.accept(&forged),
+        Err(RevealError::InvalidProof(VerificationError::RootMismatch))
+    );
+    moved.index = 9;
+    assert_eq!(
+        commitment.accept(&moved),
+        Err(RevealError::IndexOutOfRange {
+            index: 9,
+            leaf_count: 7
+        })
+    );
+    assert_eq!(commitment.revealed().count(), 3);
+
+    // The two parties can be separate processes
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&commitment).unwrap();
+        let mut restored: Commitment = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, commitment);
+        let sent = serde_json::to_string(&reveals[1]).unwrap();
+        let replayed = serde_json::from_str(&sent).unwrap();
+        assert_eq!(
+            restored.accept(&replayed),
+            Err(RevealError::AlreadyRevealed { index: 0 })
+        );
+    }
+
+    assert!(Commitment::new(&MerkleTree::new(Vec::new())).is_none());
+}
+
+#[test]
+fn test_fixed_depth_tree_matches_complete_tree() {
+    use crate::{fixed_depth_nodes, verify_fixed_proof, FixedDepthTree, FixedIndexError};
+
+    const DEPTH: usize = 4;
+    for scheme in [HashScheme::Plain, HashScheme::DomainSeparated] {
+        let mut fixed = FixedDepthTree::<DEPTH, { fixed_depth_nodes(DEPTH) }>::new(scheme);
+        let params = TreeParams {
+            padding: PaddingStrategy::Complete { depth: DEPTH as u8 },
+            ..TreeParams::from(scheme)
+        };
+        let empty = MerkleTree::from_leaf_hashes(Vec::new(), params);
+        assert_eq!(fixed.root().to_vec(), empty.root_hash().unwrap());
+
+        let mut hashes = Vec::new();
+        for (index, data) in create_test_data(11).iter().enumerate() {
+            fixed.set_leaf_data(index, data).unwrap();
+            hashes.push(fixed.leaf(index).unwrap().to_vec());
+
+            let dynamic = MerkleTree::from_leaf_hashes(hashes.clone(), params);
+            assert_eq!(fixed.root().to_vec(), dynamic.root_hash().unwrap());
+            for leaf in 0..hashes.len() {
+                let proof = fixed.proof(leaf).unwrap();
+                let expected = dynamic.proof_by_index(leaf).unwrap();
+                let steps: Vec<(Vec<u8>, bool)> = proof
+                    .iter()
+                    .map(|(hash, is_left)| (hash.to_vec(), *is_left))
+                    .collect();
+                assert_eq!(steps, expected.to_vec());
+                assert!(verify_fixed_proof(
+                    scheme,
+                    fixed.leaf(leaf).unwrap(),
+                    &proof,
+                    &fixed.root()
+                ));
+            }
+        }
+
+        // An unset leaf can be proven empty, and a proof does not verify another leaf
+        let proof = fixed.proof(15).unwrap();
+        assert!(verify_fixed_proof(
+            scheme,
+            fixed.leaf(15).unwrap(),
+            &proof,
+            &fixed.root()
+        ));
+        assert!(!verify_fixed_proof(
+            scheme,
+            fixed.leaf(0).unwrap(),
+            &proof,
+            &fixed.root()
+        ));
+        let error = fixed.set_leaf(16, [0; 32]).unwrap_err();
+        assert_eq!(
+            error,
+            FixedIndexError {
+                index: 16,
+                capacity: 16
+            }
+        );
+        assert_eq!(
+            MerkleError::from(error),
+            MerkleError::IndexOutOfRange {
+                index: 16,
+                leaf_count: 16
+            }
+        );
+        assert!(fixed.proof(16).is_none());
+    }
+}
+
+#[test]
+fn test_fixed_depth_tree_at_max_depth() {
+    use crate::{fixed_depth_nodes, verify_fixed_proof, FixedDepthTree, MAX_FIXED_DEPTH};
+
+    // A 4 MiB tree, built on a thread whose stack has room for it and its copies
+    std::thread::Builder::new()
+        .stack_size(64 << 20)
+        .spawn(|| {
+            type Tree = FixedDepthTree<MAX_FIXED_DEPTH, { fixed_depth_nodes(MAX_FIXED_DEPTH) }>;
+            let mut tree = Box::new(Tree::new(HashScheme::DomainSeparated));
+            let last = Tree::capacity() - 1;
+            tree.set_leaf_data(last, b"last").unwrap();
+            let proof = tree.proof(last).unwrap();
+            assert_eq!(proof.len(), MAX_FIXED_DEPTH);
+            assert!(verify_fixed_proof(
+                HashScheme::DomainSeparated,
+                tree.leaf(last).unwrap(),
+                &proof,
+                &tree.root()
+            ));
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_proof_size_bounds() {
+    use crate::ProofEncoding;
+
+    let encodings = [
+        ProofEncoding::Binary,
+        ProofEncoding::Hex,
+        ProofEncoding::Base64,
+        #[cfg(feature = "serde")]
+        ProofEncoding::Json,
+    ];
+    let encoded_len = |proof: &MerkleProof, encoding| match encoding {
+        ProofEncoding::Binary => proof.to_bytes().len(),
+        ProofEncoding::Hex => proof.to_hex().len(),
+        ProofEncoding::Base64 => proof.to_base64().len(),
+        #[cfg(feature = "serde")]
+        ProofEncoding::Json => serde_json::to_vec(proof).unwrap().len(),
+    };
+
+    for padding in [PaddingStrategy::DuplicateLast, PaddingStrategy::CarryUp] {
+        for count in [1, 2, 5, 13] {
+            let tree = MerkleTree::builder()
+                .padding(padding)
+                .build(create_test_data(count));
+            for &encoding in &encodings {
+                let max = tree.max_proof_size(encoding);
+                for index in 0..count {
+                    let proof = tree.proof_by_index(index).unwrap();
+                    let size = proof.serialized_size(encoding);
+                    assert_eq!(size, encoded_len(&proof, encoding));
+                    assert!(size <= max);
+                }
+            }
+        }
+    }
+
+    // The JSON bound is reached when every byte has three digits
+    #[cfg(feature = "serde")]
+    {
+        let worst = MerkleProof::from(vec![(vec![255; 32], false); 4]);
+        let tree = MerkleTree::new(create_test_data(16));
+        assert_eq!(
+            worst.serialized_size(ProofEncoding::Json),
+            tree.max_proof_size(ProofEncoding::Json)
+        );
+    }
+
+    let tree = MerkleTree::new(create_test_data(1000));
+    let binary = tree.max_proof_size(ProofEncoding::Binary);
+    assert_eq!(binary, 10 * 34);
+    assert!(tree
+        .generate_proof_bounded(7, ProofEncoding::Binary, binary)
+        .is_ok());
+    assert_eq!(
+        tree.generate_proof_bounded(7, ProofEncoding::Hex, binary),
+        Err(MerkleError::ProofTooLarge {
+            size: 2 * binary,
+            max: binary
+        })
+    );
+    assert!(matches!(
+        tree.generate_proof_bounded(1000, ProofEncoding::Binary, 4096),
+        Err(MerkleError::IndexOutOfRange { .. })
+    ));
+}
+
+#[test]
+fn test_verify_proof_from_reader() {
+    use crate::ChunkedFileTree;
+    use std::io::{self, Cursor, Read};
+
+    /// A reader that fails after yielding some bytes
+    struct Failing(usize);
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("disk failure"));
+            }
+            let read = self.0.min(buf.len());
+            buf[..read].fill(7);
+            self.0 -= read;
+            Ok(read)
+        }
+    }
+
+    let large = vec![7u8; 1 << 20];
+    let mut data = create_test_data(4);
+    data.push(large.clone());
+    let tree = MerkleTree::new(data);
+    let root = tree.root_hash().unwrap();
+    let proof = tree.proof_by_index(4).unwrap();
+
+    assert!(
+        MerkleTree::verify_proof_from_reader(io::repeat(7).take(1 << 20), &proof, &root).unwrap()
+    );
+    assert!(!MerkleTree::verify_proof_from_reader(
+        io::repeat(7).take((1 << 20) - 1),
+        &proof,
+        &root
+    )
+    .unwrap());
+    let error = MerkleTree::verify_proof_from_reader(Failing(1000), &proof, &root).unwrap_err();
+    assert_eq!(error.to_string(), "disk failure");
+
+    let built = MerkleTree::builder().build(create_test_data(5));
+    let proof = built.proof_by_index(2).unwrap();
+    assert!(MerkleTree::verify_proof_from_reader_with(
+        HashScheme::DomainSeparated,
+        Cursor::new(b"Test data 2"),
+        &proof,
+        &built.root_hash().unwrap(),
+        Some(5)
+    )
+    .unwrap());
+
+    // Chunks are checked against their expected length while streaming
+    let file: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+    let chunked = ChunkedFileTree::from_bytes(&file, 64);
+    let root = chunked.root_hash().unwrap();
+    let (index, proof) = chunked.proof_for_offset(990).unwrap();
+    let last = &file[index * 64..];
+    assert!(chunked
+        .verify_chunk_from_reader(index, last, &proof, &root)
+        .unwrap());
+    let padded = [last, &[0]].concat();
+    assert!(!chunked
+        .verify_chunk_from_reader(index, &padded[..], &proof, &root)
+        .unwrap());
+    assert!(!chunked
+        .verify_chunk_from_reader(index - 1, last, &proof, &root)
+        .unwrap());
+    assert!(chunked
+        .verify_chunk_from_reader(index, Failing(5), &proof, &root)
+        .is_err());
+}
+
+#[test]
+fn test_expected_proof_lengths() {
+    use crate::{expected_proof_len, expected_proof_len_for_index};
+
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 6 },
+    ];
+    for padding in paddings {
+        for count in 1..=64 {
+            let tree = MerkleTree::builder()
+                .padding(padding)
+                .build(create_test_data(count));
+            let mut longest = 0;
+            for index in 0..count {
+                let proof = tree.proof_by_index(index).unwrap();
+                let expected = expected_proof_len_for_index(count, padding, index);
+                assert_eq!(expected, Some(proof.len()));
+                assert!(proof.consistent_with_padding(count, index, padding));
+                longest = longest.max(proof.len());
+
+                let mut short = proof.clone();
+                short.pop();
+                assert_eq!(
+                    short.consistent_with_padding(count, index, padding),
+                    proof.is_empty()
+                );
+                let mut long = proof.clone();
+                long.push((vec![0; 32], false));
+                assert!(!long.consistent_with_padding(count, index, padding));
+                assert!(!proof.consistent_with_padding(count, count, padding));
+            }
+            assert_eq!(expected_proof_len(count, padding), longest);
+            assert_eq!(expected_proof_len_for_index(count, padding, count), None);
+        }
+    }
+
+    // Carried-up nodes shorten the proofs of later leaves
+    let tree = MerkleTree::new(create_test_data(5));
+    let proof = tree.proof_by_index(4).unwrap();
+    assert_eq!(expected_proof_len(5, PaddingStrategy::DuplicateLast), 3);
+    assert_eq!(proof.len(), 2);
+    assert!(proof.consistent_with(5, 4));
+    assert!(!proof.consistent_with(5, 3));
+    assert!(!proof.consistent_with(8, 4));
+    assert_eq!(expected_proof_len(0, PaddingStrategy::DuplicateLast), 0);
+}
+
+#[test]
+fn test_tree_is_shareable_between_threads() {
+    use std::sync::Arc;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MerkleTree>();
+    assert_send_sync::<crate::MerkleNode>();
+    assert_send_sync::<MerkleProof>();
+    assert_send_sync::<MerkleError>();
+    assert_send_sync::<crate::SnapshotReader>();
+
+    let tree = Arc::new(MerkleTree::new(create_test_data(10)));
+    let root = tree.root_hash().unwrap();
+    assert_eq!(tree.root_hash_ref(), Some(&root[..]));
+    let handles: Vec<_> = (0..4)
+        .map(|thread| {
+            let tree = Arc::clone(&tree);
+            std::thread::spawn(move || {
+                let proof = tree.proof_by_index(thread).unwrap();
+                tree.verify(format!("Test data {}", thread).as_bytes(), &proof)
+            })
+        })
+        .collect();
+    for handle in handles {
+        assert!(handle.join().unwrap());
+    }
+
+    // An empty tree has no stored root to borrow, even when one is defined
+    let empty = MerkleTree::builder()
+        .empty_root(EmptyRootPolicy::HashOfEmpty)
+        .build(Vec::new());
+    assert!(empty.root_hash().is_some());
+    assert!(empty.root_hash_ref().is_none());
+
+    assert_eq!(
+        tree.generate_proof_bounded(10, crate::ProofEncoding::Binary, 0)
+            .unwrap_err()
+            .status_code(),
+        404
+    );
+    assert_eq!(
+        MerkleError::DuplicateLeaf {
+            first: 0,
+            second: 1,
+            hash: String::new()
+        }
+        .status_code(),
+        422
+    );
+}
+
+#[test]
+fn test_reversed_byte_order() {
+    use crate::{ByteOrder, CheckpointedBuilder, MerkleVerifier};
+
+    // Computed independently as SHA-256(reverse(left) || reverse(right)) over the plain
+    // leaf hashes of "Test data 0" to "Test data 2", the last leaf duplicated
+    const REVERSED_ROOT: &str = "5574f3ec404cf9b24803fd5a908866e66790970ed6108be8a646c6760d3f02f5";
+    const NATURAL_ROOT: &str = "850806d1005b5916b57ad087ca5b0818a17d77a4303f201c5021d14f55e8343c";
+
+    let build = |byte_order| {
+        MerkleTree::builder()
+            .hash_scheme(HashScheme::Plain)
+            .byte_order(byte_order)
+            .build(create_test_data(3))
+    };
+    let natural = build(ByteOrder::Natural);
+    assert_eq!(natural.root_hash_hex(), NATURAL_ROOT);
+    assert_eq!(
+        natural.root_hash(),
+        MerkleTree::new(create_test_data(3)).root_hash()
+    );
+
+    let reversed = build(ByteOrder::Reversed);
+    assert_eq!(reversed.root_hash_hex(), REVERSED_ROOT);
+    let config = reversed.hash_config();
+    assert_eq!(config.byte_order, ByteOrder::Reversed);
+    for (index, data) in create_test_data(3).iter().enumerate() {
+        let proof = reversed.proof_by_index(index).unwrap();
+        assert!(reversed.verify(data, &proof));
+        assert!(!natural.verify(data, &proof));
+        let verifier = MerkleVerifier::new(reversed.root_hash().unwrap())
+            .with_hash_config(config)
+            .with_leaf_count(3);
+        assert_eq!(verifier.verify(data, &proof), Ok(()));
+    }
+
+    // Streaming and resumed construction agree
+    let mut builder = CheckpointedBuilder::new(config);
+    for data in create_test_data(3) {
+        builder.push_leaf_hash(config.hash_leaf(&data)).unwrap();
+    }
+    let builder = CheckpointedBuilder::resume(&builder.checkpoint()).unwrap();
+    assert_eq!(builder.root(EmptyRootPolicy::None), reversed.root_hash());
+
+    // The natural order is left out of serialized configurations
+    #[cfg(feature = "serde")]
+    {
+        let natural = serde_json::to_value(natural.hash_config()).unwrap();
+        assert!(natural.get("byte_order").is_none());
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains("\"byte_order\":\"Reversed\""));
+        assert_eq!(
+            serde_json::from_str::<crate::HashConfig>(&json).unwrap(),
+            config
+        );
+    }
+}
+
+#[test]
+fn test_leaf_metadata() {
+    let mut tree = MerkleTree::builder().build(create_test_data(5));
+    let root = tree.root_hash();
+    let proofs: Vec<MerkleProof> = (0..5)
+        .map(|index| tree.proof_by_index(index).unwrap())
+        .collect();
+
+    tree.attach_metadata(1, b"2024-01-01T00:00:00Z".to_vec())
+        .unwrap();
+    tree.attach_metadata(3, "source-7").unwrap();
+    tree.attach_metadata(3, "source-8").unwrap();
+    assert_eq!(
+        tree.attach_metadata(5, "late"),
+        Err(MerkleError::IndexOutOfRange {
+            index: 5,
+            leaf_count: 5
+        })
+    );
+    assert_eq!(tree.metadata(3), Some(&b"source-8"[..]));
+    assert_eq!(tree.metadata(0), None);
+
+    // Metadata changes neither the root nor any proof
+    assert_eq!(tree.root_hash(), root);
+    for (index, proof) in proofs.iter().enumerate() {
+        assert_eq!(tree.proof_by_index(index).as_ref(), Some(proof));
+    }
+
+    // Bundles carry it only on request, and verification ignores it
+    let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+    assert_eq!(
+        tree.generate_bundle(3).unwrap().unauthenticated_metadata,
+        None
+    );
+    let mut bundle = tree.generate_bundle_with_metadata(3).unwrap();
+    assert_eq!(
+        bundle.unauthenticated_metadata.as_deref(),
+        Some(&b"source-8"[..])
+    );
+    assert_eq!(verifier.verify_bundle(b"Test data 3", &bundle), Ok(()));
+    bundle.unauthenticated_metadata = Some(b"forged".to_vec());
+    assert_eq!(verifier.verify_bundle(b"Test data 3", &bundle), Ok(()));
+
+    // Shards keep the metadata of their leaves
+    let shards = tree.split(2).unwrap();
+    assert_eq!(shards[1].metadata(1), Some(&b"source-8"[..]));
+    assert_eq!(shards[0].metadata(1), tree.metadata(1));
+
+    #[cfg(feature = "serde")]
+    {
+        use crate::JsonExportOptions;
+
+        let json = serde_json::to_value(&bundle).unwrap();
+        assert_eq!(json["unauthenticated_metadata"], hex::encode("forged"));
+        let plain = serde_json::to_value(tree.generate_bundle(0).unwrap()).unwrap();
+        assert!(plain.get("unauthenticated_metadata").is_none());
+
+        // The side table is exported only when asked for, and survives a round trip
+        assert!(!tree.to_json_structure().to_string().contains("metadata"));
+        let opts = JsonExportOptions {
+            metadata: true,
+            ..JsonExportOptions::default()
+        };
+        let structure = tree.to_json_structure_with(&opts);
+        let rebuilt =
+            MerkleTree::from_json_structure(&structure, HashScheme::DomainSeparated).unwrap();
+        assert_eq!(rebuilt.metadata(1), tree.metadata(1));
+        assert_eq!(rebuilt.metadata(3), tree.metadata(3));
+        assert_eq!(rebuilt.root_hash(), root);
+    }
+}
+
+#[test]
+fn test_typed_indices() {
+    use crate::LeafIndex;
+
+    let mut tree = MerkleTree::new(create_test_data(5));
+    let leaf = tree.leaf_index(3).unwrap();
+    assert_eq!(leaf.get(), 3);
+    assert_eq!(LeafIndex::try_from(3usize).unwrap(), leaf);
+    assert_eq!(usize::try_from(leaf).unwrap(), 3);
+    assert_eq!(leaf.to_string(), "leaf 3");
+    assert_eq!(
+        tree.leaf_index(5),
+        Err(MerkleError::IndexOutOfRange {
+            index: 5,
+            leaf_count: 5
+        })
+    );
+
+    let proof = tree.generate_proof_at(leaf).unwrap();
+    assert!(MerkleTree::verify_proof(
+        &create_test_data(5)[3],
+        &proof,
+        &tree.root_hash().unwrap()
+    ));
+    // Indices made without a tree are still checked when used
+    let beyond = LeafIndex::try_from(5usize).unwrap();
+    assert_eq!(tree.generate_proof_at(beyond), None);
+    let max = LeafIndex::try_from(usize::MAX).unwrap();
+    assert_eq!(tree.generate_proof_at(max), None);
+
+    // The padding leaf is not addressable, but the nodes above it are
+    let coord = tree.node_coord(1, 2).unwrap();
+    assert_eq!((coord.level(), coord.index()), (1, 2));
+    assert_eq!(coord.to_string(), "node 2 on level 1");
+    assert_eq!(
+        tree.node_hash_at(coord).as_deref(),
+        tree.levels()[1].get(2).map(Vec::as_slice)
+    );
+    assert_eq!(
+        tree.node_coord(0, 5),
+        Err(MerkleError::NodeOutOfRange { level: 0, index: 5 })
+    );
+    assert!(tree.node_coord(4, 0).is_err());
+    assert!(tree.node_coord(3, 0).is_ok());
+
+    let root = tree.root_hash();
+    tree.update_leaf(leaf, b"changed".to_vec()).unwrap();
+    assert_ne!(tree.root_hash(), root);
+    assert_eq!(tree.get_leaf(3), Some(&b"changed"[..]));
+    assert!(matches!(
+        tree.update_leaf(beyond, Vec::new()),
+        Err(MerkleError::IndexOutOfRange { .. })
+    ));
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_usize_index_shims() {
+    let mut tree = MerkleTree::new(create_test_data(5));
+    let leaf = tree.leaf_index(3).unwrap();
+    assert_eq!(
+        tree.generate_proof_by_index(3),
+        tree.generate_proof_at(leaf)
+    );
+    assert_eq!(tree.generate_proof_by_index(5), None);
+    let coord = tree.node_coord(1, 2).unwrap();
+    assert_eq!(tree.node_hash(1, 2), tree.node_hash_at(coord));
+    assert_eq!(tree.node_hash(0, 5), None);
+
+    let mut typed = tree.clone();
+    tree.update(3, b"changed".to_vec()).unwrap();
+    typed.update_leaf(leaf, b"changed".to_vec()).unwrap();
+    assert_eq!(tree.root_hash(), typed.root_hash());
+    assert!(matches!(
+        tree.update(5, Vec::new()),
+        Err(MerkleError::IndexOutOfRange { .. })
+    ));
+}
+
+#[cfg(feature = "conformance")]
+#[test]
+fn test_conformance_suite() {
+    use crate::conformance::{self, ConformanceSuite};
+    use crate::ConformanceError;
+
+    // The committed suite must match the implementation, and regenerating it must not
+    // change it; refresh it after an intended change with
+    // `cargo run --example conformance --features conformance > conformance.json`
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/conformance.json");
+    let committed = std::fs::read_to_string(path).unwrap();
+    let checked = conformance::check(path).unwrap();
+    assert_eq!(checked, conformance::cases().len());
+    assert_eq!(committed, conformance::generate());
+
+    let mut suite: ConformanceSuite = serde_json::from_str(&committed).unwrap();
+    suite.cases[3].root[0] ^= 1;
+    assert!(matches!(
+        suite.check(),
+        Err(ConformanceError::RootMismatch { .. })
+    ));
+    suite.cases[3].root[0] ^= 1;
+    suite.cases[3].proofs[1].steps[0].is_left ^= true;
+    assert!(matches!(
+        suite.check(),
+        Err(ConformanceError::ProofMismatch { index: 1, .. })
+    ));
+    suite.version = 2;
+    assert!(matches!(
+        suite.check(),
+        Err(ConformanceError::UnsupportedVersion { version: 2 })
+    ));
+    assert!(matches!(
+        conformance::check("missing.json"),
+        Err(ConformanceError::Io(_))
+    ));
+}
+
+#[test]
+fn test_proof_cache() {
+    use crate::ProofCacheStats;
+
+    let data = create_test_data(9);
+    let uncached = MerkleTree::builder().build(data.clone());
+    let mut tree = MerkleTree::builder().proof_cache(2).build(data);
+    assert_eq!(uncached.proof_cache_stats(), ProofCacheStats::default());
+
+    // Cached proofs are identical to freshly computed ones
+    for _ in 0..3 {
+        for index in [0, 4, 0] {
+            let proof = tree.proof_by_index(index).unwrap();
+            assert_eq!(
+                proof.to_bytes(),
+                uncached.proof_by_index(index).unwrap().to_bytes()
+            );
+        }
+    }
+    let proof = tree.generate_proof(b"Test data 4").unwrap();
+    assert_eq!(proof, uncached.generate_proof(b"Test data 4").unwrap());
+    assert_eq!(
+        tree.proof_cache_stats(),
+        ProofCacheStats {
+            hits: 8,
+            misses: 2,
+            entries: 2
+        }
+    );
+    assert_eq!(tree.proof_by_index(9), None);
+
+    // The least recently used proof (of leaf 0) is evicted
+    tree.proof_by_index(8).unwrap();
+    tree.proof_by_index(4).unwrap();
+    tree.proof_by_index(0).unwrap();
+    assert_eq!(tree.proof_cache_stats().misses, 4);
+
+    // A mutation empties the cache, so proofs reflect the new tree
+    let before = tree.proof_by_index(1).unwrap();
+    tree.update_by_index(0, b"changed".to_vec()).unwrap();
+    assert_eq!(tree.proof_cache_stats().entries, 0);
+    let after = tree.proof_by_index(1).unwrap();
+    assert_ne!(before, after);
+    assert!(tree.verify(b"Test data 1", &after));
+    tree.push(b"pushed".to_vec());
+    assert_eq!(tree.proof_cache_stats().entries, 0);
+    assert!(tree.verify(b"Test data 1", &tree.proof_by_index(1).unwrap()));
+
+    tree.reset_metrics();
+    assert_eq!(tree.proof_cache_stats().hits, 0);
+    assert_eq!(tree.proof_cache_stats().misses, 0);
+}
+
+#[test]
+fn test_leaf_payload_storage() {
+    use std::sync::Arc;
+
+    let data = create_test_data(5);
+    let shared: Vec<Arc<[u8]>> = data.iter().map(|item| Arc::from(&item[..])).collect();
+    let owned = MerkleTree::new(data.clone());
+    let tree = MerkleTree::new_shared(shared.clone());
+    let hashes_only = MerkleTree::builder()
+        .hash_scheme(HashScheme::Plain)
+        .build_without_data(data.clone());
+
+    // Storage does not change the commitment
+    for other in [&tree, &hashes_only] {
+        assert_eq!(other.root_hash(), owned.root_hash());
+        for index in 0..5 {
+            assert_eq!(other.proof_by_index(index), owned.proof_by_index(index));
+        }
+    }
+    assert_eq!(
+        tree.generate_proof(b"Test data 3"),
+        owned.generate_proof(b"Test data 3")
+    );
+    assert_eq!(hashes_only.get_leaf(0), None);
+    assert_eq!(hashes_only.shared_leaf(0), None);
+    assert_eq!(owned.shared_leaf(0), None);
+    assert_eq!(
+        MerkleTree::builder()
+            .build_shared(shared.clone())
+            .root_hash(),
+        MerkleTree::builder().build(data).root_hash()
+    );
+
+    // Clones of a shared tree point at the application's payloads
+    let clone = tree.clone();
+    for (index, payload) in shared.iter().enumerate() {
+        assert_eq!(clone.get_leaf(index), Some(&payload[..]));
+        assert_eq!(clone.get_leaf(index).unwrap().as_ptr(), payload.as_ptr());
+        assert!(Arc::ptr_eq(&clone.shared_leaf(index).unwrap(), payload));
+    }
+    // Owned payloads are shared with a clone until one of the trees replaces them
+    let mut owned_clone = owned.clone();
+    assert_eq!(
+        owned_clone.get_leaf(0).unwrap().as_ptr(),
+        owned.get_leaf(0).unwrap().as_ptr()
+    );
+    let original = owned.get_leaf(0).unwrap().to_vec();
+    owned_clone
+        .update_by_index(0, b"replaced".to_vec())
+        .unwrap();
+    assert_eq!(owned.get_leaf(0), Some(&original[..]));
+    assert_eq!(owned_clone.get_leaf(0), Some(&b"replaced"[..]));
+    assert_ne!(owned_clone.root_hash(), owned.root_hash());
+
+    // Clearing releases shared payloads without touching them
+    let mut cleared = tree.clone();
+    cleared.clear_leaf_data();
+    assert_eq!(cleared.get_leaf(2), None);
+    assert_eq!(&shared[2][..], b"Test data 2");
+    assert_eq!(cleared.root_hash(), owned.root_hash());
+}
+
+#[test]
+fn test_rehash_with_new_params() {
+    let data = create_test_data(5);
+    let legacy = MerkleTree::new(data.clone());
+    let separated = TreeParams::from(HashScheme::DomainSeparated);
+    let migrated = legacy.rehash_with(separated).unwrap();
+    assert_eq!(
+        migrated.root_hash(),
+        MerkleTree::builder().build(data.clone()).root_hash()
+    );
+    assert_eq!(migrated.get_leaf(2), Some(&b"Test data 2"[..]));
+    assert_eq!(
+        legacy.migration_roots(separated).unwrap(),
+        (legacy.root_hash(), migrated.root_hash())
+    );
+
+    // Each root only accepts proofs made under its own parameters
+    let old_proof = legacy.proof_by_index(2).unwrap();
+    let new_proof = migrated.proof_by_index(2).unwrap();
+    let old_root = legacy.root_hash().unwrap();
+    let new_root = migrated.root_hash().unwrap();
+    assert!(MerkleTree::verify_proof_with(
+        HashScheme::DomainSeparated,
+        &data[2],
+        &new_proof,
+        &new_root,
+        Some(5)
+    ));
+    assert!(!MerkleTree::verify_proof_with(
+        HashScheme::Plain,
+        &data[2],
+        &new_proof,
+        &new_root,
+        Some(5)
+    ));
+    assert!(!MerkleTree::verify_proof_with(
+        HashScheme::DomainSeparated,
+        &data[2],
+        &old_proof,
+        &old_root,
+        Some(5)
+    ));
+    assert!(MerkleTree::verify_proof(&data[2], &old_proof, &old_root));
+    assert!(migrated.verify(&data[2], &new_proof));
+    assert!(!migrated.verify(&data[2], &old_proof));
+
+    // Without leaf data only changes that keep the leaf hashes are possible
+    let mut hashes_only = legacy.clone();
+    hashes_only.clear_leaf_data();
+    assert_eq!(
+        hashes_only.rehash_with(separated).unwrap_err(),
+        MerkleError::LeafDataUnavailable { index: 0 }
+    );
+    let carried = TreeParams {
+        padding: PaddingStrategy::CarryUp,
+        ..TreeParams::from(HashScheme::Plain)
+    };
+    assert_eq!(
+        hashes_only.rehash_with(carried).unwrap().root_hash(),
+        MerkleTree::builder()
+            .hash_scheme(HashScheme::Plain)
+            .padding(PaddingStrategy::CarryUp)
+            .build(data)
+            .root_hash()
+    );
+    assert!(matches!(
+        legacy.rehash_with(TreeParams {
+            padding: PaddingStrategy::Complete { depth: 2 },
+            ..separated
+        }),
+        Err(MerkleError::TooManyLeaves { .. })
+    ));
+}
+
+#[test]
+fn test_snapshot_reader() {
+    use crate::{SnapshotError, SnapshotReader};
+
+    let configs = [
+        TreeParams::from(HashScheme::Plain),
+        TreeParams {
+            padding: PaddingStrategy::CarryUp,
+            ..TreeParams::new(HashScheme::DomainSeparated, true)
+        },
+        TreeParams {
+            padding: PaddingStrategy::Complete { depth: 4 },
+            ..TreeParams::from(HashScheme::DomainSeparated)
+        },
+    ];
+    for params in configs {
+        for count in [1, 2, 5, 11] {
+            let tree = MerkleTree::new(create_test_data(count))
+                .rehash_with(params)
+                .unwrap();
+            let snapshot = tree.to_snapshot();
+            let loaded = MerkleTree::from_snapshot(&snapshot).unwrap();
+            let reader = SnapshotReader::open(&snapshot).unwrap();
+
+            assert_eq!(reader.params(), params);
+            assert_eq!(reader.leaf_count(), count);
+            assert_eq!(reader.root().unwrap(), tree.root_hash());
+            assert_eq!(loaded.root_hash(), tree.root_hash());
+            for index in 0..count {
+                let proof = reader.proof(index).unwrap();
+                assert_eq!(
+                    proof.to_bytes(),
+                    tree.proof_by_index(index).unwrap().to_bytes()
+                );
+                assert_eq!(proof, loaded.proof_by_index(index).unwrap());
+            }
+            assert!(matches!(
+                reader.proof(count),
+                Err(SnapshotError::IndexOutOfRange { .. })
+            ));
+        }
+    }
+
+    // An empty tree keeps its empty root policy
+    let empty = MerkleTree::builder()
+        .empty_root(EmptyRootPolicy::HashOfEmpty)
+        .build(Vec::new());
+    let snapshot = empty.to_snapshot();
+    let reader = SnapshotReader::open(&snapshot).unwrap();
+    assert_eq!(reader.leaf_count(), 0);
+    assert_eq!(reader.root().unwrap(), empty.root_hash());
+
+    // A reader over a file serves the same proofs
+    let tree = MerkleTree::new(create_test_data(9));
+    let snapshot = tree.to_snapshot();
+    let path = std::env::temp_dir().join(format!("merkleproof-snapshot-{}", std::process::id()));
+    std::fs::write(&path, &snapshot).unwrap();
+    let reader = SnapshotReader::open(path.as_path()).unwrap();
+    assert_eq!(reader.root().unwrap(), tree.root_hash());
+    assert_eq!(reader.proof(8).unwrap(), tree.proof_by_index(8).unwrap());
+    let reader = SnapshotReader::open(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reader.proof(3).unwrap(), tree.proof_by_index(3).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        SnapshotReader::open(path.as_path()),
+        Err(SnapshotError::Io(_))
+    ));
+
+    // Truncated snapshots and malformed level tables are errors, not panics
+    let malformed = |bytes: &[u8]| {
+        matches!(
+            SnapshotReader::open(bytes),
+            Err(SnapshotError::Malformed { .. })
+        )
+    };
+    for len in [0, 3, 10, 30, snapshot.len() - 1] {
+        assert!(malformed(&snapshot[..len]));
+    }
+    // The level table follows the 4-byte magic, version, 6 parameter bytes, empty root
+    // policy, leaf count and level count
+    let table = 4 + 1 + 6 + 1 + 8 + 4;
+    for (field, value) in [(0, u64::MAX), (0, 1 << 40), (8, u64::MAX), (8, 3)] {
+        let mut corrupted = snapshot.clone();
+        let at = table + 16 + field;
+        corrupted[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        assert!(malformed(&corrupted));
+    }
+    let mut corrupted = snapshot.clone();
+    corrupted[table - 4..table].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(malformed(&corrupted));
+    assert!(malformed(b"not a snapshot"));
+}
+
+#[test]
+fn test_concurrent_queries_on_shared_tree() {
+    use std::sync::Arc;
+
+    // The proof cache and metrics are updated through `&self` from every thread
+    let data = create_test_data(64);
+    let tree = Arc::new(MerkleTree::builder().proof_cache(16).build(data.clone()));
+    let root = tree.root_hash().unwrap();
+    let expected: Vec<MerkleProof> = (0..data.len())
+        .map(|index| tree.proof_by_index(index).unwrap())
+        .collect();
+
+    std::thread::scope(|scope| {
+        for thread in 0..8 {
+            let (tree, expected, data, root) = (&tree, &expected, &data, &root);
+            scope.spawn(move || {
+                for round in 0..200 {
+                    let index = (thread * 7 + round * 13) % data.len();
+                    let proof = tree.generate_proof(&data[index]).unwrap();
+                    assert_eq!(proof, expected[index]);
+                    assert_eq!(tree.root_hash().as_ref(), Some(root));
+                    assert!(tree.verify(&data[index], &proof));
+                    assert!(!tree.verify(&data[(index + 1) % data.len()], &proof));
+                }
+            });
+        }
+    });
+
+    let stats = tree.proof_cache_stats();
+    assert_eq!(stats.hits + stats.misses, 64 + 8 * 200);
+    assert!(stats.entries <= 16);
+}
+
+#[test]
+fn test_push_reports_invalidated_proofs() {
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 5 },
+    ];
+    for padding in paddings {
+        let empty = crate::empty_subtree_hashes(HashScheme::DomainSeparated, 5);
+        for n in 1..=16 {
+            let data = create_test_data(n + 1);
+            let mut tree = MerkleTree::builder()
+                .padding(padding)
+                .build(data[..n].to_vec());
+            let before: Vec<MerkleProof> = (0..n)
+                .map(|index| tree.proof_by_index(index).unwrap())
+                .collect();
+
+            let outcome = tree.push(data[n].clone());
+            assert_eq!(Some(outcome.new_root), tree.root_hash());
+
+            // A proof is invalidated if it gained a step or a padding sibling was replaced
+            let expected: Vec<usize> = (0..n)
+                .filter(|&index| {
+                    let after = tree.proof_by_index(index).unwrap();
+                    let leaf = tree.leaf_hash_at(index, &data[index]);
+                    let replaced = before[index].iter().zip(after.iter()).enumerate().any(
+                        |(step, ((old, _), (new, _)))| {
+                            let padding = empty.contains(old) || (step == 0 && *old == leaf);
+                            padding && old != new
+                        },
+                    );
+                    after.len() != before[index].len() || replaced
+                })
+                .collect();
+            assert_eq!(
+                outcome.invalidated_indices.collect::<Vec<_>>(),
+                expected,
+                "{:?}, {} leaves",
+                padding,
+                n
+            );
+        }
+    }
+
+    // The duplicate leaf 4 was paired with is replaced by leaf 5
+    let mut tree = MerkleTree::new(create_test_data(5));
+    assert_eq!(tree.push(b"Test data 5".to_vec()).invalidated_indices, 4..5);
+    // Growing a level adds a step to every earlier proof
+    let mut tree = MerkleTree::new(create_test_data(4));
+    assert_eq!(tree.push(b"Test data 4".to_vec()).invalidated_indices, 0..4);
+    let mut tree = MerkleTree::new(create_test_data(6));
+    assert_eq!(tree.push(b"Test data 6".to_vec()).invalidated_indices, 4..6);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_canonical_json() {
+    use crate::{CanonicalJsonError, ProofBundle};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+
+    // Golden bytes pin the canonical form
+    let proof = MerkleProof::new(vec![(vec![0xab; 4], true), (vec![0x01, 0x02], false)]);
+    let canonical = proof.to_canonical_json();
+    assert_eq!(
+        canonical,
+        r#"{"steps":[{"is_left":true,"sibling":"abababab"},{"is_left":false,"sibling":"0102"}]}"#
+    );
+    let bundle = ProofBundle {
+        version: None,
+        params: TreeParams::from(HashScheme::DomainSeparated),
+        leaf_count: 5,
+        index: 4,
+        proof: proof.clone(),
+        unauthenticated_metadata: Some(b"note".to_vec()),
+    };
+    let canonical_bundle = bundle.to_canonical_json();
+    assert_eq!(
+        canonical_bundle,
+        concat!(
+            r#"{"index":4,"leaf_count":5,"params":{"algorithm":"Sha256","ordering":"Positional","#,
+            r#""padding":"DuplicateLast","positional":false,"scheme":"DomainSeparated"},"#,
+            r#""proof":{"steps":[{"is_left":true,"sibling":"abababab"},"#,
+            r#"{"is_left":false,"sibling":"0102"}]},"unauthenticated_metadata":"6e6f7465"}"#
+        )
+    );
+    assert_eq!(
+        MerkleProof::from_canonical_json_strict(&canonical).unwrap(),
+        proof
+    );
+    assert_eq!(
+        ProofBundle::from_canonical_json_strict(&canonical_bundle).unwrap(),
+        bundle
+    );
+
+    // Other encodings of the same value are accepted and re-canonicalized, except in
+    // strict mode
+    let loose = r#"{ "steps": [ {"sibling": "ABABABAB", "is_left": true},
+                               {"sibling": "0102", "is_left": false} ] }"#;
+    let parsed = MerkleProof::from_canonical_json(loose).unwrap();
+    assert_eq!(parsed, proof);
+    assert_eq!(parsed.to_canonical_json(), canonical);
+    assert!(matches!(
+        MerkleProof::from_canonical_json_strict(loose),
+        Err(CanonicalJsonError::NotCanonical)
+    ));
+    let reordered = canonical_bundle.replacen(
+        r#""index":4,"leaf_count":5"#,
+        r#""leaf_count":5,"index":4"#,
+        1,
+    );
+    assert_eq!(
+        ProofBundle::from_canonical_json(&reordered).unwrap(),
+        bundle
+    );
+    assert!(matches!(
+        ProofBundle::from_canonical_json_strict(&reordered),
+        Err(CanonicalJsonError::NotCanonical)
+    ));
+
+    // Floats, unknown members and invalid hex do not match the schema
+    for invalid in [
+        canonical_bundle.replace(r#""index":4"#, r#""index":4.0"#),
+        canonical.replace(r#""is_left":true,"#, r#""is_left":true,"extra":1,"#),
+        canonical.replace("0102", "01z2"),
+    ] {
+        assert!(matches!(
+            ProofBundle::from_canonical_json(&invalid)
+                .map(|_| ())
+                .or_else(|_| MerkleProof::from_canonical_json(&invalid).map(|_| ())),
+            Err(CanonicalJsonError::Json(_))
+        ));
+    }
+
+    // The signing input is the JWS one over the canonical payload
+    let input = String::from_utf8(proof.signing_input(r#"{"alg":"ES256"}"#)).unwrap();
+    let (header, payload) = input.split_once('.').unwrap();
+    assert_eq!(header, "eyJhbGciOiJFUzI1NiJ9");
+    assert_eq!(
+        URL_SAFE_NO_PAD.decode(payload).unwrap(),
+        canonical.as_bytes()
+    );
+    assert_eq!(
+        parsed.signing_input(r#"{"alg":"ES256"}"#),
+        proof.signing_input(r#"{"alg":"ES256"}"#)
+    );
+}
+
+#[test]
+fn test_two_level_file_tree() {
+    use crate::{FileTree, FileTreeConfig, MIN_RECOMMENDED_CHUNK_SIZE};
+
+    let bytes: Vec<u8> = (0..10_000u32).map(|i| (i * 31 % 251) as u8).collect();
+    let config = FileTreeConfig::new(64, 8);
+    let tree = FileTree::from_bytes(&bytes, config);
+    let root = tree.root_hash().unwrap();
+    assert_eq!(tree.chunk_count(), 157);
+    assert_eq!(tree.group_count(), 20);
+
+    // The manifest lists group roots only, and commits to the same root
+    let manifest = tree.manifest();
+    assert_eq!(manifest.group_roots.len(), 20);
+    assert_eq!(manifest.root_hash(), Some(root.clone()));
+
+    for (index, chunk) in bytes.chunks(64).enumerate() {
+        let proof = tree.prove_chunk(index).unwrap();
+        assert!(proof.inner.len() <= 3);
+        assert!(proof.outer.len() <= 5);
+        assert!(manifest.verify_chunk(index, chunk, &proof, &root));
+        // The proof is bound to the chunk's position and contents
+        assert!(!manifest.verify_chunk((index + 1) % 157, chunk, &proof, &root));
+        assert!(!manifest.verify_chunk(index, &chunk[1..], &proof, &root));
+    }
+    assert!(tree.prove_chunk(157).is_none());
+    let proof = tree.prove_chunk(8).unwrap();
+    assert!(!manifest.verify_chunk(9, &bytes[9 * 64..10 * 64], &proof, &root));
+
+    // Reading from a file gives the same tree
+    let path = std::env::temp_dir().join(format!("merkleproof-two-level-{}", std::process::id()));
+    std::fs::write(&path, &bytes).unwrap();
+    let from_file = MerkleTree::from_file_two_level(&path, config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(from_file.root_hash(), Some(root));
+
+    // Recommendations keep proofs within the budget and split the steps between levels
+    let config = FileTreeConfig::recommend(1 << 30, 340);
+    assert_eq!(config, FileTreeConfig::new(1 << 20, 32));
+    assert!(config.max_proof_size(1 << 30) <= 340);
+    let config = FileTreeConfig::recommend(10_000, 1_000);
+    assert_eq!(config.chunk_size, MIN_RECOMMENDED_CHUNK_SIZE);
+    assert!(config.max_proof_size(10_000) <= 1_000);
+    let config = FileTreeConfig::recommend(1 << 20, 0);
+    assert_eq!(config.chunk_size, 1 << 20);
+    assert_eq!(config.max_proof_size(1 << 20), 0);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&manifest).unwrap();
+        let decoded: crate::FileManifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, manifest);
+    }
+}
+
+#[test]
+fn test_verify_all_leaves_report() {
+    let data: Vec<Vec<u8>> = (0..40u8).map(|i| vec![i; 3]).collect();
+    let tree = MerkleTree::new(data.clone());
+    let forged = b"forged".to_vec();
+
+    // Every item is checked, in order, whatever fails before it
+    let mut items: Vec<(usize, &[u8])> = data.iter().map(|d| d.as_slice()).enumerate().collect();
+    items[3].1 = &forged;
+    items.insert(5, (40, &data[0]));
+    items.push((7, &data[8]));
+    let report = tree.verify_all_leaves(items.iter().copied());
+    assert_eq!(report.checks.len(), 42);
+    assert_eq!(
+        (report.ok, report.hash_mismatches, report.out_of_range),
+        (39, 2, 1)
+    );
+    assert!(!report.all_ok());
+    let failures: Vec<_> = report.failures().copied().collect();
+    assert_eq!(
+        failures,
+        vec![
+            LeafCheck {
+                index: 3,
+                outcome: LeafOutcome::HashMismatch
+            },
+            LeafCheck {
+                index: 40,
+                outcome: LeafOutcome::IndexOutOfRange
+            },
+            LeafCheck {
+                index: 7,
+                outcome: LeafOutcome::HashMismatch
+            },
+        ]
+    );
+
+    // Checking on several threads gives the same outcomes in the same order
+    for threads in [0, 1, 3, 8] {
+        let parallel = tree.verify_all_leaves_parallel(items.iter().copied(), threads);
+        assert_eq!(parallel.checks, report.checks);
+        assert_eq!(parallel.ok, report.ok);
+    }
+
+    let summary = report.to_string();
+    assert!(summary.starts_with("Checked 42 items in "));
+    assert!(summary.contains("39 ok, 2 hash mismatches, 1 out of range"));
+    assert!(summary.contains("index 40: index out of range"));
+    assert!(!summary.contains("more"));
+
+    // Long failure lists are cut short
+    let all_bad: Vec<(usize, &[u8])> = (0..15).map(|i| (i, forged.as_slice())).collect();
+    let report = tree.verify_all_leaves(all_bad.into_iter());
+    assert_eq!(report.hash_mismatches, 15);
+    assert!(report.to_string().contains("... and 5 more"));
+
+    // Positional and domain-separated trees hash leaves as they were built
+    let positional = MerkleTree::builder()
+        .positional(true)
+        .hash_scheme(HashScheme::DomainSeparated)
+        .build(data.clone());
+    let report = positional.verify_all_leaves(data.iter().map(|d| d.as_slice()).enumerate());
+    assert!(report.all_ok());
+    let swapped = positional.verify_all_leaves([(1, data[0].as_slice())].into_iter());
+    assert_eq!(swapped.hash_mismatches, 1);
+
+    // An empty tree has no leaves to match
+    let empty = MerkleTree::new(Vec::<Vec<u8>>::new());
+    assert_eq!(
+        empty
+            .verify_all_leaves([(0, &b""[..])].into_iter())
+            .out_of_range,
+        1
+    );
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&report).unwrap();
+        let decoded: crate::BulkReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, report);
+    }
+}
+
+#[test]
+fn test_subtree_labels() {
+    let mut tree = MerkleTree::new(create_test_data(5));
+    let root = tree.root_hash();
+    tree.label_subtree(2, 0, "ledger-shard").unwrap();
+    tree.label_subtree(2, 1, "payments-shard").unwrap();
+    tree.label_subtree(0, 3, "leaf three").unwrap();
+    assert_eq!(tree.root_hash(), root);
+
+    // Coordinates are checked, and a node carried up unpaired shares its child's label
+    assert_eq!(
+        tree.label_subtree(0, 5, "padding"),
+        Err(MerkleError::NodeOutOfRange { level: 0, index: 5 })
+    );
+    assert_eq!(
+        tree.label_subtree(4, 0, "above the root"),
+        Err(MerkleError::NodeOutOfRange { level: 4, index: 0 })
+    );
+    assert_eq!(tree.subtree_label(1, 2), Some("payments-shard"));
+    assert_eq!(tree.subtree_label(2, 1), Some("payments-shard"));
+    assert_eq!(tree.enclosing_label(0, 1), Some("ledger-shard"));
+    assert_eq!(tree.enclosing_label(0, 3), Some("leaf three"));
+    assert_eq!(tree.enclosing_label(0, 4), Some("payments-shard"));
+    assert_eq!(tree.enclosing_label(3, 0), None);
+
+    let drawing = tree.render_with(&TreePrintOptions::default());
+    assert!(drawing.contains("label=\"ledger-shard\""));
+    assert!(drawing.contains("label=\"payments-shard\""));
+    assert!(drawing.contains("label=\"leaf three\""));
+
+    // Mismatch reports name the subtree the divergence is inside
+    let mut data = create_test_data(5);
+    data[4] = b"tampered".to_vec();
+    let report = tree.explain_mismatch(&MerkleTree::new(data.clone()));
+    assert_eq!(report.divergence_label.as_deref(), Some("payments-shard"));
+    assert!(report
+        .to_string()
+        .contains("First divergence at level 0, index 4, inside \"payments-shard\" subtree"));
+    data[4] = create_test_data(5)[4].clone();
+    data[0] = b"tampered".to_vec();
+    let report = tree.explain_mismatch(&MerkleTree::new(data));
+    assert_eq!(report.divergence_label.as_deref(), Some("ledger-shard"));
+
+    // Labels name nodes by position, so they survive a rehash only if the shape does
+    let separated = tree.rehash_with(HashScheme::DomainSeparated).unwrap();
+    assert_eq!(separated.subtree_label(2, 1), Some("payments-shard"));
+    let carried = tree
+        .rehash_with(TreeParams {
+            padding: PaddingStrategy::CarryUp,
+            ..TreeParams::from(HashScheme::Plain)
+        })
+        .unwrap();
+    assert_eq!(carried.subtree_label(2, 1), None);
+    assert_eq!(carried.enclosing_label(0, 4), None);
+
+    #[cfg(feature = "serde")]
+    {
+        // Labels survive a round trip through the JSON structure
+        let structure = tree.to_json_structure();
+        assert_eq!(structure["right"]["label"], "payments-shard");
+        let rebuilt = MerkleTree::from_json_structure(&structure, HashScheme::Plain).unwrap();
+        assert_eq!(rebuilt.root_hash(), root);
+        assert_eq!(rebuilt.subtree_label(2, 0), Some("ledger-shard"));
+        assert_eq!(rebuilt.subtree_label(2, 1), Some("payments-shard"));
+        assert_eq!(rebuilt.subtree_label(0, 3), Some("leaf three"));
+
+        let mut bad = structure.clone();
+        bad["left"]["label"] = serde_json::json!(7);
+        assert!(matches!(
+            MerkleTree::from_json_structure(&bad, HashScheme::Plain),
+            Err(MerkleError::InvalidStructure { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_hash_chain_commitment_scheme() {
+    // Storage code proves and verifies without knowing which scheme it holds
+    fn check_all<C: CommitmentScheme>(commitment: &C, data: &[Vec<u8>]) -> Vec<C::Proof> {
+        let root = commitment.root().unwrap();
+        let proofs: Vec<_> = (0..data.len())
+            .map(|index| commitment.prove(index).unwrap())
+            .collect();
+        for (index, proof) in proofs.iter().enumerate() {
+            assert!(commitment.verify(&data[index], proof, &root));
+            assert!(!commitment.verify(&data[(index + 1) % data.len()], proof, &root));
+        }
+        assert!(commitment.prove(data.len()).is_none());
+        proofs
+    }
+
+    let data = create_test_data(6);
+    let tree = MerkleTree::new(data.clone());
+    check_all(&tree, &data);
+    let mut chain = HashChain::new(&data);
+    let proofs = check_all(&chain, &data);
+
+    // Chain proofs hold the predecessor's link and every later item's hash
+    assert_eq!(proofs[0].predecessor, None);
+    for (index, proof) in proofs.iter().enumerate() {
+        assert_eq!(proof.successors.len(), data.len() - index - 1);
+    }
+    let hasher = HashConfig::from(HashScheme::Plain);
+    let mut link = hasher.hash_leaf(&data[0]);
+    for item in &data[1..] {
+        link = hasher.hash_pair(&link, &hasher.hash_leaf(item));
+    }
+    assert_eq!(chain.root(), Some(link));
+    assert_eq!(chain.len(), 6);
+
+    // Order matters even under sorted pair ordering, and appending moves the root on
+    let sorted = HashConfig {
+        ordering: PairOrdering::Sorted,
+        ..HashConfig::from(HashScheme::DomainSeparated)
+    };
+    let forward = HashChain::with_hasher(&data, sorted);
+    let backward = HashChain::with_hasher(data.iter().rev(), sorted);
+    assert_ne!(forward.root(), backward.root());
+    check_all(&forward, &data);
+    let old_root = chain.root().unwrap();
+    let new_root = chain.push(b"next");
+    assert_ne!(new_root, old_root);
+    assert!(!chain.verify(&data[2], &proofs[2], &new_root));
+    assert!(chain.verify(&data[2], &chain.prove(2).unwrap(), &new_root));
+
+    let empty = HashChain::new(Vec::<Vec<u8>>::new());
+    assert!(empty.is_empty());
+    assert_eq!(empty.root(), None);
+    assert!(empty.prove(0).is_none());
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&proofs[2]).unwrap();
+        let decoded: crate::ChainProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, proofs[2]);
+    }
+}
+
+#[test]
+fn test_max_leaf_size() {
+    use crate::IngestError;
+    use std::io::{self, Read};
+
+    let data: Vec<Vec<u8>> = vec![b"a".to_vec(), vec![1; 8], vec![2; 9], vec![3; 20]];
+
+    // The first oversized leaf is reported with its size
+    let error = MerkleTree::builder()
+        .max_leaf_size(8)
+        .try_build(data.clone())
+        .unwrap_err();
+    assert_eq!(
+        error,
+        MerkleError::LeafTooLarge {
+            index: 2,
+            size: 9,
+            max: 8
+        }
+    );
+    assert_eq!(error.status_code(), 413);
+    assert_eq!(
+        error.to_string(),
+        "leaf 2 is 9 bytes, more than the limit of 8"
+    );
+    let error = MerkleTree::builder()
+        .max_leaf_size(8)
+        .try_build_encoded::<RawBytes>(data.clone())
+        .unwrap_err();
+    assert!(matches!(error, MerkleError::LeafTooLarge { index: 2, .. }));
+    let fits = MerkleTree::builder()
+        .max_leaf_size(20)
+        .try_build(data.clone())
+        .unwrap();
+    assert_eq!(
+        fits.root_hash(),
+        MerkleTree::builder().build(data.clone()).root_hash()
+    );
+
+    // Readers give the same tree as the data they yield, sorted or positional
+    for (positional, sort) in [(false, false), (false, true), (true, false)] {
+        let builder = || {
+            MerkleTree::builder()
+                .positional(positional)
+                .sort_leaves(sort)
+        };
+        let read = builder()
+            .try_build_from_readers(data.iter().map(|item| item.as_slice()))
+            .unwrap();
+        assert_eq!(read.root_hash(), builder().build(data.clone()).root_hash());
+        assert_eq!(read.get_leaf(0), None);
+    }
+
+    // An endless reader is stopped one byte past the limit
+    let readers: Vec<Box<dyn Read>> = vec![
+        Box::new(&b"fine"[..]),
+        Box::new(io::repeat(0)),
+        Box::new(io::repeat(1)),
+    ];
+    match MerkleTree::builder()
+        .max_leaf_size(1024)
+        .try_build_from_readers(readers)
+    {
+        Err(IngestError::Build(MerkleError::LeafTooLarge { index, size, max })) => {
+            assert_eq!((index, size, max), (1, 1025, 1024));
+        }
+        other => panic!("expected an oversized leaf, got {:?}", other.map(|_| ())),
+    }
+
+    // The largest limit reads every leaf to its end
+    let unlimited = MerkleTree::builder()
+        .max_leaf_size(u64::MAX)
+        .try_build_from_readers(data.iter().map(|item| item.as_slice()))
+        .unwrap();
+    assert_eq!(unlimited.root_hash(), fits.root_hash());
+
+    /// A reader that always fails
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk failure"))
+        }
+    }
+
+    let error = MerkleTree::builder()
+        .try_build_from_readers([Failing])
+        .unwrap_err();
+    assert!(matches!(error, IngestError::Io(_)));
+    assert_eq!(error.to_string(), "cannot read leaf: disk failure");
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_build_is_deterministic() {
+    let data: Vec<Vec<u8>> = (0..1037u32).map(|i| i.to_be_bytes().to_vec()).collect();
+    let builders: Vec<fn() -> crate::MerkleTreeBuilder> = vec![
+        MerkleTree::builder,
+        || MerkleTree::builder().positional(true),
+        || MerkleTree::builder().sort_leaves(true),
+        || MerkleTree::builder().padding(PaddingStrategy::CarryUp),
+        || MerkleTree::builder().padding(PaddingStrategy::Complete { depth: 12 }),
+    ];
+    let sample = [0, 1, 2, 511, 512, 1023, 1024, 1035, 1036];
+
+    for builder in builders {
+        let sequential = builder().build(data.clone());
+        let expected: Vec<Vec<u8>> = sample
+            .iter()
+            .map(|&index| sequential.proof_by_index(index).unwrap().to_bytes())
+            .collect();
+        // Vary the pool the way RAYON_NUM_THREADS varies the global one
+        for threads in [1, 2, 8] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let parallel = pool.install(|| builder().parallel(true).build(data.clone()));
+            assert_eq!(parallel.root_hash(), sequential.root_hash());
+            assert_eq!(parallel.levels(), sequential.levels());
+            assert_eq!(parallel.metrics(), sequential.metrics());
+            for (&index, expected) in sample.iter().zip(&expected) {
+                let proof = parallel.proof_by_index(index).unwrap();
+                assert_eq!(&proof.to_bytes(), expected, "{} threads", threads);
+            }
+        }
+    }
+
+    // Progress is reported in order
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    MerkleTree::builder()
+        .parallel(true)
+        .on_progress(move |event| recorded.lock().unwrap().push(*event))
+        .build(data[..5].to_vec());
+    let events = events.lock().unwrap();
+    for (index, event) in events[..5].iter().enumerate() {
+        assert_eq!(*event, ProgressEvent::LeafHashed { index, total: 5 });
+    }
+}
+
+#[test]
+fn test_push_with_proof() {
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 6 },
+    ];
+    let data = create_test_data(40);
+    for padding in paddings {
+        let mut tree = MerkleTree::builder().padding(padding).build(Vec::new());
+        assert_eq!(tree.latest_proof(), None);
+        // Crosses the power-of-two boundaries at 2, 4, 8, 16 and 32 leaves
+        for (index, item) in data.iter().enumerate() {
+            let (root, proof) = tree.push_with_proof(item.clone());
+            let leaf_count = index + 1;
+            assert_eq!(tree.root_hash(), Some(root.clone()));
+            assert!(tree.verify_against(item, &proof, &root, leaf_count));
+            assert!(!tree.verify_against(b"not a leaf", &proof, &root, leaf_count));
+            assert_eq!(
+                proof,
+                tree.proof_by_index(index).unwrap(),
+                "{:?}, {} leaves",
+                padding,
+                leaf_count
+            );
+            assert_eq!(tree.latest_proof(), Some(proof));
+        }
+    }
+
+    // The proof is for the last real leaf, not the duplicate padding it
+    let tree = MerkleTree::new(create_test_data(5));
+    let proof = tree.latest_proof().unwrap();
+    assert_eq!(proof, tree.proof_by_index(4).unwrap());
+    assert!(tree.verify(b"Test data 4", &proof));
+}
+
+#[test]
+fn test_deep_proofs_are_rejected_before_hashing() {
+    let tree = MerkleTree::new(create_test_data(8));
+    let root = tree.root_hash().unwrap();
+    let genuine = tree.proof_by_index(3).unwrap();
+    let deep: MerkleProof = (0..10_000)
+        .map(|i| (vec![i as u8; 32], i % 2 == 0))
+        .collect();
+
+    // Rejection hashes nothing, and hashing a step would allocate
+    let (valid, allocations) =
+        count_allocations(|| MerkleTree::verify_proof(b"Test data 3", &deep, &root));
+    assert!(!valid);
+    assert_eq!(allocations, 0);
+    let (result, allocations) = count_allocations(|| {
+        MerkleTree::verify_proof_with_limit(b"Test data 3", &deep, &root, MAX_PROOF_STEPS)
+    });
+    assert_eq!(
+        result,
+        Err(VerificationError::TooDeep {
+            steps: 10_000,
+            max: MAX_PROOF_STEPS
+        })
+    );
+    assert_eq!(allocations, 0);
+    assert!(!MerkleTree::verify_proof_with(
+        HashScheme::Plain,
+        b"Test data 3",
+        &deep,
+        &root,
+        None
+    ));
+    let (valid, allocations) = count_allocations(|| {
+        MerkleTree::verify_subtree_proof_with(HashScheme::Plain, &root, &deep, &root)
+    });
+    assert!(!valid);
+    assert_eq!(allocations, 0);
+
+    // The in-place verifier cannot see the length up front, so it stops at the first
+    // step past the cap
+    let pulled = std::cell::Cell::new(0);
+    let steps = deep.iter().map(|(hash, is_left)| {
+        pulled.set(pulled.get() + 1);
+        (&hash[..], *is_left)
+    });
+    let root_array: [u8; 32] = root.clone().try_into().unwrap();
+    assert!(!MerkleTree::verify_proof_in_place(
+        b"Test data 3",
+        steps,
+        &root_array
+    ));
+    assert_eq!(pulled.get(), MAX_PROOF_STEPS + 1);
+
+    // Within the limit proofs verify as usual, and the limit can be raised
+    assert_eq!(
+        MerkleTree::verify_proof_with_limit(b"Test data 3", &genuine, &root, 3),
+        Ok(true)
+    );
+    assert!(matches!(
+        MerkleTree::verify_proof_with_limit(b"Test data 3", &genuine, &root, 2),
+        Err(VerificationError::TooDeep { steps: 3, max: 2 })
+    ));
+    assert_eq!(
+        MerkleTree::verify_proof_with_limit(b"Test data 3", &deep, &root, 10_000),
+        Ok(false)
+    );
+
+    // Decoders stop at the same cap, hex and base64 before decoding anything
+    let too_deep = Err(ProofDecodeError::TooDeep {
+        max: MAX_PROOF_STEPS,
+    });
+    let bytes = deep.to_bytes();
+    assert_eq!(MerkleProof::from_bytes(&bytes), too_deep);
+    let hex = deep.to_hex();
+    let (decoded, allocations) = count_allocations(|| MerkleProof::from_hex(&hex));
+    assert_eq!((decoded, allocations), (too_deep.clone(), 0));
+    let base64 = deep.to_base64();
+    let (decoded, allocations) = count_allocations(|| MerkleProof::from_base64(&base64));
+    assert_eq!((decoded, allocations), (too_deep.clone(), 0));
+    assert_eq!(
+        ProofDecodeError::TooDeep { max: 64 }.to_string(),
+        "proof has more than the maximum of 64 steps"
+    );
+
+    // The longest accepted proof, with the largest siblings, still decodes
+    let longest: MerkleProof = (0..MAX_PROOF_STEPS).map(|_| (vec![7; 255], true)).collect();
+    let deeper: MerkleProof = (0..=MAX_PROOF_STEPS).map(|_| (vec![7; 1], true)).collect();
+    assert_eq!(
+        MerkleProof::from_bytes(&longest.to_bytes()),
+        Ok(longest.clone())
+    );
+    assert_eq!(
+        MerkleProof::from_hex(&longest.to_hex()),
+        Ok(longest.clone())
+    );
+    assert_eq!(MerkleProof::from_base64(&longest.to_base64()), Ok(longest));
+    assert_eq!(MerkleProof::from_base64(&deeper.to_base64()), too_deep);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_handle_proof_request() {
+    use crate::{LeafSelector, ProofRequest, ProofResponse};
+
+    let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    let tree = MerkleTree::builder().build(data.clone());
+
+    // The wire shapes are part of the API, so they are pinned byte for byte
+    let request: ProofRequest = serde_json::from_str(r#"{"leaf":{"ByData":"62"}}"#).unwrap();
+    assert_eq!(
+        request,
+        ProofRequest {
+            leaf: LeafSelector::ByData(b"b".to_vec()),
+            want_bundle: false
+        }
+    );
+    let response = tree.handle_proof_request(&request).unwrap();
+    let snapshot = concat!(
+        r#"{"root":"e9636069c740c9ff51625b01a0b040396d265a9b920cc6febdfa5ecc9f58ecce","#,
+        r#""leaf_index":1,"proof":[[[2,42,105,121,230,218,183,170,90,228,195,229,228,95,"#,
+        r#"126,151,113,18,167,230,53,147,130,13,190,193,236,115,138,36,249,60],true],"#,
+        r#"[[248,35,101,159,64,247,139,17,151,119,242,146,198,46,245,119,250,177,192,126,"#,
+        r#"188,157,247,17,90,114,110,233,252,148,231,251],false]],"params":{"#,
+        r#""algorithm":"Sha256","scheme":"DomainSeparated","ordering":"Positional","#,
+        r#""padding":"DuplicateLast","positional":false}}"#
+    );
+    assert_eq!(serde_json::to_string(&response).unwrap(), snapshot);
+    let decoded: ProofResponse = serde_json::from_str(snapshot).unwrap();
+    assert_eq!(decoded, response);
+    assert!(tree.verify(b"b", &response.proof));
+    let selectors = [
+        (LeafSelector::ByIndex(2), r#"{"ByIndex":2}"#),
+        (
+            LeafSelector::ByHash(vec![0xab, 0x01]),
+            r#"{"ByHash":"ab01"}"#,
+        ),
+        (LeafSelector::ByData(b"c".to_vec()), r#"{"ByData":"63"}"#),
+    ];
+    for (selector, json) in selectors {
+        assert_eq!(serde_json::to_string(&selector).unwrap(), json);
+    }
+
+    // Every selector finds the same leaf, and bundles are only sent when asked for
+    let leaf_hash = tree.node_hash_by_index(0, 2).unwrap();
+    for leaf in [
+        LeafSelector::ByIndex(2),
+        LeafSelector::ByHash(leaf_hash),
+        LeafSelector::ByData(b"c".to_vec()),
+    ] {
+        let request = ProofRequest {
+            leaf,
+            want_bundle: true,
+        };
+        let response = tree.handle_proof_request(&request).unwrap();
+        assert_eq!(response.leaf_index, 2);
+        assert_eq!(response.root, tree.root_hash().unwrap());
+        assert_eq!(response.params, tree.params());
+        assert_eq!(response.bundle, tree.generate_bundle(2));
+        assert_eq!(Some(response.proof), tree.proof_by_index(2));
+    }
+
+    let error = |leaf| {
+        tree.handle_proof_request(&ProofRequest {
+            leaf,
+            want_bundle: false,
+        })
+        .unwrap_err()
+    };
+    assert_eq!(
+        error(LeafSelector::ByIndex(3)),
+        MerkleError::IndexOutOfRange {
+            index: 3,
+            leaf_count: 3
+        }
+    );
+    assert_eq!(error(LeafSelector::ByIndex(u64::MAX)).status_code(), 404);
+    assert_eq!(
+        error(LeafSelector::ByData(b"d".to_vec())),
+        MerkleError::LeafNotFound
+    );
+    assert_eq!(error(LeafSelector::ByHash(vec![0; 32])).status_code(), 404);
+    assert_eq!(
+        error(LeafSelector::ByHash(vec![0; 20])),
+        MerkleError::InvalidHashLength {
+            expected: 32,
+            actual: 20
+        }
+    );
+}
+
+#[test]
+fn test_stress_against_reference() {
+    use crate::reference::{ProofSource, ReferenceTree};
+
+    /// Check that both trees have the same root and proofs, naming the seed if not
+    fn compare(tree: &MerkleTree, reference: &ReferenceTree, seed: u64, step: usize) {
+        let context = format!("seed {}, step {}, {:?}", seed, step, tree.params());
+        assert_eq!(tree.leaf_count(), reference.leaves().len(), "{}", context);
+        assert_eq!(ProofSource::root(tree), reference.root(), "{}", context);
+        for (index, (actual, expected)) in tree
+            .all_proofs()
+            .into_iter()
+            .zip(reference.all_proofs())
+            .enumerate()
+        {
+            assert_eq!(actual, expected, "{}, leaf {}", context, index);
+        }
+    }
+
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 0 },
+        PaddingStrategy::Complete { depth: 3 },
+        PaddingStrategy::Complete { depth: 6 },
+    ];
+    for seed in 1..=300u64 {
+        let mut rng = XorShift(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let params = TreeParams {
+            hasher: HashConfig::new(
+                HashAlgorithm::Sha256,
+                [HashScheme::Plain, HashScheme::DomainSeparated][(rng.next() % 2) as usize],
+                [PairOrdering::Positional, PairOrdering::Sorted][(rng.next() % 2) as usize],
+            ),
+            padding: paddings[(rng.next() % paddings.len() as u64) as usize],
+            positional: rng.next().is_multiple_of(2),
+        };
+        let capacity = match params.padding {
+            PaddingStrategy::Complete { depth } => 1 << depth,
+            _ => 40,
+        };
+        // Short leaves from a small alphabet repeat, so duplicate leaves are covered too
+        let leaf = |rng: &mut XorShift| {
+            let len = (rng.next() % 3) as usize;
+            rng.bytes(len)
+                .iter()
+                .map(|byte| byte % 4)
+                .collect::<Vec<u8>>()
+        };
+
+        let count = (rng.next() % (capacity as u64 + 1)) as usize;
+        let data: Vec<Vec<u8>> = (0..count).map(|_| leaf(&mut rng)).collect();
+        let build = |leaves: &[Vec<u8>]| {
+            MerkleTree::new(leaves.to_vec())
+                .rehash_with(params)
+                .unwrap()
+        };
+        let mut tree = build(&data);
+        let mut reference = ReferenceTree::new(params, data);
+        compare(&tree, &reference, seed, 0);
+
+        for step in 1..=25 {
+            let len = tree.leaf_count();
+            match rng.next() % 3 {
+                0 if len < capacity => {
+                    let data = leaf(&mut rng);
+                    tree.push(data.clone());
+                    reference.push(data);
+                }
+                1 if len > 0 => {
+                    let index = (rng.next() % len as u64) as usize;
+                    let data = leaf(&mut rng);
+                    tree.update_by_index(index, data.clone()).unwrap();
+                    reference.update(index, data);
+                }
+                // Trees cannot remove leaves, so the tree is rebuilt without the leaf
+                2 if len > 0 => {
+                    reference.remove((rng.next() % len as u64) as usize);
+                    tree = build(reference.leaves());
+                }
+                _ => continue,
+            }
+            compare(&tree, &reference, seed, step);
+        }
+    }
+}
+
+#[test]
+fn test_multiproof_encoding() {
+    use crate::{MultiProof, ProofEncoding};
+
+    let encodings = [
+        ProofEncoding::Binary,
+        ProofEncoding::Hex,
+        ProofEncoding::Base64,
+        #[cfg(feature = "serde")]
+        ProofEncoding::Json,
+    ];
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 7 },
+    ];
+    for padding in paddings {
+        let tree = MerkleTree::new(create_test_data(100))
+            .rehash_with(TreeParams {
+                padding,
+                ..TreeParams::from(HashScheme::DomainSeparated)
+            })
+            .unwrap();
+        for indices in [vec![0], vec![3, 4, 5], vec![7, 40, 99], (0..100).collect()] {
+            let proof = tree.generate_multiproof(&indices).unwrap();
+            let bytes = proof.to_bytes();
+            assert_eq!(MultiProof::from_bytes(&bytes), Ok(proof.clone()));
+            assert_eq!(MultiProof::from_hex(&proof.to_hex()), Ok(proof.clone()));
+            assert_eq!(
+                MultiProof::from_base64(&proof.to_base64()),
+                Ok(proof.clone())
+            );
+            for &encoding in &encodings {
+                let actual = match encoding {
+                    ProofEncoding::Binary => bytes.len(),
+                    ProofEncoding::Hex => proof.to_hex().len(),
+                    ProofEncoding::Base64 => proof.to_base64().len(),
+                    #[cfg(feature = "serde")]
+                    ProofEncoding::Json => serde_json::to_vec(&proof).unwrap().len(),
+                };
+                assert_eq!(proof.serialized_size(encoding), actual, "{:?}", encoding);
+            }
+        }
+    }
+
+    let bytes = MerkleTree::new(create_test_data(4))
+        .generate_multiproof(&[1, 2])
+        .unwrap()
+        .to_bytes();
+    let mut bad = bytes.clone();
+    bad[8] = 3;
+    assert_eq!(
+        MultiProof::from_bytes(&bad),
+        Err(ProofDecodeError::InvalidPadding { byte: 3 })
+    );
+    // A forged index count is caught before anything is allocated for it
+    let mut forged = bytes.clone();
+    forged[9..13].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(
+        MultiProof::from_bytes(&forged),
+        Err(ProofDecodeError::Truncated)
+    );
+    assert_eq!(
+        MultiProof::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(ProofDecodeError::Truncated)
+    );
+    assert_eq!(
+        MultiProof::from_bytes(&bytes[..5]),
+        Err(ProofDecodeError::Truncated)
+    );
+}
+
+#[test]
+fn test_generate_multiproof_bounded() {
+    use crate::ProofEncoding;
+
+    let data = create_test_data(100);
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 7 },
+    ];
+    let mut rng = XorShift(0xb0d6e7);
+    // 200 requested leaves, with repeats and some out of range
+    let requested: Vec<usize> = (0..200).map(|_| (rng.next() % 110) as usize).collect();
+
+    for padding in paddings {
+        let tree = MerkleTree::new(data.clone())
+            .rehash_with(TreeParams {
+                padding,
+                ..TreeParams::from(HashScheme::DomainSeparated)
+            })
+            .unwrap();
+        let root = tree.root_hash().unwrap();
+        let hasher = tree.params().hasher;
+        for encoding in [ProofEncoding::Binary, ProofEncoding::Base64] {
+            let mut previous = Vec::new();
+            for max_bytes in (0..4000).step_by(97) {
+                let (proof, included) =
+                    tree.generate_multiproof_bounded(&requested, max_bytes, encoding);
+                let encoded = match encoding {
+                    ProofEncoding::Binary => proof.to_bytes().len(),
+                    _ => proof.to_base64().len(),
+                };
+                assert!(encoded <= max_bytes || included.is_empty());
+                assert_eq!(proof.serialized_size(encoding), encoded);
+                assert_eq!(proof.indices, included);
+                assert!(included.iter().all(|index| requested.contains(index)));
+                // A bigger budget only ever adds leaves to the selection
+                assert!(previous.iter().all(|index| included.contains(index)));
+                if !included.is_empty() {
+                    let leaves: Vec<&Vec<u8>> = included.iter().map(|&i| &data[i]).collect();
+                    assert!(proof.verify(hasher, &leaves, &root));
+                    // The proof says nothing about a leaf it left out
+                    if let Some(&left_out) = requested
+                        .iter()
+                        .find(|&&i| i < data.len() && !included.contains(&i))
+                    {
+                        let mut extra = included.clone();
+                        extra.push(left_out);
+                        extra.sort_unstable();
+                        let leaves: Vec<&Vec<u8>> = extra.iter().map(|&i| &data[i]).collect();
+                        assert!(!proof.verify(hasher, &leaves, &root));
+                    }
+                }
+                previous = included;
+            }
+        }
+
+        // Everything fits a big enough budget, and nothing fits none
+        let mut all: Vec<usize> = requested.iter().copied().filter(|&i| i < 100).collect();
+        all.sort_unstable();
+        all.dedup();
+        let (proof, included) =
+            tree.generate_multiproof_bounded(&requested, usize::MAX, ProofEncoding::Binary);
+        assert_eq!(included, all);
+        assert_eq!(Some(proof), tree.generate_multiproof(&all));
+        let (proof, included) = tree.generate_multiproof_bounded(&requested, 0, ProofEncoding::Hex);
+        assert!(included.is_empty() && proof.indices.is_empty() && proof.hashes.is_empty());
+    }
+
+    // Once 2 is in, its sibling 3 is cheaper than anything else: one index more, one
+    // hash fewer
+    let tree = MerkleTree::builder().build(data[..16].to_vec());
+    let single = tree.generate_multiproof(&[2]).unwrap();
+    let budget = single.serialized_size(ProofEncoding::Binary) + 8;
+    let (_, included) =
+        tree.generate_multiproof_bounded(&[2, 6, 11, 3], budget, ProofEncoding::Binary);
+    assert_eq!(included, vec![2, 3]);
+}
+
+#[test]
+fn test_stale_root_detection() {
+    use crate::{ProofBundle, RootId, RootStamp, ROOT_ID_LEN};
+
+    let data = create_test_data(6);
+    let mut tree = MerkleTree::builder().build(data.clone());
+    // The client caches the verifier along with the fingerprint
+    let cached_id = tree.root_id().unwrap();
+    let cached = MerkleVerifier::for_tree(&tree)
+        .unwrap()
+        .with_root_id(cached_id);
+    assert_eq!(cached.root_id(), Some(cached_id));
+    assert_eq!(
+        cached_id,
+        RootId::new(&tree.root_hash().unwrap(), 6, tree.epoch())
+    );
+    assert_eq!(cached_id.to_string().len(), 2 * ROOT_ID_LEN);
+
+    // Stamping is opt-in and leaves the plain encodings alone
+    let plain = tree.proof_by_index(2).unwrap();
+    assert_eq!(plain.stamp(), None);
+    let proof = tree.generate_stamped_proof(2).unwrap();
+    assert_eq!(proof.root_id(), Some(cached_id));
+    assert_eq!(proof, plain);
+    assert_eq!(proof.to_bytes(), plain.to_bytes());
+    assert_eq!(plain.to_stamped_bytes(), None);
+
+    // Genuinely invalid proofs against the right root stay generic mismatches
+    assert_eq!(cached.verify(&data[2], &proof), Ok(()));
+    assert_eq!(
+        cached.verify(b"Not in the tree", &proof),
+        Err(VerificationError::RootMismatch)
+    );
+    let mut tampered = proof.clone();
+    tampered[0].0[0] ^= 1;
+    assert_eq!(
+        cached.verify(&data[2], &tampered),
+        Err(VerificationError::RootMismatch)
+    );
+
+    // Once the tree moves on, its stamped proofs report the cached root as stale,
+    // whether they fail on the root or on the shape
+    tree.push(b"Test data 6".to_vec());
+    let new_id = tree.root_id().unwrap();
+    assert_ne!(new_id, cached_id);
+    let stale = VerificationError::WrongRootEpoch {
+        proof_root_id: new_id,
+        verifier_root_id: cached_id,
+    };
+    let proof = tree.generate_stamped_proof(2).unwrap();
+    assert_eq!(cached.verify(&data[2], &proof), Err(stale.clone()));
+    let without_count = MerkleVerifier::new(cached.root()).with_root_id(cached_id);
+    assert_eq!(without_count.verify(&data[2], &proof), Err(stale.clone()));
+    let bundle = ProofBundle {
+        proof: proof.clone(),
+        ..tree.generate_bundle(2).unwrap()
+    };
+    assert_eq!(
+        without_count.verify_bundle(&data[2], &bundle),
+        Err(stale.clone())
+    );
+    assert!(stale.to_string().contains(&new_id.to_string()));
+
+    // A stamp the proof does not lead to is not believed, so forged or tampered proofs
+    // stay invalid rather than stale
+    assert_eq!(
+        cached.verify(b"Not in the tree", &proof),
+        Err(VerificationError::RootMismatch)
+    );
+    let mut tampered = proof.clone();
+    tampered[0].0[0] ^= 1;
+    assert_eq!(
+        cached.verify(&data[2], &tampered),
+        Err(VerificationError::RootMismatch)
+    );
+    let forged = proof.clone().with_stamp(RootStamp {
+        root: vec![7; 32],
+        leaf_count: 7,
+        epoch: 1,
+    });
+    assert_eq!(
+        cached.verify(&data[2], &forged),
+        Err(VerificationError::RootMismatch)
+    );
+
+    // Without a fingerprint on either side the failure stays generic
+    let unstamped = tree.proof_by_index(2).unwrap();
+    assert_eq!(
+        without_count.verify(&data[2], &unstamped),
+        Err(VerificationError::RootMismatch)
+    );
+    assert_eq!(
+        MerkleVerifier::new(cached.root()).verify(&data[2], &proof),
+        Err(VerificationError::RootMismatch)
+    );
+
+    // A new state with the same root still verifies, although its fingerprint differs
+    let current = MerkleVerifier::for_tree(&tree)
+        .unwrap()
+        .with_root_id(new_id);
+    tree.update_by_index(0, data[0].clone()).unwrap();
+    assert_ne!(tree.root_id(), Some(new_id));
+    let proof = tree.generate_stamped_proof(0).unwrap();
+    assert_eq!(current.verify(&data[0], &proof), Ok(()));
+
+    // The stamped form is versioned and round trips the stamp
+    let bytes = proof.to_stamped_bytes().unwrap();
+    assert_eq!(bytes[0], 1);
+    assert_eq!(bytes.len(), 2 + 32 + 16 + proof.to_bytes().len());
+    let decoded = MerkleProof::from_stamped_bytes(&bytes).unwrap();
+    assert_eq!(decoded.stamp(), proof.stamp());
+    assert_eq!(decoded.root_id(), tree.root_id());
+    assert_eq!(decoded, proof);
+    assert_eq!(
+        MerkleProof::from_stamped_bytes(&[[2].as_slice(), &bytes[1..]].concat()),
+        Err(ProofDecodeError::UnsupportedVersion { version: 2 })
+    );
+    assert_eq!(
+        MerkleProof::from_stamped_bytes(&bytes[..40]),
+        Err(ProofDecodeError::Truncated)
+    );
+    assert_eq!(
+        MerkleProof::from_stamped_bytes(&bytes[..bytes.len() - 1]),
+        Err(ProofDecodeError::Truncated)
+    );
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&current).unwrap();
+        assert!(json.contains(&format!("\"root_id\":\"{}\"", new_id)));
+        assert_eq!(
+            serde_json::from_str::<MerkleVerifier>(&json).unwrap(),
+            current
+        );
+        // The JSON form of a proof is its steps alone
+        assert_eq!(
+            serde_json::to_string(&proof).unwrap(),
+            serde_json::to_string(&proof.to_vec()).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_audit_sample() {
+    let data = create_test_data(50);
+    let params = [
+        TreeParams::from(HashScheme::DomainSeparated),
+        TreeParams {
+            padding: PaddingStrategy::CarryUp,
+            positional: true,
+            ..TreeParams::from(HashScheme::DomainSeparated)
+        },
+        TreeParams {
+            padding: PaddingStrategy::Complete { depth: 6 },
+            positional: true,
+            ..TreeParams::from(HashConfig {
+                ordering: PairOrdering::Sorted,
+                ..HashConfig::from(HashScheme::Plain)
+            })
+        },
+    ];
+    for params in params {
+        let tree = MerkleTree::new(data.clone()).rehash_with(params).unwrap();
+        let root = tree.root_hash().unwrap();
+        for k in [0, 1, 7, 50, 80] {
+            let sample = tree.audit_sample(k).unwrap();
+            assert_eq!(sample.len(), k.min(50));
+            let indices: Vec<usize> = sample.iter().map(|(index, _, _)| *index).collect();
+            assert_eq!(indices, crate::audit_sample_indices(&root, 50, k));
+            assert_eq!(
+                indices
+                    .iter()
+                    .collect::<std::collections::HashSet<_>>()
+                    .len(),
+                indices.len()
+            );
+            assert_eq!(
+                MerkleTree::verify_audit_sample(params, &root, 50, k, &sample),
+                Ok(())
+            );
+        }
+
+        let sample = tree.audit_sample(7).unwrap();
+        let check = |sample: &[(usize, Vec<u8>, MerkleProof)]| {
+            MerkleTree::verify_audit_sample(params, &root, 50, 7, sample)
+        };
+        assert_eq!(
+            check(&sample[..6]),
+            Err(AuditError::SampleCountMismatch {
+                expected: 7,
+                actual: 6
+            })
+        );
+        // The prover cannot reorder or swap in leaves of its choosing
+        let mut swapped = sample.clone();
+        swapped.swap(0, 1);
+        assert_eq!(
+            check(&swapped),
+            Err(AuditError::SampleIndexMismatch {
+                position: 0,
+                expected: sample[0].0,
+                actual: sample[1].0
+            })
+        );
+        let chosen = (0..50).find(|i| sample.iter().all(|s| s.0 != *i)).unwrap();
+        let mut cherry_picked = sample.clone();
+        cherry_picked[3] = (
+            chosen,
+            data[chosen].clone(),
+            tree.proof_by_index(chosen).unwrap(),
+        );
+        assert!(matches!(
+            check(&cherry_picked),
+            Err(AuditError::SampleIndexMismatch { position: 3, .. })
+        ));
+        let mut forged = sample.clone();
+        forged[2].1 = b"forged".to_vec();
+        assert_eq!(
+            check(&forged),
+            Err(AuditError::SampleProofInvalid {
+                index: sample[2].0,
+                error: VerificationError::RootMismatch
+            })
+        );
+        // A proof of another leaf, relabelled, does not pass for the chosen one
+        let mut relabelled = sample.clone();
+        relabelled[4].1 = data[chosen].clone();
+        relabelled[4].2 = tree.proof_by_index(chosen).unwrap();
+        assert!(matches!(
+            check(&relabelled),
+            Err(AuditError::SampleProofInvalid { .. })
+        ));
+    }
+
+    // Without positional leaves, sorted pairs cannot bind a proof to the chosen index
+    let unbound = TreeParams::from(HashConfig {
+        ordering: PairOrdering::Sorted,
+        ..HashConfig::from(HashScheme::Plain)
+    });
+    let tree = MerkleTree::new(data.clone()).rehash_with(unbound).unwrap();
+    assert_eq!(tree.audit_sample(7), Err(AuditError::SampleIndexUnbound));
+    assert_eq!(
+        MerkleTree::verify_audit_sample(unbound, &tree.root_hash().unwrap(), 50, 0, &[]),
+        Err(AuditError::SampleIndexUnbound)
+    );
+
+    // A different root chooses different leaves, and an empty tree has nothing to reveal
+    let other = MerkleTree::new(create_test_data(51))
+        .audit_sample(7)
+        .unwrap();
+    let ours = MerkleTree::new(data.clone()).audit_sample(7).unwrap();
+    assert_ne!(
+        other.iter().map(|s| s.0).collect::<Vec<_>>(),
+        ours.iter().map(|s| s.0).collect::<Vec<_>>()
+    );
+    assert_eq!(MerkleTree::new(Vec::new()).audit_sample(3), Ok(Vec::new()));
+
+    // Leaves whose data was cleared cannot be revealed
+    let mut cleared = MerkleTree::new(data.clone());
+    cleared.clear_leaf_data();
+    let first = ours[0].0;
+    assert_eq!(
+        cleared.audit_sample(7),
+        Err(AuditError::SampleDataUnavailable { index: first })
+    );
+    assert_eq!(
+        MerkleTree::verify_audit_sample(HashScheme::Plain, &[0; 32], 0, 3, &[]),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_audit_sample_indices_are_deterministic_and_unbiased() {
+    use crate::audit_sample_indices;
+    use crate::sample::{hmac_sha256, unbiased_index};
+
+    // RFC 4231 test cases 2 and 6 (a key longer than a block is hashed first)
+    assert_eq!(
+        hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert_eq!(
+        hex::encode(hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First"
+        )),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+
+    // Known answer, so every platform and port derives the same leaves
+    let root = Sha256::digest(b"audit root");
+    assert_eq!(audit_sample_indices(&root, 1000, 8), KNOWN_AUDIT_SAMPLE);
+
+    // Only the incomplete last block of n values is rejected
+    assert_eq!(unbiased_index(u64::MAX, 1), Some(0));
+    assert_eq!(unbiased_index(u64::MAX, 1 << 40), Some((1 << 40) - 1));
+    assert_eq!(unbiased_index(u64::MAX - 1, 3), Some((u64::MAX - 1) % 3));
+    assert_eq!(unbiased_index(u64::MAX, 3), None);
+    let n = (1 << 63) + 1;
+    assert_eq!(unbiased_index(n - 1, n), Some(n - 1));
+    assert_eq!(unbiased_index(n, n), None);
+    assert_eq!(unbiased_index(u64::MAX, n), None);
+
+    // With n = 3 * 2^62 a quarter of all draws are rejected. Reducing them modulo n
+    // instead would put half of all indices in the lowest third of the range.
+    #[cfg(target_pointer_width = "64")]
+    {
+        let n = 3usize << 62;
+        let indices = audit_sample_indices(&root, n, 3000);
+        assert!(indices.iter().all(|&index| index < n));
+        let low = indices.iter().filter(|&&index| index < 1 << 62).count();
+        assert!(
+            (900..1100).contains(&low),
+            "{} of 3000 in the lowest third",
+            low
+        );
+    }
+
+    // Sampling every leaf of a small tree terminates and covers each leaf once
+    let mut all = audit_sample_indices(&root, 37, 100);
+    all.sort_unstable();
+    assert_eq!(all, (0..37).collect::<Vec<_>>());
+    assert!(audit_sample_indices(&root, 0, 5).is_empty());
+}
+
+/// The indices `audit_sample_indices(SHA-256("audit root"), 1000, 8)` must return
+const KNOWN_AUDIT_SAMPLE: [usize; 8] = [435, 490, 531, 469, 440, 84, 5, 831];
+
+#[test]
+fn test_consistency_proofs_between_sizes() {
+    use crate::ConsistencyProof;
+
+    let data = create_test_data(20);
+    let carry_up = |count: usize| {
+        MerkleTree::builder()
+            .padding(PaddingStrategy::CarryUp)
+            .build(data[..count].to_vec())
+    };
+    for new_size in 1..=data.len() {
+        let new_tree = carry_up(new_size);
+        let hasher = new_tree.params().hasher;
+        let new_root = new_tree.root_hash().unwrap();
+        for old_size in 1..=new_size {
+            let old_root = carry_up(old_size).root_hash().unwrap();
+            let proof = new_tree.generate_consistency_proof(old_size).unwrap();
+            assert!(
+                proof.verify(hasher, old_size, new_size, &old_root, &new_root),
+                "{} to {} leaves",
+                old_size,
+                new_size
+            );
+            if old_size == new_size {
+                assert!(proof.hashes.is_empty());
+            }
+
+            // Any changed hash, a wrong size or a different old tree is caught
+            for i in 0..proof.hashes.len() {
+                let mut tampered = proof.clone();
+                tampered.hashes[i][0] ^= 1;
+                assert!(!tampered.verify(hasher, old_size, new_size, &old_root, &new_root));
+            }
+            if old_size > 1 {
+                let other_root = carry_up(old_size - 1).root_hash().unwrap();
+                assert!(!proof.verify(hasher, old_size, new_size, &other_root, &new_root));
+            }
+            if old_size < new_size {
+                assert!(!proof.verify(hasher, old_size, new_size, &new_root, &old_root));
+            }
+        }
+    }
+
+    let tree = carry_up(7);
+    assert!(matches!(
+        tree.generate_consistency_proof(0),
+        Err(MerkleError::ConsistencyUnavailable { .. })
+    ));
+    assert!(matches!(
+        tree.generate_consistency_proof(8),
+        Err(MerkleError::ConsistencyUnavailable { .. })
+    ));
+    assert!(matches!(
+        MerkleTree::new(data.clone()).generate_consistency_proof(3),
+        Err(MerkleError::ConsistencyUnavailable { .. })
+    ));
+    let root = tree.root_hash().unwrap();
+    assert!(!ConsistencyProof::default().verify(tree.params().hasher, 0, 7, &root, &root));
+}
+
+#[test]
+fn test_upgrade_inclusion_proof_after_growth() {
+    let data = create_test_data(33);
+    let carry_up = |count: usize| {
+        MerkleTree::builder()
+            .padding(PaddingStrategy::CarryUp)
+            .build(data[..count].to_vec())
+    };
+    let trees: Vec<MerkleTree> = (0..=data.len()).map(carry_up).collect();
+    for new_size in 1..=data.len() {
+        let new_tree = &trees[new_size];
+        let hasher = new_tree.params().hasher;
+        let new_root = new_tree.root_hash().unwrap();
+        for (old_size, old_tree) in trees.iter().enumerate().take(new_size + 1).skip(1) {
+            let consistency = new_tree.generate_consistency_proof(old_size).unwrap();
+            for (index, item) in data[..old_size].iter().enumerate() {
+                let old_proof = old_tree.proof_by_index(index).unwrap();
+                let upgraded = old_proof
+                    .upgrade(hasher, &consistency, old_size, new_size)
+                    .unwrap();
+                assert_eq!(
+                    upgraded,
+                    new_tree.proof_by_index(index).unwrap(),
+                    "leaf {} from {} to {} leaves",
+                    index,
+                    old_size,
+                    new_size
+                );
+                assert!(upgraded.root_id().is_none());
+                assert!(MerkleTree::verify_proof_with(
+                    hasher, item, &upgraded, &new_root, None
+                ));
+            }
+        }
+    }
+
+    // Proofs that do not fit the sizes, or that disagree, are refused rather than guessed at
+    let unavailable = |result: Result<MerkleProof, MerkleError>| {
+        matches!(result, Err(MerkleError::ConsistencyUnavailable { .. }))
+    };
+    let hasher = trees[13].params().hasher;
+    let consistency = trees[13].generate_consistency_proof(6).unwrap();
+    let proof = trees[6].proof_by_index(4).unwrap();
+    assert!(unavailable(proof.upgrade(hasher, &consistency, 6, 5)));
+    assert!(unavailable(proof.upgrade(hasher, &consistency, 0, 13)));
+    assert!(unavailable(proof.upgrade(hasher, &consistency, 6, 7)));
+    assert!(unavailable(trees[7].proof_by_index(4).unwrap().upgrade(
+        hasher,
+        &consistency,
+        6,
+        13
+    )));
+    // Leaf 4's second sibling in a 6-leaf tree is the root of leaves 0..4, which the
+    // consistency proof also reveals
+    let mut tampered = consistency.clone();
+    let shared = tampered
+        .hashes
+        .iter()
+        .position(|hash| *hash == proof[1].0)
+        .unwrap();
+    tampered.hashes[shared][0] ^= 1;
+    assert!(unavailable(proof.upgrade(hasher, &tampered, 6, 13)));
+
+    let sorted = HashConfig {
+        ordering: PairOrdering::Sorted,
+        ..hasher
+    };
+    assert!(unavailable(proof.upgrade(sorted, &consistency, 6, 13)));
+}
+
+#[test]
+fn test_build_dual_matches_separate_builds() {
+    use std::sync::Arc;
+
+    let mut data = create_test_data(11);
+    data.reverse();
+    let configs = [
+        MerkleTree::builder,
+        || MerkleTree::builder().hash_scheme(HashScheme::Plain),
+        || MerkleTree::builder().padding(PaddingStrategy::CarryUp),
+        || MerkleTree::builder().positional(true),
+        || MerkleTree::builder().sort_leaves(true),
+        || MerkleTree::builder().sort_leaves(true).positional(true),
+    ];
+    for builder in configs {
+        let (sha256, sha512) = builder().build_dual(HashAlgorithm::Sha512, data.clone());
+        let expected_256 = builder().build(data.clone());
+        let expected_512 = builder()
+            .hash_algorithm(HashAlgorithm::Sha512)
+            .build(data.clone());
+        assert_eq!(sha256.root_hash(), expected_256.root_hash());
+        assert_eq!(sha512.root_hash(), expected_512.root_hash());
+        assert_eq!(sha256.params(), expected_256.params());
+        assert_eq!(sha512.params(), expected_512.params());
+        for index in 0..data.len() {
+            assert_eq!(
+                sha256.proof_by_index(index),
+                expected_256.proof_by_index(index)
+            );
+            assert_eq!(
+                sha512.proof_by_index(index),
+                expected_512.proof_by_index(index)
+            );
+            assert_eq!(
+                sha512.original_index(index),
+                expected_512.original_index(index)
+            );
+        }
+        assert!(sha512.verify(&data[3], &sha512.generate_proof(&data[3]).unwrap()));
+    }
+
+    // Each leaf is hashed once per algorithm, and both trees point at one copy of it
+    let (first, second) = MerkleTree::builder().build_dual(HashAlgorithm::Sha512, data.clone());
+    assert_eq!(first.metrics().leaf_hashes, data.len() as u64);
+    assert_eq!(second.metrics().leaf_hashes, data.len() as u64);
+    for (index, item) in data.iter().enumerate() {
+        let shared = first.shared_leaf(index).unwrap();
+        assert_eq!(&shared[..], &item[..]);
+        assert!(Arc::ptr_eq(&shared, &second.shared_leaf(index).unwrap()));
+    }
+
+    assert!(matches!(
+        MerkleTree::builder()
+            .reject_duplicates(true)
+            .try_build_dual(HashAlgorithm::Sha512, vec![b"a".to_vec(), b"a".to_vec()]),
+        Err(MerkleError::DuplicateLeaf { .. })
+    ));
+    let (empty, _) = MerkleTree::builder().build_dual(HashAlgorithm::Sha512, Vec::new());
+    assert_eq!(empty.root_hash(), None);
+}
+
+#[test]
+fn test_operation_log_replays_mutations() {
+    use crate::{Operation, ReplayError};
+
+    let data = create_test_data(5);
+    let mut tree = MerkleTree::builder()
+        .operation_log(true)
+        .build(data.clone());
+    let initial_root = tree.root_hash().unwrap();
+    let mut roots = vec![initial_root.clone()];
+    tree.push(b"pushed 5".to_vec());
+    roots.push(tree.root_hash().unwrap());
+    tree.update_by_index(2, b"updated 2".to_vec()).unwrap();
+    roots.push(tree.root_hash().unwrap());
+    tree.push(b"pushed 6".to_vec());
+    roots.push(tree.root_hash().unwrap());
+    tree.update_by_index(6, b"updated 6".to_vec()).unwrap();
+    roots.push(tree.root_hash().unwrap());
+    assert!(tree.update_by_index(9, b"out of range".to_vec()).is_err());
+
+    let log = tree.operation_log().unwrap().clone();
+    assert_eq!(log.initial_root(), Some(&initial_root[..]));
+    assert_eq!(log.initial_leaf_count(), 5);
+    assert_eq!(log.params(), tree.params());
+    assert_eq!(
+        log.entries()
+            .iter()
+            .map(|entry| (entry.operation, entry.index))
+            .collect::<Vec<_>>(),
+        [
+            (Operation::Push, 5),
+            (Operation::Update, 2),
+            (Operation::Push, 6),
+            (Operation::Update, 6)
+        ]
+    );
+    for (entry, pair) in log.entries().iter().zip(roots.windows(2)) {
+        assert_eq!(entry.root_before.as_ref(), Some(&pair[0]));
+        assert_eq!(entry.root_after, pair[1]);
+    }
+    assert_eq!(
+        log.entries()[1].leaf_hash,
+        tree.leaf_hash_at(2, b"updated 2")
+    );
+    assert_eq!(log.final_root(), tree.root_hash_ref());
+    assert!(MerkleTree::builder()
+        .build(data.clone())
+        .operation_log()
+        .is_none());
+
+    // Replaying rebuilds the final tree, which keeps logging
+    let mut replayed = MerkleTree::replay(data.clone(), &log).unwrap();
+    assert_eq!(replayed.root_hash(), tree.root_hash());
+    assert_eq!(replayed.leaf_count(), 7);
+    assert_eq!(replayed.get_leaf(0), Some(&data[0][..]));
+    assert_eq!(replayed.get_leaf(5), None);
+    assert_eq!(replayed.proof_by_index(4), tree.proof_by_index(4));
+    replayed.push(b"pushed 7".to_vec());
+    tree.push(b"pushed 7".to_vec());
+    assert_eq!(replayed.operation_log(), tree.operation_log());
+
+    // The first divergence is reported
+    let mut wrong_leaves = data.clone();
+    wrong_leaves[0] = b"other".to_vec();
+    assert!(matches!(
+        MerkleTree::replay(wrong_leaves, &log),
+        Err(ReplayError::InitialRootMismatch { .. })
+    ));
+    let mut forged = log.clone();
+    forged.entries[2].leaf_hash = tree.leaf_hash_at(6, b"forged");
+    assert!(matches!(
+        MerkleTree::replay(data.clone(), &forged),
+        Err(ReplayError::RootAfterMismatch { entry: 2, expected, actual: Some(actual) })
+            if expected == roots[3] && actual != roots[3]
+    ));
+    let mut skipped = log.clone();
+    skipped.entries.remove(1);
+    assert!(matches!(
+        MerkleTree::replay(data.clone(), &skipped),
+        Err(ReplayError::RootBeforeMismatch { entry: 1, .. })
+    ));
+    let mut misplaced = log.clone();
+    misplaced.entries[0].index = 3;
+    assert!(matches!(
+        MerkleTree::replay(data.clone(), &misplaced),
+        Err(ReplayError::InvalidEntry {
+            entry: 0,
+            error: MerkleError::IndexOutOfRange { .. }
+        })
+    ));
+
+    // A log started on an empty tree begins without a root
+    let mut empty = MerkleTree::builder()
+        .operation_log(true)
+        .empty_root(EmptyRootPolicy::HashOfEmpty)
+        .build(Vec::new());
+    empty.push(b"first".to_vec());
+    let log = empty.operation_log().unwrap();
+    assert_eq!(log.initial_root(), None);
+    assert_eq!(log.entries()[0].root_before, None);
+    assert_eq!(
+        MerkleTree::replay(Vec::new(), log).unwrap().root_hash(),
+        empty.root_hash()
+    );
+    assert_eq!(log.empty_root(), EmptyRootPolicy::HashOfEmpty);
+
+    // A sorted tree replays from the leaves it was built with, in their original order
+    let mut shuffled = data.clone();
+    shuffled.reverse();
+    let mut sorted = MerkleTree::builder()
+        .sort_leaves(true)
+        .operation_log(true)
+        .build(shuffled.clone());
+    sorted.push(b"pushed 5".to_vec());
+    let log = sorted.operation_log().unwrap();
+    assert!(log.sort_leaves());
+    let replayed = MerkleTree::replay(shuffled, log).unwrap();
+    assert_eq!(replayed.root_hash(), sorted.root_hash());
+    assert_eq!(replayed.original_index(0), sorted.original_index(0));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_operation_log_serde_roundtrip() {
+    let mut tree = MerkleTree::builder()
+        .operation_log(true)
+        .build(create_test_data(3));
+    tree.push(b"pushed".to_vec());
+    tree.update_by_index(0, b"updated".to_vec()).unwrap();
+    let log = tree.operation_log().unwrap();
+
+    let json = serde_json::to_value(log).unwrap();
+    assert_eq!(json["entries"][0]["operation"], "Push");
+    assert_eq!(
+        json["entries"][1]["root_after"],
+        hex::encode(tree.root_hash().unwrap())
+    );
+    let decoded: crate::OperationLog = serde_json::from_value(json).unwrap();
+    assert_eq!(&decoded, log);
+    assert_eq!(
+        MerkleTree::replay(create_test_data(3), &decoded)
+            .unwrap()
+            .root_hash(),
+        tree.root_hash()
+    );
+}
+
+#[test]
+fn test_root_after_update_and_append_match_mutations() {
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 5 },
+    ];
+    for padding in paddings {
+        for positional in [false, true] {
+            for count in 0..=17 {
+                let tree = MerkleTree::builder()
+                    .padding(padding)
+                    .positional(positional)
+                    .build(create_test_data(count));
+                for index in 0..count {
+                    let mut updated = tree.clone();
+                    updated
+                        .update_by_index(index, b"new data".to_vec())
+                        .unwrap();
+                    assert_eq!(
+                        tree.root_after_update(index, b"new data").ok(),
+                        updated.root_hash(),
+                        "{:?} leaf {} of {}",
+                        padding,
+                        index,
+                        count
+                    );
+                }
+                assert_eq!(
+                    tree.root_after_update(count, b"new data"),
+                    Err(MerkleError::IndexOutOfRange {
+                        index: count,
+                        leaf_count: count
+                    })
+                );
+
+                let mut appended = tree.clone();
+                appended.push(b"new data".to_vec());
+                assert_eq!(
+                    Some(tree.root_after_append(b"new data")),
+                    appended.root_hash(),
+                    "{:?} append to {}",
+                    padding,
+                    count
+                );
+            }
+        }
+    }
+
+    // The tree is left untouched, and queries can run from several threads at once
+    let tree = MerkleTree::builder().build(create_test_data(9));
+    let root = tree.root_hash();
+    let (update, append) = std::thread::scope(|scope| {
+        let update = scope.spawn(|| tree.root_after_update(8, b"x").unwrap());
+        let append = scope.spawn(|| tree.root_after_append(b"y"));
+        (update.join().unwrap(), append.join().unwrap())
+    });
+    assert_eq!(tree.root_hash(), root);
+    assert_ne!(Some(update), root);
+    assert_ne!(Some(append), root);
+    assert_eq!(tree.leaf_count(), 9);
+}
+
+#[test]
+fn test_dedup_storage_shares_identical_payloads() {
+    let values: Vec<Vec<u8>> = (0..100)
+        .map(|value| format!("payload value {:03}", value).into_bytes())
+        .collect();
+    let data: Vec<Vec<u8>> = (0..100_000)
+        .map(|index| values[index * 7 % 100].clone())
+        .collect();
+    let tree = MerkleTree::builder()
+        .dedup_storage(true)
+        .build(data.clone());
+    let plain = MerkleTree::builder().build(data.clone());
+    assert_eq!(tree.root_hash(), plain.root_hash());
+    assert_eq!(tree.proof_by_index(12_345), plain.proof_by_index(12_345));
+
+    let stats = tree.storage_stats();
+    assert_eq!(stats.pooled_payloads, 100);
+    assert_eq!(stats.logical_bytes, 100_000 * 17);
+    assert_eq!(stats.physical_bytes, 100 * 17);
+    assert_eq!(plain.storage_stats().physical_bytes, 100_000 * 17);
+    assert_eq!(plain.storage_stats().pooled_payloads, 0);
+    for index in [0, 1, 99_999] {
+        assert_eq!(tree.get_leaf(index), Some(&data[index][..]));
+    }
+    assert_eq!(
+        tree.get_leaf(3).unwrap().as_ptr(),
+        tree.get_leaf(103).unwrap().as_ptr()
+    );
+
+    // The pool follows mutations, freeing a payload with the last leaf referencing it
+    let values = [b"a".to_vec(), b"b".to_vec(), b"a".to_vec(), b"c".to_vec()];
+    let mut tree = MerkleTree::builder()
+        .dedup_storage(true)
+        .positional(true)
+        .build(values.to_vec());
+    assert_eq!(tree.storage_stats().pooled_payloads, 3);
+    tree.update_by_index(1, b"a".to_vec()).unwrap();
+    assert_eq!(tree.storage_stats().pooled_payloads, 2);
+    tree.update_by_index(0, b"d".to_vec()).unwrap();
+    assert_eq!(tree.storage_stats().pooled_payloads, 3);
+    tree.push(b"c".to_vec());
+    assert_eq!(
+        tree.storage_stats(),
+        crate::StorageStats {
+            logical_bytes: 5,
+            physical_bytes: 3,
+            pooled_payloads: 3
+        }
+    );
+    assert_eq!(tree.get_leaf(1), Some(&b"a"[..]));
+    assert_eq!(tree.get_leaf(4), Some(&b"c"[..]));
+    let expected = MerkleTree::builder().positional(true).build(vec![
+        b"d".to_vec(),
+        b"a".to_vec(),
+        b"a".to_vec(),
+        b"c".to_vec(),
+        b"c".to_vec(),
+    ]);
+    assert_eq!(tree.root_hash(), expected.root_hash());
+
+    // A clone shares the payloads but keeps its own counts
+    let mut clone = tree.clone();
+    clone.update_by_index(0, b"e".to_vec()).unwrap();
+    assert_eq!(tree.get_leaf(0), Some(&b"d"[..]));
+    assert_eq!(tree.storage_stats().pooled_payloads, 3);
+    clone.clear_leaf_data();
+    assert_eq!(clone.storage_stats(), crate::StorageStats::default());
+}
+
+#[test]
+fn test_leaf_bundles_release_data_only_once_verified() {
+    use crate::{LeafBundle, VerifiedLeaf};
+
+    let data = create_test_data(7);
+    let tree = MerkleTree::builder().build(data.clone());
+    let verifier = MerkleVerifier::for_tree(&tree).unwrap();
+    let bundle = tree.generate_leaf_bundle(3).unwrap();
+    assert_eq!(bundle.bundle(), &tree.generate_bundle(3).unwrap());
+    let leaf: VerifiedLeaf = bundle.clone().into_verified(&verifier).unwrap();
+    assert_eq!(leaf.data(), &data[3][..]);
+    assert_eq!(leaf.index(), Some(3));
+    assert_eq!(leaf.root(), &tree.root_hash().unwrap()[..]);
+    assert_eq!(leaf.into_data(), data[3]);
+
+    // No tampering yields a verified leaf
+    let (leaf_data, proof_bundle) = bundle.clone().into_unverified_parts();
+    assert_eq!(leaf_data, data[3]);
+    let tampered_data = LeafBundle::new(b"Test data 4".to_vec(), proof_bundle.clone());
+    assert_eq!(
+        tampered_data.into_verified(&verifier),
+        Err(VerificationError::RootMismatch)
+    );
+    let mut tampered_proof = proof_bundle.clone();
+    tampered_proof.proof[1].0[0] ^= 1;
+    assert!(LeafBundle::new(data[3].clone(), tampered_proof)
+        .into_verified(&verifier)
+        .is_err());
+    let mut moved = proof_bundle.clone();
+    moved.index = 2;
+    assert!(matches!(
+        LeafBundle::new(data[3].clone(), moved).into_verified(&verifier),
+        Err(VerificationError::IndexMismatch { .. })
+    ));
+    let mut relabelled = proof_bundle.clone();
+    relabelled.params = HashScheme::Plain.into();
+    assert!(matches!(
+        LeafBundle::new(data[3].clone(), relabelled).into_verified(&verifier),
+        Err(VerificationError::ParamsMismatch { .. })
+    ));
+    let other_root = MerkleVerifier::for_tree(&MerkleTree::builder().build(create_test_data(8)))
+        .unwrap()
+        .with_leaf_count(7);
+    assert!(bundle.clone().into_verified(&other_root).is_err());
+
+    // A batch keeps the valid leaves and reports the position of each reject
+    let bundles = vec![
+        tree.generate_leaf_bundle(0).unwrap(),
+        LeafBundle::new(b"forged".to_vec(), tree.generate_bundle(1).unwrap()),
+        tree.generate_leaf_bundle(6).unwrap(),
+        LeafBundle::new(data[2].clone(), tree.generate_bundle(5).unwrap()),
+    ];
+    let (verified, rejected) = verifier.verify_leaf_bundles(bundles);
+    assert_eq!(
+        verified
+            .iter()
+            .map(|leaf| (leaf.index(), leaf.data()))
+            .collect::<Vec<_>>(),
+        [(Some(0), &data[0][..]), (Some(6), &data[6][..])]
+    );
+    assert_eq!(
+        rejected,
+        [
+            (1, VerificationError::RootMismatch),
+            (3, VerificationError::RootMismatch)
+        ]
+    );
+
+    // Sorted proofs do not bind the index unless leaves are positional, so a relabelled
+    // bundle still verifies but reports no index
+    for positional in [false, true] {
+        let sorted = MerkleTree::builder()
+            .pair_ordering(PairOrdering::Sorted)
+            .positional(positional)
+            .build(data.clone());
+        let verifier = MerkleVerifier::for_tree(&sorted).unwrap();
+        let leaf = sorted.generate_leaf_bundle(3).unwrap();
+        let index = positional.then_some(3);
+        assert_eq!(leaf.into_verified(&verifier).unwrap().index(), index);
+        let (leaf_data, mut relabelled) = sorted
+            .generate_leaf_bundle(3)
+            .unwrap()
+            .into_unverified_parts();
+        relabelled.index = 5;
+        let relabelled = LeafBundle::new(leaf_data, relabelled).into_verified(&verifier);
+        match positional {
+            false => assert_eq!(relabelled.unwrap().index(), None),
+            true => assert!(relabelled.is_err()),
+        }
+    }
+
+    let mut cleared = tree.clone();
+    cleared.clear_leaf_data();
+    assert_eq!(cleared.generate_leaf_bundle(3), None);
+    assert_eq!(tree.generate_leaf_bundle(7), None);
+}
+
+#[test]
+fn test_diagnose_proof_recognizes_common_mistakes() {
+    use crate::{Diagnosis, ProofRepair};
+
+    let data = create_test_data(8);
+    let tree = MerkleTree::builder().build(data.clone());
+    let hasher = tree.params().hasher;
+    let root = tree.root_hash().unwrap();
+    let proof = tree.generate_proof(&data[3]).unwrap();
+    let diagnose = |proof: &MerkleProof| MerkleTree::diagnose_proof(hasher, &data[3], proof, &root);
+    let steps = || proof.iter().cloned();
+
+    assert_eq!(diagnose(&proof), Diagnosis::Valid);
+    assert_eq!(diagnose(&proof).to_string(), "the proof verifies");
+
+    let flipped = MerkleProof::new(steps().map(|(hash, is_left)| (hash, !is_left)).collect());
+    let diagnosis = diagnose(&flipped);
+    assert_eq!(
+        diagnosis,
+        Diagnosis::Repairable(ProofRepair::FlippedDirections)
+    );
+    assert!(diagnosis.to_string().contains("is_left convention"));
+    assert!(diagnosis.to_string().contains("still rejected"));
+    // Diagnosis never makes the proof acceptable
+    assert!(!MerkleTree::verify_proof(&data[3], &flipped, &root));
+
+    let reversed = MerkleProof::new(steps().rev().collect());
+    assert_eq!(
+        diagnose(&reversed),
+        Diagnosis::Repairable(ProofRepair::ReversedSteps)
+    );
+    let reversed_flipped = MerkleProof::new(
+        steps()
+            .rev()
+            .map(|(hash, is_left)| (hash, !is_left))
+            .collect(),
+    );
+    assert_eq!(
+        diagnose(&reversed_flipped),
+        Diagnosis::Repairable(ProofRepair::ReversedAndFlippedSteps)
+    );
+
+    let mut swapped: Vec<_> = steps().collect();
+    swapped[1].1 = !swapped[1].1;
+    let diagnosis = diagnose(&MerkleProof::new(swapped));
+    assert_eq!(
+        diagnosis,
+        Diagnosis::Repairable(ProofRepair::SwappedAtStep { step: 1 })
+    );
+    assert!(diagnosis.to_string().contains("step 1"));
+
+    let mut extra: Vec<_> = steps().collect();
+    extra.push((root.clone(), false));
+    assert_eq!(
+        diagnose(&MerkleProof::new(extra)),
+        Diagnosis::Repairable(ProofRepair::ExtraFinalStep)
+    );
+
+    // A proof from a tree that sorts pairs, checked against one that does not, and back
+    let sorted_tree = MerkleTree::builder()
+        .pair_ordering(PairOrdering::Sorted)
+        .build(data.clone());
+    let sorted_hasher = sorted_tree.params().hasher;
+    let sorted_root = sorted_tree.root_hash().unwrap();
+    let sorted_proof = sorted_tree.generate_proof(&data[3]).unwrap();
+    let positional = HashConfig {
+        ordering: PairOrdering::Positional,
+        ..sorted_hasher
+    };
+    let sorted_as_positional = MerkleProof::new(
+        sorted_proof
+            .iter()
+            .map(|(hash, _)| (hash.clone(), false))
+            .collect(),
+    );
+    let diagnosis =
+        MerkleTree::diagnose_proof(positional, &data[3], &sorted_as_positional, &sorted_root);
+    assert_eq!(diagnosis, Diagnosis::Repairable(ProofRepair::SortedPairs));
+    assert!(diagnosis.to_string().contains("PairOrdering::Sorted"));
+    assert_eq!(
+        MerkleTree::diagnose_proof(sorted_hasher, &data[3], &proof, &root),
+        Diagnosis::Repairable(ProofRepair::PositionalPairs)
+    );
+
+    // An RFC 6962 style proof checked against a tree that duplicates the last node
+    let three = create_test_data(3);
+    let carry_up = MerkleTree::builder()
+        .padding(PaddingStrategy::CarryUp)
+        .build(three.clone());
+    let duplicate_last = MerkleTree::builder().build(three.clone());
+    let diagnosis = MerkleTree::diagnose_proof(
+        hasher,
+        &three[2],
+        &carry_up.generate_proof(&three[2]).unwrap(),
+        &duplicate_last.root_hash().unwrap(),
+    );
+    assert_eq!(
+        diagnosis,
+        Diagnosis::Repairable(ProofRepair::MissingDuplicateStep { step: 0 })
+    );
+    assert!(diagnosis.to_string().contains("carrying it up"));
+
+    assert_eq!(
+        MerkleTree::diagnose_proof(hasher, b"other data", &proof, &root),
+        Diagnosis::Unrecognized
+    );
+    assert_eq!(
+        MerkleTree::diagnose_proof(hasher, &data[3], &proof, &root[1..]),
+        Diagnosis::Malformed(VerificationError::InvalidRootLength {
+            expected: 32,
+            actual: 31
+        })
+    );
+}
+
+#[test]
+fn test_slotted_tree_matches_tree_with_empty_slots() {
+    use crate::{SlottedMerkleTree, EMPTY_LEAF_DATA};
+
+    let paddings = [
+        PaddingStrategy::DuplicateLast,
+        PaddingStrategy::CarryUp,
+        PaddingStrategy::Complete { depth: 4 },
+    ];
+    for padding in paddings {
+        for positional in [false, true] {
+            let params = TreeParams {
+                padding,
+                ..TreeParams::new(HashScheme::DomainSeparated, positional)
+            };
+            for capacity in 1..=9 {
+                let mut slotted = SlottedMerkleTree::with_params(capacity, params).unwrap();
+                let mut contents = vec![EMPTY_LEAF_DATA.to_vec(); capacity];
+                // Fill every other slot from the end, then the rest
+                let order = (0..capacity)
+                    .rev()
+                    .step_by(2)
+                    .chain((0..capacity).rev().skip(1).step_by(2));
+                for slot in std::iter::once(None).chain(order.map(Some)) {
+                    if let Some(slot) = slot {
+                        let data = format!("Record {}", slot).into_bytes();
+                        slotted.set(slot, data.clone()).unwrap();
+                        contents[slot] = data;
+                    }
+                    let expected = MerkleTree::builder()
+                        .padding(padding)
+                        .positional(positional)
+                        .build(contents.clone());
+                    assert_eq!(slotted.root(), expected.root_hash().unwrap());
+                    for (index, data) in contents.iter().enumerate() {
+                        let proof = slotted.generate_proof(index).unwrap();
+                        assert_eq!(Some(&proof), expected.proof_by_index(index).as_ref());
+                        // The shape check assumes duplicated padding
+                        let leaf_count =
+                            (padding == PaddingStrategy::DuplicateLast).then_some(capacity);
+                        let leaf = if positional {
+                            crate::verify::positional_leaf(index, data)
+                        } else {
+                            data.clone()
+                        };
+                        assert_eq!(
+                            MerkleTree::verify_proof_checked(
+                                params.hasher,
+                                &leaf,
+                                &proof,
+                                &slotted.root(),
+                                leaf_count,
+                                Some(index),
+                            ),
+                            Ok(())
+                        );
+                    }
+                }
+                assert_eq!(slotted.filled_count(), capacity);
+            }
+        }
+    }
+
+    // An empty slot is proved empty, and stays so while its neighbours fill
+    let mut slotted = SlottedMerkleTree::new(6);
+    slotted.set(4, b"late record".to_vec()).unwrap();
+    let proof = slotted.generate_proof(5).unwrap();
+    let hasher = slotted.tree().params().hasher;
+    assert!(!slotted.is_filled(5));
+    assert_eq!(slotted.get(5), None);
+    assert_eq!(slotted.get(4), Some(&b"late record"[..]));
+    assert_eq!(
+        MerkleTree::verify_proof_checked(
+            hasher,
+            &EMPTY_LEAF_DATA,
+            &proof,
+            &slotted.root(),
+            Some(6),
+            Some(5)
+        ),
+        Ok(())
+    );
+
+    // Slots are filled once unless overwriting is allowed
+    assert_eq!(
+        slotted.set(4, b"again".to_vec()),
+        Err(MerkleError::SlotOccupied { slot: 4 })
+    );
+    assert_eq!(
+        slotted.set(4, b"again".to_vec()).unwrap_err().status_code(),
+        422
+    );
+    assert_eq!(
+        slotted.set(6, b"outside".to_vec()),
+        Err(MerkleError::IndexOutOfRange {
+            index: 6,
+            leaf_count: 6
+        })
+    );
+    assert_eq!(
+        slotted.set(0, EMPTY_LEAF_DATA.to_vec()),
+        Err(MerkleError::EmptyLeafData { slot: 0 })
+    );
+    let mut slotted = slotted.with_overwrite(true);
+    slotted.set(4, b"again".to_vec()).unwrap();
+    assert_eq!(slotted.get(4), Some(&b"again"[..]));
+    assert_eq!(slotted.filled_count(), 1);
+
+    // Filling a slot only rehashes its path
+    let mut large = SlottedMerkleTree::new(1024);
+    large.tree().reset_metrics();
+    large.set(700, b"record".to_vec()).unwrap();
+    let metrics = large.tree().metrics();
+    assert_eq!((metrics.leaf_hashes, metrics.node_hashes), (1, 10));
+
+    // Complete padding is made of empty slots, so the capacity does not change the root
+    let complete = TreeParams {
+        padding: PaddingStrategy::Complete { depth: 3 },
+        ..TreeParams::new(HashScheme::Plain, false)
+    };
+    let mut five = SlottedMerkleTree::with_params(5, complete).unwrap();
+    let mut eight = SlottedMerkleTree::with_params(8, complete).unwrap();
+    assert_eq!(five.root(), eight.root());
+    five.set(2, b"record".to_vec()).unwrap();
+    eight.set(2, b"record".to_vec()).unwrap();
+    assert_eq!(five.root(), eight.root());
+    let mut contents = vec![EMPTY_LEAF_DATA.to_vec(); 5];
+    contents[2] = b"record".to_vec();
+    assert_eq!(
+        Some(five.root()),
+        MerkleTree::new_complete(contents, 3).unwrap().root_hash()
+    );
+    assert_eq!(
+        SlottedMerkleTree::with_params(9, complete).unwrap_err(),
+        MerkleError::TooManyLeaves {
+            leaf_count: 9,
+            depth: 3
+        }
+    );
+
+    // A tree has at least one slot
+    assert_eq!(
+        SlottedMerkleTree::with_params(0, complete).unwrap_err(),
+        MerkleError::NoSlots
+    );
+    assert_eq!(MerkleError::NoSlots.status_code(), 400);
+    let panic = std::panic::catch_unwind(|| SlottedMerkleTree::new(0)).unwrap_err();
+    assert_eq!(
+        panic.downcast_ref::<String>().map(String::as_str),
+        Some("a slotted tree needs at least one slot")
+    );
+}
+
+#[test]
+#[ignore = "needs fixtures/cbergoon/vectors.json, generated by fixtures/cbergoon/main.go"]
+fn test_compat_cbergoon_matches_go_fixtures() {
+    use crate::Compat;
+
+    // Generate the vectors with the Go program next to them (`go run . > vectors.json`)
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/cbergoon/vectors.json"
+    );
+    let fixtures: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let hex_bytes = |value: &serde_json::Value| hex::decode(value.as_str().unwrap()).unwrap();
+    let compat = Compat::CbergoonGo;
+
+    let fixtures = fixtures.as_array().unwrap();
+    assert_eq!(fixtures.len(), 13);
+    for fixture in fixtures {
+        let leaves: Vec<Vec<u8>> = fixture["leaves"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|leaf| leaf.as_str().unwrap().as_bytes().to_vec())
+            .collect();
+        let root = hex_bytes(&fixture["root"]);
+        let tree = MerkleTree::new_compat(leaves.clone(), compat);
+        assert_eq!(tree.root_hash().unwrap(), root, "{} leaves", leaves.len());
+        assert!(tree.leaf_count().is_power_of_two() && tree.leaf_count() >= 2);
+
+        // Callers with their own leaf hashing pad the hashes instead
+        let leaf_hashes = leaves.iter().map(|leaf| Sha256::digest(leaf).to_vec());
+        let from_hashes =
+            MerkleTree::from_leaf_hashes(compat.pad_leaves(leaf_hashes.collect()), compat.params());
+        assert_eq!(from_hashes.root_hash().unwrap(), root);
+
+        for proof in fixture["proofs"].as_array().unwrap() {
+            let index = proof["index"].as_u64().unwrap() as usize;
+            let siblings: Vec<Vec<u8>> = proof["path"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(hex_bytes)
+                .collect();
+            let directions: Vec<i64> = proof["directions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|direction| direction.as_i64().unwrap())
+                .collect();
+            let imported = compat.import_proof(siblings.clone(), &directions).unwrap();
+            assert!(MerkleTree::verify_proof(&leaves[index], &imported, &root));
+            assert!(MerkleTree::verify_proof_with(
+                compat.params().hasher,
+                &leaves[index],
+                &imported,
+                &root,
+                Some(tree.leaf_count())
+            ));
+            let generated = tree.proof_by_index(index).unwrap();
+            assert_eq!(generated, imported);
+            assert_eq!(compat.export_proof(&generated), (siblings, directions));
+        }
+    }
+}
+
+#[test]
+fn test_compat_cbergoon_preset() {
+    use crate::{Compat, ProofDecodeError};
+
+    let compat = Compat::CbergoonGo;
+    for count in [1, 2, 3, 5, 6, 7, 9, 17] {
+        let leaves = create_test_data(count);
+        let tree = MerkleTree::new_compat(leaves.clone(), compat);
+        let root = tree.root_hash().unwrap();
+
+        // The tree holds the padding leaves, so its leaf count is not the caller's
+        assert_eq!(tree.leaf_count(), count.next_power_of_two().max(2));
+        assert_eq!(
+            tree.get_leaf(tree.leaf_count() - 1),
+            leaves.last().map(Vec::as_slice)
+        );
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof_by_index(index).unwrap();
+            let (siblings, directions) = compat.export_proof(&proof);
+            assert_eq!(compat.import_proof(siblings, &directions).unwrap(), proof);
+            assert!(MerkleTree::verify_proof(leaf, &proof, &root));
+        }
+    }
+
+    // The Go package pads upper levels too, so its roots differ from a duplicating tree's
+    for count in [1, 6] {
+        let leaves = create_test_data(count);
+        assert_ne!(
+            MerkleTree::new_compat(leaves.clone(), compat).root_hash(),
+            MerkleTree::new(leaves).root_hash()
+        );
+    }
+    let four = create_test_data(4);
+    assert_eq!(
+        MerkleTree::new_compat(four.clone(), compat).root_hash(),
+        MerkleTree::new(four).root_hash()
+    );
+    assert_eq!(
+        compat.pad_leaves(vec![0, 1, 2, 3, 4, 5]),
+        [0, 1, 2, 3, 4, 5, 4, 5]
+    );
+    assert_eq!(
+        compat.pad_leaves(vec![0, 1, 2, 3, 4]),
+        [0, 1, 2, 3, 4, 4, 4, 4]
+    );
+    assert!(compat.pad_leaves(Vec::<u8>::new()).is_empty());
+    assert_eq!(MerkleTree::new_compat(Vec::new(), compat).root_hash(), None);
+
+    assert_eq!(
+        compat.import_proof(vec![vec![0; 32]; 2], &[1]),
+        Err(ProofDecodeError::DirectionCountMismatch {
+            steps: 2,
+            directions: 1
+        })
+    );
+    assert_eq!(
+        compat.import_proof(vec![vec![0; 32]], &[2]),
+        Err(ProofDecodeError::InvalidDirectionValue { value: 2 })
+    );
+}
+
+#[test]
+fn test_decode_limits_bound_every_decoder() {
+    use crate::{
+        CheckpointedBuilder, DecodeLimit, DecodeLimits, MultiProof, SnapshotError, SnapshotReader,
+        MAX_PROOF_STEPS,
+    };
+
+    let exceeded = |which, limit, requested| MerkleError::LimitExceeded {
+        which,
+        limit,
+        requested,
+    };
+    // Limits that allow just `amount` of one resource and anything of the others
+    let just = |which, amount: u64| {
+        let mut limits = DecodeLimits::relaxed();
+        match which {
+            DecodeLimit::Leaves => limits.max_leaves = amount,
+            DecodeLimit::LeafSize => limits.max_leaf_size = amount,
+            DecodeLimit::ProofDepth => limits.max_proof_depth = amount as usize,
+            DecodeLimit::TotalBytes => limits.max_total_bytes = amount,
+        }
+        limits
+    };
+
+    let tree = MerkleTree::new(create_test_data(8));
+
+    // Proofs: 3 steps, in every encoding
+    let proof = tree.proof_by_index(5).unwrap();
+    let bytes = proof.to_bytes();
+    let len = bytes.len() as u64;
+    assert_eq!(
+        MerkleProof::from_bytes_with(&bytes, &just(DecodeLimit::TotalBytes, len)),
+        Ok(proof.clone())
+    );
+    assert_eq!(
+        MerkleProof::from_bytes_with(&bytes, &just(DecodeLimit::TotalBytes, len - 1)),
+        Err(ProofDecodeError::LimitExceeded(exceeded(
+            DecodeLimit::TotalBytes,
+            len - 1,
+            len
+        )))
+    );
+    assert_eq!(
+        MerkleProof::from_hex_with(&proof.to_hex(), &just(DecodeLimit::ProofDepth, 3)),
+        Ok(proof.clone())
+    );
+    assert_eq!(
+        MerkleProof::from_hex_with(&proof.to_hex(), &just(DecodeLimit::ProofDepth, 2)),
+        Err(ProofDecodeError::LimitExceeded(exceeded(
+            DecodeLimit::ProofDepth,
+            2,
+            3
+        )))
+    );
+    assert_eq!(
+        MerkleProof::from_base64_with(&proof.to_base64(), &just(DecodeLimit::TotalBytes, len)),
+        Ok(proof.clone())
+    );
+    assert!(matches!(
+        MerkleProof::from_base64_with(&proof.to_base64(), &just(DecodeLimit::TotalBytes, len - 1)),
+        Err(ProofDecodeError::LimitExceeded(_))
+    ));
+
+    // Multiproofs: 8 leaves, a tree of height 3, and 2 indices
+    let multiproof = tree.generate_multiproof(&[1, 6]).unwrap();
+    let bytes = multiproof.to_bytes();
+    for (which, amount) in [
+        (DecodeLimit::TotalBytes, bytes.len() as u64),
+        (DecodeLimit::Leaves, 8),
+        (DecodeLimit::ProofDepth, 3),
+    ] {
+        assert_eq!(
+            MultiProof::from_bytes_with(&bytes, &just(which, amount)),
+            Ok(multiproof.clone())
+        );
+        assert_eq!(
+            MultiProof::from_bytes_with(&bytes, &just(which, amount - 1)),
+            Err(ProofDecodeError::LimitExceeded(exceeded(
+                which,
+                amount - 1,
+                amount
+            )))
+        );
+    }
+    assert!(MultiProof::from_hex_with(&multiproof.to_hex(), &just(DecodeLimit::Leaves, 8)).is_ok());
+    assert!(matches!(
+        MultiProof::from_base64_with(&multiproof.to_base64(), &just(DecodeLimit::Leaves, 7)),
+        Err(ProofDecodeError::LimitExceeded(_))
+    ));
+
+    // Snapshots: 8 leaves in a tree of height 3
+    let snapshot = tree.to_snapshot();
+    for (which, amount) in [
+        (DecodeLimit::TotalBytes, snapshot.len() as u64),
+        (DecodeLimit::Leaves, 8),
+        (DecodeLimit::ProofDepth, 3),
+    ] {
+        let reader = SnapshotReader::open_with(&snapshot[..], &just(which, amount)).unwrap();
+        assert_eq!(reader.leaf_count(), 8);
+        match SnapshotReader::open_with(&snapshot[..], &just(which, amount - 1)) {
+            Err(SnapshotError::LimitExceeded(e)) => {
+                assert_eq!(e, exceeded(which, amount - 1, amount))
+            }
+            other => panic!("expected a limit error, got {:?}", other.map(|_| ())),
+        }
+    }
+    assert_eq!(
+        MerkleTree::from_snapshot_with(&snapshot, &just(DecodeLimit::Leaves, 8))
+            .unwrap()
+            .root_hash(),
+        tree.root_hash()
+    );
+    assert!(matches!(
+        MerkleTree::from_snapshot_with(&snapshot, &just(DecodeLimit::Leaves, 7)),
+        Err(SnapshotError::LimitExceeded(_))
+    ));
+
+    // Checkpoints: 8 leaves
+    let mut builder = CheckpointedBuilder::new(HashScheme::Plain);
+    for hash in tree.clone().into_leaf_hashes() {
+        builder.push_leaf_hash(hash).unwrap();
+    }
+    let checkpoint = builder.checkpoint();
+    let len = checkpoint.len() as u64;
+    assert!(
+        CheckpointedBuilder::resume_with(&checkpoint, &just(DecodeLimit::TotalBytes, len)).is_ok()
+    );
+    assert_eq!(
+        CheckpointedBuilder::resume_with(&checkpoint, &just(DecodeLimit::TotalBytes, len - 1))
+            .unwrap_err(),
+        exceeded(DecodeLimit::TotalBytes, len - 1, len)
+    );
+    assert!(CheckpointedBuilder::resume_with(&checkpoint, &just(DecodeLimit::Leaves, 8)).is_ok());
+    assert_eq!(
+        CheckpointedBuilder::resume_with(&checkpoint, &just(DecodeLimit::Leaves, 7)).unwrap_err(),
+        exceeded(DecodeLimit::Leaves, 7, 8)
+    );
+
+    // Fixed-size records: 4 records of 16 bytes
+    let records = vec![7u8; 64];
+    for (which, amount) in [
+        (DecodeLimit::TotalBytes, 64),
+        (DecodeLimit::LeafSize, 16),
+        (DecodeLimit::Leaves, 4),
+    ] {
+        assert!(MerkleTree::from_fixed_records_with(&records, 16, &just(which, amount)).is_ok());
+        let error = MerkleTree::from_fixed_records_with(&records, 16, &just(which, amount - 1))
+            .unwrap_err();
+        assert_eq!(error, exceeded(which, amount - 1, amount));
+        assert_eq!(error.status_code(), 413);
+    }
+
+    // The decoders without limits use the defaults, which a proof over MAX_PROOF_STEPS
+    // levels fits but a tree over 2^32 leaves does not
+    let defaults = DecodeLimits::default();
+    assert_eq!(
+        defaults.limit(DecodeLimit::ProofDepth),
+        MAX_PROOF_STEPS as u64
+    );
+    assert_eq!(MerkleProof::from_bytes(&proof.to_bytes()), Ok(proof));
+    let mut forged = multiproof.to_bytes();
+    forged[..8].copy_from_slice(&((1u64 << 32) + 1).to_le_bytes());
+    assert_eq!(
+        MultiProof::from_bytes(&forged),
+        Err(ProofDecodeError::LimitExceeded(exceeded(
+            DecodeLimit::Leaves,
+            1 << 32,
+            (1 << 32) + 1
+        )))
+    );
+
+    let strict = DecodeLimits::strict();
+    assert!(strict.max_leaves < defaults.max_leaves);
+    assert!(strict.max_leaf_size < defaults.max_leaf_size);
+    assert!(strict.max_proof_depth < defaults.max_proof_depth);
+    assert!(strict.max_total_bytes < defaults.max_total_bytes);
+    let relaxed = DecodeLimits::relaxed();
+    assert!(relaxed.max_leaves > defaults.max_leaves);
+    assert!(relaxed.max_total_bytes > defaults.max_total_bytes);
+    assert_eq!(
+        exceeded(DecodeLimit::LeafSize, 16, 17).to_string(),
+        "input asks for 17 of leaf size, more than the limit of 16"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_decode_limits_bound_canonical_json() {
+    use crate::{CanonicalJsonError, DecodeLimit, DecodeLimits, ProofBundle};
+
+    let tree = MerkleTree::new(create_test_data(8));
+    let bundle = tree.generate_bundle(2).unwrap();
+    let json = bundle.to_canonical_json();
+    let mut limits = DecodeLimits::relaxed();
+    limits.max_total_bytes = json.len() as u64;
+    limits.max_leaves = 8;
+    limits.max_proof_depth = 3;
+    assert_eq!(
+        ProofBundle::from_canonical_json_with(&json, &limits).unwrap(),
+        bundle
+    );
+    for over in [
+        DecodeLimits {
+            max_total_bytes: json.len() as u64 - 1,
+            ..limits
+        },
+        DecodeLimits {
+            max_leaves: 7,
+            ..limits
+        },
+        DecodeLimits {
+            max_proof_depth: 2,
+            ..limits
+        },
+    ] {
+        assert!(matches!(
+            ProofBundle::from_canonical_json_with(&json, &over),
+            Err(CanonicalJsonError::LimitExceeded(
+                MerkleError::LimitExceeded { .. }
+            ))
+        ));
+    }
+
+    let proof_json = bundle.proof.to_canonical_json();
+    assert!(MerkleProof::from_canonical_json_with(&proof_json, &limits).is_ok());
+    match MerkleProof::from_canonical_json_with(
+        &proof_json,
+        &DecodeLimits {
+            max_proof_depth: 2,
+            ..limits
+        },
+    ) {
+        Err(CanonicalJsonError::LimitExceeded(e)) => assert_eq!(
+            e,
+            MerkleError::LimitExceeded {
+                which: DecodeLimit::ProofDepth,
+                limit: 2,
+                requested: 3
+            }
+        ),
+        other => panic!("expected a limit error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_build_verified_locates_corrupted_leaves() {
+    use crate::BuildVerifyError;
+
+    let data = create_test_data(11);
+    let tree = MerkleTree::new(data.clone());
+    let root = tree.root_hash().unwrap();
+    let canopy = tree.canopy(usize::MAX);
+    let top = tree.canopy(2);
+
+    let built = MerkleTree::build_verified(data.clone(), &root).unwrap();
+    assert_eq!(built.root_hash(), Some(root.clone()));
+    let built = MerkleTree::build_verified_with_canopy(data.clone(), &canopy).unwrap();
+    assert_eq!(built.root_hash(), Some(root.clone()));
+    assert_eq!(built.get_leaf(3), Some(&data[3][..]));
+
+    let corrupt = |index: usize| {
+        let mut data = data.clone();
+        data[index].push(0xff);
+        data
+    };
+    for index in [0, 5, 10] {
+        // The root alone only says the whole dataset is wrong
+        match MerkleTree::build_verified(corrupt(index), &root) {
+            Err(BuildVerifyError::Mismatch {
+                node,
+                leaves,
+                expected,
+                ..
+            }) => {
+                assert_eq!((node.level(), node.index()), (4, 0));
+                assert_eq!(leaves, 0..11);
+                assert_eq!(expected, root);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+
+        // The whole tree as a canopy names the leaf
+        match MerkleTree::build_verified_with_canopy(corrupt(index), &canopy) {
+            Err(BuildVerifyError::Mismatch {
+                node,
+                leaves,
+                expected,
+                actual,
+            }) => {
+                assert_eq!((node.level(), node.index()), (0, index as u64));
+                assert_eq!(leaves, index..index + 1);
+                assert_eq!(expected, tree.node_hash_at(node).unwrap());
+                assert_ne!(actual, expected);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+
+        // Two levels of canopy narrow it to the leaves under a node on level 3
+        match MerkleTree::build_verified_with_canopy(corrupt(index), &top) {
+            Err(BuildVerifyError::Mismatch { node, leaves, .. }) => {
+                assert_eq!(node.level(), 3);
+                assert!(leaves.contains(&index) && leaves.len() <= 8);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    // A truncated dataset
+    let truncated = data[..10].to_vec();
+    assert!(matches!(
+        MerkleTree::build_verified(truncated.clone(), &root),
+        Err(BuildVerifyError::Mismatch { .. })
+    ));
+    assert_eq!(
+        MerkleTree::build_verified_with_canopy(truncated, &canopy).unwrap_err(),
+        BuildVerifyError::LeafCountMismatch {
+            expected: 11,
+            actual: 10
+        }
+    );
+    assert_eq!(
+        MerkleTree::build_verified(Vec::new(), &root).unwrap_err(),
+        BuildVerifyError::Empty
+    );
+
+    let error = MerkleTree::build_verified_with_canopy(corrupt(5), &canopy).unwrap_err();
+    assert!(error.to_string().contains("a leaf in 5..6 is corrupted"));
+}
+
+#[test]
+fn test_root_change_observers_run_once_per_mutation() {
+    use crate::{MutationKind, RootChange};
+    use std::sync::{Arc, Mutex};
+
+    let mut tree = MerkleTree::new(create_test_data(3));
+    let changes: Arc<Mutex<Vec<RootChange>>> = Arc::default();
+    let second: Arc<Mutex<usize>> = Arc::default();
+    let handle = tree.on_root_change({
+        let changes = Arc::clone(&changes);
+        move |change| changes.lock().unwrap().push(change)
+    });
+    let other = tree.on_root_change({
+        let second = Arc::clone(&second);
+        move |_| *second.lock().unwrap() += 1
+    });
+
+    let root = tree.root_hash();
+    tree.push(b"pushed".to_vec());
+    assert_eq!(
+        changes.lock().unwrap().as_slice(),
+        [RootChange {
+            old_root: root,
+            new_root: tree.root_hash().unwrap(),
+            epoch: tree.epoch(),
+            kind: MutationKind::Push,
+            leaves: 3..4,
+        }]
+    );
+
+    tree.update_by_index(1, b"updated".to_vec()).unwrap();
+    tree.update_leaf(tree.leaf_index(2).unwrap(), b"updated again".to_vec())
+        .unwrap();
+    tree.push_with_proof(b"proved".to_vec());
+    assert!(tree.update_by_index(99, Vec::new()).is_err());
+    let kinds: Vec<_> = changes.lock().unwrap().iter().map(|c| c.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            MutationKind::Push,
+            MutationKind::Update,
+            MutationKind::Update,
+            MutationKind::Push
+        ]
+    );
+
+    // A batch notifies once, with the roots on either side of the whole batch
+    let root = tree.root_hash();
+    let epoch = tree.epoch();
+    tree.extend(create_test_data(5));
+    tree.extend(Vec::new());
+    {
+        let changes = changes.lock().unwrap();
+        assert_eq!(changes.len(), 5);
+        let batch = &changes[4];
+        assert_eq!(batch.kind, MutationKind::Extend);
+        assert_eq!(batch.leaves, 5..10);
+        assert_eq!(batch.old_root, root);
+        assert_eq!(Some(batch.new_root.clone()), tree.root_hash());
+        assert_eq!(batch.epoch, epoch + 5);
+        // Each change starts where the one before ended
+        for pair in changes.windows(2) {
+            assert_eq!(pair[1].old_root.as_ref(), Some(&pair[0].new_root));
+        }
+    }
+    assert_eq!(*second.lock().unwrap(), 5);
+    let mut expected = create_test_data(3);
+    expected[1] = b"updated".to_vec();
+    expected[2] = b"updated again".to_vec();
+    expected.push(b"pushed".to_vec());
+    expected.push(b"proved".to_vec());
+    expected.extend(create_test_data(5));
+    assert_eq!(tree.root_hash(), MerkleTree::new(expected).root_hash());
+
+    // Removed observers stop running, and clones have none
+    assert!(tree.remove_observer(other));
+    assert!(!tree.remove_observer(other));
+    let mut clone = tree.clone();
+    clone.push(b"clone".to_vec());
+    tree.push(b"tree".to_vec());
+    assert_eq!(changes.lock().unwrap().len(), 6);
+    assert_eq!(*second.lock().unwrap(), 5);
+    assert!(tree.remove_observer(handle));
+
+    // A panicking observer leaves the tree consistent, and later observers still run
+    let mut tree = MerkleTree::new(create_test_data(4));
+    tree.on_root_change(|_| panic!("observer failed"));
+    let ran: Arc<Mutex<usize>> = Arc::default();
+    tree.on_root_change({
+        let ran = Arc::clone(&ran);
+        move |_| *ran.lock().unwrap() += 1
+    });
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        tree.push(b"fifth".to_vec());
+    }));
+    assert!(panicked.is_err());
+    assert_eq!(*ran.lock().unwrap(), 1);
+    let mut expected = create_test_data(4);
+    expected.push(b"fifth".to_vec());
+    assert_eq!(tree.root_hash(), MerkleTree::new(expected).root_hash());
+    assert_eq!(tree.leaf_count(), 5);
+}
+
+#[test]
+fn test_snapshot_diff_updates_replicas() {
+    use crate::{DecodeLimit, DecodeLimits, DiffLeaf, SnapshotDiff};
+
+    let data = create_test_data(9);
+    let older = MerkleTree::new(data.clone());
+    let roundtrip = |diff: &SnapshotDiff| {
+        let decoded = SnapshotDiff::from_bytes(&diff.to_bytes()).unwrap();
+        assert_eq!(&decoded, diff);
+        decoded
+    };
+
+    // Updates and appends carry only the changed leaves
+    let mut newer = older.clone();
+    newer.update_by_index(2, b"two".to_vec()).unwrap();
+    newer.update_by_index(8, b"eight".to_vec()).unwrap();
+    newer.extend([b"nine".to_vec(), b"ten".to_vec()]);
+    let diff = newer.snapshot_diff(&older);
+    assert_eq!(diff.leaf_count, 11);
+    assert_eq!(diff.base_root, older.root_hash());
+    assert_eq!(diff.new_root, newer.root_hash());
+    assert_eq!(
+        diff.changes.keys().copied().collect::<Vec<_>>(),
+        [2, 8, 9, 10]
+    );
+    assert_eq!(diff.changes[&2], DiffLeaf::Data(b"two".to_vec()));
+    let full = newer.to_snapshot().len();
+    assert!(diff.to_bytes().len() < full / 2);
+
+    let mut replica = older.clone();
+    let epoch = replica.epoch();
+    let notified = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    replica.on_root_change({
+        let notified = std::sync::Arc::clone(&notified);
+        move |change| notified.lock().unwrap().push(change.leaves)
+    });
+    replica.apply_diff(roundtrip(&diff)).unwrap();
+    assert_eq!(replica.root_hash(), newer.root_hash());
+    assert_eq!(replica.get_leaf(9), Some(&b"nine"[..]));
+    assert_eq!(replica.get_leaf(3), Some(&data[3][..]));
+    assert_eq!(replica.epoch(), epoch + 1);
+    assert_eq!(*notified.lock().unwrap(), vec![2..11]);
+
+    // A tree without leaf data ships hashes instead
+    let mut hashes_only = newer.clone();
+    hashes_only.clear_leaf_data();
+    let diff = hashes_only.snapshot_diff(&older);
+    assert!(matches!(diff.changes[&9], DiffLeaf::Hash(_)));
+    let mut replica = older.clone();
+    replica.apply_diff(roundtrip(&diff)).unwrap();
+    assert_eq!(replica.root_hash(), newer.root_hash());
+    assert_eq!(replica.get_leaf(9), None);
+    assert_eq!(replica.get_leaf(0), Some(&data[0][..]));
+
+    // Truncation drops leaves, with or without changes to the rest
+    for (count, changed) in [(5, false), (4, true), (1, true), (0, false)] {
+        let mut leaves = data[..count].to_vec();
+        if changed {
+            leaves[0] = b"first".to_vec();
+        }
+        let newer = MerkleTree::new(leaves);
+        let diff = newer.snapshot_diff(&older);
+        assert_eq!(diff.changes.len(), usize::from(changed));
+        let mut replica = older.clone();
+        replica.apply_diff(roundtrip(&diff)).unwrap();
+        assert_eq!(replica.leaf_count(), count);
+        assert_eq!(replica.root_hash(), newer.root_hash());
+        assert_eq!(replica.get_leaf(count), None);
+        assert_eq!(replica.proof_by_index(0), newer.proof_by_index(0));
+    }
+
+    // A diff only applies to its base, and only if it gives its new root
+    let diff = newer.snapshot_diff(&older);
+    let mut wrong_base = MerkleTree::new(create_test_data(8));
+    let root = wrong_base.root_hash();
+    assert_eq!(
+        wrong_base.apply_diff(diff.clone()).unwrap_err(),
+        MerkleError::DiffBaseMismatch {
+            expected: older.root_hash(),
+            actual: root.clone()
+        }
+    );
+    assert_eq!(wrong_base.root_hash(), root);
+    let mut forged = diff.clone();
+    forged.changes.insert(2, DiffLeaf::Data(b"forged".to_vec()));
+    let mut replica = older.clone();
+    let error = replica.apply_diff(forged).unwrap_err();
+    assert!(matches!(error, MerkleError::DiffRootMismatch { .. }));
+    assert_eq!(error.status_code(), 422);
+    assert_eq!(replica.root_hash(), older.root_hash());
+    assert_eq!(replica.get_leaf(2), Some(&data[2][..]));
+    let mut missing = diff.clone();
+    missing.changes.remove(&10);
+    assert!(matches!(
+        older.clone().apply_diff(missing),
+        Err(MerkleError::InvalidDiff { .. })
+    ));
+
+    // Decoding is bounded and strict
+    let bytes = diff.to_bytes();
+    let mut limits = DecodeLimits {
+        max_leaves: 10,
+        ..DecodeLimits::default()
+    };
+    assert_eq!(
+        SnapshotDiff::from_bytes_with(&bytes, &limits).unwrap_err(),
+        MerkleError::LimitExceeded {
+            which: DecodeLimit::Leaves,
+            limit: 10,
+            requested: 11
+        }
+    );
+    limits.max_leaves = 11;
+    limits.max_leaf_size = 4;
+    assert!(matches!(
+        SnapshotDiff::from_bytes_with(&bytes, &limits),
+        Err(MerkleError::LimitExceeded {
+            which: DecodeLimit::LeafSize,
+            ..
+        })
+    ));
+    for corrupt in [
+        &bytes[..bytes.len() - 1],
+        &[bytes.as_slice(), &[0]].concat(),
+    ] {
+        assert!(matches!(
+            SnapshotDiff::from_bytes(corrupt),
+            Err(MerkleError::InvalidDiff { .. })
+        ));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snapshot_diff_serde_roundtrip() {
+    use crate::SnapshotDiff;
+
+    let older = MerkleTree::new(create_test_data(4));
+    let mut newer = older.clone();
+    newer.push(b"appended".to_vec());
+    let diff = newer.snapshot_diff(&older);
+    let json = serde_json::to_string(&diff).unwrap();
+    assert_eq!(serde_json::from_str::<SnapshotDiff>(&json).unwrap(), diff);
+}
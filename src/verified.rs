@@ -0,0 +1,200 @@
+//! Leaf data that can only be read once its proof has been checked
+
+use crate::error::VerificationError;
+use crate::hashing::PairOrdering;
+use crate::proof::ProofBundle;
+use crate::verifier::MerkleVerifier;
+#[cfg(feature = "build")]
+use crate::MerkleTree;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A leaf's data together with the proof bundle for it, as received from an untrusted
+/// source
+///
+/// The data is private: `into_verified` checks the proof against a trusted verifier and
+/// only then hands the data out, as a `VerifiedLeaf`. The bundle itself, which carries no
+/// data, can be inspected with `bundle`. `into_unverified_parts` gives up the check for
+/// callers that verify some other way.
+///
+/// With the `serde` feature the bundle serializes as an object with the data in
+/// hexadecimal and the proof bundle under `bundle`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeafBundle {
+    /// The leaf's data, which is not to be used before the proof is checked
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    data: Vec<u8>,
+    /// The proof bundle for the data
+    bundle: ProofBundle,
+}
+
+/// Leaf data whose proof has been checked against a trusted root
+///
+/// Only `LeafBundle::into_verified` and `MerkleVerifier::verify_leaf_bundles` create
+/// these, so holding one means the check has been made:
+///
+/// ```compile_fail
+/// use merkleproof::VerifiedLeaf;
+///
+/// let forged = VerifiedLeaf { data: b"forged".to_vec(), index: None, root: vec![0; 32] };
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VerifiedLeaf {
+    /// The leaf's data
+    data: Vec<u8>,
+    /// The index of the leaf, if the proof binds it
+    index: Option<usize>,
+    /// The root the leaf was verified against
+    root: Vec<u8>,
+}
+
+impl LeafBundle {
+    /// Pair a leaf's data with its proof bundle
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf's data
+    /// * `bundle` - The proof bundle for the data
+    ///
+    /// # Returns
+    ///
+    /// The leaf bundle
+    pub fn new(data: impl Into<Vec<u8>>, bundle: ProofBundle) -> Self {
+        LeafBundle {
+            data: data.into(),
+            bundle,
+        }
+    }
+
+    /// Get the proof bundle, which does not include the leaf's data
+    ///
+    /// # Returns
+    ///
+    /// The proof bundle
+    pub fn bundle(&self) -> &ProofBundle {
+        &self.bundle
+    }
+
+    /// Check the proof and release the data
+    ///
+    /// The proof is checked as by `MerkleVerifier::verify_bundle`, so the bundle's
+    /// parameters, leaf count and index must agree with what the verifier trusts.
+    ///
+    /// # Arguments
+    ///
+    /// * `verifier` - The verifier holding the trusted root
+    ///
+    /// # Returns
+    ///
+    /// The verified leaf, or the reason the proof was rejected
+    pub fn into_verified(
+        self,
+        verifier: &MerkleVerifier,
+    ) -> Result<VerifiedLeaf, VerificationError> {
+        verifier.verify_bundle(&self.data, &self.bundle)?;
+        // Sorted pairs carry no directions, so only a positional leaf hash binds the index
+        let params = verifier.params();
+        let binds_index = params.positional || params.hasher.ordering == PairOrdering::Positional;
+        Ok(VerifiedLeaf {
+            data: self.data,
+            index: binds_index.then_some(self.bundle.index),
+            root: verifier.root().to_vec(),
+        })
+    }
+
+    /// Take the data and proof bundle apart without checking the proof
+    ///
+    /// # Returns
+    ///
+    /// The unverified data and the proof bundle
+    pub fn into_unverified_parts(self) -> (Vec<u8>, ProofBundle) {
+        (self.data, self.bundle)
+    }
+}
+
+impl VerifiedLeaf {
+    /// Get the leaf's data
+    ///
+    /// # Returns
+    ///
+    /// The verified data
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the index of the leaf
+    ///
+    /// Under `PairOrdering::Sorted` proofs do not address a leaf, so unless leaves are
+    /// positional the bundle's index is not authenticated and is not reported.
+    ///
+    /// # Returns
+    ///
+    /// The index, or None if the proof does not bind it
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Get the root the leaf was verified against
+    ///
+    /// # Returns
+    ///
+    /// The trusted root
+    pub fn root(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// Take the verified data
+    ///
+    /// # Returns
+    ///
+    /// The data
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl MerkleVerifier {
+    /// Check a batch of leaf bundles, keeping the leaves whose proofs are valid
+    ///
+    /// # Arguments
+    ///
+    /// * `bundles` - The leaf bundles
+    ///
+    /// # Returns
+    ///
+    /// The verified leaves in order, and the position of each rejected bundle with the
+    /// reason it was rejected
+    pub fn verify_leaf_bundles(
+        &self,
+        bundles: impl IntoIterator<Item = LeafBundle>,
+    ) -> (Vec<VerifiedLeaf>, Vec<(usize, VerificationError)>) {
+        let mut verified = Vec::new();
+        let mut rejected = Vec::new();
+        for (position, bundle) in bundles.into_iter().enumerate() {
+            match bundle.into_verified(self) {
+                Ok(leaf) => verified.push(leaf),
+                Err(error) => rejected.push((position, error)),
+            }
+        }
+        (verified, rejected)
+    }
+}
+
+#[cfg(feature = "build")]
+impl MerkleTree {
+    /// Generate a proof bundle for a leaf together with the leaf's data
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    /// # Returns
+    ///
+    /// The leaf bundle, or None if the index is out of range or the leaf's data is not
+    /// kept
+    pub fn generate_leaf_bundle(&self, index: usize) -> Option<LeafBundle> {
+        let data = self.get_leaf(index)?.to_vec();
+        Some(LeafBundle::new(data, self.generate_bundle(index)?))
+    }
+}
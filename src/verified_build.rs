@@ -0,0 +1,142 @@
+//! Building trees from untrusted leaf data against a trusted root or canopy
+//!
+//! A mirror may serve every leaf while only the root, or the top levels of the tree, come
+//! from a trusted source. The tree is built as usual and then compared with the trusted
+//! hashes from the root down, so a corrupted dataset is rejected with the lowest trusted
+//! node it changes and the leaves under that node, rather than with a bare root mismatch.
+
+use crate::canopy::Canopy;
+use crate::error::BuildVerifyError;
+use crate::hashing::HashScheme;
+use crate::index::NodeCoord;
+use crate::merkle_tree::{BuildOptions, MerkleTree};
+use crate::params::TreeParams;
+
+impl MerkleTree {
+    /// Create a tree like `new` and check it against a trusted root
+    ///
+    /// A root alone cannot tell which leaf is wrong, so a mismatch names the root node and
+    /// every leaf. Use `build_verified_with_canopy` to narrow it down.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaves, from any source
+    /// * `expected_root` - The root from a trusted source
+    ///
+    /// # Returns
+    ///
+    /// The tree, or `BuildVerifyError::Empty` or `BuildVerifyError::Mismatch` if the
+    /// leaves do not give the expected root
+    pub fn build_verified(
+        data: Vec<Vec<u8>>,
+        expected_root: &[u8],
+    ) -> Result<MerkleTree, BuildVerifyError> {
+        let params = TreeParams::new(HashScheme::Plain, false);
+        let tree = MerkleTree::build_with(data, params, BuildOptions::default(), None)
+            .map_err(BuildVerifyError::Build)?;
+        let top = tree
+            .levels()
+            .len()
+            .checked_sub(1)
+            .ok_or(BuildVerifyError::Empty)?;
+        check_levels(&tree, &[vec![expected_root.to_vec()]], top)?;
+        Ok(tree)
+    }
+
+    /// Create a tree and check it against a trusted canopy
+    ///
+    /// The tree is built with the canopy's parameters and compared with the canopy from
+    /// the root down. On a mismatch, the error names the lowest canopy node that differs,
+    /// following the first differing child at each level, so with a canopy of `h` levels
+    /// a single corrupted leaf is narrowed to a range of `2^(height - h)` leaves. A canopy
+    /// of the whole tree (`tree.canopy(usize::MAX)`) names the leaf itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaves, from any source
+    /// * `canopy` - The top levels of the tree, from a trusted source
+    ///
+    /// # Returns
+    ///
+    /// The tree, or `BuildVerifyError::LeafCountMismatch`, `BuildVerifyError::Empty` or
+    /// `BuildVerifyError::Mismatch` if the leaves do not give the canopy
+    pub fn build_verified_with_canopy(
+        data: Vec<Vec<u8>>,
+        canopy: &Canopy,
+    ) -> Result<MerkleTree, BuildVerifyError> {
+        if data.len() != canopy.leaf_count() {
+            return Err(BuildVerifyError::LeafCountMismatch {
+                expected: canopy.leaf_count(),
+                actual: data.len(),
+            });
+        }
+        let tree = MerkleTree::build_with(data, canopy.params(), BuildOptions::default(), None)
+            .map_err(BuildVerifyError::Build)?;
+        if canopy.levels().is_empty() {
+            return Ok(tree);
+        }
+        let top = tree
+            .levels()
+            .len()
+            .checked_sub(1)
+            .ok_or(BuildVerifyError::Empty)?;
+        // A malformed canopy is taller than the tree it claims to be of
+        let base_level = top.checked_sub(canopy.levels().len() - 1).ok_or(
+            BuildVerifyError::LeafCountMismatch {
+                expected: canopy.leaf_count(),
+                actual: tree.leaf_count(),
+            },
+        )?;
+        check_levels(&tree, canopy.levels(), base_level)?;
+        Ok(tree)
+    }
+}
+
+/// Compare a tree with trusted levels from the root down
+///
+/// # Arguments
+///
+/// * `tree` - The tree built from untrusted leaves
+/// * `trusted` - The trusted levels, from the lowest up to the root
+/// * `base_level` - The tree level of the lowest trusted level
+///
+/// # Returns
+///
+/// Ok if the roots match, or `BuildVerifyError::Mismatch` for the lowest differing node
+fn check_levels(
+    tree: &MerkleTree,
+    trusted: &[Vec<Vec<u8>>],
+    base_level: usize,
+) -> Result<(), BuildVerifyError> {
+    let built = &tree.levels()[base_level..];
+    let differs = |level: usize, index: usize| match trusted[level].get(index) {
+        Some(expected) => built[level].get(index) != Some(expected),
+        None => built[level].get(index).is_some(),
+    };
+    let mut level = trusted.len() - 1;
+    let mut index = 0;
+    if !differs(level, index) {
+        return Ok(());
+    }
+    // A node differs only if one of its children does, unless the trusted levels are
+    // malformed, in which case the search stops at the node
+    while level > 0 {
+        match (2 * index..2 * index + 2).find(|&child| differs(level - 1, child)) {
+            Some(child) => {
+                level -= 1;
+                index = child;
+            }
+            None => break,
+        }
+    }
+
+    let height = base_level + level;
+    let start = index.checked_shl(height as u32).unwrap_or(usize::MAX);
+    let width = 1usize.checked_shl(height as u32).unwrap_or(usize::MAX);
+    Err(BuildVerifyError::Mismatch {
+        node: NodeCoord::new(height as u32, index as u64),
+        leaves: start.min(tree.leaf_count())..start.saturating_add(width).min(tree.leaf_count()),
+        expected: trusted[level].get(index).cloned().unwrap_or_default(),
+        actual: built[level].get(index).cloned().unwrap_or_default(),
+    })
+}
@@ -0,0 +1,468 @@
+use crate::error::VerificationError;
+use crate::hashing::{HashConfig, HashScheme};
+use crate::multiproof::MultiProof;
+use crate::params::{TreeParams, TreeVersion};
+use crate::proof::{MerkleProof, ProofBundle, RootId};
+use crate::MerkleTree;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A standalone verifier for proofs against a trusted root
+///
+/// The verifier holds only what is needed to check proofs — the root, optionally the
+/// number of leaves and the root's fingerprint, and the tree's parameters — so it can be
+/// embedded in clients that never see the tree. With the `serde` feature it can be loaded
+/// from a small policy file, for example:
+///
+/// ```json
+/// {"root": "9ca0…65df", "leaf_count": 5, "algorithm": "Sha256",
+///  "scheme": "DomainSeparated", "ordering": "Positional", "padding": "CarryUp"}
+/// ```
+///
+/// `padding` and `positional` may be left out for trees with the default padding and
+/// without leaf indices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MerkleVerifier {
+    /// The trusted root hash
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    root: Vec<u8>,
+    /// The number of leaves committed to by the root, if known
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    leaf_count: Option<usize>,
+    /// The fingerprint of the tree state the root belongs to, if known
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    root_id: Option<RootId>,
+    /// The parameters the tree was built with
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    params: TreeParams,
+}
+
+impl MerkleVerifier {
+    /// Create a verifier for a trusted root
+    ///
+    /// The verifier assumes the defaults of `MerkleTree::builder()`: SHA-256,
+    /// domain-separated hashing and positional pairs.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The trusted root hash
+    ///
+    /// # Returns
+    ///
+    /// A new verifier
+    pub fn new(root: impl Into<Vec<u8>>) -> Self {
+        MerkleVerifier {
+            root: root.into(),
+            leaf_count: None,
+            root_id: None,
+            params: HashScheme::DomainSeparated.into(),
+        }
+    }
+
+    /// Create a verifier for a trusted root given as a hexadecimal string
+    ///
+    /// # Arguments
+    ///
+    /// * `root_hex` - The trusted root hash in hexadecimal
+    ///
+    /// # Returns
+    ///
+    /// A new verifier, or an error if the string is not valid hexadecimal
+    pub fn from_hex(root_hex: &str) -> Result<Self, hex::FromHexError> {
+        Ok(Self::new(hex::decode(root_hex)?))
+    }
+
+    /// Create a verifier matching an existing tree
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The tree whose root, leaf count and parameters to use
+    ///
+    /// # Returns
+    ///
+    /// A new verifier, or None if the tree is empty
+    #[cfg(feature = "build")]
+    pub fn for_tree(tree: &MerkleTree) -> Option<Self> {
+        Some(MerkleVerifier {
+            root: tree.root_hash()?,
+            leaf_count: Some(tree.committed_leaf_count()),
+            root_id: None,
+            params: tree.params(),
+        })
+    }
+
+    /// Set the number of leaves committed to by the root
+    ///
+    /// Proofs whose shape does not fit a tree of this size are then rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_count` - The number of leaves
+    ///
+    /// # Returns
+    ///
+    /// The updated verifier
+    pub fn with_leaf_count(mut self, leaf_count: usize) -> Self {
+        self.leaf_count = Some(leaf_count);
+        self
+    }
+
+    /// Set the fingerprint of the tree state the root belongs to
+    ///
+    /// A proof that is rejected for not leading to the root, or for not fitting the
+    /// tree's shape, is then reported as `VerificationError::WrongRootEpoch` if it carries
+    /// a stamp (see `MerkleTree::generate_stamped_proof`) of a different state and
+    /// verifies against the stamped root: the proof was made from another state of the
+    /// tree, and the root is most likely stale. A stamp the proof does not verify against
+    /// is ignored, so a tampered proof is still reported as invalid.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_id` - The fingerprint, as returned by `MerkleTree::root_id` along with the
+    ///   root
+    ///
+    /// # Returns
+    ///
+    /// The updated verifier
+    pub fn with_root_id(mut self, root_id: RootId) -> Self {
+        self.root_id = Some(root_id);
+        self
+    }
+
+    /// Set the hash configuration the tree was built with
+    ///
+    /// The padding and whether leaves are positional are left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme)
+    ///
+    /// # Returns
+    ///
+    /// The updated verifier
+    pub fn with_hash_config(mut self, hasher: impl Into<HashConfig>) -> Self {
+        self.params.hasher = hasher.into();
+        self
+    }
+
+    /// Set the parameters the tree was built with
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The tree parameters
+    ///
+    /// # Returns
+    ///
+    /// The updated verifier
+    pub fn with_params(mut self, params: TreeParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Get the trusted root hash
+    ///
+    /// # Returns
+    ///
+    /// The root hash
+    pub fn root(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// Get the number of leaves committed to by the root
+    ///
+    /// # Returns
+    ///
+    /// The leaf count, or None if it is not known
+    pub fn leaf_count(&self) -> Option<usize> {
+        self.leaf_count
+    }
+
+    /// Get the fingerprint of the tree state the root belongs to
+    ///
+    /// # Returns
+    ///
+    /// The fingerprint, or None if it is not known
+    pub fn root_id(&self) -> Option<RootId> {
+        self.root_id
+    }
+
+    /// Get the hash configuration the verifier uses
+    ///
+    /// # Returns
+    ///
+    /// The hash configuration
+    pub fn hash_config(&self) -> HashConfig {
+        self.params.hasher
+    }
+
+    /// Get the tree parameters the verifier uses
+    ///
+    /// # Returns
+    ///
+    /// The tree parameters
+    pub fn params(&self) -> TreeParams {
+        self.params
+    }
+
+    /// Get the version of the semantics the verifier expects
+    ///
+    /// # Returns
+    ///
+    /// The version matching the verifier's parameters, or None if they are unversioned
+    pub fn version(&self) -> Option<TreeVersion> {
+        TreeVersion::of(self.params)
+    }
+
+    /// Verify a proof for a piece of data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    ///
+    /// # Returns
+    ///
+    /// Ok if the proof is valid, or the reason it was rejected
+    pub fn verify(&self, data: &[u8], proof: &MerkleProof) -> Result<(), VerificationError> {
+        let result = MerkleTree::verify_proof_checked(
+            self.params,
+            data,
+            proof,
+            &self.root,
+            self.leaf_count,
+            None,
+        );
+        self.check_root_id(data, proof, None, result)
+    }
+
+    /// Verify a proof bundle for a piece of data
+    ///
+    /// A bundle tagged with a version other than the verifier's is rejected with
+    /// `VerificationError::VersionMismatch`. The bundle's parameters must then match the
+    /// verifier's, including the padding and whether leaves are positional; otherwise the
+    /// bundle is rejected with `VerificationError::ParamsMismatch` before any hashing. The
+    /// proof's shape is then checked against the bundle's leaf count and index.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `bundle` - The proof bundle to verify
+    ///
+    /// # Returns
+    ///
+    /// Ok if the proof is valid, or the reason it was rejected
+    pub fn verify_bundle(
+        &self,
+        data: &[u8],
+        bundle: &ProofBundle,
+    ) -> Result<(), VerificationError> {
+        let expected = self.params;
+        if let Some(actual) = bundle.version {
+            if TreeVersion::of(expected) != Some(actual) {
+                return Err(VerificationError::VersionMismatch {
+                    expected: TreeVersion::of(expected),
+                    actual,
+                });
+            }
+        }
+        if bundle.params != expected {
+            return Err(VerificationError::ParamsMismatch {
+                expected,
+                actual: bundle.params,
+            });
+        }
+        if let Some(expected) = self.leaf_count {
+            if bundle.leaf_count != expected {
+                return Err(VerificationError::LeafCountMismatch {
+                    expected,
+                    actual: bundle.leaf_count,
+                });
+            }
+        }
+
+        let result = MerkleTree::verify_proof_checked(
+            self.params,
+            data,
+            &bundle.proof,
+            &self.root,
+            Some(bundle.leaf_count),
+            Some(bundle.index),
+        );
+        self.check_root_id(data, &bundle.proof, Some(bundle.index), result)
+    }
+
+    /// Report a rejected proof from another state of the tree as a stale root
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data that was verified
+    /// * `proof` - The proof that was verified
+    /// * `index` - The index of the leaf, if known
+    /// * `result` - The result of verifying it
+    ///
+    /// # Returns
+    ///
+    /// `VerificationError::WrongRootEpoch` if the proof did not lead to the root or fit
+    /// the tree's shape, its stamp differs from the verifier's fingerprint, and it
+    /// verifies against the stamped state; otherwise the result unchanged
+    fn check_root_id(
+        &self,
+        data: &[u8],
+        proof: &MerkleProof,
+        index: Option<usize>,
+        result: Result<(), VerificationError>,
+    ) -> Result<(), VerificationError> {
+        let (Some(stamp), Some(verifier_root_id)) = (proof.stamp(), self.root_id) else {
+            return result;
+        };
+        let proof_root_id = stamp.root_id();
+        let stale = matches!(
+            result,
+            Err(VerificationError::RootMismatch | VerificationError::ShapeMismatch { .. })
+        ) && proof_root_id != verifier_root_id
+            && usize::try_from(stamp.leaf_count).is_ok_and(|leaf_count| {
+                MerkleTree::verify_proof_checked(
+                    self.params,
+                    data,
+                    proof,
+                    &stamp.root,
+                    Some(leaf_count),
+                    index,
+                )
+                .is_ok()
+            });
+        if stale {
+            return Err(VerificationError::WrongRootEpoch {
+                proof_root_id,
+                verifier_root_id,
+            });
+        }
+        result
+    }
+
+    /// Verify a multiproof for several pieces of data
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The data of the proven leaves, in the order of the proof's indices
+    /// * `proof` - The multiproof to verify
+    ///
+    /// # Returns
+    ///
+    /// Ok if the multiproof is valid, or the reason it was rejected
+    pub fn verify_multiproof(
+        &self,
+        leaves: &[impl AsRef<[u8]>],
+        proof: &MultiProof,
+    ) -> Result<(), VerificationError> {
+        if let Some(expected) = self.leaf_count {
+            if proof.leaf_count != expected {
+                return Err(VerificationError::LeafCountMismatch {
+                    expected,
+                    actual: proof.leaf_count,
+                });
+            }
+        }
+        if self.root.len() != self.params.hasher.digest_len() {
+            return Err(VerificationError::InvalidRootLength {
+                expected: self.params.hasher.digest_len(),
+                actual: self.root.len(),
+            });
+        }
+
+        if proof.compute_root(&self.params.hasher, leaves)? == self.root {
+            Ok(())
+        } else {
+            Err(VerificationError::RootMismatch)
+        }
+    }
+}
+
+/// Verifies proofs against several trusted roots at once
+///
+/// Useful while migrating between hash configurations, when clients may hold proofs
+/// against either the old or the new commitment. With the `serde` feature the list can
+/// be loaded from a policy file, for example:
+///
+/// ```json
+/// {"verifiers": [
+///   {"root": "9ca0…65df", "algorithm": "Sha256", "scheme": "DomainSeparated", "ordering": "Positional"},
+///   {"root": "4d1e…0a7c", "algorithm": "Keccak256", "scheme": "Plain", "ordering": "Sorted"}
+/// ]}
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiVerifier {
+    /// The verifiers, in order of preference
+    verifiers: Vec<MerkleVerifier>,
+}
+
+impl MultiVerifier {
+    /// Create a verifier for several roots
+    ///
+    /// # Arguments
+    ///
+    /// * `verifiers` - The verifiers, in order of preference
+    ///
+    /// # Returns
+    ///
+    /// A new multi-verifier
+    pub fn new(verifiers: Vec<MerkleVerifier>) -> Self {
+        MultiVerifier { verifiers }
+    }
+
+    /// Get the verifiers
+    ///
+    /// # Returns
+    ///
+    /// The verifiers, in order of preference
+    pub fn verifiers(&self) -> &[MerkleVerifier] {
+        &self.verifiers
+    }
+
+    /// Verify a proof against whichever root it was made for
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    ///
+    /// # Returns
+    ///
+    /// The position of the first verifier that accepts the proof, or None if none does
+    pub fn verify_any(&self, data: &[u8], proof: &MerkleProof) -> Option<usize> {
+        self.verifiers
+            .iter()
+            .position(|verifier| verifier.verify(data, proof).is_ok())
+    }
+
+    /// Verify that a piece of data is committed to by every root
+    ///
+    /// A proof only fits one tree, so one proof is needed per verifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proofs` - A proof for each verifier, in the same order
+    ///
+    /// # Returns
+    ///
+    /// True if there is at least one verifier, one proof per verifier and every proof is
+    /// accepted by its verifier, false otherwise
+    pub fn verify_all(&self, data: &[u8], proofs: &[MerkleProof]) -> bool {
+        !self.verifiers.is_empty()
+            && proofs.len() == self.verifiers.len()
+            && self
+                .verifiers
+                .iter()
+                .zip(proofs)
+                .all(|(verifier, proof)| verifier.verify(data, proof).is_ok())
+    }
+}
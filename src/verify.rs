@@ -0,0 +1,949 @@
+//! Proof verification, available without the tree-construction machinery
+//!
+//! Everything here compiles with `default-features = false, features = ["verify"]`:
+//! the associated verification functions of `MerkleTree`, the proof types, and the
+//! shape checks they rely on. The paths are the same with and without the `build`
+//! feature, so a verifier-only consumer can switch configurations without code changes:
+//!
+//! ```
+//! use merkleproof::{HashConfig, HashScheme, MerkleProof, MerkleTree};
+//!
+//! // A two-leaf tree under `MerkleTree::new`'s plain hashing, committed by hand
+//! let hasher = HashConfig::from(HashScheme::Plain);
+//! let (a, b) = (hasher.hash_leaf(b"a"), hasher.hash_leaf(b"b"));
+//! let root = hasher.hash_pair(&a, &b);
+//!
+//! let proof = MerkleProof::new(vec![(b, false)]);
+//! assert!(MerkleTree::verify_proof(b"a", &proof, &root));
+//! assert!(MerkleTree::verify_proof_checked(HashScheme::Plain, b"a", &proof, &root, Some(2), Some(0)).is_ok());
+//! assert!(!MerkleTree::verify_proof(b"b", &proof, &root));
+//! ```
+
+use crate::error::VerificationError;
+use crate::hashing::{HashConfig, HashScheme, NodeHasher, PairOrdering};
+#[cfg(feature = "build")]
+use crate::merkle_tree::MerkleTree;
+use crate::params::{PaddingStrategy, TreeParams};
+use crate::proof::MerkleProof;
+use std::io::{self, Read};
+
+/// The maximum number of steps accepted in a proof by the checked verifier
+///
+/// A tree can hold at most `usize::MAX` leaves, so no genuine proof is longer than this.
+pub const MAX_PROOF_STEPS: usize = 64;
+
+/// The type hosting the verification functions in verify-only builds
+///
+/// Without the `build` feature a tree cannot be constructed. The type is kept so that
+/// `MerkleTree::verify_proof` and the other associated verification functions have the
+/// same paths as in full builds.
+#[cfg(not(feature = "build"))]
+#[derive(Debug)]
+pub struct MerkleTree {
+    /// Prevents construction
+    _private: (),
+}
+
+impl MerkleTree {
+    /// Verify a proof against the root hash
+    ///
+    /// This uses `HashScheme::Plain` hashing and cannot check the proof's length against
+    /// the tree, so it should only be used with trees built by `MerkleTree::new`. Prefer
+    /// `verify` or `verify_proof_with` when the tree or its leaf count is available. A
+    /// proof with more than `MAX_PROOF_STEPS` steps is rejected without any hashing; use
+    /// `verify_proof_with_limit` to choose a different limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_proof(data: &[u8], proof: &MerkleProof, root_hash: &[u8]) -> bool {
+        Self::verify_proof_with(HashScheme::Plain, data, proof, root_hash, None)
+    }
+
+    /// Verify a proof against the root hash, with a limit on the number of steps
+    ///
+    /// Like `verify_proof`, this uses `HashScheme::Plain` hashing. The limit is checked
+    /// before anything is hashed, so a proof with millions of steps from a hostile peer
+    /// costs no more to reject than a short one. Only the steps of a proof within the
+    /// limit are hashed, so the limit caps the work verification can be made to do; it
+    /// may be raised past `MAX_PROOF_STEPS`, for example for fabricated proofs in tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `max_steps` - The largest number of steps to accept
+    ///
+    /// # Returns
+    ///
+    /// Whether the proof is valid, or `VerificationError::TooDeep` if it has more than
+    /// `max_steps` steps
+    pub fn verify_proof_with_limit(
+        data: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        max_steps: usize,
+    ) -> Result<bool, VerificationError> {
+        if proof.len() > max_steps {
+            return Err(VerificationError::TooDeep {
+                steps: proof.len(),
+                max: max_steps,
+            });
+        }
+        let hasher = HashConfig::from(HashScheme::Plain);
+        Ok(siblings_fit(proof, hasher.digest_len())
+            && fold_proof(&hasher, hasher.hash_leaf(data), proof) == root_hash)
+    }
+
+    /// Compute every intermediate hash of a proof's verification
+    ///
+    /// Like `verify_proof`, this uses `HashScheme::Plain` hashing. See
+    /// `verification_trace_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to trace
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash, followed by the hash after each proof step
+    pub fn verification_trace(data: &[u8], proof: &MerkleProof) -> Vec<Vec<u8>> {
+        Self::verification_trace_with(HashScheme::Plain, data, proof)
+    }
+
+    /// Compute every intermediate hash of a proof's verification using the given hash
+    /// configuration
+    ///
+    /// The trace starts with the leaf hash and ends with the root the proof leads to, so
+    /// it has one more entry than the proof has steps. Verification succeeds exactly when
+    /// the last entry is the trusted root. Comparing the traces computed by two parties
+    /// locates the first step at which they diverge:
+    ///
+    /// ```
+    /// use merkleproof::{HashConfig, HashScheme, MerkleProof, MerkleTree};
+    ///
+    /// // The second leaf of a two-leaf tree built by `MerkleTree::new`
+    /// let plain = HashConfig::from(HashScheme::Plain);
+    /// let (first, second) = (plain.hash_leaf(&[0]), plain.hash_leaf(&[1]));
+    /// let root = plain.hash_pair(&first, &second);
+    /// let proof = MerkleProof::new(vec![(first, true)]);
+    ///
+    /// let ours = MerkleTree::verification_trace(&[1], &proof);
+    /// let theirs = MerkleTree::verification_trace_with(HashScheme::DomainSeparated, &[1], &proof);
+    /// assert_eq!(ours.last(), Some(&root));
+    ///
+    /// let first_mismatch = ours.iter().zip(&theirs).position(|(a, b)| a != b);
+    /// assert_eq!(first_mismatch, Some(0)); // the leaf hashes already differ
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees)
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to trace
+    ///
+    /// # Returns
+    ///
+    /// The leaf hash, followed by the hash after each proof step
+    pub fn verification_trace_with(
+        hasher: impl Into<HashConfig>,
+        data: &[u8],
+        proof: &MerkleProof,
+    ) -> Vec<Vec<u8>> {
+        let hasher = hasher.into();
+        trace_proof(&hasher, hasher.hash_leaf(data), proof).collect()
+    }
+
+    /// Verify a proof for a leaf hash against the root hash
+    ///
+    /// Like `verify_proof`, this uses `HashScheme::Plain` hashing for the branches and
+    /// cannot check the proof's length. Prefer `verify_proof_for_hash_with` when the leaf
+    /// count is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hash` - The hash of the leaf to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_proof_for_hash(leaf_hash: &[u8], proof: &MerkleProof, root_hash: &[u8]) -> bool {
+        Self::verify_proof_for_hash_with(HashScheme::Plain, leaf_hash, proof, root_hash, None)
+    }
+
+    /// Verify a proof for a leaf hash against the root hash using the given hash
+    /// configuration
+    ///
+    /// The leaf hash is taken as is, so it must be the value stored on the tree's leaf
+    /// level. As with `verify_proof_with`, proofs with more than `MAX_PROOF_STEPS` steps
+    /// are rejected, and when the number of leaves is known the proof's shape is checked
+    /// too.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or just the hash configuration or scheme, for trees
+    ///   with the default padding) the tree was built with
+    /// * `leaf_hash` - The hash of the leaf to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `leaf_count` - The number of leaves in the tree, if known
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_proof_for_hash_with(
+        params: impl Into<TreeParams>,
+        leaf_hash: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        leaf_count: Option<usize>,
+    ) -> bool {
+        let params = params.into();
+        let hasher = params.hasher;
+        if proof.len() > MAX_PROOF_STEPS {
+            return false;
+        }
+        if let Some(leaf_count) = leaf_count {
+            if !proof_fits_shape(proof, leaf_count, params.padding, hasher.ordering) {
+                return false;
+            }
+        }
+
+        leaf_hash.len() == hasher.digest_len()
+            && siblings_fit(proof, hasher.digest_len())
+            && fold_proof(&hasher, leaf_hash.to_vec(), proof) == root_hash
+    }
+
+    /// Verify a proof for a string against the root hash
+    ///
+    /// Like `verify_proof`, this uses `HashScheme::Plain` hashing and should only be used
+    /// with trees built by `MerkleTree::from_strings` or `MerkleTree::new`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The string to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_proof_str(s: &str, proof: &MerkleProof, root_hash: &[u8]) -> bool {
+        Self::verify_proof(s.as_bytes(), proof, root_hash)
+    }
+
+    /// Verify a proof against the root hash using the given hash configuration
+    ///
+    /// When the number of leaves committed to by `root_hash` is known, the proof's shape
+    /// is checked against it before hashing: a proof that is shorter or longer than the
+    /// path from a real leaf to the root is rejected. This stops an internal node from
+    /// being passed off as a leaf even under `HashScheme::Plain`. Sibling hashes that are
+    /// not digests of the hasher's length, and proofs with more than `MAX_PROOF_STEPS`
+    /// steps, are always rejected.
+    ///
+    /// For a tree with positional leaves the leaf's index is read from the proof's
+    /// directions, so the leaf count must be given and the pairs must be positional;
+    /// otherwise the proof is rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or just the hash configuration or scheme, for trees
+    ///   with the default padding) the tree was built with
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `leaf_count` - The number of leaves in the tree, if known
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_proof_with(
+        params: impl Into<TreeParams>,
+        data: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        leaf_count: Option<usize>,
+    ) -> bool {
+        let params = params.into();
+        let hasher = params.hasher;
+        if proof.len() > MAX_PROOF_STEPS {
+            return false;
+        }
+        if let Some(leaf_count) = leaf_count {
+            if !proof_fits_shape(proof, leaf_count, params.padding, hasher.ordering) {
+                return false;
+            }
+        }
+
+        let leaf_hash = if params.positional {
+            match leaf_index_of(&params, proof, leaf_count, None) {
+                Some(index) => hasher.hash_leaf(&positional_leaf(index, data)),
+                None => return false,
+            }
+        } else {
+            hasher.hash_leaf(data)
+        };
+        siblings_fit(proof, hasher.digest_len())
+            && fold_proof(&hasher, leaf_hash, proof) == root_hash
+    }
+
+    /// Verify a proof for data read from a reader
+    ///
+    /// This uses `HashScheme::Plain` hashing like `verify_proof`. The data is hashed a
+    /// buffer at a time, so a leaf of any size is verified in constant memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The data to verify, read to its end
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// Whether the proof is valid, or the error that interrupted reading
+    pub fn verify_proof_from_reader(
+        reader: impl Read,
+        proof: &MerkleProof,
+        root_hash: &[u8],
+    ) -> io::Result<bool> {
+        Self::verify_proof_from_reader_with(HashScheme::Plain, reader, proof, root_hash, None)
+    }
+
+    /// Verify a proof for data read from a reader using a specific hash configuration
+    ///
+    /// This is `verify_proof_with` with the data hashed a buffer at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or hash configuration or scheme) the tree was built
+    ///   with
+    /// * `reader` - The data to verify, read to its end
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `leaf_count` - The number of leaves in the tree, if known
+    ///
+    /// # Returns
+    ///
+    /// Whether the proof is valid, or the error that interrupted reading
+    pub fn verify_proof_from_reader_with(
+        params: impl Into<TreeParams>,
+        reader: impl Read,
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        leaf_count: Option<usize>,
+    ) -> io::Result<bool> {
+        let params = params.into();
+        let hasher = params.hasher;
+        if proof.len() > MAX_PROOF_STEPS {
+            return Ok(false);
+        }
+        if let Some(leaf_count) = leaf_count {
+            if !proof_fits_shape(proof, leaf_count, params.padding, hasher.ordering) {
+                return Ok(false);
+            }
+        }
+        if !siblings_fit(proof, hasher.digest_len()) {
+            return Ok(false);
+        }
+
+        let leaf_hash = if params.positional {
+            let Some(index) = leaf_index_of(&params, proof, leaf_count, None) else {
+                return Ok(false);
+            };
+            let prefix = (index as u64).to_le_bytes();
+            hasher.hash_leaf_reader(io::Read::chain(&prefix[..], reader))?
+        } else {
+            hasher.hash_leaf_reader(reader)?
+        };
+        Ok(fold_proof(&hasher, leaf_hash, proof) == root_hash)
+    }
+
+    /// Verify a proof against a 32-byte root hash without allocating
+    ///
+    /// This uses `HashScheme::Plain` SHA-256 hashing like `verify_proof`, but takes the
+    /// proof as an iterator of borrowed steps and hashes into a buffer on the stack, so
+    /// it makes no heap allocations. It is meant for embedded and other constrained
+    /// targets. The crate itself still depends on `std`; this only keeps verification
+    /// off the heap. A proof with more than `MAX_PROOF_STEPS` steps is rejected when its
+    /// next step is reached, so at most `MAX_PROOF_STEPS` steps are ever hashed.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to verify
+    /// * `proof_steps` - The proof's steps, each a sibling hash and whether the sibling is
+    ///   on the left
+    /// * `root` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise (including when a sibling hash is not
+    /// 32 bytes long)
+    pub fn verify_proof_in_place<'a>(
+        data: &[u8],
+        proof_steps: impl IntoIterator<Item = (&'a [u8], bool)>,
+        root: &[u8; 32],
+    ) -> bool {
+        Self::verify_proof_in_place_with(HashScheme::Plain, data, proof_steps, root)
+    }
+
+    /// Verify a proof against a 32-byte root hash without allocating, using the given
+    /// hash configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or just the scheme, for SHA-256 trees) the
+    ///   tree was built with
+    /// * `data` - The data to verify
+    /// * `proof_steps` - The proof's steps, each a sibling hash and whether the sibling is
+    ///   on the left
+    /// * `root` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise (including when the hash function's
+    /// digests or a sibling hash are not 32 bytes long, or the proof has more than
+    /// `MAX_PROOF_STEPS` steps)
+    pub fn verify_proof_in_place_with<'a>(
+        hasher: impl Into<HashConfig>,
+        data: &[u8],
+        proof_steps: impl IntoIterator<Item = (&'a [u8], bool)>,
+        root: &[u8; 32],
+    ) -> bool {
+        let hasher = hasher.into();
+        if hasher.digest_len() != 32 {
+            return false;
+        }
+
+        let mut current = [0u8; 32];
+        let mut next = [0u8; 32];
+        hasher.hash_leaf_into(data, &mut current);
+        for (step, (sibling, is_left)) in proof_steps.into_iter().enumerate() {
+            if step == MAX_PROOF_STEPS || sibling.len() != 32 {
+                return false;
+            }
+            if is_left {
+                hasher.hash_pair_into(sibling, &current, &mut next);
+            } else {
+                hasher.hash_pair_into(&current, sibling, &mut next);
+            }
+            current = next;
+        }
+        current == *root
+    }
+
+    /// Verify a proof for a leaf of a positional tree
+    ///
+    /// The leaf is hashed as `H(le64(index) || data)`, so the proof only verifies for the
+    /// index the data was committed at. When the leaf count is known the proof's shape is
+    /// checked too, and under `PairOrdering::Positional` its directions must address the
+    /// index.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or just the hash configuration or scheme, for trees
+    ///   with the default padding) the tree was built with
+    /// * `index` - The index of the leaf
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `leaf_count` - The number of leaves in the tree, if known
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_proof_positional(
+        params: impl Into<TreeParams>,
+        index: usize,
+        data: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        leaf_count: Option<usize>,
+    ) -> bool {
+        // The index is prefixed here, so it is not derived again from the proof
+        let params = TreeParams {
+            positional: false,
+            ..params.into()
+        };
+        Self::verify_proof_checked(
+            params,
+            &positional_leaf(index, data),
+            proof,
+            root_hash,
+            leaf_count,
+            Some(index),
+        )
+        .is_ok()
+    }
+
+    /// Verify a subtree proof against the root hash
+    ///
+    /// This uses `HashScheme::Plain` hashing; see `verify_subtree_proof_with` for trees
+    /// built with another scheme.
+    ///
+    /// # Arguments
+    ///
+    /// * `subtree_root` - The root hash of the subtree, as rebuilt by the verifier
+    /// * `proof` - The proof generated by `generate_subtree_proof`
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_subtree_proof(
+        subtree_root: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+    ) -> bool {
+        Self::verify_subtree_proof_with(HashScheme::Plain, subtree_root, proof, root_hash)
+    }
+
+    /// Verify a subtree proof against the root hash using the given hash configuration
+    ///
+    /// A proof with more than `MAX_PROOF_STEPS` steps is rejected without any hashing.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash configuration (or scheme) the tree was built with
+    /// * `subtree_root` - The root hash of the subtree, as rebuilt by the verifier
+    /// * `proof` - The proof generated by `generate_subtree_proof`
+    /// * `root_hash` - The root hash to verify against
+    ///
+    /// # Returns
+    ///
+    /// True if the proof is valid, false otherwise
+    pub fn verify_subtree_proof_with(
+        hasher: impl Into<HashConfig>,
+        subtree_root: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+    ) -> bool {
+        let hasher = hasher.into();
+        proof.len() <= MAX_PROOF_STEPS
+            && siblings_fit(proof, hasher.digest_len())
+            && fold_proof(&hasher, subtree_root.to_vec(), proof) == root_hash
+    }
+
+    /// Verify a proof, validating its structure before any hashing
+    ///
+    /// Unlike `verify_proof_with`, the proof is rejected with a descriptive error if it is
+    /// malformed: the root and every sibling must be exactly the hasher's digest length and
+    /// the proof may have at most `MAX_PROOF_STEPS` steps. When the leaf count is known the
+    /// proof must also fit the tree's shape, and when the leaf index is known as well the
+    /// proof's directions must address that leaf (directions carry no meaning under
+    /// `PairOrdering::Sorted`, so only the proof's length is checked there). For a tree
+    /// with positional leaves the index hashed with the data is the one the proof's
+    /// directions address, or `leaf_index` when they cannot be read; if neither is known
+    /// the proof is rejected with `VerificationError::RootMismatch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The parameters (or hash configuration or scheme) the tree was built
+    ///   with
+    /// * `data` - The data to verify
+    /// * `proof` - The proof to verify
+    /// * `root_hash` - The root hash to verify against
+    /// * `leaf_count` - The number of leaves in the tree, if known
+    /// * `leaf_index` - The index of the leaf being proven, if known (only checked
+    ///   together with `leaf_count`)
+    ///
+    /// # Returns
+    ///
+    /// Ok if the proof is valid, or the reason it was rejected
+    pub fn verify_proof_checked(
+        params: impl Into<TreeParams>,
+        data: &[u8],
+        proof: &MerkleProof,
+        root_hash: &[u8],
+        leaf_count: Option<usize>,
+        leaf_index: Option<usize>,
+    ) -> Result<(), VerificationError> {
+        let params = params.into();
+        let hasher = params.hasher;
+        let digest_len = hasher.digest_len();
+        if root_hash.len() != digest_len {
+            return Err(VerificationError::InvalidRootLength {
+                expected: digest_len,
+                actual: root_hash.len(),
+            });
+        }
+        if proof.len() > MAX_PROOF_STEPS {
+            return Err(VerificationError::TooDeep {
+                steps: proof.len(),
+                max: MAX_PROOF_STEPS,
+            });
+        }
+        for (step, (sibling_hash, _)) in proof.iter().enumerate() {
+            if sibling_hash.len() != digest_len {
+                return Err(VerificationError::InvalidSiblingLength {
+                    step,
+                    expected: digest_len,
+                    actual: sibling_hash.len(),
+                });
+            }
+        }
+
+        if let Some(leaf_count) = leaf_count {
+            if !proof_fits_shape(proof, leaf_count, params.padding, hasher.ordering) {
+                return Err(VerificationError::ShapeMismatch {
+                    steps: proof.len(),
+                    leaf_count,
+                });
+            }
+            if let (Some(expected), PairOrdering::Positional) = (leaf_index, hasher.ordering) {
+                let index =
+                    proof_leaf_index(proof, leaf_count, params.padding).unwrap_or(usize::MAX);
+                if index != expected {
+                    return Err(VerificationError::IndexMismatch {
+                        expected,
+                        actual: index,
+                    });
+                }
+            }
+        }
+
+        let leaf_hash = if params.positional {
+            match leaf_index_of(&params, proof, leaf_count, leaf_index) {
+                Some(index) => hasher.hash_leaf(&positional_leaf(index, data)),
+                None => return Err(VerificationError::RootMismatch),
+            }
+        } else {
+            hasher.hash_leaf(data)
+        };
+        if fold_proof(&hasher, leaf_hash, proof) == root_hash {
+            Ok(())
+        } else {
+            Err(VerificationError::RootMismatch)
+        }
+    }
+}
+
+/// Prefix a leaf's data with its index for positional trees
+///
+/// # Arguments
+///
+/// * `index` - The index of the leaf
+/// * `data` - The leaf's data
+///
+/// # Returns
+///
+/// `le64(index) || data`
+pub(crate) fn positional_leaf(index: usize, data: &[u8]) -> Vec<u8> {
+    [&(index as u64).to_le_bytes()[..], data].concat()
+}
+
+/// Find the index a positional tree hashes a proven leaf's data with
+///
+/// # Arguments
+///
+/// * `params` - The tree's parameters
+/// * `proof` - The proof of the leaf
+/// * `leaf_count` - The number of leaves in the tree, if known
+/// * `leaf_index` - The index of the leaf, if known
+///
+/// # Returns
+///
+/// The index the proof's directions address when the pairs are positional and the leaf
+/// count is known, `leaf_index` otherwise, or None if neither is available
+fn leaf_index_of(
+    params: &TreeParams,
+    proof: &MerkleProof,
+    leaf_count: Option<usize>,
+    leaf_index: Option<usize>,
+) -> Option<usize> {
+    match (params.hasher.ordering, leaf_count) {
+        (PairOrdering::Positional, Some(leaf_count)) => {
+            proof_leaf_index(proof, leaf_count, params.padding)
+        }
+        _ => leaf_index,
+    }
+}
+
+/// Compute the number of steps in the longest proof of a tree
+///
+/// This is the tree's depth, and the length of the first leaf's proof. Under
+/// `PaddingStrategy::Complete` every proof has this length; otherwise a node carried up
+/// unpaired contributes no step, so the proofs of later leaves may be shorter (see
+/// `expected_proof_len_for_index`).
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves in the tree, excluding padding
+/// * `padding` - How the tree's odd levels are padded
+///
+/// # Returns
+///
+/// The number of steps (0 for an empty or single-leaf tree)
+pub fn expected_proof_len(leaf_count: usize, padding: PaddingStrategy) -> usize {
+    level_sizes(leaf_count, padding).len().saturating_sub(1)
+}
+
+/// Compute the number of steps in the proof of a specific leaf
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves in the tree, excluding padding
+/// * `padding` - How the tree's odd levels are padded
+/// * `index` - The index of the leaf
+///
+/// # Returns
+///
+/// The number of levels at which the leaf's ancestor has a sibling, or None if the index
+/// is out of range
+pub fn expected_proof_len_for_index(
+    leaf_count: usize,
+    padding: PaddingStrategy,
+    index: usize,
+) -> Option<usize> {
+    if index >= leaf_count {
+        return None;
+    }
+    Some(path_len(leaf_count, padding, index))
+}
+
+/// Check that every sibling hash in a proof is a digest of the expected length
+///
+/// # Arguments
+///
+/// * `proof` - The proof to check
+/// * `digest_len` - The digest length of the hasher
+///
+/// # Returns
+///
+/// True if every sibling hash has the digest length
+pub(crate) fn siblings_fit(proof: &MerkleProof, digest_len: usize) -> bool {
+    proof.iter().all(|(hash, _)| hash.len() == digest_len)
+}
+
+/// Hash a node up to the root along a proof
+///
+/// # Arguments
+///
+/// * `hasher` - The configuration used to hash branches
+/// * `hash` - The hash of the starting node
+/// * `proof` - The proof steps from the node to the root
+///
+/// # Returns
+///
+/// The computed root hash
+pub(crate) fn fold_proof(hasher: &impl NodeHasher, hash: Vec<u8>, proof: &MerkleProof) -> Vec<u8> {
+    trace_proof(hasher, hash, proof)
+        .last()
+        .expect("a trace starts with the starting node")
+}
+
+/// Hash a node up to the root along a proof, yielding every intermediate hash
+///
+/// # Arguments
+///
+/// * `hasher` - The configuration used to hash branches
+/// * `hash` - The hash of the starting node
+/// * `proof` - The proof steps from the node to the root
+///
+/// # Returns
+///
+/// An iterator over the starting hash followed by the hash after each step, ending with
+/// the computed root hash
+pub(crate) fn trace_proof<'a>(
+    hasher: &'a impl NodeHasher,
+    hash: Vec<u8>,
+    proof: &'a MerkleProof,
+) -> impl Iterator<Item = Vec<u8>> + 'a {
+    let mut steps = proof.iter();
+    std::iter::successors(Some(hash), move |current_hash| {
+        let (sibling_hash, is_left) = steps.next()?;
+        Some(if *is_left {
+            hasher.hash_pair(sibling_hash, current_hash)
+        } else {
+            hasher.hash_pair(current_hash, sibling_hash)
+        })
+    })
+}
+
+/// Compute the number of nodes on each level of a tree with `leaf_count` leaves
+///
+/// Under `PaddingStrategy::DuplicateLast` the leaf level is padded to an even length by
+/// duplicating the last leaf; an unpaired node on any other level is carried up unchanged.
+/// Under `PaddingStrategy::Complete` every level below the root is padded to an even
+/// length, so the tree always has `depth + 1` levels.
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves, excluding padding
+/// * `padding` - How an odd leaf level is padded
+///
+/// # Returns
+///
+/// The level sizes from the leaves up to the root (empty for an empty tree)
+pub(crate) fn level_sizes(leaf_count: usize, padding: PaddingStrategy) -> Vec<usize> {
+    if leaf_count == 0 {
+        return Vec::new();
+    }
+
+    if let PaddingStrategy::Complete { depth } = padding {
+        let depth = usize::from(depth);
+        let mut size = leaf_count;
+        let mut sizes = Vec::with_capacity(depth + 1);
+        loop {
+            // Every odd level below the root is padded with an empty subtree
+            if sizes.len() < depth && size % 2 == 1 {
+                size = size.saturating_add(1);
+            }
+            sizes.push(size);
+            if size == 1 && sizes.len() > depth {
+                return sizes;
+            }
+            size = size.div_ceil(2);
+        }
+    }
+
+    let mut size = if padding.duplicates_last(leaf_count) {
+        // Saturating, as untrusted leaf counts reach this through verification
+        leaf_count.saturating_add(1)
+    } else {
+        leaf_count
+    };
+    let mut sizes = vec![size];
+    while size > 1 {
+        size = size.div_ceil(2);
+        sizes.push(size);
+    }
+    sizes
+}
+
+/// Check whether a stored tree is the final shard of a split tree, holding one leaf
+///
+/// In a `PaddingStrategy::DuplicateLast` tree the last leaf of an odd leaf level is
+/// paired with its duplicate, even when `MerkleTree::split` puts it in a shard of its
+/// own. Such a shard keeps the duplicate, so its leaf level has two nodes where a tree
+/// built from one leaf has one, and its root is the root of the subtree it covers.
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves, excluding padding
+/// * `padding` - How an odd leaf level is padded
+/// * `leaf_level_len` - The number of nodes stored on the leaf level
+///
+/// # Returns
+///
+/// True if the tree has the shape of a tree of two leaves
+#[cfg(feature = "build")]
+pub(crate) fn is_padded_lone_leaf(
+    leaf_count: usize,
+    padding: PaddingStrategy,
+    leaf_level_len: usize,
+) -> bool {
+    leaf_count == 1 && padding == PaddingStrategy::DuplicateLast && leaf_level_len == 2
+}
+
+/// Find the leaf a proof addresses in a tree with `leaf_count` leaves
+///
+/// Walks down from the root consuming proof steps from the end. At each level the
+/// position of the current node determines whether it had a sibling (and therefore a
+/// proof step), and each step's direction selects the child to descend into. The proof
+/// fits the tree if every step is consumed and the path ends on a real (non-padding) leaf.
+///
+/// # Arguments
+///
+/// * `proof` - The proof to check
+/// * `leaf_count` - The number of leaves in the tree, excluding padding
+/// * `padding` - How the tree's odd leaf level is padded
+///
+/// # Returns
+///
+/// The index of the leaf, or None if the proof's length and directions do not fit the tree
+pub(crate) fn proof_leaf_index(
+    proof: &MerkleProof,
+    leaf_count: usize,
+    padding: PaddingStrategy,
+) -> Option<usize> {
+    let sizes = level_sizes(leaf_count, padding);
+    if sizes.is_empty() {
+        return None;
+    }
+
+    let mut steps = proof.iter().rev();
+    let mut index = 0;
+    for &size in sizes.iter().rev().skip(1) {
+        let left = index * 2;
+        index = if left + 1 < size {
+            match steps.next()? {
+                (_, true) => left + 1,
+                (_, false) => left,
+            }
+        } else {
+            left
+        };
+    }
+
+    if steps.next().is_none() && index < leaf_count {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Compute the number of steps in the proof for a specific leaf
+///
+/// # Arguments
+///
+/// * `leaf_count` - The number of leaves in the tree, excluding padding
+/// * `padding` - How the tree's odd leaf level is padded
+/// * `index` - The index of the leaf
+///
+/// # Returns
+///
+/// The number of levels at which the leaf's ancestor has a sibling
+pub(crate) fn path_len(leaf_count: usize, padding: PaddingStrategy, index: usize) -> usize {
+    let sizes = level_sizes(leaf_count, padding);
+    let mut index = index;
+    let mut len = 0;
+    for &size in sizes.iter().take(sizes.len().saturating_sub(1)) {
+        if index ^ 1 < size {
+            len += 1;
+        }
+        index /= 2;
+    }
+    len
+}
+
+/// Check that a proof fits the shape of a tree with `leaf_count` leaves
+///
+/// With positional pairs the proof's directions must address a real leaf. With sorted
+/// pairs the directions are meaningless, so only the length is checked: it must lie
+/// between the shortest path (the last leaf's) and the tree's depth.
+///
+/// # Arguments
+///
+/// * `proof` - The proof to check
+/// * `leaf_count` - The number of leaves in the tree, excluding padding
+/// * `padding` - How the tree's odd leaf level is padded
+/// * `ordering` - How the tree orders child hashes
+///
+/// # Returns
+///
+/// True if the proof fits the tree
+pub(crate) fn proof_fits_shape(
+    proof: &MerkleProof,
+    leaf_count: usize,
+    padding: PaddingStrategy,
+    ordering: PairOrdering,
+) -> bool {
+    match ordering {
+        PairOrdering::Positional => proof_leaf_index(proof, leaf_count, padding).is_some(),
+        PairOrdering::Sorted => {
+            leaf_count > 0
+                && proof.len() >= path_len(leaf_count, padding, leaf_count - 1)
+                && proof.len() < level_sizes(leaf_count, padding).len()
+        }
+    }
+}
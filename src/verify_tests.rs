@@ -0,0 +1,77 @@
+// Tests of proof verification that need only the `verify` feature, so they also run
+// with `default-features = false, features = ["verify"]`. Proofs are put together by
+// hand, since there are no trees to generate them from in that configuration.
+use crate::{
+    expected_proof_len, HashScheme, MerkleProof, MerkleTree, MerkleVerifier, PaddingStrategy,
+    VerificationError,
+};
+use sha2::{Digest, Sha256};
+
+// The leaf hashes, root and proof of the last leaf of the plain three-leaf tree over
+// "a", "b" and "c", whose last leaf is paired with its duplicate
+fn three_leaf_proof() -> (Vec<u8>, MerkleProof) {
+    let scheme = HashScheme::Plain;
+    let [a, b, c] = [b"a", b"b", b"c"].map(|data| scheme.hash_leaf(data));
+    let left = scheme.hash_pair(&a, &b);
+    let right = scheme.hash_pair(&c, &c);
+    let root = scheme.hash_pair(&left, &right);
+    (root, MerkleProof::new(vec![(c, false), (left, true)]))
+}
+
+#[test]
+fn test_plain_leaves_are_sha256_of_the_data() {
+    assert_eq!(
+        HashScheme::Plain.hash_leaf(b"a"),
+        Sha256::digest(b"a").to_vec()
+    );
+    assert_eq!(
+        HashScheme::Plain.hash_pair(b"left", b"right"),
+        Sha256::digest(b"leftright").to_vec()
+    );
+}
+
+#[test]
+fn test_verify_hand_built_proof() {
+    let (root, proof) = three_leaf_proof();
+    assert!(MerkleTree::verify_proof(b"c", &proof, &root));
+    assert!(!MerkleTree::verify_proof(b"b", &proof, &root));
+    assert_eq!(expected_proof_len(3, PaddingStrategy::DuplicateLast), 2);
+
+    assert_eq!(
+        MerkleTree::verify_proof_checked(HashScheme::Plain, b"c", &proof, &root, Some(3), Some(2)),
+        Ok(())
+    );
+    assert_eq!(
+        MerkleTree::verify_proof_checked(HashScheme::Plain, b"c", &proof, &root, Some(3), Some(1)),
+        Err(VerificationError::IndexMismatch {
+            expected: 1,
+            actual: 2
+        })
+    );
+
+    let verifier = MerkleVerifier::new(root.clone())
+        .with_hash_config(HashScheme::Plain)
+        .with_leaf_count(3);
+    assert_eq!(verifier.verify(b"c", &proof), Ok(()));
+    assert_eq!(
+        verifier.verify(b"x", &proof),
+        Err(VerificationError::RootMismatch)
+    );
+}
+
+#[test]
+fn test_decoded_proof_verifies() {
+    let (root, proof) = three_leaf_proof();
+    let decoded = MerkleProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert_eq!(decoded, proof);
+    assert!(MerkleTree::verify_proof(b"c", &decoded, &root));
+    assert_eq!(MerkleProof::from_hex(&proof.to_hex()).unwrap(), proof);
+
+    let mut tampered = proof.into_steps();
+    tampered[1].0[0] ^= 1;
+    assert!(!MerkleTree::verify_proof(
+        b"c",
+        &MerkleProof::new(tampered),
+        &root
+    ));
+}